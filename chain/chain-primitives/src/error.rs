@@ -12,7 +12,8 @@ use near_primitives::errors::{EpochError, StorageError};
 use near_primitives::serialize::to_base;
 use near_primitives::shard_layout::ShardLayoutError;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
-use near_primitives::types::{BlockHeight, EpochId, ShardId};
+use near_primitives::types::{BlockHeight, EpochHeight, EpochId, Gas, NumBlocks, ShardId};
+use near_primitives::version::ProtocolVersion;
 
 #[derive(thiserror::Error, Debug)]
 pub enum QueryError {
@@ -87,6 +88,10 @@ pub enum ErrorKind {
     /// Block time is from too much in the future.
     #[error("Invalid Block Time: Too far in the future: {0}")]
     InvalidBlockFutureTime(DateTime<Utc>),
+    /// Block time is ahead of the local clock, but within the grace period, so the block is held
+    /// back until the local clock catches up.
+    #[error("Block time {0} is ahead of the local clock, delaying the block")]
+    BlockAheadOfClock(DateTime<Utc>),
     /// Block height is invalid (not previous + 1).
     #[error("Invalid Block Height {0}")]
     InvalidBlockHeight(BlockHeight),
@@ -111,12 +116,46 @@ pub enum ErrorKind {
     /// Invalid receipts proof.
     #[error("Invalid Receipts Proof")]
     InvalidReceiptsProof,
+    /// Incoming receipts of a block aren't proven to be outgoing receipts of their source chunk.
+    #[error(
+        "Invalid proof of incoming receipts from shard {from_shard_id} to shard {to_shard_id}"
+    )]
+    InvalidIncomingReceiptProof { from_shard_id: ShardId, to_shard_id: ShardId },
     /// Invalid outcomes proof.
     #[error("Invalid Outcomes Proof")]
     InvalidOutcomesProof,
     /// Invalid state payload on state sync.
     #[error("Invalid State Payload")]
     InvalidStatePayload,
+    /// Proofs of the incoming receipts in a state sync header don't prove the receipts.
+    #[error("Invalid proofs of incoming receipts in the state sync header of shard {0}")]
+    InvalidStateHeaderReceiptProofs(ShardId),
+    /// Incoming receipts in a state sync header don't follow the chain back to the previous chunk.
+    #[error("Invalid incoming receipts in the state sync header of shard {0}")]
+    InvalidStateHeaderIncomingReceipts(ShardId),
+    /// State root node in a state sync header doesn't match the state root of the chunk.
+    #[error("Invalid state root node in the state sync header of shard {0}")]
+    InvalidStateRootNode(ShardId),
+    /// State part doesn't match the state root of the state sync header.
+    #[error("Invalid state part {part_id} of shard {shard_id}")]
+    InvalidStatePart { shard_id: ShardId, part_id: u64 },
+    /// Serialized block exceeds the configured size limit.
+    #[error("Block Too Large: {0} bytes, limit is {1} bytes")]
+    BlockTooLarge(usize, usize),
+    /// Chunk contains more transactions than the configured limit.
+    #[error("Too Many Chunk Transactions: {1} transactions in shard {0}, limit is {2}")]
+    TooManyChunkTransactions(ShardId, usize, usize),
+    /// Outgoing receipts produced locally don't match the root claimed by the next chunk.
+    #[error("Outgoing Receipts Root Mismatch in shard {0}")]
+    OutgoingReceiptsRootMismatch(ShardId),
+    /// Stored execution outcomes don't match the outcome root of the chunk.
+    #[error("Outcomes Root Mismatch in shard {0}")]
+    OutcomesRootMismatch(ShardId),
+    /// Block is from an epoch with a protocol version the binary doesn't support.
+    #[error(
+        "Unsupported Protocol Version {epoch_version}, binary supports up to {binary_version}"
+    )]
+    UnsupportedProtocolVersion { epoch_version: ProtocolVersion, binary_version: ProtocolVersion },
     /// Invalid transactions in the block.
     #[error("Invalid Transactions")]
     InvalidTransactions,
@@ -141,9 +180,12 @@ pub enum ErrorKind {
     /// Invalid chunk state.
     #[error("Invalid Chunk State")]
     InvalidChunkState(Box<ChunkState>),
-    /// Invalid chunk mask
-    #[error("Invalid Chunk Mask")]
-    InvalidChunkMask,
+    /// A chunk new in the block is stored at the position of another shard
+    #[error("Invalid Chunk, chunk at position {position} is for shard {shard_id}")]
+    InvalidChunkShardId { position: ShardId, shard_id: ShardId },
+    /// Invalid chunk mask, with the shard whose bit doesn't match the block body if known
+    #[error("Invalid Chunk Mask, shard: {0:?}")]
+    InvalidChunkMask(Option<ShardId>),
     /// The chunk height is outside of the horizon
     #[error("Invalid Chunk Height")]
     InvalidChunkHeight,
@@ -204,6 +246,9 @@ pub enum ErrorKind {
     /// A challenged block is on the chain that was attempted to become the head
     #[error("Challenged block on chain")]
     ChallengedBlockOnChain,
+    /// The block or header builds on top of a challenged block
+    #[error("Block descends from a challenged block")]
+    ChallengedBlock,
     /// IO Error.
     #[error("IO Error: {0}")]
     IOErr(String),
@@ -216,6 +261,75 @@ pub enum ErrorKind {
     /// GC error.
     #[error("GC Error: {0}")]
     GCError(String),
+    /// The records of blocks waiting to be caught up disagree with the stored blocks.
+    #[error("Inconsistent catchup bookkeeping: {0}")]
+    InconsistentCatchupBookkeeping(String),
+    /// A chunk extra saved by an earlier, interrupted state sync finalization differs from the
+    /// recomputed one, so the state of the shard is corrupted.
+    #[error(
+        "Chunk extra of shard {shard_id} at block {block_hash} saved by state sync does not match"
+    )]
+    StateSyncChunkExtraMismatch { block_hash: near_primitives::hash::CryptoHash, shard_id: ShardId },
+    /// The local data used to serve a state request doesn't pass verification.
+    #[error("Local data for state sync of shard {shard_id} at block {block_hash} is corrupted: {reason}")]
+    StateSyncLocalDataCorrupted {
+        block_hash: near_primitives::hash::CryptoHash,
+        shard_id: ShardId,
+        reason: String,
+    },
+    /// State requests are already being served for as many sync hashes as allowed.
+    #[error("Too many sync hashes are being served, state request for {0} rejected")]
+    StateSyncBusy(near_primitives::hash::CryptoHash),
+    /// The state sync header is being generated for another request.
+    #[error("State sync header of shard {shard_id} for {sync_hash} is being generated for another request")]
+    StateSyncHeaderInFlight { shard_id: ShardId, sync_hash: near_primitives::hash::CryptoHash },
+    /// The chain stopped accepting blocks because the node is shutting down.
+    #[error("Chain is shutting down")]
+    ShuttingDown,
+    /// Trusted chain replay skips validation, so it's refused on public networks.
+    #[error("Trusted chain replay is not allowed on chain {0}")]
+    TrustedChainReplayNotAllowed(String),
+    /// Economics overrides change block validity, so they're refused on public networks.
+    #[error("Economics overrides are not allowed on chain {0}")]
+    EconomicsOverridesNotAllowed(String),
+    /// Requested block ordinal is not known to the canonical chain.
+    #[error("Block ordinal {ordinal} is out of range, the maximum known ordinal is {max_ordinal}")]
+    BlockOrdinalOutOfRange { ordinal: NumBlocks, max_ordinal: NumBlocks },
+    /// Requested block height is below the tail, so its data is garbage collected.
+    #[error("Block height {height} is below the tail at height {tail_height}")]
+    BlockHeightBelowTail { height: BlockHeight, tail_height: BlockHeight },
+    /// The block at the requested height carries over an older chunk of the shard, which was
+    /// produced and included at `included_height`.
+    #[error("Chunk of shard {shard_id} is not included at height {height}, the last chunk was included at height {included_height}")]
+    ChunkNotIncluded { height: BlockHeight, shard_id: ShardId, included_height: BlockHeight },
+    /// A chain segment proof doesn't prove the segment.
+    #[error("Invalid chain segment proof: {0}")]
+    InvalidChainSegmentProof(String),
+    /// A final block was required, but the block is ahead of the last final block.
+    #[error(
+        "Block {block_hash} is not final yet, the current final height is {current_final_height}"
+    )]
+    NotFinalYet { block_hash: near_primitives::hash::CryptoHash, current_final_height: BlockHeight },
+    /// A block proof was requested against a head older than the block, so the block isn't in the
+    /// merkle tree of the head yet.
+    #[error("Block at height {block_height} is ahead of the head block at height {head_height}")]
+    BlockAheadOfHead { block_height: BlockHeight, head_height: BlockHeight },
+    /// Applying a chunk again, e.g. to build a challenge, gives a different state root than the
+    /// one saved when the chunk was first applied.
+    #[error(
+        "Chunk of shard {shard_id} applied again results in state root {got_root}, expected {expected_root}"
+    )]
+    NonDeterministicApply {
+        shard_id: ShardId,
+        expected_root: near_primitives::types::StateRoot,
+        got_root: near_primitives::types::StateRoot,
+    },
+    /// The epoch hasn't ended yet, or it started before epoch summaries were recorded.
+    #[error("Summary of epoch at height {0} is not computed")]
+    EpochSummaryNotComputed(EpochHeight),
+    /// The chain genesis doesn't match the genesis the runtime was initialized with.
+    #[error("Chain genesis is inconsistent with the runtime: {}", display_list(.0))]
+    InconsistentGenesis(Vec<GenesisInconsistency>),
     /// Anything else
     #[error("Other Error: {0}")]
     Other(String),
@@ -264,6 +378,14 @@ impl Error {
         self.inner.backtrace()
     }
 
+    /// Adds a description of what failed to the error, keeping its kind.
+    pub fn context<C>(self, context: C) -> Self
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        Self { inner: self.inner.context(context) }
+    }
+
     pub fn is_bad_data(&self) -> bool {
         match self.kind() {
             ErrorKind::BlockKnown(_)
@@ -276,8 +398,29 @@ impl Error {
             | ErrorKind::ValidatorError(_)
             | ErrorKind::EpochOutOfBounds(_)
             | ErrorKind::ChallengedBlockOnChain
+            | ErrorKind::ChallengedBlock
             | ErrorKind::StorageError(_)
             | ErrorKind::GCError(_)
+            | ErrorKind::InconsistentCatchupBookkeeping(_)
+            | ErrorKind::StateSyncChunkExtraMismatch { .. }
+            | ErrorKind::StateSyncLocalDataCorrupted { .. }
+            | ErrorKind::StateSyncBusy(_)
+            | ErrorKind::StateSyncHeaderInFlight { .. }
+            | ErrorKind::OutgoingReceiptsRootMismatch(_)
+            | ErrorKind::OutcomesRootMismatch(_)
+            | ErrorKind::UnsupportedProtocolVersion { .. }
+            | ErrorKind::ShuttingDown
+            | ErrorKind::TrustedChainReplayNotAllowed(_)
+            | ErrorKind::EconomicsOverridesNotAllowed(_)
+            | ErrorKind::BlockOrdinalOutOfRange { .. }
+            | ErrorKind::BlockHeightBelowTail { .. }
+            | ErrorKind::ChunkNotIncluded { .. }
+            | ErrorKind::NotFinalYet { .. }
+            | ErrorKind::BlockAheadOfHead { .. }
+            | ErrorKind::BlockAheadOfClock(_)
+            | ErrorKind::NonDeterministicApply { .. }
+            | ErrorKind::EpochSummaryNotComputed(_)
+            | ErrorKind::InconsistentGenesis(_)
             | ErrorKind::DBNotFoundErr(_) => false,
             ErrorKind::InvalidBlockPastTime(_, _)
             | ErrorKind::InvalidBlockFutureTime(_)
@@ -286,7 +429,8 @@ impl Error {
             | ErrorKind::InvalidChunk
             | ErrorKind::InvalidChunkProofs(_)
             | ErrorKind::InvalidChunkState(_)
-            | ErrorKind::InvalidChunkMask
+            | ErrorKind::InvalidChunkMask(_)
+            | ErrorKind::InvalidChunkShardId { .. }
             | ErrorKind::InvalidStateRoot
             | ErrorKind::InvalidTxRoot
             | ErrorKind::InvalidChunkReceiptsRoot
@@ -294,8 +438,15 @@ impl Error {
             | ErrorKind::InvalidChunkHeadersRoot
             | ErrorKind::InvalidChunkTxRoot
             | ErrorKind::InvalidReceiptsProof
+            | ErrorKind::InvalidIncomingReceiptProof { .. }
             | ErrorKind::InvalidStatePayload
+            | ErrorKind::InvalidStateHeaderReceiptProofs(_)
+            | ErrorKind::InvalidStateHeaderIncomingReceipts(_)
+            | ErrorKind::InvalidStateRootNode(_)
+            | ErrorKind::InvalidStatePart { .. }
             | ErrorKind::InvalidTransactions
+            | ErrorKind::BlockTooLarge(_, _)
+            | ErrorKind::TooManyChunkTransactions(_, _, _)
             | ErrorKind::InvalidChallenge
             | ErrorKind::MaliciousChallenge
             | ErrorKind::IncorrectNumberOfChunkHeaders
@@ -315,7 +466,8 @@ impl Error {
             | ErrorKind::InvalidRandomnessBeaconOutput
             | ErrorKind::InvalidBlockMerkleRoot
             | ErrorKind::NotAValidator
-            | ErrorKind::InvalidChallengeRoot => true,
+            | ErrorKind::InvalidChallengeRoot
+            | ErrorKind::InvalidChainSegmentProof(_) => true,
         }
     }
 
@@ -376,8 +528,12 @@ impl From<BlockValidityError> for Error {
             BlockValidityError::InvalidReceiptRoot => ErrorKind::InvalidChunkReceiptsRoot,
             BlockValidityError::InvalidTransactionRoot => ErrorKind::InvalidTxRoot,
             BlockValidityError::InvalidChunkHeaderRoot => ErrorKind::InvalidChunkHeadersRoot,
-            BlockValidityError::InvalidChunkMask => ErrorKind::InvalidChunkMask,
+            BlockValidityError::InvalidChunkMask => ErrorKind::InvalidChunkMask(None),
             BlockValidityError::InvalidChallengeRoot => ErrorKind::InvalidChallengeRoot,
+            BlockValidityError::MissingChunk(shard_id) => ErrorKind::InvalidShardId(shard_id),
+            BlockValidityError::InvalidChunkShardId { position, shard_id } => {
+                ErrorKind::InvalidChunkShardId { position, shard_id }
+            }
         }
         .into()
     }
@@ -389,6 +545,22 @@ impl From<StorageError> for Error {
     }
 }
 
+/// A mismatch between the chain genesis and the genesis of the runtime, see
+/// `ErrorKind::InconsistentGenesis`.
+#[derive(Clone, Eq, PartialEq, Debug, thiserror::Error)]
+pub enum GenesisInconsistency {
+    #[error("genesis height {chain_genesis} in chain genesis, {runtime} in genesis config")]
+    Height { chain_genesis: BlockHeight, runtime: BlockHeight },
+    #[error("gas limit {chain_genesis} in chain genesis, {runtime} in genesis config")]
+    GasLimit { chain_genesis: Gas, runtime: Gas },
+    #[error("protocol version {chain_genesis} in chain genesis, {runtime} in genesis config")]
+    ProtocolVersion { chain_genesis: ProtocolVersion, runtime: ProtocolVersion },
+}
+
+fn display_list<T: Display>(items: &[T]) -> String {
+    items.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, thiserror::Error)]
 pub enum BlockKnownError {
     #[error("already known in header")]
@@ -401,4 +573,6 @@ pub enum BlockKnownError {
     KnownInMissingChunks,
     #[error("already known in store")]
     KnownInStore,
+    #[error("already being processed")]
+    KnownInProcessing,
 }
@@ -1,3 +1,5 @@
 pub use error::{Error, ErrorKind};
+pub use misbehavior::ChainMisbehavior;
 
 pub mod error;
+pub mod misbehavior;
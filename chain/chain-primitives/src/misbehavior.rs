@@ -0,0 +1,36 @@
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::ShardId;
+
+use crate::error::ErrorKind;
+
+/// Chain-level misbehavior detected while validating data received from the network. It doesn't
+/// carry the peer the data came from, the client attaches it before the network layer uses the
+/// event for scoring or banning the peer.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ChainMisbehavior {
+    /// A block failed the basic validation upon receiving it.
+    InvalidBlock { block_hash: CryptoHash, error: ErrorKind },
+    /// A header in a batch of headers received during header sync failed the validation.
+    InvalidHeader { header_hash: CryptoHash, error: ErrorKind },
+    /// A state sync header failed the validation.
+    InvalidStateHeader { shard_id: ShardId, sync_hash: CryptoHash, error: ErrorKind },
+}
+
+impl ChainMisbehavior {
+    /// Hash of the offending object, or of the sync block for state sync headers.
+    pub fn hash(&self) -> &CryptoHash {
+        match self {
+            ChainMisbehavior::InvalidBlock { block_hash, .. } => block_hash,
+            ChainMisbehavior::InvalidHeader { header_hash, .. } => header_hash,
+            ChainMisbehavior::InvalidStateHeader { sync_hash, .. } => sync_hash,
+        }
+    }
+
+    pub fn error(&self) -> &ErrorKind {
+        match self {
+            ChainMisbehavior::InvalidBlock { error, .. }
+            | ChainMisbehavior::InvalidHeader { error, .. }
+            | ChainMisbehavior::InvalidStateHeader { error, .. } => error,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+#[macro_use]
+extern crate bencher;
+
+use bencher::{black_box, Bencher};
+use near_chain::chain::AccountShardCache;
+use near_chain::Chain;
+use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::ShardLayout;
+
+/// Receipts to 1000 accounts, as after a congested chunk is flushed.
+fn create_receipts() -> Vec<Receipt> {
+    (0..30_000)
+        .map(|i| Receipt::new_balance_refund(&format!("test{}", i % 1000).parse().unwrap(), 0))
+        .collect()
+}
+
+fn receipts_hashes_single_shard(bench: &mut Bencher) {
+    let receipts = create_receipts();
+    let shard_layout = ShardLayout::v0_single_shard();
+    bench.iter(|| {
+        let hashes = Chain::build_receipts_hashes(&receipts, &shard_layout);
+        black_box(hashes)
+    });
+}
+
+fn receipts_hashes(bench: &mut Bencher) {
+    let receipts = create_receipts();
+    let shard_layout = ShardLayout::v1_test();
+    bench.iter(|| {
+        let hashes = Chain::build_receipts_hashes(&receipts, &shard_layout);
+        black_box(hashes)
+    });
+}
+
+fn receipts_hashes_with_cache(bench: &mut Bencher) {
+    let receipts = create_receipts();
+    let shard_layout = ShardLayout::v1_test();
+    let mut cache = AccountShardCache::default();
+    bench.iter(|| {
+        let hashes = Chain::build_receipts_hashes_with_cache(&receipts, &shard_layout, &mut cache);
+        black_box(hashes)
+    });
+}
+
+benchmark_group!(
+    benches,
+    receipts_hashes_single_shard,
+    receipts_hashes,
+    receipts_hashes_with_cache
+);
+benchmark_main!(benches);
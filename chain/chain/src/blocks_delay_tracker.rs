@@ -3,12 +3,21 @@ use near_primitives::block::Block;
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{BlockHeight, ShardId};
-use std::collections::HashMap;
-use std::time::Instant;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 use tracing::error;
 
 use crate::metrics;
 
+/// Maximum number of block readiness samples kept, regardless of pruning by height.
+const MAX_BLOCK_READINESS_SAMPLES: usize = 200_000;
+/// Number of most recently finalized blocks whose time to finality is summarized.
+const FINALITY_LATENCY_WINDOW: usize = 1000;
+/// Number of most recently accepted blocks the rate of applying blocks is measured over.
+const BLOCK_APPLY_RATE_WINDOW: usize = 100;
+/// Number of most recently applied chunks of a shard whose apply time is averaged.
+const CHUNK_APPLY_TIME_WINDOW: usize = 20;
+
 /// Provides monitoring information about the important timestamps throughout the lifetime of
 /// blocks and chunks. It keeps information of all pending blocks and chunks that have not been fully processed yet.
 /// A block is added the first time it is received and removed when it finishes processing
@@ -20,6 +29,63 @@ pub struct BlocksDelayTracker {
     /// An entry gets created when a chunk gets requested for the first time.
     /// Chunks get deleted when the block gets processed.
     pub chunks_in_progress: HashMap<ChunkHash, ChunkInProgress>,
+    /// Readiness delays of processed blocks, in the order they finished processing.
+    /// Pruned by height together with garbage collection.
+    pub readiness_samples: VecDeque<BlockReadinessSample>,
+    /// Height and acceptance time of the accepted blocks which are not final yet.
+    pub accepted_blocks: HashMap<CryptoHash, (BlockHeight, Instant)>,
+    /// Time from acceptance to finality of the last finalized blocks, in the order they became
+    /// final.
+    pub finality_latencies: VecDeque<Duration>,
+    /// Acceptance times of the last accepted blocks, oldest first.
+    pub applied_blocks: VecDeque<Instant>,
+    /// Time spent applying the last chunks of each shard, oldest first.
+    pub chunk_apply_times: HashMap<ShardId, VecDeque<Duration>>,
+    /// Expected interval between blocks. Shards whose chunks take longer than that to apply on
+    /// average are falling behind. If unset, no shard is reported as falling behind.
+    pub block_production_interval: Option<Duration>,
+}
+
+/// How long it took for a processed block to become fully processable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockReadinessSample {
+    pub height: BlockHeight,
+    /// Time between receiving the block and having all of its chunks. For blocks which never
+    /// waited for chunks this is the time until the block finished processing.
+    pub delay: Duration,
+}
+
+/// Summary of block readiness delays, see `Chain::block_readiness_slo`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SloReport {
+    pub num_blocks: usize,
+    pub num_within_threshold: usize,
+    /// Percentage of blocks which became ready within the threshold, 100 if there were no blocks.
+    pub percent_within_threshold: f64,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Summary of the time to finality of recently finalized blocks, see
+/// `Chain::finality_latency_stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FinalityLatencyStats {
+    pub num_blocks: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::default();
+    }
+    let rank = (percent * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
 }
 
 #[derive(Debug)]
@@ -110,6 +176,14 @@ impl BlocksDelayTracker {
         }
     }
 
+    /// Forgets a block which is not going to be processed, e.g. an orphan dropped from the pool,
+    /// together with the chunks requested for it.
+    pub fn mark_block_dropped(&mut self, block_hash: &CryptoHash) {
+        if self.blocks_in_progress.remove(block_hash).is_some() {
+            self.chunks_in_progress.retain(|_, chunk| &chunk.block_hash != block_hash);
+        }
+    }
+
     pub fn mark_chunk_received(&mut self, chunk_hash: &ChunkHash, timestamp: Instant) {
         self.chunks_in_progress
             .get_mut(&chunk_hash)
@@ -129,9 +203,21 @@ impl BlocksDelayTracker {
         });
     }
 
-    pub fn finish_block_processing(&mut self, block_hash: &CryptoHash, chunks: &[ChunkHash]) {
+    pub fn finish_block_processing(
+        &mut self,
+        block_hash: &CryptoHash,
+        chunks: &[ChunkHash],
+        timestamp: Instant,
+    ) {
         if let Some(processed_block) = self.blocks_in_progress.remove(&block_hash) {
             self.update_block_metrics(&processed_block);
+            let ready_timestamp =
+                processed_block.removed_from_missing_chunks_timestamp.unwrap_or(timestamp);
+            self.record_block_readiness(BlockReadinessSample {
+                height: processed_block.height,
+                delay: ready_timestamp
+                    .saturating_duration_since(processed_block.received_timestamp),
+            });
             for (shard_id, chunk_hash) in chunks.iter().enumerate() {
                 if let Some(processed_chunk) = self.chunks_in_progress.remove(&chunk_hash) {
                     self.update_chunk_metrics(
@@ -144,6 +230,145 @@ impl BlocksDelayTracker {
         }
     }
 
+    fn record_block_readiness(&mut self, sample: BlockReadinessSample) {
+        metrics::BLOCK_READINESS_DELAY.observe(sample.delay.as_secs_f64());
+        self.readiness_samples.push_back(sample);
+        if self.readiness_samples.len() > MAX_BLOCK_READINESS_SAMPLES {
+            self.readiness_samples.pop_front();
+        }
+    }
+
+    /// Removes readiness samples of blocks below `min_height`, together with the acceptance
+    /// times of blocks below it which never became final.
+    pub fn prune_readiness_samples(&mut self, min_height: BlockHeight) {
+        self.readiness_samples.retain(|sample| sample.height >= min_height);
+        self.accepted_blocks.retain(|_, (height, _)| *height >= min_height);
+    }
+
+    pub fn mark_block_accepted(
+        &mut self,
+        block_hash: &CryptoHash,
+        height: BlockHeight,
+        timestamp: Instant,
+    ) {
+        self.accepted_blocks.entry(*block_hash).or_insert((height, timestamp));
+        self.applied_blocks.push_back(timestamp);
+        if self.applied_blocks.len() > BLOCK_APPLY_RATE_WINDOW {
+            self.applied_blocks.pop_front();
+        }
+    }
+
+    /// Records the time it took to apply a chunk of the shard, and whether the node is falling
+    /// behind in the shard since.
+    pub fn mark_chunk_applied(&mut self, shard_id: ShardId, apply_duration: Duration) {
+        let apply_times = self.chunk_apply_times.entry(shard_id).or_default();
+        apply_times.push_back(apply_duration);
+        if apply_times.len() > CHUNK_APPLY_TIME_WINDOW {
+            apply_times.pop_front();
+        }
+        metrics::FALLING_BEHIND_PER_SHARD
+            .with_label_values(&[&shard_id.to_string()])
+            .set(self.is_falling_behind(shard_id) as i64);
+    }
+
+    /// Average time spent applying the last `CHUNK_APPLY_TIME_WINDOW` chunks of the shard.
+    pub fn average_chunk_apply_time(&self, shard_id: ShardId) -> Option<Duration> {
+        let apply_times = self.chunk_apply_times.get(&shard_id)?;
+        if apply_times.is_empty() {
+            return None;
+        }
+        Some(apply_times.iter().sum::<Duration>() / apply_times.len() as u32)
+    }
+
+    /// Whether applying the chunks of the shard takes longer on average than the interval
+    /// between blocks, so that the node can't keep up with the shard.
+    pub fn is_falling_behind(&self, shard_id: ShardId) -> bool {
+        match (self.block_production_interval, self.average_chunk_apply_time(shard_id)) {
+            (Some(interval), Some(average)) => average > interval,
+            _ => false,
+        }
+    }
+
+    /// Shards the node is falling behind in, see `is_falling_behind`.
+    pub fn shards_falling_behind(&self) -> Vec<ShardId> {
+        let mut shards = self
+            .chunk_apply_times
+            .keys()
+            .copied()
+            .filter(|shard_id| self.is_falling_behind(*shard_id))
+            .collect::<Vec<_>>();
+        shards.sort_unstable();
+        shards
+    }
+
+    /// Blocks accepted per second over the last `BLOCK_APPLY_RATE_WINDOW` accepted blocks. `None`
+    /// if there are fewer than two of them or no time passed between them.
+    pub fn blocks_applied_per_second(&self) -> Option<f64> {
+        let first = self.applied_blocks.front()?;
+        let last = self.applied_blocks.back()?;
+        let elapsed = last.saturating_duration_since(*first).as_secs_f64();
+        if elapsed > 0. {
+            Some((self.applied_blocks.len() - 1) as f64 / elapsed)
+        } else {
+            None
+        }
+    }
+
+    /// Records the time to finality of an accepted block. Blocks which weren't accepted by this
+    /// node, e.g. downloaded during sync, are ignored.
+    pub fn mark_block_final(&mut self, block_hash: &CryptoHash, timestamp: Instant) {
+        if let Some((_, accepted)) = self.accepted_blocks.remove(block_hash) {
+            let latency = timestamp.saturating_duration_since(accepted);
+            metrics::BLOCK_FINALITY_LATENCY.observe(latency.as_secs_f64());
+            self.finality_latencies.push_back(latency);
+            if self.finality_latencies.len() > FINALITY_LATENCY_WINDOW {
+                self.finality_latencies.pop_front();
+            }
+        }
+    }
+
+    /// Summarizes the time to finality of the last `FINALITY_LATENCY_WINDOW` finalized blocks.
+    pub fn finality_latency_stats(&self) -> FinalityLatencyStats {
+        let mut latencies = self.finality_latencies.iter().cloned().collect_vec();
+        latencies.sort();
+        FinalityLatencyStats {
+            num_blocks: latencies.len(),
+            p50: percentile(&latencies, 50),
+            p90: percentile(&latencies, 90),
+            p99: percentile(&latencies, 99),
+            max: latencies.last().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Summarizes readiness delays of the processed blocks at `min_height` and above.
+    pub fn block_readiness_report(
+        &self,
+        min_height: BlockHeight,
+        threshold: Duration,
+    ) -> SloReport {
+        let mut delays = self
+            .readiness_samples
+            .iter()
+            .filter(|sample| sample.height >= min_height)
+            .map(|sample| sample.delay)
+            .collect_vec();
+        delays.sort();
+        let num_within_threshold = delays.iter().filter(|delay| **delay <= threshold).count();
+        SloReport {
+            num_blocks: delays.len(),
+            num_within_threshold,
+            percent_within_threshold: if delays.is_empty() {
+                100.
+            } else {
+                num_within_threshold as f64 * 100. / delays.len() as f64
+            },
+            p50: percentile(&delays, 50),
+            p90: percentile(&delays, 90),
+            p99: percentile(&delays, 99),
+            max: delays.last().cloned().unwrap_or_default(),
+        }
+    }
+
     fn update_block_metrics(&mut self, block: &BlockInProgress) {
         if let Some(start) = block.orphaned_timestamp {
             if let Some(end) = block.removed_from_orphan_timestamp {
@@ -1,19 +1,31 @@
-use std::collections::{HashMap, HashSet};
-
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Range, RangeInclusive};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex};
 use std::time::{Duration as TimeDuration, Instant};
 
 use borsh::BorshSerialize;
-use chrono::Duration;
+use chrono::{DateTime, Duration};
 use itertools::Itertools;
-use near_primitives::time::Clock;
+use lru::LruCache;
+use near_primitives::time::{Clock, Utc};
 use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rand::SeedableRng;
 use tracing::{debug, error, info, warn};
 
+use near_chain_configs::{
+    is_public_chain, EconomicsOverrides, UnsupportedProtocolVersionPolicy,
+    DEFAULT_FUTURE_BLOCK_GRACE_PERIOD, DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE,
+    DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND, DEFAULT_MAX_ORPHANS_PER_PREV_HASH,
+    DEFAULT_RECEIPT_BACKLOG_WARN_BYTES,
+};
 use near_chain_primitives::error::{BlockKnownError, Error, ErrorKind, LogTransientStorageError};
-use near_primitives::block::{genesis_chunks, Tip};
+use near_chain_primitives::ChainMisbehavior;
+use near_primitives::block::Tip;
+use near_primitives::block_header::HeaderDescription;
 use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
     MaybeEncodedShardChunk, SlashedValidator,
@@ -23,6 +35,7 @@ use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{
     combine_hash, merklize, verify_path, Direction, MerklePath, MerklePathItem,
 };
+use near_primitives::network::PeerId;
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
     ChunkHash, ChunkHashHeight, ReceiptList, ReceiptProof, ShardChunk, ShardChunkHeader, ShardInfo,
@@ -36,34 +49,62 @@ use near_primitives::syncing::{
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{
-    AccountId, Balance, BlockExtra, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
-    NumBlocks, NumShards, ShardId, StateChangesForSplitStates, StateRoot,
+    AccountId, Balance, BlockExtra, BlockHeight, BlockHeightDelta, EpochHeight, EpochId, Finality,
+    Gas, MerkleHash, NumBlocks, NumShards, ShardId, StateChangesForSplitStates, StateRoot,
 };
 use near_primitives::unwrap_or_return;
-use near_primitives::utils::MaybeValidated;
+use near_primitives::utils::{index_to_bytes, to_timestamp, MaybeValidated};
+use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
 use near_primitives::views::{
-    ExecutionOutcomeWithIdView, ExecutionStatusView, FinalExecutionOutcomeView,
-    FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
-    SignedTransactionView,
+    BlockAtHeightView, CatchupDiagnosticsView, ChainParametersView, DataAvailabilityView,
+    DiscardedForkView, EpochStateDownloadStatusView, EpochSummaryView, ExecutionOutcomeWithIdView,
+    ExecutionStatusView, FinalExecutionOutcomeLiteView, FinalExecutionOutcomeView,
+    FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, GcPlanView, GcRunView,
+    LightClientBlockView, OrphanPoolSummaryView, OrphanView, PartOwnershipView,
+    PartialChunkPartView, PartialFinalExecutionOutcomeView, RejectedBlockView, ShardLayoutView,
+    ShardStateDownloadStatusView, SignedTransactionView, StatePartProviderView,
+    SyncDiagnosticsView, SyncProgressView,
+};
+use near_store::{
+    ColBlock, ColBlockHeight, ColState, ColStateHeaders, ColStateParts,
+    ColStateSyncFinalizeProgress, ShardTries, Store, StoreUpdate, TrieChanges, TrieOverlay,
 };
-use near_store::{ColState, ColStateHeaders, ColStateParts, ShardTries, StoreUpdate};
 
 use near_primitives::state_record::StateRecord;
 
-use crate::blocks_delay_tracker::BlocksDelayTracker;
+use crate::blocks_delay_tracker::{BlocksDelayTracker, FinalityLatencyStats, SloReport};
 use crate::crypto_hash_timer::CryptoHashTimer;
-use crate::lightclient::get_epoch_block_producers_view;
-use crate::migrations::check_if_block_is_first_with_chunk_of_version;
+use crate::events::{
+    BlockAcceptedEvent, ChainEventHandler, ChainEventHandlers, ChainEventSubscription,
+    ChunkAppliedEvent, ReorgEvent,
+};
+use crate::genesis_block_info::GenesisBlockInfo;
+use crate::lightclient::{
+    get_epoch_block_producers_view, light_client_block_hash, ChainSegmentProof,
+};
+use crate::migrations::{
+    check_if_block_is_first_with_chunk_of_version, get_chain_schema_version, CHAIN_SCHEMA_VERSION,
+};
 use crate::missing_chunks::{BlockLike, MissingChunksPool};
-use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode, SavedStoreUpdate};
+use crate::shared_chain_cache::SharedChainCache;
+use crate::state_header_cache::{HeaderLookup, StateHeaderCache};
+#[cfg(feature = "test_features")]
+use crate::state_sync_corruptor::StateSyncCorruptor;
+use crate::store::{
+    option_to_not_found, ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode, SavedStoreUpdate,
+};
+use crate::sync_hash_registry::SyncHashRegistry;
 use crate::types::{
     AcceptedBlock, ApplySplitStateResult, ApplySplitStateResultOrStateChanges,
-    ApplyTransactionResult, Block, BlockEconomicsConfig, BlockHeader, BlockHeaderInfo, BlockStatus,
-    ChainGenesis, Provenance, RuntimeAdapter,
+    ApplyTransactionResult, ApprovalAuditRecord, Block, BlockApprovals, BlockEconomicsConfig,
+    BlockHeader, BlockHeaderInfo, BlockLimitsConfig, BlockSaveMode, BlockStatus,
+    BlockValidationConfig, ChainGenesis, DiscardedFork, EpochSummary, Provenance,
+    ReceiptsRootCheckMode, RejectedBlock, RequiredChunks, RuntimeAdapter, ShardCareMatrix,
+    ShardQuarantine, StatePartCacheSink, StatePartPayload,
 };
 use crate::validate::{
-    validate_challenge, validate_chunk_proofs, validate_chunk_with_chunk_extra,
-    validate_transactions_order,
+    validate_challenge, validate_chunk_proofs, validate_chunk_state_root,
+    validate_chunk_with_chunk_extra, validate_transactions_order,
 };
 use crate::{byzantine_assert, create_light_client_block_view, Doomslug};
 use crate::{metrics, DoomslugThresholdMode};
@@ -72,7 +113,7 @@ use delay_detector::DelayDetector;
 use near_primitives::shard_layout::{
     account_id_to_shard_id, account_id_to_shard_uid, ShardLayout, ShardUId,
 };
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 /// Maximum number of orphans chain can store.
 pub const MAX_ORPHAN_SIZE: usize = 1024;
@@ -80,16 +121,20 @@ pub const MAX_ORPHAN_SIZE: usize = 1024;
 /// Maximum age of orhpan to store in the chain.
 const MAX_ORPHAN_AGE_SECS: u64 = 300;
 
+/// `get_orphans_within_depth` returns at most this many orphans per height of depth.
+const MAX_ORPHANS_WITHIN_DEPTH_PER_HEIGHT: usize = 100;
+
 // Number of orphan ancestors should be checked to request chunks
 // Orphans for which we will request for missing chunks must satisfy,
 // its NUM_ORPHAN_ANCESTORS_CHECK'th ancestor has been accepted
 pub const NUM_ORPHAN_ANCESTORS_CHECK: u64 = 3;
 
-// Maximum number of orphans that we can request missing chunks
+// Maximum number of orphans on forks that we can request missing chunks
+// Orphans extending the head, see `Chain::orphan_extends_head`, are allowed up to
+// NUM_ORPHAN_ANCESTORS_CHECK requests per shard instead, but never less than this.
 // Note that if there are no forks, the maximum number of orphans we would
 // request missing chunks will not exceed NUM_ORPHAN_ANCESTORS_CHECK,
 // this number only adds another restriction when there are multiple forks.
-// It should almost never be hit
 const MAX_ORPHAN_MISSING_CHUNKS: usize = 5;
 
 /// 10000 years in seconds. Big constant for sandbox to allow time traveling.
@@ -109,9 +154,68 @@ const NEAR_BASE: Balance = 1_000_000_000_000_000_000_000_000;
 /// Number of epochs for which we keep store data
 pub const NUM_EPOCHS_TO_KEEP_STORE_DATA: u64 = 5;
 
+/// Number of epochs for which records of discarded fork blocks are kept after the blocks are
+/// garbage collected.
+pub const NUM_EPOCHS_TO_KEEP_DISCARDED_FORKS: u64 = 50;
+
+/// Number of most recent epochs for which approval participation is tracked.
+const NUM_EPOCHS_TO_KEEP_APPROVAL_PARTICIPATION: usize = 3;
+
+/// Number of most recent sync hashes for which the served state sync data is tallied.
+const NUM_SYNC_HASHES_TO_KEEP_SERVING_STATS: usize = 3;
+
+/// Maximum number of canonical blocks between a requested sync hash and the epoch boundary for
+/// which the actual sync hash is suggested to the requesting peer.
+pub const SYNC_HASH_SUGGESTION_DISTANCE: NumBlocks = 2;
+
+/// `AccountShardCache` starts over once it holds this many accounts.
+const ACCOUNT_SHARD_CACHE_MAX_SIZE: usize = 100_000;
+
 /// Maximum number of height to go through at each step when cleaning forks during garbage collection.
 const GC_FORK_CLEAN_STEP: u64 = 1000;
 
+/// Minimum number of heights between the final head and the tail pruned to by
+/// `Chain::garbage_collect_to_height`.
+pub const GC_TO_HEIGHT_FINAL_HEAD_MARGIN: BlockHeightDelta = 100;
+
+/// Keys deleted with every block by garbage collection, as estimated by `Chain::simulate_gc`:
+/// the block, its extra, info, refcount, next block hash and blocks per height entry.
+const GC_KEYS_PER_BLOCK: u64 = 6;
+
+/// Keys deleted with a block for each shard: outgoing and incoming receipts, chunk per height
+/// and shard, previous block with a new chunk, chunk extra and trie changes.
+const GC_KEYS_PER_SHARD: u64 = 6;
+
+/// Keys deleted with a block for each chunk new in it: outcome ids and outcomes by shard.
+const GC_KEYS_PER_NEW_CHUNK: u64 = 2;
+
+/// Keys deleted for each chunk below the chunk tail, besides its transactions and receipts:
+/// the chunk, partial chunk and invalid chunk.
+const GC_KEYS_PER_CHUNK: u64 = 3;
+
+/// Keys deleted for each height below the chunk tail: chunk and header hashes by height.
+const GC_KEYS_PER_CHUNK_HEIGHT: u64 = 2;
+
+/// Maximum number of ancestors to go through when checking whether a header builds on top of
+/// a challenged block. The walk normally stops much earlier, at the last final block.
+pub const MAX_CHALLENGED_ANCESTORS_CHECK: NumBlocks = 1000;
+
+/// Maximum number of parts listed with their owner by `Chain::part_ownership`.
+pub const MAX_PART_OWNERSHIP_OWNERS: usize = 1000;
+
+/// Number of recently rejected blocks remembered by `Chain::is_block_invalid`.
+const INVALID_BLOCKS_CACHE_SIZE: usize = 1000;
+
+/// Number of block merkle tree nodes reconstructed for block proofs kept across calls to
+/// `Chain::get_block_proof`.
+const BLOCK_MERKLE_NODES_CACHE_SIZE: usize = 10_000;
+
+/// Maximum number of blocks ahead of the local clock held back until the clock catches up.
+const MAX_TIME_DELAYED_BLOCKS: usize = 32;
+
+/// Applying a chunk for longer than this is logged along with the shard.
+const SLOW_CHUNK_APPLY_THRESHOLD: TimeDuration = TimeDuration::from_millis(500);
+
 /// apply_chunks may be called in two code paths, through process_block or through catchup_blocks
 /// When it is called through process_block, it is possible that the shard state for the next epoch
 /// has not been caught up yet, thus the two modes IsCaughtUp and NotCaughtUp.
@@ -134,6 +238,12 @@ pub struct Orphan {
     added: Instant,
 }
 
+impl Orphan {
+    pub(crate) fn new(block: MaybeValidated<Block>, provenance: Provenance) -> Self {
+        Orphan { block, provenance, added: Clock::instant() }
+    }
+}
+
 impl BlockLike for Orphan {
     fn hash(&self) -> CryptoHash {
         *self.block.hash()
@@ -165,14 +275,24 @@ pub struct OrphanBlockPool {
     /// when certain requirements are satisfied (see check_orphans)
     /// It can only be removed from this set when the orphan is removed from the pool
     orphans_requested_missing_chunks: HashSet<CryptoHash>,
+    /// A subset of `orphans_requested_missing_chunks` with the orphans that extended the head
+    /// when their missing chunks were requested
+    orphans_extending_head_requested_missing_chunks: HashSet<CryptoHash>,
     /// A map from block heights to orphan blocks at the height
     /// It's used to evict orphans when the pool is saturated
     height_idx: HashMap<BlockHeight, Vec<CryptoHash>>,
     /// A map from block hashes to orphan blocks whose prev block is the block
     /// It's used to check which orphan blocks are ready to be processed when a block is accepted
     prev_hash_idx: HashMap<CryptoHash, Vec<CryptoHash>>,
-    /// number of orphans that were evicted
-    evicted: usize,
+    /// number of orphans that were evicted because they were in the pool for too long
+    evicted_by_age: usize,
+    /// number of orphans that were evicted because they were the highest in a full pool
+    evicted_by_height: usize,
+    /// Maximum number of orphans stored with the same prev block, further ones are dropped
+    max_orphans_per_prev_hash: usize,
+    /// Number of index entries scanned when removing orphans.
+    #[cfg(test)]
+    pub(crate) index_entries_scanned: usize,
 }
 
 impl OrphanBlockPool {
@@ -180,9 +300,14 @@ impl OrphanBlockPool {
         OrphanBlockPool {
             orphans: HashMap::default(),
             orphans_requested_missing_chunks: HashSet::default(),
+            orphans_extending_head_requested_missing_chunks: HashSet::default(),
             height_idx: HashMap::default(),
             prev_hash_idx: HashMap::default(),
-            evicted: 0,
+            evicted_by_age: 0,
+            evicted_by_height: 0,
+            max_orphans_per_prev_hash: DEFAULT_MAX_ORPHANS_PER_PREV_HASH,
+            #[cfg(test)]
+            index_entries_scanned: 0,
         }
     }
 
@@ -191,17 +316,28 @@ impl OrphanBlockPool {
     }
 
     fn len_evicted(&self) -> usize {
-        self.evicted
+        self.evicted_by_age + self.evicted_by_height
     }
 
     /// Add a block to the orphan pool
-    /// `requested_missing_chunks`: whether missing chunks has been requested for the orphan
-    fn add(&mut self, orphan: Orphan, requested_missing_chunks: bool) {
+    /// `requested_missing_chunks`: whether missing chunks has been requested for the orphan,
+    /// such a request is counted as one for an orphan on a fork
+    pub(crate) fn add(&mut self, orphan: Orphan, requested_missing_chunks: bool) {
         let block_hash = *orphan.block.hash();
+        if self.orphans.contains_key(&block_hash) {
+            // Adding it again would list it twice in the indices.
+            return;
+        }
+        let prev_hash = *orphan.block.header().prev_hash();
+        let num_siblings = self.prev_hash_idx.get(&prev_hash).map_or(0, |hashes| hashes.len());
+        if num_siblings >= self.max_orphans_per_prev_hash {
+            warn!(target: "chain", "Dropping orphan {:?}, there are already {} orphans with prev block {:?}", block_hash, num_siblings, prev_hash);
+            metrics::ORPHANS_DROPPED_PER_PREV_HASH_CAP.inc();
+            return;
+        }
         let height_hashes = self.height_idx.entry(orphan.block.header().height()).or_default();
         height_hashes.push(*orphan.block.hash());
-        let prev_hash_entries =
-            self.prev_hash_idx.entry(*orphan.block.header().prev_hash()).or_default();
+        let prev_hash_entries = self.prev_hash_idx.entry(prev_hash).or_default();
         prev_hash_entries.push(block_hash);
         self.orphans.insert(block_hash, orphan);
         if requested_missing_chunks {
@@ -209,36 +345,79 @@ impl OrphanBlockPool {
         }
 
         if self.orphans.len() > MAX_ORPHAN_SIZE {
-            let old_len = self.orphans.len();
-
-            let mut removed_hashes: HashSet<CryptoHash> = HashSet::default();
-            self.orphans.retain(|_, ref mut x| {
-                let keep = x.added.elapsed() < TimeDuration::from_secs(MAX_ORPHAN_AGE_SECS);
-                if !keep {
-                    removed_hashes.insert(*x.block.hash());
-                }
-                keep
-            });
+            let expired_hashes = self
+                .orphans
+                .values()
+                .filter(|x| x.added.elapsed() >= TimeDuration::from_secs(MAX_ORPHAN_AGE_SECS))
+                .map(|x| *x.block.hash())
+                .collect::<Vec<_>>();
+            self.evicted_by_age += expired_hashes.len();
+            for hash in expired_hashes {
+                self.remove(&hash);
+            }
             let mut heights = self.height_idx.keys().cloned().collect::<Vec<u64>>();
             heights.sort_unstable();
             for h in heights.iter().rev() {
-                if let Some(hash) = self.height_idx.remove(h) {
-                    for h in hash {
-                        let _ = self.orphans.remove(&h);
-                        removed_hashes.insert(h);
+                if let Some(hashes) = self.height_idx.get(h).cloned() {
+                    self.evicted_by_height += hashes.len();
+                    for hash in hashes {
+                        self.remove(&hash);
                     }
                 }
                 if self.orphans.len() < MAX_ORPHAN_SIZE {
                     break;
                 }
             }
-            self.height_idx.retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(x)));
-            self.prev_hash_idx
-                .retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(x)));
-            self.orphans_requested_missing_chunks.retain(|x| !removed_hashes.contains(x));
+        }
+    }
 
-            self.evicted += old_len - self.orphans.len();
+    /// Removes the orphan from the pool and from the buckets of its height and prev block in the
+    /// indices, so that the cost doesn't depend on the size of the pool.
+    fn remove(&mut self, hash: &CryptoHash) -> Option<Orphan> {
+        let orphan = self.orphans.remove(hash)?;
+        #[cfg(test)]
+        {
+            self.index_entries_scanned +=
+                self.height_idx.get(&orphan.height()).map_or(0, |hashes| hashes.len())
+                    + self.prev_hash_idx.get(orphan.prev_hash()).map_or(0, |hashes| hashes.len());
+        }
+        self.orphans_requested_missing_chunks.remove(hash);
+        self.orphans_extending_head_requested_missing_chunks.remove(hash);
+        remove_from_orphan_index(&mut self.height_idx, &orphan.height(), hash);
+        remove_from_orphan_index(&mut self.prev_hash_idx, orphan.prev_hash(), hash);
+        Some(orphan)
+    }
+
+    /// Panics if the orphans and the indices disagree: every orphan must be listed exactly once
+    /// in the bucket of its height and of its prev block, the indices must have no empty buckets
+    /// and the orphans with requested missing chunks must be in the pool.
+    #[cfg(test)]
+    pub(crate) fn debug_check_consistency(&self) {
+        let mut num_by_height = 0;
+        for (height, hashes) in &self.height_idx {
+            assert!(!hashes.is_empty(), "empty orphan bucket of height {}", height);
+            for hash in hashes {
+                let orphan = self.orphans.get(hash).expect("height index refers to unknown orphan");
+                assert_eq!(orphan.height(), *height, "orphan {} in wrong bucket", hash);
+            }
+            num_by_height += hashes.len();
+        }
+        let mut num_by_prev_hash = 0;
+        for (prev_hash, hashes) in &self.prev_hash_idx {
+            assert!(!hashes.is_empty(), "empty orphan bucket of prev block {}", prev_hash);
+            for hash in hashes {
+                let orphan =
+                    self.orphans.get(hash).expect("prev hash index refers to unknown orphan");
+                assert_eq!(orphan.prev_hash(), prev_hash, "orphan {} in wrong bucket", hash);
+            }
+            num_by_prev_hash += hashes.len();
         }
+        assert_eq!(num_by_height, self.orphans.len(), "orphans missing from the height index");
+        assert_eq!(num_by_prev_hash, self.orphans.len(), "orphans missing from prev hash index");
+        assert!(self.orphans_requested_missing_chunks.iter().all(|x| self.orphans.contains_key(x)));
+        assert!(self
+            .orphans_extending_head_requested_missing_chunks
+            .is_subset(&self.orphans_requested_missing_chunks));
     }
 
     pub fn contains(&self, hash: &CryptoHash) -> bool {
@@ -262,20 +441,27 @@ impl OrphanBlockPool {
     /// This function is called when `prev_hash` is accepted, thus its children can be removed
     /// from the orphan pool and be processed.
     pub fn remove_by_prev_hash(&mut self, prev_hash: CryptoHash) -> Option<Vec<Orphan>> {
-        let mut removed_hashes: HashSet<CryptoHash> = HashSet::default();
-        let ret = self.prev_hash_idx.remove(&prev_hash).map(|hs| {
-            hs.iter()
-                .filter_map(|h| {
-                    removed_hashes.insert(*h);
-                    self.orphans_requested_missing_chunks.remove(h);
-                    self.orphans.remove(h)
-                })
-                .collect()
-        });
-
-        self.height_idx.retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(x)));
-
-        ret
+        let hashes = self.prev_hash_idx.get(&prev_hash)?.clone();
+        Some(hashes.iter().filter_map(|h| self.remove(h)).collect())
+    }
+
+    /// Removes the orphans below `height` and returns their hashes, lowest first. Used when the
+    /// blocks below `height` are gone from the store, so that the orphans can never be processed.
+    pub fn prune_below_height(&mut self, height: BlockHeight) -> Vec<CryptoHash> {
+        let mut heights =
+            self.height_idx.keys().filter(|h| **h < height).cloned().collect::<Vec<_>>();
+        heights.sort_unstable();
+        let mut pruned = vec![];
+        for h in heights {
+            if let Some(hashes) = self.height_idx.get(&h).cloned() {
+                for hash in hashes {
+                    if self.remove(&hash).is_some() {
+                        pruned.push(hash);
+                    }
+                }
+            }
+        }
+        pruned
     }
 
     /// Return a list of orphans that are among the `target_depth` immediate descendants of
@@ -287,6 +473,7 @@ impl OrphanBlockPool {
     ) -> Vec<CryptoHash> {
         let mut _visited = HashSet::new();
 
+        let max_orphans = MAX_ORPHANS_WITHIN_DEPTH_PER_HEIGHT * target_depth as usize;
         let mut res = vec![];
         let mut queue = vec![(parent_hash, 0)];
         while let Some((prev_hash, depth)) = queue.pop() {
@@ -302,25 +489,108 @@ impl OrphanBlockPool {
                 }
             }
 
-            // probably something serious went wrong here because there shouldn't be so many forks
-            assert!(
-                res.len() <= 100 * target_depth as usize,
-                "found too many orphans {:?}, probably something is wrong with the chain",
-                res
-            );
+            // There shouldn't be so many forks, but the orphans come from peers, so don't trust
+            // them to be reasonable.
+            if res.len() > max_orphans {
+                warn!(target: "chain", "Found too many orphans within depth {} of {:?}, only {} of them are used", target_depth, parent_hash, max_orphans);
+                metrics::ORPHAN_FANOUT_TRUNCATIONS.inc();
+                res.truncate(max_orphans);
+                break;
+            }
         }
         res
     }
 
-    /// Returns true if the block has not been requested yet and the number of orphans
-    /// for which we have requested missing chunks have not exceeded MAX_ORPHAN_MISSING_CHUNKS
-    fn can_request_missing_chunks_for_orphan(&self, block_hash: &CryptoHash) -> bool {
-        self.orphans_requested_missing_chunks.len() < MAX_ORPHAN_MISSING_CHUNKS
-            && !self.orphans_requested_missing_chunks.contains(block_hash)
+    /// Returns true if the block has not been requested yet and the number of orphans of the
+    /// same kind for which we have requested missing chunks allows another request. Orphans
+    /// extending the head share up to NUM_ORPHAN_ANCESTORS_CHECK requests per shard, while
+    /// orphans on forks share MAX_ORPHAN_MISSING_CHUNKS requests.
+    pub(crate) fn can_request_missing_chunks_for_orphan(
+        &self,
+        block_hash: &CryptoHash,
+        extends_head: bool,
+        num_shards: NumShards,
+    ) -> bool {
+        if self.orphans_requested_missing_chunks.contains(block_hash) {
+            return false;
+        }
+        let num_head_extension_requests =
+            self.orphans_extending_head_requested_missing_chunks.len();
+        if extends_head {
+            let max_requests = MAX_ORPHAN_MISSING_CHUNKS
+                .max(NUM_ORPHAN_ANCESTORS_CHECK as usize * num_shards as usize);
+            num_head_extension_requests < max_requests
+        } else {
+            self.orphans_requested_missing_chunks.len() - num_head_extension_requests
+                < MAX_ORPHAN_MISSING_CHUNKS
+        }
     }
 
-    fn mark_missing_chunks_requested_for_orphan(&mut self, block_hash: CryptoHash) {
+    /// Records that missing chunks were requested for the orphan, `extends_head` tells which
+    /// limit the request is counted against. Does nothing if the orphan is not in the pool.
+    pub(crate) fn mark_missing_chunks_requested_for_orphan(
+        &mut self,
+        block_hash: CryptoHash,
+        extends_head: bool,
+    ) {
+        if !self.orphans.contains_key(&block_hash) {
+            return;
+        }
         self.orphans_requested_missing_chunks.insert(block_hash);
+        if extends_head {
+            self.orphans_extending_head_requested_missing_chunks.insert(block_hash);
+        }
+    }
+
+    pub fn set_max_orphans_per_prev_hash(&mut self, max_orphans_per_prev_hash: usize) {
+        self.max_orphans_per_prev_hash = max_orphans_per_prev_hash;
+    }
+
+    /// Lists the orphans in the pool with the prev blocks they are waiting for, and counts the
+    /// evicted orphans by the reason of their eviction.
+    pub fn summary(&self) -> OrphanPoolSummaryView {
+        let mut orphans = self
+            .orphans
+            .values()
+            .map(|orphan| OrphanView {
+                hash: orphan.hash(),
+                height: orphan.height(),
+                prev_hash: *orphan.prev_hash(),
+                requested_missing_chunks: self
+                    .orphans_requested_missing_chunks
+                    .contains(orphan.block.hash()),
+                age_ms: orphan.added.elapsed().as_millis() as u64,
+            })
+            .collect::<Vec<_>>();
+        orphans.sort_by_key(|orphan| (orphan.height, orphan.hash));
+        OrphanPoolSummaryView {
+            num_orphans: orphans.len(),
+            orphans_per_height: self
+                .height_idx
+                .iter()
+                .map(|(height, hashes)| (*height, hashes.len()))
+                .collect(),
+            oldest_orphan_age_ms: orphans.iter().map(|orphan| orphan.age_ms).max(),
+            orphans,
+            num_evicted: self.len_evicted(),
+            num_evicted_by_age: self.evicted_by_age,
+            num_evicted_by_height: self.evicted_by_height,
+        }
+    }
+}
+
+/// Removes the orphan from the bucket of `key` of an orphan pool index, and the bucket if it
+/// becomes empty.
+fn remove_from_orphan_index<K: Hash + Eq>(
+    index: &mut HashMap<K, Vec<CryptoHash>>,
+    key: &K,
+    hash: &CryptoHash,
+) {
+    if let Some(hashes) = index.get_mut(key) {
+        hashes.retain(|x| x != hash);
+        if hashes.is_empty() {
+            index.remove(key);
+        }
     }
 }
 
@@ -346,2642 +616,6286 @@ pub struct OrphanMissingChunks {
     pub requestor_block_hash: CryptoHash,
 }
 
-/// Provides view on the current chain state
-/// Both Chain and ChainUpdate implement this trait,
-/// to avoid duplicate functions
-pub trait ChainAccess {
-    fn orphans(&self) -> &OrphanBlockPool;
-    fn blocks_with_missing_chunks(&self) -> &MissingChunksPool<Orphan>;
-    fn chain_store(&self) -> &dyn ChainStoreAccess;
+/// State changes of a block that still have to be applied to the split states of a shard.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitStateBacklogEntry {
+    pub block_hash: CryptoHash,
+    pub shard_id: ShardId,
+    /// Number of consolidated state changes waiting to be applied.
+    pub num_changes: usize,
 }
 
-/// Check if block header is known
-/// Returns Err(Error) if any error occurs when checking store
-///         Ok(Err(BlockKnownError)) if the block header is known
-///         Ok(Ok()) otherwise
-pub fn check_header_known(
-    chain: &dyn ChainAccess,
-    header: &BlockHeader,
-) -> Result<Result<(), BlockKnownError>, Error> {
-    let header_head = chain.chain_store().header_head()?;
-    if header.hash() == &header_head.last_block_hash
-        || header.hash() == &header_head.prev_block_hash
-    {
-        return Ok(Err(BlockKnownError::KnownInHeader));
-    }
-    check_known_store(chain, header.hash())
+/// Chunk extra produced by replaying a block which differs from the one stored for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    pub height: BlockHeight,
+    pub block_hash: CryptoHash,
+    pub shard_id: ShardId,
+    /// `None` if there is no chunk extra stored for the block and shard.
+    pub stored: Option<ChunkExtra>,
+    pub replayed: ChunkExtra,
 }
 
-/// Check if this block is in the store already.
-/// Returns Err(Error) if any error occurs when checking store
-///         Ok(Err(BlockKnownError)) if the block is in the store
-///         Ok(Ok()) otherwise
-fn check_known_store(
-    chain: &dyn ChainAccess,
-    block_hash: &CryptoHash,
-) -> Result<Result<(), BlockKnownError>, Error> {
-    if chain.chain_store().block_exists(block_hash)? {
-        Ok(Err(BlockKnownError::KnownInStore))
-    } else {
-        // Not yet processed this block, we can proceed.
-        Ok(Ok(()))
-    }
+/// Result of replaying a range of blocks with `Chain::replay_blocks`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub blocks_replayed: u64,
+    /// Number of chunk extras compared against the stored ones.
+    pub chunks_compared: u64,
+    /// First divergence found for each shard.
+    pub divergences: BTreeMap<ShardId, ReplayDivergence>,
 }
 
-/// Check if block is known: head, orphan or in store.
-/// Returns Err(Error) if any error occurs when checking store
-///         Ok(Err(BlockKnownError)) if the block is known
-///         Ok(Ok()) otherwise
-pub fn check_known(
-    chain: &dyn ChainAccess,
-    block_hash: &CryptoHash,
-) -> Result<Result<(), BlockKnownError>, Error> {
-    let head = chain.chain_store().head()?;
-    // Quick in-memory check for fast-reject any block handled recently.
-    if block_hash == &head.last_block_hash || block_hash == &head.prev_block_hash {
-        return Ok(Err(BlockKnownError::KnownInHead));
-    }
-    // Check if this block is in the set of known orphans.
-    if chain.orphans().contains(block_hash) {
-        return Ok(Err(BlockKnownError::KnownInOrphan));
-    }
-    if chain.blocks_with_missing_chunks().contains(block_hash) {
-        return Ok(Err(BlockKnownError::KnownInMissingChunks));
-    }
-    check_known_store(chain, block_hash)
+/// Result of adding missing epoch manager data with `Chain::repair_epoch_data`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub headers_checked: u64,
+    /// Headers whose epoch manager data was added, in the order of repair.
+    pub repaired: Vec<CryptoHash>,
 }
 
-/// Facade to the blockchain block processing and storage.
-/// Provides current view on the state according to the chain state.
-pub struct Chain {
-    store: ChainStore,
-    pub runtime_adapter: Arc<dyn RuntimeAdapter>,
-    orphans: OrphanBlockPool,
-    pub blocks_with_missing_chunks: MissingChunksPool<Orphan>,
-    genesis: Block,
-    pub transaction_validity_period: NumBlocks,
-    pub epoch_length: BlockHeightDelta,
-    /// Block economics, relevant to changes when new block must be produced.
-    pub block_economics_config: BlockEconomicsConfig,
-    pub doomslug_threshold_mode: DoomslugThresholdMode,
-    pending_states_to_patch: Option<Vec<StateRecord>>,
-    pub blocks_delay_tracker: BlocksDelayTracker,
+/// Result of pruning with `Chain::garbage_collect_to_height`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Tail after pruning.
+    pub tail: BlockHeight,
+    pub blocks_removed: NumBlocks,
+    /// Whether the tail reached the target. If not, the call ran out of its block budget or
+    /// stopped at a fork, and can be repeated.
+    pub reached_target: bool,
 }
 
-impl ChainAccess for Chain {
-    fn orphans(&self) -> &OrphanBlockPool {
-        &self.orphans
-    }
+/// What `Chain::clear_data` would delete, computed by `Chain::simulate_gc`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GcPlan {
+    /// Fork blocks in the order Forks Clearing would delete them.
+    pub fork_blocks: Vec<CryptoHash>,
+    /// Heights of the canonical blocks Canonical Chain Clearing would delete. The tail would
+    /// move to the end of the range.
+    pub canonical_range: Range<BlockHeight>,
+    /// Estimated number of keys deleted with the blocks and chunks, not counting the state and
+    /// the execution outcomes.
+    pub estimated_keys: u64,
+}
 
-    fn blocks_with_missing_chunks(&self) -> &MissingChunksPool<Orphan> {
-        &self.blocks_with_missing_chunks
+/// Deletions accounted for so far by `Chain::simulate_gc`.
+struct GcSimulation {
+    plan: GcPlan,
+    /// Fork blocks already accounted for.
+    removed: HashSet<CryptoHash>,
+    /// Refcounts as they would be after the deletions accounted for.
+    refcounts: HashMap<CryptoHash, u64>,
+    gc_blocks_remaining: NumBlocks,
+}
+
+impl GcSimulation {
+    fn refcount(&self, store: &mut ChainStore, block_hash: &CryptoHash) -> Result<u64, Error> {
+        match self.refcounts.get(block_hash) {
+            Some(refcount) => Ok(*refcount),
+            None => Ok(*store.get_block_refcount(block_hash)?),
+        }
     }
 
-    fn chain_store(&self) -> &dyn ChainStoreAccess {
-        &self.store
+    /// Accounts for the fork blocks `clear_forks_data` would delete at `height`.
+    fn clear_forks(&mut self, store: &mut ChainStore, height: BlockHeight) -> Result<(), Error> {
+        let blocks_current_height = match store.get_ordered_block_hashes_by_height(height) {
+            Ok(blocks_current_height) => blocks_current_height,
+            Err(_) => return Ok(()),
+        };
+        for block_hash in blocks_current_height {
+            // Blocks deleted when walking back from a higher fork are gone from their height.
+            if self.removed.contains(&block_hash) {
+                continue;
+            }
+            let mut current_hash = block_hash;
+            loop {
+                if self.gc_blocks_remaining == 0 {
+                    return Ok(());
+                }
+                if self.refcount(store, &current_hash)? != 0 {
+                    // Block of `current_hash` is an ancestor for some other blocks, stopping
+                    break;
+                }
+                let prev_hash = *store.get_block_header(&current_hash)?.prev_hash();
+                let prev_block_refcount = self.refcount(store, &prev_hash)?;
+                self.refcounts.insert(prev_hash, prev_block_refcount.saturating_sub(1));
+                self.plan.estimated_keys += estimate_block_gc_keys(store, &current_hash)?;
+                self.plan.fork_blocks.push(current_hash);
+                self.removed.insert(current_hash);
+                self.gc_blocks_remaining -= 1;
+                current_hash = prev_hash;
+            }
+        }
+        Ok(())
     }
 }
 
-impl Chain {
-    pub fn new_for_view_client(
-        runtime_adapter: Arc<dyn RuntimeAdapter>,
-        chain_genesis: &ChainGenesis,
-        doomslug_threshold_mode: DoomslugThresholdMode,
-    ) -> Result<Chain, Error> {
-        let (store, state_roots) = runtime_adapter.genesis_state();
-        let store = ChainStore::new(store, chain_genesis.height);
-        let genesis_chunks = genesis_chunks(
-            state_roots,
-            runtime_adapter.num_shards(&EpochId::default())?,
-            chain_genesis.gas_limit,
-            chain_genesis.height,
-            chain_genesis.protocol_version,
-        );
-        let genesis = Block::genesis(
-            chain_genesis.protocol_version,
-            genesis_chunks.into_iter().map(|chunk| chunk.take_header()).collect(),
-            chain_genesis.time,
-            chain_genesis.height,
-            chain_genesis.min_gas_price,
-            chain_genesis.total_supply,
-            Chain::compute_bp_hash(
-                &*runtime_adapter,
-                EpochId::default(),
-                EpochId::default(),
-                &CryptoHash::default(),
-            )?,
+/// Computes what `Chain::clear_data` would delete with the same budget, without modifying the
+/// store, so it can run on a read-only store. Follows Forks Clearing and Canonical Chain
+/// Clearing, tracking the refcounts the deletions would change instead of writing them.
+pub fn simulate_gc(
+    store: &mut ChainStore,
+    runtime_adapter: &dyn RuntimeAdapter,
+    gc_blocks_limit: NumBlocks,
+) -> Result<GcPlan, Error> {
+    let head = store.head()?;
+    let tail = store.tail()?;
+    let gc_stop_height = runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+
+    if gc_stop_height > head.height {
+        return Err(
+            ErrorKind::GCError("gc_stop_height cannot be larger than head.height".into()).into()
         );
-        Ok(Chain {
-            store,
-            runtime_adapter,
-            orphans: OrphanBlockPool::new(),
-            blocks_with_missing_chunks: MissingChunksPool::new(),
-            genesis: genesis,
-            transaction_validity_period: chain_genesis.transaction_validity_period,
-            epoch_length: chain_genesis.epoch_length,
-            block_economics_config: BlockEconomicsConfig::from(chain_genesis),
-            doomslug_threshold_mode,
-            pending_states_to_patch: None,
-            blocks_delay_tracker: BlocksDelayTracker::default(),
-        })
+    }
+    let epoch_change = store.get_block_header(&head.prev_block_hash)?.epoch_id() != &head.epoch_id;
+    let mut fork_tail = store.fork_tail()?;
+    if epoch_change && fork_tail < gc_stop_height {
+        fork_tail = gc_stop_height;
+    }
+    let mut simulation = GcSimulation {
+        plan: GcPlan { fork_blocks: vec![], canonical_range: tail..tail, estimated_keys: 0 },
+        removed: HashSet::new(),
+        refcounts: HashMap::new(),
+        gc_blocks_remaining: gc_blocks_limit,
+    };
+
+    // Forks Cleaning
+    let stop_height = std::cmp::max(tail, fork_tail.saturating_sub(GC_FORK_CLEAN_STEP));
+    for height in (stop_height..fork_tail).rev() {
+        simulation.clear_forks(store, height)?;
+        if simulation.gc_blocks_remaining == 0 {
+            return Ok(simulation.plan);
+        }
     }
 
-    pub fn new(
-        runtime_adapter: Arc<dyn RuntimeAdapter>,
-        chain_genesis: &ChainGenesis,
-        doomslug_threshold_mode: DoomslugThresholdMode,
-    ) -> Result<Chain, Error> {
-        // Get runtime initial state and create genesis block out of it.
-        let (store, state_roots) = runtime_adapter.genesis_state();
-        let mut store = ChainStore::new(store, chain_genesis.height);
-        let genesis_chunks = genesis_chunks(
-            state_roots.clone(),
-            runtime_adapter.num_shards(&EpochId::default())?,
-            chain_genesis.gas_limit,
-            chain_genesis.height,
-            chain_genesis.protocol_version,
-        );
-        let genesis = Block::genesis(
-            chain_genesis.protocol_version,
-            genesis_chunks.iter().map(|chunk| chunk.cloned_header()).collect(),
-            chain_genesis.time,
-            chain_genesis.height,
-            chain_genesis.min_gas_price,
-            chain_genesis.total_supply,
-            Chain::compute_bp_hash(
-                &*runtime_adapter,
-                EpochId::default(),
-                EpochId::default(),
-                &CryptoHash::default(),
-            )?,
-        );
+    // Canonical Chain Clearing. The block cleared when processing a height is the previous
+    // block of the canonical block at that height.
+    let mut chunk_tail = store.chunk_tail()?;
+    for height in tail + 1..gc_stop_height {
+        if simulation.gc_blocks_remaining == 0 {
+            break;
+        }
+        let blocks_current_height =
+            store.get_ordered_block_hashes_by_height(height).unwrap_or_default();
+        if let Some(block_hash) =
+            blocks_current_height.iter().find(|hash| !simulation.removed.contains(hash))
+        {
+            let prev_hash = *store.get_block_header(block_hash)?.prev_hash();
+            let prev_block_refcount = simulation.refcount(store, &prev_hash)?;
+            if prev_block_refcount > 1 {
+                // Block of `prev_hash` starts a Fork, stopping
+                break;
+            } else if prev_block_refcount == 1 {
+                simulation.plan.estimated_keys += estimate_block_gc_keys(store, &prev_hash)?;
+                // Chunks are cleared up to the tail or the lowest chunk of the block.
+                let min_chunk_height = store
+                    .get_block(&prev_hash)?
+                    .chunks()
+                    .iter()
+                    .map(|chunk_header| chunk_header.height_created())
+                    .fold(height - 1, std::cmp::min);
+                for chunk_height in chunk_tail..min_chunk_height {
+                    simulation.plan.estimated_keys += estimate_chunk_gc_keys(store, chunk_height)?;
+                }
+                chunk_tail = min_chunk_height;
+                simulation.gc_blocks_remaining -= 1;
+            } else {
+                return Err(ErrorKind::GCError(
+                    "block on canonical chain shouldn't have refcount 0".into(),
+                )
+                .into());
+            }
+        }
+        simulation.plan.canonical_range.end = height;
+    }
+    Ok(simulation.plan)
+}
 
-        // Check if we have a head in the store, otherwise pick genesis block.
-        let mut store_update = store.store_update();
-        let head_res = store_update.head();
-        let head: Tip;
-        match head_res {
-            Ok(h) => {
-                head = h;
+/// Estimates the number of keys `ChainStoreUpdate::clear_block_data` deletes for the block,
+/// besides the chunks, the state and the execution outcomes.
+fn estimate_block_gc_keys(store: &mut ChainStore, block_hash: &CryptoHash) -> Result<u64, Error> {
+    let block = store.get_block(block_hash)?;
+    let num_shards = block.header().chunk_mask().len() as u64;
+    let num_new_chunks = block
+        .chunks()
+        .iter()
+        .filter(|chunk_header| chunk_header.height_included() == block.header().height())
+        .count() as u64;
+    Ok(GC_KEYS_PER_BLOCK + num_shards * GC_KEYS_PER_SHARD + num_new_chunks * GC_KEYS_PER_NEW_CHUNK)
+}
 
-                // Check that genesis in the store is the same as genesis given in the config.
-                let genesis_hash = store_update.get_block_hash_by_height(chain_genesis.height)?;
-                if &genesis_hash != genesis.hash() {
-                    return Err(ErrorKind::Other(format!(
-                        "Genesis mismatch between storage and config: {:?} vs {:?}",
-                        genesis_hash,
-                        genesis.hash()
-                    ))
-                    .into());
-                }
+/// Estimates the number of keys `ChainStoreUpdate::clear_chunk_data_and_headers` deletes for the
+/// chunks created at `height`.
+fn estimate_chunk_gc_keys(store: &mut ChainStore, height: BlockHeight) -> Result<u64, Error> {
+    let mut keys = GC_KEYS_PER_CHUNK_HEIGHT;
+    for chunk_hash in store.get_all_chunk_hashes_by_height(height)? {
+        let chunk = store.get_chunk(&chunk_hash)?;
+        keys +=
+            GC_KEYS_PER_CHUNK + chunk.transactions().len() as u64 + chunk.receipts().len() as u64;
+    }
+    Ok(keys)
+}
 
-                // Check we have the header corresponding to the header_head.
-                let header_head = store_update.header_head()?;
-                if store_update.get_block_header(&header_head.last_block_hash).is_err() {
-                    // Reset header head and "sync" head to be consistent with current block head.
-                    store_update.save_header_head_if_not_challenged(&head)?;
-                }
-                // TODO: perform validation that latest state in runtime matches the stored chain.
-            }
-            Err(err) => match err.kind() {
-                ErrorKind::DBNotFoundErr(_) => {
-                    for chunk in genesis_chunks {
-                        store_update.save_chunk(chunk.clone());
-                    }
-                    store_update.merge(runtime_adapter.add_validator_proposals(
-                        BlockHeaderInfo::new(
-                            genesis.header(),
-                            // genesis height is considered final
-                            chain_genesis.height,
-                        ),
-                    )?);
-                    store_update.save_block_header(genesis.header().clone())?;
-                    store_update.save_block(genesis.clone());
-                    store_update
-                        .save_block_extra(genesis.hash(), BlockExtra { challenges_result: vec![] });
+/// Height gap above which heads lagging behind each other are reported by `SyncDiagnostics`.
+pub const SYNC_DIAGNOSTICS_HEIGHT_GAP_THRESHOLD: BlockHeightDelta = 10;
 
-                    for (chunk_header, state_root) in
-                        genesis.chunks().iter().zip(state_roots.iter())
-                    {
-                        store_update.save_chunk_extra(
-                            genesis.hash(),
-                            &runtime_adapter
-                                .shard_id_to_uid(chunk_header.shard_id(), &EpochId::default())?,
-                            ChunkExtra::new(
-                                state_root,
-                                CryptoHash::default(),
-                                vec![],
-                                0,
-                                chain_genesis.gas_limit,
-                                0,
-                            ),
-                        );
-                    }
+/// Catchup of the state for the next epoch, started at the first block of the epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatchupDiagnostics {
+    pub sync_hash: CryptoHash,
+    pub num_shards: usize,
+    /// Number of blocks waiting for the catchup to be applied.
+    pub num_blocks_to_catchup: usize,
+}
 
-                    head = Tip::from_header(genesis.header());
-                    store_update.save_head(&head)?;
-                    store_update.save_final_head(&head)?;
+/// Disagreement between the records of blocks waiting to be caught up and the stored data,
+/// see `Chain::validate_catchup_bookkeeping`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CatchupBookkeepingIssue {
+    /// A block queued for catchup is not stored.
+    MissingBlock { prev_hash: CryptoHash, hash: CryptoHash },
+    /// A block is queued after a block which is not its previous block.
+    PrevHashMismatch { prev_hash: CryptoHash, hash: CryptoHash },
+    /// A block is queued after a block which is not queued itself, but there is no state sync
+    /// started at it either.
+    MissingStateSyncInfo { hash: CryptoHash },
+    /// A block is queued after a block from another epoch.
+    EpochMismatch { prev_hash: CryptoHash, hash: CryptoHash },
+    /// A state sync was started at a block which is not queued for catchup.
+    UnqueuedStateSyncInfo { sync_hash: CryptoHash },
+}
 
-                    info!(target: "chain", "Init: saved genesis: {:?} / {:?}", genesis.hash(), state_roots);
-                }
-                e => return Err(e.into()),
-            },
+/// Likely reason of the node not making progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncStallCause {
+    /// Header head is far ahead of the head, blocks are not downloaded or fail to be processed.
+    BlocksBehindHeaders,
+    /// Blocks are waiting for chunks which were not received.
+    MissingChunks,
+    /// Blocks are waiting for their previous blocks.
+    Orphans,
+    /// State for the next epoch is still being caught up.
+    PendingCatchup,
+    /// State sync has downloaded headers but hasn't finished.
+    UnfinishedStateSync,
+    /// Epoch of the head block is unknown to the runtime.
+    UnknownHeadEpoch,
+    /// Records of blocks waiting to be caught up are inconsistent, catchup may never finish.
+    InconsistentCatchupBookkeeping,
+    /// Head is far ahead of the last final block.
+    FinalityLag,
+}
+
+/// Outcome of the last garbage collection run, see `Chain::clear_data`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GcRunInfo {
+    /// Height the run was allowed to collect the blocks below.
+    pub gc_stop_height: BlockHeight,
+    /// Tail of the chain once the run finished.
+    pub tail: BlockHeight,
+    /// Number of blocks collected by the run, both on forks and on the canonical chain.
+    pub num_blocks_collected: NumBlocks,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Chain conditions which usually explain why a node is not syncing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncDiagnostics {
+    pub head_height: BlockHeight,
+    pub header_head_height: BlockHeight,
+    pub final_head_height: BlockHeight,
+    pub num_orphans: usize,
+    pub num_orphans_evicted: usize,
+    pub num_blocks_with_missing_chunks: usize,
+    pub pending_catchups: Vec<CatchupDiagnostics>,
+    pub catchup_bookkeeping_issues: Vec<CatchupBookkeepingIssue>,
+    /// Sync hashes and shards of state syncs which have a header but didn't reach the head yet.
+    pub unfinished_state_syncs: Vec<(CryptoHash, ShardId)>,
+    /// Number of blocks applied without the shards deferred to catchup.
+    pub num_partially_applied_blocks: usize,
+    /// Peers the state parts of the state syncs in progress were downloaded from, the slowest
+    /// first, see `Chain::state_part_provenance_stats`.
+    pub state_part_providers: Vec<PeerStatePartStats>,
+    pub tail: BlockHeight,
+    pub chunk_tail: BlockHeight,
+    pub fork_tail: BlockHeight,
+    /// Last garbage collection run since the node started, if any.
+    pub last_gc_run: Option<GcRunInfo>,
+    pub head_epoch_known: bool,
+}
+
+impl SyncDiagnostics {
+    pub fn header_head_gap(&self) -> BlockHeightDelta {
+        self.header_head_height.saturating_sub(self.head_height)
+    }
+
+    pub fn finality_lag(&self) -> BlockHeightDelta {
+        self.head_height.saturating_sub(self.final_head_height)
+    }
+
+    /// Returns the conditions which look abnormal, most specific first.
+    pub fn likely_causes(&self) -> Vec<SyncStallCause> {
+        let mut causes = vec![];
+        if !self.head_epoch_known {
+            causes.push(SyncStallCause::UnknownHeadEpoch);
         }
-        store_update.commit()?;
+        if !self.catchup_bookkeeping_issues.is_empty() {
+            causes.push(SyncStallCause::InconsistentCatchupBookkeeping);
+        }
+        if self.num_blocks_with_missing_chunks > 0 {
+            causes.push(SyncStallCause::MissingChunks);
+        }
+        if !self.pending_catchups.is_empty() {
+            causes.push(SyncStallCause::PendingCatchup);
+        }
+        if !self.unfinished_state_syncs.is_empty() {
+            causes.push(SyncStallCause::UnfinishedStateSync);
+        }
+        if self.num_orphans > 0 {
+            causes.push(SyncStallCause::Orphans);
+        }
+        if self.header_head_gap() > SYNC_DIAGNOSTICS_HEIGHT_GAP_THRESHOLD {
+            causes.push(SyncStallCause::BlocksBehindHeaders);
+        }
+        if self.finality_lag() > SYNC_DIAGNOSTICS_HEIGHT_GAP_THRESHOLD {
+            causes.push(SyncStallCause::FinalityLag);
+        }
+        causes
+    }
+}
 
-        info!(target: "chain", "Init: head @ {} [{}]", head.height, head.last_block_hash);
+impl fmt::Display for SyncDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "causes: {:?}, head: {}, header head: {}, final head: {}, orphans: {}, \
+             blocks with missing chunks: {}, pending catchups: {}, \
+             catchup bookkeeping issues: {}, unfinished state syncs: {}, \
+             partially applied blocks: {}, state part providers: {}, tail: {}, \
+             last gc run: {:?}, head epoch known: {}",
+            self.likely_causes(),
+            self.head_height,
+            self.header_head_height,
+            self.final_head_height,
+            self.num_orphans,
+            self.num_blocks_with_missing_chunks,
+            self.pending_catchups.len(),
+            self.catchup_bookkeeping_issues.len(),
+            self.unfinished_state_syncs.len(),
+            self.num_partially_applied_blocks,
+            self.state_part_providers.len(),
+            self.tail,
+            self.last_gc_run,
+            self.head_epoch_known,
+        )
+    }
+}
 
-        Ok(Chain {
-            store,
-            runtime_adapter,
-            orphans: OrphanBlockPool::new(),
-            blocks_with_missing_chunks: MissingChunksPool::new(),
-            genesis: genesis.clone(),
-            transaction_validity_period: chain_genesis.transaction_validity_period,
-            epoch_length: chain_genesis.epoch_length,
-            block_economics_config: BlockEconomicsConfig::from(chain_genesis),
-            doomslug_threshold_mode,
-            pending_states_to_patch: None,
-            blocks_delay_tracker: BlocksDelayTracker::default(),
-        })
+impl From<SyncDiagnostics> for SyncDiagnosticsView {
+    fn from(diagnostics: SyncDiagnostics) -> Self {
+        Self {
+            likely_causes: diagnostics
+                .likely_causes()
+                .into_iter()
+                .map(|cause| format!("{:?}", cause))
+                .collect(),
+            head_height: diagnostics.head_height,
+            header_head_height: diagnostics.header_head_height,
+            final_head_height: diagnostics.final_head_height,
+            num_orphans: diagnostics.num_orphans,
+            num_orphans_evicted: diagnostics.num_orphans_evicted,
+            num_blocks_with_missing_chunks: diagnostics.num_blocks_with_missing_chunks,
+            pending_catchups: diagnostics
+                .pending_catchups
+                .into_iter()
+                .map(|catchup| CatchupDiagnosticsView {
+                    sync_hash: catchup.sync_hash,
+                    num_shards: catchup.num_shards,
+                    num_blocks_to_catchup: catchup.num_blocks_to_catchup,
+                })
+                .collect(),
+            catchup_bookkeeping_issues: diagnostics
+                .catchup_bookkeeping_issues
+                .into_iter()
+                .map(|issue| format!("{:?}", issue))
+                .collect(),
+            unfinished_state_syncs: diagnostics.unfinished_state_syncs,
+            num_partially_applied_blocks: diagnostics.num_partially_applied_blocks,
+            state_part_providers: diagnostics
+                .state_part_providers
+                .into_iter()
+                .map(|stats| StatePartProviderView {
+                    peer_id: stats.peer_id,
+                    num_parts: stats.num_parts,
+                    average_latency_ms: stats.average_latency.as_millis() as u64,
+                    max_latency_ms: stats.max_latency.as_millis() as u64,
+                })
+                .collect(),
+            tail: diagnostics.tail,
+            chunk_tail: diagnostics.chunk_tail,
+            fork_tail: diagnostics.fork_tail,
+            last_gc_run: diagnostics.last_gc_run.map(|run| GcRunView {
+                gc_stop_height: run.gc_stop_height,
+                tail: run.tail,
+                num_blocks_collected: run.num_blocks_collected,
+                finished_at: run.finished_at,
+            }),
+            head_epoch_known: diagnostics.head_epoch_known,
+        }
     }
+}
 
-    #[cfg(feature = "test_features")]
-    pub fn adv_disable_doomslug(&mut self) {
-        self.doomslug_threshold_mode = DoomslugThresholdMode::NoApprovals
+/// Stage of the sync the node is in, see `SyncProgressEstimate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPhase {
+    /// Header head is behind the highest height known in the network.
+    HeaderSync,
+    /// State parts are being downloaded.
+    StateSync,
+    /// Head is behind the header head or the highest height known in the network.
+    BlockSync,
+    /// Head reached the highest height known in the network.
+    Synced,
+}
+
+/// Estimated progress of the sync, see `Chain::estimate_sync_progress`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncProgressEstimate {
+    pub phase: SyncPhase,
+    /// Progress of the current phase, between 0 and 100.
+    pub percent: f64,
+    /// Time until the node is synced, `None` if the throughput it depends on isn't known.
+    pub eta: Option<TimeDuration>,
+}
+
+impl From<SyncProgressEstimate> for SyncProgressView {
+    fn from(estimate: SyncProgressEstimate) -> Self {
+        Self {
+            phase: format!("{:?}", estimate.phase),
+            percent: estimate.percent,
+            eta_secs: estimate.eta.map(|eta| eta.as_secs()),
+        }
     }
+}
 
-    pub fn compute_collection_hash<T: BorshSerialize>(elems: Vec<T>) -> Result<CryptoHash, Error> {
-        Ok(hash(&elems.try_to_vec()?))
+/// Chain state and throughput the sync progress is estimated from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncProgressInputs {
+    pub genesis_height: BlockHeight,
+    pub head_height: BlockHeight,
+    pub header_head_height: BlockHeight,
+    /// Highest height known in the network, as reported by the peers.
+    pub network_height: BlockHeight,
+    /// Expected rate of downloading headers.
+    pub headers_per_second: u64,
+    /// Recent rate of applying blocks, `None` if it couldn't be measured.
+    pub blocks_applied_per_second: Option<f64>,
+    /// Parts downloaded so far by the state syncs in progress.
+    pub state_parts_downloaded: u64,
+    /// Total number of parts of the state syncs in progress.
+    pub state_parts_total: u64,
+}
+
+impl SyncProgressInputs {
+    pub fn estimate(&self) -> SyncProgressEstimate {
+        let target_height = std::cmp::max(self.network_height, self.header_head_height);
+        let blocks_eta = time_at_rate(
+            target_height.saturating_sub(self.head_height),
+            self.blocks_applied_per_second,
+        );
+        if self.state_parts_downloaded < self.state_parts_total {
+            // The rate of downloading state parts isn't measured.
+            return SyncProgressEstimate {
+                phase: SyncPhase::StateSync,
+                percent: percent_done(self.state_parts_downloaded, self.state_parts_total),
+                eta: None,
+            };
+        }
+        if self.network_height > self.header_head_height {
+            let headers_eta = time_at_rate(
+                self.network_height - self.header_head_height,
+                Some(self.headers_per_second as f64),
+            );
+            return SyncProgressEstimate {
+                phase: SyncPhase::HeaderSync,
+                percent: self.height_percent(self.header_head_height, self.network_height),
+                eta: headers_eta.zip(blocks_eta).map(|(headers, blocks)| headers + blocks),
+            };
+        }
+        if self.head_height < target_height {
+            return SyncProgressEstimate {
+                phase: SyncPhase::BlockSync,
+                percent: self.height_percent(self.head_height, target_height),
+                eta: blocks_eta,
+            };
+        }
+        SyncProgressEstimate {
+            phase: SyncPhase::Synced,
+            percent: 100.,
+            eta: Some(TimeDuration::from_secs(0)),
+        }
     }
 
-    pub fn compute_bp_hash(
-        runtime_adapter: &dyn RuntimeAdapter,
-        epoch_id: EpochId,
-        prev_epoch_id: EpochId,
-        last_known_hash: &CryptoHash,
-    ) -> Result<CryptoHash, Error> {
-        let bps = runtime_adapter.get_epoch_block_producers_ordered(&epoch_id, last_known_hash)?;
-        let protocol_version = runtime_adapter.get_epoch_protocol_version(&prev_epoch_id)?;
-        if checked_feature!("stable", BlockHeaderV3, protocol_version) {
-            let validator_stakes = bps.into_iter().map(|(bp, _)| bp).collect();
-            Chain::compute_collection_hash(validator_stakes)
-        } else {
-            let validator_stakes = bps.into_iter().map(|(bp, _)| bp.into_v1()).collect();
-            Chain::compute_collection_hash(validator_stakes)
+    /// Progress from the genesis towards `target_height`.
+    fn height_percent(&self, height: BlockHeight, target_height: BlockHeight) -> f64 {
+        percent_done(
+            height.saturating_sub(self.genesis_height),
+            target_height.saturating_sub(self.genesis_height),
+        )
+    }
+}
+
+fn percent_done(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        100.
+    } else {
+        std::cmp::min(done, total) as f64 * 100. / total as f64
+    }
+}
+
+/// Time to process `remaining` items at the given rate per second, `None` if the rate is unknown
+/// or zero while there is something left to process.
+fn time_at_rate(remaining: u64, rate_per_second: Option<f64>) -> Option<TimeDuration> {
+    if remaining == 0 {
+        return Some(TimeDuration::from_secs(0));
+    }
+    match rate_per_second {
+        Some(rate) if rate > 0. && rate.is_finite() => {
+            Some(TimeDuration::from_secs_f64(remaining as f64 / rate))
         }
+        _ => None,
     }
+}
 
-    /// Creates a light client block for the last final block from perspective of some other block
-    ///
-    /// # Arguments
-    ///  * `header` - the last finalized block seen from `header` (not pushed back) will be used to
-    ///               compute the light client block
-    pub fn create_light_client_block(
-        header: &BlockHeader,
-        runtime_adapter: &dyn RuntimeAdapter,
-        chain_store: &mut dyn ChainStoreAccess,
-    ) -> Result<LightClientBlockView, Error> {
-        let final_block_header = {
-            let ret = chain_store.get_block_header(header.last_final_block())?.clone();
-            let two_ahead = chain_store.get_header_by_height(ret.height() + 2)?;
-            if two_ahead.epoch_id() != ret.epoch_id() {
-                let one_ahead = chain_store.get_header_by_height(ret.height() + 1)?;
-                if one_ahead.epoch_id() != ret.epoch_id() {
-                    let new_final_hash = *ret.last_final_block();
-                    chain_store.get_block_header(&new_final_hash)?.clone()
-                } else {
-                    let new_final_hash = *one_ahead.last_final_block();
-                    chain_store.get_block_header(&new_final_hash)?.clone()
-                }
-            } else {
-                ret
-            }
-        };
+/// Earliest heights from which each class of data can be queried from this node.
+///
+/// Different kinds of data are garbage collected with different horizons: headers are kept
+/// forever, block bodies, execution outcomes and state go together with `tail`, chunk bodies
+/// are kept down to `chunk_tail` and archival nodes only drop partial chunks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataAvailability {
+    pub headers: BlockHeight,
+    pub blocks: BlockHeight,
+    pub chunks: BlockHeight,
+    pub partial_chunks: BlockHeight,
+    pub execution_outcomes: BlockHeight,
+    pub state: BlockHeight,
+}
 
-        let next_block_producers = get_epoch_block_producers_view(
-            final_block_header.next_epoch_id(),
-            header.prev_hash(),
-            runtime_adapter,
-        )?;
+impl From<DataAvailability> for DataAvailabilityView {
+    fn from(availability: DataAvailability) -> Self {
+        Self {
+            headers: availability.headers,
+            blocks: availability.blocks,
+            chunks: availability.chunks,
+            partial_chunks: availability.partial_chunks,
+            execution_outcomes: availability.execution_outcomes,
+            state: availability.state,
+        }
+    }
+}
 
-        create_light_client_block_view(&final_block_header, chain_store, Some(next_block_producers))
+/// Progress of the download of the state of a shard for the catchup of the next epoch, see
+/// `Chain::state_download_status`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardStateDownloadStatus {
+    pub shard_id: ShardId,
+    /// Whether the state header has been downloaded.
+    pub header_stored: bool,
+    /// Parts downloaded so far, 0 until the header is downloaded.
+    pub parts_downloaded: u64,
+    /// Number of parts of the state, 0 until the header is downloaded.
+    pub parts_total: u64,
+    /// Height the state has been finalized up to, while finalization is in progress.
+    pub finalize_height: Option<BlockHeight>,
+    /// Whether the state has been finalized up to the block before the sync block.
+    pub finalized: bool,
+}
+
+/// State downloads of the shards tracked from the epoch of the sync block on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochStateDownloadStatus {
+    pub epoch_id: EpochId,
+    /// First block of the epoch, the state is downloaded as of its previous block.
+    pub sync_hash: CryptoHash,
+    pub shards: Vec<ShardStateDownloadStatus>,
+}
+
+impl From<ShardStateDownloadStatus> for ShardStateDownloadStatusView {
+    fn from(status: ShardStateDownloadStatus) -> Self {
+        Self {
+            shard_id: status.shard_id,
+            header_stored: status.header_stored,
+            parts_downloaded: status.parts_downloaded,
+            parts_total: status.parts_total,
+            finalize_height: status.finalize_height,
+            finalized: status.finalized,
+        }
     }
+}
 
-    pub fn save_block(&mut self, block: MaybeValidated<Block>) -> Result<(), Error> {
-        if self.store.get_block(block.hash()).is_ok() {
-            return Ok(());
+impl From<EpochStateDownloadStatus> for EpochStateDownloadStatusView {
+    fn from(status: EpochStateDownloadStatus) -> Self {
+        Self {
+            epoch_id: status.epoch_id,
+            sync_hash: status.sync_hash,
+            shards: status.shards.into_iter().map(Into::into).collect(),
         }
-        if let Err(e) = self.validate_block(&block) {
-            byzantine_assert!(false);
-            return Err(e.into());
+    }
+}
+
+/// Everything the node knows about a block stored at some height.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockAtHeightInfo {
+    pub hash: CryptoHash,
+    /// `None` if the header is not available.
+    pub prev_hash: Option<CryptoHash>,
+    pub epoch_id: EpochId,
+    /// `None` if the producer can't be determined, e.g. the epoch is not known anymore.
+    pub producer: Option<AccountId>,
+    /// Whether the block is on the canonical chain.
+    pub is_canonical: bool,
+    pub is_challenged: bool,
+    /// Whether the block itself is stored, as opposed to just its header.
+    pub has_body: bool,
+}
+
+impl From<BlockAtHeightInfo> for BlockAtHeightView {
+    fn from(info: BlockAtHeightInfo) -> Self {
+        Self {
+            hash: info.hash,
+            prev_hash: info.prev_hash,
+            epoch_id: info.epoch_id,
+            producer: info.producer,
+            is_canonical: info.is_canonical,
+            is_challenged: info.is_challenged,
+            has_body: info.has_body,
         }
+    }
+}
 
-        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
+/// Iterator over the blocks of the canonical chain in a range of heights, see
+/// `Chain::iter_canonical_blocks`. Reads the blocks straight from the store one at a time,
+/// bypassing the chain store caches.
+struct CanonicalBlocksIter {
+    store: Store,
+    next_height: BlockHeight,
+    /// Last height to read, inclusive.
+    last_height: BlockHeight,
+    /// Error found while checking the range, yielded before anything else.
+    error: Option<Error>,
+}
 
-        chain_store_update.save_block(block.into_inner());
-        // We don't need to increase refcount for `prev_hash` at this point
-        // because this is the block before State Sync.
+impl CanonicalBlocksIter {
+    /// The iterator yields only the error if the range can't be read.
+    fn new(chain_store: &ChainStore, from_height: BlockHeight, to_height: BlockHeight) -> Self {
+        let store = chain_store.owned_store().clone();
+        let range = chain_store.tail().and_then(|tail_height| {
+            if from_height < tail_height {
+                return Err(
+                    ErrorKind::BlockHeightBelowTail { height: from_height, tail_height }.into()
+                );
+            }
+            Ok(std::cmp::min(to_height, chain_store.head()?.height))
+        });
+        match range {
+            Ok(last_height) => Self { store, next_height: from_height, last_height, error: None },
+            Err(err) => Self { store, next_height: 1, last_height: 0, error: Some(err) },
+        }
+    }
+}
 
-        chain_store_update.commit()?;
-        Ok(())
+impl Iterator for CanonicalBlocksIter {
+    type Item = Result<Block, Error>;
+
+    /// Skips the heights without a block. Reading stops at the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        while self.next_height <= self.last_height {
+            let height = self.next_height;
+            self.next_height += 1;
+            let block =
+                match self.store.get_ser::<CryptoHash>(ColBlockHeight, &index_to_bytes(height)) {
+                    Ok(Some(block_hash)) => option_to_not_found(
+                        self.store.get_ser(ColBlock, block_hash.as_ref()),
+                        &format!("BLOCK: {}", block_hash),
+                    ),
+                    Ok(None) => continue,
+                    Err(err) => Err(err.into()),
+                };
+            if block.is_err() {
+                self.next_height = self.last_height + 1;
+            }
+            return Some(block);
+        }
+        None
     }
+}
 
-    pub fn save_orphan(
-        &mut self,
-        block: MaybeValidated<Block>,
-        requested_missing_chunks: bool,
-    ) -> Result<(), Error> {
-        if self.orphans.contains(block.hash()) {
-            return Ok(());
+impl From<DiscardedFork> for DiscardedForkView {
+    fn from(fork: DiscardedFork) -> Self {
+        Self {
+            height: fork.height,
+            hash: fork.hash,
+            prev_hash: fork.prev_hash,
+            producer: fork.producer,
+            timestamp_nanosec: fork.timestamp,
         }
-        if let Err(e) = self.validate_block(&block) {
-            byzantine_assert!(false);
-            return Err(e.into());
+    }
+}
+
+impl From<RejectedBlock> for RejectedBlockView {
+    fn from(block: RejectedBlock) -> Self {
+        Self {
+            hash: block.hash,
+            height: block.height,
+            producer: block.producer,
+            error: block.error,
+            timestamp_nanosec: block.timestamp,
         }
-        self.orphans.add(
-            Orphan { block, provenance: Provenance::NONE, added: Clock::instant() },
-            requested_missing_chunks,
-        );
-        Ok(())
     }
+}
 
-    fn save_block_height_processed(&mut self, block_height: BlockHeight) -> Result<(), Error> {
-        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
-        if !chain_store_update.is_height_processed(block_height)? {
-            chain_store_update.save_block_height_processed(block_height);
+impl From<GcPlan> for GcPlanView {
+    fn from(plan: GcPlan) -> Self {
+        Self {
+            fork_blocks: plan.fork_blocks,
+            canonical_start_height: plan.canonical_range.start,
+            canonical_end_height: plan.canonical_range.end,
+            estimated_keys: plan.estimated_keys,
         }
-        chain_store_update.commit()?;
-        Ok(())
     }
+}
 
-    // GC CONTRACT
-    // ===
-    //
-    // Prerequisites, guaranteed by the System:
-    // 1. Genesis block is available and should not be removed by GC.
-    // 2. No block in storage except Genesis has height lower or equal to `genesis_height`.
-    // 3. There is known lowest block height (Tail) came from Genesis or State Sync.
-    //    a. Tail is always on the Canonical Chain.
-    //    b. Only one Tail exists.
-    //    c. Tail's height is higher than or equal to `genesis_height`,
-    // 4. There is a known highest block height (Head).
-    //    a. Head is always on the Canonical Chain.
-    // 5. All blocks in the storage have heights in range [Tail; Head].
-    //    a. All forks end up on height of Head or lower.
-    // 6. If block A is ancestor of block B, height of A is strictly less then height of B.
-    // 7. (Property 1). A block with the lowest height among all the blocks at which the fork has started,
-    //    i.e. all the blocks with the outgoing degree 2 or more,
-    //    has the least height among all blocks on the fork.
-    // 8. (Property 2). The oldest block where the fork happened is never affected
-    //    by Canonical Chain Switching and always stays on Canonical Chain.
-    //
-    // Overall:
-    // 1. GC procedure is handled by `clear_data()` function.
-    // 2. `clear_data()` runs GC process for all blocks from the Tail to GC Stop Height provided by Epoch Manager.
-    // 3. `clear_data()` executes separately:
-    //    a. Forks Clearing runs for each height from Tail up to GC Stop Height.
-    //    b. Canonical Chain Clearing from (Tail + 1) up to GC Stop Height.
-    // 4. Before actual clearing is started, Block Reference Map should be built.
-    // 5. `clear_data()` executes every time when block at new height is added.
-    // 6. In case of State Sync, State Sync Clearing happens.
-    //
-    // Forks Clearing:
-    // 1. Any fork which ends up on height `height` INCLUSIVELY and earlier will be completely deleted
-    //    from the Store with all its ancestors up to the ancestor block where fork is happened
-    //    EXCLUDING the ancestor block where fork is happened.
-    // 2. The oldest ancestor block always remains on the Canonical Chain by property 2.
-    // 3. All forks which end up on height `height + 1` and further are protected from deletion and
-    //    no their ancestor will be deleted (even with lowest heights).
-    // 4. `clear_forks_data()` handles forks clearing for fixed height `height`.
-    //
-    // Canonical Chain Clearing:
-    // 1. Blocks on the Canonical Chain with the only descendant (if no forks started from them)
-    //    are unlocked for Canonical Chain Clearing.
-    // 2. If Forks Clearing ended up on the Canonical Chain, the block may be unlocked
-    //    for the Canonical Chain Clearing. There is no other reason to unlock the block exists.
-    // 3. All the unlocked blocks will be completely deleted
-    //    from the Tail up to GC Stop Height EXCLUSIVELY.
-    // 4. (Property 3, GC invariant). Tail can be shifted safely to the height of the
-    //    earliest existing block. There is always only one Tail (based on property 1)
-    //    and it's always on the Canonical Chain (based on property 2).
-    //
-    // Example:
-    //
-    // height: 101   102   103   104
-    // --------[A]---[B]---[C]---[D]
-    //          \     \
-    //           \     \---[E]
-    //            \
-    //             \-[F]---[G]
-    //
-    // 1. Let's define clearing height = 102. It this case fork A-F-G is protected from deletion
-    //    because of G which is on height 103. Nothing will be deleted.
-    // 2. Let's define clearing height = 103. It this case Fork Clearing will be executed for A
-    //    to delete blocks G and F, then Fork Clearing will be executed for B to delete block E.
-    //    Then Canonical Chain Clearing will delete blocks A and B as unlocked.
-    //    Block C is the only block of height 103 remains on the Canonical Chain (invariant).
-    //
-    // State Sync Clearing:
-    // 1. Executing State Sync means that no data in the storage is useful for block processing
-    //    and should be removed completely.
-    // 2. The Tail should be set to the block preceding Sync Block.
-    // 3. All the data preceding new Tail is deleted in State Sync Clearing
-    //    and the Trie is updated with having only Genesis data.
-    // 4. State Sync Clearing happens in `reset_data_pre_state_sync()`.
-    //
-    pub fn clear_data(
-        &mut self,
-        tries: ShardTries,
-        gc_blocks_limit: NumBlocks,
-    ) -> Result<(), Error> {
-        let _d = DelayDetector::new(|| "GC".into());
+impl From<EpochSummary> for EpochSummaryView {
+    fn from(summary: EpochSummary) -> Self {
+        Self {
+            epoch_height: summary.epoch_height,
+            blocks_produced: summary.blocks_produced,
+            chunks_produced_per_shard: summary.chunks_produced_per_shard,
+            total_txs: summary.total_txs,
+            total_gas_used: summary.total_gas_used,
+            first_block_hash: summary.first_block_hash,
+            last_block_hash: summary.last_block_hash,
+        }
+    }
+}
 
-        let head = self.store.head()?;
-        let tail = self.store.tail()?;
-        let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+/// Whether a block was produced at some height on the canonical chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeightStatus {
+    /// Hash of the canonical block at the height.
+    Block(CryptoHash),
+    /// The canonical chain goes from a block below the height directly to a block above it.
+    Skipped,
+    /// The height is above the head.
+    NotYetProduced,
+    /// The height is below the tail, blocks there are garbage collected.
+    GarbageCollected,
+}
 
-        if gc_stop_height > head.height {
-            return Err(ErrorKind::GCError(
-                "gc_stop_height cannot be larger than head.height".into(),
-            )
-            .into());
-        }
-        let prev_epoch_id = self.get_block_header(&head.prev_block_hash)?.epoch_id();
-        let epoch_change = prev_epoch_id != &head.epoch_id;
-        let mut fork_tail = self.store.fork_tail()?;
-        if epoch_change && fork_tail < gc_stop_height {
-            // if head doesn't change on the epoch boundary, we may update fork tail several times
-            // but that is fine since it doesn't affect correctness and also we limit the number of
-            // heights that fork cleaning goes through so it doesn't slow down client either.
-            let mut chain_store_update = self.store.store_update();
-            chain_store_update.update_fork_tail(gc_stop_height);
-            chain_store_update.commit()?;
-            fork_tail = gc_stop_height;
-        }
-        let mut gc_blocks_remaining = gc_blocks_limit;
+/// Provides view on the current chain state
+/// Both Chain and ChainUpdate implement this trait,
+/// to avoid duplicate functions
+pub trait ChainAccess {
+    fn orphans(&self) -> &OrphanBlockPool;
+    fn blocks_with_missing_chunks(&self) -> &MissingChunksPool<Orphan>;
+    fn chain_store(&self) -> &dyn ChainStoreAccess;
+}
 
-        // Forks Cleaning
-        let stop_height = std::cmp::max(tail, fork_tail.saturating_sub(GC_FORK_CLEAN_STEP));
-        for height in (stop_height..fork_tail).rev() {
-            self.clear_forks_data(tries.clone(), height, &mut gc_blocks_remaining)?;
-            if gc_blocks_remaining == 0 {
-                return Ok(());
-            }
-            let mut chain_store_update = self.store.store_update();
-            chain_store_update.update_fork_tail(height);
-            chain_store_update.commit()?;
-        }
+/// Check if block header is known
+/// Returns Err(Error) if any error occurs when checking store
+///         Ok(Err(BlockKnownError)) if the block header is known
+///         Ok(Ok()) otherwise
+pub fn check_header_known(
+    chain: &dyn ChainAccess,
+    header: &BlockHeader,
+) -> Result<Result<(), BlockKnownError>, Error> {
+    let header_head = chain.chain_store().header_head()?;
+    if header.hash() == &header_head.last_block_hash
+        || header.hash() == &header_head.prev_block_hash
+    {
+        return Ok(Err(BlockKnownError::KnownInHeader));
+    }
+    check_known_store(chain, header.hash())
+}
 
-        // Canonical Chain Clearing
-        for height in tail + 1..gc_stop_height {
-            if gc_blocks_remaining == 0 {
-                return Ok(());
-            }
-            let mut chain_store_update = self.store.store_update();
-            if let Ok(blocks_current_height) =
-                chain_store_update.get_chain_store().get_all_block_hashes_by_height(height)
-            {
-                let blocks_current_height =
-                    blocks_current_height.values().flatten().cloned().collect::<Vec<_>>();
-                if let Some(block_hash) = blocks_current_height.first() {
-                    let prev_hash = *chain_store_update.get_block_header(block_hash)?.prev_hash();
-                    let prev_block_refcount = *chain_store_update.get_block_refcount(&prev_hash)?;
-                    if prev_block_refcount > 1 {
-                        // Block of `prev_hash` starts a Fork, stopping
-                        break;
-                    } else if prev_block_refcount == 1 {
-                        debug_assert_eq!(blocks_current_height.len(), 1);
-                        chain_store_update.clear_block_data(
-                            &*self.runtime_adapter,
-                            *block_hash,
-                            GCMode::Canonical(tries.clone()),
-                        )?;
-                        gc_blocks_remaining -= 1;
-                    } else {
-                        return Err(ErrorKind::GCError(
-                            "block on canonical chain shouldn't have refcount 0".into(),
-                        )
-                        .into());
-                    }
-                }
-            }
-            chain_store_update.update_tail(height);
-            chain_store_update.commit()?;
-        }
-        Ok(())
+/// Check if this block is in the store already.
+/// Returns Err(Error) if any error occurs when checking store
+///         Ok(Err(BlockKnownError)) if the block is in the store
+///         Ok(Ok()) otherwise
+fn check_known_store(
+    chain: &dyn ChainAccess,
+    block_hash: &CryptoHash,
+) -> Result<Result<(), BlockKnownError>, Error> {
+    if chain.chain_store().block_exists(block_hash)? {
+        Ok(Err(BlockKnownError::KnownInStore))
+    } else {
+        // Not yet processed this block, we can proceed.
+        Ok(Ok(()))
     }
+}
 
-    /// Garbage collect data which archival node doesn’t need to keep.
-    ///
-    /// Normally, archival nodes keep all the data from the genesis block and
-    /// don’t run garbage collection.  On the other hand, for better performance
-    /// the storage contains some data duplication, i.e. values in some of the
-    /// columns can be recomputed from data in different columns.  To save on
-    /// storage, archival nodes do garbage collect that data.
-    ///
-    /// `gc_height_limit` limits how many heights will the function process.
-    pub fn clear_archive_data(&mut self, gc_height_limit: BlockHeightDelta) -> Result<(), Error> {
-        let _d = DelayDetector::new(|| "GC".into());
+/// Check if block is known: head, orphan or in store.
+/// Returns Err(Error) if any error occurs when checking store
+///         Ok(Err(BlockKnownError)) if the block is known
+///         Ok(Ok()) otherwise
+pub fn check_known(
+    chain: &dyn ChainAccess,
+    block_hash: &CryptoHash,
+) -> Result<Result<(), BlockKnownError>, Error> {
+    let head = chain.chain_store().head()?;
+    // Quick in-memory check for fast-reject any block handled recently.
+    if block_hash == &head.last_block_hash || block_hash == &head.prev_block_hash {
+        return Ok(Err(BlockKnownError::KnownInHead));
+    }
+    // Check if this block is in the set of known orphans.
+    if chain.orphans().contains(block_hash) {
+        return Ok(Err(BlockKnownError::KnownInOrphan));
+    }
+    if chain.blocks_with_missing_chunks().contains(block_hash) {
+        return Ok(Err(BlockKnownError::KnownInMissingChunks));
+    }
+    check_known_store(chain, block_hash)
+}
 
-        let head = self.store.head()?;
-        let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
-        if gc_stop_height > head.height {
-            return Err(ErrorKind::GCError(
-                "gc_stop_height cannot be larger than head.height".into(),
-            )
-            .into());
-        }
+#[derive(Default)]
+struct ShutdownState {
+    requested: bool,
+    in_flight: usize,
+}
 
-        let mut chain_store_update = self.store.store_update();
-        chain_store_update.clear_redundant_chunk_data(gc_stop_height, gc_height_limit)?;
-        chain_store_update.commit()
+/// Shared switch that makes the chain stop accepting blocks and the sync jobs stop touching the
+/// store. It can be cloned and used from other threads to request the shutdown and wait for the
+/// blocks and sync jobs in flight to be processed.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    state: Arc<(Mutex<ShutdownState>, Condvar)>,
+}
+
+/// Marks block processing or a sync job as in flight until dropped.
+pub struct InFlightGuard {
+    state: Arc<(Mutex<ShutdownState>, Condvar)>,
+}
+
+impl ShutdownHandle {
+    pub fn request_shutdown(&self) {
+        self.state.0.lock().unwrap().requested = true;
     }
 
-    pub fn clear_forks_data(
-        &mut self,
-        tries: ShardTries,
-        height: BlockHeight,
-        gc_blocks_remaining: &mut NumBlocks,
-    ) -> Result<(), Error> {
-        if let Ok(blocks_current_height) = self.store.get_all_block_hashes_by_height(height) {
-            let blocks_current_height =
-                blocks_current_height.values().flatten().cloned().collect::<Vec<_>>();
-            for block_hash in blocks_current_height.iter() {
-                let mut current_hash = *block_hash;
-                loop {
-                    if *gc_blocks_remaining == 0 {
-                        return Ok(());
-                    }
-                    // Block `block_hash` is not on the Canonical Chain
-                    // because shorter chain cannot be Canonical one
-                    // and it may be safely deleted
-                    // and all its ancestors while there are no other sibling blocks rely on it.
-                    let mut chain_store_update = self.store.store_update();
-                    if *chain_store_update.get_block_refcount(&current_hash)? == 0 {
-                        let prev_hash =
-                            *chain_store_update.get_block_header(&current_hash)?.prev_hash();
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.state.0.lock().unwrap().requested
+    }
 
-                        // It's safe to call `clear_block_data` for prev data because it clears fork only here
-                        chain_store_update.clear_block_data(
-                            &*self.runtime_adapter,
-                            current_hash,
-                            GCMode::Fork(tries.clone()),
-                        )?;
-                        chain_store_update.commit()?;
-                        *gc_blocks_remaining -= 1;
+    /// Waits until no block or sync job is being processed, up to the given timeout.
+    /// Returns false if the timeout expired first.
+    pub fn wait_until_idle(&self, timeout: TimeDuration) -> bool {
+        let (lock, idle) = &*self.state;
+        let state = lock.lock().unwrap();
+        let (_state, result) =
+            idle.wait_timeout_while(state, timeout, |state| state.in_flight > 0).unwrap();
+        !result.timed_out()
+    }
 
-                        current_hash = prev_hash;
-                    } else {
-                        // Block of `current_hash` is an ancestor for some other blocks, stopping
-                        break;
-                    }
-                }
-            }
+    /// Marks chain work as in flight until the returned guard is dropped, so that the shutdown
+    /// waits for it. Fails once the shutdown is requested.
+    pub fn enter(&self) -> Result<InFlightGuard, Error> {
+        let mut state = self.state.0.lock().unwrap();
+        if state.requested {
+            return Err(ErrorKind::ShuttingDown.into());
         }
+        state.in_flight += 1;
+        Ok(InFlightGuard { state: self.state.clone() })
+    }
+}
 
-        Ok(())
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let (lock, idle) = &*self.state;
+        lock.lock().unwrap().in_flight -= 1;
+        idle.notify_all();
     }
+}
 
-    /// Do basic validation of a block upon receiving it. Check that block is
-    /// well-formed (various roots match).
-    pub fn validate_block(&mut self, block: &MaybeValidated<Block>) -> Result<(), Error> {
-        block
-            .validate_with(|block| {
-                Chain::validate_block_impl(
-                    self.runtime_adapter.as_ref(),
-                    self.genesis_block(),
-                    block,
-                )
-                .map(|_| true)
-            })
-            .map(|_| ())
+/// Chain work that was not completed before the shutdown.
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// Whether the blocks and sync jobs in flight were processed before the timeout.
+    pub drained: bool,
+    /// Orphans dropped from the orphan pool.
+    pub orphans: usize,
+    /// Blocks dropped while waiting for their chunks.
+    pub blocks_with_missing_chunks: usize,
+    /// Whether state records scheduled to be patched into the next block were dropped.
+    pub dropped_states_to_patch: bool,
+    /// Sync hashes of the state syncs and catchups that have to be resumed after restart.
+    pub pending_state_syncs: Vec<CryptoHash>,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.drained
+            && self.orphans == 0
+            && self.blocks_with_missing_chunks == 0
+            && !self.dropped_states_to_patch
+            && self.pending_state_syncs.is_empty()
     }
+}
 
-    fn validate_block_impl(
-        runtime_adapter: &dyn RuntimeAdapter,
-        genesis_block: &Block,
-        block: &Block,
-    ) -> Result<(), Error> {
-        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
-            if chunk_header.height_created() == genesis_block.header().height() {
-                // Special case: genesis chunks can be in non-genesis blocks and don't have a signature
-                // We must verify that content matches and signature is empty.
-                // TODO: this code will not work when genesis block has different number of chunks as the current block
-                // https://github.com/near/nearcore/issues/4908
-                let genesis_chunk = &genesis_block.chunks()[shard_id];
-                if genesis_chunk.chunk_hash() != chunk_header.chunk_hash()
-                    || genesis_chunk.signature() != chunk_header.signature()
-                {
-                    return Err(ErrorKind::InvalidChunk.into());
-                }
-            } else if chunk_header.height_created() == block.header().height() {
-                if !runtime_adapter.verify_chunk_header_signature(
-                    &chunk_header.clone(),
-                    block.header().epoch_id(),
-                    block.header().prev_hash(),
-                )? {
-                    byzantine_assert!(false);
-                    return Err(ErrorKind::InvalidChunk.into());
-                }
-            }
-        }
-        block.check_validity().map_err(|e| e.into())
-    }
+/// How often the approvals of a validator were included in the accepted blocks of an epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApprovalParticipation {
+    /// Number of accepted blocks which include an approval of the validator.
+    pub num_included: u64,
+    /// Number of accepted blocks the validator was expected to approve, but which don't include
+    /// its approval.
+    pub num_missing: u64,
+}
 
-    /// Process a block header received during "header first" propagation.
-    pub fn process_block_header(
-        &mut self,
-        header: &BlockHeader,
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Result<(), Error> {
-        // We create new chain update, but it's not going to be committed so it's read only.
-        let mut chain_update = self.chain_update();
-        chain_update.process_block_header(header, on_challenge)?;
-        Ok(())
-    }
+/// How many heights after the height they were produced for the new chunks of the accepted
+/// blocks were included. Chunks are included late if the blocks at their heights are skipped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkInclusionDelayStats {
+    /// Number of new chunks of each shard by their delay in heights.
+    pub delays_per_shard: HashMap<ShardId, BTreeMap<BlockHeightDelta, u64>>,
+    /// Number of chunks of each chunk producer which were included late.
+    pub delayed_chunks_per_producer: HashMap<AccountId, u64>,
+}
 
-    pub fn mark_block_as_challenged(
-        &mut self,
-        block_hash: &CryptoHash,
-        challenger_hash: &CryptoHash,
-    ) -> Result<(), Error> {
-        let mut chain_update = self.chain_update();
-        chain_update.mark_block_as_challenged(block_hash, Some(challenger_hash))?;
-        chain_update.commit()?;
-        Ok(())
-    }
+/// Incoming receipts of a shard saved for the blocks after its last new chunk. They are applied
+/// all at once by the next chunk of the shard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReceiptBacklog {
+    /// Number of receipt proofs, each carrying the receipts from one shard and block.
+    pub num_receipt_proofs: u64,
+    /// Total size of the receipt proofs.
+    pub size_bytes: u64,
+}
 
-    /// Process a received or produced block, and unroll any orphans that may depend on it.
-    /// Changes current state, and calls `block_accepted` callback in case block was successfully applied.
-    pub fn process_block(
-        &mut self,
-        me: &Option<AccountId>,
-        block: MaybeValidated<Block>,
-        provenance: Provenance,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        block_orphaned_with_missing_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Result<Option<Tip>, Error> {
-        self.blocks_delay_tracker.mark_block_received(block.get_inner(), Clock::instant());
-        let block_hash = *block.hash();
-        let res = self.process_block_single(
-            me,
-            block,
-            provenance,
-            block_accepted,
-            block_misses_chunks,
-            block_orphaned_with_missing_chunks,
-            on_challenge,
-        );
-        if res.is_ok() {
-            if let Some(new_res) = self.check_orphans(
-                me,
-                block_hash,
-                block_accepted,
-                block_misses_chunks,
-                block_orphaned_with_missing_chunks,
-                on_challenge,
-            ) {
-                return Ok(Some(new_res));
-            }
-        }
-        res
-    }
+/// State sync data served by a chain to the nodes syncing to a sync hash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateSyncServingStats {
+    pub headers_served: u64,
+    pub parts_served: u64,
+    /// Number of headers and parts served from memory or storage without being generated.
+    pub cache_hits: u64,
+    /// Total size of the served headers and parts.
+    pub bytes_served: u64,
+}
 
-    /// Process challenge to invalidate chain. This is done between blocks to unroll the chain as
-    /// soon as possible and allow next block producer to skip invalid blocks.
-    pub fn process_challenge(&mut self, challenge: &Challenge) {
-        let head = unwrap_or_return!(self.head());
-        let mut chain_update = self.chain_update();
-        match chain_update.verify_challenges(
-            &vec![challenge.clone()],
-            &head.epoch_id,
-            &head.last_block_hash,
-            None,
-        ) {
-            Ok(_) => {}
-            Err(err) => {
-                warn!(target: "chain", "Invalid challenge: {}\nChallenge: {:#?}", err, challenge);
-            }
-        }
-        unwrap_or_return!(chain_update.commit());
+/// Peer a downloaded state part was received from and when it was requested, see
+/// `Chain::set_state_part`.
+#[derive(Clone, Debug)]
+pub struct PartProvenance {
+    pub peer_id: PeerId,
+    pub request_sent: DateTime<Utc>,
+    pub received: DateTime<Utc>,
+}
+
+impl PartProvenance {
+    fn latency(&self) -> TimeDuration {
+        (self.received - self.request_sent).to_std().unwrap_or_default()
     }
+}
 
-    /// Processes headers and adds them to store for syncing.
-    pub fn sync_block_headers(
-        &mut self,
-        mut headers: Vec<BlockHeader>,
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Result<(), Error> {
-        // Sort headers by heights if they are out of order.
-        headers.sort_by_key(|left| left.height());
+/// State parts downloaded from a peer for the state syncs in progress, see
+/// `Chain::state_part_provenance_stats`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerStatePartStats {
+    pub peer_id: PeerId,
+    pub num_parts: usize,
+    pub average_latency: TimeDuration,
+    pub max_latency: TimeDuration,
+}
 
-        if let Some(header) = headers.first() {
-            debug!(target: "chain", "Sync block headers: {} headers from {} at {}", headers.len(), header.hash(), header.height());
-        } else {
-            return Ok(());
-        };
+/// Shards of the receivers of receipts, reused by `Chain::build_receipts_hashes_with_cache`
+/// across the chunks of consecutive blocks. Starts over when the shard layout changes.
+#[derive(Default)]
+pub struct AccountShardCache {
+    shard_layout: Option<ShardLayout>,
+    shards: HashMap<AccountId, ShardId>,
+}
 
-        let all_known = if let Some(last_header) = headers.last() {
-            self.store.get_block_header(last_header.hash()).is_ok()
-        } else {
-            false
-        };
+impl AccountShardCache {
+    fn shard_id(&mut self, account_id: &AccountId, shard_layout: &ShardLayout) -> ShardId {
+        if let Some(shard_id) = self.shards.get(account_id) {
+            return *shard_id;
+        }
+        let shard_id = account_id_to_shard_id(account_id, shard_layout);
+        if self.shards.len() >= ACCOUNT_SHARD_CACHE_MAX_SIZE {
+            self.shards.clear();
+        }
+        self.shards.insert(account_id.clone(), shard_id);
+        shard_id
+    }
 
-        if !all_known {
-            // Validate header and then add to the chain.
-            for header in headers.iter() {
-                let mut chain_update = self.chain_update();
+    fn set_shard_layout(&mut self, shard_layout: &ShardLayout) {
+        if self.shard_layout.as_ref() != Some(shard_layout) {
+            self.shard_layout = Some(shard_layout.clone());
+            self.shards.clear();
+        }
+    }
+}
 
-                match check_header_known(&chain_update, header)? {
-                    Ok(_) => {}
-                    Err(_) => continue,
-                }
+/// Merkle proof of the block containing an execution outcome, see
+/// `Chain::get_execution_outcome_with_block_proof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutcomeBlockProof {
+    /// Block against whose block merkle tree the proof is made.
+    pub head_block_hash: CryptoHash,
+    pub proof: MerklePath,
+    /// Finality of the block containing the outcome as seen from the head of the chain.
+    pub finality: Finality,
+}
 
-                chain_update.validate_header(header, &Provenance::SYNC, on_challenge)?;
-                chain_update.chain_store_update.save_block_header(header.clone())?;
+/// Kind of the state sync data served, used as a metric label.
+#[derive(Clone, Copy)]
+enum StateSyncServedItem {
+    Header,
+    Part,
+}
 
-                // Add validator proposals for given header.
-                let last_finalized_height =
-                    chain_update.chain_store_update.get_block_height(header.last_final_block())?;
-                let epoch_manager_update = chain_update
-                    .runtime_adapter
-                    .add_validator_proposals(BlockHeaderInfo::new(header, last_finalized_height))?;
-                chain_update.chain_store_update.merge(epoch_manager_update);
-                chain_update.commit()?;
-            }
+impl StateSyncServedItem {
+    fn label(self) -> &'static str {
+        match self {
+            StateSyncServedItem::Header => "header",
+            StateSyncServedItem::Part => "part",
         }
+    }
+}
 
-        let mut chain_update = self.chain_update();
-
-        if let Some(header) = headers.last() {
-            // Update header_head if it's the new tip
-            chain_update.update_header_head_if_not_challenged(header)?;
-        }
+/// Facade to the blockchain block processing and storage.
+/// Provides current view on the state according to the chain state.
+pub struct Chain {
+    store: ChainStore,
+    pub runtime_adapter: Arc<dyn RuntimeAdapter>,
+    orphans: OrphanBlockPool,
+    pub blocks_with_missing_chunks: MissingChunksPool<Orphan>,
+    /// Blocks ahead of the local clock within the grace period, see
+    /// `Chain::retry_time_delayed_blocks`.
+    time_delayed_blocks: Vec<Orphan>,
+    genesis: Block,
+    pub transaction_validity_period: NumBlocks,
+    pub epoch_length: BlockHeightDelta,
+    /// Block economics, relevant to changes when new block must be produced.
+    pub block_economics_config: BlockEconomicsConfig,
+    /// Limits on block size and number of transactions per chunk.
+    pub block_limits_config: BlockLimitsConfig,
+    pub receipts_root_check_mode: ReceiptsRootCheckMode,
+    /// Skip most of the chunk validation, see `Chain::enable_trusted_chain_replay`.
+    trusted_chain_replay: bool,
+    /// Verify the merkle proofs of incoming receipts against the outgoing receipts roots of their
+    /// source chunks before saving them.
+    pub verify_incoming_receipts: bool,
+    /// Blocks ahead of the local clock by at most this much more than the acceptable time
+    /// difference are held back until the clock catches up instead of being rejected.
+    pub future_block_grace_period: TimeDuration,
+    /// What to do with blocks from epochs with a protocol version the binary doesn't support.
+    pub unsupported_protocol_version_policy: UnsupportedProtocolVersionPolicy,
+    /// Keep a record of fork blocks deleted by garbage collection, see `Chain::discarded_forks`.
+    pub record_discarded_forks: bool,
+    /// Keep a record of the approvals included in the blocks produced by this node, see
+    /// `Chain::get_approval_audit`.
+    pub record_approval_audit: bool,
+    /// Archival nodes keep the partial chunks of the blocks of this many latest epochs, even if
+    /// their other redundant chunk data is already garbage collected.
+    pub retain_partial_chunk_parts_epochs: u64,
+    /// Allow `Chain::garbage_collect_to_height` to prune beyond the regular GC stop height.
+    pub allow_aggressive_gc: bool,
+    /// Number of headers `Chain::sync_block_headers` validates before committing them.
+    pub header_sync_commit_batch_size: usize,
+    pub doomslug_threshold_mode: DoomslugThresholdMode,
+    pending_states_to_patch: Option<Vec<StateRecord>>,
+    pub blocks_delay_tracker: BlocksDelayTracker,
+    /// Approval participation of the validators in the most recent epochs, oldest epoch first.
+    approval_participation: VecDeque<(EpochId, HashMap<AccountId, ApprovalParticipation>)>,
+    /// Recently served and in-flight state sync headers, possibly shared with other chains.
+    state_header_cache: Arc<StateHeaderCache>,
+    /// Sync hashes state is being served for, possibly shared with other chains.
+    sync_hash_registry: Arc<SyncHashRegistry>,
+    /// Incoming receipts waiting for the next chunk of each shard, as of the head.
+    receipt_backlog: HashMap<ShardId, ReceiptBacklog>,
+    /// Delays of the inclusion of the new chunks of the accepted blocks.
+    chunk_inclusion_delays: ChunkInclusionDelayStats,
+    /// Recently rejected blocks with the error, see `is_cacheable_rejection`.
+    invalid_blocks: LruCache<CryptoHash, ErrorKind>,
+    /// Nodes of the block merkle trees used by block proofs, by head block hash, level and index.
+    block_merkle_nodes: LruCache<(CryptoHash, u64, u64), Option<MerkleHash>>,
+    /// A warning is logged once the receipt backlog of a shard exceeds this size.
+    pub receipt_backlog_warn_bytes: u64,
+    /// Expected rate of downloading headers, used to estimate the sync progress.
+    pub header_sync_expected_height_per_second: u64,
+    /// State sync data served for the most recent sync hashes, oldest first.
+    state_sync_serving_stats: VecDeque<(CryptoHash, StateSyncServingStats)>,
+    /// Provenance of the state parts downloaded for the state syncs in progress by shard and sync
+    /// hash, and part id. Dropped once the parts are cleared.
+    state_part_provenance: HashMap<(ShardId, CryptoHash), HashMap<u64, PartProvenance>>,
+    /// Ids of the state parts stored by shard and sync hash, loaded from storage on the first
+    /// lookup of `Chain::state_parts_progress` and kept up to date as parts are saved and cleared.
+    stored_state_parts: HashMap<(ShardId, CryptoHash), HashSet<u64>>,
+    /// Reported by `Chain::sync_diagnostics`.
+    last_gc_run: Option<GcRunInfo>,
+    /// Receives the invalid data detected while validating blocks, headers and state sync headers
+    /// received from the network.
+    pub malicious_behavior_sink: Option<Sender<ChainMisbehavior>>,
+    /// Stops block processing once the node is shutting down, see `Chain::prepare_shutdown`.
+    shutdown: ShutdownHandle,
+    /// Hashes of the blocks being processed. Copies of them received from other peers in the
+    /// meantime are rejected before any validation work.
+    blocks_in_processing: HashSet<CryptoHash>,
+    /// Notified of the blocks and chunks accepted by this chain, see `ChainEventHandler`.
+    event_handlers: ChainEventHandlers,
+    /// Corrupts the state sync responses served by this chain.
+    #[cfg(feature = "test_features")]
+    state_sync_corruptor: Option<StateSyncCorruptor>,
+    /// Called right before a block is validated, once it's marked as being processed.
+    #[cfg(test)]
+    pub(crate) block_validation_hook: Option<Box<dyn FnMut(&mut Chain, &Block) + Send>>,
+    /// Number of block merkle tree nodes looked up for block proofs which were not cached.
+    #[cfg(test)]
+    pub(crate) block_merkle_nodes_cache_misses: u64,
+}
 
-        chain_update.commit()
+impl ChainAccess for Chain {
+    fn orphans(&self) -> &OrphanBlockPool {
+        &self.orphans
     }
 
-    /// Returns if given block header is on the current chain.
-    pub fn is_on_current_chain(&mut self, header: &BlockHeader) -> Result<(), Error> {
-        let chain_header = self.get_header_by_height(header.height())?;
-        if chain_header.hash() == header.hash() {
-            Ok(())
-        } else {
-            Err(ErrorKind::Other(format!("{} not on current chain", header.hash())).into())
-        }
+    fn blocks_with_missing_chunks(&self) -> &MissingChunksPool<Orphan> {
+        &self.blocks_with_missing_chunks
     }
 
-    /// Finds first of the given hashes that is known on the main chain.
-    pub fn find_common_header(&mut self, hashes: &[CryptoHash]) -> Option<BlockHeader> {
-        for hash in hashes {
-            if let Ok(header) = self.get_block_header(hash).map(|h| h.clone()) {
-                if let Ok(header_at_height) = self.get_header_by_height(header.height()) {
-                    if header.hash() == header_at_height.hash() {
-                        return Some(header);
-                    }
-                }
-            }
-        }
-        None
+    fn chain_store(&self) -> &dyn ChainStoreAccess {
+        &self.store
     }
+}
 
-    fn determine_status(&self, head: Option<Tip>, prev_head: Tip) -> BlockStatus {
-        let has_head = head.is_some();
-        let mut is_next_block = false;
-
-        let old_hash = if let Some(head) = head {
-            if head.prev_block_hash == prev_head.last_block_hash {
-                is_next_block = true;
-                None
-            } else {
-                Some(prev_head.last_block_hash)
-            }
-        } else {
-            None
-        };
+impl Chain {
+    pub fn new_for_view_client(
+        runtime_adapter: Arc<dyn RuntimeAdapter>,
+        chain_genesis: &ChainGenesis,
+        doomslug_threshold_mode: DoomslugThresholdMode,
+    ) -> Result<Chain, Error> {
+        let (store, state_roots) = runtime_adapter.genesis_state();
+        chain_genesis.check_consistency(&*runtime_adapter)?;
+        let store = ChainStore::new(store, chain_genesis.height);
+        let genesis =
+            GenesisBlockInfo::get_or_compute(&runtime_adapter, chain_genesis, &state_roots)?
+                .block
+                .clone();
+        Ok(Chain {
+            store,
+            runtime_adapter,
+            orphans: OrphanBlockPool::new(),
+            blocks_with_missing_chunks: MissingChunksPool::new(),
+            time_delayed_blocks: vec![],
+            genesis: genesis,
+            transaction_validity_period: chain_genesis.transaction_validity_period,
+            epoch_length: chain_genesis.epoch_length,
+            block_economics_config: BlockEconomicsConfig::from(chain_genesis),
+            block_limits_config: BlockLimitsConfig::default(),
+            receipts_root_check_mode: ReceiptsRootCheckMode::default(),
+            trusted_chain_replay: false,
+            verify_incoming_receipts: false,
+            future_block_grace_period: DEFAULT_FUTURE_BLOCK_GRACE_PERIOD,
+            unsupported_protocol_version_policy: UnsupportedProtocolVersionPolicy::default(),
+            record_discarded_forks: false,
+            record_approval_audit: false,
+            retain_partial_chunk_parts_epochs: 0,
+            allow_aggressive_gc: false,
+            header_sync_commit_batch_size: DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE,
+            doomslug_threshold_mode,
+            pending_states_to_patch: None,
+            blocks_delay_tracker: BlocksDelayTracker::default(),
+            approval_participation: VecDeque::new(),
+            state_header_cache: Arc::new(StateHeaderCache::default()),
+            sync_hash_registry: Arc::new(SyncHashRegistry::default()),
+            receipt_backlog: HashMap::new(),
+            chunk_inclusion_delays: ChunkInclusionDelayStats::default(),
+            invalid_blocks: LruCache::new(INVALID_BLOCKS_CACHE_SIZE),
+            block_merkle_nodes: LruCache::new(BLOCK_MERKLE_NODES_CACHE_SIZE),
+            receipt_backlog_warn_bytes: DEFAULT_RECEIPT_BACKLOG_WARN_BYTES,
+            header_sync_expected_height_per_second: DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND,
+            state_sync_serving_stats: VecDeque::new(),
+            state_part_provenance: HashMap::new(),
+            stored_state_parts: HashMap::new(),
+            last_gc_run: None,
+            malicious_behavior_sink: None,
+            shutdown: ShutdownHandle::default(),
+            blocks_in_processing: HashSet::new(),
+            event_handlers: ChainEventHandlers::default(),
+            #[cfg(feature = "test_features")]
+            state_sync_corruptor: None,
+            #[cfg(test)]
+            block_validation_hook: None,
+            #[cfg(test)]
+            block_merkle_nodes_cache_misses: 0,
+        })
+    }
 
-        match (has_head, is_next_block) {
-            (true, true) => BlockStatus::Next,
-            (true, false) => BlockStatus::Reorg(old_hash.unwrap()),
-            (false, _) => BlockStatus::Fork,
+    pub fn new(
+        runtime_adapter: Arc<dyn RuntimeAdapter>,
+        chain_genesis: &ChainGenesis,
+        doomslug_threshold_mode: DoomslugThresholdMode,
+    ) -> Result<Chain, Error> {
+        // Get runtime initial state and create genesis block out of it.
+        let (store, state_roots) = runtime_adapter.genesis_state();
+        // Fail before anything is written, rather than with a genesis mismatch later on.
+        chain_genesis.check_consistency(&*runtime_adapter)?;
+        let mut store = ChainStore::new(store, chain_genesis.height);
+        store.enable_negative_cache();
+        // The chain data is migrated together with the rest of the store before the chain is
+        // created, see `migrate_chain_schema`.
+        let chain_version = get_chain_schema_version(&store)?;
+        if chain_version != CHAIN_SCHEMA_VERSION {
+            return Err(ErrorKind::Other(format!(
+                "Chain schema version {} doesn't match the supported version {}, the chain data has to be migrated first",
+                chain_version, CHAIN_SCHEMA_VERSION
+            ))
+            .into());
         }
-    }
+        // Catchup starts over after a restart, so updates spilled by a previous run are unused.
+        store.clear_spilled_store_updates()?;
+        let genesis_info =
+            GenesisBlockInfo::get_or_compute(&runtime_adapter, chain_genesis, &state_roots)?;
+        let genesis = &genesis_info.block;
 
-    pub fn reset_data_pre_state_sync(&mut self, sync_hash: CryptoHash) -> Result<(), Error> {
-        let head = self.head()?;
-        // Get header we were syncing into.
-        let header = self.get_block_header(&sync_hash)?;
-        let prev_hash = *header.prev_hash();
-        let sync_height = header.height();
-        let gc_height = std::cmp::min(head.height + 1, sync_height);
+        // Check if we have a head in the store, otherwise pick genesis block.
+        let mut store_update = store.store_update();
+        let head_res = store_update.head();
+        let head: Tip;
+        match head_res {
+            Ok(h) => {
+                head = h;
 
-        // GC all the data from current tail up to `gc_height`. In case tail points to a height where
-        // there is no block, we need to make sure that the last block before tail is cleaned.
-        let tail = self.store.tail()?;
-        let mut tail_prev_block_cleaned = false;
-        for height in tail..gc_height {
-            if let Ok(blocks_current_height) = self.store.get_all_block_hashes_by_height(height) {
-                let blocks_current_height =
-                    blocks_current_height.values().flatten().cloned().collect::<Vec<_>>();
-                for block_hash in blocks_current_height {
-                    let runtime_adapter = self.runtime_adapter();
-                    let mut chain_store_update = self.mut_store().store_update();
-                    if !tail_prev_block_cleaned {
-                        let prev_block_hash =
-                            *chain_store_update.get_block_header(&block_hash)?.prev_hash();
-                        if chain_store_update.get_block(&prev_block_hash).is_ok() {
-                            chain_store_update.clear_block_data(
-                                &*runtime_adapter,
-                                prev_block_hash,
-                                GCMode::StateSync { clear_block_info: true },
-                            )?;
+                // Check that genesis in the store is the same as genesis given in the config.
+                let genesis_hash = store_update.get_block_hash_by_height(chain_genesis.height)?;
+                if &genesis_hash != genesis.hash() {
+                    return Err(ErrorKind::Other(format!(
+                        "Genesis mismatch between storage and config: {:?} vs {:?}",
+                        genesis_hash,
+                        genesis.hash()
+                    ))
+                    .into());
+                }
+
+                // Check we have the header corresponding to the header_head.
+                let header_head = store_update.header_head()?;
+                if store_update.get_block_header(&header_head.last_block_hash).is_err() {
+                    // Reset header head and "sync" head to be consistent with current block head.
+                    store_update.save_header_head_if_not_challenged(&head)?;
+                }
+                // TODO: perform validation that latest state in runtime matches the stored chain.
+            }
+            Err(err) => match err.kind() {
+                ErrorKind::DBNotFoundErr(_) => {
+                    // A previous run may have been interrupted before the head was saved, so
+                    // some of the genesis data can already be in the store. Only write what is
+                    // missing and commit everything together with the head below.
+                    for chunk in genesis_info.chunks.iter() {
+                        if store_update.get_chunk(&chunk.chunk_hash()).is_err() {
+                            store_update.save_chunk(chunk.clone());
                         }
-                        tail_prev_block_cleaned = true;
                     }
-                    chain_store_update.clear_block_data(
-                        &*runtime_adapter,
-                        block_hash,
-                        GCMode::StateSync { clear_block_info: block_hash != prev_hash },
-                    )?;
-                    chain_store_update.commit()?;
+                    // Epoch manager already knowing the genesis block means its block info was
+                    // persisted; recording it again is not guaranteed to be idempotent.
+                    if runtime_adapter.get_epoch_start_height(genesis.hash()).is_err() {
+                        store_update.merge(runtime_adapter.add_validator_proposals(
+                            BlockHeaderInfo::new(
+                                genesis.header(),
+                                // genesis height is considered final
+                                chain_genesis.height,
+                            ),
+                        )?);
+                    }
+                    if store_update.get_block_header(genesis.hash()).is_err() {
+                        store_update.save_block_header(genesis.header().clone())?;
+                    }
+                    if store_update.get_block(genesis.hash()).is_err() {
+                        store_update.save_block(genesis.clone());
+                    }
+                    if store_update.get_block_extra(genesis.hash()).is_err() {
+                        store_update.save_block_extra(
+                            genesis.hash(),
+                            BlockExtra { challenges_result: vec![] },
+                        );
+                    }
+
+                    for (chunk_header, state_root) in
+                        genesis.chunks().iter().zip(state_roots.iter())
+                    {
+                        let shard_uid = runtime_adapter
+                            .shard_id_to_uid(chunk_header.shard_id(), &EpochId::default())?;
+                        if store_update.get_chunk_extra(genesis.hash(), &shard_uid).is_ok() {
+                            continue;
+                        }
+                        store_update.save_chunk_extra(
+                            genesis.hash(),
+                            &shard_uid,
+                            ChunkExtra::new(
+                                state_root,
+                                CryptoHash::default(),
+                                vec![],
+                                0,
+                                chain_genesis.gas_limit,
+                                0,
+                            ),
+                        );
+                    }
+
+                    head = Tip::from_header(genesis.header());
+                    store_update.save_head(&head)?;
+                    store_update.save_final_head(&head)?;
+                    store_update.save_chain_schema_version(CHAIN_SCHEMA_VERSION)?;
+
+                    info!(target: "chain", "Init: saved genesis: {:?} / {:?}", genesis.hash(), state_roots);
                 }
-            }
+                e => return Err(e.into()),
+            },
         }
+        store_update.commit()?;
 
-        // Clear Chunks data
-        let mut chain_store_update = self.mut_store().store_update();
-        // The largest height of chunk we have in storage is head.height + 1
-        let chunk_height = std::cmp::min(head.height + 2, sync_height);
-        chain_store_update.clear_chunk_data_and_headers(chunk_height)?;
-        chain_store_update.commit()?;
+        info!(target: "chain", "Init: head @ {} [{}]", head.height, head.last_block_hash);
 
-        // clear all trie data
+        Ok(Chain {
+            store,
+            runtime_adapter,
+            orphans: OrphanBlockPool::new(),
+            blocks_with_missing_chunks: MissingChunksPool::new(),
+            time_delayed_blocks: vec![],
+            genesis: genesis.clone(),
+            transaction_validity_period: chain_genesis.transaction_validity_period,
+            epoch_length: chain_genesis.epoch_length,
+            block_economics_config: BlockEconomicsConfig::from(chain_genesis),
+            block_limits_config: BlockLimitsConfig::default(),
+            receipts_root_check_mode: ReceiptsRootCheckMode::default(),
+            trusted_chain_replay: false,
+            verify_incoming_receipts: false,
+            future_block_grace_period: DEFAULT_FUTURE_BLOCK_GRACE_PERIOD,
+            unsupported_protocol_version_policy: UnsupportedProtocolVersionPolicy::default(),
+            record_discarded_forks: false,
+            record_approval_audit: false,
+            retain_partial_chunk_parts_epochs: 0,
+            allow_aggressive_gc: false,
+            header_sync_commit_batch_size: DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE,
+            doomslug_threshold_mode,
+            pending_states_to_patch: None,
+            blocks_delay_tracker: BlocksDelayTracker::default(),
+            approval_participation: VecDeque::new(),
+            state_header_cache: Arc::new(StateHeaderCache::default()),
+            sync_hash_registry: Arc::new(SyncHashRegistry::default()),
+            receipt_backlog: HashMap::new(),
+            chunk_inclusion_delays: ChunkInclusionDelayStats::default(),
+            invalid_blocks: LruCache::new(INVALID_BLOCKS_CACHE_SIZE),
+            block_merkle_nodes: LruCache::new(BLOCK_MERKLE_NODES_CACHE_SIZE),
+            receipt_backlog_warn_bytes: DEFAULT_RECEIPT_BACKLOG_WARN_BYTES,
+            header_sync_expected_height_per_second: DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND,
+            state_sync_serving_stats: VecDeque::new(),
+            state_part_provenance: HashMap::new(),
+            stored_state_parts: HashMap::new(),
+            last_gc_run: None,
+            malicious_behavior_sink: None,
+            shutdown: ShutdownHandle::default(),
+            blocks_in_processing: HashSet::new(),
+            event_handlers: ChainEventHandlers::default(),
+            #[cfg(feature = "test_features")]
+            state_sync_corruptor: None,
+            #[cfg(test)]
+            block_validation_hook: None,
+            #[cfg(test)]
+            block_merkle_nodes_cache_misses: 0,
+        })
+    }
 
-        let tries = self.runtime_adapter.get_tries();
-        let mut chain_store_update = self.mut_store().store_update();
-        let mut store_update = StoreUpdate::new_with_tries(tries);
-        store_update.delete_all(ColState);
-        chain_store_update.merge(store_update);
+    #[cfg(feature = "test_features")]
+    pub fn adv_disable_doomslug(&mut self) {
+        self.doomslug_threshold_mode = DoomslugThresholdMode::NoApprovals
+    }
 
-        // The reason to reset tail here is not to allow Tail be greater than Head
-        chain_store_update.reset_tail();
-        chain_store_update.commit()?;
-        Ok(())
+    #[cfg(feature = "test_features")]
+    pub fn adv_set_state_sync_corruptor(&mut self, corruptor: Option<StateSyncCorruptor>) {
+        self.state_sync_corruptor = corruptor;
     }
 
-    /// Set the new head after state sync was completed if it is indeed newer.
-    /// Check for potentially unlocked orphans after this update.
-    pub fn reset_heads_post_state_sync(
-        &mut self,
-        me: &Option<AccountId>,
-        sync_hash: CryptoHash,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Result<(), Error> {
-        // Get header we were syncing into.
-        let header = self.get_block_header(&sync_hash)?;
-        let hash = *header.prev_hash();
-        let prev_block = self.get_block(&hash)?;
-        let new_tail = prev_block.header().height();
-        let new_chunk_tail = prev_block.chunks().iter().map(|x| x.height_created()).min().unwrap();
-        let tip = Tip::from_header(prev_block.header());
-        let final_head = Tip::from_header(self.genesis.header());
-        // Update related heads now.
-        let mut chain_store_update = self.mut_store().store_update();
-        chain_store_update.save_body_head(&tip)?;
-        // Reset final head to genesis since at this point we don't have the last final block.
-        chain_store_update.save_final_head(&final_head)?;
-        // New Tail can not be earlier than `prev_block.header.inner_lite.height`
-        chain_store_update.update_tail(new_tail);
-        // New Chunk Tail can not be earlier than minimum of height_created in Block `prev_block`
-        chain_store_update.update_chunk_tail(new_chunk_tail);
-        chain_store_update.commit()?;
+    pub fn compute_collection_hash<T: BorshSerialize>(elems: Vec<T>) -> Result<CryptoHash, Error> {
+        Ok(hash(&elems.try_to_vec()?))
+    }
 
-        // Check if there are any orphans unlocked by this state sync.
-        // We can't fail beyond this point because the caller will not process accepted blocks
-        //    and the blocks with missing chunks if this method fails
-        self.check_orphans(
-            me,
-            hash,
-            block_accepted,
-            block_misses_chunks,
-            orphan_misses_chunks,
-            on_challenge,
-        );
-        Ok(())
+    pub fn compute_bp_hash(
+        runtime_adapter: &dyn RuntimeAdapter,
+        epoch_id: EpochId,
+        prev_epoch_id: EpochId,
+        last_known_hash: &CryptoHash,
+    ) -> Result<CryptoHash, Error> {
+        let bps = runtime_adapter.get_epoch_block_producers_ordered(&epoch_id, last_known_hash)?;
+        let protocol_version = runtime_adapter.get_epoch_protocol_version(&prev_epoch_id)?;
+        if checked_feature!("stable", BlockHeaderV3, protocol_version) {
+            let validator_stakes = bps.into_iter().map(|(bp, _)| bp).collect();
+            Chain::compute_collection_hash(validator_stakes)
+        } else {
+            let validator_stakes = bps.into_iter().map(|(bp, _)| bp.into_v1()).collect();
+            Chain::compute_collection_hash(validator_stakes)
+        }
     }
 
-    // Processes a single block, increments the metric for the number of blocks processing and also
-    // for the number of blocks processed successfully (returns OK).
-    fn process_block_single(
-        &mut self,
-        me: &Option<AccountId>,
-        block: MaybeValidated<Block>,
-        provenance: Provenance,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Result<Option<Tip>, Error> {
-        metrics::BLOCK_PROCESSING_ATTEMPTS_TOTAL.inc();
-        metrics::NUM_ORPHANS.set(self.orphans.len() as i64);
-        let block_hash = *block.hash();
-        let _timer = CryptoHashTimer::new(block_hash);
-        let success_timer = metrics::BLOCK_PROCESSING_TIME.start_timer();
+    /// Creates a light client block for the last final block from perspective of some other block
+    ///
+    /// # Arguments
+    ///  * `header` - the last finalized block seen from `header` (not pushed back) will be used to
+    ///               compute the light client block
+    pub fn create_light_client_block(
+        header: &BlockHeader,
+        runtime_adapter: &dyn RuntimeAdapter,
+        chain_store: &mut dyn ChainStoreAccess,
+    ) -> Result<LightClientBlockView, Error> {
+        let final_block_header = {
+            let ret = chain_store.get_block_header(header.last_final_block())?.clone();
+            let two_ahead = chain_store.get_header_by_height(ret.height() + 2)?;
+            if two_ahead.epoch_id() != ret.epoch_id() {
+                let one_ahead = chain_store.get_header_by_height(ret.height() + 1)?;
+                if one_ahead.epoch_id() != ret.epoch_id() {
+                    let new_final_hash = *ret.last_final_block();
+                    chain_store.get_block_header(&new_final_hash)?.clone()
+                } else {
+                    let new_final_hash = *one_ahead.last_final_block();
+                    chain_store.get_block_header(&new_final_hash)?.clone()
+                }
+            } else {
+                ret
+            }
+        };
 
-        let res = self.process_block_single_impl(
-            me,
-            block,
-            provenance,
-            block_accepted,
-            block_misses_chunks,
-            orphan_misses_chunks,
-            on_challenge,
-        );
+        let next_block_producers = get_epoch_block_producers_view(
+            final_block_header.next_epoch_id(),
+            header.prev_hash(),
+            runtime_adapter,
+        )?;
 
-        if res.is_ok() {
-            metrics::BLOCK_PROCESSED_TOTAL.inc();
-            success_timer.stop_and_record();
-        } else {
-            success_timer.stop_and_discard();
-        }
-        res
+        create_light_client_block_view(&final_block_header, chain_store, Some(next_block_producers))
     }
 
-    // Block processing. Unlike process_block_single() this function doesn't update metrics for
-    // successful blocks processing.
-    fn process_block_single_impl(
+    pub fn save_block(
         &mut self,
-        me: &Option<AccountId>,
         block: MaybeValidated<Block>,
-        provenance: Provenance,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Result<Option<Tip>, Error> {
-        let prev_head = self.store.head()?;
-        let mut chain_update = self.chain_update();
-        let maybe_new_head = chain_update.process_block(me, &block, &provenance, on_challenge);
-        let block_height = block.header().height();
+        mode: BlockSaveMode,
+    ) -> Result<(), Error> {
+        if self.store.get_block(block.hash()).is_ok() {
+            return Ok(());
+        }
+        if let Err(e) = self.validate_block(&block) {
+            byzantine_assert!(false);
+            return Err(e.into());
+        }
 
-        match maybe_new_head {
-            Ok(head) => {
-                chain_update.chain_store_update.save_block_height_processed(block_height);
-                chain_update.commit()?;
+        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
+        let block_hash = *block.hash();
+        let prev_hash = *block.header().prev_hash();
+        chain_store_update.save_block(block.into_inner());
+        match mode {
+            BlockSaveMode::Normal => chain_store_update.inc_block_refcount(&prev_hash)?,
+            BlockSaveMode::StateSyncBoundary => {
+                chain_store_update.save_state_sync_boundary_block(&block_hash)?
+            }
+        }
+        chain_store_update.commit()?;
+        Ok(())
+    }
 
-                self.pending_states_to_patch = None;
+    pub fn save_orphan(
+        &mut self,
+        block: MaybeValidated<Block>,
+        requested_missing_chunks: bool,
+    ) -> Result<(), Error> {
+        if self.orphans.contains(block.hash()) {
+            return Ok(());
+        }
+        if let Err(e) = self.validate_block(&block) {
+            byzantine_assert!(false);
+            return Err(e.into());
+        }
+        self.orphans.add(
+            Orphan { block, provenance: Provenance::NONE, added: Clock::instant() },
+            requested_missing_chunks,
+        );
+        Ok(())
+    }
 
-                match &head {
-                    Some(tip) => {
-                        if let Ok(producers) = self
-                            .runtime_adapter
-                            .get_epoch_block_producers_ordered(&tip.epoch_id, &tip.last_block_hash)
+    fn save_block_height_processed(&mut self, block_height: BlockHeight) -> Result<(), Error> {
+        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
+        if !chain_store_update.is_height_processed(block_height)? {
+            chain_store_update.save_block_height_processed(block_height);
+        }
+        chain_store_update.commit()?;
+        Ok(())
+    }
+
+    // GC CONTRACT
+    // ===
+    //
+    // Prerequisites, guaranteed by the System:
+    // 1. Genesis block is available and should not be removed by GC.
+    // 2. No block in storage except Genesis has height lower or equal to `genesis_height`.
+    // 3. There is known lowest block height (Tail) came from Genesis or State Sync.
+    //    a. Tail is always on the Canonical Chain.
+    //    b. Only one Tail exists.
+    //    c. Tail's height is higher than or equal to `genesis_height`,
+    // 4. There is a known highest block height (Head).
+    //    a. Head is always on the Canonical Chain.
+    // 5. All blocks in the storage have heights in range [Tail; Head].
+    //    a. All forks end up on height of Head or lower.
+    // 6. If block A is ancestor of block B, height of A is strictly less then height of B.
+    // 7. (Property 1). A block with the lowest height among all the blocks at which the fork has started,
+    //    i.e. all the blocks with the outgoing degree 2 or more,
+    //    has the least height among all blocks on the fork.
+    // 8. (Property 2). The oldest block where the fork happened is never affected
+    //    by Canonical Chain Switching and always stays on Canonical Chain.
+    //
+    // Overall:
+    // 1. GC procedure is handled by `clear_data()` function.
+    // 2. `clear_data()` runs GC process for all blocks from the Tail to GC Stop Height provided by Epoch Manager.
+    // 3. `clear_data()` executes separately:
+    //    a. Forks Clearing runs for each height from Tail up to GC Stop Height.
+    //    b. Canonical Chain Clearing from (Tail + 1) up to GC Stop Height.
+    // 4. Before actual clearing is started, Block Reference Map should be built.
+    // 5. `clear_data()` executes when the head is updated, as often as the client's `GcSchedule`
+    //    allows. Each execution is limited by the number of blocks it may remove.
+    // 6. In case of State Sync, State Sync Clearing happens.
+    //
+    // Forks Clearing:
+    // 1. Any fork which ends up on height `height` INCLUSIVELY and earlier will be completely deleted
+    //    from the Store with all its ancestors up to the ancestor block where fork is happened
+    //    EXCLUDING the ancestor block where fork is happened.
+    // 2. The oldest ancestor block always remains on the Canonical Chain by property 2.
+    // 3. All forks which end up on height `height + 1` and further are protected from deletion and
+    //    no their ancestor will be deleted (even with lowest heights).
+    // 4. `clear_forks_data()` handles forks clearing for fixed height `height`.
+    //
+    // Canonical Chain Clearing:
+    // 1. Blocks on the Canonical Chain with the only descendant (if no forks started from them)
+    //    are unlocked for Canonical Chain Clearing.
+    // 2. If Forks Clearing ended up on the Canonical Chain, the block may be unlocked
+    //    for the Canonical Chain Clearing. There is no other reason to unlock the block exists.
+    // 3. All the unlocked blocks will be completely deleted
+    //    from the Tail up to GC Stop Height EXCLUSIVELY.
+    // 4. (Property 3, GC invariant). Tail can be shifted safely to the height of the
+    //    earliest existing block. There is always only one Tail (based on property 1)
+    //    and it's always on the Canonical Chain (based on property 2).
+    //
+    // Example:
+    //
+    // height: 101   102   103   104
+    // --------[A]---[B]---[C]---[D]
+    //          \     \
+    //           \     \---[E]
+    //            \
+    //             \-[F]---[G]
+    //
+    // 1. Let's define clearing height = 102. It this case fork A-F-G is protected from deletion
+    //    because of G which is on height 103. Nothing will be deleted.
+    // 2. Let's define clearing height = 103. It this case Fork Clearing will be executed for A
+    //    to delete blocks G and F, then Fork Clearing will be executed for B to delete block E.
+    //    Then Canonical Chain Clearing will delete blocks A and B as unlocked.
+    //    Block C is the only block of height 103 remains on the Canonical Chain (invariant).
+    //
+    // State Sync Clearing:
+    // 1. Executing State Sync means that no data in the storage is useful for block processing
+    //    and should be removed completely.
+    // 2. The Tail should be set to the block preceding Sync Block.
+    // 3. All the data preceding new Tail is deleted in State Sync Clearing
+    //    and the Trie is updated with having only Genesis data.
+    // 4. State Sync Clearing happens in `reset_data_pre_state_sync()`.
+    //
+    pub fn clear_data(
+        &mut self,
+        tries: ShardTries,
+        gc_blocks_limit: NumBlocks,
+    ) -> Result<(), Error> {
+        let _d = DelayDetector::new(|| "GC".into());
+
+        let head = self.store.head()?;
+        let tail = self.store.tail()?;
+        let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+
+        if gc_stop_height > head.height {
+            return Err(ErrorKind::GCError(
+                "gc_stop_height cannot be larger than head.height".into(),
+            )
+            .into());
+        }
+        let prev_epoch_id = self.get_block_header(&head.prev_block_hash)?.epoch_id();
+        let epoch_change = prev_epoch_id != &head.epoch_id;
+        let stored_fork_tail = self.store.fork_tail()?;
+        let mut fork_tail = stored_fork_tail;
+        if epoch_change && fork_tail < gc_stop_height {
+            // if head doesn't change on the epoch boundary, we may update fork tail several times
+            // but that is fine since it doesn't affect correctness and also we limit the number of
+            // heights that fork cleaning goes through so it doesn't slow down client either.
+            fork_tail = gc_stop_height;
+        }
+        let mut gc_blocks_remaining = gc_blocks_limit;
+        self.blocks_delay_tracker.prune_readiness_samples(gc_stop_height);
+
+        // Forks Cleaning
+        // Fork tail is only persisted once below. If the process stops in between, the heights
+        // above the stored fork tail are cleaned again, which is a no-op for cleaned heights.
+        let mut new_fork_tail = fork_tail;
+        let stop_height = std::cmp::max(tail, fork_tail.saturating_sub(GC_FORK_CLEAN_STEP));
+        for height in (stop_height..fork_tail).rev() {
+            self.clear_forks_data(tries.clone(), height, &mut gc_blocks_remaining)?;
+            if gc_blocks_remaining == 0 {
+                break;
+            }
+            new_fork_tail = height;
+        }
+        if new_fork_tail != stored_fork_tail {
+            let mut chain_store_update = self.store.store_update();
+            chain_store_update.update_fork_tail(new_fork_tail);
+            chain_store_update.commit()?;
+        }
+
+        // Canonical Chain Clearing
+        if gc_blocks_remaining > 0 {
+            self.clear_canonical_chain_data(tries, tail, gc_stop_height, &mut gc_blocks_remaining)?;
+        }
+        self.last_gc_run = Some(GcRunInfo {
+            gc_stop_height,
+            tail: self.store.tail()?,
+            num_blocks_collected: gc_blocks_limit - gc_blocks_remaining,
+            finished_at: Clock::utc(),
+        });
+        Ok(())
+    }
+
+    /// Clears the canonical chain from above `tail` up to `gc_stop_height`, stopping at a block
+    /// with forks or once `gc_blocks_remaining` runs out.
+    fn clear_canonical_chain_data(
+        &mut self,
+        tries: ShardTries,
+        tail: BlockHeight,
+        gc_stop_height: BlockHeight,
+        gc_blocks_remaining: &mut NumBlocks,
+    ) -> Result<(), Error> {
+        for height in tail + 1..gc_stop_height {
+            if *gc_blocks_remaining == 0 {
+                return Ok(());
+            }
+            let mut chain_store_update = self.store.store_update();
+            if let Ok(blocks_current_height) =
+                chain_store_update.get_chain_store().get_ordered_block_hashes_by_height(height)
+            {
+                if let Some(block_hash) = blocks_current_height.first() {
+                    let prev_hash = *chain_store_update.get_block_header(block_hash)?.prev_hash();
+                    let prev_block_refcount = *chain_store_update.get_block_refcount(&prev_hash)?;
+                    if prev_block_refcount > 1 {
+                        // Block of `prev_hash` starts a Fork, stopping
+                        break;
+                    } else if prev_block_refcount == 1 {
+                        debug_assert_eq!(blocks_current_height.len(), 1);
+                        // A block saved before state sync is at the tail, where the loop doesn't
+                        // reach, so it is cleared together with its only child.
+                        if chain_store_update
+                            .get_chain_store()
+                            .is_state_sync_boundary_block(&prev_hash)?
                         {
-                            let mut count = 0;
-                            let mut stake = 0;
-                            for (info, is_slashed) in producers.iter() {
-                                if !*is_slashed {
-                                    stake += info.stake();
-                                    count += 1;
-                                }
-                            }
-                            stake /= NEAR_BASE;
-                            metrics::VALIDATOR_AMOUNT_STAKED
-                                .set(i64::try_from(stake).unwrap_or(i64::MAX));
-                            metrics::VALIDATOR_ACTIVE_TOTAL.set(count);
+                            chain_store_update.clear_block_data(
+                                &*self.runtime_adapter,
+                                prev_hash,
+                                GCMode::StateSync { clear_block_info: true },
+                            )?;
                         }
+                        chain_store_update.clear_block_data(
+                            &*self.runtime_adapter,
+                            *block_hash,
+                            GCMode::Canonical(tries.clone()),
+                        )?;
+                        *gc_blocks_remaining -= 1;
+                    } else {
+                        return Err(ErrorKind::GCError(
+                            "block on canonical chain shouldn't have refcount 0".into(),
+                        )
+                        .into());
                     }
-                    None => {}
                 }
+            }
+            if let Some(expired_height) =
+                height.checked_sub(NUM_EPOCHS_TO_KEEP_DISCARDED_FORKS * self.epoch_length)
+            {
+                chain_store_update.clear_discarded_forks(expired_height)?;
+            }
+            chain_store_update.update_tail(height);
+            chain_store_update.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Computes what `clear_data` would delete with the same budget, see `simulate_gc`. Reads
+    /// through a fresh view of the store, so that the blocks cached for processing aren't evicted.
+    pub fn simulate_gc(&self, gc_blocks_limit: NumBlocks) -> Result<GcPlan, Error> {
+        let mut store = ChainStore::new(self.store.owned_store().clone(), self.genesis().height());
+        simulate_gc(&mut store, &*self.runtime_adapter, gc_blocks_limit)
+    }
+
+    /// Prunes the blocks up to `target_tail`, beyond the GC stop height of `clear_data`, for
+    /// operators who urgently need disk space and accept that the removed history can no longer
+    /// be served. Requires `allow_aggressive_gc`.
+    ///
+    /// The target is rejected if it is above `max_aggressive_gc_tail`. Forks below the final
+    /// head are cleared first, so that they don't stop the Canonical Chain Clearing, which then
+    /// runs up to the target. At most `gc_blocks_limit` blocks are removed by one call, the
+    /// report tells whether the target was reached.
+    pub fn garbage_collect_to_height(
+        &mut self,
+        target_tail: BlockHeight,
+        tries: ShardTries,
+        gc_blocks_limit: NumBlocks,
+    ) -> Result<GcReport, Error> {
+        let _d = DelayDetector::new(|| "GC".into());
+
+        if !self.allow_aggressive_gc {
+            return Err(ErrorKind::GCError(
+                "pruning to a height requires allow_aggressive_gc to be set".into(),
+            )
+            .into());
+        }
+        let max_tail = self.max_aggressive_gc_tail()?;
+        if target_tail > max_tail {
+            return Err(ErrorKind::GCError(format!(
+                "target tail {} is above the highest allowed tail {}",
+                target_tail, max_tail
+            ))
+            .into());
+        }
+
+        let tail = self.store.tail()?;
+        let mut gc_blocks_remaining = gc_blocks_limit;
+        if target_tail > tail {
+            self.blocks_delay_tracker.prune_readiness_samples(target_tail + 1);
+            let final_height = self.store.final_head()?.height;
+            for height in (tail..final_height).rev() {
+                self.clear_forks_data(tries.clone(), height, &mut gc_blocks_remaining)?;
+                if gc_blocks_remaining == 0 {
+                    break;
+                }
+            }
+            self.clear_canonical_chain_data(
+                tries,
+                tail,
+                target_tail + 1,
+                &mut gc_blocks_remaining,
+            )?;
+        }
+        let tail = self.store.tail()?;
+        Ok(GcReport {
+            tail,
+            blocks_removed: gc_blocks_limit - gc_blocks_remaining,
+            reached_target: tail >= target_tail,
+        })
+    }
+
+    /// Highest tail `garbage_collect_to_height` may prune to. It is more than
+    /// `GC_TO_HEIGHT_FINAL_HEAD_MARGIN` blocks behind the final head and keeps the blocks of the
+    /// current and the previous epoch, as well as the last block before them, whose epoch
+    /// manager data is needed to validate and process new blocks.
+    pub fn max_aggressive_gc_tail(&mut self) -> Result<BlockHeight, Error> {
+        let genesis_height = self.genesis.header().height();
+        let head = self.store.head()?;
+        let final_height = self.store.final_head()?.height;
+        let epoch_start_height =
+            self.runtime_adapter.get_epoch_start_height(&head.last_block_hash)?;
+        if epoch_start_height <= genesis_height {
+            return Ok(genesis_height);
+        }
+        let epoch_first_block = self.store.get_block_hash_by_height(epoch_start_height)?;
+        let prev_epoch_last_block = *self.get_block_header(&epoch_first_block)?.prev_hash();
+        let prev_epoch_start_height =
+            self.runtime_adapter.get_epoch_start_height(&prev_epoch_last_block)?;
+        Ok(final_height
+            .saturating_sub(GC_TO_HEIGHT_FINAL_HEAD_MARGIN)
+            .min(prev_epoch_start_height.saturating_sub(2))
+            .max(genesis_height))
+    }
+
+    /// Garbage collect data which archival node doesn’t need to keep.
+    ///
+    /// Normally, archival nodes keep all the data from the genesis block and
+    /// don’t run garbage collection.  On the other hand, for better performance
+    /// the storage contains some data duplication, i.e. values in some of the
+    /// columns can be recomputed from data in different columns.  To save on
+    /// storage, archival nodes do garbage collect that data.
+    ///
+    /// `gc_height_limit` limits how many heights will the function process.
+    pub fn clear_archive_data(&mut self, gc_height_limit: BlockHeightDelta) -> Result<(), Error> {
+        let _d = DelayDetector::new(|| "GC".into());
+
+        let head = self.store.head()?;
+        let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+        if gc_stop_height > head.height {
+            return Err(ErrorKind::GCError(
+                "gc_stop_height cannot be larger than head.height".into(),
+            )
+            .into());
+        }
+
+        self.blocks_delay_tracker.prune_readiness_samples(gc_stop_height);
+        // Partial chunks of the latest `retain_partial_chunk_parts_epochs` epochs are kept.
+        let retained_heights = self.retain_partial_chunk_parts_epochs * self.epoch_length;
+        let chunk_gc_stop_height = gc_stop_height.min(head.height.saturating_sub(retained_heights));
+
+        let mut chain_store_update = self.store.store_update();
+        chain_store_update.clear_redundant_chunk_data(chunk_gc_stop_height, gc_height_limit)?;
+        chain_store_update.commit()
+    }
+
+    pub fn clear_forks_data(
+        &mut self,
+        tries: ShardTries,
+        height: BlockHeight,
+        gc_blocks_remaining: &mut NumBlocks,
+    ) -> Result<(), Error> {
+        if let Ok(blocks_current_height) = self.store.get_ordered_block_hashes_by_height(height) {
+            for block_hash in blocks_current_height.iter() {
+                let mut current_hash = *block_hash;
+                loop {
+                    if *gc_blocks_remaining == 0 {
+                        return Ok(());
+                    }
+                    // Block `block_hash` is not on the Canonical Chain
+                    // because shorter chain cannot be Canonical one
+                    // and it may be safely deleted
+                    // and all its ancestors while there are no other sibling blocks rely on it.
+                    let mut chain_store_update = self.store.store_update();
+                    if *chain_store_update.get_block_refcount(&current_hash)? == 0 {
+                        let header = chain_store_update.get_block_header(&current_hash)?.clone();
+                        let prev_hash = *header.prev_hash();
+                        if self.record_discarded_forks {
+                            let fork = DiscardedFork {
+                                height: header.height(),
+                                hash: current_hash,
+                                prev_hash,
+                                producer: self
+                                    .runtime_adapter
+                                    .get_block_producer(header.epoch_id(), header.height())
+                                    .ok(),
+                                timestamp: header.raw_timestamp(),
+                            };
+                            debug!(target: "chain", "Discarding fork block {:?}", fork);
+                            chain_store_update.save_discarded_fork(fork)?;
+                        }
+
+                        // It's safe to call `clear_block_data` for prev data because it clears fork only here
+                        chain_store_update.clear_block_data(
+                            &*self.runtime_adapter,
+                            current_hash,
+                            GCMode::Fork(tries.clone()),
+                        )?;
+                        chain_store_update.commit()?;
+                        *gc_blocks_remaining -= 1;
+
+                        current_hash = prev_hash;
+                    } else {
+                        // Block of `current_hash` is an ancestor for some other blocks, stopping
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Do basic validation of a block upon receiving it. Check that block is
+    /// well-formed (various roots match).
+    pub fn validate_block(&mut self, block: &MaybeValidated<Block>) -> Result<(), Error> {
+        let result = block
+            .validate_with(|block| {
+                Chain::validate_block_impl(
+                    self.runtime_adapter.as_ref(),
+                    self.genesis_block(),
+                    block,
+                    self.trusted_chain_replay,
+                )
+                .map(|_| true)
+            })
+            .map(|_| ());
+        if let Err(err) = &result {
+            if err.is_bad_data() {
+                self.report_misbehavior(ChainMisbehavior::InvalidBlock {
+                    block_hash: *block.get_inner().hash(),
+                    error: err.kind(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Sends the misbehavior to `malicious_behavior_sink` if it is set.
+    fn report_misbehavior(&self, misbehavior: ChainMisbehavior) {
+        if let Some(sink) = &self.malicious_behavior_sink {
+            debug!(target: "chain", "Reporting misbehavior: {:?}", misbehavior);
+            // The receiver may already be gone while the node is shutting down.
+            let _ = sink.send(misbehavior);
+        }
+    }
+
+    fn validate_block_impl(
+        runtime_adapter: &dyn RuntimeAdapter,
+        genesis_block: &Block,
+        block: &Block,
+        trusted_chain_replay: bool,
+    ) -> Result<(), Error> {
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            // Chunks are looked up by their position, so a chunk new in the block must be for
+            // the shard of its position. Carried over chunk headers are compared with the ones of
+            // the previous block when the block is processed.
+            if chunk_header.height_included() == block.header().height()
+                && chunk_header.shard_id() != shard_id as ShardId
+            {
+                return Err(ErrorKind::InvalidChunkShardId {
+                    position: shard_id as ShardId,
+                    shard_id: chunk_header.shard_id(),
+                }
+                .into());
+            }
+            if chunk_header.height_created() == genesis_block.header().height() {
+                // Special case: genesis chunks can be in non-genesis blocks and don't have a signature
+                // We must verify that content matches and signature is empty.
+                // TODO: this code will not work when genesis block has different number of chunks as the current block
+                // https://github.com/near/nearcore/issues/4908
+                let genesis_chunk = &genesis_block.chunks()[shard_id];
+                if genesis_chunk.chunk_hash() != chunk_header.chunk_hash()
+                    || genesis_chunk.signature() != chunk_header.signature()
+                {
+                    return Err(ErrorKind::InvalidChunk.into());
+                }
+            } else if chunk_header.height_created() == block.header().height() {
+                if !Chain::verify_new_chunk_signature(
+                    runtime_adapter,
+                    block,
+                    chunk_header,
+                    trusted_chain_replay,
+                )? {
+                    byzantine_assert!(false);
+                    return Err(ErrorKind::InvalidChunk.into());
+                }
+            }
+        }
+        block.check_validity().map_err(|e| e.into())
+    }
+
+    /// Verifies the signature of a chunk header created at the height of `block`. Chunk header
+    /// signatures are trusted when replaying a trusted chain.
+    fn verify_new_chunk_signature(
+        runtime_adapter: &dyn RuntimeAdapter,
+        block: &Block,
+        chunk_header: &ShardChunkHeader,
+        trusted_chain_replay: bool,
+    ) -> Result<bool, Error> {
+        if trusted_chain_replay {
+            return Ok(true);
+        }
+        runtime_adapter.verify_chunk_header_signature(
+            chunk_header,
+            block.header().epoch_id(),
+            block.header().prev_hash(),
+        )
+    }
+
+    /// Process a block header received during "header first" propagation.
+    pub fn process_block_header(
+        &mut self,
+        header: &BlockHeader,
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<(), Error> {
+        // We create new chain update, but it's not going to be committed so it's read only.
+        let mut chain_update = self.chain_update();
+        chain_update.process_block_header(header, on_challenge)?;
+        Ok(())
+    }
+
+    /// Returns the chunks `me` needs to have locally before `block` can be processed, so they can
+    /// be requested up front instead of after the processing fails with `ChunksMissing`.
+    /// Includes the chunks which are already available.
+    pub fn required_chunks_for_block(
+        &mut self,
+        me: &Option<AccountId>,
+        block: &Block,
+    ) -> Result<RequiredChunks, Error> {
+        let mut chain_update = self.chain_update();
+        chain_update.required_chunks(me, *block.header().prev_hash(), block)
+    }
+
+    pub fn mark_block_as_challenged(
+        &mut self,
+        block_hash: &CryptoHash,
+        challenger_hash: &CryptoHash,
+    ) -> Result<(), Error> {
+        let mut chain_update = self.chain_update();
+        chain_update.mark_block_as_challenged(block_hash, Some(challenger_hash))?;
+        chain_update.commit()?;
+        Ok(())
+    }
+
+    /// Process a received or produced block, and unroll any orphans that may depend on it.
+    /// Changes current state, and calls `block_accepted` callback in case block was successfully applied.
+    pub fn process_block(
+        &mut self,
+        me: &Option<AccountId>,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        block_orphaned_with_missing_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<Option<Tip>, Error> {
+        let _in_flight = self.shutdown.enter()?;
+        self.blocks_delay_tracker.mark_block_received(block.get_inner(), Clock::instant());
+        let block_hash = *block.hash();
+        let res = self.process_block_single(
+            me,
+            block,
+            provenance,
+            block_accepted,
+            block_misses_chunks,
+            block_orphaned_with_missing_chunks,
+            on_challenge,
+        );
+        if res.is_ok() {
+            if let Some(new_res) = self.check_orphans(
+                me,
+                block_hash,
+                block_accepted,
+                block_misses_chunks,
+                block_orphaned_with_missing_chunks,
+                on_challenge,
+            ) {
+                return Ok(Some(new_res));
+            }
+        }
+        res
+    }
+
+    /// Process challenge to invalidate chain. This is done between blocks to unroll the chain as
+    /// soon as possible and allow next block producer to skip invalid blocks.
+    pub fn process_challenge(&mut self, challenge: &Challenge) {
+        let head = unwrap_or_return!(self.head());
+        let mut chain_update = self.chain_update();
+        match chain_update.validate_challenges(
+            &[challenge.clone()],
+            &head.epoch_id,
+            &head.last_block_hash,
+        ) {
+            Ok(validated_challenges) => {
+                unwrap_or_return!(chain_update.apply_challenge_results(&validated_challenges, None));
+            }
+            Err(err) => {
+                warn!(target: "chain", "Invalid challenge: {}\nChallenge: {:#?}", err, challenge);
+            }
+        }
+        unwrap_or_return!(chain_update.commit());
+    }
+
+    /// Processes a batch of challenges like `process_challenge`. Duplicates are dropped, the
+    /// challenges are validated in parallel and the results of the valid ones are committed at
+    /// once. Returns these results.
+    pub fn process_challenges(
+        &mut self,
+        challenges: Vec<Challenge>,
+    ) -> Result<Vec<ValidatedChallenge>, Error> {
+        let head = self.head()?;
+        let mut seen = HashSet::new();
+        let challenges: Vec<_> =
+            challenges.into_iter().filter(|challenge| seen.insert(challenge.hash)).collect();
+        let runtime_adapter = self.runtime_adapter.clone();
+        let results: Vec<_> = challenges
+            .par_iter()
+            .map(|challenge| {
+                validate_single_challenge(
+                    &*runtime_adapter,
+                    &head.epoch_id,
+                    &head.last_block_hash,
+                    challenge,
+                )
+            })
+            .collect();
+
+        let mut validated_challenges = vec![];
+        for (challenge, result) in challenges.iter().zip(results) {
+            match result {
+                Ok(validated_challenge) => validated_challenges.push(validated_challenge),
+                Err(err) => {
+                    warn!(
+                        target: "chain",
+                        "Invalid challenge: {}\nChallenge: {:#?}",
+                        err,
+                        challenge
+                    );
+                }
+            }
+        }
+        let mut chain_update = self.chain_update();
+        chain_update.apply_challenge_results(&validated_challenges, None)?;
+        chain_update.commit()?;
+        Ok(validated_challenges)
+    }
+
+    /// Processes headers and adds them to store for syncing.
+    pub fn sync_block_headers(
+        &mut self,
+        mut headers: Vec<BlockHeader>,
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<(), Error> {
+        // Sort headers by heights if they are out of order.
+        headers.sort_by_key(|left| left.height());
+
+        if let Some(header) = headers.first() {
+            debug!(target: "chain", "Sync block headers: {} headers from {} at {}", headers.len(), header.hash(), header.height());
+        } else {
+            return Ok(());
+        };
+
+        let all_known = if let Some(last_header) = headers.last() {
+            self.store.get_block_header(last_header.hash()).is_ok()
+        } else {
+            false
+        };
+
+        let batch_size = self.header_sync_commit_batch_size;
+        let mut chain_update = self.chain_update();
+        chain_update.deferred_approvals = Some(DeferredApprovals::default());
+        if !all_known {
+            // Validate header and then add to the chain. Validated headers are committed in
+            // batches, so that a long header sync doesn't write to the store once per header.
+            // The approvals of the headers of a batch are verified together before it's
+            // committed.
+            let mut num_uncommitted = 0;
+            let mut batch_epoch_id = None;
+            let mut batch_start = 0;
+            for (index, header) in headers.iter().enumerate() {
+                match check_header_known(&chain_update, header)? {
+                    Ok(_) => {}
+                    Err(_) => continue,
+                }
+
+                let epoch_manager_update = match chain_update
+                    .validate_header(header, &Provenance::SYNC, on_challenge)
+                    .and_then(|_| chain_update.header_epoch_manager_update(header))
+                {
+                    Ok(epoch_manager_update) => epoch_manager_update,
+                    Err(err) => {
+                        // Invalid approvals of an earlier header are reported first.
+                        if let Some((invalid_hash, err)) =
+                            chain_update.verify_deferred_approvals()?
+                        {
+                            drop(chain_update);
+                            return Err(self.reject_synced_header_approvals(
+                                &headers[batch_start..index],
+                                &invalid_hash,
+                                err,
+                            ));
+                        }
+                        // Keep the headers validated before the failing one.
+                        chain_update.commit()?;
+                        if err.is_bad_data() {
+                            self.report_misbehavior(ChainMisbehavior::InvalidHeader {
+                                header_hash: *header.hash(),
+                                error: err.kind(),
+                            });
+                        }
+                        return Err(err.context(format!(
+                            "Failed to sync header {} at height {}",
+                            header.hash(),
+                            header.height()
+                        )));
+                    }
+                };
+                // The epoch manager only keeps the data of uncommitted headers in its caches, and
+                // writes some records, e.g. at the start of an epoch, under keys which other
+                // headers of the batch may have written too. A batch therefore doesn't span
+                // epochs.
+                if num_uncommitted >= batch_size
+                    || batch_epoch_id
+                        .as_ref()
+                        .map_or(false, |epoch_id| epoch_id != header.epoch_id())
+                    || chain_update.chain_store_update.overlaps(&epoch_manager_update)
+                {
+                    if let Some((invalid_hash, err)) = chain_update.verify_deferred_approvals()? {
+                        drop(chain_update);
+                        return Err(self.reject_synced_header_approvals(
+                            &headers[batch_start..index],
+                            &invalid_hash,
+                            err,
+                        ));
+                    }
+                    chain_update.commit()?;
+                    chain_update = self.chain_update();
+                    chain_update.deferred_approvals = Some(DeferredApprovals::default());
+                    num_uncommitted = 0;
+                    batch_start = index;
+                }
+                chain_update.chain_store_update.save_block_header(header.clone())?;
+                chain_update.chain_store_update.merge(epoch_manager_update);
+                batch_epoch_id = Some(header.epoch_id().clone());
+                num_uncommitted += 1;
+            }
+            if let Some((invalid_hash, err)) = chain_update.verify_deferred_approvals()? {
+                drop(chain_update);
+                return Err(self.reject_synced_header_approvals(
+                    &headers[batch_start..],
+                    &invalid_hash,
+                    err,
+                ));
+            }
+        }
+
+        if let Some(header) = headers.last() {
+            // Update header_head if it's the new tip
+            chain_update.update_header_head_if_not_challenged(header)?;
+        }
+
+        chain_update.commit()
+    }
+
+    /// Saves the synced headers of a batch before the one with invalid or not enough approvals,
+    /// found once the batch was validated, and returns the error for the invalid header.
+    fn reject_synced_header_approvals(
+        &mut self,
+        batch: &[BlockHeader],
+        invalid_hash: &CryptoHash,
+        err: Error,
+    ) -> Error {
+        let num_valid =
+            batch.iter().position(|header| header.hash() == invalid_hash).unwrap_or(batch.len());
+        // Challenges were already sent while the batch was validated the first time.
+        let mut chain_update = self.chain_update();
+        for header in &batch[..num_valid] {
+            match check_header_known(&chain_update, header) {
+                Ok(Ok(_)) => {}
+                Ok(Err(_)) => continue,
+                Err(err) => return err,
+            }
+            let res = chain_update
+                .validate_header(header, &Provenance::SYNC, &mut |_| {})
+                .and_then(|_| chain_update.header_epoch_manager_update(header))
+                .and_then(|epoch_manager_update| {
+                    chain_update.chain_store_update.save_block_header(header.clone())?;
+                    chain_update.chain_store_update.merge(epoch_manager_update);
+                    Ok(())
+                });
+            if let Err(err) = res {
+                return err;
+            }
+        }
+        if let Err(err) = chain_update.commit() {
+            return err;
+        }
+
+        let invalid_height = batch.get(num_valid).map_or(0, |header| header.height());
+        self.report_misbehavior(ChainMisbehavior::InvalidHeader {
+            header_hash: *invalid_hash,
+            error: err.kind(),
+        });
+        err.context(format!("Failed to sync header {} at height {}", invalid_hash, invalid_height))
+    }
+
+    /// Returns if given block header is on the current chain.
+    pub fn is_on_current_chain(&mut self, header: &BlockHeader) -> Result<(), Error> {
+        let chain_header = self.get_header_by_height(header.height())?;
+        if chain_header.hash() == header.hash() {
+            Ok(())
+        } else {
+            Err(ErrorKind::Other(format!("{} not on current chain", header.hash())).into())
+        }
+    }
+
+    /// Finds first of the given hashes that is known on the main chain.
+    pub fn find_common_header(&mut self, hashes: &[CryptoHash]) -> Option<BlockHeader> {
+        for hash in hashes {
+            if let Ok(header) = self.get_block_header(hash).map(|h| h.clone()) {
+                if let Ok(header_at_height) = self.get_header_by_height(header.height()) {
+                    if header.hash() == header_at_height.hash() {
+                        return Some(header);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn determine_status(&self, head: Option<Tip>, prev_head: Tip) -> BlockStatus {
+        let has_head = head.is_some();
+        let mut is_next_block = false;
+
+        let old_hash = if let Some(head) = head {
+            if head.prev_block_hash == prev_head.last_block_hash {
+                is_next_block = true;
+                None
+            } else {
+                Some(prev_head.last_block_hash)
+            }
+        } else {
+            None
+        };
+
+        match (has_head, is_next_block) {
+            (true, true) => BlockStatus::Next,
+            (true, false) => BlockStatus::Reorg(old_hash.unwrap()),
+            (false, _) => BlockStatus::Fork,
+        }
+    }
+
+    pub fn reset_data_pre_state_sync(&mut self, sync_hash: CryptoHash) -> Result<(), Error> {
+        let head = self.head()?;
+        // Get header we were syncing into.
+        let header = self.get_block_header(&sync_hash)?;
+        let prev_hash = *header.prev_hash();
+        let sync_height = header.height();
+        let gc_height = std::cmp::min(head.height + 1, sync_height);
+
+        // GC all the data from current tail up to `gc_height`. In case tail points to a height where
+        // there is no block, we need to make sure that the last block before tail is cleaned.
+        let tail = self.store.tail()?;
+        let mut tail_prev_block_cleaned = false;
+        for height in tail..gc_height {
+            if let Ok(blocks_current_height) = self.store.get_ordered_block_hashes_by_height(height)
+            {
+                for block_hash in blocks_current_height {
+                    let runtime_adapter = self.runtime_adapter();
+                    let mut chain_store_update = self.mut_store().store_update();
+                    if !tail_prev_block_cleaned {
+                        let prev_block_hash =
+                            *chain_store_update.get_block_header(&block_hash)?.prev_hash();
+                        if chain_store_update.get_block(&prev_block_hash).is_ok() {
+                            chain_store_update.clear_block_data(
+                                &*runtime_adapter,
+                                prev_block_hash,
+                                GCMode::StateSync { clear_block_info: true },
+                            )?;
+                        }
+                        tail_prev_block_cleaned = true;
+                    }
+                    chain_store_update.clear_block_data(
+                        &*runtime_adapter,
+                        block_hash,
+                        GCMode::StateSync { clear_block_info: block_hash != prev_hash },
+                    )?;
+                    chain_store_update.commit()?;
+                }
+            }
+        }
+
+        // Clear Chunks data
+        let mut chain_store_update = self.mut_store().store_update();
+        // The largest height of chunk we have in storage is head.height + 1
+        let chunk_height = std::cmp::min(head.height + 2, sync_height);
+        chain_store_update.clear_chunk_data_and_headers(chunk_height)?;
+        chain_store_update.commit()?;
+
+        // clear all trie data
+
+        let tries = self.runtime_adapter.get_tries();
+        let mut chain_store_update = self.mut_store().store_update();
+        let mut store_update = StoreUpdate::new_with_tries(tries);
+        store_update.delete_all(ColState);
+        // Finalization progress refers to the state deleted above.
+        store_update.delete_all(ColStateSyncFinalizeProgress);
+        chain_store_update.merge(store_update);
+
+        // The reason to reset tail here is not to allow Tail be greater than Head
+        chain_store_update.reset_tail();
+        chain_store_update.commit()?;
+
+        // Parts downloaded for other syncs won't be used anymore.
+        self.state_part_provenance.retain(|(_, hash), _| hash == &sync_hash);
+        self.stored_state_parts.retain(|(_, hash), _| hash == &sync_hash);
+
+        self.prune_orphans_below_height(gc_height);
+        Ok(())
+    }
+
+    /// Removes the orphans below `height`, whose prev blocks are gone after the data is reset
+    /// for state sync, so that they don't take slots in the orphan pool until they expire.
+    /// Returns the hashes of the removed orphans.
+    pub fn prune_orphans_below_height(&mut self, height: BlockHeight) -> Vec<CryptoHash> {
+        let pruned = self.orphans.prune_below_height(height);
+        if !pruned.is_empty() {
+            debug!(target: "chain", "Pruned {} orphans below height {}", pruned.len(), height);
+            metrics::NUM_ORPHANS.set(self.orphans.len() as i64);
+        }
+        for hash in pruned.iter() {
+            self.blocks_delay_tracker.mark_block_dropped(hash);
+        }
+        pruned
+    }
+
+    /// Set the new head after state sync was completed if it is indeed newer.
+    /// Check for potentially unlocked orphans after this update.
+    pub fn reset_heads_post_state_sync(
+        &mut self,
+        me: &Option<AccountId>,
+        sync_hash: CryptoHash,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<(), Error> {
+        // Get header we were syncing into.
+        let header = self.get_block_header(&sync_hash)?;
+        let hash = *header.prev_hash();
+        let prev_block = self.get_block(&hash)?;
+        let new_tail = prev_block.header().height();
+        let new_chunk_tail = prev_block.chunks().iter().map(|x| x.height_created()).min().unwrap();
+        let tip = Tip::from_header(prev_block.header());
+        let last_final_block = *prev_block.header().last_final_block();
+        // We don't have the body of the last final block, but its header was synced before
+        // state sync, which is enough to tell how far the chain is finalized.
+        let (final_head, source) = if last_final_block == CryptoHash::default() {
+            (Tip::from_header(self.genesis.header()), "genesis")
+        } else if let Ok(header) = self.get_block_header(&last_final_block) {
+            (Tip::from_header(header), "last_final_block")
+        } else {
+            (tip.clone(), "prev_block")
+        };
+        metrics::STATE_SYNC_FINAL_HEAD_SOURCE.with_label_values(&[source]).inc();
+        debug!(
+            target: "chain",
+            "Resetting final head to {:?} at height {} after state sync ({})",
+            final_head.last_block_hash, final_head.height, source
+        );
+        // Update related heads now.
+        let mut chain_store_update = self.mut_store().store_update();
+        chain_store_update.save_body_head(&tip)?;
+        chain_store_update.save_final_head(&final_head)?;
+        // New Tail can not be earlier than `prev_block.header.inner_lite.height`
+        chain_store_update.update_tail(new_tail);
+        // New Chunk Tail can not be earlier than minimum of height_created in Block `prev_block`
+        chain_store_update.update_chunk_tail(new_chunk_tail);
+        chain_store_update.commit()?;
+        self.prune_orphans_below_height(new_tail);
+
+        // Check if there are any orphans unlocked by this state sync.
+        // We can't fail beyond this point because the caller will not process accepted blocks
+        //    and the blocks with missing chunks if this method fails
+        self.check_orphans(
+            me,
+            hash,
+            block_accepted,
+            block_misses_chunks,
+            orphan_misses_chunks,
+            on_challenge,
+        );
+        Ok(())
+    }
+
+    // Processes a single block, increments the metric for the number of blocks processing and also
+    // for the number of blocks processed successfully (returns OK).
+    fn process_block_single(
+        &mut self,
+        me: &Option<AccountId>,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<Option<Tip>, Error> {
+        let block_hash = *block.hash();
+        if !self.blocks_in_processing.insert(block_hash) {
+            return Err(ErrorKind::BlockKnown(BlockKnownError::KnownInProcessing).into());
+        }
+        metrics::BLOCK_PROCESSING_ATTEMPTS_TOTAL.inc();
+        metrics::NUM_ORPHANS.set(self.orphans.len() as i64);
+        let _timer = CryptoHashTimer::new(block_hash);
+        let success_timer = metrics::BLOCK_PROCESSING_TIME.start_timer();
+
+        let res = self.process_block_single_impl(
+            me,
+            block,
+            provenance,
+            block_accepted,
+            block_misses_chunks,
+            orphan_misses_chunks,
+            on_challenge,
+        );
+        self.blocks_in_processing.remove(&block_hash);
+
+        if res.is_ok() {
+            metrics::BLOCK_PROCESSED_TOTAL.inc();
+            success_timer.stop_and_record();
+        } else {
+            success_timer.stop_and_discard();
+        }
+        res
+    }
+
+    // Block processing. Unlike process_block_single() this function doesn't update metrics for
+    // successful blocks processing.
+    fn process_block_single_impl(
+        &mut self,
+        me: &Option<AccountId>,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Result<Option<Tip>, Error> {
+        // Blocks known to be invalid are rejected before looking at the store.
+        if let Some(kind) = self.invalid_blocks.get(block.hash()) {
+            debug!(target: "chain", "Block {} is known to be invalid: {:?}", block.hash(), kind);
+            return Err(kind.clone().into());
+        }
+        #[cfg(test)]
+        if let Some(mut hook) = self.block_validation_hook.take() {
+            hook(self, block.get_inner());
+            self.block_validation_hook = Some(hook);
+        }
+        let prev_head = self.store.head()?;
+        let prev_final_height = self.store.final_head()?.height;
+        let record_approval_audit =
+            self.record_approval_audit && provenance == Provenance::PRODUCED;
+        let mut chain_update = self.chain_update();
+        let maybe_new_head = chain_update.process_block(me, &block, &provenance, on_challenge);
+        let block_height = block.header().height();
+
+        match maybe_new_head {
+            Ok(head) => {
+                chain_update.chain_store_update.save_block_height_processed(block_height);
+                if record_approval_audit {
+                    chain_update.save_approval_audit(block.header())?;
+                }
+                let chunk_applied_events = std::mem::take(&mut chain_update.chunk_applied_events);
+                let chunk_apply_durations = std::mem::take(&mut chain_update.chunk_apply_durations);
+                chain_update.commit()?;
+
+                self.pending_states_to_patch = None;
+
+                let now = Clock::instant();
+                self.blocks_delay_tracker.mark_block_accepted(block.hash(), block_height, now);
+                for (shard_id, apply_duration) in chunk_apply_durations.iter() {
+                    self.blocks_delay_tracker.mark_chunk_applied(*shard_id, *apply_duration);
+                }
+                let slowest_shard =
+                    chunk_apply_durations.into_iter().max_by_key(|(_, duration)| *duration);
+                self.record_approval_participation(block.header());
+                self.record_chunk_inclusion_delays(block.get_inner());
+                if let Ok(final_head) = self.store.final_head() {
+                    if final_head.height > prev_final_height {
+                        self.record_newly_final_blocks(
+                            final_head.last_block_hash,
+                            prev_final_height,
+                            now,
+                        );
+                    }
+                }
+
+                match &head {
+                    Some(tip) => {
+                        self.record_receipt_backlog(&tip.last_block_hash);
+                        if let Ok(producers) = self
+                            .runtime_adapter
+                            .get_epoch_block_producers_ordered(&tip.epoch_id, &tip.last_block_hash)
+                        {
+                            let mut count = 0;
+                            let mut stake = 0;
+                            for (info, is_slashed) in producers.iter() {
+                                if !*is_slashed {
+                                    stake += info.stake();
+                                    count += 1;
+                                }
+                            }
+                            stake /= NEAR_BASE;
+                            metrics::VALIDATOR_AMOUNT_STAKED
+                                .set(i64::try_from(stake).unwrap_or(i64::MAX));
+                            metrics::VALIDATOR_ACTIVE_TOTAL.set(count);
+                        }
+                    }
+                    None => {}
+                }
+
+                let status = self.determine_status(head.clone(), prev_head.clone());
+
+                if !self.event_handlers.is_empty() {
+                    for event in chunk_applied_events {
+                        self.event_handlers.on_chunk_applied(event);
+                    }
+                    if let (BlockStatus::Reorg(_), Some(new_head)) = (&status, &head) {
+                        self.event_handlers.on_reorg(ReorgEvent {
+                            old_head: prev_head,
+                            new_head: new_head.clone(),
+                        });
+                    }
+                    self.event_handlers.on_block_accepted(BlockAcceptedEvent {
+                        block: block.get_inner().clone(),
+                        status: status.clone(),
+                        provenance: provenance.clone(),
+                    });
+                }
+
+                // Notify other parts of the system of the update.
+                block_accepted(AcceptedBlock {
+                    hash: *block.hash(),
+                    status,
+                    provenance,
+                    slowest_shard,
+                });
+
+                Ok(head)
+            }
+            Err(e) => {
+                if e.is_bad_data() {
+                    self.record_rejected_block(block.header(), &e);
+                }
+                if Self::is_cacheable_rejection(block.get_inner(), &e) {
+                    self.invalid_blocks.put(*block.hash(), e.kind());
+                }
+                match e.kind() {
+                    ErrorKind::Orphan => {
+                        let tail_height = self.store.tail()?;
+                        // we only add blocks that couldn't have been gc'ed to the orphan pool.
+                        if block_height >= tail_height {
+                            let block_hash = *block.hash();
+                            let requested_missing_chunks = if let Some(orphan_missing_chunks) =
+                                self.should_request_chunks_for_orphan(me, &block)
+                            {
+                                debug!(target:"chain", "Request missing chunks for orphan {:?} {:?}", block_hash, orphan_missing_chunks.missing_chunks);
+                                // This callback handles requesting missing chunks. It adds the missing chunks
+                                // to a list and all missing chunks in the list will be requested
+                                // at the end of Client::process_block
+                                orphan_misses_chunks(orphan_missing_chunks);
+                                Some(self.orphan_extends_head(&block))
+                            } else {
+                                None
+                            };
+
+                            let time = Clock::instant();
+                            self.blocks_delay_tracker.mark_block_orphaned(block.hash(), time);
+                            let orphan = Orphan { block, provenance, added: time };
+                            self.orphans.add(orphan, false);
+                            if let Some(extends_head) = requested_missing_chunks {
+                                self.orphans.mark_missing_chunks_requested_for_orphan(
+                                    block_hash,
+                                    extends_head,
+                                );
+                            }
+
+                            debug!(
+                                target: "chain",
+                                "Process block: orphan: {:?}, # orphans {}{}",
+                                block_hash,
+                                self.orphans.len(),
+                                if self.orphans.len_evicted() > 0 {
+                                    format!(", # evicted {}", self.orphans.len_evicted())
+                                } else {
+                                    String::new()
+                                },
+                            );
+                        }
+                    }
+                    ErrorKind::ChunksMissing(missing_chunks) => {
+                        let block_hash = *block.hash();
+                        let missing_chunk_hashes: Vec<_> =
+                            missing_chunks.iter().map(|header| header.chunk_hash()).collect();
+                        block_misses_chunks(BlockMissingChunks {
+                            prev_hash: *block.header().prev_hash(),
+                            missing_chunks,
+                            block_hash,
+                        });
+                        let time = Clock::instant();
+                        self.blocks_delay_tracker.mark_block_has_missing_chunks(block.hash(), time);
+                        let orphan = Orphan { block, provenance, added: time };
+                        self.blocks_with_missing_chunks
+                            .add_block_with_missing_chunks(orphan, missing_chunk_hashes.clone());
+                        debug!(
+                            target: "chain",
+                            "Process block: missing chunks. Block hash: {:?}. Missing chunks: {:?}",
+                            block_hash, missing_chunk_hashes,
+                        );
+                    }
+                    ErrorKind::BlockAheadOfClock(timestamp) => {
+                        let block_hash = *block.hash();
+                        if self
+                            .time_delayed_blocks
+                            .iter()
+                            .any(|delayed| delayed.block.hash() == &block_hash)
+                        {
+                            debug!(target: "chain", "Block {} ahead of the local clock is already delayed", block_hash);
+                        } else if self.time_delayed_blocks.len() >= MAX_TIME_DELAYED_BLOCKS {
+                            debug!(target: "chain", "Dropping block {} ahead of the local clock, {} blocks are already delayed", block_hash, self.time_delayed_blocks.len());
+                        } else {
+                            let orphan = Orphan { block, provenance, added: Clock::instant() };
+                            self.time_delayed_blocks.push(orphan);
+                            debug!(
+                                target: "chain",
+                                "Process block: delaying block {} with timestamp {} until the local clock catches up, # delayed {}",
+                                block_hash,
+                                timestamp,
+                                self.time_delayed_blocks.len(),
+                            );
+                        }
+                    }
+                    ErrorKind::EpochOutOfBounds(ref epoch_id) => {
+                        // Possibly block arrived before we finished processing all of the blocks for epoch before last.
+                        // Or someone is attacking with invalid chain.
+                        debug!(target: "chain", "Received block {}/{} ignored, as epoch {:?} is unknown", block_height, block.hash(), epoch_id);
+                    }
+                    ErrorKind::BlockKnown(ref block_known_error) => {
+                        debug!(
+                            target: "chain",
+                            "Block {} at {} is known at this time: {:?}",
+                            block.hash(),
+                            block_height,
+                            block_known_error);
+                    }
+                    ErrorKind::UnsupportedProtocolVersion { epoch_version, binary_version } => {
+                        match self.unsupported_protocol_version_policy {
+                            UnsupportedProtocolVersionPolicy::Halt => {
+                                error!(
+                                    target: "chain",
+                                    "Block {} at {} has protocol version {}, but this binary only supports up to {}. Block processing is halted, please update nearcore",
+                                    block.hash(),
+                                    block_height,
+                                    epoch_version,
+                                    binary_version);
+                            }
+                            UnsupportedProtocolVersionPolicy::FollowHeadersOnly => {
+                                error!(
+                                    target: "chain",
+                                    "Block {} at {} has protocol version {}, but this binary only supports up to {}. Following headers only, please update nearcore",
+                                    block.hash(),
+                                    block_height,
+                                    epoch_version,
+                                    binary_version);
+                                if let Err(err) = self
+                                    .sync_block_headers(vec![block.header().clone()], on_challenge)
+                                {
+                                    debug!(target: "chain", "Failed to process header of block {}: {}", block.hash(), err);
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                if let Err(e) = self.save_block_height_processed(block_height) {
+                    warn!(target: "chain", "Failed to save processed height {}: {}", block_height, e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether the block can be rejected with the error again by its hash alone. Errors
+    /// depending on the clock or the head of the node may go away, and errors caused by data the
+    /// hash doesn't commit to, i.e. signatures or chunks not matching the chunk headers root,
+    /// would let a tampered copy of a valid block get the valid block rejected.
+    fn is_cacheable_rejection(block: &Block, error: &Error) -> bool {
+        if !error.is_bad_data() {
+            return false;
+        }
+        match error.kind() {
+            ErrorKind::InvalidSignature
+            | ErrorKind::InvalidChunk
+            | ErrorKind::InvalidBlockFutureTime(_)
+            | ErrorKind::InvalidBlockHeight(_)
+            | ErrorKind::BlockTooLarge(_, _) => false,
+            _ => {
+                block.header().chunk_headers_root()
+                    == &Block::compute_chunk_headers_root(block.chunks().iter()).0
+                    && block.header().challenges_root()
+                        == &Block::compute_challenges_root(block.challenges())
+            }
+        }
+    }
+
+    /// Returns the error a recently processed block was rejected with, if it is known to be
+    /// invalid. Blocks rejected for transient reasons, like orphans or blocks with missing
+    /// chunks, are not remembered.
+    pub fn is_block_invalid(&self, block_hash: &CryptoHash) -> Option<ErrorKind> {
+        self.invalid_blocks.peek(block_hash).cloned()
+    }
+
+    /// Like `is_block_invalid`, but only if a peer relaying the block could have found the error
+    /// as well. Blocks are relayed before their chunks are applied, by peers which may not track
+    /// the shards, so errors found when applying the chunks or comparing them with the results
+    /// of the previous chunks don't count.
+    pub fn is_block_invalid_before_apply(&self, block_hash: &CryptoHash) -> Option<ErrorKind> {
+        self.is_block_invalid(block_hash).filter(|kind| match kind {
+            ErrorKind::InvalidBlockPastTime(_, _)
+            | ErrorKind::InvalidBlockProposer
+            | ErrorKind::InvalidChunkHeadersRoot
+            | ErrorKind::InvalidChunkTxRoot
+            | ErrorKind::InvalidChunkReceiptsRoot
+            | ErrorKind::InvalidChunkMask(_)
+            | ErrorKind::InvalidChunkShardId { .. }
+            | ErrorKind::IncorrectNumberOfChunkHeaders
+            | ErrorKind::InvalidChallengeRoot
+            | ErrorKind::InvalidEpochHash
+            | ErrorKind::InvalidNextBPHash
+            | ErrorKind::NotEnoughApprovals
+            | ErrorKind::InvalidFinalityInfo
+            | ErrorKind::InvalidApprovals
+            | ErrorKind::InvalidGasPrice
+            | ErrorKind::InvalidRandomnessBeaconOutput
+            | ErrorKind::InvalidBlockMerkleRoot => true,
+            _ => false,
+        })
+    }
+
+    /// Keeps a record of a block rejected as invalid, see `Chain::rejected_blocks`.
+    fn record_rejected_block(&mut self, header: &BlockHeader, error: &Error) {
+        let rejected = RejectedBlock {
+            hash: *header.hash(),
+            height: header.height(),
+            producer: self
+                .runtime_adapter
+                .get_block_producer(header.epoch_id(), header.height())
+                .ok(),
+            error: format!("{:?}", error.kind()),
+            timestamp: to_timestamp(Clock::utc()),
+        };
+        debug!(target: "chain", "Rejected block {:?}", rejected);
+        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
+        if let Err(err) = chain_store_update
+            .save_rejected_block(rejected)
+            .and_then(|_| chain_store_update.commit())
+        {
+            warn!(target: "chain", "Failed to record rejected block {}: {}", header.hash(), err);
+        }
+    }
+
+    /// Returns true if the orphan extends the head, i.e. it descends from the head or from a
+    /// block on the chain of the header head. The ancestors of the orphan are looked up among
+    /// the orphans, the blocks with missing chunks and the stored headers, and only the
+    /// `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the orphan are checked, as orphans
+    /// further away from an accepted block don't get their chunks requested anyway.
+    pub(crate) fn orphan_extends_head(&mut self, orphan: &Block) -> bool {
+        let head = match self.head() {
+            Ok(head) => head,
+            Err(_) => return false,
+        };
+        let mut hash = *orphan.hash();
+        let mut height = orphan.header().height();
+        let mut prev_hash = *orphan.header().prev_hash();
+        let mut num_checked = 0;
+        loop {
+            if hash == head.last_block_hash {
+                return true;
+            }
+            if height <= head.height {
+                return false;
+            }
+            if self.get_block_hash_by_height(height).map_or(false, |h| h == hash) {
+                return true;
+            }
+            if num_checked == NUM_ORPHAN_ANCESTORS_CHECK {
+                return false;
+            }
+            num_checked += 1;
+            hash = prev_hash;
+            if let Some(parent) = self.orphans.get(&hash) {
+                height = parent.height();
+                prev_hash = *parent.prev_hash();
+            } else if let Some(parent) = self.blocks_with_missing_chunks.get(&hash) {
+                height = parent.height();
+                prev_hash = *parent.prev_hash();
+            } else if let Ok(header) = self.get_block_header(&hash) {
+                height = header.height();
+                prev_hash = *header.prev_hash();
+            } else {
+                return false;
+            }
+        }
+    }
+
+    /// Check if we can request chunks for this orphan. Conditions are
+    /// 1) Orphans of the same kind with outstanding missing chunks requests don't exceed their
+    ///    limit, see `OrphanBlockPool::can_request_missing_chunks_for_orphan`
+    /// 2) we haven't already requested missing chunks for the orphan
+    /// 3) All the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block are either accepted,
+    ///    or orphans or in `blocks_with_missing_chunks`
+    /// 4) Among the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block at least one is
+    ///    accepted(is in store), call it `ancestor`
+    /// 5) The next block of `ancestor` has the same epoch_id as the orphan block
+    ///    (This is because when requesting chunks, we will use `ancestor` hash instead of the
+    ///     previous block hash of the orphan to decide epoch id)
+    /// 6) The orphan has missing chunks
+    pub fn should_request_chunks_for_orphan(
+        &mut self,
+        me: &Option<AccountId>,
+        orphan: &Block,
+    ) -> Option<OrphanMissingChunks> {
+        // 1) Orphans of the same kind with outstanding missing chunks requests don't exceed their limit
+        // 2) we haven't already requested missing chunks for the orphan
+        let head = self.head().ok()?;
+        let num_shards = self.runtime_adapter.num_shards(&head.epoch_id).ok()?;
+        let extends_head = self.orphan_extends_head(orphan);
+        if !self.orphans.can_request_missing_chunks_for_orphan(
+            orphan.hash(),
+            extends_head,
+            num_shards,
+        ) {
+            return None;
+        }
+        let mut block_hash = *orphan.header().prev_hash();
+        for _ in 0..NUM_ORPHAN_ANCESTORS_CHECK {
+            // 3) All the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block are either accepted,
+            //    or orphans or in `blocks_with_missing_chunks`
+            if let Some(block) = self.blocks_with_missing_chunks.get(&block_hash) {
+                block_hash = *block.prev_hash();
+                continue;
+            }
+            if let Some(orphan) = self.orphans.get(&block_hash) {
+                block_hash = *orphan.prev_hash();
+                continue;
+            }
+            // 4) Among the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block at least one is
+            //    accepted(is in store), call it `ancestor`
+            if self.get_block(&block_hash).is_ok() {
+                if let Ok(epoch_id) = self.runtime_adapter.get_epoch_id_from_prev_block(&block_hash)
+                {
+                    // 5) The next block of `ancestor` has the same epoch_id as the orphan block
+                    if &epoch_id == orphan.header().epoch_id() {
+                        let mut chain_update = self.chain_update();
+                        // 6) The orphan has missing chunks
+                        if let Err(e) = chain_update.ping_missing_chunks(me, block_hash, orphan) {
+                            return match e.kind() {
+                                ErrorKind::ChunksMissing(missing_chunks) => {
+                                    Some(OrphanMissingChunks {
+                                        missing_chunks,
+                                        epoch_id,
+                                        ancestor_hash: block_hash,
+                                        requestor_block_hash: *orphan.header().hash(),
+                                    })
+                                }
+                                _ => None,
+                            };
+                        }
+                    }
+                }
+                return None;
+            }
+            return None;
+        }
+        None
+    }
+
+    /// only used for test
+    pub fn check_orphan_partial_chunks_requested(&self, block_hash: &CryptoHash) -> bool {
+        self.orphans.orphans_requested_missing_chunks.contains(block_hash)
+    }
+
+    pub fn prev_block_is_caught_up(
+        &self,
+        prev_prev_hash: &CryptoHash,
+        prev_hash: &CryptoHash,
+    ) -> Result<bool, Error> {
+        // This method is identical to `ChainUpdate::prev_block_is_caught_up`, see important
+        //    disclaimers in there on some dangers of using it
+        Ok(!self.store.get_blocks_to_catchup(prev_prev_hash)?.contains(prev_hash))
+    }
+
+    /// Return all shards that whose states need to be caught up
+    /// That has two cases:
+    /// 1) Shard layout will change in the next epoch. In this case, the method returns all shards
+    ///    in the current epoch that will be split into a future shard that `me` will track.
+    /// 2) Shard layout will be the same. In this case, the method returns all shards that `me` will
+    ///    track in the next epoch but not this epoch
+    fn get_shards_to_dl_state(
+        runtime_adapter: &dyn RuntimeAdapter,
+        shard_care: &ShardCareMatrix,
+        parent_hash: &CryptoHash,
+    ) -> Vec<ShardId> {
+        let will_shard_layout_change =
+            runtime_adapter.will_shard_layout_change_next_epoch(parent_hash).unwrap_or(false);
+        (0..shard_care.num_shards())
+            .filter(|shard_id| {
+                Self::should_catch_up_shard(shard_care, will_shard_layout_change, *shard_id)
+            })
+            .collect()
+    }
+
+    fn should_catch_up_shard(
+        shard_care: &ShardCareMatrix,
+        will_shard_layout_change: bool,
+        shard_id: ShardId,
+    ) -> bool {
+        // if shard layout will change the next epoch, we should catch up the shard regardless
+        // whether we already have the shard's state this epoch, because we need to generate
+        // new states for shards split from the current shard for the next epoch
+        shard_care.will_care_about_shard(shard_id)
+            && (will_shard_layout_change || !shard_care.cares_about_shard(shard_id))
+    }
+
+    /// Check if any block with missing chunk is ready to be processed. Once `time_budget` is used
+    /// up, the remaining ready blocks are left in the pool for the next check.
+    /// Returns the number of such deferred blocks. Does nothing once the shutdown is requested.
+    pub fn check_blocks_with_missing_chunks(
+        &mut self,
+        me: &Option<AccountId>,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+        time_budget: TimeDuration,
+    ) -> usize {
+        let _in_flight = match self.shutdown.enter() {
+            Ok(in_flight) => in_flight,
+            Err(_) => return 0,
+        };
+        let _timer = metrics::CHECK_BLOCKS_WITH_MISSING_CHUNKS_TIME.start_timer();
+        let start = Clock::instant();
+        let mut new_blocks_accepted = vec![];
+        let mut orphans = self.blocks_with_missing_chunks.ready_blocks().into_iter();
+        while let Some(orphan) = orphans.next() {
+            let block_hash = *orphan.block.header().hash();
+            let time = Clock::instant();
+            if let Err(err) = self.prefetch_for_block(me, orphan.block.get_inner()) {
+                debug!(target: "chain", "Failed to prefetch state for block {}: {:?}", block_hash, err);
+            }
+            let res = self.process_block_single(
+                me,
+                orphan.block,
+                orphan.provenance,
+                block_accepted,
+                block_misses_chunks,
+                orphan_misses_chunks,
+                on_challenge,
+            );
+            match res {
+                Ok(_) => {
+                    debug!(target: "chain", "Block with missing chunks is accepted; me: {:?}", me);
+                    self.blocks_delay_tracker
+                        .mark_block_completed_missing_chunks(&block_hash, time);
+                    new_blocks_accepted.push(block_hash);
+                }
+                Err(_) => {
+                    debug!(target: "chain", "Block with missing chunks is declined; me: {:?}", me);
+                }
+            }
+            if Clock::instant().saturating_duration_since(start) >= time_budget {
+                break;
+            }
+        }
+        let deferred = orphans.collect::<Vec<_>>();
+        let num_deferred = deferred.len();
+        if num_deferred > 0 {
+            debug!(target: "chain", "Time budget of {:?} for blocks with missing chunks is used up, deferring {} blocks", time_budget, num_deferred);
+            metrics::BLOCKS_WITH_MISSING_CHUNKS_DEFERRED.inc_by(num_deferred as u64);
+            self.blocks_with_missing_chunks.return_ready_blocks(deferred);
+        }
+
+        for accepted_block in new_blocks_accepted {
+            self.check_orphans(
+                me,
+                accepted_block,
+                block_accepted,
+                block_misses_chunks,
+                orphan_misses_chunks,
+                on_challenge,
+            );
+        }
+        num_deferred
+    }
+
+    /// Whether there are blocks held back until the local clock catches up with them.
+    pub fn has_time_delayed_blocks(&self) -> bool {
+        !self.time_delayed_blocks.is_empty()
+    }
+
+    /// Processes the blocks held back because they were ahead of the local clock, once the clock
+    /// caught up with them. Returns the number of blocks still waiting.
+    pub fn retry_time_delayed_blocks(
+        &mut self,
+        me: &Option<AccountId>,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> usize {
+        let max_timestamp = Clock::utc() + Duration::seconds(ACCEPTABLE_TIME_DIFFERENCE);
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.time_delayed_blocks)
+            .into_iter()
+            .partition(|delayed| delayed.block.header().timestamp() <= max_timestamp);
+        self.time_delayed_blocks = waiting;
+        let mut new_blocks_accepted = vec![];
+        for delayed in ready {
+            let block_hash = *delayed.block.hash();
+            let res = self.process_block_single(
+                me,
+                delayed.block,
+                delayed.provenance,
+                block_accepted,
+                block_misses_chunks,
+                orphan_misses_chunks,
+                on_challenge,
+            );
+            match res {
+                Ok(_) => {
+                    debug!(target: "chain", "Block {} delayed for {:?} until the local clock caught up is accepted", block_hash, delayed.added.elapsed());
+                    new_blocks_accepted.push(block_hash);
+                }
+                Err(err) => {
+                    debug!(target: "chain", "Block {} delayed until the local clock caught up is declined: {}", block_hash, err);
+                }
+            }
+        }
+
+        for accepted_block in new_blocks_accepted {
+            self.check_orphans(
+                me,
+                accepted_block,
+                block_accepted,
+                block_misses_chunks,
+                orphan_misses_chunks,
+                on_challenge,
+            );
+        }
+        self.time_delayed_blocks.len()
+    }
+
+    /// Check for orphans that are ready to be processed or request missing chunks, once a block
+    /// is successfully accepted. Does nothing once the shutdown is requested.
+    /// `prev_hash`: hash of the block that is just accepted
+    /// `block_accepted`: callback to be called when an orphan is accepted
+    /// `block_misses_chunks`: callback to be called when an orphan is added to the pool of blocks
+    ///                        that have missing chunks
+    /// `orphan_misses_chunks`: callback to be called when it is ready to request missing chunks for
+    ///                         an orphan
+    /// `on_challenge`: callback to be called when an orphan should be challenged
+    pub fn check_orphans(
+        &mut self,
+        me: &Option<AccountId>,
+        prev_hash: CryptoHash,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+    ) -> Option<Tip> {
+        let _in_flight = self.shutdown.enter().ok()?;
+        let mut queue = vec![prev_hash];
+        let mut queue_idx = 0;
+
+        let mut maybe_new_head = None;
+
+        // Check if there are orphans we can process.
+        debug!(target: "chain", "Check orphans: from {}, # orphans {}", prev_hash, self.orphans.len());
+        while queue_idx < queue.len() {
+            let prev_hash = queue[queue_idx];
+            // check within the descendents of `prev_hash` to see if there are orphans there that
+            // are ready to request missing chunks for
+            let orphans_to_check =
+                self.orphans.get_orphans_within_depth(prev_hash, NUM_ORPHAN_ANCESTORS_CHECK);
+            for orphan_hash in orphans_to_check {
+                let orphan = self.orphans.get(&orphan_hash).unwrap().block.clone();
+                if let Some(orphan_missing_chunks) =
+                    self.should_request_chunks_for_orphan(me, &orphan)
+                {
+                    debug!(target:"chain", "Request missing chunks for orphan {:?}", orphan_hash);
+                    orphan_misses_chunks(orphan_missing_chunks);
+                    let extends_head = self.orphan_extends_head(&orphan);
+                    self.orphans
+                        .mark_missing_chunks_requested_for_orphan(orphan_hash, extends_head);
+                }
+            }
+            if let Some(orphans) = self.orphans.remove_by_prev_hash(prev_hash) {
+                debug!(target: "chain", "Check orphans: found {} orphans", orphans.len());
+                for orphan in orphans.into_iter() {
+                    let block_hash = orphan.hash();
+                    self.blocks_delay_tracker.mark_block_unorphaned(&block_hash, Clock::instant());
+                    let res = self.process_block_single(
+                        me,
+                        orphan.block,
+                        orphan.provenance,
+                        block_accepted,
+                        block_misses_chunks,
+                        orphan_misses_chunks,
+                        on_challenge,
+                    );
+                    match res {
+                        Ok(maybe_tip) => {
+                            maybe_new_head = maybe_tip;
+                            queue.push(block_hash);
+                        }
+                        Err(_) => {
+                            debug!(target: "chain", "Orphan declined");
+                        }
+                    }
+                }
+            }
+            queue_idx += 1;
+        }
+
+        if queue.len() > 1 {
+            debug!(
+                target: "chain",
+                "Check orphans: {} blocks accepted, remaining # orphans {}",
+                queue.len() - 1,
+                self.orphans.len(),
+            );
+        }
+
+        maybe_new_head
+    }
+
+    pub fn get_outgoing_receipts_for_shard(
+        &mut self,
+        prev_block_hash: CryptoHash,
+        shard_id: ShardId,
+        last_height_included: BlockHeight,
+    ) -> Result<Vec<Receipt>, Error> {
+        self.store.get_outgoing_receipts_for_shard(
+            &*self.runtime_adapter,
+            prev_block_hash,
+            shard_id,
+            last_height_included,
+        )
+    }
+
+    /// Skips the validation of chunk header signatures, chunk extras and transactions order when
+    /// processing blocks, for operators replaying a chain they trust. Transactions are still
+    /// applied and the resulting state roots checked. Refused on mainnet and testnet.
+    pub fn enable_trusted_chain_replay(&mut self, chain_id: &str) -> Result<(), Error> {
+        if is_public_chain(chain_id) {
+            return Err(ErrorKind::TrustedChainReplayNotAllowed(chain_id.to_string()).into());
+        }
+        warn!(target: "chain", "Trusted chain replay enabled, chunks are partially validated");
+        self.trusted_chain_replay = true;
+        Ok(())
+    }
+
+    /// Makes blocks starting at the activation height use the given gas price parameters instead
+    /// of the genesis ones. Refused on mainnet and testnet, like trusted chain replay.
+    pub fn set_economics_overrides(
+        &mut self,
+        chain_id: &str,
+        overrides: EconomicsOverrides,
+    ) -> Result<(), Error> {
+        if is_public_chain(chain_id) {
+            return Err(ErrorKind::EconomicsOverridesNotAllowed(chain_id.to_string()).into());
+        }
+        warn!(target: "chain", "Economics overrides enabled: {:?}", overrides);
+        self.block_economics_config.set_overrides(Some(overrides));
+        Ok(())
+    }
+
+    /// Shares the cache of state sync headers with other chains serving state requests, so that
+    /// they don't generate the same header concurrently.
+    pub fn set_state_header_cache(&mut self, state_header_cache: Arc<StateHeaderCache>) {
+        self.state_header_cache = state_header_cache;
+    }
+
+    /// Shares the sync hashes state is being served for with other chains serving state requests,
+    /// so that the limit on them applies to all of the chains together.
+    pub fn set_sync_hash_registry(&mut self, sync_hash_registry: Arc<SyncHashRegistry>) {
+        self.sync_hash_registry = sync_hash_registry;
+    }
+
+    /// Registers a handler notified of every block and chunk accepted by this chain from now on.
+    pub fn register_event_handler(
+        &mut self,
+        handler: Box<dyn ChainEventHandler>,
+        subscription: ChainEventSubscription,
+    ) {
+        self.event_handlers.register(handler, subscription);
+    }
+
+    /// Shares the cache of headers, blocks and chunk extras with other chains reading the same
+    /// database, so that hot data is read and kept in memory once.
+    pub fn set_shared_chain_cache(&mut self, shared_cache: Arc<SharedChainCache>) {
+        self.store.set_shared_cache(shared_cache);
+    }
+
+    pub fn get_state_response_header(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<ShardStateSyncResponseHeader, Error> {
+        let start = Clock::instant();
+        let result = self.get_state_response_header_impl(shard_id, sync_hash);
+        let served = match &result {
+            Ok((header, cache_hit)) => Some((*cache_hit, header.try_to_vec()?.len())),
+            Err(_) => None,
+        };
+        self.record_state_sync_serving(
+            StateSyncServedItem::Header,
+            shard_id,
+            sync_hash,
+            served,
+            start,
+        );
+        #[cfg(feature = "test_features")]
+        let result = result.map(|(mut header, cache_hit)| {
+            if let Some(corruptor) = &self.state_sync_corruptor {
+                corruptor.corrupt_header(&mut header);
+            }
+            (header, cache_hit)
+        });
+        result.map(|(header, _)| header)
+    }
+
+    /// Returns the state sync header and whether it was found in memory or storage instead of
+    /// being generated.
+    fn get_state_response_header_impl(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<(ShardStateSyncResponseHeader, bool), Error> {
+        let state_header_cache = self.state_header_cache.clone();
+        let guard = match state_header_cache.get_or_start(shard_id, sync_hash) {
+            HeaderLookup::Found(header) => return Ok((header, true)),
+            HeaderLookup::InFlight => {
+                return Err(ErrorKind::StateSyncHeaderInFlight { shard_id, sync_hash }.into())
+            }
+            HeaderLookup::Generate(guard) => guard,
+        };
+        let (header, cache_hit) = self.get_state_response_header_uncached(shard_id, sync_hash)?;
+        guard.finish(&header);
+        Ok((header, cache_hit))
+    }
+
+    fn get_state_response_header_uncached(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<(ShardStateSyncResponseHeader, bool), Error> {
+        // Check cache
+        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
+        if let Ok(Some(header)) = self.store.store().get_ser(ColStateHeaders, &key) {
+            return Ok((header, true));
+        }
+        metrics::STATE_SYNC_HEADER_GENERATIONS.inc();
+
+        // Consistency rules:
+        // 1. Everything prefixed with `sync_` indicates new epoch, for which we are syncing.
+        // 1a. `sync_prev` means the last of the prev epoch.
+        // 2. Empty prefix means the height where chunk was applied last time in the prev epoch.
+        //    Let's call it `current`.
+        // 2a. `prev_` means we're working with height before current.
+        // 3. In inner loops we use all prefixes with no relation to the context described above.
+        let sync_block = self
+            .get_block(&sync_hash)
+            .log_storage_error("block has already been checked for existence")?;
+        let sync_block_header = sync_block.header().clone();
+        let sync_block_epoch_id = sync_block.header().epoch_id().clone();
+        if shard_id as usize >= sync_block.chunks().len() {
+            return Err(ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into());
+        }
+
+        // The chunk was applied at height `chunk_header.height_included`.
+        // Getting the `current` state.
+        let sync_prev_block = self.get_block(sync_block_header.prev_hash())?;
+        if &sync_block_epoch_id == sync_prev_block.header().epoch_id() {
+            return Err(ErrorKind::InvalidStateRequest(
+                "sync_hash is not the first hash of the epoch".into(),
+            )
+            .into());
+        }
+        if shard_id as usize >= sync_prev_block.chunks().len() {
+            return Err(ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into());
+        }
+        // Chunk header here is the same chunk header as at the `current` height.
+        let sync_prev_hash = *sync_prev_block.hash();
+        let chunk_header = sync_prev_block.chunk_for_shard(shard_id)?;
+        let (chunk_headers_root, chunk_proofs) = merklize(
+            &sync_prev_block
+                .chunks()
+                .iter()
+                .map(|shard_chunk| {
+                    ChunkHashHeight(shard_chunk.chunk_hash(), shard_chunk.height_included())
+                })
+                .collect::<Vec<ChunkHashHeight>>(),
+        );
+        check_state_sync_local_data(
+            &chunk_headers_root == sync_prev_block.header().chunk_headers_root(),
+            &sync_prev_hash,
+            shard_id,
+            "chunk headers root mismatch",
+        )?;
+
+        let chunk = self.get_chunk_clone_from_header(&chunk_header)?;
+        let chunk_proof = chunk_proofs[shard_id as usize].clone();
+        let block_header =
+            self.get_header_on_chain_by_height(&sync_hash, chunk_header.height_included())?.clone();
+
+        // Collecting the `prev` state.
+        let (prev_chunk_header, prev_chunk_proof, prev_chunk_height_included) = match self
+            .get_block(block_header.prev_hash())
+        {
+            Ok(prev_block) => {
+                if shard_id as usize >= prev_block.chunks().len() {
+                    return Err(
+                        ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into()
+                    );
+                }
+                let prev_chunk_header = prev_block.chunk_for_shard(shard_id)?;
+                let (prev_chunk_headers_root, prev_chunk_proofs) = merklize(
+                    &prev_block
+                        .chunks()
+                        .iter()
+                        .map(|shard_chunk| {
+                            ChunkHashHeight(shard_chunk.chunk_hash(), shard_chunk.height_included())
+                        })
+                        .collect::<Vec<ChunkHashHeight>>(),
+                );
+                check_state_sync_local_data(
+                    &prev_chunk_headers_root == prev_block.header().chunk_headers_root(),
+                    prev_block.hash(),
+                    shard_id,
+                    "chunk headers root mismatch",
+                )?;
+
+                let prev_chunk_proof = prev_chunk_proofs[shard_id as usize].clone();
+                let prev_chunk_height_included = prev_chunk_header.height_included();
+
+                (Some(prev_chunk_header), Some(prev_chunk_proof), prev_chunk_height_included)
+            }
+            Err(e) => match e.kind() {
+                ErrorKind::DBNotFoundErr(_) => {
+                    if block_header.prev_hash() == &CryptoHash::default() {
+                        (None, None, 0)
+                    } else {
+                        return Err(e);
+                    }
+                }
+                _ => return Err(e),
+            },
+        };
+
+        // Getting all existing incoming_receipts from prev_chunk height to the new epoch.
+        let incoming_receipts_proofs = ChainStoreUpdate::new(&mut self.store)
+            .get_incoming_receipts_for_shard(shard_id, sync_hash, prev_chunk_height_included)?;
+
+        // Collecting proofs for incoming receipts.
+        let mut root_proofs = vec![];
+        for receipt_response in incoming_receipts_proofs.iter() {
+            let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
+            let block_header = self.get_block_header(block_hash)?.clone();
+            let block = self.get_block(block_hash)?;
+            let (block_receipts_root, block_receipts_proofs) = merklize(
+                &block
+                    .chunks()
+                    .iter()
+                    .map(|chunk| chunk.outgoing_receipts_root())
+                    .collect::<Vec<CryptoHash>>(),
+            );
+
+            let mut root_proofs_cur = vec![];
+            check_state_sync_local_data(
+                receipt_proofs.len() == block_header.chunks_included() as usize,
+                block_hash,
+                shard_id,
+                "number of incoming receipt proofs doesn't match the number of new chunks",
+            )?;
+            // Make sure we send something reasonable.
+            check_state_sync_local_data(
+                block_header.chunk_receipts_root() == &block_receipts_root,
+                block_hash,
+                shard_id,
+                "chunk receipts root mismatch",
+            )?;
+            for receipt_proof in receipt_proofs {
+                let ReceiptProof(receipts, shard_proof) = receipt_proof;
+                let ShardProof { from_shard_id, to_shard_id: _, proof } = shard_proof;
+                let receipts_hash = hash(&ReceiptList(shard_id, receipts).try_to_vec()?);
+                let from_shard_id = *from_shard_id as usize;
+                check_state_sync_local_data(
+                    from_shard_id < block_receipts_proofs.len(),
+                    block_hash,
+                    shard_id,
+                    "incoming receipts from an unknown shard",
+                )?;
+
+                let root_proof = block.chunks()[from_shard_id].outgoing_receipts_root();
+                root_proofs_cur
+                    .push(RootProof(root_proof, block_receipts_proofs[from_shard_id].clone()));
+
+                check_state_sync_local_data(
+                    verify_path(root_proof, proof, &receipts_hash),
+                    block_hash,
+                    shard_id,
+                    "incoming receipts don't match the outgoing receipts root",
+                )?;
+                check_state_sync_local_data(
+                    verify_path(
+                        block_receipts_root,
+                        &block_receipts_proofs[from_shard_id],
+                        &root_proof,
+                    ),
+                    block_hash,
+                    shard_id,
+                    "outgoing receipts root doesn't match the chunk receipts root",
+                )?;
+            }
+            root_proofs.push(root_proofs_cur);
+        }
+
+        let state_root_node = self.runtime_adapter.get_state_root_node(
+            shard_id,
+            &sync_prev_hash,
+            &chunk_header.prev_state_root(),
+        )?;
+
+        let shard_state_header = match chunk {
+            ShardChunk::V1(chunk) => {
+                let prev_chunk_header =
+                    prev_chunk_header.and_then(|prev_header| match prev_header {
+                        ShardChunkHeader::V1(header) => Some(header),
+                        ShardChunkHeader::V2(_) => None,
+                        ShardChunkHeader::V3(_) => None,
+                    });
+                ShardStateSyncResponseHeader::V1(ShardStateSyncResponseHeaderV1 {
+                    chunk,
+                    chunk_proof,
+                    prev_chunk_header,
+                    prev_chunk_proof,
+                    incoming_receipts_proofs,
+                    root_proofs,
+                    state_root_node,
+                })
+            }
+
+            chunk @ ShardChunk::V2(_) => {
+                ShardStateSyncResponseHeader::V2(ShardStateSyncResponseHeaderV2 {
+                    chunk,
+                    chunk_proof,
+                    prev_chunk_header,
+                    prev_chunk_proof,
+                    incoming_receipts_proofs,
+                    root_proofs,
+                    state_root_node,
+                })
+            }
+        };
+
+        // Saving the header data. A read-only store relies on the state header cache instead.
+        if !self.store.store().is_read_only() {
+            let mut store_update = self.store.store().store_update();
+            store_update.set_ser(ColStateHeaders, &key, &shard_state_header)?;
+            store_update.commit()?;
+        }
+
+        Ok((shard_state_header, false))
+    }
+
+    pub fn get_state_response_part(
+        &mut self,
+        shard_id: ShardId,
+        part_id: u64,
+        sync_hash: CryptoHash,
+    ) -> Result<StatePartPayload, Error> {
+        let start = Clock::instant();
+        let result = self.get_state_response_part_impl(shard_id, part_id, sync_hash);
+        let served = result.as_ref().ok().map(|(part, cache_hit)| (*cache_hit, part.len()));
+        self.record_state_sync_serving(
+            StateSyncServedItem::Part,
+            shard_id,
+            sync_hash,
+            served,
+            start,
+        );
+        #[cfg(feature = "test_features")]
+        let result = result.and_then(|(part, cache_hit)| match &self.state_sync_corruptor {
+            Some(corruptor) => {
+                let mut part = part.into_vec()?;
+                corruptor.corrupt_part(&mut part);
+                Ok((StatePartPayload::Owned(part), cache_hit))
+            }
+            None => Ok((part, cache_hit)),
+        });
+        result.map(|(part, _)| part)
+    }
+
+    /// Returns the state part and whether it was found in storage instead of being generated.
+    fn get_state_response_part_impl(
+        &mut self,
+        shard_id: ShardId,
+        part_id: u64,
+        sync_hash: CryptoHash,
+    ) -> Result<(StatePartPayload, bool), Error> {
+        // Check cache
+        let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+        if let Ok(Some(state_part)) = self.store.store().get(ColStateParts, &key) {
+            return Ok((StatePartPayload::Owned(state_part), true));
+        }
+
+        let sync_block = self
+            .get_block(&sync_hash)
+            .log_storage_error("block has already been checked for existence")?;
+        let sync_block_header = sync_block.header().clone();
+        let sync_block_epoch_id = sync_block.header().epoch_id().clone();
+        if shard_id as usize >= sync_block.chunks().len() {
+            return Err(ErrorKind::InvalidStateRequest("shard_id out of bounds".into()).into());
+        }
+        let sync_prev_block = self.get_block(sync_block_header.prev_hash())?;
+        if &sync_block_epoch_id == sync_prev_block.header().epoch_id() {
+            return Err(ErrorKind::InvalidStateRequest(
+                "sync_hash is not the first hash of the epoch".into(),
+            )
+            .into());
+        }
+        if shard_id as usize >= sync_prev_block.chunks().len() {
+            return Err(ErrorKind::InvalidStateRequest("shard_id out of bounds".into()).into());
+        }
+        let state_root = sync_prev_block.chunk_for_shard(shard_id)?.prev_state_root();
+        let sync_prev_hash = *sync_prev_block.hash();
+        let state_root_node = self
+            .runtime_adapter
+            .get_state_root_node(shard_id, &sync_prev_hash, &state_root)
+            .log_storage_error("get_state_root_node fail")?;
+        let num_parts = get_num_state_parts(state_root_node.memory_usage);
+
+        if part_id >= num_parts {
+            return Err(ErrorKind::InvalidStateRequest("part_id out of bound".to_string()).into());
+        }
+        let state_part_stream = self
+            .runtime_adapter
+            .obtain_state_part_stream(
+                shard_id,
+                &sync_prev_hash,
+                &state_root,
+                PartId::new(part_id, num_parts),
+            )
+            .log_storage_error("obtain_state_part fail")?;
+
+        // Before saving State Part data, we need to make sure we can calculate and save State Header
+        self.get_state_response_header_impl(shard_id, sync_hash)?;
+
+        // The trie nodes are read one at a time as the part is written out, so that they are never
+        // held in memory next to it. The part is saved from the buffer it's written into, unless
+        // the store is read-only and the part has to be generated on every request.
+        let cache_sink = if self.store.store().is_read_only() {
+            None
+        } else {
+            // The ids of the stored parts are loaded from storage again on the next lookup.
+            self.stored_state_parts.remove(&(shard_id, sync_hash));
+            let store = self.store.store().clone();
+            let cache_sink: StatePartCacheSink = Box::new(
+                move |state_part: &[u8], state_part_hash: &CryptoHash| {
+                    debug!(target: "sync", "Saving state part {} of shard {} for {} of {} bytes with hash {}", part_id, shard_id, sync_hash, state_part.len(), state_part_hash);
+                    let mut store_update = store.store_update();
+                    store_update.set(ColStateParts, &key, state_part);
+                    store_update.commit()
+                },
+            );
+            Some(cache_sink)
+        };
+
+        Ok((StatePartPayload::Stream(state_part_stream, cache_sink), false))
+    }
+
+    /// Updates the metrics and the per sync hash tally after serving a state sync header or part.
+    /// `served` is whether the item was served without being generated and its size, or `None`
+    /// if the request failed. Failed requests aren't labeled by shard nor tallied, as their shard
+    /// and sync hash come unchecked from the network.
+    fn record_state_sync_serving(
+        &mut self,
+        item: StateSyncServedItem,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        served: Option<(bool, usize)>,
+        start: Instant,
+    ) {
+        let (cache_hit, size) = match served {
+            Some(served) => served,
+            None => {
+                metrics::STATE_SYNC_SERVE_FAILURES.with_label_values(&[item.label()]).inc();
+                return;
+            }
+        };
+        let shard_label = shard_id.to_string();
+        let labels = [shard_label.as_str(), item.label()];
+        metrics::STATE_SYNC_SERVED_REQUESTS.with_label_values(&labels).inc();
+        if cache_hit {
+            metrics::STATE_SYNC_SERVED_CACHE_HITS.with_label_values(&labels).inc();
+        }
+        metrics::STATE_SYNC_SERVED_BYTES.with_label_values(&labels).inc_by(size as u64);
+        metrics::STATE_SYNC_SERVE_LATENCY
+            .with_label_values(&labels)
+            .observe(Clock::instant().saturating_duration_since(start).as_secs_f64());
+
+        let tally = &mut self.state_sync_serving_stats;
+        let position = match tally.iter().position(|(hash, _)| hash == &sync_hash) {
+            Some(position) => position,
+            None => {
+                tally.push_back((sync_hash, StateSyncServingStats::default()));
+                if tally.len() > NUM_SYNC_HASHES_TO_KEEP_SERVING_STATS {
+                    tally.pop_front();
+                }
+                tally.len() - 1
+            }
+        };
+        let stats = &mut tally[position].1;
+        match item {
+            StateSyncServedItem::Header => stats.headers_served += 1,
+            StateSyncServedItem::Part => stats.parts_served += 1,
+        }
+        if cache_hit {
+            stats.cache_hits += 1;
+        }
+        stats.bytes_served += size as u64;
+    }
+
+    /// Returns the state sync data served by this chain for each of the most recent sync hashes.
+    pub fn state_sync_serving_stats(&self) -> HashMap<CryptoHash, StateSyncServingStats> {
+        self.state_sync_serving_stats.iter().cloned().collect()
+    }
+
+    /// Validates the state sync header received from a peer and saves it.
+    pub fn set_state_header(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        shard_state_header: ShardStateSyncResponseHeader,
+    ) -> Result<(), Error> {
+        let result = self.set_state_header_impl(shard_id, sync_hash, shard_state_header);
+        if let Err(err) = &result {
+            // Some validation failures of the state header are reported as `ErrorKind::Other`,
+            // so only the errors caused by the local node are filtered out.
+            if !matches!(
+                err.kind(),
+                ErrorKind::DBNotFoundErr(_)
+                    | ErrorKind::IOErr(_)
+                    | ErrorKind::StorageError(_)
+                    | ErrorKind::ValidatorError(_)
+                    | ErrorKind::EpochOutOfBounds(_)
+            ) {
+                self.report_misbehavior(ChainMisbehavior::InvalidStateHeader {
+                    shard_id,
+                    sync_hash,
+                    error: err.kind(),
+                });
+            }
+        }
+        result
+    }
+
+    fn set_state_header_impl(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        shard_state_header: ShardStateSyncResponseHeader,
+    ) -> Result<(), Error> {
+        let sync_block_header = self.get_block_header(&sync_hash)?.clone();
+
+        let chunk = shard_state_header.cloned_chunk();
+        let prev_chunk_header = shard_state_header.cloned_prev_chunk_header();
+
+        // 1-2. Checking chunk validity
+        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter)? {
+            byzantine_assert!(false);
+            return Err(ErrorKind::Other(
+                "set_shard_state failed: chunk header proofs are invalid".into(),
+            )
+            .into());
+        }
 
-                let status = self.determine_status(head.clone(), prev_head);
+        // Consider chunk itself is valid.
 
-                // Notify other parts of the system of the update.
-                block_accepted(AcceptedBlock { hash: *block.hash(), status, provenance });
+        // 3. Checking that chunks `chunk` and `prev_chunk` are included in appropriate blocks
+        // 3a. Checking that chunk `chunk` is included into block at last height before sync_hash
+        // 3aa. Also checking chunk.height_included
+        let sync_prev_block_header = self.get_block_header(sync_block_header.prev_hash())?.clone();
+        if !verify_path(
+            *sync_prev_block_header.chunk_headers_root(),
+            shard_state_header.chunk_proof(),
+            &ChunkHashHeight(chunk.chunk_hash(), chunk.height_included()),
+        ) {
+            byzantine_assert!(false);
+            return Err(ErrorKind::Other(
+                "set_shard_state failed: chunk isn't included into block".into(),
+            )
+            .into());
+        }
 
-                Ok(head)
+        let block_header =
+            self.get_header_on_chain_by_height(&sync_hash, chunk.height_included())?.clone();
+        // 3b. Checking that chunk `prev_chunk` is included into block at height before chunk.height_included
+        // 3ba. Also checking prev_chunk.height_included - it's important for getting correct incoming receipts
+        match (&prev_chunk_header, shard_state_header.prev_chunk_proof()) {
+            (Some(prev_chunk_header), Some(prev_chunk_proof)) => {
+                let prev_block_header =
+                    self.get_block_header(block_header.prev_hash())?.clone();
+                if !verify_path(
+                    *prev_block_header.chunk_headers_root(),
+                    prev_chunk_proof,
+                    &ChunkHashHeight(prev_chunk_header.chunk_hash(), prev_chunk_header.height_included()),
+                ) {
+                    byzantine_assert!(false);
+                    return Err(ErrorKind::Other(
+                        "set_shard_state failed: prev_chunk isn't included into block".into(),
+                    )
+                    .into());
+                }
             }
-            Err(e) => {
-                match e.kind() {
-                    ErrorKind::Orphan => {
-                        let tail_height = self.store.tail()?;
-                        // we only add blocks that couldn't have been gc'ed to the orphan pool.
-                        if block_height >= tail_height {
-                            let block_hash = *block.hash();
-                            let requested_missing_chunks = if let Some(orphan_missing_chunks) =
-                                self.should_request_chunks_for_orphan(me, &block)
-                            {
-                                debug!(target:"chain", "Request missing chunks for orphan {:?} {:?}", block_hash, orphan_missing_chunks.missing_chunks);
-                                // This callback handles requesting missing chunks. It adds the missing chunks
-                                // to a list and all missing chunks in the list will be requested
-                                // at the end of Client::process_block
-                                orphan_misses_chunks(orphan_missing_chunks);
-                                true
-                            } else {
-                                false
-                            };
+            (None, None) => {
+                if chunk.height_included() != 0 {
+                    return Err(ErrorKind::Other(
+                    "set_shard_state failed: received empty state response for a chunk that is not at height 0".into()
+                ).into());
+                }
+            }
+            _ =>
+                return Err(ErrorKind::Other("set_shard_state failed: `prev_chunk_header` and `prev_chunk_proof` must either both be present or both absent".into()).into())
+        };
 
-                            let time = Clock::instant();
-                            self.blocks_delay_tracker.mark_block_orphaned(block.hash(), time);
-                            let orphan = Orphan { block, provenance, added: time };
-                            self.orphans.add(orphan, requested_missing_chunks);
+        // 4. Proving incoming receipts validity
+        // 4a. Checking len of proofs
+        if shard_state_header.root_proofs().len()
+            != shard_state_header.incoming_receipts_proofs().len()
+        {
+            byzantine_assert!(false);
+            return Err(ErrorKind::InvalidStateHeaderReceiptProofs(shard_id).into());
+        }
+        let mut hash_to_compare = sync_hash;
+        for (i, receipt_response) in
+            shard_state_header.incoming_receipts_proofs().iter().enumerate()
+        {
+            let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
 
-                            debug!(
-                                target: "chain",
-                                "Process block: orphan: {:?}, # orphans {}{}",
-                                block_hash,
-                                self.orphans.len(),
-                                if self.orphans.len_evicted() > 0 {
-                                    format!(", # evicted {}", self.orphans.len_evicted())
-                                } else {
-                                    String::new()
-                                },
-                            );
-                        }
-                    }
-                    ErrorKind::ChunksMissing(missing_chunks) => {
-                        let block_hash = *block.hash();
-                        let missing_chunk_hashes: Vec<_> =
-                            missing_chunks.iter().map(|header| header.chunk_hash()).collect();
-                        block_misses_chunks(BlockMissingChunks {
-                            prev_hash: *block.header().prev_hash(),
-                            missing_chunks,
-                            block_hash,
-                        });
-                        let time = Clock::instant();
-                        self.blocks_delay_tracker.mark_block_has_missing_chunks(block.hash(), time);
-                        let orphan = Orphan { block, provenance, added: time };
-                        self.blocks_with_missing_chunks
-                            .add_block_with_missing_chunks(orphan, missing_chunk_hashes.clone());
-                        debug!(
-                            target: "chain",
-                            "Process block: missing chunks. Block hash: {:?}. Missing chunks: {:?}",
-                            block_hash, missing_chunk_hashes,
-                        );
-                    }
-                    ErrorKind::EpochOutOfBounds(ref epoch_id) => {
-                        // Possibly block arrived before we finished processing all of the blocks for epoch before last.
-                        // Or someone is attacking with invalid chain.
-                        debug!(target: "chain", "Received block {}/{} ignored, as epoch {:?} is unknown", block_height, block.hash(), epoch_id);
-                    }
-                    ErrorKind::BlockKnown(ref block_known_error) => {
-                        debug!(
-                            target: "chain",
-                            "Block {} at {} is known at this time: {:?}",
-                            block.hash(),
-                            block_height,
-                            block_known_error);
+            // 4b. Checking that there is a valid sequence of continuous blocks
+            if *block_hash != hash_to_compare {
+                byzantine_assert!(false);
+                return Err(ErrorKind::InvalidStateHeaderIncomingReceipts(shard_id).into());
+            }
+            let header = self.get_block_header(&hash_to_compare)?;
+            hash_to_compare = *header.prev_hash();
+
+            let block_header = self.get_block_header(block_hash)?;
+            // 4c. Checking len of receipt_proofs for current block
+            if receipt_proofs.len() != shard_state_header.root_proofs()[i].len()
+                || receipt_proofs.len() != block_header.chunks_included() as usize
+            {
+                byzantine_assert!(false);
+                return Err(ErrorKind::InvalidStateHeaderReceiptProofs(shard_id).into());
+            }
+            // We know there were exactly `block_header.chunks_included` chunks included
+            // on the height of block `block_hash`.
+            // There were no other proofs except for included chunks.
+            // According to Pigeonhole principle, it's enough to ensure all receipt_proofs are distinct
+            // to prove that all receipts were received and no receipts were hidden.
+            let mut visited_shard_ids = HashSet::<ShardId>::new();
+            for (j, receipt_proof) in receipt_proofs.iter().enumerate() {
+                let ReceiptProof(receipts, shard_proof) = receipt_proof;
+                let ShardProof { from_shard_id, to_shard_id: _, proof } = shard_proof;
+                // 4d. Checking uniqueness for set of `from_shard_id`
+                match visited_shard_ids.get(from_shard_id) {
+                    Some(_) => {
+                        byzantine_assert!(false);
+                        return Err(ErrorKind::InvalidStateHeaderReceiptProofs(shard_id).into());
                     }
-                    _ => {}
+                    _ => visited_shard_ids.insert(*from_shard_id),
+                };
+                let RootProof(root, block_proof) = &shard_state_header.root_proofs()[i][j];
+                let receipts_hash = hash(&ReceiptList(shard_id, receipts).try_to_vec()?);
+                // 4e. Proving the set of receipts is the subset of outgoing_receipts of shard `shard_id`
+                if !verify_path(*root, proof, &receipts_hash) {
+                    byzantine_assert!(false);
+                    return Err(ErrorKind::InvalidStateHeaderReceiptProofs(shard_id).into());
                 }
-                if let Err(e) = self.save_block_height_processed(block_height) {
-                    warn!(target: "chain", "Failed to save processed height {}: {}", block_height, e);
+                // 4f. Proving the outgoing_receipts_root matches that in the block
+                if !verify_path(*block_header.chunk_receipts_root(), block_proof, root) {
+                    byzantine_assert!(false);
+                    return Err(ErrorKind::InvalidStateHeaderReceiptProofs(shard_id).into());
                 }
-                Err(e)
             }
         }
+        // 4g. Checking that there are no more heights to get incoming_receipts
+        let header = self.get_block_header(&hash_to_compare)?;
+        if header.height() != prev_chunk_header.map_or(0, |h| h.height_included()) {
+            byzantine_assert!(false);
+            return Err(ErrorKind::InvalidStateHeaderIncomingReceipts(shard_id).into());
+        }
+
+        // 5. Checking that state_root_node is valid
+        let chunk_inner = chunk.take_header().take_inner();
+        if !self.runtime_adapter.validate_state_root_node(
+            shard_state_header.state_root_node(),
+            chunk_inner.prev_state_root(),
+        ) {
+            byzantine_assert!(false);
+            return Err(ErrorKind::InvalidStateRootNode(shard_id).into());
+        }
+
+        // Saving the header data.
+        let mut store_update = self.store.store().store_update();
+        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
+        store_update.set_ser(ColStateHeaders, &key, &shard_state_header)?;
+        store_update.commit()?;
+
+        Ok(())
     }
 
-    /// Check if we can request chunks for this orphan. Conditions are
-    /// 1) Orphans that with outstanding missing chunks request has not exceed `MAX_ORPHAN_MISSING_CHUNKS`
-    /// 2) we haven't already requested missing chunks for the orphan
-    /// 3) All the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block are either accepted,
-    ///    or orphans or in `blocks_with_missing_chunks`
-    /// 4) Among the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block at least one is
-    ///    accepted(is in store), call it `ancestor`
-    /// 5) The next block of `ancestor` has the same epoch_id as the orphan block
-    ///    (This is because when requesting chunks, we will use `ancestor` hash instead of the
-    ///     previous block hash of the orphan to decide epoch id)
-    /// 6) The orphan has missing chunks
-    pub fn should_request_chunks_for_orphan(
+    pub fn get_state_header(
         &mut self,
-        me: &Option<AccountId>,
-        orphan: &Block,
-    ) -> Option<OrphanMissingChunks> {
-        // 1) Orphans that with outstanding missing chunks request has not exceed `MAX_ORPHAN_MISSING_CHUNKS`
-        // 2) we haven't already requested missing chunks for the orphan
-        if !self.orphans.can_request_missing_chunks_for_orphan(orphan.hash()) {
-            return None;
-        }
-        let mut block_hash = *orphan.header().prev_hash();
-        for _ in 0..NUM_ORPHAN_ANCESTORS_CHECK {
-            // 3) All the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block are either accepted,
-            //    or orphans or in `blocks_with_missing_chunks`
-            if let Some(block) = self.blocks_with_missing_chunks.get(&block_hash) {
-                block_hash = *block.prev_hash();
-                continue;
-            }
-            if let Some(orphan) = self.orphans.get(&block_hash) {
-                block_hash = *orphan.prev_hash();
-                continue;
-            }
-            // 4) Among the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the block at least one is
-            //    accepted(is in store), call it `ancestor`
-            if self.get_block(&block_hash).is_ok() {
-                if let Ok(epoch_id) = self.runtime_adapter.get_epoch_id_from_prev_block(&block_hash)
-                {
-                    // 5) The next block of `ancestor` has the same epoch_id as the orphan block
-                    if &epoch_id == orphan.header().epoch_id() {
-                        let mut chain_update = self.chain_update();
-                        // 6) The orphan has missing chunks
-                        if let Err(e) = chain_update.ping_missing_chunks(me, block_hash, orphan) {
-                            return match e.kind() {
-                                ErrorKind::ChunksMissing(missing_chunks) => {
-                                    Some(OrphanMissingChunks {
-                                        missing_chunks,
-                                        epoch_id,
-                                        ancestor_hash: block_hash,
-                                        requestor_block_hash: *orphan.header().hash(),
-                                    })
-                                }
-                                _ => None,
-                            };
-                        }
-                    }
-                }
-                return None;
-            }
-            return None;
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<ShardStateSyncResponseHeader, Error> {
+        self.store.get_state_header(shard_id, sync_hash)
+    }
+
+    /// Saves a downloaded state part. The `provenance` of the part, if known, is kept in memory
+    /// until the parts are cleared, see `Chain::state_part_provenance_stats`.
+    pub fn set_state_part(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: PartId,
+        data: &Vec<u8>,
+        provenance: Option<PartProvenance>,
+    ) -> Result<(), Error> {
+        let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
+        let chunk = shard_state_header.take_chunk();
+        let state_root = *chunk.take_header().take_inner().prev_state_root();
+        if !self.runtime_adapter.validate_state_part(&state_root, part_id, data) {
+            byzantine_assert!(false);
+            return Err(ErrorKind::InvalidStatePart { shard_id, part_id: part_id.idx }.into());
         }
-        None
-    }
 
-    /// only used for test
-    pub fn check_orphan_partial_chunks_requested(&self, block_hash: &CryptoHash) -> bool {
-        self.orphans.orphans_requested_missing_chunks.contains(block_hash)
+        // Saving the part data.
+        let mut store_update = self.store.store().store_update();
+        let key = StatePartKey(sync_hash, shard_id, part_id.idx).try_to_vec()?;
+        store_update.set(ColStateParts, &key, data);
+        store_update.commit()?;
+        if let Some(parts) = self.stored_state_parts.get_mut(&(shard_id, sync_hash)) {
+            parts.insert(part_id.idx);
+        }
+
+        if let Some(provenance) = provenance {
+            self.state_part_provenance
+                .entry((shard_id, sync_hash))
+                .or_default()
+                .insert(part_id.idx, provenance);
+        }
+        Ok(())
     }
 
-    pub fn prev_block_is_caught_up(
-        &self,
-        prev_prev_hash: &CryptoHash,
-        prev_hash: &CryptoHash,
-    ) -> Result<bool, Error> {
-        // This method is identical to `ChainUpdate::prev_block_is_caught_up`, see important
-        //    disclaimers in there on some dangers of using it
-        Ok(!self.store.get_blocks_to_catchup(prev_prev_hash)?.contains(prev_hash))
+    /// Drops the provenance of the state parts downloaded for the sync hashes no longer synced,
+    /// see `Chain::set_state_part`.
+    pub fn retain_state_part_provenance(&mut self, is_syncing: impl Fn(&CryptoHash) -> bool) {
+        self.state_part_provenance.retain(|(_, sync_hash), _| is_syncing(sync_hash));
     }
 
-    /// Return all shards that whose states need to be caught up
-    /// That has two cases:
-    /// 1) Shard layout will change in the next epoch. In this case, the method returns all shards
-    ///    in the current epoch that will be split into a future shard that `me` will track.
-    /// 2) Shard layout will be the same. In this case, the method returns all shards that `me` will
-    ///    track in the next epoch but not this epoch
-    fn get_shards_to_dl_state(
-        runtime_adapter: Arc<dyn RuntimeAdapter>,
-        me: &Option<AccountId>,
-        parent_hash: &CryptoHash,
-    ) -> Result<Vec<ShardId>, Error> {
-        let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
-        Ok((0..runtime_adapter.num_shards(&epoch_id)?)
-            .filter(|shard_id| {
-                Self::should_catch_up_shard(runtime_adapter.clone(), me, parent_hash, *shard_id)
+    /// Summarizes the provenance of the state parts downloaded for the state syncs in progress
+    /// per peer, the slowest peers first.
+    pub fn state_part_provenance_stats(&self) -> Vec<PeerStatePartStats> {
+        let mut latencies: HashMap<&PeerId, Vec<TimeDuration>> = HashMap::new();
+        for parts in self.state_part_provenance.values() {
+            for provenance in parts.values() {
+                latencies.entry(&provenance.peer_id).or_default().push(provenance.latency());
+            }
+        }
+        let mut stats = latencies
+            .into_iter()
+            .map(|(peer_id, latencies)| PeerStatePartStats {
+                peer_id: peer_id.clone(),
+                num_parts: latencies.len(),
+                average_latency: latencies.iter().sum::<TimeDuration>() / latencies.len() as u32,
+                max_latency: latencies.iter().max().copied().unwrap_or_default(),
             })
-            .collect())
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| {
+            b.average_latency.cmp(&a.average_latency).then_with(|| a.peer_id.cmp(&b.peer_id))
+        });
+        stats
     }
 
-    fn should_catch_up_shard(
-        runtime_adapter: Arc<dyn RuntimeAdapter>,
-        me: &Option<AccountId>,
-        parent_hash: &CryptoHash,
+    pub fn schedule_apply_state_parts(
+        &mut self,
         shard_id: ShardId,
-    ) -> bool {
-        let will_shard_layout_change =
-            runtime_adapter.will_shard_layout_change_next_epoch(parent_hash).unwrap_or(false);
-        // if shard layout will change the next epoch, we should catch up the shard regardless
-        // whether we already have the shard's state this epoch, because we need to generate
-        // new states for shards split from the current shard for the next epoch
-        runtime_adapter.will_care_about_shard(me.as_ref(), parent_hash, shard_id, true)
-            && (will_shard_layout_change
-                || !runtime_adapter.cares_about_shard(me.as_ref(), parent_hash, shard_id, true))
+        sync_hash: CryptoHash,
+        num_parts: u64,
+        state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
+    ) -> Result<(), Error> {
+        let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
+        let state_root = shard_state_header.chunk_prev_state_root();
+        let epoch_id = self.get_block_header(&sync_hash)?.epoch_id().clone();
+
+        state_parts_task_scheduler(ApplyStatePartsRequest {
+            runtime: Arc::clone(&self.runtime_adapter),
+            shard_id,
+            state_root,
+            num_parts,
+            epoch_id,
+            sync_hash,
+        });
+
+        Ok(())
     }
 
-    /// Check if any block with missing chunk is ready to be processed
-    pub fn check_blocks_with_missing_chunks(
+    pub fn set_state_finalize(
         &mut self,
-        me: &Option<AccountId>,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) {
-        let mut new_blocks_accepted = vec![];
-        let orphans = self.blocks_with_missing_chunks.ready_blocks();
-        for orphan in orphans {
-            let block_hash = *orphan.block.header().hash();
-            let time = Clock::instant();
-            let res = self.process_block_single(
-                me,
-                orphan.block,
-                orphan.provenance,
-                block_accepted,
-                block_misses_chunks,
-                orphan_misses_chunks,
-                on_challenge,
-            );
-            match res {
-                Ok(_) => {
-                    debug!(target: "chain", "Block with missing chunks is accepted; me: {:?}", me);
-                    self.blocks_delay_tracker
-                        .mark_block_completed_missing_chunks(&block_hash, time);
-                    new_blocks_accepted.push(block_hash);
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        apply_result: Result<(), near_chain_primitives::Error>,
+    ) -> Result<(), Error> {
+        apply_result?;
+        while self.set_state_finalize_step(shard_id, sync_hash)? {}
+        Ok(())
+    }
+
+    /// Performs the next step of `set_state_finalize`: first applies the chunk of the state
+    /// header, then builds the chain up to the `sync_hash` block one height at a time.
+    /// The progress is committed together with each step, so a finalization interrupted e.g. by a
+    /// restart resumes from the height it reached instead of reapplying chunks.
+    /// Returns whether there are more steps to perform.
+    pub fn set_state_finalize_step(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<bool, Error> {
+        match self.store.get_state_sync_finalize_progress(shard_id, &sync_hash)? {
+            None => {
+                let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
+                let height = shard_state_header.chunk_height_included();
+                let mut chain_update = self.chain_update();
+                chain_update.set_state_finalize(shard_id, sync_hash, shard_state_header)?;
+                chain_update
+                    .chain_store_update
+                    .save_state_sync_finalize_progress(shard_id, &sync_hash, height)?;
+                let chunk_applied_events = std::mem::take(&mut chain_update.chunk_applied_events);
+                chain_update.commit()?;
+                for event in chunk_applied_events {
+                    self.event_handlers.on_chunk_applied(event);
                 }
-                Err(_) => {
-                    debug!(target: "chain", "Block with missing chunks is declined; me: {:?}", me);
+                Ok(true)
+            }
+            Some(height) => {
+                // We restored the state on height `height`. Now we should build a chain up to
+                // height of `sync_hash` block.
+                let height = height + 1;
+                let mut chain_update = self.chain_update();
+                // Result of successful execution of set_state_finalize_on_height is bool,
+                // should we commit and continue or stop.
+                let has_more =
+                    chain_update.set_state_finalize_on_height(height, shard_id, sync_hash)?;
+                if has_more {
+                    chain_update
+                        .chain_store_update
+                        .save_state_sync_finalize_progress(shard_id, &sync_hash, height)?;
+                } else {
+                    chain_update
+                        .chain_store_update
+                        .remove_state_sync_finalize_progress(shard_id, &sync_hash);
+                }
+                let chunk_applied_events = std::mem::take(&mut chain_update.chunk_applied_events);
+                chain_update.commit()?;
+                for event in chunk_applied_events {
+                    self.event_handlers.on_chunk_applied(event);
                 }
+                Ok(has_more)
             }
         }
+    }
 
-        for accepted_block in new_blocks_accepted {
-            self.check_orphans(
-                me,
-                accepted_block,
-                block_accepted,
-                block_misses_chunks,
-                orphan_misses_chunks,
-                on_challenge,
-            );
-        }
+    pub fn build_state_for_split_shards_preprocessing(
+        &mut self,
+        sync_hash: &CryptoHash,
+        shard_id: ShardId,
+        state_split_scheduler: &dyn Fn(StateSplitRequest),
+    ) -> Result<(), Error> {
+        let (epoch_id, next_epoch_id) = {
+            let block_header = self.get_block_header(sync_hash)?;
+            (block_header.epoch_id().clone(), block_header.next_epoch_id().clone())
+        };
+        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        let next_epoch_shard_layout = self.runtime_adapter.get_shard_layout(&next_epoch_id)?;
+        let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+        let prev_hash = *self.get_block_header(sync_hash)?.prev_hash();
+        let state_root = *self.get_chunk_extra(&prev_hash, &shard_uid)?.state_root();
+        assert_ne!(shard_layout, next_epoch_shard_layout);
+
+        state_split_scheduler(StateSplitRequest {
+            runtime: Arc::clone(&self.runtime_adapter),
+            sync_hash: *sync_hash,
+            shard_id,
+            shard_uid,
+            state_root: state_root,
+            next_epoch_shard_layout,
+        });
+
+        Ok(())
     }
 
-    /// Check for orphans that are ready to be processed or request missing chunks, once a block
-    /// is successfully accepted.
-    /// `prev_hash`: hash of the block that is just accepted
-    /// `block_accepted`: callback to be called when an orphan is accepted
-    /// `block_misses_chunks`: callback to be called when an orphan is added to the pool of blocks
-    ///                        that have missing chunks
-    /// `orphan_misses_chunks`: callback to be called when it is ready to request missing chunks for
-    ///                         an orphan
-    /// `on_challenge`: callback to be called when an orphan should be challenged
-    pub fn check_orphans(
+    pub fn build_state_for_split_shards_postprocessing(
         &mut self,
-        me: &Option<AccountId>,
-        prev_hash: CryptoHash,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-    ) -> Option<Tip> {
-        let mut queue = vec![prev_hash];
-        let mut queue_idx = 0;
+        sync_hash: &CryptoHash,
+        state_roots: Result<HashMap<ShardUId, StateRoot>, Error>,
+    ) -> Result<(), Error> {
+        let prev_hash = *self.get_block_header(sync_hash)?.prev_hash();
+        let mut chain_update = self.chain_update();
+        for (shard_uid, state_root) in state_roots? {
+            // here we store the state roots in chunk_extra in the database for later use
+            let chunk_extra = ChunkExtra::new_with_only_state_root(&state_root);
+            chain_update.chain_store_update.save_chunk_extra(&prev_hash, &shard_uid, chunk_extra);
+            debug!(target:"chain", "Finish building split state for shard {:?} {:?} {:?} ", shard_uid, prev_hash, state_root);
+        }
+        chain_update.commit()
+    }
 
-        let mut maybe_new_head = None;
+    pub fn clear_downloaded_parts(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+    ) -> Result<(), Error> {
+        self.state_part_provenance.remove(&(shard_id, sync_hash));
+        self.stored_state_parts.remove(&(shard_id, sync_hash));
+        let mut chain_store_update = self.mut_store().store_update();
+        chain_store_update.gc_col_state_parts(sync_hash, shard_id, num_parts)?;
+        Ok(chain_store_update.commit()?)
+    }
 
-        // Check if there are orphans we can process.
-        debug!(target: "chain", "Check orphans: from {}, # orphans {}", prev_hash, self.orphans.len());
-        while queue_idx < queue.len() {
-            let prev_hash = queue[queue_idx];
-            // check within the descendents of `prev_hash` to see if there are orphans there that
-            // are ready to request missing chunks for
-            let orphans_to_check =
-                self.orphans.get_orphans_within_depth(prev_hash, NUM_ORPHAN_ANCESTORS_CHECK);
-            for orphan_hash in orphans_to_check {
-                let orphan = self.orphans.get(&orphan_hash).unwrap().block.clone();
-                if let Some(orphan_missing_chunks) =
-                    self.should_request_chunks_for_orphan(me, &orphan)
-                {
-                    debug!(target:"chain", "Request missing chunks for orphan {:?}", orphan_hash);
-                    orphan_misses_chunks(orphan_missing_chunks);
-                    self.orphans.mark_missing_chunks_requested_for_orphan(orphan_hash);
-                }
-            }
-            if let Some(orphans) = self.orphans.remove_by_prev_hash(prev_hash) {
-                debug!(target: "chain", "Check orphans: found {} orphans", orphans.len());
-                for orphan in orphans.into_iter() {
-                    let block_hash = orphan.hash();
-                    self.blocks_delay_tracker.mark_block_unorphaned(&block_hash, Clock::instant());
-                    let res = self.process_block_single(
-                        me,
-                        orphan.block,
-                        orphan.provenance,
-                        block_accepted,
-                        block_misses_chunks,
-                        orphan_misses_chunks,
-                        on_challenge,
-                    );
-                    match res {
-                        Ok(maybe_tip) => {
-                            maybe_new_head = maybe_tip;
-                            queue.push(block_hash);
-                        }
-                        Err(_) => {
-                            debug!(target: "chain", "Orphan declined");
+    pub fn catchup_blocks_step(
+        &mut self,
+        me: &Option<AccountId>,
+        sync_hash: &CryptoHash,
+        blocks_catch_up_state: &mut BlocksCatchUpState,
+        block_catch_up_scheduler: &dyn Fn(BlockCatchUpRequest),
+    ) -> Result<(), Error> {
+        debug!(target:"catchup", "catch up blocks: pending blocks: {:?}, processed {:?}, scheduled: {:?}, done: {:?}",
+               blocks_catch_up_state.pending_blocks, blocks_catch_up_state.processed_blocks.keys().collect::<Vec<_>>(),
+               blocks_catch_up_state.scheduled_blocks.keys().collect::<Vec<_>>(), blocks_catch_up_state.done_blocks.len());
+        for (queued_block, (catch_up_store_update, results)) in
+            blocks_catch_up_state.processed_blocks.drain()
+        {
+            let saved_store_update = match catch_up_store_update {
+                CatchUpStoreUpdate::InMemory(saved_store_update, size) => {
+                    blocks_catch_up_state.memory_usage_bytes -= size;
+                    metrics::CATCHUP_SAVED_UPDATES_MEMORY_BYTES.sub(size as i64);
+                    saved_store_update
+                }
+                CatchUpStoreUpdate::Spilled => self.store.restore_spilled_store_update(
+                    &queued_block,
+                    self.runtime_adapter.get_tries(),
+                )?,
+            };
+            match self.block_catch_up_postprocess(&queued_block, results, saved_store_update) {
+                Ok(_) => {
+                    let mut saw_one = false;
+                    for next_block_hash in self.store.get_blocks_to_catchup(&queued_block)?.clone()
+                    {
+                        saw_one = true;
+                        blocks_catch_up_state.pending_blocks.push(next_block_hash);
+                    }
+                    if saw_one {
+                        let epoch_id =
+                            self.runtime_adapter.get_epoch_id_from_prev_block(&queued_block)?;
+                        if epoch_id != blocks_catch_up_state.epoch_id {
+                            return Err(ErrorKind::InconsistentCatchupBookkeeping(format!(
+                                "blocks queued after {} are in epoch {:?}, catching up {:?}",
+                                queued_block, epoch_id, blocks_catch_up_state.epoch_id
+                            ))
+                            .into());
                         }
                     }
+                    blocks_catch_up_state.done_blocks.push(queued_block);
+                }
+                Err(_) => {
+                    error!("Error processing block during catch up, retrying");
+                    blocks_catch_up_state.pending_blocks.push(queued_block);
                 }
             }
-            queue_idx += 1;
         }
 
-        if queue.len() > 1 {
-            debug!(
-                target: "chain",
-                "Check orphans: {} blocks accepted, remaining # orphans {}",
-                queue.len() - 1,
-                self.orphans.len(),
-            );
+        for pending_block in blocks_catch_up_state.pending_blocks.drain(..) {
+            let block = self.store.get_block(&pending_block)?.clone();
+            let prev_block = self.store.get_block(block.header().prev_hash())?.clone();
+
+            let mut chain_update = self.chain_update();
+            let challenges_result = challenges_result(&chain_update.validate_challenges(
+                block.challenges(),
+                block.header().epoch_id(),
+                block.header().prev_hash(),
+            )?);
+            let work = chain_update.apply_chunks_preprocessing(
+                me,
+                &block,
+                &prev_block,
+                challenges_result,
+                ApplyChunksMode::CatchingUp,
+            )?;
+            let saved_store_update = chain_update.into_saved_store_update();
+            let size = saved_store_update.size_bytes();
+            let catch_up_store_update = if blocks_catch_up_state.memory_usage_bytes + size
+                > blocks_catch_up_state.memory_limit_bytes
+            {
+                debug!(target: "catchup", "Spilling store update of block {} of {} bytes", pending_block, size);
+                self.store.spill_saved_store_update(&pending_block, saved_store_update)?;
+                blocks_catch_up_state.num_spilled_blocks += 1;
+                metrics::CATCHUP_SAVED_UPDATES_SPILLED.inc();
+                CatchUpStoreUpdate::Spilled
+            } else {
+                blocks_catch_up_state.memory_usage_bytes += size;
+                metrics::CATCHUP_SAVED_UPDATES_MEMORY_BYTES.add(size as i64);
+                CatchUpStoreUpdate::InMemory(saved_store_update, size)
+            };
+            blocks_catch_up_state.scheduled_blocks.insert(pending_block, catch_up_store_update);
+            block_catch_up_scheduler(BlockCatchUpRequest {
+                sync_hash: *sync_hash,
+                block_hash: pending_block,
+                work,
+            });
         }
 
-        maybe_new_head
+        Ok(())
     }
 
-    pub fn get_outgoing_receipts_for_shard(
+    fn block_catch_up_postprocess(
         &mut self,
-        prev_block_hash: CryptoHash,
-        shard_id: ShardId,
-        last_height_included: BlockHeight,
-    ) -> Result<Vec<Receipt>, Error> {
-        self.store.get_outgoing_receipts_for_shard(
-            &*self.runtime_adapter,
-            prev_block_hash,
-            shard_id,
-            last_height_included,
-        )
+        block_hash: &CryptoHash,
+        results: Vec<Result<ApplyChunkResult, Error>>,
+        saved_store_update: SavedStoreUpdate,
+    ) -> Result<(), Error> {
+        let block = self.store.get_block(block_hash)?.clone();
+        let prev_block = self.store.get_block(block.header().prev_hash())?.clone();
+        let mut chain_update = self.chain_update_from_save_store_update(saved_store_update);
+        chain_update.apply_chunk_postprocessing(&block, &prev_block, results)?;
+        chain_update.chain_store_update.remove_partially_applied_block(block_hash);
+        let chunk_applied_events = std::mem::take(&mut chain_update.chunk_applied_events);
+        chain_update.commit()?;
+        for event in chunk_applied_events {
+            self.event_handlers.on_chunk_applied(event);
+        }
+        Ok(())
     }
 
-    pub fn get_state_response_header(
+    /// Apply transactions in chunks for the next epoch in blocks that were blocked on the state sync
+    pub fn finish_catchup_blocks(
         &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-    ) -> Result<ShardStateSyncResponseHeader, Error> {
-        // Check cache
-        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
-        if let Ok(Some(header)) = self.store.store().get_ser(ColStateHeaders, &key) {
-            return Ok(header);
-        }
-
-        // Consistency rules:
-        // 1. Everything prefixed with `sync_` indicates new epoch, for which we are syncing.
-        // 1a. `sync_prev` means the last of the prev epoch.
-        // 2. Empty prefix means the height where chunk was applied last time in the prev epoch.
-        //    Let's call it `current`.
-        // 2a. `prev_` means we're working with height before current.
-        // 3. In inner loops we use all prefixes with no relation to the context described above.
-        let sync_block = self
-            .get_block(&sync_hash)
-            .log_storage_error("block has already been checked for existence")?;
-        let sync_block_header = sync_block.header().clone();
-        let sync_block_epoch_id = sync_block.header().epoch_id().clone();
-        if shard_id as usize >= sync_block.chunks().len() {
-            return Err(ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into());
-        }
-
-        // The chunk was applied at height `chunk_header.height_included`.
-        // Getting the `current` state.
-        let sync_prev_block = self.get_block(sync_block_header.prev_hash())?;
-        if &sync_block_epoch_id == sync_prev_block.header().epoch_id() {
-            return Err(ErrorKind::InvalidStateRequest(
-                "sync_hash is not the first hash of the epoch".into(),
-            )
-            .into());
-        }
-        if shard_id as usize >= sync_prev_block.chunks().len() {
-            return Err(ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into());
-        }
-        // Chunk header here is the same chunk header as at the `current` height.
-        let sync_prev_hash = *sync_prev_block.hash();
-        let chunk_header = sync_prev_block.chunks()[shard_id as usize].clone();
-        let (chunk_headers_root, chunk_proofs) = merklize(
-            &sync_prev_block
-                .chunks()
-                .iter()
-                .map(|shard_chunk| {
-                    ChunkHashHeight(shard_chunk.chunk_hash(), shard_chunk.height_included())
-                })
-                .collect::<Vec<ChunkHashHeight>>(),
+        me: &Option<AccountId>,
+        epoch_first_block: &CryptoHash,
+        block_accepted: &mut dyn FnMut(AcceptedBlock),
+        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
+        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
+        on_challenge: &mut dyn FnMut(ChallengeBody),
+        affected_blocks: &Vec<CryptoHash>,
+    ) -> Result<(), Error> {
+        debug!(
+            "Finishing catching up blocks after syncing pre {:?}, me: {:?}",
+            epoch_first_block, me
         );
-        assert_eq!(&chunk_headers_root, sync_prev_block.header().chunk_headers_root());
-
-        let chunk = self.get_chunk_clone_from_header(&chunk_header)?;
-        let chunk_proof = chunk_proofs[shard_id as usize].clone();
-        let block_header =
-            self.get_header_on_chain_by_height(&sync_hash, chunk_header.height_included())?.clone();
-
-        // Collecting the `prev` state.
-        let (prev_chunk_header, prev_chunk_proof, prev_chunk_height_included) = match self
-            .get_block(block_header.prev_hash())
-        {
-            Ok(prev_block) => {
-                if shard_id as usize >= prev_block.chunks().len() {
-                    return Err(
-                        ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into()
-                    );
-                }
-                let prev_chunk_header = prev_block.chunks()[shard_id as usize].clone();
-                let (prev_chunk_headers_root, prev_chunk_proofs) = merklize(
-                    &prev_block
-                        .chunks()
-                        .iter()
-                        .map(|shard_chunk| {
-                            ChunkHashHeight(shard_chunk.chunk_hash(), shard_chunk.height_included())
-                        })
-                        .collect::<Vec<ChunkHashHeight>>(),
-                );
-                assert_eq!(&prev_chunk_headers_root, prev_block.header().chunk_headers_root());
-
-                let prev_chunk_proof = prev_chunk_proofs[shard_id as usize].clone();
-                let prev_chunk_height_included = prev_chunk_header.height_included();
 
-                (Some(prev_chunk_header), Some(prev_chunk_proof), prev_chunk_height_included)
-            }
-            Err(e) => match e.kind() {
-                ErrorKind::DBNotFoundErr(_) => {
-                    if block_header.prev_hash() == &CryptoHash::default() {
-                        (None, None, 0)
-                    } else {
-                        return Err(e);
-                    }
-                }
-                _ => return Err(e),
-            },
-        };
-
-        // Getting all existing incoming_receipts from prev_chunk height to the new epoch.
-        let incoming_receipts_proofs = ChainStoreUpdate::new(&mut self.store)
-            .get_incoming_receipts_for_shard(shard_id, sync_hash, prev_chunk_height_included)?;
-
-        // Collecting proofs for incoming receipts.
-        let mut root_proofs = vec![];
-        for receipt_response in incoming_receipts_proofs.iter() {
-            let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
-            let block_header = self.get_block_header(block_hash)?.clone();
-            let block = self.get_block(block_hash)?;
-            let (block_receipts_root, block_receipts_proofs) = merklize(
-                &block
-                    .chunks()
-                    .iter()
-                    .map(|chunk| chunk.outgoing_receipts_root())
-                    .collect::<Vec<CryptoHash>>(),
-            );
+        let first_block = self.store.get_block(epoch_first_block)?.clone();
+        let quarantined_shards = self.store.get_quarantined_shards()?;
 
-            let mut root_proofs_cur = vec![];
-            assert_eq!(receipt_proofs.len(), block_header.chunks_included() as usize);
-            for receipt_proof in receipt_proofs {
-                let ReceiptProof(receipts, shard_proof) = receipt_proof;
-                let ShardProof { from_shard_id, to_shard_id: _, proof } = shard_proof;
-                let receipts_hash = hash(&ReceiptList(shard_id, receipts).try_to_vec()?);
-                let from_shard_id = *from_shard_id as usize;
+        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
 
-                let root_proof = block.chunks()[from_shard_id].outgoing_receipts_root();
-                root_proofs_cur
-                    .push(RootProof(root_proof, block_receipts_proofs[from_shard_id].clone()));
+        // `blocks_to_catchup` consists of pairs (`prev_hash`, `hash`). For the block that precedes
+        // `epoch_first_block` we should only remove the pair with hash = epoch_first_block, while
+        // for all the blocks in the queue we can remove all the pairs that have them as `prev_hash`
+        // since we processed all the blocks built on top of them above during the BFS
+        chain_store_update
+            .remove_block_to_catchup(*first_block.header().prev_hash(), *epoch_first_block);
 
-                // Make sure we send something reasonable.
-                assert_eq!(block_header.chunk_receipts_root(), &block_receipts_root);
-                assert!(verify_path(root_proof, proof, &receipts_hash));
-                assert!(verify_path(
-                    block_receipts_root,
-                    &block_receipts_proofs[from_shard_id],
-                    &root_proof,
-                ));
-            }
-            root_proofs.push(root_proofs_cur);
+        for block_hash in affected_blocks {
+            debug!(target: "chain", "Catching up: removing prev={:?} from the queue. I'm {:?}", block_hash, me);
+            chain_store_update.remove_prev_block_to_catchup(*block_hash);
         }
+        chain_store_update.remove_state_dl_info(*epoch_first_block);
 
-        let state_root_node = self.runtime_adapter.get_state_root_node(
-            shard_id,
-            &sync_prev_hash,
-            &chunk_header.prev_state_root(),
-        )?;
-
-        let shard_state_header = match chunk {
-            ShardChunk::V1(chunk) => {
-                let prev_chunk_header =
-                    prev_chunk_header.and_then(|prev_header| match prev_header {
-                        ShardChunkHeader::V1(header) => Some(header),
-                        ShardChunkHeader::V2(_) => None,
-                        ShardChunkHeader::V3(_) => None,
-                    });
-                ShardStateSyncResponseHeader::V1(ShardStateSyncResponseHeaderV1 {
-                    chunk,
-                    chunk_proof,
-                    prev_chunk_header,
-                    prev_chunk_proof,
-                    incoming_receipts_proofs,
-                    root_proofs,
-                    state_root_node,
-                })
+        // Shards quarantined before this epoch were downloaded again along with the other shards.
+        for (shard_uid, quarantine) in quarantined_shards {
+            if quarantine.epoch_id != *first_block.header().epoch_id() {
+                info!(target: "chain", "Lifting quarantine of shard {:?}, its state was synced at {:?}", shard_uid, epoch_first_block);
+                chain_store_update.remove_shard_quarantine(&shard_uid);
             }
+        }
 
-            chunk @ ShardChunk::V2(_) => {
-                ShardStateSyncResponseHeader::V2(ShardStateSyncResponseHeaderV2 {
-                    chunk,
-                    chunk_proof,
-                    prev_chunk_header,
-                    prev_chunk_proof,
-                    incoming_receipts_proofs,
-                    root_proofs,
-                    state_root_node,
-                })
-            }
-        };
+        chain_store_update.commit()?;
 
-        // Saving the header data
-        let mut store_update = self.store.store().store_update();
-        store_update.set_ser(ColStateHeaders, &key, &shard_state_header)?;
-        store_update.commit()?;
+        for hash in affected_blocks.iter() {
+            self.check_orphans(
+                me,
+                *hash,
+                block_accepted,
+                block_misses_chunks,
+                orphan_misses_chunks,
+                on_challenge,
+            );
+        }
 
-        Ok(shard_state_header)
+        Ok(())
     }
 
-    pub fn get_state_response_part(
+    /// Marks the local state of the shard as corrupted. Chunks of the shard are no longer applied,
+    /// blocks stay not caught up for it, and its state is downloaded again from peers at the start
+    /// of the next epoch, after which the quarantine is lifted. Blocks rejected because the state
+    /// of the shard didn't match their chunk are no longer remembered as invalid. Returns whether
+    /// the shard wasn't quarantined yet.
+    pub fn quarantine_shard_state(
         &mut self,
-        shard_id: ShardId,
-        part_id: u64,
-        sync_hash: CryptoHash,
-    ) -> Result<Vec<u8>, Error> {
-        // Check cache
-        let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
-        if let Ok(Some(state_part)) = self.store.store().get(ColStateParts, &key) {
-            return Ok(state_part);
-        }
-
-        let sync_block = self
-            .get_block(&sync_hash)
-            .log_storage_error("block has already been checked for existence")?;
-        let sync_block_header = sync_block.header().clone();
-        let sync_block_epoch_id = sync_block.header().epoch_id().clone();
-        if shard_id as usize >= sync_block.chunks().len() {
-            return Err(ErrorKind::InvalidStateRequest("shard_id out of bounds".into()).into());
+        shard_uid: ShardUId,
+        reason: String,
+    ) -> Result<bool, Error> {
+        let head = self.head()?;
+        let shard_layout = self.runtime_adapter.get_shard_layout(&head.epoch_id)?;
+        if !shard_layout.get_shard_uids().contains(&shard_uid) {
+            return Err(ErrorKind::InvalidShardId(shard_uid.shard_id()).into());
         }
-        let sync_prev_block = self.get_block(sync_block_header.prev_hash())?;
-        if &sync_block_epoch_id == sync_prev_block.header().epoch_id() {
-            return Err(ErrorKind::InvalidStateRequest(
-                "sync_hash is not the first hash of the epoch".into(),
-            )
-            .into());
+        if self.store.get_quarantined_shards()?.contains_key(&shard_uid) {
+            // Re-quarantining would postpone the download of the state.
+            return Ok(false);
         }
-        if shard_id as usize >= sync_prev_block.chunks().len() {
-            return Err(ErrorKind::InvalidStateRequest("shard_id out of bounds".into()).into());
+        warn!(target: "chain", "Quarantining state of shard {:?} at {:?}: {}", shard_uid, head.last_block_hash, reason);
+        let quarantine =
+            ShardQuarantine { reason, epoch_id: head.epoch_id, head_hash: head.last_block_hash };
+        let mut chain_store_update = self.store.store_update();
+        chain_store_update.save_shard_quarantine(&shard_uid, &quarantine)?;
+        chain_store_update.commit()?;
+        let rejected_by_state = self
+            .invalid_blocks
+            .iter()
+            .filter(|(_, kind)| match kind {
+                ErrorKind::InvalidChunkState(chunk_state) => {
+                    chunk_state.chunk_header.shard_id() == shard_uid.shard_id()
+                }
+                _ => false,
+            })
+            .map(|(block_hash, _)| *block_hash)
+            .collect::<Vec<_>>();
+        for block_hash in rejected_by_state {
+            self.invalid_blocks.pop(&block_hash);
         }
-        let state_root = sync_prev_block.chunks()[shard_id as usize].prev_state_root();
-        let sync_prev_hash = *sync_prev_block.hash();
-        let state_root_node = self
-            .runtime_adapter
-            .get_state_root_node(shard_id, &sync_prev_hash, &state_root)
-            .log_storage_error("get_state_root_node fail")?;
-        let num_parts = get_num_state_parts(state_root_node.memory_usage);
+        Ok(true)
+    }
 
-        if part_id >= num_parts {
-            return Err(ErrorKind::InvalidStateRequest("part_id out of bound".to_string()).into());
+    /// Returns the shards whose state is quarantined, see `quarantine_shard_state`.
+    pub fn shard_quarantine_status(&self) -> Result<HashMap<ShardUId, ShardQuarantine>, Error> {
+        self.store.get_quarantined_shards()
+    }
+
+    pub fn get_transaction_execution_result(
+        &mut self,
+        id: &CryptoHash,
+    ) -> Result<Vec<ExecutionOutcomeWithIdView>, Error> {
+        Ok(self.store.get_outcomes_by_id(id)?.into_iter().map(Into::into).collect())
+    }
+
+    /// Outcomes of the transaction or receipt and of all the receipts it produced, recursively,
+    /// in depth-first order. If `allow_missing` is set, receipts whose outcome isn't found are
+    /// returned separately instead of failing, the outcome of `id` itself is always required.
+    fn get_recursive_transaction_results(
+        &mut self,
+        id: &CryptoHash,
+        allow_missing: bool,
+    ) -> Result<(Vec<ExecutionOutcomeWithIdView>, Vec<CryptoHash>), Error> {
+        let mut results = vec![];
+        let mut missing_receipt_ids = vec![];
+        // Receipt chains may be arbitrarily deep, so they are walked with an explicit stack
+        // instead of recursion. Receipt ids are pushed in reverse to keep the depth-first order.
+        let mut pending = vec![*id];
+        while let Some(current_id) = pending.pop() {
+            let outcome: ExecutionOutcomeWithIdView = match self.get_execution_outcome(&current_id)
+            {
+                Ok(outcome) => outcome.into(),
+                Err(err)
+                    if allow_missing
+                        && current_id != *id
+                        && matches!(err.kind(), ErrorKind::DBNotFoundErr(_)) =>
+                {
+                    missing_receipt_ids.push(current_id);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            pending.extend(outcome.outcome.receipt_ids.iter().rev());
+            results.push(outcome);
         }
-        let state_part = self
-            .runtime_adapter
-            .obtain_state_part(
-                shard_id,
-                &sync_prev_hash,
-                &state_root,
-                PartId::new(part_id, num_parts),
-            )
-            .log_storage_error("obtain_state_part fail")?;
+        Ok((results, missing_receipt_ids))
+    }
 
-        // Before saving State Part data, we need to make sure we can calculate and save State Header
-        self.get_state_response_header(shard_id, sync_hash)?;
+    /// Final outcome of the transaction and its receipts, without reading the transaction itself.
+    /// Use it when `get_final_transaction_result` fails because the node doesn't have the
+    /// transaction, e.g. after its chunk was garbage collected, while the outcomes are still kept.
+    pub fn get_final_transaction_result_lite(
+        &mut self,
+        transaction_hash: &CryptoHash,
+    ) -> Result<FinalExecutionOutcomeLiteView, Error> {
+        Ok(self.get_final_transaction_result_lite_opts(transaction_hash, false)?.0)
+    }
 
-        // Saving the part data
-        let mut store_update = self.store.store().store_update();
-        store_update.set(ColStateParts, &key, &state_part);
-        store_update.commit()?;
+    /// Like `get_final_transaction_result_lite`, also returning the receipts whose outcome is
+    /// missing if `allow_missing` is set. The status is `Started` if any outcome is missing.
+    fn get_final_transaction_result_lite_opts(
+        &mut self,
+        transaction_hash: &CryptoHash,
+        allow_missing: bool,
+    ) -> Result<(FinalExecutionOutcomeLiteView, Vec<CryptoHash>), Error> {
+        let (mut outcomes, missing_receipt_ids) =
+            self.get_recursive_transaction_results(transaction_hash, allow_missing)?;
+        let mut looking_for_id = (*transaction_hash).into();
+        let num_outcomes = outcomes.len();
+        // With missing outcomes the chain of receipts may not lead to a final outcome.
+        let status = if !missing_receipt_ids.is_empty() {
+            FinalExecutionStatus::Started
+        } else {
+            outcomes
+                .iter()
+                .find_map(|outcome_with_id| {
+                    if outcome_with_id.id == looking_for_id {
+                        match &outcome_with_id.outcome.status {
+                            ExecutionStatusView::Unknown if num_outcomes == 1 => {
+                                Some(FinalExecutionStatus::NotStarted)
+                            }
+                            ExecutionStatusView::Unknown => Some(FinalExecutionStatus::Started),
+                            ExecutionStatusView::Failure(e) => {
+                                Some(FinalExecutionStatus::Failure(e.clone()))
+                            }
+                            ExecutionStatusView::SuccessValue(v) => {
+                                Some(FinalExecutionStatus::SuccessValue(v.clone()))
+                            }
+                            ExecutionStatusView::SuccessReceiptId(id) => {
+                                looking_for_id = *id;
+                                None
+                            }
+                        }
+                    } else {
+                        None
+                    }
+                })
+                // The receipt leading to the final outcome may not be executed yet.
+                .unwrap_or(FinalExecutionStatus::Started)
+        };
+        let receipts_outcome = outcomes.split_off(1);
+        let transaction_outcome = outcomes.pop().unwrap();
+        Ok((
+            FinalExecutionOutcomeLiteView {
+                status,
+                transaction_hash: *transaction_hash,
+                transaction_outcome,
+                receipts_outcome,
+            },
+            missing_receipt_ids,
+        ))
+    }
 
-        Ok(state_part)
+    pub fn get_final_transaction_result(
+        &mut self,
+        transaction_hash: &CryptoHash,
+    ) -> Result<FinalExecutionOutcomeView, Error> {
+        Ok(self.get_final_transaction_result_opts(transaction_hash, false)?.final_outcome)
     }
 
-    pub fn set_state_header(
+    /// Final outcome of the transaction and its receipts. If `allow_missing` is set, receipts
+    /// whose outcome isn't found, e.g. because it was garbage collected or the node doesn't track
+    /// the shard of the receipt, are listed in `missing_receipt_ids` instead of failing, and the
+    /// status is `Started`.
+    pub fn get_final_transaction_result_opts(
         &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-        shard_state_header: ShardStateSyncResponseHeader,
-    ) -> Result<(), Error> {
-        let sync_block_header = self.get_block_header(&sync_hash)?.clone();
+        transaction_hash: &CryptoHash,
+        allow_missing: bool,
+    ) -> Result<PartialFinalExecutionOutcomeView, Error> {
+        let (
+            FinalExecutionOutcomeLiteView {
+                status,
+                transaction_hash: _,
+                transaction_outcome,
+                receipts_outcome,
+            },
+            missing_receipt_ids,
+        ) = self.get_final_transaction_result_lite_opts(transaction_hash, allow_missing)?;
+        let transaction: SignedTransactionView = self
+            .store
+            .get_transaction(transaction_hash)?
+            .ok_or_else(|| {
+                ErrorKind::DBNotFoundErr(format!("Transaction {} is not found", transaction_hash))
+            })?
+            .clone()
+            .into();
+        Ok(PartialFinalExecutionOutcomeView {
+            final_outcome: FinalExecutionOutcomeView {
+                status,
+                transaction,
+                transaction_outcome,
+                receipts_outcome,
+            },
+            missing_receipt_ids,
+        })
+    }
 
-        let chunk = shard_state_header.cloned_chunk();
-        let prev_chunk_header = shard_state_header.cloned_prev_chunk_header();
+    pub fn get_final_transaction_result_with_receipt(
+        &mut self,
+        final_outcome: FinalExecutionOutcomeView,
+    ) -> Result<FinalExecutionOutcomeWithReceiptView, Error> {
+        let receipt_id_from_transaction =
+            final_outcome.transaction_outcome.outcome.receipt_ids.get(0).cloned();
+        let is_local_receipt =
+            final_outcome.transaction.signer_id == final_outcome.transaction.receiver_id;
 
-        // 1-2. Checking chunk validity
-        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter)? {
-            byzantine_assert!(false);
-            return Err(ErrorKind::Other(
-                "set_shard_state failed: chunk header proofs are invalid".into(),
-            )
-            .into());
-        }
+        let receipts = final_outcome
+            .receipts_outcome
+            .iter()
+            .filter_map(|outcome| {
+                if Some(outcome.id) == receipt_id_from_transaction && is_local_receipt {
+                    None
+                } else {
+                    Some(self.store.get_receipt(&outcome.id).and_then(|r| {
+                        r.cloned().map(Into::into).ok_or_else(|| {
+                            ErrorKind::DBNotFoundErr(format!("Receipt {} is not found", outcome.id))
+                                .into()
+                        })
+                    }))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        // Consider chunk itself is valid.
+        Ok(FinalExecutionOutcomeWithReceiptView { final_outcome, receipts })
+    }
 
-        // 3. Checking that chunks `chunk` and `prev_chunk` are included in appropriate blocks
-        // 3a. Checking that chunk `chunk` is included into block at last height before sync_hash
-        // 3aa. Also checking chunk.height_included
-        let sync_prev_block_header = self.get_block_header(sync_block_header.prev_hash())?.clone();
-        if !verify_path(
-            *sync_prev_block_header.chunk_headers_root(),
-            shard_state_header.chunk_proof(),
-            &ChunkHashHeight(chunk.chunk_hash(), chunk.height_included()),
-        ) {
-            byzantine_assert!(false);
-            return Err(ErrorKind::Other(
-                "set_shard_state failed: chunk isn't included into block".into(),
-            )
-            .into());
-        }
+    /// Find a validator to forward transactions to
+    pub fn find_chunk_producer_for_forwarding(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        horizon: BlockHeight,
+    ) -> Result<AccountId, Error> {
+        let head = self.head()?;
+        let target_height = head.height + horizon - 1;
+        self.runtime_adapter.get_chunk_producer(epoch_id, target_height, shard_id)
+    }
 
-        let block_header =
-            self.get_header_on_chain_by_height(&sync_hash, chunk.height_included())?.clone();
-        // 3b. Checking that chunk `prev_chunk` is included into block at height before chunk.height_included
-        // 3ba. Also checking prev_chunk.height_included - it's important for getting correct incoming receipts
-        match (&prev_chunk_header, shard_state_header.prev_chunk_proof()) {
-            (Some(prev_chunk_header), Some(prev_chunk_proof)) => {
-                let prev_block_header =
-                    self.get_block_header(block_header.prev_hash())?.clone();
-                if !verify_path(
-                    *prev_block_header.chunk_headers_root(),
-                    prev_chunk_proof,
-                    &ChunkHashHeight(prev_chunk_header.chunk_hash(), prev_chunk_header.height_included()),
-                ) {
-                    byzantine_assert!(false);
-                    return Err(ErrorKind::Other(
-                        "set_shard_state failed: prev_chunk isn't included into block".into(),
-                    )
-                    .into());
-                }
-            }
-            (None, None) => {
-                if chunk.height_included() != 0 {
-                    return Err(ErrorKind::Other(
-                    "set_shard_state failed: received empty state response for a chunk that is not at height 0".into()
-                ).into());
-                }
-            }
-            _ =>
-                return Err(ErrorKind::Other("set_shard_state failed: `prev_chunk_header` and `prev_chunk_proof` must either both be present or both absent".into()).into())
-        };
+    /// Find a validator that is responsible for a given shard to forward requests to
+    pub fn find_validator_for_forwarding(&self, shard_id: ShardId) -> Result<AccountId, Error> {
+        let head = self.head()?;
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
+        self.find_chunk_producer_for_forwarding(&epoch_id, shard_id, TX_ROUTING_HEIGHT_HORIZON)
+    }
 
-        // 4. Proving incoming receipts validity
-        // 4a. Checking len of proofs
-        if shard_state_header.root_proofs().len()
-            != shard_state_header.incoming_receipts_proofs().len()
-        {
-            byzantine_assert!(false);
-            return Err(ErrorKind::Other("set_shard_state failed: invalid proofs".into()).into());
+    pub fn check_block_final_and_canonical(
+        &mut self,
+        block_hash: &CryptoHash,
+    ) -> Result<(), Error> {
+        let last_final_block_hash = *self.head_header()?.last_final_block();
+        let last_final_height = self.get_block_header(&last_final_block_hash)?.height();
+        let block_header = self.get_block_header(block_hash)?.clone();
+        if block_header.height() <= last_final_height {
+            self.is_on_current_chain(&block_header)
+        } else {
+            Err(ErrorKind::Other(format!("{} not on current chain", block_hash)).into())
         }
-        let mut hash_to_compare = sync_hash;
-        for (i, receipt_response) in
-            shard_state_header.incoming_receipts_proofs().iter().enumerate()
-        {
-            let ReceiptProofResponse(block_hash, receipt_proofs) = receipt_response;
+    }
+
+    /// Get all execution outcomes generated when the chunk are applied. If `verified` is set, the
+    /// outcomes of each shard are checked against the outcome root of its chunk, see
+    /// `verify_block_execution_outcomes`.
+    pub fn get_block_execution_outcomes(
+        &mut self,
+        block_hash: &CryptoHash,
+        verified: bool,
+    ) -> Result<HashMap<ShardId, Vec<ExecutionOutcomeWithIdAndProof>>, Error> {
+        let block = self.get_block(block_hash)?;
+        let height = block.header().height();
+        let epoch_id = block.header().epoch_id().clone();
+        let chunk_headers = block.chunks().iter().cloned().collect::<Vec<_>>();
 
-            // 4b. Checking that there is a valid sequence of continuous blocks
-            if *block_hash != hash_to_compare {
-                byzantine_assert!(false);
-                return Err(ErrorKind::Other(
-                    "set_shard_state failed: invalid incoming receipts".into(),
-                )
-                .into());
-            }
-            let header = self.get_block_header(&hash_to_compare)?;
-            hash_to_compare = *header.prev_hash();
+        // Shards are independent, so their outcomes are read in parallel.
+        let store = &self.store;
+        let shard_outcomes = chunk_headers
+            .par_iter()
+            .map(|chunk_header| store.get_block_shard_outcomes(block_hash, chunk_header.shard_id()))
+            .collect::<Result<Vec<_>, Error>>()?;
 
-            let block_header = self.get_block_header(block_hash)?;
-            // 4c. Checking len of receipt_proofs for current block
-            if receipt_proofs.len() != shard_state_header.root_proofs()[i].len()
-                || receipt_proofs.len() != block_header.chunks_included() as usize
-            {
-                byzantine_assert!(false);
-                return Err(
-                    ErrorKind::Other("set_shard_state failed: invalid proofs".into()).into()
-                );
-            }
-            // We know there were exactly `block_header.chunks_included` chunks included
-            // on the height of block `block_hash`.
-            // There were no other proofs except for included chunks.
-            // According to Pigeonhole principle, it's enough to ensure all receipt_proofs are distinct
-            // to prove that all receipts were received and no receipts were hidden.
-            let mut visited_shard_ids = HashSet::<ShardId>::new();
-            for (j, receipt_proof) in receipt_proofs.iter().enumerate() {
-                let ReceiptProof(receipts, shard_proof) = receipt_proof;
-                let ShardProof { from_shard_id, to_shard_id: _, proof } = shard_proof;
-                // 4d. Checking uniqueness for set of `from_shard_id`
-                match visited_shard_ids.get(from_shard_id) {
-                    Some(_) => {
-                        byzantine_assert!(false);
-                        return Err(ErrorKind::Other(
-                            "set_shard_state failed: invalid proofs".into(),
-                        )
-                        .into());
-                    }
-                    _ => visited_shard_ids.insert(*from_shard_id),
-                };
-                let RootProof(root, block_proof) = &shard_state_header.root_proofs()[i][j];
-                let receipts_hash = hash(&ReceiptList(shard_id, receipts).try_to_vec()?);
-                // 4e. Proving the set of receipts is the subset of outgoing_receipts of shard `shard_id`
-                if !verify_path(*root, proof, &receipts_hash) {
-                    byzantine_assert!(false);
-                    return Err(
-                        ErrorKind::Other("set_shard_state failed: invalid proofs".into()).into()
-                    );
-                }
-                // 4f. Proving the outgoing_receipts_root matches that in the block
-                if !verify_path(*block_header.chunk_receipts_root(), block_proof, root) {
-                    byzantine_assert!(false);
-                    return Err(
-                        ErrorKind::Other("set_shard_state failed: invalid proofs".into()).into()
-                    );
-                }
+        let mut res = HashMap::new();
+        for (chunk_header, outcomes) in chunk_headers.into_iter().zip(shard_outcomes) {
+            let shard_id = chunk_header.shard_id();
+            if verified {
+                let is_new_chunk = chunk_header.height_included() == height;
+                self.verify_block_execution_outcomes(
+                    block_hash,
+                    &epoch_id,
+                    shard_id,
+                    is_new_chunk,
+                    &outcomes,
+                )?;
             }
+            res.insert(shard_id, outcomes);
         }
-        // 4g. Checking that there are no more heights to get incoming_receipts
-        let header = self.get_block_header(&hash_to_compare)?;
-        if header.height() != prev_chunk_header.map_or(0, |h| h.height_included()) {
-            byzantine_assert!(false);
-            return Err(ErrorKind::Other(
-                "set_shard_state failed: invalid incoming receipts".into(),
-            )
-            .into());
+        Ok(res)
+    }
+
+    /// Checks that the outcomes of the shard produced in the block are the ones committed to by
+    /// the outcome root stored in the chunk extra, both as a whole and through the stored proof
+    /// of each outcome. Outcomes are only produced, and the outcome root only updated, in blocks
+    /// with a new chunk for the shard.
+    fn verify_block_execution_outcomes(
+        &mut self,
+        block_hash: &CryptoHash,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        is_new_chunk: bool,
+        outcomes: &[ExecutionOutcomeWithIdAndProof],
+    ) -> Result<(), Error> {
+        if !is_new_chunk {
+            return if outcomes.is_empty() {
+                Ok(())
+            } else {
+                Err(ErrorKind::OutcomesRootMismatch(shard_id).into())
+            };
+        }
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, epoch_id)?;
+        let outcome_root = match self.get_chunk_extra(block_hash, &shard_uid) {
+            Ok(chunk_extra) => *chunk_extra.outcome_root(),
+            Err(e) => match e.kind() {
+                // The shard is not tracked.
+                ErrorKind::DBNotFoundErr(_) if outcomes.is_empty() => return Ok(()),
+                _ => return Err(e),
+            },
+        };
+        let outcomes_with_id =
+            outcomes.iter().map(|outcome| outcome.outcome_with_id.clone()).collect::<Vec<_>>();
+        let (root, _) = ApplyTransactionResult::compute_outcomes_proof(&outcomes_with_id);
+        let proofs_valid = outcomes.iter().all(|outcome| {
+            verify_path(outcome_root, &outcome.proof, &outcome.outcome_with_id.to_hashes())
+        });
+        if root != outcome_root || !proofs_valid {
+            return Err(ErrorKind::OutcomesRootMismatch(shard_id).into());
         }
+        Ok(())
+    }
+}
 
-        // 5. Checking that state_root_node is valid
-        let chunk_inner = chunk.take_header().take_inner();
-        if !self.runtime_adapter.validate_state_root_node(
-            shard_state_header.state_root_node(),
-            chunk_inner.prev_state_root(),
-        ) {
-            byzantine_assert!(false);
-            return Err(ErrorKind::Other(
-                "set_shard_state failed: state_root_node is invalid".into(),
-            )
-            .into());
+/// Implement block merkle proof retrieval.
+impl Chain {
+    fn combine_maybe_hashes(
+        hash1: Option<MerkleHash>,
+        hash2: Option<MerkleHash>,
+    ) -> Option<MerkleHash> {
+        match (hash1, hash2) {
+            (Some(h1), Some(h2)) => Some(combine_hash(&h1, &h2)),
+            (Some(h1), None) => Some(h1),
+            (None, Some(_)) => {
+                debug_assert!(false, "Inconsistent state in merkle proof computation: left node is None but right node exists");
+                None
+            }
+            _ => None,
         }
+    }
 
-        // Saving the header data.
-        let mut store_update = self.store.store().store_update();
-        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
-        store_update.set_ser(ColStateHeaders, &key, &shard_state_header)?;
-        store_update.commit()?;
+    /// How strictly the blocks are validated, as configured on this chain.
+    fn block_validation_config(&self) -> BlockValidationConfig {
+        BlockValidationConfig {
+            block_limits_config: self.block_limits_config,
+            receipts_root_check_mode: self.receipts_root_check_mode,
+            trusted_chain_replay: self.trusted_chain_replay,
+            verify_incoming_receipts: self.verify_incoming_receipts,
+        }
+    }
 
-        Ok(())
+    pub(crate) fn chain_update(&mut self) -> ChainUpdate {
+        let chunk_event_subscription = self.event_handlers.subscription();
+        let mut chain_update = ChainUpdate::new(
+            &mut self.store,
+            self.runtime_adapter.clone(),
+            &self.orphans,
+            &self.blocks_with_missing_chunks,
+            self.epoch_length,
+            &self.block_economics_config,
+            self.block_validation_config(),
+            self.doomslug_threshold_mode,
+            &self.genesis,
+            self.transaction_validity_period,
+            self.pending_states_to_patch.take(),
+        );
+        chain_update.chunk_event_subscription = chunk_event_subscription;
+        chain_update.future_block_grace_period = self.future_block_grace_period;
+        chain_update
     }
 
-    pub fn get_state_header(
+    fn chain_update_from_save_store_update(
         &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-    ) -> Result<ShardStateSyncResponseHeader, Error> {
-        self.store.get_state_header(shard_id, sync_hash)
+        saved_store_update: SavedStoreUpdate,
+    ) -> ChainUpdate {
+        let chunk_event_subscription = self.event_handlers.subscription();
+        let mut chain_update = ChainUpdate::new_from_save_store_update(
+            &mut self.store,
+            saved_store_update,
+            self.runtime_adapter.clone(),
+            &self.orphans,
+            &self.blocks_with_missing_chunks,
+            self.epoch_length,
+            &self.block_economics_config,
+            self.block_validation_config(),
+            self.doomslug_threshold_mode,
+            &self.genesis,
+            self.transaction_validity_period,
+            self.pending_states_to_patch.take(),
+        );
+        chain_update.chunk_event_subscription = chunk_event_subscription;
+        chain_update
     }
 
-    pub fn set_state_part(
+    /// Looks up the node at given position (index, level) of the block merkle tree of
+    /// `head_block_hash` in the cache of nodes computed for block proofs.
+    fn get_cached_merkle_tree_node(
         &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-        part_id: PartId,
-        data: &Vec<u8>,
-    ) -> Result<(), Error> {
-        let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
-        let chunk = shard_state_header.take_chunk();
-        let state_root = *chunk.take_header().take_inner().prev_state_root();
-        if !self.runtime_adapter.validate_state_part(&state_root, part_id, data) {
-            byzantine_assert!(false);
-            return Err(ErrorKind::Other(
-                "set_state_part failed: validate_state_part failed".into(),
-            )
-            .into());
+        head_block_hash: &CryptoHash,
+        index: u64,
+        level: u64,
+    ) -> Option<Option<MerkleHash>> {
+        let maybe_hash = self.block_merkle_nodes.get(&(*head_block_hash, level, index)).copied();
+        #[cfg(test)]
+        if maybe_hash.is_none() {
+            self.block_merkle_nodes_cache_misses += 1;
         }
-
-        // Saving the part data.
-        let mut store_update = self.store.store().store_update();
-        let key = StatePartKey(sync_hash, shard_id, part_id.idx).try_to_vec()?;
-        store_update.set(ColStateParts, &key, data);
-        store_update.commit()?;
-        Ok(())
+        maybe_hash
     }
 
-    pub fn schedule_apply_state_parts(
+    /// Get node at given position (index, level). If the node does not exist, return `None`.
+    fn get_merkle_tree_node(
         &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-        num_parts: u64,
-        state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
-    ) -> Result<(), Error> {
-        let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
-        let state_root = shard_state_header.chunk_prev_state_root();
-        let epoch_id = self.get_block_header(&sync_hash)?.epoch_id().clone();
+        head_block_hash: &CryptoHash,
+        index: u64,
+        level: u64,
+        counter: u64,
+        tree_size: u64,
+    ) -> Result<Option<MerkleHash>, Error> {
+        if let Some(hash) = self.get_cached_merkle_tree_node(head_block_hash, index, level) {
+            Ok(hash)
+        } else {
+            if level == 0 {
+                let maybe_hash = if index >= tree_size {
+                    None
+                } else {
+                    Some(*self.mut_store().get_block_hash_from_ordinal(index)?)
+                };
+                self.block_merkle_nodes.put((*head_block_hash, level, index), maybe_hash);
+                Ok(maybe_hash)
+            } else {
+                let cur_tree_size = (index + 1) * counter;
+                let maybe_hash = if cur_tree_size > tree_size {
+                    if index * counter <= tree_size {
+                        let left_hash = self.get_merkle_tree_node(
+                            head_block_hash,
+                            index * 2,
+                            level - 1,
+                            counter / 2,
+                            tree_size,
+                        )?;
+                        let right_hash = self.reconstruct_merkle_tree_node(
+                            head_block_hash,
+                            index * 2 + 1,
+                            level - 1,
+                            counter / 2,
+                            tree_size,
+                        )?;
+                        Self::combine_maybe_hashes(left_hash, right_hash)
+                    } else {
+                        None
+                    }
+                } else {
+                    Some(
+                        *self
+                            .mut_store()
+                            .get_block_merkle_tree_from_ordinal(cur_tree_size)?
+                            .get_path()
+                            .last()
+                            .ok_or_else(|| {
+                                ErrorKind::Other("Merkle tree node missing".to_string())
+                            })?,
+                    )
+                };
+                self.block_merkle_nodes.put((*head_block_hash, level, index), maybe_hash);
+                Ok(maybe_hash)
+            }
+        }
+    }
 
-        state_parts_task_scheduler(ApplyStatePartsRequest {
-            runtime: Arc::clone(&self.runtime_adapter),
-            shard_id,
-            state_root,
-            num_parts,
-            epoch_id,
-            sync_hash,
-        });
+    /// Reconstruct node at given position (index, level). If the node does not exist, return `None`.
+    fn reconstruct_merkle_tree_node(
+        &mut self,
+        head_block_hash: &CryptoHash,
+        index: u64,
+        level: u64,
+        counter: u64,
+        tree_size: u64,
+    ) -> Result<Option<MerkleHash>, Error> {
+        if let Some(hash) = self.get_cached_merkle_tree_node(head_block_hash, index, level) {
+            Ok(hash)
+        } else {
+            if level == 0 {
+                let maybe_hash = if index >= tree_size {
+                    None
+                } else {
+                    Some(*self.mut_store().get_block_hash_from_ordinal(index)?)
+                };
+                self.block_merkle_nodes.put((*head_block_hash, level, index), maybe_hash);
+                Ok(maybe_hash)
+            } else {
+                let left_hash = self.get_merkle_tree_node(
+                    head_block_hash,
+                    index * 2,
+                    level - 1,
+                    counter / 2,
+                    tree_size,
+                )?;
+                let right_hash = self.reconstruct_merkle_tree_node(
+                    head_block_hash,
+                    index * 2 + 1,
+                    level - 1,
+                    counter / 2,
+                    tree_size,
+                )?;
+                let maybe_hash = Self::combine_maybe_hashes(left_hash, right_hash);
+                self.block_merkle_nodes.put((*head_block_hash, level, index), maybe_hash);
 
-        Ok(())
+                Ok(maybe_hash)
+            }
+        }
     }
 
-    pub fn set_state_finalize(
+    /// Whether `get_block_proof` can prove the block `block_hash` against `head_block_hash`, i.e.
+    /// the block is the head or is included in the block merkle tree of the head. Fails if either
+    /// block is unknown.
+    pub fn can_prove_block(
         &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-        apply_result: Result<(), near_chain_primitives::Error>,
-    ) -> Result<(), Error> {
-        apply_result?;
-
-        let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
-        let mut height = shard_state_header.chunk_height_included();
-        let mut chain_update = self.chain_update();
-        chain_update.set_state_finalize(shard_id, sync_hash, shard_state_header)?;
-        chain_update.commit()?;
+        block_hash: &CryptoHash,
+        head_block_hash: &CryptoHash,
+    ) -> Result<bool, Error> {
+        if block_hash == head_block_hash {
+            return Ok(true);
+        }
+        let leaf_index = self.mut_store().get_block_merkle_tree(block_hash)?.size();
+        let tree_size = self.mut_store().get_block_merkle_tree(head_block_hash)?.size();
+        Ok(leaf_index < tree_size)
+    }
 
-        // We restored the state on height `shard_state_header.chunk.header.height_included`.
-        // Now we should build a chain up to height of `sync_hash` block.
-        loop {
-            height += 1;
-            let mut chain_update = self.chain_update();
-            // Result of successful execution of set_state_finalize_on_height is bool,
-            // should we commit and continue or stop.
-            if chain_update.set_state_finalize_on_height(height, shard_id, sync_hash)? {
-                chain_update.commit()?;
+    /// Get merkle proof for block with hash `block_hash` in the merkle tree of `head_block_hash`.
+    /// Fails with `ErrorKind::BlockAheadOfHead` if the block is ahead of the head, see
+    /// `can_prove_block`. The merkle tree nodes computed for the proof are cached, so that
+    /// following proofs against the same head are cheaper.
+    pub fn get_block_proof(
+        &mut self,
+        block_hash: &CryptoHash,
+        head_block_hash: &CryptoHash,
+    ) -> Result<MerklePath, Error> {
+        if block_hash == head_block_hash {
+            // special case if the block to prove is the same as head
+            return Ok(vec![]);
+        }
+        let leaf_index = self.mut_store().get_block_merkle_tree(block_hash)?.size();
+        let tree_size = self.mut_store().get_block_merkle_tree(head_block_hash)?.size();
+        if leaf_index >= tree_size {
+            return Err(ErrorKind::BlockAheadOfHead {
+                block_height: self.get_block_header(block_hash)?.height(),
+                head_height: self.get_block_header(head_block_hash)?.height(),
+            }
+            .into());
+        }
+        let mut level = 0;
+        let mut counter = 1;
+        let mut cur_index = leaf_index;
+        let mut path = vec![];
+        let mut iter = tree_size;
+        while iter > 1 {
+            if cur_index % 2 == 0 {
+                cur_index += 1
             } else {
-                break;
+                cur_index -= 1;
+            }
+            let direction = if cur_index % 2 == 0 { Direction::Left } else { Direction::Right };
+            let maybe_hash = if cur_index % 2 == 1 {
+                // node not immediately available. Needs to be reconstructed
+                self.reconstruct_merkle_tree_node(
+                    head_block_hash,
+                    cur_index,
+                    level,
+                    counter,
+                    tree_size,
+                )?
+            } else {
+                self.get_merkle_tree_node(head_block_hash, cur_index, level, counter, tree_size)?
+            };
+            if let Some(hash) = maybe_hash {
+                path.push(MerklePathItem { hash, direction });
             }
+            cur_index /= 2;
+            iter = (iter + 1) / 2;
+            level += 1;
+            counter *= 2;
         }
-
-        Ok(())
+        Ok(path)
     }
 
-    pub fn build_state_for_split_shards_preprocessing(
+    /// Get merkle proof for the block `block_hash` containing an execution outcome, made for a
+    /// light client at `light_client_head`. Unless `allow_non_final` is set, both blocks have to
+    /// be final and canonical, and `ErrorKind::NotFinalYet` is returned for a block which is not
+    /// final yet. Otherwise the block only has to be canonical, and if it is ahead of
+    /// `light_client_head` or the latter isn't canonical, the proof is made against the header
+    /// head instead. The finality of the block is returned along with the proof either way.
+    pub fn get_execution_outcome_with_block_proof(
         &mut self,
-        sync_hash: &CryptoHash,
-        shard_id: ShardId,
-        state_split_scheduler: &dyn Fn(StateSplitRequest),
-    ) -> Result<(), Error> {
-        let (epoch_id, next_epoch_id) = {
-            let block_header = self.get_block_header(sync_hash)?;
-            (block_header.epoch_id().clone(), block_header.next_epoch_id().clone())
+        block_hash: &CryptoHash,
+        light_client_head: &CryptoHash,
+        allow_non_final: bool,
+    ) -> Result<OutcomeBlockProof, Error> {
+        let head_header = self.head_header()?.clone();
+        let last_final_height = self.get_block_header(head_header.last_final_block())?.height();
+        let last_ds_final_height =
+            self.get_block_header(head_header.last_ds_final_block())?.height();
+        let block_header = self.get_block_header(block_hash)?.clone();
+        let finality = if block_header.height() <= last_final_height {
+            Finality::Final
+        } else if block_header.height() <= last_ds_final_height {
+            Finality::DoomSlug
+        } else {
+            Finality::None
         };
-        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
-        let next_epoch_shard_layout = self.runtime_adapter.get_shard_layout(&next_epoch_id)?;
-        let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
-        let prev_hash = *self.get_block_header(sync_hash)?.prev_hash();
-        let state_root = *self.get_chunk_extra(&prev_hash, &shard_uid)?.state_root();
-        assert_ne!(shard_layout, next_epoch_shard_layout);
-
-        state_split_scheduler(StateSplitRequest {
-            runtime: Arc::clone(&self.runtime_adapter),
-            sync_hash: *sync_hash,
-            shard_id,
-            shard_uid,
-            state_root: state_root,
-            next_epoch_shard_layout,
-        });
 
-        Ok(())
+        let head_block_hash = if !allow_non_final {
+            if finality != Finality::Final {
+                return Err(ErrorKind::NotFinalYet {
+                    block_hash: *block_hash,
+                    current_final_height: last_final_height,
+                }
+                .into());
+            }
+            self.check_block_final_and_canonical(block_hash)?;
+            self.check_block_final_and_canonical(light_client_head)?;
+            *light_client_head
+        } else {
+            self.is_on_current_chain(&block_header)?;
+            let covers_block = match self.get_block_header(light_client_head) {
+                Ok(header) => {
+                    let header = header.clone();
+                    header.height() >= block_header.height()
+                        && self.is_on_current_chain(&header).is_ok()
+                }
+                Err(_) => false,
+            };
+            if covers_block {
+                *light_client_head
+            } else {
+                self.header_head()?.last_block_hash
+            }
+        };
+        let proof = self.get_block_proof(block_hash, &head_block_hash)?;
+        Ok(OutcomeBlockProof { head_block_hash, proof, finality })
     }
 
-    pub fn build_state_for_split_shards_postprocessing(
+    /// Assembles the proof of the segment of the canonical chain from `from_hash` to `to_hash`,
+    /// see `ChainSegmentProof`. The last block of the segment has to be before the last final
+    /// block, otherwise `ErrorKind::NotFinalYet` is returned.
+    pub fn export_chain_segment_proof(
         &mut self,
-        sync_hash: &CryptoHash,
-        state_roots: Result<HashMap<ShardUId, StateRoot>, Error>,
-    ) -> Result<(), Error> {
-        let prev_hash = *self.get_block_header(sync_hash)?.prev_hash();
-        let mut chain_update = self.chain_update();
-        for (shard_uid, state_root) in state_roots? {
-            // here we store the state roots in chunk_extra in the database for later use
-            let chunk_extra = ChunkExtra::new_with_only_state_root(&state_root);
-            chain_update.chain_store_update.save_chunk_extra(&prev_hash, &shard_uid, chunk_extra);
-            debug!(target:"chain", "Finish building split state for shard {:?} {:?} {:?} ", shard_uid, prev_hash, state_root);
+        from_hash: &CryptoHash,
+        to_hash: &CryptoHash,
+    ) -> Result<ChainSegmentProof, Error> {
+        let from_header = self.get_block_header(from_hash)?.clone();
+        let to_header = self.get_block_header(to_hash)?.clone();
+        self.is_on_current_chain(&from_header)?;
+        self.is_on_current_chain(&to_header)?;
+        if from_header.height() > to_header.height() {
+            return Err(ErrorKind::Other(format!(
+                "block {} is ahead of block {}",
+                from_hash, to_hash
+            ))
+            .into());
         }
-        chain_update.commit()
-    }
-
-    pub fn clear_downloaded_parts(
-        &mut self,
-        shard_id: ShardId,
-        sync_hash: CryptoHash,
-        num_parts: u64,
-    ) -> Result<(), Error> {
-        let mut chain_store_update = self.mut_store().store_update();
-        chain_store_update.gc_col_state_parts(sync_hash, shard_id, num_parts)?;
-        Ok(chain_store_update.commit()?)
-    }
 
-    pub fn catchup_blocks_step(
-        &mut self,
-        me: &Option<AccountId>,
-        sync_hash: &CryptoHash,
-        blocks_catch_up_state: &mut BlocksCatchUpState,
-        block_catch_up_scheduler: &dyn Fn(BlockCatchUpRequest),
-    ) -> Result<(), Error> {
-        debug!(target:"catchup", "catch up blocks: pending blocks: {:?}, processed {:?}, scheduled: {:?}, done: {:?}",
-               blocks_catch_up_state.pending_blocks, blocks_catch_up_state.processed_blocks.keys().collect::<Vec<_>>(),
-               blocks_catch_up_state.scheduled_blocks.keys().collect::<Vec<_>>(), blocks_catch_up_state.done_blocks.len());
-        for (queued_block, (saved_store_update, results)) in
-            blocks_catch_up_state.processed_blocks.drain()
-        {
-            match self.block_catch_up_postprocess(&queued_block, results, saved_store_update) {
-                Ok(_) => {
-                    let mut saw_one = false;
-                    for next_block_hash in self.store.get_blocks_to_catchup(&queued_block)?.clone()
-                    {
-                        saw_one = true;
-                        blocks_catch_up_state.pending_blocks.push(next_block_hash);
-                    }
-                    if saw_one {
-                        assert_eq!(
-                            self.runtime_adapter.get_epoch_id_from_prev_block(&queued_block)?,
-                            blocks_catch_up_state.epoch_id
-                        );
-                    }
-                    blocks_catch_up_state.done_blocks.push(queued_block);
-                }
-                Err(_) => {
-                    error!("Error processing block during catch up, retrying");
-                    blocks_catch_up_state.pending_blocks.push(queued_block);
-                }
+        let head_header = self.head_header()?.clone();
+        let head = Chain::create_light_client_block(
+            &head_header,
+            &*self.runtime_adapter,
+            &mut self.store,
+        )?;
+        if to_header.height() >= head.inner_lite.height {
+            return Err(ErrorKind::NotFinalYet {
+                block_hash: *to_hash,
+                current_final_height: head.inner_lite.height,
             }
+            .into());
         }
+        let last_block_proof = self.get_block_proof(to_hash, &light_client_block_hash(&head))?;
 
-        for pending_block in blocks_catch_up_state.pending_blocks.drain(..) {
-            let block = self.store.get_block(&pending_block)?.clone();
-            let prev_block = self.store.get_block(block.header().prev_hash())?.clone();
+        let mut headers = vec![to_header];
+        while headers.last().unwrap().hash() != from_hash {
+            let prev_hash = *headers.last().unwrap().prev_hash();
+            headers.push(self.get_block_header(&prev_hash)?.clone());
+        }
+        headers.reverse();
 
-            let mut chain_update = self.chain_update();
-            let work = chain_update.apply_chunks_preprocessing(
-                me,
-                &block,
-                &prev_block,
-                ApplyChunksMode::CatchingUp,
-            )?;
-            blocks_catch_up_state
-                .scheduled_blocks
-                .insert(pending_block, chain_update.into_saved_store_update());
-            block_catch_up_scheduler(BlockCatchUpRequest {
-                sync_hash: *sync_hash,
-                block_hash: pending_block,
-                work,
-            });
+        let block_producers = get_epoch_block_producers_view(
+            from_header.epoch_id(),
+            from_header.prev_hash(),
+            &*self.runtime_adapter,
+        )?;
+        let mut epoch_light_client_blocks = vec![];
+        let mut epoch_id = from_header.epoch_id().clone();
+        while epoch_id.0 != head.inner_lite.epoch_id {
+            let light_client_block = self.get_light_client_block_at_epoch(&epoch_id, true)?;
+            if light_client_block.inner_lite.epoch_id != epoch_id.0 {
+                return Err(ErrorKind::Other(format!(
+                    "light client block of epoch {:?} is in another epoch",
+                    epoch_id
+                ))
+                .into());
+            }
+            epoch_id = EpochId(light_client_block.inner_lite.next_epoch_id);
+            epoch_light_client_blocks.push(light_client_block);
         }
 
-        Ok(())
+        Ok(ChainSegmentProof {
+            headers,
+            block_producers,
+            epoch_light_client_blocks,
+            head,
+            last_block_proof,
+        })
     }
+}
 
-    fn block_catch_up_postprocess(
-        &mut self,
-        block_hash: &CryptoHash,
-        results: Vec<Result<ApplyChunkResult, Error>>,
-        saved_store_update: SavedStoreUpdate,
-    ) -> Result<(), Error> {
-        let block = self.store.get_block(block_hash)?.clone();
-        let prev_block = self.store.get_block(block.header().prev_hash())?.clone();
-        let mut chain_update = self.chain_update_from_save_store_update(saved_store_update);
-        chain_update.apply_chunk_postprocessing(&block, &prev_block, results)?;
-        chain_update.commit()?;
-        Ok(())
+/// Various chain getters.
+impl Chain {
+    /// Gets chain head.
+    #[inline]
+    pub fn head(&self) -> Result<Tip, Error> {
+        self.store.head()
     }
 
-    /// Apply transactions in chunks for the next epoch in blocks that were blocked on the state sync
-    pub fn finish_catchup_blocks(
-        &mut self,
-        me: &Option<AccountId>,
-        epoch_first_block: &CryptoHash,
-        block_accepted: &mut dyn FnMut(AcceptedBlock),
-        block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
-        orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
-        on_challenge: &mut dyn FnMut(ChallengeBody),
-        affected_blocks: &Vec<CryptoHash>,
-    ) -> Result<(), Error> {
-        debug!(
-            "Finishing catching up blocks after syncing pre {:?}, me: {:?}",
-            epoch_first_block, me
-        );
-
-        let first_block = self.store.get_block(epoch_first_block)?.clone();
-
-        let mut chain_store_update = ChainStoreUpdate::new(&mut self.store);
+    /// Gets chain tail height
+    #[inline]
+    pub fn tail(&self) -> Result<BlockHeight, Error> {
+        self.store.tail()
+    }
 
-        // `blocks_to_catchup` consists of pairs (`prev_hash`, `hash`). For the block that precedes
-        // `epoch_first_block` we should only remove the pair with hash = epoch_first_block, while
-        // for all the blocks in the queue we can remove all the pairs that have them as `prev_hash`
-        // since we processed all the blocks built on top of them above during the BFS
-        chain_store_update
-            .remove_block_to_catchup(*first_block.header().prev_hash(), *epoch_first_block);
+    /// Gets chain header head.
+    #[inline]
+    pub fn header_head(&self) -> Result<Tip, Error> {
+        self.store.header_head()
+    }
 
-        for block_hash in affected_blocks {
-            debug!(target: "chain", "Catching up: removing prev={:?} from the queue. I'm {:?}", block_hash, me);
-            chain_store_update.remove_prev_block_to_catchup(*block_hash);
-        }
-        chain_store_update.remove_state_dl_info(*epoch_first_block);
+    /// Header of the block at the head of the block chain (not the same thing as header_head).
+    #[inline]
+    pub fn head_header(&mut self) -> Result<&BlockHeader, Error> {
+        self.store.head_header()
+    }
 
-        chain_store_update.commit()?;
+    /// Get final head of the chain.
+    #[inline]
+    pub fn final_head(&self) -> Result<Tip, Error> {
+        self.store.final_head()
+    }
 
-        for hash in affected_blocks.iter() {
-            self.check_orphans(
-                me,
-                *hash,
-                block_accepted,
-                block_misses_chunks,
-                orphan_misses_chunks,
-                on_challenge,
-            );
-        }
+    /// Gets a block by hash.
+    #[inline]
+    pub fn get_block(&mut self, hash: &CryptoHash) -> Result<&Block, Error> {
+        self.store.get_block(hash)
+    }
 
-        Ok(())
+    /// Gets a chunk from hash.
+    #[inline]
+    pub fn get_chunk(&mut self, chunk_hash: &ChunkHash) -> Result<&ShardChunk, Error> {
+        self.store.get_chunk(chunk_hash)
     }
 
-    pub fn get_transaction_execution_result(
+    /// Gets an erasure coded part of a chunk stored with the partial chunk, together with its
+    /// merkle proof and the chunk header. Only the parts the node owned or tracked are stored.
+    pub fn get_partial_chunk_part(
         &mut self,
-        id: &CryptoHash,
-    ) -> Result<Vec<ExecutionOutcomeWithIdView>, Error> {
-        Ok(self.store.get_outcomes_by_id(id)?.into_iter().map(Into::into).collect())
+        chunk_hash: &ChunkHash,
+        part_ord: u64,
+    ) -> Result<PartialChunkPartView, Error> {
+        let partial_chunk = self.store.get_partial_chunk(chunk_hash)?;
+        let part = match partial_chunk.parts().iter().find(|part| part.part_ord == part_ord) {
+            Some(part) => part,
+            None => {
+                let key = format!("PARTIAL CHUNK PART: {:?} {}", chunk_hash, part_ord);
+                return Err(ErrorKind::DBNotFoundErr(key).into());
+            }
+        };
+        Ok(PartialChunkPartView {
+            header: partial_chunk.cloned_header().into(),
+            part_ord,
+            part: part.part.to_vec(),
+            merkle_proof: part.merkle_proof.clone(),
+        })
     }
 
-    fn get_recursive_transaction_results(
+    /// Gets a chunk from header.
+    #[inline]
+    pub fn get_chunk_clone_from_header(
         &mut self,
-        id: &CryptoHash,
-    ) -> Result<Vec<ExecutionOutcomeWithIdView>, Error> {
-        let outcome: ExecutionOutcomeWithIdView = self.get_execution_outcome(id)?.into();
-        let receipt_ids = outcome.outcome.receipt_ids.clone();
-        let mut results = vec![outcome];
-        for receipt_id in &receipt_ids {
-            results.extend(self.get_recursive_transaction_results(receipt_id)?);
-        }
-        Ok(results)
+        header: &ShardChunkHeader,
+    ) -> Result<ShardChunk, Error> {
+        self.store.get_chunk_clone_from_header(header)
     }
 
-    pub fn get_final_transaction_result(
-        &mut self,
-        transaction_hash: &CryptoHash,
-    ) -> Result<FinalExecutionOutcomeView, Error> {
-        let mut outcomes = self.get_recursive_transaction_results(transaction_hash)?;
-        let mut looking_for_id = (*transaction_hash).into();
-        let num_outcomes = outcomes.len();
-        let status = outcomes
-            .iter()
-            .find_map(|outcome_with_id| {
-                if outcome_with_id.id == looking_for_id {
-                    match &outcome_with_id.outcome.status {
-                        ExecutionStatusView::Unknown if num_outcomes == 1 => {
-                            Some(FinalExecutionStatus::NotStarted)
-                        }
-                        ExecutionStatusView::Unknown => Some(FinalExecutionStatus::Started),
-                        ExecutionStatusView::Failure(e) => {
-                            Some(FinalExecutionStatus::Failure(e.clone()))
-                        }
-                        ExecutionStatusView::SuccessValue(v) => {
-                            Some(FinalExecutionStatus::SuccessValue(v.clone()))
-                        }
-                        ExecutionStatusView::SuccessReceiptId(id) => {
-                            looking_for_id = *id;
-                            None
-                        }
-                    }
-                } else {
-                    None
-                }
-            })
-            .expect("results should resolve to a final outcome");
-        let receipts_outcome = outcomes.split_off(1);
-        let transaction: SignedTransactionView = self
-            .store
-            .get_transaction(transaction_hash)?
-            .ok_or_else(|| {
-                ErrorKind::DBNotFoundErr(format!("Transaction {} is not found", transaction_hash))
-            })?
-            .clone()
-            .into();
-        let transaction_outcome = outcomes.pop().unwrap();
-        Ok(FinalExecutionOutcomeView { status, transaction, transaction_outcome, receipts_outcome })
+    /// Gets a block from the current chain by height.
+    #[inline]
+    pub fn get_block_by_height(&mut self, height: BlockHeight) -> Result<&Block, Error> {
+        let hash = self.store.get_block_hash_by_height(height)?;
+        self.store.get_block(&hash)
     }
 
-    pub fn get_final_transaction_result_with_receipt(
+    /// Gets the chunk of the shard included in the block at the given height of the current chain.
+    /// `shard_id` is a shard of the layout of the head's epoch, in blocks of earlier epochs with
+    /// another shard layout the shard it was split from is looked up. Fails with
+    /// `ChunkNotIncluded` if the block carries over an older chunk of the shard.
+    pub fn get_chunk_by_height_and_shard(
         &mut self,
-        final_outcome: FinalExecutionOutcomeView,
-    ) -> Result<FinalExecutionOutcomeWithReceiptView, Error> {
-        let receipt_id_from_transaction =
-            final_outcome.transaction_outcome.outcome.receipt_ids.get(0).cloned();
-        let is_local_receipt =
-            final_outcome.transaction.signer_id == final_outcome.transaction.receiver_id;
-
-        let receipts = final_outcome
-            .receipts_outcome
-            .iter()
-            .filter_map(|outcome| {
-                if Some(outcome.id) == receipt_id_from_transaction && is_local_receipt {
-                    None
-                } else {
-                    Some(self.store.get_receipt(&outcome.id).and_then(|r| {
-                        r.cloned().map(Into::into).ok_or_else(|| {
-                            ErrorKind::DBNotFoundErr(format!("Receipt {} is not found", outcome.id))
-                                .into()
-                        })
-                    }))
-                }
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        height: BlockHeight,
+        shard_id: ShardId,
+    ) -> Result<ShardChunk, Error> {
+        let block_hash = self.store.get_block_hash_by_height(height)?;
+        let block_shard_id = self.shard_id_at_block(shard_id, &block_hash)?;
+        let chunk_header = self.store.get_block(&block_hash)?.chunk_for_shard(block_shard_id)?;
+        if chunk_header.height_included() != height {
+            return Err(ErrorKind::ChunkNotIncluded {
+                height,
+                shard_id,
+                included_height: chunk_header.height_included(),
+            }
+            .into());
+        }
+        self.store.get_chunk_clone_from_header(&chunk_header)
+    }
 
-        Ok(FinalExecutionOutcomeWithReceiptView { final_outcome, receipts })
+    /// Maps a shard of the layout of the head's epoch to the shard it was split from in the epoch
+    /// of the given block of the current chain. The chain is walked back one epoch at a time and
+    /// only as long as the shard layout differs from the one of the block's epoch, since layouts
+    /// never change back.
+    fn shard_id_at_block(
+        &mut self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+    ) -> Result<ShardId, Error> {
+        let block_header = self.get_block_header(block_hash)?;
+        let (block_height, block_epoch_id) =
+            (block_header.height(), block_header.epoch_id().clone());
+        let block_shard_layout = self.runtime_adapter.get_shard_layout(&block_epoch_id)?;
+        let mut header = self.head_header()?.clone();
+        let mut shard_layout = self.runtime_adapter.get_shard_layout(header.epoch_id())?;
+        let mut shard_id = shard_id;
+        while shard_layout != block_shard_layout && header.height() > block_height {
+            // The next epoch id of a block is the hash of the last block of the previous epoch.
+            header = self.get_block_header(&header.next_epoch_id().0)?.clone();
+            let prev_shard_layout = self.runtime_adapter.get_shard_layout(header.epoch_id())?;
+            if prev_shard_layout != shard_layout {
+                shard_id = shard_layout.get_parent_shard_id(shard_id)?;
+            }
+            shard_layout = prev_shard_layout;
+        }
+        Ok(shard_id)
     }
 
-    /// Find a validator to forward transactions to
-    pub fn find_chunk_producer_for_forwarding(
+    /// Iterates over the blocks of the current chain from `from_height` to `to_height`
+    /// inclusive, skipping the heights without a block and stopping at the head. The head is
+    /// taken when the iterator is created, the blocks accepted afterwards are never yielded.
+    /// Fails with `BlockHeightBelowTail` if `from_height` is already garbage collected.
+    pub fn iter_canonical_blocks(
         &self,
-        epoch_id: &EpochId,
-        shard_id: ShardId,
-        horizon: BlockHeight,
-    ) -> Result<AccountId, Error> {
-        let head = self.head()?;
-        let target_height = head.height + horizon - 1;
-        self.runtime_adapter.get_chunk_producer(epoch_id, target_height, shard_id)
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+    ) -> impl Iterator<Item = Result<Block, Error>> {
+        CanonicalBlocksIter::new(&self.store, from_height, to_height)
     }
 
-    /// Find a validator that is responsible for a given shard to forward requests to
-    pub fn find_validator_for_forwarding(&self, shard_id: ShardId) -> Result<AccountId, Error> {
-        let head = self.head()?;
-        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
-        self.find_chunk_producer_for_forwarding(&epoch_id, shard_id, TX_ROUTING_HEIGHT_HORIZON)
+    /// Gets block hash from the current chain by height.
+    #[inline]
+    pub fn get_block_hash_by_height(&mut self, height: BlockHeight) -> Result<CryptoHash, Error> {
+        self.store.get_block_hash_by_height(height)
     }
 
-    pub fn check_block_final_and_canonical(
+    /// Gets a block header by hash.
+    #[inline]
+    pub fn get_block_header(&mut self, hash: &CryptoHash) -> Result<&BlockHeader, Error> {
+        self.store.get_block_header(hash)
+    }
+
+    /// Gets the header of the canonical chain block with the given ordinal. Ordinals match
+    /// `BlockHeader::block_ordinal`, i.e. they start from 1 for genesis and skipped heights do not
+    /// consume them.
+    pub fn get_block_header_by_ordinal(
         &mut self,
-        block_hash: &CryptoHash,
-    ) -> Result<(), Error> {
-        let last_final_block_hash = *self.head_header()?.last_final_block();
-        let last_final_height = self.get_block_header(&last_final_block_hash)?.height();
-        let block_header = self.get_block_header(block_hash)?.clone();
-        if block_header.height() <= last_final_height {
-            self.is_on_current_chain(&block_header)
-        } else {
-            Err(ErrorKind::Other(format!("{} not on current chain", block_hash)).into())
+        ordinal: NumBlocks,
+    ) -> Result<&BlockHeader, Error> {
+        let head = self.store.head()?;
+        let max_ordinal = self.store.get_block_merkle_tree(&head.last_block_hash)?.size() + 1;
+        if ordinal == 0 || ordinal > max_ordinal {
+            return Err(ErrorKind::BlockOrdinalOutOfRange { ordinal, max_ordinal }.into());
         }
+        // The ordinal index is keyed by the block's position in the block merkle tree.
+        let block_hash = *self.store.get_block_hash_from_ordinal(ordinal - 1)?;
+        self.store.get_block_header(&block_hash)
     }
 
-    /// Get all execution outcomes generated when the chunk are applied
-    pub fn get_block_execution_outcomes(
+    /// Describes the layout version of the block header and the optional fields it carries.
+    pub fn describe_header(&mut self, hash: &CryptoHash) -> Result<HeaderDescription, Error> {
+        Ok(self.get_block_header(hash)?.describe())
+    }
+
+    /// Returns block header from the canonical chain for given height if present.
+    #[inline]
+    pub fn get_header_by_height(&mut self, height: BlockHeight) -> Result<&BlockHeader, Error> {
+        self.store.get_header_by_height(height)
+    }
+
+    /// Returns block header from the current chain defined by `sync_hash` for given height if present.
+    #[inline]
+    pub fn get_header_on_chain_by_height(
         &mut self,
-        block_hash: &CryptoHash,
-    ) -> Result<HashMap<ShardId, Vec<ExecutionOutcomeWithIdAndProof>>, Error> {
-        let block = self.get_block(block_hash)?;
-        let chunk_headers = block.chunks().iter().cloned().collect::<Vec<_>>();
+        sync_hash: &CryptoHash,
+        height: BlockHeight,
+    ) -> Result<&BlockHeader, Error> {
+        self.store.get_header_on_chain_by_height(sync_hash, height)
+    }
 
-        let mut res = HashMap::new();
-        for chunk_header in chunk_headers {
-            let shard_id = chunk_header.shard_id();
-            let outcomes = self
-                .mut_store()
-                .get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?
-                .into_iter()
-                .flat_map(|id| {
-                    let mut outcomes =
-                        self.store.get_outcomes_by_id(&id).unwrap_or_else(|_| vec![]);
-                    outcomes.retain(|outcome| &outcome.block_hash == block_hash);
-                    outcomes
-                })
-                .collect::<Vec<_>>();
-            res.insert(shard_id, outcomes);
-        }
-        Ok(res)
+    /// Get previous block header.
+    #[inline]
+    pub fn get_previous_header(&mut self, header: &BlockHeader) -> Result<&BlockHeader, Error> {
+        self.store.get_previous_header(header)
     }
-}
 
-/// Implement block merkle proof retrieval.
-impl Chain {
-    fn combine_maybe_hashes(
-        hash1: Option<MerkleHash>,
-        hash2: Option<MerkleHash>,
-    ) -> Option<MerkleHash> {
-        match (hash1, hash2) {
-            (Some(h1), Some(h2)) => Some(combine_hash(&h1, &h2)),
-            (Some(h1), None) => Some(h1),
-            (None, Some(_)) => {
-                debug_assert!(false, "Inconsistent state in merkle proof computation: left node is None but right node exists");
-                None
-            }
-            _ => None,
-        }
+    /// Returns hash of the first available block after genesis.
+    pub fn get_earliest_block_hash(&mut self) -> Result<Option<CryptoHash>, Error> {
+        self.store.get_earliest_block_hash()
     }
 
-    fn chain_update(&mut self) -> ChainUpdate {
-        ChainUpdate::new(
-            &mut self.store,
-            self.runtime_adapter.clone(),
-            &self.orphans,
-            &self.blocks_with_missing_chunks,
-            self.epoch_length,
-            &self.block_economics_config,
-            self.doomslug_threshold_mode,
-            &self.genesis,
-            self.transaction_validity_period,
-            self.pending_states_to_patch.take(),
-        )
+    /// Check if block exists.
+    #[inline]
+    pub fn block_exists(&self, hash: &CryptoHash) -> Result<bool, Error> {
+        self.store.block_exists(hash)
     }
 
-    fn chain_update_from_save_store_update(
+    /// Get block extra that was computer after applying previous block.
+    #[inline]
+    pub fn get_block_extra(&mut self, block_hash: &CryptoHash) -> Result<&BlockExtra, Error> {
+        self.store.get_block_extra(block_hash)
+    }
+
+    /// Get chunk extra that was computed after applying chunk with given hash.
+    #[inline]
+    pub fn get_chunk_extra(
         &mut self,
-        saved_store_update: SavedStoreUpdate,
-    ) -> ChainUpdate {
-        ChainUpdate::new_from_save_store_update(
-            &mut self.store,
-            saved_store_update,
-            self.runtime_adapter.clone(),
-            &self.orphans,
-            &self.blocks_with_missing_chunks,
-            self.epoch_length,
-            &self.block_economics_config,
-            self.doomslug_threshold_mode,
-            &self.genesis,
-            self.transaction_validity_period,
-            self.pending_states_to_patch.take(),
-        )
+        block_hash: &CryptoHash,
+        shard_uid: &ShardUId,
+    ) -> Result<&ChunkExtra, Error> {
+        self.store.get_chunk_extra(block_hash, shard_uid)
     }
 
-    /// Get node at given position (index, level). If the node does not exist, return `None`.
-    fn get_merkle_tree_node(
+    /// Get destination shard id for a given receipt id.
+    #[inline]
+    pub fn get_shard_id_for_receipt_id(
         &mut self,
-        index: u64,
-        level: u64,
-        counter: u64,
-        tree_size: u64,
-        tree_nodes: &mut HashMap<(u64, u64), Option<MerkleHash>>,
-    ) -> Result<Option<MerkleHash>, Error> {
-        if let Some(hash) = tree_nodes.get(&(index, level)) {
-            Ok(*hash)
-        } else {
-            if level == 0 {
-                let maybe_hash = if index >= tree_size {
-                    None
-                } else {
-                    Some(*self.mut_store().get_block_hash_from_ordinal(index)?)
-                };
-                tree_nodes.insert((index, level), maybe_hash);
-                Ok(maybe_hash)
-            } else {
-                let cur_tree_size = (index + 1) * counter;
-                let maybe_hash = if cur_tree_size > tree_size {
-                    if index * counter <= tree_size {
-                        let left_hash = self.get_merkle_tree_node(
-                            index * 2,
-                            level - 1,
-                            counter / 2,
-                            tree_size,
-                            tree_nodes,
-                        )?;
-                        let right_hash = self.reconstruct_merkle_tree_node(
-                            index * 2 + 1,
-                            level - 1,
-                            counter / 2,
-                            tree_size,
-                            tree_nodes,
-                        )?;
-                        Self::combine_maybe_hashes(left_hash, right_hash)
-                    } else {
-                        None
-                    }
-                } else {
-                    Some(
-                        *self
-                            .mut_store()
-                            .get_block_merkle_tree_from_ordinal(cur_tree_size)?
-                            .get_path()
-                            .last()
-                            .ok_or_else(|| {
-                                ErrorKind::Other("Merkle tree node missing".to_string())
-                            })?,
-                    )
-                };
-                tree_nodes.insert((index, level), maybe_hash);
-                Ok(maybe_hash)
-            }
-        }
+        receipt_id: &CryptoHash,
+    ) -> Result<&ShardId, Error> {
+        self.store.get_shard_id_for_receipt_id(receipt_id)
     }
 
-    /// Reconstruct node at given position (index, level). If the node does not exist, return `None`.
-    fn reconstruct_merkle_tree_node(
+    /// Get next block hash for which there is a new chunk for the shard.
+    /// If sharding changes before we can find a block with a new chunk for the shard,
+    /// find the first block that contains a new chunk for any of the shards that split from the
+    /// original shard
+    pub fn get_next_block_hash_with_new_chunk(
         &mut self,
-        index: u64,
-        level: u64,
-        counter: u64,
-        tree_size: u64,
-        tree_nodes: &mut HashMap<(u64, u64), Option<MerkleHash>>,
-    ) -> Result<Option<MerkleHash>, Error> {
-        if let Some(hash) = tree_nodes.get(&(index, level)) {
-            Ok(*hash)
-        } else {
-            if level == 0 {
-                let maybe_hash = if index >= tree_size {
-                    None
-                } else {
-                    Some(*self.mut_store().get_block_hash_from_ordinal(index)?)
-                };
-                tree_nodes.insert((index, level), maybe_hash);
-                Ok(maybe_hash)
-            } else {
-                let left_hash = self.get_merkle_tree_node(
-                    index * 2,
-                    level - 1,
-                    counter / 2,
-                    tree_size,
-                    tree_nodes,
-                )?;
-                let right_hash = self.reconstruct_merkle_tree_node(
-                    index * 2 + 1,
-                    level - 1,
-                    counter / 2,
-                    tree_size,
-                    tree_nodes,
-                )?;
-                let maybe_hash = Self::combine_maybe_hashes(left_hash, right_hash);
-                tree_nodes.insert((index, level), maybe_hash);
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Option<(CryptoHash, ShardId)>, Error> {
+        let mut block_hash = *block_hash;
+        let mut epoch_id = self.get_block_header(&block_hash)?.epoch_id().clone();
+        let mut shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        // this corrects all the shard where the original shard will split to if sharding changes
+        let mut shard_ids = vec![shard_id];
 
-                Ok(maybe_hash)
+        while let Ok(next_block_hash) = self.store.get_next_block_hash(&block_hash) {
+            let next_block_hash = *next_block_hash;
+            let next_epoch_id = self.get_block_header(&next_block_hash)?.epoch_id().clone();
+            if next_epoch_id != epoch_id {
+                let next_shard_layout = self.runtime_adapter.get_shard_layout(&next_epoch_id)?;
+                if next_shard_layout != shard_layout {
+                    shard_ids = shard_ids
+                        .into_iter()
+                        .flat_map(|id| {
+                            next_shard_layout.get_split_shard_ids(id).unwrap_or_else(|| {
+                                panic!("invalid shard layout {:?} because it does not contain split shards for parent shard {}", next_shard_layout, id)
+                            })
+                        })
+                        .collect();
+
+                    shard_layout = next_shard_layout;
+                }
+                epoch_id = next_epoch_id;
+            }
+            block_hash = next_block_hash;
+
+            let block = self.get_block(&block_hash)?;
+            let chunks = block.chunks();
+            for &shard_id in shard_ids.iter() {
+                if chunks[shard_id as usize].height_included() == block.header().height() {
+                    return Ok(Some((block_hash, shard_id)));
+                }
             }
         }
+
+        Ok(None)
     }
 
-    /// Get merkle proof for block with hash `block_hash` in the merkle tree of `head_block_hash`.
-    pub fn get_block_proof(
+    /// Get previous block hash for which there is a new chunk for the shard.
+    /// If sharding changed between the blocks, the returned shard id is the id of the parent shard
+    /// in the shard layout of the returned block.
+    /// Returns `None` for the genesis block.
+    pub fn get_prev_block_hash_with_new_chunk(
         &mut self,
         block_hash: &CryptoHash,
-        head_block_hash: &CryptoHash,
-    ) -> Result<MerklePath, Error> {
-        let leaf_index = self.mut_store().get_block_merkle_tree(block_hash)?.size();
-        let tree_size = self.mut_store().get_block_merkle_tree(head_block_hash)?.size();
-        if leaf_index >= tree_size {
-            if block_hash == head_block_hash {
-                // special case if the block to prove is the same as head
-                return Ok(vec![]);
-            }
-            return Err(ErrorKind::Other(format!(
-                "block {} is ahead of head block {}",
-                block_hash, head_block_hash
+        shard_id: ShardId,
+    ) -> Result<Option<(CryptoHash, ShardId)>, Error> {
+        self.store.get_prev_block_hash_with_new_chunk(&*self.runtime_adapter, block_hash, shard_id)
+    }
+
+    /// Get the light client block recorded when the given epoch ended.
+    /// If the record is missing and `regenerate` is set, it is recomputed from the last block
+    /// of the epoch on the canonical chain and saved.
+    pub fn get_light_client_block_at_epoch(
+        &mut self,
+        epoch_id: &EpochId,
+        regenerate: bool,
+    ) -> Result<LightClientBlockView, Error> {
+        match self.store.get_epoch_light_client_block(&epoch_id.0) {
+            Ok(light_client_block) => return Ok(light_client_block.clone()),
+            Err(err) => match err.kind() {
+                ErrorKind::DBNotFoundErr(_) if regenerate => {}
+                _ => return Err(err),
+            },
+        }
+
+        let last_header = self.get_last_block_header_of_epoch(epoch_id)?;
+        if last_header.last_final_block() == &CryptoHash::default() {
+            return Err(ErrorKind::DBNotFoundErr(format!(
+                "EPOCH LIGHT CLIENT BLOCK: {}",
+                epoch_id.0
             ))
             .into());
         }
-        let mut level = 0;
-        let mut counter = 1;
-        let mut cur_index = leaf_index;
-        let mut path = vec![];
-        let mut tree_nodes = HashMap::new();
-        let mut iter = tree_size;
-        while iter > 1 {
-            if cur_index % 2 == 0 {
-                cur_index += 1
-            } else {
-                cur_index -= 1;
-            }
-            let direction = if cur_index % 2 == 0 { Direction::Left } else { Direction::Right };
-            let maybe_hash = if cur_index % 2 == 1 {
-                // node not immediately available. Needs to be reconstructed
-                self.reconstruct_merkle_tree_node(
-                    cur_index,
-                    level,
-                    counter,
-                    tree_size,
-                    &mut tree_nodes,
-                )?
-            } else {
-                self.get_merkle_tree_node(cur_index, level, counter, tree_size, &mut tree_nodes)?
-            };
-            if let Some(hash) = maybe_hash {
-                path.push(MerklePathItem { hash, direction });
+        let light_client_block = Chain::create_light_client_block(
+            &last_header,
+            &*self.runtime_adapter,
+            &mut self.store,
+        )?;
+        let mut chain_store_update = self.store.store_update();
+        chain_store_update.save_epoch_light_client_block(&epoch_id.0, light_client_block.clone());
+        chain_store_update.commit()?;
+        Ok(light_client_block)
+    }
+
+    /// Walks the canonical chain back from the head one epoch at a time to find the last block
+    /// of the given finished epoch.
+    fn get_last_block_header_of_epoch(&mut self, epoch_id: &EpochId) -> Result<BlockHeader, Error> {
+        let not_found =
+            || Error::from(ErrorKind::DBNotFoundErr(format!("EPOCH LAST BLOCK: {}", epoch_id.0)));
+        let genesis_height = self.genesis.header().height();
+        let mut header = self.head_header()?.clone();
+        if header.epoch_id() == epoch_id {
+            // The epoch hasn't finished yet.
+            return Err(not_found());
+        }
+        while header.epoch_id() != epoch_id {
+            let epoch_start_height = self.runtime_adapter.get_epoch_start_height(header.hash())?;
+            if epoch_start_height <= genesis_height {
+                return Err(not_found());
             }
-            cur_index /= 2;
-            iter = (iter + 1) / 2;
-            level += 1;
-            counter *= 2;
+            let epoch_first_header = self.get_header_by_height(epoch_start_height)?.clone();
+            header = self.get_previous_header(&epoch_first_header)?.clone();
         }
-        Ok(path)
+        Ok(header)
     }
-}
 
-/// Various chain getters.
-impl Chain {
-    /// Gets chain head.
+    /// Returns underlying ChainStore.
     #[inline]
-    pub fn head(&self) -> Result<Tip, Error> {
-        self.store.head()
+    pub fn store(&self) -> &ChainStore {
+        &self.store
     }
 
-    /// Gets chain tail height
+    /// Returns mutable ChainStore.
     #[inline]
-    pub fn tail(&self) -> Result<BlockHeight, Error> {
-        self.store.tail()
+    pub fn mut_store(&mut self) -> &mut ChainStore {
+        &mut self.store
     }
 
-    /// Gets chain header head.
+    /// Returns underlying RuntimeAdapter.
     #[inline]
-    pub fn header_head(&self) -> Result<Tip, Error> {
-        self.store.header_head()
+    pub fn runtime_adapter(&self) -> Arc<dyn RuntimeAdapter> {
+        self.runtime_adapter.clone()
     }
 
-    /// Header of the block at the head of the block chain (not the same thing as header_head).
+    /// Returns genesis block.
     #[inline]
-    pub fn head_header(&mut self) -> Result<&BlockHeader, Error> {
-        self.store.head_header()
+    pub fn genesis_block(&self) -> &Block {
+        &self.genesis
     }
 
-    /// Get final head of the chain.
+    /// Returns genesis block header.
     #[inline]
-    pub fn final_head(&self) -> Result<Tip, Error> {
-        self.store.final_head()
+    pub fn genesis(&self) -> &BlockHeader {
+        self.genesis.header()
     }
 
-    /// Gets a block by hash.
+    /// Returns number of orphans currently in the orphan pool.
     #[inline]
-    pub fn get_block(&mut self, hash: &CryptoHash) -> Result<&Block, Error> {
-        self.store.get_block(hash)
+    pub fn orphans_len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Returns number of orphans currently in the orphan pool.
+    #[inline]
+    pub fn blocks_with_missing_chunks_len(&self) -> usize {
+        self.blocks_with_missing_chunks.len()
+    }
+
+    /// Returns number of evicted orphans.
+    #[inline]
+    pub fn orphans_evicted_len(&self) -> usize {
+        self.orphans.len_evicted()
+    }
+
+    /// Returns the orphans in the orphan pool and the number of evicted orphans.
+    pub fn orphan_pool_summary(&self) -> OrphanPoolSummaryView {
+        self.orphans.summary()
+    }
+
+    /// Summarizes how quickly blocks of the last `window_epochs` epochs, including the current
+    /// one, became fully processable after being received, and updates the related metrics.
+    pub fn block_readiness_slo(
+        &mut self,
+        window_epochs: u64,
+        threshold: TimeDuration,
+    ) -> Result<SloReport, Error> {
+        let genesis_height = self.genesis.header().height();
+        let head = self.head()?;
+        let mut block_hash = head.last_block_hash;
+        let mut min_height = head.height;
+        for _ in 0..window_epochs {
+            min_height = self.runtime_adapter.get_epoch_start_height(&block_hash)?;
+            if min_height <= genesis_height {
+                break;
+            }
+            block_hash = *self.get_header_by_height(min_height)?.prev_hash();
+        }
+
+        let report = self.blocks_delay_tracker.block_readiness_report(min_height, threshold);
+        for (percentile, delay) in [("50", report.p50), ("90", report.p90), ("99", report.p99)] {
+            metrics::BLOCK_READINESS_DELAY_PERCENTILE_MS
+                .with_label_values(&[percentile])
+                .set(delay.as_millis() as i64);
+        }
+        metrics::BLOCK_READINESS_WITHIN_THRESHOLD_PERCENT
+            .set(report.percent_within_threshold as i64);
+        Ok(report)
+    }
+
+    /// Records the time to finality of every block which became final together with
+    /// `final_block_hash`, i.e. the blocks on its chain above `prev_final_height`.
+    fn record_newly_final_blocks(
+        &mut self,
+        final_block_hash: CryptoHash,
+        prev_final_height: BlockHeight,
+        timestamp: Instant,
+    ) {
+        let mut block_hash = final_block_hash;
+        while let Ok(header) = self.store.get_block_header(&block_hash) {
+            if header.height() <= prev_final_height {
+                break;
+            }
+            let prev_hash = *header.prev_hash();
+            self.blocks_delay_tracker.mark_block_final(&block_hash, timestamp);
+            block_hash = prev_hash;
+        }
+    }
+
+    /// Summarizes the time from accepting a block to the block becoming final over the recently
+    /// finalized blocks, and updates the related metrics.
+    pub fn finality_latency_stats(&self) -> FinalityLatencyStats {
+        let stats = self.blocks_delay_tracker.finality_latency_stats();
+        for (percentile, latency) in [("50", stats.p50), ("90", stats.p90), ("99", stats.p99)] {
+            metrics::BLOCK_FINALITY_LATENCY_PERCENTILE_MS
+                .with_label_values(&[percentile])
+                .set(latency.as_millis() as i64);
+        }
+        stats
+    }
+
+    /// Returns the handle which can stop block processing from other threads.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Stops accepting new blocks, waits up to `timeout` for the blocks and sync jobs in flight
+    /// to be processed and reports the chain work which is lost or has to be resumed after restart.
+    /// Every processed block is committed atomically, so nothing else needs to be flushed.
+    pub fn prepare_shutdown(&mut self, timeout: TimeDuration) -> ShutdownReport {
+        self.shutdown.request_shutdown();
+        let report = ShutdownReport {
+            drained: self.shutdown.wait_until_idle(timeout),
+            orphans: self.orphans.len(),
+            blocks_with_missing_chunks: self.blocks_with_missing_chunks.len(),
+            dropped_states_to_patch: self.pending_states_to_patch.take().is_some(),
+            pending_state_syncs: self
+                .store
+                .iterate_state_sync_infos()
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .collect(),
+        };
+        if report.is_clean() {
+            info!(target: "chain", "Chain is ready for shutdown");
+        } else {
+            warn!(target: "chain", "Chain shut down with incomplete work: {:?}", report);
+        }
+        report
     }
 
-    /// Gets a chunk from hash.
-    #[inline]
-    pub fn get_chunk(&mut self, chunk_hash: &ChunkHash) -> Result<&ShardChunk, Error> {
-        self.store.get_chunk(chunk_hash)
+    /// Sets how many orphans with the same prev block can be stored in the orphan pool.
+    pub fn set_max_orphans_per_prev_hash(&mut self, max_orphans_per_prev_hash: usize) {
+        self.orphans.set_max_orphans_per_prev_hash(max_orphans_per_prev_hash);
     }
 
-    /// Gets a chunk from header.
-    #[inline]
-    pub fn get_chunk_clone_from_header(
-        &mut self,
-        header: &ShardChunkHeader,
-    ) -> Result<ShardChunk, Error> {
-        self.store.get_chunk_clone_from_header(header)
+    /// Returns state changes which are stored for catchup but not yet applied to split states,
+    /// sorted by block hash and shard id. Reads all of them, see `split_state_backlog_exceeds`
+    /// for a cheap check of the size.
+    pub fn split_state_backlog(&mut self) -> Result<Vec<SplitStateBacklogEntry>, Error> {
+        let mut backlog = self
+            .store
+            .iterate_state_changes_for_split_states()?
+            .into_iter()
+            .map(|(block_hash, shard_id, state_changes)| SplitStateBacklogEntry {
+                block_hash,
+                shard_id,
+                num_changes: state_changes.changes.len(),
+            })
+            .collect::<Vec<_>>();
+        backlog.sort_by_key(|entry| (entry.block_hash, entry.shard_id));
+        metrics::SPLIT_STATE_BACKLOG_SIZE.set(backlog.len() as i64);
+        Ok(backlog)
     }
 
-    /// Gets a block from the current chain by height.
-    #[inline]
-    pub fn get_block_by_height(&mut self, height: BlockHeight) -> Result<&Block, Error> {
-        let hash = self.store.get_block_hash_by_height(height)?;
-        self.store.get_block(&hash)
+    /// Whether more than `threshold` block and shard pairs have state changes waiting to be
+    /// applied to split states. Reads at most `threshold + 1` of them.
+    pub fn split_state_backlog_exceeds(&self, threshold: usize) -> bool {
+        self.store.count_state_changes_for_split_states(threshold + 1) > threshold
     }
 
-    /// Gets block hash from the current chain by height.
-    #[inline]
-    pub fn get_block_hash_by_height(&mut self, height: BlockHeight) -> Result<CryptoHash, Error> {
-        self.store.get_block_hash_by_height(height)
+    /// Returns the blocks which were applied before the state for the next epoch was caught up,
+    /// with the shards whose chunks are deferred until catchup. Blocks are removed once caught up.
+    pub fn partially_applied_blocks(&mut self) -> Result<Vec<(CryptoHash, Vec<ShardId>)>, Error> {
+        self.store.get_partially_applied_blocks()
     }
 
-    /// Gets a block header by hash.
-    #[inline]
-    pub fn get_block_header(&mut self, hash: &CryptoHash) -> Result<&BlockHeader, Error> {
-        self.store.get_block_header(hash)
-    }
+    /// Collects chain conditions which usually explain why the node is not making progress.
+    pub fn sync_diagnostics(&mut self) -> Result<SyncDiagnostics, Error> {
+        let head = self.head()?;
+        let header_head = self.header_head()?;
+        let final_head = self.final_head()?;
+
+        let mut pending_catchups = vec![];
+        for (sync_hash, state_sync_info) in self.store.iterate_state_sync_infos() {
+            let mut num_blocks_to_catchup = 0;
+            let mut queue = vec![sync_hash];
+            while let Some(block_hash) = queue.pop() {
+                let blocks_to_catchup = self.store.get_blocks_to_catchup(&block_hash)?;
+                num_blocks_to_catchup += blocks_to_catchup.len();
+                queue.extend(blocks_to_catchup);
+            }
+            pending_catchups.push(CatchupDiagnostics {
+                sync_hash,
+                num_shards: state_sync_info.shards.len(),
+                num_blocks_to_catchup,
+            });
+        }
 
-    /// Returns block header from the canonical chain for given height if present.
-    #[inline]
-    pub fn get_header_by_height(&mut self, height: BlockHeight) -> Result<&BlockHeader, Error> {
-        self.store.get_header_by_height(height)
+        let mut unfinished_state_syncs = vec![];
+        for StateHeaderKey(shard_id, sync_hash) in self.store.iterate_state_header_keys()? {
+            let finished = match self.get_block_header(&sync_hash) {
+                Ok(header) => header.height() <= head.height,
+                Err(_) => false,
+            };
+            if !finished {
+                unfinished_state_syncs.push((sync_hash, shard_id));
+            }
+        }
+
+        Ok(SyncDiagnostics {
+            head_height: head.height,
+            header_head_height: header_head.height,
+            final_head_height: final_head.height,
+            num_orphans: self.orphans.len(),
+            num_orphans_evicted: self.orphans.len_evicted(),
+            num_blocks_with_missing_chunks: self.blocks_with_missing_chunks.len(),
+            pending_catchups,
+            catchup_bookkeeping_issues: self.validate_catchup_bookkeeping()?,
+            unfinished_state_syncs,
+            num_partially_applied_blocks: self.store.get_partially_applied_blocks()?.len(),
+            state_part_providers: self.state_part_provenance_stats(),
+            tail: self.store.tail()?,
+            chunk_tail: self.store.chunk_tail()?,
+            fork_tail: self.store.fork_tail()?,
+            last_gc_run: self.last_gc_run.clone(),
+            head_epoch_known: self.runtime_adapter.epoch_exists(&head.epoch_id),
+        })
     }
 
-    /// Returns block header from the current chain defined by `sync_hash` for given height if present.
-    #[inline]
-    pub fn get_header_on_chain_by_height(
+    /// Estimates how far the node is in syncing to `network_height`, the highest height reported
+    /// by the peers, and how long it will take to finish.
+    pub fn estimate_sync_progress(
         &mut self,
-        sync_hash: &CryptoHash,
-        height: BlockHeight,
-    ) -> Result<&BlockHeader, Error> {
-        self.store.get_header_on_chain_by_height(sync_hash, height)
-    }
+        network_height: BlockHeight,
+    ) -> Result<SyncProgressEstimate, Error> {
+        let head = self.head()?;
+        let header_head = self.header_head()?;
 
-    /// Get previous block header.
-    #[inline]
-    pub fn get_previous_header(&mut self, header: &BlockHeader) -> Result<&BlockHeader, Error> {
-        self.store.get_previous_header(header)
-    }
+        let mut state_parts_downloaded = 0;
+        let mut state_parts_total = 0;
+        for StateHeaderKey(shard_id, sync_hash) in self.store.iterate_state_header_keys()? {
+            let finished = match self.get_block_header(&sync_hash) {
+                Ok(header) => header.height() <= head.height,
+                Err(_) => false,
+            };
+            if finished {
+                continue;
+            }
+            if let Some((downloaded, total)) = self.state_parts_progress(shard_id, sync_hash)? {
+                state_parts_downloaded += downloaded;
+                state_parts_total += total;
+            }
+        }
 
-    /// Returns hash of the first available block after genesis.
-    pub fn get_earliest_block_hash(&mut self) -> Result<Option<CryptoHash>, Error> {
-        self.store.get_earliest_block_hash()
+        Ok(SyncProgressInputs {
+            genesis_height: self.store.get_genesis_height(),
+            head_height: head.height,
+            header_head_height: header_head.height,
+            network_height,
+            headers_per_second: self.header_sync_expected_height_per_second,
+            blocks_applied_per_second: self.blocks_delay_tracker.blocks_applied_per_second(),
+            state_parts_downloaded,
+            state_parts_total,
+        }
+        .estimate())
     }
 
-    /// Check if block exists.
-    #[inline]
-    pub fn block_exists(&self, hash: &CryptoHash) -> Result<bool, Error> {
-        self.store.block_exists(hash)
+    /// Returns the number of parts of the state of the shard for `sync_hash` downloaded so far
+    /// and the total number of parts, or `None` if the state header isn't downloaded yet.
+    /// Storage is only checked for the parts on the first call for the shard and sync hash.
+    pub fn state_parts_progress(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<Option<(u64, u64)>, Error> {
+        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
+        let state_header: ShardStateSyncResponseHeader =
+            match self.store.store().get_ser(ColStateHeaders, &key)? {
+                Some(state_header) => state_header,
+                None => {
+                    // The parts are garbage collected together with the header.
+                    self.stored_state_parts.remove(&(shard_id, sync_hash));
+                    return Ok(None);
+                }
+            };
+        let num_parts = get_num_state_parts(state_header.state_root_node().memory_usage);
+        if !self.stored_state_parts.contains_key(&(shard_id, sync_hash)) {
+            let mut parts = HashSet::new();
+            for part_id in 0..num_parts {
+                let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+                if self.store.store().exists(ColStateParts, &key)? {
+                    parts.insert(part_id);
+                }
+            }
+            self.stored_state_parts.insert((shard_id, sync_hash), parts);
+        }
+        let downloaded = self.stored_state_parts[&(shard_id, sync_hash)].len() as u64;
+        Ok(Some((downloaded, num_parts)))
+    }
+
+    /// Reports the state downloads started for the catchup of the next epoch, see
+    /// `start_downloading_state`, ordered by the height of their sync block.
+    pub fn state_download_status(&mut self) -> Result<Vec<EpochStateDownloadStatus>, Error> {
+        let mut statuses = vec![];
+        for (sync_hash, state_sync_info) in self.store.iterate_state_sync_infos() {
+            let sync_header = self.get_block_header(&sync_hash)?.clone();
+            let prev_epoch_id = self.get_block_header(sync_header.prev_hash())?.epoch_id().clone();
+            let mut shards = vec![];
+            for ShardInfo(shard_id, _) in state_sync_info.shards {
+                let parts_progress = self.state_parts_progress(shard_id, sync_hash)?;
+                let finalize_height =
+                    self.store.get_state_sync_finalize_progress(shard_id, &sync_hash)?;
+                // Finalization builds the state of the shard up to the block before the sync
+                // block, and forgets its progress once done.
+                let finalized = finalize_height.is_none() && {
+                    let shard_uid =
+                        self.runtime_adapter.shard_id_to_uid(shard_id, &prev_epoch_id)?;
+                    match self.get_chunk_extra(sync_header.prev_hash(), &shard_uid) {
+                        Ok(_) => true,
+                        Err(err) => match err.kind() {
+                            ErrorKind::DBNotFoundErr(_) => false,
+                            _ => return Err(err),
+                        },
+                    }
+                };
+                shards.push(ShardStateDownloadStatus {
+                    shard_id,
+                    header_stored: parts_progress.is_some(),
+                    parts_downloaded: parts_progress.map_or(0, |(downloaded, _)| downloaded),
+                    parts_total: parts_progress.map_or(0, |(_, total)| total),
+                    finalize_height,
+                    finalized,
+                });
+            }
+            statuses.push((
+                sync_header.height(),
+                EpochStateDownloadStatus {
+                    epoch_id: sync_header.epoch_id().clone(),
+                    sync_hash,
+                    shards,
+                },
+            ));
+        }
+        statuses.sort_by_key(|(height, _)| *height);
+        Ok(statuses.into_iter().map(|(_, status)| status).collect())
     }
 
-    /// Get block extra that was computer after applying previous block.
-    #[inline]
-    pub fn get_block_extra(&mut self, block_hash: &CryptoHash) -> Result<&BlockExtra, Error> {
-        self.store.get_block_extra(block_hash)
+    /// Cross-checks the records of blocks waiting to be caught up against the stored blocks and
+    /// state sync infos. The first queued block of an epoch must have a state sync info, and
+    /// every block queued after it must be stored, built on top of the block it is queued after
+    /// and belong to the same epoch.
+    pub fn validate_catchup_bookkeeping(&mut self) -> Result<Vec<CatchupBookkeepingIssue>, Error> {
+        let records = self.store.get_all_blocks_to_catchup()?;
+        let sync_hashes = self
+            .store
+            .iterate_state_sync_infos()
+            .into_iter()
+            .map(|(sync_hash, _)| sync_hash)
+            .collect::<Vec<_>>();
+        let queued =
+            records.iter().flat_map(|(_, hashes)| hashes.iter().cloned()).collect::<HashSet<_>>();
+
+        let mut issues = vec![];
+        for (prev_hash, hashes) in records {
+            for hash in hashes {
+                if !self.store.block_exists(&hash)? {
+                    issues.push(CatchupBookkeepingIssue::MissingBlock { prev_hash, hash });
+                    continue;
+                }
+                let header = self.get_block_header(&hash)?.clone();
+                if *header.prev_hash() != prev_hash {
+                    issues.push(CatchupBookkeepingIssue::PrevHashMismatch { prev_hash, hash });
+                } else if sync_hashes.contains(&hash) {
+                    // The first block of the epoch, queued after the last block of the
+                    // previous one.
+                } else if !queued.contains(&prev_hash) {
+                    issues.push(CatchupBookkeepingIssue::MissingStateSyncInfo { hash });
+                } else if self.get_block_header(&prev_hash)?.epoch_id() != header.epoch_id() {
+                    issues.push(CatchupBookkeepingIssue::EpochMismatch { prev_hash, hash });
+                }
+            }
+        }
+        for sync_hash in sync_hashes {
+            if !queued.contains(&sync_hash) {
+                issues.push(CatchupBookkeepingIssue::UnqueuedStateSyncInfo { sync_hash });
+            }
+        }
+        Ok(issues)
     }
 
-    /// Get chunk extra that was computed after applying chunk with given hash.
-    #[inline]
-    pub fn get_chunk_extra(
-        &mut self,
-        block_hash: &CryptoHash,
-        shard_uid: &ShardUId,
-    ) -> Result<&ChunkExtra, Error> {
-        self.store.get_chunk_extra(block_hash, shard_uid)
+    /// Reports the earliest height from which every class of data is still available.
+    pub fn data_availability_summary(&mut self) -> Result<DataAvailability, Error> {
+        let genesis_height = self.genesis.header().height();
+        let tail = self.store.tail()?;
+        let chunk_tail = self.store.chunk_tail()?;
+        let earliest_block_height = match self.store.get_earliest_block_hash()? {
+            Some(block_hash) => self.get_block_header(&block_hash)?.height(),
+            None => tail,
+        };
+        Ok(DataAvailability {
+            // Block headers are never garbage collected.
+            headers: genesis_height,
+            blocks: earliest_block_height,
+            // Full chunks are removed together with the blocks which include them, so
+            // `chunk_tail` is never above `tail` on a regular node. Archival nodes only drop
+            // partial chunks and advance `chunk_tail` without moving `tail`.
+            chunks: std::cmp::min(chunk_tail, tail),
+            partial_chunks: chunk_tail,
+            // Outcomes and trie changes are garbage collected with their blocks.
+            execution_outcomes: earliest_block_height,
+            state: earliest_block_height,
+        })
     }
 
-    /// Get destination shard id for a given receipt id.
-    #[inline]
-    pub fn get_shard_id_for_receipt_id(
+    /// Returns all blocks known at `height`, canonical one first and the rest ordered by hash.
+    pub fn blocks_at_height_info(
         &mut self,
-        receipt_id: &CryptoHash,
-    ) -> Result<&ShardId, Error> {
-        self.store.get_shard_id_for_receipt_id(receipt_id)
+        height: BlockHeight,
+    ) -> Result<Vec<BlockAtHeightInfo>, Error> {
+        let blocks_per_epoch = match self.store.get_all_block_hashes_by_height(height) {
+            Ok(blocks_per_epoch) => blocks_per_epoch.clone(),
+            Err(e) => match e.kind() {
+                ErrorKind::DBNotFoundErr(_) => return Ok(vec![]),
+                _ => return Err(e),
+            },
+        };
+        let canonical_hash = self.store.get_block_hash_by_height(height).ok();
+
+        let mut result = vec![];
+        for (epoch_id, hashes) in blocks_per_epoch {
+            for hash in hashes {
+                let prev_hash = self.get_block_header(&hash).ok().map(|header| *header.prev_hash());
+                result.push(BlockAtHeightInfo {
+                    hash,
+                    prev_hash,
+                    epoch_id: epoch_id.clone(),
+                    producer: self.runtime_adapter.get_block_producer(&epoch_id, height).ok(),
+                    is_canonical: canonical_hash == Some(hash),
+                    is_challenged: self.store.is_block_challenged(&hash)?,
+                    has_body: self.store.block_exists(&hash)?,
+                });
+            }
+        }
+        result.sort_by_key(|info| (!info.is_canonical, info.hash));
+        Ok(result)
     }
 
-    /// Get next block hash for which there is a new chunk for the shard.
-    /// If sharding changes before we can find a block with a new chunk for the shard,
-    /// find the first block that contains a new chunk for any of the shards that split from the
-    /// original shard
-    pub fn get_next_block_hash_with_new_chunk(
+    /// Asks the runtime to prefetch the state of the accounts touched by the new chunks of
+    /// `block` in the shards we track: the signers of the transactions and the receivers of the
+    /// incoming receipts. Should be called once the chunks of the block are available and before
+    /// the block is processed.
+    pub fn prefetch_for_block(
         &mut self,
-        block_hash: &CryptoHash,
-        shard_id: ShardId,
-    ) -> Result<Option<(CryptoHash, ShardId)>, Error> {
-        let mut block_hash = *block_hash;
-        let mut epoch_id = self.get_block_header(&block_hash)?.epoch_id().clone();
-        let mut shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
-        // this corrects all the shard where the original shard will split to if sharding changes
-        let mut shard_ids = vec![shard_id];
+        me: &Option<AccountId>,
+        block: &Block,
+    ) -> Result<(), Error> {
+        let prev_hash = block.header().prev_hash();
+        if !self.block_exists(prev_hash)? {
+            // Orphans are prefetched once they can be processed.
+            return Ok(());
+        }
+        let height = block.header().height();
+        let mut accounts_by_shard: HashMap<ShardId, BTreeSet<AccountId>> = HashMap::new();
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            let chunk_hash = chunk_header.chunk_hash();
+            if let Ok(chunk) = self.store.get_chunk(&chunk_hash) {
+                accounts_by_shard
+                    .entry(chunk_header.shard_id())
+                    .or_default()
+                    .extend(chunk.transactions().iter().map(|tx| tx.transaction.signer_id.clone()));
+            }
+            if let Ok(partial_chunk) = self.store.get_partial_chunk(&chunk_hash) {
+                for ReceiptProof(receipts, shard_proof) in partial_chunk.receipts().iter() {
+                    accounts_by_shard
+                        .entry(shard_proof.to_shard_id)
+                        .or_default()
+                        .extend(receipts.iter().map(|receipt| receipt.receiver_id.clone()));
+                }
+            }
+        }
+        let chunks = block.chunks();
+        for (shard_id, accounts) in accounts_by_shard {
+            let chunk_header = match chunks.get(shard_id as usize) {
+                Some(chunk_header) if chunk_header.height_included() == height => chunk_header,
+                _ => continue,
+            };
+            if accounts.is_empty()
+                || !self.runtime_adapter.cares_about_shard(me.as_ref(), prev_hash, shard_id, true)
+            {
+                continue;
+            }
+            let shard_uid =
+                self.runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+            self.runtime_adapter.prefetch_state_keys(
+                shard_uid,
+                chunk_header.prev_state_root(),
+                accounts.into_iter().collect(),
+            )?;
+        }
+        Ok(())
+    }
 
-        while let Ok(next_block_hash) = self.store.get_next_block_hash(&block_hash) {
-            let next_block_hash = *next_block_hash;
-            let next_epoch_id = self.get_block_header(&next_block_hash)?.epoch_id().clone();
-            if next_epoch_id != epoch_id {
-                let next_shard_layout = self.runtime_adapter.get_shard_layout(&next_epoch_id)?;
-                if next_shard_layout != shard_layout {
-                    shard_ids = shard_ids
-                        .into_iter()
-                        .flat_map(|id| {
-                            next_shard_layout.get_split_shard_ids(id).unwrap_or_else(|| {
-                                panic!("invalid shard layout {:?} because it does not contain split shards for parent shard {}", next_shard_layout, id)
-                            })
-                        })
-                        .collect();
+    /// Counts which of the expected approvers of `header` have their approvals included in it.
+    fn record_approval_participation(&mut self, header: &BlockHeader) {
+        let approvers = match self
+            .runtime_adapter
+            .get_epoch_block_approvers_ordered(header.prev_hash())
+        {
+            Ok(approvers) => approvers,
+            Err(err) => {
+                debug!(target: "chain", "Failed to get approvers of block {}: {}", header.hash(), err);
+                return;
+            }
+        };
+        let epoch_id = header.epoch_id();
+        let position = match self.approval_participation.iter().position(|(id, _)| id == epoch_id) {
+            Some(position) => position,
+            None => {
+                self.approval_participation.push_back((epoch_id.clone(), HashMap::new()));
+                if self.approval_participation.len() > NUM_EPOCHS_TO_KEEP_APPROVAL_PARTICIPATION {
+                    self.approval_participation.pop_front();
+                }
+                // The metrics only cover the validators of the latest epoch.
+                metrics::VALIDATORS_APPROVALS_INCLUDED.reset();
+                metrics::VALIDATORS_APPROVALS_MISSING.reset();
+                self.approval_participation.len() - 1
+            }
+        };
+        let is_latest_epoch = position + 1 == self.approval_participation.len();
+        let participation = &mut self.approval_participation[position].1;
+        for (index, (approver, _)) in approvers.iter().enumerate() {
+            let counts = participation.entry(approver.account_id.clone()).or_default();
+            if header.approvals().get(index).map_or(false, Option::is_some) {
+                counts.num_included += 1;
+            } else {
+                counts.num_missing += 1;
+            }
+            if is_latest_epoch {
+                let account_id = approver.account_id.as_str();
+                metrics::VALIDATORS_APPROVALS_INCLUDED
+                    .with_label_values(&[account_id])
+                    .set(counts.num_included as i64);
+                metrics::VALIDATORS_APPROVALS_MISSING
+                    .with_label_values(&[account_id])
+                    .set(counts.num_missing as i64);
+            }
+        }
+    }
 
-                    shard_layout = next_shard_layout;
+    /// Records the delay between the height the new chunks of `block` were produced for and the
+    /// height of `block`.
+    fn record_chunk_inclusion_delays(&mut self, block: &Block) {
+        let height = block.header().height();
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            let shard_id = chunk_header.shard_id();
+            let height_created = chunk_header.height_created();
+            let delay = height.saturating_sub(height_created);
+            *self
+                .chunk_inclusion_delays
+                .delays_per_shard
+                .entry(shard_id)
+                .or_default()
+                .entry(delay)
+                .or_default() += 1;
+            metrics::CHUNK_INCLUSION_DELAY
+                .with_label_values(&[shard_id.to_string().as_str()])
+                .observe(delay as f64);
+            if delay == 0 {
+                continue;
+            }
+            match self.runtime_adapter.get_chunk_producer(
+                block.header().epoch_id(),
+                height_created,
+                shard_id,
+            ) {
+                Ok(chunk_producer) => {
+                    metrics::CHUNKS_INCLUDED_LATE
+                        .with_label_values(&[chunk_producer.as_str()])
+                        .inc();
+                    *self
+                        .chunk_inclusion_delays
+                        .delayed_chunks_per_producer
+                        .entry(chunk_producer)
+                        .or_default() += 1;
+                }
+                Err(err) => {
+                    debug!(target: "chain", "Failed to get the producer of chunk {:?}: {}", chunk_header.chunk_hash(), err);
                 }
-                epoch_id = next_epoch_id;
             }
-            block_hash = next_block_hash;
+        }
+    }
 
-            let block = self.get_block(&block_hash)?;
-            let chunks = block.chunks();
-            for &shard_id in shard_ids.iter() {
-                if chunks[shard_id as usize].height_included() == block.header().height() {
-                    return Ok(Some((block_hash, shard_id)));
+    /// Recomputes the receipt backlog of every shard as of the new head `head_hash`.
+    fn record_receipt_backlog(&mut self, head_hash: &CryptoHash) {
+        if let Err(err) = self.record_receipt_backlog_impl(head_hash) {
+            debug!(target: "chain", "Failed to compute receipt backlog at {}: {}", head_hash, err);
+        }
+    }
+
+    fn record_receipt_backlog_impl(&mut self, head_hash: &CryptoHash) -> Result<(), Error> {
+        let heights_included: Vec<_> = self
+            .store
+            .get_block(head_hash)?
+            .chunks()
+            .iter()
+            .map(|chunk_header| chunk_header.height_included())
+            .collect();
+        let mut receipt_backlog = HashMap::new();
+        for (shard_id, height_included) in heights_included.into_iter().enumerate() {
+            let shard_id = shard_id as ShardId;
+            // The receipts saved for the block including the last new chunk are consumed by it.
+            let mut backlog = ReceiptBacklog::default();
+            let mut block_hash = *head_hash;
+            loop {
+                let header = self.store.get_block_header(&block_hash)?;
+                if header.height() <= height_included {
+                    break;
+                }
+                let prev_hash = *header.prev_hash();
+                if let Ok(receipt_proofs) = self.store.get_incoming_receipts(&block_hash, shard_id)
+                {
+                    backlog.num_receipt_proofs += receipt_proofs.len() as u64;
+                    for receipt_proof in receipt_proofs {
+                        backlog.size_bytes += receipt_proof.try_to_vec()?.len() as u64;
+                    }
                 }
+                block_hash = prev_hash;
+            }
+
+            let shard_label = shard_id.to_string();
+            metrics::RECEIPT_BACKLOG_PROOFS
+                .with_label_values(&[shard_label.as_str()])
+                .set(backlog.num_receipt_proofs as i64);
+            metrics::RECEIPT_BACKLOG_BYTES
+                .with_label_values(&[shard_label.as_str()])
+                .set(backlog.size_bytes as i64);
+            let prev_size_bytes =
+                self.receipt_backlog.get(&shard_id).map_or(0, |backlog| backlog.size_bytes);
+            if backlog.size_bytes > self.receipt_backlog_warn_bytes
+                && prev_size_bytes <= self.receipt_backlog_warn_bytes
+            {
+                warn!(
+                    target: "chain",
+                    "Receipt backlog of shard {} reached {} bytes in {} receipt proofs, no new chunk since height {}",
+                    shard_id, backlog.size_bytes, backlog.num_receipt_proofs, height_included
+                );
             }
+            receipt_backlog.insert(shard_id, backlog);
         }
+        self.receipt_backlog = receipt_backlog;
+        Ok(())
+    }
 
-        Ok(None)
+    /// Returns the incoming receipts waiting for the next chunk of each shard, as of the head.
+    pub fn receipt_backlog_per_shard(&self) -> HashMap<ShardId, ReceiptBacklog> {
+        self.receipt_backlog.clone()
     }
 
-    /// Returns underlying ChainStore.
-    #[inline]
-    pub fn store(&self) -> &ChainStore {
-        &self.store
+    /// Returns how often the approvals of each validator were included in the blocks of
+    /// `epoch_id` accepted by this node. Only the most recent epochs are tracked.
+    pub fn approval_participation(
+        &self,
+        epoch_id: &EpochId,
+    ) -> HashMap<AccountId, ApprovalParticipation> {
+        self.approval_participation
+            .iter()
+            .find(|(id, _)| id == epoch_id)
+            .map(|(_, participation)| participation.clone())
+            .unwrap_or_default()
     }
 
-    /// Returns mutable ChainStore.
-    #[inline]
-    pub fn mut_store(&mut self) -> &mut ChainStore {
-        &mut self.store
+    /// Returns the inclusion delays of the new chunks of the blocks accepted by this node since
+    /// it started.
+    pub fn chunk_inclusion_delay_stats(&self) -> ChunkInclusionDelayStats {
+        self.chunk_inclusion_delays.clone()
     }
 
-    /// Returns underlying RuntimeAdapter.
-    #[inline]
-    pub fn runtime_adapter(&self) -> Arc<dyn RuntimeAdapter> {
-        self.runtime_adapter.clone()
+    /// Returns up to `limit` of the latest blocks rejected by block processing as invalid, the
+    /// latest first.
+    pub fn rejected_blocks(&self, limit: usize) -> Result<Vec<RejectedBlock>, Error> {
+        self.store.get_rejected_blocks(limit)
+    }
+
+    /// Returns the fork blocks deleted by garbage collection at `heights`, ordered by height and
+    /// hash. Only recorded if `record_discarded_forks` is set.
+    pub fn discarded_forks(
+        &self,
+        heights: RangeInclusive<BlockHeight>,
+    ) -> Result<Vec<DiscardedFork>, Error> {
+        self.store.get_discarded_forks(heights)
+    }
+
+    /// Returns the approvals included in a block produced by this node. Only recorded if
+    /// `record_approval_audit` is set, and garbage collected with the block.
+    pub fn get_approval_audit(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<ApprovalAuditRecord>, Error> {
+        self.store.get_approval_audit(block_hash)
     }
 
-    /// Returns genesis block.
-    #[inline]
-    pub fn genesis_block(&self) -> &Block {
-        &self.genesis
+    /// Returns the status of `height` on the canonical chain. A height without a canonical block
+    /// is skipped if the first canonical block above it links to a block below it.
+    pub fn height_status(&mut self, height: BlockHeight) -> Result<HeightStatus, Error> {
+        let head = self.head()?;
+        if height > head.height {
+            return Ok(HeightStatus::NotYetProduced);
+        }
+        if height < self.store.tail()? {
+            return Ok(HeightStatus::GarbageCollected);
+        }
+        match self.store.get_block_hash_by_height(height) {
+            Ok(hash) => return Ok(HeightStatus::Block(hash)),
+            Err(e) => match e.kind() {
+                ErrorKind::DBNotFoundErr(_) => {}
+                _ => return Err(e),
+            },
+        }
+        for next_height in height + 1..=head.height {
+            let next_hash = match self.store.get_block_hash_by_height(next_height) {
+                Ok(hash) => hash,
+                Err(e) => match e.kind() {
+                    ErrorKind::DBNotFoundErr(_) => continue,
+                    _ => return Err(e),
+                },
+            };
+            let prev_hash = *self.get_block_header(&next_hash)?.prev_hash();
+            let prev_height = self.get_block_header(&prev_hash)?.height();
+            return if prev_height < height {
+                Ok(HeightStatus::Skipped)
+            } else {
+                Err(ErrorKind::Other(format!(
+                    "No canonical block at height {}, but canonical block {} has parent at {}",
+                    height, next_hash, prev_height
+                ))
+                .into())
+            };
+        }
+        Err(ErrorKind::DBNotFoundErr(format!("BLOCK HEIGHT: {}", head.height)).into())
     }
 
-    /// Returns genesis block header.
-    #[inline]
-    pub fn genesis(&self) -> &BlockHeader {
-        self.genesis.header()
+    /// Returns the inputs used to apply the chunk of `shard_id` included in the block.
+    pub fn get_chunk_apply_inputs(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<ChunkApplyInputs, Error> {
+        let block = self.get_block(block_hash)?.clone();
+        let prev_block = self.get_block(block.header().prev_hash())?.clone();
+        let mut chain_store_update = self.store.store_update();
+        chunk_apply_inputs(
+            &mut chain_store_update,
+            self.runtime_adapter.as_ref(),
+            &block,
+            &prev_block,
+            shard_id,
+        )
     }
 
-    /// Returns number of orphans currently in the orphan pool.
-    #[inline]
-    pub fn orphans_len(&self) -> usize {
-        self.orphans.len()
+    /// Re-applies chunks of the canonical blocks at heights `from_height..=to_height` and
+    /// compares the resulting chunk extras with the stored ones. Chunk application jobs are built
+    /// by `apply_chunks_preprocessing`, the same way as during block processing. Each block is
+    /// applied on top of the replayed results of the previous ones, so that a single divergence
+    /// is not propagated to the following blocks. The trie nodes of the replayed state are held
+    /// in a `TrieOverlay` for that, nothing is written. If `shards` is given, only chunk extras
+    /// of these shards are compared.
+    pub fn replay_blocks(
+        &mut self,
+        me: &Option<AccountId>,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+        shards: Option<Vec<ShardId>>,
+    ) -> Result<ReplayReport, Error> {
+        let tries = self.runtime_adapter.get_tries();
+        let overlay = TrieOverlay::default();
+        tries.set_overlay(Some(overlay.clone()));
+        // Sandbox state patches are left for the next processed block.
+        let states_to_patch = self.pending_states_to_patch.take();
+        let res = self.replay_blocks_impl(me, from_height, to_height, shards, &overlay);
+        self.pending_states_to_patch = states_to_patch;
+        tries.set_overlay(None);
+        res
     }
 
-    /// Returns number of orphans currently in the orphan pool.
-    #[inline]
-    pub fn blocks_with_missing_chunks_len(&self) -> usize {
-        self.blocks_with_missing_chunks.len()
+    /// Replays the blocks for `replay_blocks`, adding the trie nodes of the replayed state to
+    /// `overlay`. The chain store update is dropped without being committed.
+    fn replay_blocks_impl(
+        &mut self,
+        me: &Option<AccountId>,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+        shards: Option<Vec<ShardId>>,
+        overlay: &TrieOverlay,
+    ) -> Result<ReplayReport, Error> {
+        let mut report = ReplayReport::default();
+        let genesis_height = self.genesis.header().height();
+        let mut chain_update = self.chain_update();
+        // The replayed chunks aren't reported to the chain event handlers.
+        chain_update.chunk_event_subscription = None;
+        for height in from_height.max(genesis_height + 1)..=to_height {
+            let block_hash = match chain_update.chain_store_update.get_block_hash_by_height(height)
+            {
+                Ok(block_hash) => block_hash,
+                Err(err) => match err.kind() {
+                    ErrorKind::DBNotFoundErr(_) => continue,
+                    _ => return Err(err),
+                },
+            };
+            let block = chain_update.chain_store_update.get_block(&block_hash)?.clone();
+            let prev_block =
+                chain_update.chain_store_update.get_block(block.header().prev_hash())?.clone();
+            let work = chain_update.apply_chunks_preprocessing(
+                me,
+                &block,
+                &prev_block,
+                block.header().challenges_result().clone(),
+                ApplyChunksMode::IsCaughtUp,
+            )?;
+            for result in do_apply_chunks(work) {
+                let result = result?;
+                if let Some((shard_uid, replayed)) =
+                    chain_update.replayed_chunk_extra(&result, prev_block.hash())?
+                {
+                    let shard_id = shard_uid.shard_id();
+                    if shards.as_ref().map_or(true, |shards| shards.contains(&shard_id)) {
+                        report.chunks_compared += 1;
+                        let stored = chain_update
+                            .chain_store_update
+                            .get_chunk_extra(&block_hash, &shard_uid)
+                            .ok()
+                            .cloned();
+                        if stored.as_ref() != Some(&replayed) {
+                            report.divergences.entry(shard_id).or_insert(ReplayDivergence {
+                                height,
+                                block_hash,
+                                shard_id,
+                                stored,
+                                replayed,
+                            });
+                        }
+                    }
+                }
+                for (shard_uid, trie_changes) in result.trie_changes() {
+                    overlay.add_insertions(trie_changes, shard_uid);
+                }
+                chain_update.process_apply_chunk_result(result, block_hash, *prev_block.hash())?;
+            }
+            report.blocks_replayed += 1;
+        }
+        Ok(report)
     }
 
-    /// Returns number of evicted orphans.
-    #[inline]
-    pub fn orphans_evicted_len(&self) -> usize {
-        self.orphans.len_evicted()
+    /// Adds the epoch manager data missing for the stored headers at heights from `from_height`
+    /// to `to_height`, which is left behind if the node stops between saving a header and
+    /// recording it in the epoch manager and makes header sync fail on the following headers.
+    /// Headers are visited by height, so that a header is recorded after its parent, and each
+    /// one is committed separately. Headers already known to the epoch manager are skipped.
+    pub fn repair_epoch_data(
+        &mut self,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+    ) -> Result<RepairReport, Error> {
+        let mut report = RepairReport::default();
+        for height in from_height.max(self.genesis.header().height() + 1)..=to_height {
+            let mut hashes: Vec<_> =
+                self.store.get_all_header_hashes_by_height(height)?.into_iter().collect();
+            hashes.sort();
+            for hash in hashes {
+                report.headers_checked += 1;
+                if self.runtime_adapter.get_epoch_start_height(&hash).is_ok() {
+                    continue;
+                }
+                let header = self.store.get_block_header(&hash)?.clone();
+                let mut chain_store_update = self.store.store_update();
+                let last_finalized_height =
+                    chain_store_update.get_block_height(header.last_final_block())?;
+                let epoch_manager_update = self.runtime_adapter.add_validator_proposals(
+                    BlockHeaderInfo::new(&header, last_finalized_height),
+                )?;
+                chain_store_update.merge(epoch_manager_update);
+                chain_store_update.commit()?;
+                info!(target: "chain", "Added missing epoch manager data of header {} at {}", hash, height);
+                report.repaired.push(hash);
+            }
+        }
+        Ok(report)
     }
 
     /// Check if hash is for a known orphan.
@@ -3014,6 +6928,64 @@ impl Chain {
         }
     }
 
+    /// Checks the sync hash of a state request, like `check_sync_hash_validity`, and records it
+    /// as served if it's valid. Fails with `StateSyncBusy` if state is already served for too
+    /// many other sync hashes.
+    pub fn check_state_request_sync_hash(&mut self, sync_hash: &CryptoHash) -> Result<bool, Error> {
+        if !self.check_sync_hash_validity(sync_hash)? {
+            return Ok(false);
+        }
+        if !self.sync_hash_registry.admit(sync_hash) {
+            metrics::STATE_SYNC_REQUESTS_REJECTED_BUSY.inc();
+            return Err(ErrorKind::StateSyncBusy(*sync_hash).into());
+        }
+        Ok(true)
+    }
+
+    /// Returns a valid sync hash state is already served for, which a peer whose request for
+    /// `sync_hash` was rejected as busy can sync to instead. The most recently requested one is
+    /// chosen.
+    pub fn suggest_served_sync_hash(&mut self, sync_hash: &CryptoHash) -> Option<CryptoHash> {
+        self.sync_hash_registry.served_sync_hashes().into_iter().find(|served| {
+            served != sync_hash && matches!(self.check_sync_hash_validity(served), Ok(true))
+        })
+    }
+
+    /// Returns a valid sync hash within `SYNC_HASH_SUGGESTION_DISTANCE` blocks of `candidate` on
+    /// the canonical chain, nearest first. Peers often request state with the hash of the block
+    /// right before or right after the first block of an epoch.
+    pub fn suggest_sync_hash(
+        &mut self,
+        candidate: &CryptoHash,
+    ) -> Result<Option<CryptoHash>, Error> {
+        let header = self.get_block_header(candidate)?.clone();
+        if self.is_on_current_chain(&header).is_err() {
+            return Ok(None);
+        }
+        let mut next_hash = *candidate;
+        let mut prev_hash = *candidate;
+        for _ in 0..SYNC_HASH_SUGGESTION_DISTANCE {
+            let mut neighbors = vec![];
+            if let Ok(hash) = self.store.get_next_block_hash(&next_hash).map(|hash| *hash) {
+                neighbors.push(hash);
+                next_hash = hash;
+            }
+            if let Ok(hash) = self.get_block_header(&prev_hash).map(|header| *header.prev_hash()) {
+                neighbors.push(hash);
+                prev_hash = hash;
+            }
+            if neighbors.is_empty() {
+                break;
+            }
+            for hash in neighbors {
+                if let Ok(true) = self.check_sync_hash_validity(&hash) {
+                    return Ok(Some(hash));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     /// Get transaction result for given hash of transaction or receipt id on the canonical chain
     pub fn get_execution_outcome(
         &mut self,
@@ -3032,6 +7004,116 @@ impl Chain {
             .ok_or_else(|| ErrorKind::DBNotFoundErr(format!("EXECUTION OUTCOME: {}", id)).into())
     }
 
+    /// Returns the chain parameters in effect at the head, taking into account the overrides of
+    /// the gas price bounds in later protocol versions and the configured economics overrides.
+    pub fn chain_parameters(&self) -> Result<ChainParametersView, Error> {
+        let head = self.head()?;
+        let epoch_id = head.epoch_id;
+        let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
+        let economics = &self.block_economics_config;
+        Ok(ChainParametersView {
+            protocol_version,
+            transaction_validity_period: self.transaction_validity_period,
+            epoch_length: self.epoch_length,
+            min_gas_price: economics.min_gas_price(protocol_version, head.height),
+            max_gas_price: economics.max_gas_price(protocol_version, head.height),
+            gas_price_adjustment_rate: economics
+                .gas_price_adjustment_rate(protocol_version, head.height),
+            num_shards: self.runtime_adapter.num_shards(&epoch_id)?,
+            shard_layout: self.runtime_adapter.get_shard_layout(&epoch_id)?,
+            gc_num_epochs_to_keep: NUM_EPOCHS_TO_KEEP_STORE_DATA,
+        })
+    }
+
+    /// Returns the shard of the given account in the given epoch, or in the epoch of the head.
+    pub fn resolve_account_shard(
+        &mut self,
+        account_id: &AccountId,
+        epoch_id: Option<EpochId>,
+    ) -> Result<(ShardId, ShardUId), Error> {
+        let epoch_id = self.epoch_id_or_head(epoch_id)?;
+        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        Ok((
+            account_id_to_shard_id(account_id, &shard_layout),
+            account_id_to_shard_uid(account_id, &shard_layout),
+        ))
+    }
+
+    /// Returns the shard layout of the given epoch, or of the epoch of the head.
+    pub fn get_shard_layout_view(
+        &self,
+        epoch_id: Option<EpochId>,
+    ) -> Result<ShardLayoutView, Error> {
+        let epoch_id = self.epoch_id_or_head(epoch_id)?;
+        let shard_layout = self.runtime_adapter.get_shard_layout(&epoch_id)?;
+        Ok(ShardLayoutView {
+            epoch_id,
+            version: shard_layout.version(),
+            num_shards: shard_layout.num_shards(),
+            fixed_shards: shard_layout.fixed_shards().to_vec(),
+            boundary_accounts: shard_layout.boundary_accounts().to_vec(),
+        })
+    }
+
+    /// Returns the parts of the chunks of the epoch of the block after `epoch_prev_hash` owned by
+    /// `me` together with the owners of the parts, i.e. the parts `me` receives to forward and
+    /// the accounts the other parts are expected from.
+    pub fn part_ownership(
+        &self,
+        epoch_prev_hash: &CryptoHash,
+        me: &AccountId,
+    ) -> Result<PartOwnershipView, Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(epoch_prev_hash)?;
+        let num_total_parts = self.runtime_adapter.num_total_parts();
+        let mut owned_parts = vec![];
+        let mut part_owners = vec![];
+        for part_id in 0..num_total_parts as u64 {
+            let owner = self.runtime_adapter.get_part_owner(epoch_prev_hash, part_id)?;
+            if &owner == me {
+                owned_parts.push(part_id);
+            }
+            if part_owners.len() < MAX_PART_OWNERSHIP_OWNERS {
+                part_owners.push(owner);
+            }
+        }
+        Ok(PartOwnershipView {
+            epoch_id,
+            account_id: me.clone(),
+            num_total_parts: num_total_parts as u64,
+            num_data_parts: self.runtime_adapter.num_data_parts() as u64,
+            owned_parts,
+            part_owners,
+        })
+    }
+
+    fn epoch_id_or_head(&self, epoch_id: Option<EpochId>) -> Result<EpochId, Error> {
+        match epoch_id {
+            Some(epoch_id) => Ok(epoch_id),
+            None => Ok(self.head()?.epoch_id),
+        }
+    }
+
+    /// Returns the ids of the outcome with the given id and of all its ancestors: the receipt
+    /// that produced it, the receipt that produced that one and so on up to the originating
+    /// transaction. The chain stops early if an ancestor was garbage collected.
+    pub fn get_outcome_ancestry(&mut self, id: &CryptoHash) -> Result<Vec<CryptoHash>, Error> {
+        self.get_execution_outcome(id)?;
+        let mut ancestry = vec![*id];
+        let mut current_id = *id;
+        while let Some(parent_id) = self.store.get_outcome_parent(&current_id)? {
+            ancestry.push(parent_id);
+            current_id = parent_id;
+        }
+        Ok(ancestry)
+    }
+
+    /// Returns the totals of the blocks of the epoch at the given height on the canonical chain.
+    pub fn get_epoch_summary(&self, epoch_height: EpochHeight) -> Result<EpochSummary, Error> {
+        self.store
+            .get_epoch_summary(epoch_height)?
+            .ok_or_else(|| ErrorKind::EpochSummaryNotComputed(epoch_height).into())
+    }
+
     /// Retrieve the up to `max_headers_returned` headers on the main chain
     /// `hashes`: a list of block "locators". `hashes` should be ordered from older blocks to
     ///           more recent blocks. This function will find the first block in `hashes`
@@ -3096,7 +7178,7 @@ impl Chain {
     ) -> Result<ShardChunkHeader, Error> {
         let prev_shard_id =
             runtime_adapter.get_prev_shard_ids(prev_block.hash(), vec![shard_id])?[0];
-        Ok(prev_block.chunks().get(prev_shard_id as usize).unwrap().clone())
+        Ok(prev_block.chunk_for_shard(prev_shard_id)?)
     }
 
     pub fn group_receipts_by_shard(
@@ -3116,28 +7198,48 @@ impl Chain {
         receipts: &[Receipt],
         shard_layout: &ShardLayout,
     ) -> Vec<CryptoHash> {
-        if shard_layout.num_shards() == 1 {
+        Self::build_receipts_hashes_with_cache(
+            receipts,
+            shard_layout,
+            &mut AccountShardCache::default(),
+        )
+    }
+
+    /// Same as `build_receipts_hashes`, looking up the shards of the receivers in the given cache
+    /// first, so that callers processing consecutive blocks map each account once.
+    pub fn build_receipts_hashes_with_cache(
+        receipts: &[Receipt],
+        shard_layout: &ShardLayout,
+        cache: &mut AccountShardCache,
+    ) -> Vec<CryptoHash> {
+        let num_shards = shard_layout.num_shards() as usize;
+        if num_shards == 1 {
             return vec![hash(&ReceiptList(0, receipts).try_to_vec().unwrap())];
         }
-        let mut account_id_to_shard_id_map = HashMap::new();
-        let mut shard_receipts: Vec<_> =
-            (0..shard_layout.num_shards()).map(|i| (i, Vec::new())).collect();
-        for receipt in receipts.iter() {
-            let shard_id = match account_id_to_shard_id_map.get(&receipt.receiver_id) {
-                Some(id) => *id,
-                None => {
-                    let id = account_id_to_shard_id(&receipt.receiver_id, shard_layout);
-                    account_id_to_shard_id_map.insert(receipt.receiver_id.clone(), id);
-                    id
-                }
-            };
-            shard_receipts[shard_id as usize].1.push(receipt);
+        cache.set_shard_layout(shard_layout);
+        let receipt_shards: Vec<ShardId> = receipts
+            .iter()
+            .map(|receipt| cache.shard_id(&receipt.receiver_id, shard_layout))
+            .collect();
+        let mut num_shard_receipts = vec![0; num_shards];
+        for shard_id in receipt_shards.iter() {
+            num_shard_receipts[*shard_id as usize] += 1;
         }
+        let mut shard_receipts: Vec<Vec<&Receipt>> =
+            num_shard_receipts.into_iter().map(Vec::with_capacity).collect();
+        for (receipt, shard_id) in receipts.iter().zip(receipt_shards) {
+            shard_receipts[shard_id as usize].push(receipt);
+        }
+        // Same encoding as `ReceiptList`, serialized into a single buffer.
+        let mut buffer = vec![];
         shard_receipts
-            .into_iter()
-            .map(|(i, rs)| {
-                let bytes = (i, rs).try_to_vec().unwrap();
-                hash(&bytes)
+            .iter()
+            .enumerate()
+            .map(|(shard_id, receipts)| {
+                buffer.clear();
+                (shard_id as ShardId).serialize(&mut buffer).unwrap();
+                receipts.serialize(&mut buffer).unwrap();
+                hash(&buffer)
             })
             .collect()
     }
@@ -3172,11 +7274,27 @@ pub struct ChainUpdate<'a> {
     blocks_with_missing_chunks: &'a MissingChunksPool<Orphan>,
     epoch_length: BlockHeightDelta,
     block_economics_config: &'a BlockEconomicsConfig,
+    validation_config: BlockValidationConfig,
     doomslug_threshold_mode: DoomslugThresholdMode,
     genesis: &'a Block,
     #[allow(unused)]
     transaction_validity_period: BlockHeightDelta,
     states_to_patch: Option<Vec<StateRecord>>,
+    /// Shards we care about for the blocks after given prev blocks, see `cares_about_shards`.
+    shard_care: HashMap<(Option<AccountId>, CryptoHash), ShardCareMatrix>,
+    /// Payloads of the chunk applied events wanted by the chain event handlers, `None` if there
+    /// are no handlers and the applied chunks aren't recorded.
+    chunk_event_subscription: Option<ChainEventSubscription>,
+    /// Headers ahead of the local clock within this grace period are reported with
+    /// `ErrorKind::BlockAheadOfClock` instead of being rejected.
+    future_block_grace_period: TimeDuration,
+    /// Approvals of the validated headers left to `ChainUpdate::verify_deferred_approvals`, if
+    /// they aren't verified with each header.
+    deferred_approvals: Option<DeferredApprovals>,
+    /// Chunks applied by this update, emitted once it's committed.
+    chunk_applied_events: Vec<ChunkAppliedEvent>,
+    /// Shards whose chunks were applied by this update, with the time it took.
+    chunk_apply_durations: Vec<(ShardId, TimeDuration)>,
 }
 
 impl<'a> ChainAccess for ChainUpdate<'a> {
@@ -3193,17 +7311,28 @@ impl<'a> ChainAccess for ChainUpdate<'a> {
     }
 }
 
+/// Approvals of validated headers which are yet to be verified, along with their threshold.
+#[derive(Default)]
+struct DeferredApprovals {
+    header_hashes: Vec<CryptoHash>,
+    approvals: Vec<BlockApprovals>,
+}
+
 pub struct SameHeightResult {
     shard_uid: ShardUId,
     gas_limit: Gas,
     apply_result: ApplyTransactionResult,
     apply_split_result_or_state_changes: Option<ApplySplitStateResultOrStateChanges>,
+    /// Time spent in `RuntimeAdapter::apply_transactions`.
+    apply_duration: TimeDuration,
 }
 
 pub struct DifferentHeightResult {
     shard_uid: ShardUId,
     apply_result: ApplyTransactionResult,
     apply_split_result_or_state_changes: Option<ApplySplitStateResultOrStateChanges>,
+    /// Time spent in `RuntimeAdapter::apply_transactions`.
+    apply_duration: TimeDuration,
 }
 
 pub struct SplitStateResult {
@@ -3212,12 +7341,109 @@ pub struct SplitStateResult {
     results: Vec<ApplySplitStateResult>,
 }
 
+/// Result of validating a single challenge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatedChallenge {
+    /// Block invalidated by the challenge. `None` for double sign and malicious challenges,
+    /// which only slash validators.
+    pub challenged_block: Option<CryptoHash>,
+    pub slashed_validators: Vec<SlashedValidator>,
+}
+
+/// Validates a single challenge. Malicious challenges are valid and slash the challenger.
+fn validate_single_challenge(
+    runtime_adapter: &dyn RuntimeAdapter,
+    epoch_id: &EpochId,
+    prev_block_hash: &CryptoHash,
+    challenge: &Challenge,
+) -> Result<ValidatedChallenge, Error> {
+    match validate_challenge(runtime_adapter, epoch_id, prev_block_hash, challenge) {
+        Ok((hash, account_ids)) => {
+            // If it's double signed block, we don't invalidate blocks just slash.
+            let is_double_sign = matches!(challenge.body, ChallengeBody::BlockDoubleSign(_));
+            Ok(ValidatedChallenge {
+                challenged_block: if is_double_sign { None } else { Some(hash) },
+                slashed_validators: account_ids
+                    .into_iter()
+                    .map(|id| SlashedValidator::new(id, is_double_sign))
+                    .collect(),
+            })
+        }
+        Err(ref err) if err.kind() == ErrorKind::MaliciousChallenge => Ok(ValidatedChallenge {
+            challenged_block: None,
+            slashed_validators: vec![SlashedValidator::new(challenge.account_id.clone(), false)],
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+/// Collects validators slashed by the given challenges.
+pub fn challenges_result(validated_challenges: &[ValidatedChallenge]) -> ChallengesResult {
+    validated_challenges
+        .iter()
+        .flat_map(|challenge| challenge.slashed_validators.iter().cloned())
+        .collect()
+}
+
+/// Everything needed to apply a chunk, apart from the block itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkApplyInputs {
+    pub chunk: ShardChunk,
+    /// Height at which the previous chunk of the shard was included.
+    pub prev_chunk_height_included: BlockHeight,
+    /// Incoming receipts since the previous chunk of the shard.
+    pub receipts: Vec<Receipt>,
+    /// Gas price of the previous block.
+    pub gas_price: Balance,
+    /// Chunk extra of the shard after the previous block.
+    pub prev_chunk_extra: ChunkExtra,
+    /// Whether receipts lost in `apply_chunks` have to be restored while applying the chunk
+    /// (see https://github.com/near/nearcore/pull/4248/).
+    pub is_first_block_with_chunk_of_version: bool,
+}
+
 pub enum ApplyChunkResult {
     SameHeight(SameHeightResult),
     DifferentHeight(DifferentHeightResult),
     SplitState(SplitStateResult),
 }
 
+impl ApplyChunkResult {
+    /// Trie changes of the applied shard and of the split states updated with it.
+    fn trie_changes(&self) -> Vec<(ShardUId, &TrieChanges)> {
+        let mut trie_changes = vec![];
+        let split_results = match self {
+            ApplyChunkResult::SameHeight(SameHeightResult {
+                shard_uid,
+                apply_result,
+                apply_split_result_or_state_changes,
+                ..
+            })
+            | ApplyChunkResult::DifferentHeight(DifferentHeightResult {
+                shard_uid,
+                apply_result,
+                apply_split_result_or_state_changes,
+                ..
+            }) => {
+                trie_changes.push((*shard_uid, apply_result.trie_changes.trie_changes()));
+                match apply_split_result_or_state_changes {
+                    Some(ApplySplitStateResultOrStateChanges::ApplySplitStateResults(results)) => {
+                        results.as_slice()
+                    }
+                    _ => &[],
+                }
+            }
+            ApplyChunkResult::SplitState(SplitStateResult { results, .. }) => results.as_slice(),
+        };
+        trie_changes.extend(
+            split_results
+                .iter()
+                .map(|result| (result.shard_uid, result.trie_changes.trie_changes())),
+        );
+        trie_changes
+    }
+}
+
 impl<'a> ChainUpdate<'a> {
     pub fn new(
         store: &'a mut ChainStore,
@@ -3226,6 +7452,7 @@ impl<'a> ChainUpdate<'a> {
         blocks_with_missing_chunks: &'a MissingChunksPool<Orphan>,
         epoch_length: BlockHeightDelta,
         block_economics_config: &'a BlockEconomicsConfig,
+        validation_config: BlockValidationConfig,
         doomslug_threshold_mode: DoomslugThresholdMode,
         genesis: &'a Block,
         transaction_validity_period: BlockHeightDelta,
@@ -3238,6 +7465,7 @@ impl<'a> ChainUpdate<'a> {
             blocks_with_missing_chunks,
             epoch_length,
             block_economics_config,
+            validation_config,
             doomslug_threshold_mode,
             genesis,
             transaction_validity_period,
@@ -3254,6 +7482,7 @@ impl<'a> ChainUpdate<'a> {
         blocks_with_missing_chunks: &'a MissingChunksPool<Orphan>,
         epoch_length: BlockHeightDelta,
         block_economics_config: &'a BlockEconomicsConfig,
+        validation_config: BlockValidationConfig,
         doomslug_threshold_mode: DoomslugThresholdMode,
         genesis: &'a Block,
         transaction_validity_period: BlockHeightDelta,
@@ -3266,6 +7495,7 @@ impl<'a> ChainUpdate<'a> {
             blocks_with_missing_chunks,
             epoch_length,
             block_economics_config,
+            validation_config,
             doomslug_threshold_mode,
             genesis,
             transaction_validity_period,
@@ -3280,6 +7510,7 @@ impl<'a> ChainUpdate<'a> {
         blocks_with_missing_chunks: &'a MissingChunksPool<Orphan>,
         epoch_length: BlockHeightDelta,
         block_economics_config: &'a BlockEconomicsConfig,
+        validation_config: BlockValidationConfig,
         doomslug_threshold_mode: DoomslugThresholdMode,
         genesis: &'a Block,
         transaction_validity_period: BlockHeightDelta,
@@ -3293,10 +7524,17 @@ impl<'a> ChainUpdate<'a> {
             blocks_with_missing_chunks,
             epoch_length,
             block_economics_config,
+            validation_config,
             doomslug_threshold_mode,
             genesis,
             transaction_validity_period,
             states_to_patch,
+            shard_care: HashMap::new(),
+            chunk_event_subscription: None,
+            future_block_grace_period: TimeDuration::default(),
+            deferred_approvals: None,
+            chunk_applied_events: vec![],
+            chunk_apply_durations: vec![],
         }
     }
 
@@ -3334,23 +7572,60 @@ impl<'a> ChainUpdate<'a> {
         })
     }
 
+    /// Shards `me` cares about in the epoch of the block after `parent_hash` and in the next
+    /// epoch. Asks the runtime once per prev block during this update.
+    fn cares_about_shards(
+        &mut self,
+        me: &Option<AccountId>,
+        parent_hash: &CryptoHash,
+    ) -> Result<ShardCareMatrix, Error> {
+        let key = (me.clone(), *parent_hash);
+        if let Some(shard_care) = self.shard_care.get(&key) {
+            return Ok(shard_care.clone());
+        }
+        let shard_care = self.runtime_adapter.cares_about_shards(me.as_ref(), parent_hash)?;
+        self.shard_care.insert(key, shard_care.clone());
+        Ok(shard_care)
+    }
+
+    /// Shards of the block which are not applied until the state for the next epoch is caught up,
+    /// i.e. the shards applied with `ApplyChunksMode::CatchingUp` but not with `NotCaughtUp`.
+    fn shards_deferred_to_catchup(
+        &mut self,
+        me: &Option<AccountId>,
+        block: &Block,
+    ) -> Result<Vec<ShardId>, Error> {
+        let shard_care = self.cares_about_shards(me, block.header().prev_hash())?;
+        let quarantined_shards =
+            self.chain_store_update.get_chain_store().get_quarantined_shards()?;
+        let mut deferred_shards = vec![];
+        for shard_id in 0..block.chunks().len() as ShardId {
+            let shard_uid =
+                self.runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+            let deferred = match quarantined_shards.get(&shard_uid) {
+                None => {
+                    !shard_care.cares_about_shard(shard_id)
+                        && shard_care.will_care_about_shard(shard_id)
+                }
+                Some(quarantine) => {
+                    shard_care.cares_about_shard(shard_id)
+                        && quarantine.epoch_id != *block.header().epoch_id()
+                }
+            };
+            if deferred {
+                deferred_shards.push(shard_id);
+            }
+        }
+        Ok(deferred_shards)
+    }
+
     fn care_about_any_shard_or_part(
         &mut self,
         me: &Option<AccountId>,
         parent_hash: CryptoHash,
     ) -> Result<bool, Error> {
-        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&parent_hash)?;
-        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
-            if self.runtime_adapter.cares_about_shard(me.as_ref(), &parent_hash, shard_id, true)
-                || self.runtime_adapter.will_care_about_shard(
-                    me.as_ref(),
-                    &parent_hash,
-                    shard_id,
-                    true,
-                )
-            {
-                return Ok(true);
-            }
+        if self.cares_about_shards(me, &parent_hash)?.cares_about_any_shard() {
+            return Ok(true);
         }
         for part_id in 0..self.runtime_adapter.num_total_parts() {
             if &Some(self.runtime_adapter.get_part_owner(&parent_hash, part_id as u64)?) == me {
@@ -3360,6 +7635,36 @@ impl<'a> ChainUpdate<'a> {
         Ok(false)
     }
 
+    /// New chunks of `block` that `me` needs to process it: full chunks of the shards it cares
+    /// about in this or the next epoch, and partial chunks of the other shards. Nothing is needed
+    /// if `me` cares about neither a shard nor a part.
+    fn required_chunks(
+        &mut self,
+        me: &Option<AccountId>,
+        parent_hash: CryptoHash,
+        block: &Block,
+    ) -> Result<RequiredChunks, Error> {
+        let mut required = RequiredChunks::default();
+        if !self.care_about_any_shard_or_part(me, parent_hash)? {
+            return Ok(required);
+        }
+        let shard_care = self.cares_about_shards(me, &parent_hash)?;
+        let height = block.header().height();
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            let shard_id = shard_id as ShardId;
+            if shard_care.cares_about_shard(shard_id) || shard_care.will_care_about_shard(shard_id)
+            {
+                required.need_full.push(chunk_header.clone());
+            } else {
+                required.need_partial.push(chunk_header.clone());
+            }
+        }
+        Ok(required)
+    }
+
     pub fn ping_missing_chunks(
         &mut self,
         me: &Option<AccountId>,
@@ -3369,8 +7674,6 @@ impl<'a> ChainUpdate<'a> {
         if !self.care_about_any_shard_or_part(me, parent_hash)? {
             return Ok(());
         }
-        let mut missing = vec![];
-        let height = block.header().height();
         for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
             // Check if any chunks are invalid in this block.
             if let Some(encoded_chunk) =
@@ -3384,31 +7687,23 @@ impl<'a> ChainUpdate<'a> {
                 };
                 return Err(ErrorKind::InvalidChunkProofs(Box::new(chunk_proof)).into());
             }
-            let shard_id = shard_id as ShardId;
-            if chunk_header.height_included() == height {
-                let chunk_hash = chunk_header.chunk_hash();
-
-                if let Err(_) =
-                    self.chain_store_update.get_partial_chunk(&chunk_header.chunk_hash())
-                {
-                    missing.push(chunk_header.clone());
-                } else if self.runtime_adapter.cares_about_shard(
-                    me.as_ref(),
-                    &parent_hash,
-                    shard_id,
-                    true,
-                ) || self.runtime_adapter.will_care_about_shard(
-                    me.as_ref(),
-                    &parent_hash,
-                    shard_id,
-                    true,
-                ) {
-                    if let Err(_) = self.chain_store_update.get_chunk(&chunk_hash) {
-                        missing.push(chunk_header.clone());
-                    }
-                }
+        }
+        let required = self.required_chunks(me, parent_hash, block)?;
+        let mut missing = vec![];
+        for chunk_header in required.need_partial {
+            if self.chain_store_update.get_partial_chunk(&chunk_header.chunk_hash()).is_err() {
+                missing.push(chunk_header);
+            }
+        }
+        for chunk_header in required.need_full {
+            let chunk_hash = chunk_header.chunk_hash();
+            if self.chain_store_update.get_partial_chunk(&chunk_hash).is_err()
+                || self.chain_store_update.get_chunk(&chunk_hash).is_err()
+            {
+                missing.push(chunk_header);
             }
         }
+        missing.sort_by_key(|chunk_header| chunk_header.shard_id());
         if !missing.is_empty() {
             return Err(ErrorKind::ChunksMissing(missing).into());
         }
@@ -3425,12 +7720,25 @@ impl<'a> ChainUpdate<'a> {
         }
         let height = block.header().height();
         let mut receipt_proofs_by_shard_id = HashMap::new();
+        // Outgoing receipts roots of the new chunks by shard, computed on the first proof checked.
+        let mut outgoing_receipts_roots = None;
 
         for chunk_header in block.chunks().iter() {
             if chunk_header.height_included() == height {
                 let partial_encoded_chunk =
                     self.chain_store_update.get_partial_chunk(&chunk_header.chunk_hash()).unwrap();
                 for receipt in partial_encoded_chunk.receipts().iter() {
+                    if self.validation_config.verify_incoming_receipts {
+                        let roots = outgoing_receipts_roots.get_or_insert_with(|| {
+                            block
+                                .chunks()
+                                .iter()
+                                .filter(|chunk| chunk.height_included() == height)
+                                .map(|chunk| (chunk.shard_id(), chunk.outgoing_receipts_root()))
+                                .collect::<HashMap<_, _>>()
+                        });
+                        verify_incoming_receipt_proof(roots, chunk_header.shard_id(), receipt)?;
+                    }
                     let ReceiptProof(_, shard_proof) = receipt;
                     let ShardProof { from_shard_id: _, to_shard_id, proof: _ } = shard_proof;
                     receipt_proofs_by_shard_id
@@ -3459,58 +7767,76 @@ impl<'a> ChainUpdate<'a> {
         chunk_header: &ShardChunkHeader,
     ) -> Result<ChunkState, Error> {
         let chunk_shard_id = chunk_header.shard_id();
-        let prev_chunk_header = &prev_block.chunks()[chunk_shard_id as usize];
         let prev_merkle_proofs = Block::compute_chunk_headers_root(prev_block.chunks().iter()).1;
         let merkle_proofs = Block::compute_chunk_headers_root(block.chunks().iter()).1;
-        let prev_chunk = self
-            .chain_store_update
-            .get_chain_store()
-            .get_chunk_clone_from_header(&prev_block.chunks()[chunk_shard_id as usize].clone())
-            .unwrap();
-        let receipt_proof_response: Vec<ReceiptProofResponse> =
-            self.chain_store_update.get_incoming_receipts_for_shard(
-                chunk_shard_id,
-                *prev_block.hash(),
-                prev_chunk_header.height_included(),
-            )?;
-        let receipts = collect_receipts_from_response(&receipt_proof_response);
+        // The previous chunk is re-applied the same way it was applied with `prev_block`.
+        let prev_prev_block =
+            self.chain_store_update.get_block(prev_block.header().prev_hash())?.clone();
+        let ChunkApplyInputs {
+            chunk: prev_chunk,
+            receipts,
+            gas_price,
+            is_first_block_with_chunk_of_version,
+            ..
+        } = chunk_apply_inputs(
+            &mut self.chain_store_update,
+            self.runtime_adapter.as_ref(),
+            prev_block,
+            &prev_prev_block,
+            chunk_shard_id,
+        )?;
 
-        let challenges_result = self.verify_challenges(
+        let challenges_result = challenges_result(&self.validate_challenges(
             block.challenges(),
             block.header().epoch_id(),
             block.header().prev_hash(),
-            Some(block.hash()),
-        )?;
+        )?);
         let prev_chunk_inner = prev_chunk.cloned_header().take_inner();
-        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
-            &mut self.chain_store_update,
-            self.runtime_adapter.as_ref(),
-            prev_block.hash(),
+        let apply_result = self.runtime_adapter.apply_transactions_with_optional_storage_proof(
             chunk_shard_id,
+            prev_chunk_inner.prev_state_root(),
+            prev_chunk.height_included(),
+            prev_block.header().raw_timestamp(),
+            prev_chunk_inner.prev_block_hash(),
+            prev_block.hash(),
+            &receipts,
+            prev_chunk.transactions(),
+            prev_chunk_inner.validator_proposals(),
+            gas_price,
+            prev_chunk_inner.gas_limit(),
+            &challenges_result,
+            *block.header().random_value(),
+            true,
+            true,
+            is_first_block_with_chunk_of_version,
+            None,
         )?;
-        let apply_result = self
-            .runtime_adapter
-            .apply_transactions_with_optional_storage_proof(
-                chunk_shard_id,
-                prev_chunk_inner.prev_state_root(),
-                prev_chunk.height_included(),
-                prev_block.header().raw_timestamp(),
-                prev_chunk_inner.prev_block_hash(),
-                prev_block.hash(),
-                &receipts,
-                prev_chunk.transactions(),
-                prev_chunk_inner.validator_proposals(),
-                prev_block.header().gas_price(),
-                prev_chunk_inner.gas_limit(),
-                &challenges_result,
-                *block.header().random_value(),
-                true,
-                true,
-                is_first_block_with_chunk_of_version,
-                None,
-            )
-            .unwrap();
-        let partial_state = apply_result.proof.unwrap().nodes;
+        // The chunk extra of `prev_block` holds the result of the first application only if the
+        // chunk is new in `prev_block`.
+        if prev_chunk.height_included() == prev_block.header().height() {
+            let shard_uid = self
+                .runtime_adapter
+                .shard_id_to_uid(chunk_shard_id, prev_block.header().epoch_id())?;
+            let expected_root = *self
+                .chain_store_update
+                .get_chunk_extra(prev_block.hash(), &shard_uid)?
+                .state_root();
+            if apply_result.new_root != expected_root {
+                metrics::NON_DETERMINISTIC_APPLY.inc();
+                error!(target: "chain", "Chunk of shard {} at block {} applied again results in state root {}, while {} was saved",
+                       chunk_shard_id, prev_block.hash(), apply_result.new_root, expected_root);
+                return Err(ErrorKind::NonDeterministicApply {
+                    shard_id: chunk_shard_id,
+                    expected_root,
+                    got_root: apply_result.new_root,
+                }
+                .into());
+            }
+        }
+        let partial_state = apply_result
+            .proof
+            .ok_or_else(|| ErrorKind::Other("Storage proof was not generated".to_string()))?
+            .nodes;
         Ok(ChunkState {
             prev_block_header: prev_block.header().try_to_vec()?,
             block_header: block.header().try_to_vec()?,
@@ -3565,22 +7891,18 @@ impl<'a> ChainUpdate<'a> {
     }
 
     /// Creates jobs that would apply chunks
+    /// `challenges_result` are validators slashed by the challenges included in the block.
     fn apply_chunks_preprocessing(
         &mut self,
         me: &Option<AccountId>,
         block: &Block,
         prev_block: &Block,
+        challenges_result: ChallengesResult,
         mode: ApplyChunksMode,
     ) -> Result<Vec<Box<dyn FnOnce() -> Result<ApplyChunkResult, Error> + Send + 'static>>, Error>
     {
         let mut result: Vec<Box<dyn FnOnce() -> Result<ApplyChunkResult, Error> + Send + 'static>> =
             Vec::new();
-        let challenges_result = self.verify_challenges(
-            block.challenges(),
-            block.header().epoch_id(),
-            block.header().prev_hash(),
-            Some(block.hash()),
-        )?;
         self.chain_store_update.save_block_extra(block.hash(), BlockExtra { challenges_result });
         #[cfg(not(feature = "mock_network"))]
         let protocol_version =
@@ -3590,14 +7912,17 @@ impl<'a> ChainUpdate<'a> {
         let will_shard_layout_change =
             self.runtime_adapter.will_shard_layout_change_next_epoch(prev_hash)?;
         let prev_chunk_headers = Chain::get_prev_chunk_headers(&*self.runtime_adapter, prev_block)?;
+        let quarantined_shards =
+            self.chain_store_update.get_chain_store().get_quarantined_shards()?;
+        let shard_care = self.cares_about_shards(me, prev_hash)?;
         for (shard_id, (chunk_header, prev_chunk_header)) in
             (block.chunks().iter().zip(prev_chunk_headers.iter())).enumerate()
         {
             let shard_id = shard_id as ShardId;
-            let cares_about_shard_this_epoch =
-                self.runtime_adapter.cares_about_shard(me.as_ref(), prev_hash, shard_id, true);
-            let cares_about_shard_next_epoch =
-                self.runtime_adapter.will_care_about_shard(me.as_ref(), prev_hash, shard_id, true);
+            let shard_uid =
+                self.runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+            let cares_about_shard_this_epoch = shard_care.cares_about_shard(shard_id);
+            let cares_about_shard_next_epoch = shard_care.will_care_about_shard(shard_id);
             // We want to guarantee that transactions are only applied once for each shard, even
             // though apply_chunks may be called twice, once with ApplyChunksMode::NotCaughtUp
             // once with ApplyChunksMode::CatchingUp
@@ -3616,7 +7941,20 @@ impl<'a> ChainUpdate<'a> {
                     !cares_about_shard_this_epoch && cares_about_shard_next_epoch
                 }
             };
-            let need_to_split_states = will_shard_layout_change && cares_about_shard_next_epoch;
+            // The state of a quarantined shard is corrupted, so its chunks are only applied while
+            // catching up from the state downloaded again at the start of a later epoch.
+            let quarantine = quarantined_shards.get(&shard_uid);
+            let should_apply_transactions = match quarantine {
+                None => should_apply_transactions,
+                Some(quarantine) => {
+                    mode == ApplyChunksMode::CatchingUp
+                        && cares_about_shard_this_epoch
+                        && quarantine.epoch_id != *block.header().epoch_id()
+                }
+            };
+            let need_to_split_states = will_shard_layout_change
+                && cares_about_shard_next_epoch
+                && (quarantine.is_none() || should_apply_transactions);
             // We can only split states when states are ready, i.e., mode != ApplyChunksMode::NotCaughtUp
             // 1) if should_apply_transactions == true && split_state_roots.is_some(),
             //     that means split states are ready.
@@ -3642,8 +7980,6 @@ impl<'a> ChainUpdate<'a> {
             } else {
                 None
             };
-            let shard_uid =
-                self.runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id())?;
             let is_new_chunk = chunk_header.height_included() == block.header().height();
             if should_apply_transactions {
                 if is_new_chunk {
@@ -3656,50 +7992,82 @@ impl<'a> ChainUpdate<'a> {
                             prev_chunk_height_included,
                         )?;
                     }
-                    // Validate state root.
-                    let prev_chunk_extra =
-                        self.chain_store_update.get_chunk_extra(prev_hash, &shard_uid)?.clone();
-
-                    // Validate that all next chunk information matches previous chunk extra.
-                    validate_chunk_with_chunk_extra(
-                        // It's safe here to use ChainStore instead of ChainStoreUpdate
-                        // because we're asking prev_chunk_header for already committed block
-                        self.chain_store_update.get_chain_store(),
-                        &*self.runtime_adapter,
-                        block.header().prev_hash(),
-                        &prev_chunk_extra,
-                        prev_chunk_height_included,
-                        chunk_header,
-                    )
-                    .map_err(|e| {
-                        warn!(target: "chain", "Failed to validate chunk extra: {:?}.\n\
-                                                block prev_hash: {}\n\
-                                                block hash: {}\n\
-                                                shard_id: {}\n\
-                                                prev_chunk_height_included: {}\n\
-                                                prev_chunk_extra: {:#?}\n\
-                                                chunk_header: {:#?}", e,block.header().prev_hash(),block.header().hash(),shard_id,prev_chunk_height_included,prev_chunk_extra,chunk_header);
-                        byzantine_assert!(false);
-                        match self.create_chunk_state_challenge(prev_block, block, chunk_header) {
-                            Ok(chunk_state) => {
-                                Error::from(ErrorKind::InvalidChunkState(Box::new(chunk_state)))
-                            }
-                            Err(err) => err,
-                        }
-                    })?;
-                    let receipt_proof_response: Vec<ReceiptProofResponse> =
-                        self.chain_store_update.get_incoming_receipts_for_shard(
-                            shard_id,
-                            *block.hash(),
-                            prev_chunk_height_included,
-                        )?;
-                    let receipts = collect_receipts_from_response(&receipt_proof_response);
-                    let chunk = self
-                        .chain_store_update
-                        .get_chunk_clone_from_header(&chunk_header.clone())?;
+                    let ChunkApplyInputs {
+                        chunk,
+                        receipts,
+                        gas_price,
+                        prev_chunk_extra,
+                        is_first_block_with_chunk_of_version,
+                        ..
+                    } = chunk_apply_inputs(
+                        &mut self.chain_store_update,
+                        self.runtime_adapter.as_ref(),
+                        block,
+                        prev_block,
+                        shard_id,
+                    )?;
 
+                    if self.validation_config.trusted_chain_replay {
+                        validate_chunk_state_root(&prev_chunk_extra, chunk_header)?;
+                    } else {
+                        // Validate that all next chunk information matches previous chunk extra.
+                        validate_chunk_with_chunk_extra(
+                            // It's safe here to use ChainStore instead of ChainStoreUpdate
+                            // because we're asking prev_chunk_header for already committed block
+                            self.chain_store_update.get_chain_store(),
+                            &*self.runtime_adapter,
+                            block.header().prev_hash(),
+                            &prev_chunk_extra,
+                            prev_chunk_height_included,
+                            chunk_header,
+                        )
+                        .map_err(|e| {
+                            warn!(target: "chain", "Failed to validate chunk extra: {:?}.\n\
+                                                    block prev_hash: {}\n\
+                                                    block hash: {}\n\
+                                                    shard_id: {}\n\
+                                                    prev_chunk_height_included: {}\n\
+                                                    prev_chunk_extra: {:#?}\n\
+                                                    chunk_header: {:#?}", e,block.header().prev_hash(),block.header().hash(),shard_id,prev_chunk_height_included,prev_chunk_extra,chunk_header);
+                            // With a deterministic runtime the outgoing receipts can only be
+                            // wrong because of the local node, not the chunk producer.
+                            if matches!(e.kind(), ErrorKind::InvalidReceiptsProof)
+                                && self.validation_config.receipts_root_check_mode
+                                    != ReceiptsRootCheckMode::Disabled
+                            {
+                                metrics::OUTGOING_RECEIPTS_ROOT_MISMATCHES.inc();
+                                error!(target: "chain", "Outgoing receipts root mismatch in shard {}: chunk {:?} claims {}, which doesn't match the receipts produced locally on top of {}",
+                                       shard_id, chunk_header.chunk_hash(), chunk_header.outgoing_receipts_root(), prev_hash);
+                                if self.validation_config.receipts_root_check_mode
+                                    == ReceiptsRootCheckMode::FailOnMismatch
+                                {
+                                    return ErrorKind::OutgoingReceiptsRootMismatch(shard_id).into();
+                                }
+                            }
+                            byzantine_assert!(false);
+                            match self.create_chunk_state_challenge(prev_block, block, chunk_header) {
+                                Ok(chunk_state) => {
+                                    Error::from(ErrorKind::InvalidChunkState(Box::new(chunk_state)))
+                                }
+                                Err(err) => err,
+                            }
+                        })?;
+                    }
                     let transactions = chunk.transactions();
-                    if !validate_transactions_order(transactions) {
+                    if transactions.len()
+                        > self.validation_config.block_limits_config.max_chunk_transactions
+                    {
+                        return Err(ErrorKind::TooManyChunkTransactions(
+                            shard_id,
+                            transactions.len(),
+                            self.validation_config.block_limits_config.max_chunk_transactions,
+                        )
+                        .into());
+                    }
+                    // The transactions order is trusted when replaying a trusted chain.
+                    if !self.validation_config.trusted_chain_replay
+                        && !validate_transactions_order(transactions)
+                    {
                         let merkle_paths =
                             Block::compute_chunk_headers_root(block.chunks().iter()).1;
                         let chunk_proof = ChunkProofs {
@@ -3733,23 +8101,10 @@ impl<'a> ChainUpdate<'a> {
                     let chunk_inner = chunk.cloned_header().take_inner();
                     let gas_limit = chunk_inner.gas_limit();
 
-                    // This variable is responsible for checking to which block we can apply receipts previously lost in apply_chunks
-                    // (see https://github.com/near/nearcore/pull/4248/)
-                    // We take the first block with existing chunk in the first epoch in which protocol feature
-                    // RestoreReceiptsAfterFixApplyChunks was enabled, and put the restored receipts there.
-                    let is_first_block_with_chunk_of_version =
-                        check_if_block_is_first_with_chunk_of_version(
-                            &mut self.chain_store_update,
-                            self.runtime_adapter.as_ref(),
-                            prev_block.hash(),
-                            shard_id,
-                        )?;
-
                     let runtime_adapter = self.runtime_adapter.clone();
                     let block_hash = *block.hash();
                     let challenges_result = block.header().challenges_result().clone();
                     let block_timestamp = block.header().raw_timestamp();
-                    let gas_price = prev_block.header().gas_price();
                     let random_seed = *block.header().random_value();
                     let height = chunk_header.height_included();
                     let prev_block_hash = chunk_header.prev_block_hash();
@@ -3758,6 +8113,7 @@ impl<'a> ChainUpdate<'a> {
 
                     result.push(Box::new(move || -> Result<ApplyChunkResult, Error> {
                         let _timer = CryptoHashTimer::new(chunk.chunk_hash().0);
+                        let apply_start = Instant::now();
                         match runtime_adapter.apply_transactions(
                             shard_id,
                             chunk_inner.prev_state_root(),
@@ -3780,6 +8136,7 @@ impl<'a> ChainUpdate<'a> {
                             None,
                         ) {
                             Ok(apply_result) => {
+                                let apply_duration = apply_start.elapsed();
                                 let apply_split_result_or_state_changes =
                                     if will_shard_layout_change {
                                         Some(Self::apply_split_state_changes(
@@ -3797,6 +8154,7 @@ impl<'a> ChainUpdate<'a> {
                                     shard_uid,
                                     apply_result,
                                     apply_split_result_or_state_changes,
+                                    apply_duration,
                                 }))
                             }
                             Err(err) => Err(ErrorKind::Other(err.to_string()).into()),
@@ -3822,6 +8180,7 @@ impl<'a> ChainUpdate<'a> {
                     let _ = self.states_to_patch;
 
                     result.push(Box::new(move || -> Result<ApplyChunkResult, Error> {
+                        let apply_start = Instant::now();
                         match runtime_adapter.apply_transactions(
                             shard_id,
                             new_extra.state_root(),
@@ -3844,6 +8203,7 @@ impl<'a> ChainUpdate<'a> {
                             None,
                         ) {
                             Ok(apply_result) => {
+                                let apply_duration = apply_start.elapsed();
                                 let apply_split_result_or_state_changes =
                                     if will_shard_layout_change {
                                         Some(Self::apply_split_state_changes(
@@ -3860,6 +8220,7 @@ impl<'a> ChainUpdate<'a> {
                                     shard_uid,
                                     apply_result,
                                     apply_split_result_or_state_changes,
+                                    apply_duration,
                                 }))
                             }
                             Err(err) => Err(ErrorKind::Other(err.to_string()).into()),
@@ -4023,7 +8384,106 @@ impl<'a> ChainUpdate<'a> {
         Ok(())
     }
 
-    /// Processed results of applying chunk
+    /// Chunk extra which `process_apply_chunk_result` would save for the given result.
+    /// Returns `None` for results which only update split states.
+    fn replayed_chunk_extra(
+        &mut self,
+        result: &ApplyChunkResult,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<Option<(ShardUId, ChunkExtra)>, Error> {
+        match result {
+            ApplyChunkResult::SameHeight(SameHeightResult {
+                gas_limit,
+                shard_uid,
+                apply_result,
+                ..
+            }) => {
+                let (outcome_root, _) =
+                    ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
+                let chunk_extra = ChunkExtra::new(
+                    &apply_result.new_root,
+                    outcome_root,
+                    apply_result.validator_proposals.clone(),
+                    apply_result.total_gas_burnt,
+                    *gas_limit,
+                    apply_result.total_balance_burnt,
+                );
+                Ok(Some((*shard_uid, chunk_extra)))
+            }
+            ApplyChunkResult::DifferentHeight(DifferentHeightResult {
+                shard_uid,
+                apply_result,
+                ..
+            }) => {
+                let mut chunk_extra =
+                    self.chain_store_update.get_chunk_extra(prev_block_hash, shard_uid)?.clone();
+                *chunk_extra.state_root_mut() = apply_result.new_root;
+                Ok(Some((*shard_uid, chunk_extra)))
+            }
+            ApplyChunkResult::SplitState(_) => Ok(None),
+        }
+    }
+
+    /// Saves the link from the block to the previous block with a new chunk for the shard.
+    fn save_prev_block_with_new_chunk(
+        &mut self,
+        block_hash: &CryptoHash,
+        prev_block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<(), Error> {
+        let prev_block_with_new_chunk = self.chain_store_update.get_last_block_with_new_chunk(
+            &*self.runtime_adapter,
+            prev_block_hash,
+            shard_id,
+        )?;
+        self.chain_store_update.save_prev_block_with_new_chunk(
+            block_hash,
+            shard_id,
+            prev_block_with_new_chunk,
+        );
+        Ok(())
+    }
+
+    /// Records a chunk applied by this update for the chain event handlers, if there are any.
+    fn record_chunk_applied(
+        &mut self,
+        block_hash: CryptoHash,
+        shard_id: ShardId,
+        is_new_chunk: bool,
+        apply_result: &ApplyTransactionResult,
+    ) {
+        let subscription = match self.chunk_event_subscription {
+            Some(subscription) => subscription,
+            None => return,
+        };
+        self.chunk_applied_events.push(ChunkAppliedEvent {
+            block_hash,
+            shard_id,
+            is_new_chunk,
+            new_state_root: apply_result.new_root,
+            outcomes: subscription.outcomes.then(|| apply_result.outcomes.clone()),
+            state_changes: subscription
+                .state_changes
+                .then(|| apply_result.trie_changes.state_changes().to_vec()),
+        });
+    }
+
+    /// Reports the time it took to apply a chunk of the block.
+    fn record_chunk_apply_duration(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        apply_duration: TimeDuration,
+    ) {
+        metrics::CHUNK_APPLY_TIME
+            .with_label_values(&[&shard_id.to_string()])
+            .observe(apply_duration.as_secs_f64());
+        if apply_duration > SLOW_CHUNK_APPLY_THRESHOLD {
+            debug!(target: "chain", "Applying chunk of shard {} in block {} took {:?}", shard_id, block_hash, apply_duration);
+        }
+        self.chunk_apply_durations.push((shard_id, apply_duration));
+    }
+
     fn process_apply_chunk_result(
         &mut self,
         result: ApplyChunkResult,
@@ -4036,10 +8496,13 @@ impl<'a> ChainUpdate<'a> {
                 shard_uid,
                 apply_result,
                 apply_split_result_or_state_changes,
+                apply_duration,
             }) => {
                 let (outcome_root, outcome_paths) =
                     ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
                 let shard_id = shard_uid.shard_id();
+                self.record_chunk_apply_duration(&block_hash, shard_id, apply_duration);
+                self.record_chunk_applied(block_hash, shard_id, true, &apply_result);
 
                 // Save state root after applying transactions.
                 self.chain_store_update.save_chunk_extra(
@@ -4055,6 +8518,7 @@ impl<'a> ChainUpdate<'a> {
                     ),
                 );
                 self.chain_store_update.save_trie_changes(apply_result.trie_changes);
+                self.save_prev_block_with_new_chunk(&block_hash, &prev_block_hash, shard_id)?;
                 self.chain_store_update.save_outgoing_receipt(
                     &block_hash,
                     shard_id,
@@ -4080,7 +8544,10 @@ impl<'a> ChainUpdate<'a> {
                 shard_uid,
                 apply_result,
                 apply_split_result_or_state_changes,
+                apply_duration,
             }) => {
+                self.record_chunk_apply_duration(&block_hash, shard_uid.shard_id(), apply_duration);
+                self.record_chunk_applied(block_hash, shard_uid.shard_id(), false, &apply_result);
                 let mut new_extra =
                     self.chain_store_update.get_chunk_extra(&prev_block_hash, &shard_uid)?.clone();
 
@@ -4088,6 +8555,11 @@ impl<'a> ChainUpdate<'a> {
 
                 self.chain_store_update.save_chunk_extra(&block_hash, &shard_uid, new_extra);
                 self.chain_store_update.save_trie_changes(apply_result.trie_changes);
+                self.save_prev_block_with_new_chunk(
+                    &block_hash,
+                    &prev_block_hash,
+                    shard_uid.shard_id(),
+                )?;
 
                 if let Some(apply_results_or_state_changes) = apply_split_result_or_state_changes {
                     self.process_split_state(
@@ -4116,8 +8588,25 @@ impl<'a> ChainUpdate<'a> {
         block: &Block,
     ) -> Result<bool, Error> {
         let prev_hash = *block.header().prev_hash();
-        let shards_to_dl =
-            Chain::get_shards_to_dl_state(self.runtime_adapter.clone(), me, &prev_hash)?;
+        let shard_care = self.cares_about_shards(me, &prev_hash)?;
+        let mut shards_to_dl =
+            Chain::get_shards_to_dl_state(&*self.runtime_adapter, &shard_care, &prev_hash);
+        // Shards quarantined in an earlier epoch are downloaded again if we still track them.
+        let epoch_id = block.header().epoch_id();
+        let shard_layout = self.runtime_adapter.get_shard_layout(epoch_id)?;
+        for (shard_uid, quarantine) in
+            self.chain_store_update.get_chain_store().get_quarantined_shards()?
+        {
+            let shard_id = shard_uid.shard_id();
+            if quarantine.epoch_id != *epoch_id
+                && shard_layout.get_shard_uids().contains(&shard_uid)
+                && shard_care.cares_about_shard(shard_id)
+                && !shards_to_dl.contains(&shard_id)
+            {
+                shards_to_dl.push(shard_id);
+            }
+        }
+        shards_to_dl.sort();
         let prev_block = self.chain_store_update.get_block(&prev_hash)?;
 
         if prev_block.chunks().len() != block.chunks().len() && !shards_to_dl.is_empty() {
@@ -4147,10 +8636,10 @@ impl<'a> ChainUpdate<'a> {
                 shards: shards_to_dl
                     .iter()
                     .map(|shard_id| {
-                        let chunk = &prev_block.chunks()[*shard_id as usize];
-                        ShardInfo(*shard_id, chunk.chunk_hash())
+                        let chunk = prev_block.chunk_for_shard(*shard_id)?;
+                        Ok(ShardInfo(*shard_id, chunk.chunk_hash()))
                     })
-                    .collect(),
+                    .collect::<Result<_, Error>>()?,
             };
 
             self.chain_store_update.add_state_dl_info(state_dl_info);
@@ -4179,12 +8668,33 @@ impl<'a> ChainUpdate<'a> {
             return Err(ErrorKind::EpochOutOfBounds(block.header().epoch_id().clone()).into());
         }
 
+        // Refuse blocks the binary may not be able to apply correctly.
+        let epoch_version =
+            self.runtime_adapter.get_epoch_protocol_version(block.header().epoch_id())?;
+        if epoch_version > PROTOCOL_VERSION {
+            return Err(ErrorKind::UnsupportedProtocolVersion {
+                epoch_version,
+                binary_version: PROTOCOL_VERSION,
+            }
+            .into());
+        }
+
         if block.chunks().len()
             != self.runtime_adapter.num_shards(block.header().epoch_id())? as usize
         {
             return Err(ErrorKind::IncorrectNumberOfChunkHeaders.into());
         }
 
+        // Refuse oversized blocks before doing anything expensive with them.
+        let block_size = block.get_inner().try_to_vec()?.len();
+        if block_size > self.validation_config.block_limits_config.max_block_serialized_bytes {
+            return Err(ErrorKind::BlockTooLarge(
+                block_size,
+                self.validation_config.block_limits_config.max_block_serialized_bytes,
+            )
+            .into());
+        }
+
         // Check if we have already processed this block previously.
         check_known(self, block.header().hash())?
             .map_err(|e| Error::from(ErrorKind::BlockKnown(e)))?;
@@ -4270,8 +8780,13 @@ impl<'a> ChainUpdate<'a> {
         }
 
         let res = block.validate_with(|block| {
-            Chain::validate_block_impl(self.runtime_adapter.as_ref(), self.genesis, block)
-                .map(|_| true)
+            Chain::validate_block_impl(
+                self.runtime_adapter.as_ref(),
+                self.genesis,
+                block,
+                self.validation_config.trusted_chain_replay,
+            )
+            .map(|_| true)
         });
         if let Err(e) = res {
             byzantine_assert!(false);
@@ -4280,11 +8795,12 @@ impl<'a> ChainUpdate<'a> {
 
         let protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(block.header().epoch_id())?;
+        let height = block.header().height();
         if !block.verify_gas_price(
             prev_gas_price,
-            self.block_economics_config.min_gas_price(protocol_version),
-            self.block_economics_config.max_gas_price(protocol_version),
-            self.block_economics_config.gas_price_adjustment_rate(protocol_version),
+            self.block_economics_config.min_gas_price(protocol_version, height),
+            self.block_economics_config.max_gas_price(protocol_version, height),
+            self.block_economics_config.gas_price_adjustment_rate(protocol_version, height),
         ) {
             byzantine_assert!(false);
             return Err(ErrorKind::InvalidGasPrice.into());
@@ -4311,16 +8827,46 @@ impl<'a> ChainUpdate<'a> {
                 }
             }
         }
+        // The header is validated separately from the body, so make sure that the chunk mask
+        // agrees with the chunks actually included in this block.
+        for (shard_id, (chunk_header, &included)) in
+            block.chunks().iter().zip(block.header().chunk_mask().iter()).enumerate()
+        {
+            if (chunk_header.height_included() == block.header().height()) != included {
+                return Err(ErrorKind::InvalidChunkMask(Some(shard_id as ShardId)).into());
+            }
+        }
 
         // If we have the state for shards in the next epoch already downloaded, apply the state transition
         // for these states as well
         // otherwise put the block into the permanent storage, waiting for be caught up
+        // Challenges are only validated here, blocks they invalidate are marked once this block
+        // is accepted.
+        let validated_challenges = self.validate_challenges(
+            block.challenges(),
+            block.header().epoch_id(),
+            block.header().prev_hash(),
+        )?;
         let apply_chunk_work = if is_caught_up {
-            self.apply_chunks_preprocessing(me, block, &prev_block, ApplyChunksMode::IsCaughtUp)?
+            self.apply_chunks_preprocessing(
+                me,
+                block,
+                &prev_block,
+                challenges_result(&validated_challenges),
+                ApplyChunksMode::IsCaughtUp,
+            )?
         } else {
             debug!("Add block to catch up {:?} {:?}", prev_hash, *block.hash());
             self.chain_store_update.add_block_to_catchup(prev_hash, *block.hash());
-            self.apply_chunks_preprocessing(me, block, &prev_block, ApplyChunksMode::NotCaughtUp)?
+            let deferred_shards = self.shards_deferred_to_catchup(me, block)?;
+            self.chain_store_update.save_partially_applied_block(block.hash(), &deferred_shards)?;
+            self.apply_chunks_preprocessing(
+                me,
+                block,
+                &prev_block,
+                challenges_result(&validated_challenges),
+                ApplyChunksMode::NotCaughtUp,
+            )?
         };
 
         self.apply_chunks_and_process_results(block, &prev_block, apply_chunk_work)?;
@@ -4365,10 +8911,17 @@ impl<'a> ChainUpdate<'a> {
         // Add validated block to the db, even if it's not the canonical fork.
         self.chain_store_update.save_block(block.clone().into_inner());
         self.chain_store_update.inc_block_refcount(block.header().prev_hash())?;
+        self.save_epoch_summary_counters(block, prev_height)?;
+
+        // The block is accepted, so its header can become the header head together with the
+        // rest of the changes.
+        self.update_header_head_if_not_challenged(block.header())?;
 
         // Update the chain head if it's the new tip
         let res = self.update_head(block.header())?;
 
+        self.apply_challenge_results(&validated_challenges, Some(block.hash()))?;
+
         if res.is_some() {
             // On the epoch switch record the epoch light client block
             // Note that we only do it if `res.is_some()`, i.e. if the current block is the head.
@@ -4387,11 +8940,76 @@ impl<'a> ChainUpdate<'a> {
                         .save_epoch_light_client_block(&prev_epoch_id.0, light_client_block);
                 }
             }
+            // The first two epochs share the same id, so the epoch start is checked separately.
+            // The summary is absent if the epoch started before the counters were recorded.
+            if self.runtime_adapter.is_next_block_epoch_start(&prev_hash)? {
+                if let Some(summary) = self
+                    .chain_store_update
+                    .get_chain_store()
+                    .get_epoch_summary_counters(&prev_hash)?
+                {
+                    self.chain_store_update.save_epoch_summary(&summary)?;
+                }
+            }
         }
 
         Ok(res)
     }
 
+    /// Saves the approvals included in a block produced by this node, see
+    /// `Chain::get_approval_audit`.
+    fn save_approval_audit(&mut self, header: &BlockHeader) -> Result<(), Error> {
+        let approvers =
+            self.runtime_adapter.get_epoch_block_approvers_ordered(header.prev_hash())?;
+        let prev_height = self.get_previous_header(header)?.height();
+        let record = ApprovalAuditRecord::new(header, prev_height, &approvers);
+        self.chain_store_update.save_approval_audit(header.hash(), &record)
+    }
+
+    /// Saves the totals of the epoch of the block up to and including it, if the ones of the
+    /// previous block are known or the block starts an epoch. The first epoch starts after
+    /// genesis.
+    fn save_epoch_summary_counters(
+        &mut self,
+        block: &Block,
+        prev_height: BlockHeight,
+    ) -> Result<(), Error> {
+        let prev_hash = block.header().prev_hash();
+        let counters = if self.runtime_adapter.is_next_block_epoch_start(prev_hash)?
+            || prev_height == self.chain_store_update.get_genesis_height()
+        {
+            let epoch_height = self.runtime_adapter.get_epoch_height_from_prev_block(prev_hash)?;
+            Some(EpochSummary::new(epoch_height, *block.hash()))
+        } else {
+            self.chain_store_update.get_chain_store().get_epoch_summary_counters(prev_hash)?
+        };
+        let mut counters = match counters {
+            Some(counters) => counters,
+            None => return Ok(()),
+        };
+        let height = block.header().height();
+        let mut num_txs = 0;
+        let mut gas_used = 0;
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() == height {
+                if let Ok(chunk) = self.chain_store_update.get_chunk(&chunk_header.chunk_hash()) {
+                    num_txs += chunk.transactions().len() as u64;
+                }
+                // The gas used in the chunk header is the one of the previous chunk of the shard.
+                let shard_uid = self
+                    .runtime_adapter
+                    .shard_id_to_uid(chunk_header.shard_id(), block.header().epoch_id())?;
+                if let Ok(chunk_extra) =
+                    self.chain_store_update.get_chunk_extra(block.hash(), &shard_uid)
+                {
+                    gas_used += chunk_extra.gas_used();
+                }
+            }
+        }
+        counters.add_block(block, num_txs, gas_used);
+        self.chain_store_update.save_epoch_summary_counters(block.hash(), &counters)
+    }
+
     pub fn create_light_client_block(
         &mut self,
         header: &BlockHeader,
@@ -4422,6 +9040,8 @@ impl<'a> ChainUpdate<'a> {
 
     /// Process a block header as part of processing a full block.
     /// We want to be sure the header is valid before processing the full block.
+    /// The header head is only updated once the whole block is accepted, so that it doesn't
+    /// point to a block whose body turns out to be invalid.
     fn process_header_for_block(
         &mut self,
         header: &BlockHeader,
@@ -4430,19 +9050,66 @@ impl<'a> ChainUpdate<'a> {
     ) -> Result<(), Error> {
         self.validate_header(header, provenance, on_challenge)?;
         self.chain_store_update.save_block_header(header.clone())?;
-        self.update_header_head_if_not_challenged(header)?;
         Ok(())
     }
 
+    /// Records the block of a validated header in the epoch manager. The returned update has to
+    /// be committed together with the header.
+    fn header_epoch_manager_update(&mut self, header: &BlockHeader) -> Result<StoreUpdate, Error> {
+        let last_finalized_height =
+            self.chain_store_update.get_block_height(header.last_final_block())?;
+        self.runtime_adapter
+            .add_validator_proposals(BlockHeaderInfo::new(header, last_finalized_height))
+    }
+
+    /// Verifies the approvals deferred while validating headers, see `deferred_approvals`.
+    /// Returns the hash of the first header with invalid or not enough approvals, if any, with
+    /// the error for it.
+    fn verify_deferred_approvals(&mut self) -> Result<Option<(CryptoHash, Error)>, Error> {
+        let deferred_approvals = match self.deferred_approvals.as_mut() {
+            Some(deferred_approvals) if !deferred_approvals.approvals.is_empty() => {
+                std::mem::take(deferred_approvals)
+            }
+            _ => return Ok(None),
+        };
+        let results = self.runtime_adapter.verify_approvals_and_threshold_batch(
+            self.doomslug_threshold_mode,
+            &deferred_approvals.approvals,
+        )?;
+        Ok(deferred_approvals
+            .header_hashes
+            .into_iter()
+            .zip(results)
+            .find_map(|(header_hash, result)| result.err().map(|err| (header_hash, err))))
+    }
+
     fn validate_header(
         &mut self,
         header: &BlockHeader,
         provenance: &Provenance,
         on_challenge: &mut dyn FnMut(ChallengeBody),
     ) -> Result<(), Error> {
-        // Refuse blocks from the too distant future.
-        if header.timestamp() > Clock::utc() + Duration::seconds(ACCEPTABLE_TIME_DIFFERENCE) {
-            return Err(ErrorKind::InvalidBlockFutureTime(header.timestamp()).into());
+        // Refuse blocks from the too distant future. Blocks only slightly ahead are likely
+        // caused by the local clock being behind, those are held back until it catches up.
+        let max_timestamp = Clock::utc() + Duration::seconds(ACCEPTABLE_TIME_DIFFERENCE);
+        if header.timestamp() > max_timestamp {
+            let grace_period = Duration::from_std(self.future_block_grace_period)
+                .unwrap_or_else(|_| Duration::zero());
+            if header.timestamp() > max_timestamp + grace_period {
+                return Err(ErrorKind::InvalidBlockFutureTime(header.timestamp()).into());
+            }
+            if !self.runtime_adapter.verify_header_signature(header)? {
+                return Err(ErrorKind::InvalidSignature.into());
+            }
+            metrics::BLOCKS_AHEAD_OF_CLOCK.inc();
+            warn!(
+                target: "chain",
+                "Block {} at {} is {}s ahead of the local clock, the clock of this node may be behind",
+                header.hash(),
+                header.height(),
+                (header.timestamp() - max_timestamp).num_seconds() + ACCEPTABLE_TIME_DIFFERENCE,
+            );
+            return Err(ErrorKind::BlockAheadOfClock(header.timestamp()).into());
         }
 
         // First I/O cost, delay as much as possible.
@@ -4452,30 +9119,40 @@ impl<'a> ChainUpdate<'a> {
 
         // Check we don't know a block with given height already.
         // If we do - send out double sign challenge and keep going as double signed blocks are valid blocks.
-        if let Ok(epoch_id_to_blocks) = self
+        if let Ok(block_hashes) = self
             .chain_store_update
             .get_chain_store()
-            .get_all_block_hashes_by_height(header.height())
-            .map(Clone::clone)
+            .get_ordered_block_hashes_by_height(header.height())
         {
-            // Check if there is already known block of the same height that has the same epoch id
-            if let Some(block_hashes) = epoch_id_to_blocks.get(header.epoch_id()) {
-                // This should be guaranteed but it doesn't hurt to check again
-                if !block_hashes.contains(header.hash()) {
-                    let other_header = self
-                        .chain_store_update
-                        .get_block_header(block_hashes.iter().next().unwrap())?;
-
-                    on_challenge(ChallengeBody::BlockDoubleSign(BlockDoubleSign {
-                        left_block_header: header.try_to_vec().expect("Failed to serialize"),
-                        right_block_header: other_header.try_to_vec().expect("Failed to serialize"),
-                    }));
+            // This should be guaranteed but it doesn't hurt to check again
+            if !block_hashes.contains(header.hash()) {
+                // Check if there is already a known block of the same height and epoch id
+                for block_hash in block_hashes {
+                    let other_header = self.chain_store_update.get_block_header(&block_hash)?;
+                    if other_header.epoch_id() == header.epoch_id() {
+                        on_challenge(ChallengeBody::BlockDoubleSign(BlockDoubleSign {
+                            left_block_header: header.try_to_vec().expect("Failed to serialize"),
+                            right_block_header: other_header
+                                .try_to_vec()
+                                .expect("Failed to serialize"),
+                        }));
+                        break;
+                    }
                 }
             }
         }
 
         let prev_header = self.get_previous_header(header)?.clone();
 
+        // Refuse headers that build on top of a challenged block.
+        if self.chain_store_update.is_block_challenged(header.hash())?
+            || self
+                .chain_store_update
+                .is_descendant_of_challenged(header.prev_hash(), MAX_CHALLENGED_ANCESTORS_CHECK)?
+        {
+            return Err(ErrorKind::ChallengedBlock.into());
+        }
+
         // Check that epoch_id in the header does match epoch given previous header (only if previous header is present).
         let epoch_id_from_prev_block =
             &self.runtime_adapter.get_epoch_id_from_prev_block(header.prev_hash())?;
@@ -4509,17 +9186,20 @@ impl<'a> ChainUpdate<'a> {
         }
 
         if header.chunk_mask().len() as u64 != self.runtime_adapter.num_shards(header.epoch_id())? {
-            return Err(ErrorKind::InvalidChunkMask.into());
+            return Err(ErrorKind::InvalidChunkMask(None).into());
         }
 
         if !header.verify_chunks_included() {
-            return Err(ErrorKind::InvalidChunkMask.into());
+            return Err(ErrorKind::InvalidChunkMask(None).into());
         }
 
-        if let Some(prev_height) = header.prev_height() {
-            if prev_height != prev_header.height() {
-                return Err(ErrorKind::Other("Invalid prev_height".to_string()).into());
+        match header.prev_height() {
+            Some(prev_height) => {
+                if prev_height != prev_header.height() {
+                    return Err(ErrorKind::Other("Invalid prev_height".to_string()).into());
+                }
             }
+            None => self.check_prev_height_optional(header)?,
         }
 
         // Prevent time warp attacks and some timestamp manipulations by forcing strict
@@ -4534,28 +9214,33 @@ impl<'a> ChainUpdate<'a> {
         // If this is not the block we produced (hence trust in it) - validates block
         // producer, confirmation signatures and finality info.
         if *provenance != Provenance::PRODUCED {
-            // first verify aggregated signature
-            if !self.runtime_adapter.verify_approval(
-                prev_header.hash(),
-                prev_header.height(),
-                header.height(),
-                header.approvals(),
-            )? {
-                return Err(ErrorKind::InvalidApprovals.into());
-            };
+            // first verify aggregated signature, then the approval threshold
+            if let Some(deferred_approvals) = self.deferred_approvals.as_mut() {
+                deferred_approvals.header_hashes.push(*header.hash());
+                deferred_approvals.approvals.push(BlockApprovals::new(header, &prev_header));
+            } else {
+                if !self.runtime_adapter.verify_approval(
+                    prev_header.hash(),
+                    prev_header.height(),
+                    header.height(),
+                    header.approvals(),
+                )? {
+                    return Err(ErrorKind::InvalidApprovals.into());
+                };
 
-            let stakes = self
-                .runtime_adapter
-                .get_epoch_block_approvers_ordered(header.prev_hash())?
-                .iter()
-                .map(|(x, is_slashed)| (x.stake_this_epoch, x.stake_next_epoch, *is_slashed))
-                .collect();
-            if !Doomslug::can_approved_block_be_produced(
-                self.doomslug_threshold_mode,
-                header.approvals(),
-                &stakes,
-            ) {
-                return Err(ErrorKind::NotEnoughApprovals.into());
+                let stakes = self
+                    .runtime_adapter
+                    .get_epoch_block_approvers_ordered(header.prev_hash())?
+                    .iter()
+                    .map(|(x, is_slashed)| (x.stake_this_epoch, x.stake_next_epoch, *is_slashed))
+                    .collect();
+                if !Doomslug::can_approved_block_be_produced(
+                    self.doomslug_threshold_mode,
+                    header.approvals(),
+                    &stakes,
+                ) {
+                    return Err(ErrorKind::NotEnoughApprovals.into());
+                }
             }
 
             let expected_last_ds_final_block = if prev_header.height() + 1 == header.height() {
@@ -4589,13 +9274,29 @@ impl<'a> ChainUpdate<'a> {
         Ok(())
     }
 
+    /// Headers of epochs with `BlockHeaderV3` enabled always carry `prev_height`, so a header
+    /// without it is only valid in an earlier epoch.
+    fn check_prev_height_optional(&self, header: &BlockHeader) -> Result<(), Error> {
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(header.epoch_id())?;
+        if ProtocolFeature::BlockHeaderV3.protocol_version() <= protocol_version {
+            return Err(ErrorKind::Other(format!(
+                "Missing prev_height in header version {} of protocol version {}",
+                header.version(),
+                protocol_version
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     #[allow(dead_code)]
     fn verify_orphan_header_approvals(&mut self, header: &BlockHeader) -> Result<(), Error> {
         let prev_hash = header.prev_hash();
         let prev_height = match header.prev_height() {
             None => {
-                // this will accept orphans of V1 and V2
-                // TODO: reject header V1 and V2 after a certain height
+                // this will accept orphans of V1 and V2 from epochs before `BlockHeaderV3`
+                self.check_prev_height_optional(header)?;
                 return Ok(());
             }
             Some(prev_height) => prev_height,
@@ -4620,6 +9321,12 @@ impl<'a> ChainUpdate<'a> {
     ) -> Result<Option<Tip>, Error> {
         let header_head = self.chain_store_update.header_head()?;
         if header.height() > header_head.height {
+            if self
+                .chain_store_update
+                .is_descendant_of_challenged(header.hash(), MAX_CHALLENGED_ANCESTORS_CHECK)?
+            {
+                return Err(ErrorKind::ChallengedBlock.into());
+            }
             let tip = Tip::from_header(header);
             self.chain_store_update.save_header_head_if_not_challenged(&tip)?;
             debug!(target: "chain", "Header head updated to {} at {}", tip.last_block_hash, tip.height);
@@ -4799,19 +9506,26 @@ impl<'a> ChainUpdate<'a> {
 
         let (outcome_root, outcome_proofs) =
             ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
-
-        self.chain_store_update.save_chunk(chunk);
-
-        self.chain_store_update.save_trie_changes(apply_result.trie_changes);
         let chunk_extra = ChunkExtra::new(
             &apply_result.new_root,
             outcome_root,
-            apply_result.validator_proposals,
+            apply_result.validator_proposals.clone(),
             apply_result.total_gas_burnt,
             gas_limit,
             apply_result.total_balance_burnt,
         );
         let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, block_header.epoch_id())?;
+        self.chain_store_update.save_chunk(chunk);
+
+        let is_new_chunk_extra =
+            self.is_new_state_sync_chunk_extra(block_header.hash(), &shard_uid, &chunk_extra)?;
+        if is_new_chunk_extra {
+            self.record_chunk_applied(*block_header.hash(), shard_id, true, &apply_result);
+        }
+        self.chain_store_update.save_trie_changes(apply_result.trie_changes);
+        if !is_new_chunk_extra {
+            return Ok(());
+        }
         self.chain_store_update.save_chunk_extra(block_header.hash(), &shard_uid, chunk_extra);
 
         self.chain_store_update.save_outgoing_receipt(
@@ -4880,49 +9594,83 @@ impl<'a> ChainUpdate<'a> {
             None,
         )?;
 
-        self.chain_store_update.save_trie_changes(apply_result.trie_changes);
         *chunk_extra.state_root_mut() = apply_result.new_root;
+        let is_new_chunk_extra =
+            self.is_new_state_sync_chunk_extra(block_header.hash(), &shard_uid, &chunk_extra)?;
+        if is_new_chunk_extra {
+            self.record_chunk_applied(*block_header.hash(), shard_id, false, &apply_result);
+        }
+        self.chain_store_update.save_trie_changes(apply_result.trie_changes);
+        if !is_new_chunk_extra {
+            return Ok(true);
+        }
 
         self.chain_store_update.save_chunk_extra(block_header.hash(), &shard_uid, chunk_extra);
         Ok(true)
     }
 
-    /// Returns correct / malicious challenges or Error if any challenge is invalid.
-    pub fn verify_challenges(
+    /// Checks the chunk extra computed while finalizing state sync against the one saved for the
+    /// block, if any. Returns false if the same extra has already been saved by an interrupted
+    /// finalization, in which case the rest of the results of the application must not be saved
+    /// again. Trie changes are saved regardless, so that the refcounts of the trie nodes and the
+    /// data used by GC to revert them stay consistent with the chunk extra.
+    fn is_new_state_sync_chunk_extra(
         &mut self,
-        challenges: &Vec<Challenge>,
+        block_hash: &CryptoHash,
+        shard_uid: &ShardUId,
+        chunk_extra: &ChunkExtra,
+    ) -> Result<bool, Error> {
+        match self.chain_store_update.get_chunk_extra(block_hash, shard_uid) {
+            Ok(saved_chunk_extra) if saved_chunk_extra == chunk_extra => Ok(false),
+            Ok(_) => Err(ErrorKind::StateSyncChunkExtraMismatch {
+                block_hash: *block_hash,
+                shard_id: shard_uid.shard_id as ShardId,
+            }
+            .into()),
+            Err(err) => match err.kind() {
+                ErrorKind::DBNotFoundErr(_) => Ok(true),
+                _ => Err(err),
+            },
+        }
+    }
+
+    /// Returns correct / malicious challenges or Error if any challenge is invalid.
+    /// Validates given challenges without changing anything in the store.
+    /// Results have to be passed to `apply_challenge_results` once the challenges are accepted.
+    pub fn validate_challenges(
+        &self,
+        challenges: &[Challenge],
         epoch_id: &EpochId,
         prev_block_hash: &CryptoHash,
-        block_hash: Option<&CryptoHash>,
-    ) -> Result<ChallengesResult, Error> {
+    ) -> Result<Vec<ValidatedChallenge>, Error> {
         debug!(target: "chain", "Verifying challenges {:?}", challenges);
         let mut result = vec![];
         for challenge in challenges.iter() {
-            match validate_challenge(&*self.runtime_adapter, epoch_id, prev_block_hash, challenge) {
-                Ok((hash, account_ids)) => {
-                    let is_double_sign = match challenge.body {
-                        // If it's double signed block, we don't invalidate blocks just slash.
-                        ChallengeBody::BlockDoubleSign(_) => true,
-                        _ => {
-                            self.mark_block_as_challenged(&hash, block_hash)?;
-                            false
-                        }
-                    };
-                    let slash_validators: Vec<_> = account_ids
-                        .into_iter()
-                        .map(|id| SlashedValidator::new(id, is_double_sign))
-                        .collect();
-                    result.extend(slash_validators);
-                }
-                Err(ref err) if err.kind() == ErrorKind::MaliciousChallenge => {
-                    result.push(SlashedValidator::new(challenge.account_id.clone(), false));
-                }
-                Err(err) => return Err(err),
-            }
+            result.push(validate_single_challenge(
+                &*self.runtime_adapter,
+                epoch_id,
+                prev_block_hash,
+                challenge,
+            )?);
         }
         Ok(result)
     }
 
+    /// Marks blocks invalidated by validated challenges as challenged.
+    /// `block_hash` is the block which included the challenges, if any.
+    pub fn apply_challenge_results(
+        &mut self,
+        validated_challenges: &[ValidatedChallenge],
+        block_hash: Option<&CryptoHash>,
+    ) -> Result<(), Error> {
+        for challenged_block in
+            validated_challenges.iter().filter_map(|challenge| challenge.challenged_block.as_ref())
+        {
+            self.mark_block_as_challenged(challenged_block, block_hash)?;
+        }
+        Ok(())
+    }
+
     /// Verify header signature when the epoch is known, but not the whole chain.
     /// Same as verify_header_signature except it does not verify that block producer hasn't been slashed
     fn partial_verify_orphan_header_signature(&self, header: &BlockHeader) -> Result<bool, Error> {
@@ -4953,6 +9701,50 @@ where
     receipt_proofs.into_iter().flat_map(|ReceiptProof(receipts, _)| receipts).cloned().collect()
 }
 
+/// Checks that the receipts of a proof included in the chunk of `chunk_shard_id` are part of the
+/// outgoing receipts of that chunk, given the outgoing receipts roots of the new chunks by shard.
+fn verify_incoming_receipt_proof(
+    outgoing_receipts_roots: &HashMap<ShardId, CryptoHash>,
+    chunk_shard_id: ShardId,
+    receipt_proof: &ReceiptProof,
+) -> Result<(), Error> {
+    let ReceiptProof(receipts, ShardProof { from_shard_id, to_shard_id, proof }) = receipt_proof;
+    let receipts_hash = hash(&ReceiptList(*to_shard_id, receipts).try_to_vec()?);
+    let root = outgoing_receipts_roots.get(from_shard_id);
+    if *from_shard_id != chunk_shard_id
+        || !root.map_or(false, |root| verify_path(*root, proof, &receipts_hash))
+    {
+        return Err(ErrorKind::InvalidIncomingReceiptProof {
+            from_shard_id: *from_shard_id,
+            to_shard_id: *to_shard_id,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Fails generating a state sync response if a consistency check of the local data fails. The
+/// data of this node is corrupted then, which shouldn't take the node down on a peer's request.
+fn check_state_sync_local_data(
+    is_consistent: bool,
+    block_hash: &CryptoHash,
+    shard_id: ShardId,
+    reason: &str,
+) -> Result<(), Error> {
+    if is_consistent {
+        return Ok(());
+    }
+    metrics::STATE_SYNC_LOCAL_DATA_CORRUPTION.inc();
+    error!(target: "sync", "Local data for state sync of shard {} at block {} is corrupted: {}", shard_id, block_hash, reason);
+    byzantine_assert!(false);
+    Err(ErrorKind::StateSyncLocalDataCorrupted {
+        block_hash: *block_hash,
+        shard_id,
+        reason: reason.to_string(),
+    }
+    .into())
+}
+
 pub fn collect_receipts_from_response(
     receipt_proof_response: &[ReceiptProofResponse],
 ) -> Vec<Receipt> {
@@ -4961,6 +9753,52 @@ pub fn collect_receipts_from_response(
     )
 }
 
+/// Collects inputs for applying the chunk of `shard_id` included in `block`, the same way block
+/// processing does for new chunks.
+fn chunk_apply_inputs(
+    chain_store_update: &mut ChainStoreUpdate,
+    runtime_adapter: &dyn RuntimeAdapter,
+    block: &Block,
+    prev_block: &Block,
+    shard_id: ShardId,
+) -> Result<ChunkApplyInputs, Error> {
+    let chunk_header = block.chunk_for_shard(shard_id)?;
+    let prev_chunk_header = Chain::get_prev_chunk_header(runtime_adapter, prev_block, shard_id)?;
+    let shard_uid = runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+    let prev_chunk_extra =
+        chain_store_update.get_chunk_extra(prev_block.hash(), &shard_uid)?.clone();
+    let receipt_proof_response = chain_store_update.get_incoming_receipts_for_shard(
+        shard_id,
+        *block.hash(),
+        prev_chunk_header.height_included(),
+    )?;
+    let receipts = collect_receipts_from_response(&receipt_proof_response);
+    let chunk = chain_store_update.get_chunk_clone_from_header(&chunk_header)?;
+    // We take the first block with existing chunk in the first epoch in which protocol feature
+    // RestoreReceiptsAfterFixApplyChunks was enabled, and put the restored receipts there.
+    let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+        chain_store_update,
+        runtime_adapter,
+        prev_block.hash(),
+        shard_id,
+    )?;
+    Ok(ChunkApplyInputs {
+        chunk,
+        prev_chunk_height_included: prev_chunk_header.height_included(),
+        receipts,
+        gas_price: prev_block.header().gas_price(),
+        prev_chunk_extra,
+        is_first_block_with_chunk_of_version,
+    })
+}
+
+/// Asks the client to stop processing blocks before the node shuts down.
+#[derive(Message)]
+#[rtype(result = "ShutdownReport")]
+pub struct PrepareShutdown {
+    pub timeout: TimeDuration,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct ApplyStatePartsRequest {
@@ -5026,6 +9864,8 @@ pub struct StateSplitResponse {
 /// 4. Results are postprocessed. If there is any error block goes back to pending to try again.
 ///     Otherwise results are commited, block is moved to done blocks and any blocks that
 ///     have this block as previous are added to pending
+/// Store updates from preprocessing are spilled to the store instead of being held in memory once
+/// the ones held in memory exceed `memory_limit_bytes`.
 pub struct BlocksCatchUpState {
     /// Hash of first block of an epoch
     pub first_block_hash: CryptoHash,
@@ -5035,16 +9875,30 @@ pub struct BlocksCatchUpState {
     pub pending_blocks: Vec<CryptoHash>,
     /// Map from block hashes that are scheduled for processing to saved store updates from their
     /// preprocessing
-    pub scheduled_blocks: HashMap<CryptoHash, SavedStoreUpdate>,
+    pub scheduled_blocks: HashMap<CryptoHash, CatchUpStoreUpdate>,
     /// Map from block hashes that were processed to (saved store update, process results)
     pub processed_blocks:
-        HashMap<CryptoHash, (SavedStoreUpdate, Vec<Result<ApplyChunkResult, Error>>)>,
+        HashMap<CryptoHash, (CatchUpStoreUpdate, Vec<Result<ApplyChunkResult, Error>>)>,
     /// Collection of block hashes that are fully processed
     pub done_blocks: Vec<CryptoHash>,
+    /// Maximum estimated size of the saved store updates held in memory
+    pub memory_limit_bytes: u64,
+    /// Estimated size of the saved store updates currently held in memory
+    pub memory_usage_bytes: u64,
+    /// Number of saved store updates spilled to the store so far
+    pub num_spilled_blocks: u64,
+}
+
+/// Saved store update from the preprocessing of a block being caught up.
+pub enum CatchUpStoreUpdate {
+    /// The update is held in memory, along with its estimated size in bytes.
+    InMemory(SavedStoreUpdate, u64),
+    /// The update was spilled to the store, see `ChainStore::spill_saved_store_update`.
+    Spilled,
 }
 
 impl BlocksCatchUpState {
-    pub fn new(first_block_hash: CryptoHash, epoch_id: EpochId) -> Self {
+    pub fn new(first_block_hash: CryptoHash, epoch_id: EpochId, memory_limit_bytes: u64) -> Self {
         Self {
             first_block_hash,
             epoch_id,
@@ -5052,6 +9906,9 @@ impl BlocksCatchUpState {
             scheduled_blocks: HashMap::new(),
             processed_blocks: HashMap::new(),
             done_blocks: vec![],
+            memory_limit_bytes,
+            memory_usage_bytes: 0,
+            num_spilled_blocks: 0,
         }
     }
 
@@ -5061,3 +9918,9 @@ impl BlocksCatchUpState {
             && self.processed_blocks.is_empty()
     }
 }
+
+impl Drop for BlocksCatchUpState {
+    fn drop(&mut self) {
+        metrics::CATCHUP_SAVED_UPDATES_MEMORY_BYTES.sub(self.memory_usage_bytes as i64);
+    }
+}
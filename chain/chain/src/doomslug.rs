@@ -453,6 +453,20 @@ impl Doomslug {
             && (approved_stake2 > threshold2 || threshold2 == 0)
     }
 
+    /// Same as `can_approved_block_be_produced`, given the approvers of the block as returned by
+    /// `RuntimeAdapter::get_epoch_block_approvers_ordered`.
+    pub fn can_block_be_produced_with_approvers(
+        mode: DoomslugThresholdMode,
+        approvals: &[Option<Signature>],
+        approvers: &[(ApprovalStake, bool)],
+    ) -> bool {
+        let stakes = approvers
+            .iter()
+            .map(|(x, is_slashed)| (x.stake_this_epoch, x.stake_next_epoch, *is_slashed))
+            .collect();
+        Self::can_approved_block_be_produced(mode, approvals, &stakes)
+    }
+
     pub fn remove_witness(
         &mut self,
         prev_hash: &CryptoHash,
@@ -0,0 +1,125 @@
+use near_primitives::block::{Block, Tip};
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::ExecutionOutcomeWithId;
+use near_primitives::types::{RawStateChangesWithTrieKey, ShardId, StateRoot};
+
+use crate::types::{BlockStatus, Provenance};
+
+/// Receives the blocks and chunks accepted by a `Chain`, e.g. to stream them to an indexer.
+/// Handlers are called synchronously once the changes are committed to the store, so they
+/// should hand the events over to another thread instead of doing any heavy work.
+///
+/// For every accepted block, the chunks applied by it come first, then the reorg if the block
+/// switched the head to another fork, and then the block itself.
+pub trait ChainEventHandler: Send {
+    fn on_block_accepted(&mut self, _event: BlockAcceptedEvent) {}
+    fn on_reorg(&mut self, _event: ReorgEvent) {}
+    fn on_chunk_applied(&mut self, _event: ChunkAppliedEvent) {}
+}
+
+/// Heavy parts of the events a handler wants to receive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChainEventSubscription {
+    /// Fill `ChunkAppliedEvent::outcomes`.
+    pub outcomes: bool,
+    /// Fill `ChunkAppliedEvent::state_changes`.
+    pub state_changes: bool,
+}
+
+impl ChainEventSubscription {
+    fn union(self, other: ChainEventSubscription) -> ChainEventSubscription {
+        ChainEventSubscription {
+            outcomes: self.outcomes || other.outcomes,
+            state_changes: self.state_changes || other.state_changes,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockAcceptedEvent {
+    pub block: Block,
+    pub status: BlockStatus,
+    pub provenance: Provenance,
+}
+
+/// The head moved from one fork to another.
+#[derive(Clone, Debug)]
+pub struct ReorgEvent {
+    pub old_head: Tip,
+    pub new_head: Tip,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChunkAppliedEvent {
+    pub block_hash: CryptoHash,
+    pub shard_id: ShardId,
+    /// Whether the block contains a new chunk of the shard, otherwise only the end of block
+    /// changes of the previous chunk were applied.
+    pub is_new_chunk: bool,
+    pub new_state_root: StateRoot,
+    /// Outcomes of the transactions and receipts of the chunk, if subscribed to.
+    pub outcomes: Option<Vec<ExecutionOutcomeWithId>>,
+    /// Changes of the state of the shard, if subscribed to.
+    pub state_changes: Option<Vec<RawStateChangesWithTrieKey>>,
+}
+
+/// Handlers registered on a chain, each with its own subscription.
+#[derive(Default)]
+pub(crate) struct ChainEventHandlers {
+    handlers: Vec<(Box<dyn ChainEventHandler>, ChainEventSubscription)>,
+}
+
+impl ChainEventHandlers {
+    pub(crate) fn register(
+        &mut self,
+        handler: Box<dyn ChainEventHandler>,
+        subscription: ChainEventSubscription,
+    ) {
+        self.handlers.push((handler, subscription));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Payloads wanted by any of the handlers, `None` if there are no handlers.
+    pub(crate) fn subscription(&self) -> Option<ChainEventSubscription> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(
+            self.handlers
+                .iter()
+                .fold(ChainEventSubscription::default(), |acc, (_, sub)| acc.union(*sub)),
+        )
+    }
+
+    pub(crate) fn on_block_accepted(&mut self, event: BlockAcceptedEvent) {
+        for (handler, _) in self.handlers.iter_mut() {
+            handler.on_block_accepted(event.clone());
+        }
+    }
+
+    pub(crate) fn on_reorg(&mut self, event: ReorgEvent) {
+        for (handler, _) in self.handlers.iter_mut() {
+            handler.on_reorg(event.clone());
+        }
+    }
+
+    pub(crate) fn on_chunk_applied(&mut self, event: ChunkAppliedEvent) {
+        for (handler, subscription) in self.handlers.iter_mut() {
+            handler.on_chunk_applied(ChunkAppliedEvent {
+                block_hash: event.block_hash,
+                shard_id: event.shard_id,
+                is_new_chunk: event.is_new_chunk,
+                new_state_root: event.new_state_root,
+                outcomes: event.outcomes.as_ref().filter(|_| subscription.outcomes).cloned(),
+                state_changes: event
+                    .state_changes
+                    .as_ref()
+                    .filter(|_| subscription.state_changes)
+                    .cloned(),
+            });
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::{Arc, Mutex, Weak};
+
+use near_primitives::block::{genesis_chunks, Block};
+use near_primitives::hash::CryptoHash;
+use near_primitives::sharding::ShardChunk;
+use near_primitives::types::{EpochId, StateRoot};
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::types::{ChainGenesis, RuntimeAdapter};
+use crate::{Chain, Error};
+
+/// Genesis block info computed so far in this process, see `GenesisBlockInfo::get_or_compute`.
+static GENESIS_BLOCK_INFOS: Lazy<Mutex<Vec<CacheEntry>>> = Lazy::new(|| Mutex::new(vec![]));
+
+struct CacheEntry {
+    /// Keeps the allocation of the runtime adapter alive, so that its address identifies it for
+    /// as long as the entry exists.
+    runtime_adapter: Weak<dyn RuntimeAdapter>,
+    chain_genesis: ChainGenesis,
+    info: Arc<OnceCell<Arc<GenesisBlockInfo>>>,
+}
+
+/// The genesis block with its chunks and the hash of the genesis block producers. Building it
+/// is slow for large validator sets, so it's computed once per runtime adapter and chain genesis
+/// and shared by the chains constructed with them, e.g. the ones of the view client threads.
+pub struct GenesisBlockInfo {
+    pub block: Block,
+    pub chunks: Vec<ShardChunk>,
+    pub bp_hash: CryptoHash,
+}
+
+impl GenesisBlockInfo {
+    /// Builds the genesis block out of the genesis state roots of the runtime, without looking
+    /// at or filling the cache.
+    pub fn new(
+        runtime_adapter: &dyn RuntimeAdapter,
+        chain_genesis: &ChainGenesis,
+        state_roots: Vec<StateRoot>,
+    ) -> Result<Self, Error> {
+        let chunks = genesis_chunks(
+            state_roots,
+            runtime_adapter.num_shards(&EpochId::default())?,
+            chain_genesis.gas_limit,
+            chain_genesis.height,
+            chain_genesis.protocol_version,
+        );
+        let bp_hash = Chain::compute_bp_hash(
+            runtime_adapter,
+            EpochId::default(),
+            EpochId::default(),
+            &CryptoHash::default(),
+        )?;
+        let block = Block::genesis(
+            chain_genesis.protocol_version,
+            chunks.iter().map(|chunk| chunk.cloned_header()).collect(),
+            chain_genesis.time,
+            chain_genesis.height,
+            chain_genesis.min_gas_price,
+            chain_genesis.total_supply,
+            bp_hash,
+        );
+        Ok(GenesisBlockInfo { block, chunks, bp_hash })
+    }
+
+    /// Returns the genesis block info of the runtime adapter and chain genesis, computed by the
+    /// first caller in the process. Concurrent callers wait for it to be computed instead of
+    /// computing it themselves. Entries are dropped once their runtime adapter is.
+    pub fn get_or_compute(
+        runtime_adapter: &Arc<dyn RuntimeAdapter>,
+        chain_genesis: &ChainGenesis,
+        state_roots: &[StateRoot],
+    ) -> Result<Arc<GenesisBlockInfo>, Error> {
+        let info = {
+            let mut entries = GENESIS_BLOCK_INFOS.lock().unwrap();
+            entries.retain(|entry| entry.runtime_adapter.strong_count() > 0);
+            let runtime_adapter_ptr = Arc::as_ptr(runtime_adapter) as *const u8;
+            let entry = entries.iter().find(|entry| {
+                entry.runtime_adapter.as_ptr() as *const u8 == runtime_adapter_ptr
+                    && entry.chain_genesis == *chain_genesis
+            });
+            match entry {
+                Some(entry) => entry.info.clone(),
+                None => {
+                    let info = Arc::new(OnceCell::new());
+                    entries.push(CacheEntry {
+                        runtime_adapter: Arc::downgrade(runtime_adapter),
+                        chain_genesis: chain_genesis.clone(),
+                        info: info.clone(),
+                    });
+                    info
+                }
+            }
+        };
+        let info = info.get_or_try_init(|| {
+            GenesisBlockInfo::new(&**runtime_adapter, chain_genesis, state_roots.to_vec())
+                .map(Arc::new)
+        })?;
+        Ok(info.clone())
+    }
+}
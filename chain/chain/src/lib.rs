@@ -1,22 +1,38 @@
-pub use chain::{check_known, collect_receipts, Chain, MAX_ORPHAN_SIZE};
+pub use chain::{
+    check_known, collect_receipts, Chain, PartProvenance, PeerStatePartStats,
+    StateSyncServingStats, MAX_ORPHAN_SIZE,
+};
 pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
-pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
-pub use near_chain_primitives::{self, Error, ErrorKind};
+pub use lightclient::{
+    create_light_client_block_view, get_epoch_block_producers_view, verify_chain_segment_proof,
+    ChainSegmentProof,
+};
+pub use near_chain_primitives::{self, ChainMisbehavior, Error, ErrorKind};
 pub use near_primitives::receipt::ReceiptResult;
 pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
 pub use store_validator::{ErrorMessage, StoreValidator};
-pub use types::{Block, BlockHeader, BlockStatus, ChainGenesis, Provenance, RuntimeAdapter};
+pub use types::{
+    Block, BlockHeader, BlockSaveMode, BlockStatus, ChainGenesis, Provenance, RequiredChunks,
+    RuntimeAdapter,
+};
 
 pub mod blocks_delay_tracker;
 pub mod chain;
 pub mod crypto_hash_timer;
 mod doomslug;
+pub mod events;
+pub mod genesis_block_info;
 mod lightclient;
 mod metrics;
 pub mod migrations;
 pub mod missing_chunks;
+pub mod shared_chain_cache;
+pub mod state_header_cache;
+#[cfg(feature = "test_features")]
+pub mod state_sync_corruptor;
 mod store;
 pub mod store_validator;
+pub mod sync_hash_registry;
 pub mod test_utils;
 #[cfg(test)]
 mod tests;
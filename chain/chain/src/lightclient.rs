@@ -1,7 +1,10 @@
-use near_chain_primitives::Error;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_chain_primitives::{Error, ErrorKind};
 use near_primitives::block::BlockHeader;
+use near_primitives::block_header::{Approval, ApprovalInner, BlockHeaderInnerLite};
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::types::EpochId;
+use near_primitives::merkle::{combine_hash, verify_hash, MerklePath};
+use near_primitives::types::{Balance, EpochId};
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{BlockHeaderInnerLiteView, LightClientBlockView};
 
@@ -70,3 +73,156 @@ pub fn create_light_client_block_view(
         approvals_after_next,
     })
 }
+
+/// Proof that a segment of the canonical chain is part of the chain approved by the block
+/// producers of the epoch of its first block. Can be checked without a node by
+/// `verify_chain_segment_proof`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ChainSegmentProof {
+    /// Headers of the segment, from its first block to its last block.
+    pub headers: Vec<BlockHeader>,
+    /// Block producers of the epoch of the first block of the segment.
+    pub block_producers: Vec<ValidatorStakeView>,
+    /// Light client blocks of the epochs from the one of the first block of the segment up to the
+    /// one before the epoch of `head`, each with the block producers of the next epoch.
+    pub epoch_light_client_blocks: Vec<LightClientBlockView>,
+    /// Light client block of a final block after the segment.
+    pub head: LightClientBlockView,
+    /// Merkle path of the last block of the segment to the block merkle root of `head`.
+    pub last_block_proof: MerklePath,
+}
+
+/// Hash of the block the light client block is made for.
+pub(crate) fn light_client_block_hash(block: &LightClientBlockView) -> CryptoHash {
+    let inner_lite = BlockHeaderInnerLite::from(block.inner_lite.clone());
+    let inner_lite_hash = hash(&inner_lite.try_to_vec().expect("Failed to serialize"));
+    let inner_hash = combine_hash(&inner_lite_hash, &block.inner_rest_hash);
+    combine_hash(&inner_hash, &block.prev_block_hash)
+}
+
+/// Checks that the light client block is approved by more than 2/3 of the stake of the block
+/// producers of its epoch, and that its next block producers, if any, match the header.
+fn verify_light_client_block(
+    block: &LightClientBlockView,
+    epoch_id: &EpochId,
+    block_producers: &[ValidatorStakeView],
+) -> Result<(), Error> {
+    let block_hash = light_client_block_hash(block);
+    if block.inner_lite.epoch_id != epoch_id.0 {
+        return Err(invalid_chain_segment_proof(format!(
+            "light client block {} is not in epoch {:?}",
+            block_hash, epoch_id
+        )));
+    }
+    if let Some(next_bps) = &block.next_bps {
+        if hash(&next_bps.try_to_vec()?) != block.inner_lite.next_bp_hash {
+            return Err(invalid_chain_segment_proof(format!(
+                "next block producers of light client block {} don't match its header",
+                block_hash
+            )));
+        }
+    }
+
+    let next_block_hash = combine_hash(&block.next_block_inner_hash, &block_hash);
+    let data = Approval::get_data_for_sig(
+        &ApprovalInner::Endorsement(next_block_hash),
+        block.inner_lite.height + 2,
+    );
+    let mut total_stake: Balance = 0;
+    let mut approved_stake: Balance = 0;
+    for (index, block_producer) in block_producers.iter().enumerate() {
+        let block_producer = block_producer.clone().into_validator_stake();
+        total_stake += block_producer.stake();
+        if let Some(Some(signature)) = block.approvals_after_next.get(index) {
+            if !signature.verify(&data, block_producer.public_key()) {
+                return Err(invalid_chain_segment_proof(format!(
+                    "invalid approval of {} for light client block {}",
+                    block_producer.account_id(),
+                    block_hash
+                )));
+            }
+            approved_stake += block_producer.stake();
+        }
+    }
+    if approved_stake * 3 <= total_stake * 2 {
+        return Err(invalid_chain_segment_proof(format!(
+            "light client block {} is not approved by enough stake",
+            block_hash
+        )));
+    }
+    Ok(())
+}
+
+fn invalid_chain_segment_proof(reason: String) -> Error {
+    ErrorKind::InvalidChainSegmentProof(reason).into()
+}
+
+/// Verifies a proof made by `Chain::export_chain_segment_proof`, trusting only the hash of the
+/// block producers of the epoch of the first block of the segment, e.g. the `next_bp_hash` of a
+/// block of the previous epoch or of the genesis.
+///
+/// The headers of the segment are checked to be linked by their hashes, the light client blocks
+/// to be approved by the block producers of their epochs, starting with the trusted ones, and
+/// the last block of the segment to be in the block merkle tree of the head.
+pub fn verify_chain_segment_proof(
+    trusted_bp_hash: &CryptoHash,
+    proof: &ChainSegmentProof,
+) -> Result<(), Error> {
+    let (first_header, last_header) = match (proof.headers.first(), proof.headers.last()) {
+        (Some(first_header), Some(last_header)) => (first_header, last_header),
+        _ => return Err(invalid_chain_segment_proof("the segment is empty".to_string())),
+    };
+    for header in proof.headers.iter() {
+        let block_hash = BlockHeader::compute_hash(
+            *header.prev_hash(),
+            &header.inner_lite_bytes(),
+            &header.inner_rest_bytes(),
+        );
+        if &block_hash != header.hash() {
+            return Err(invalid_chain_segment_proof(format!(
+                "header {} doesn't match its hash",
+                header.hash()
+            )));
+        }
+    }
+    for headers in proof.headers.windows(2) {
+        if headers[1].prev_hash() != headers[0].hash() {
+            return Err(invalid_chain_segment_proof(format!(
+                "header {} doesn't follow header {}",
+                headers[1].hash(),
+                headers[0].hash()
+            )));
+        }
+    }
+
+    if &hash(&proof.block_producers.try_to_vec()?) != trusted_bp_hash {
+        return Err(invalid_chain_segment_proof(
+            "block producers don't match the trusted hash".to_string(),
+        ));
+    }
+    let mut epoch_id = first_header.epoch_id().clone();
+    let mut block_producers = &proof.block_producers;
+    for block in proof.epoch_light_client_blocks.iter() {
+        verify_light_client_block(block, &epoch_id, block_producers)?;
+        block_producers = block.next_bps.as_ref().ok_or_else(|| {
+            invalid_chain_segment_proof(format!(
+                "light client block {} has no next block producers",
+                light_client_block_hash(block)
+            ))
+        })?;
+        epoch_id = EpochId(block.inner_lite.next_epoch_id);
+    }
+    verify_light_client_block(&proof.head, &epoch_id, block_producers)?;
+
+    if !verify_hash(
+        proof.head.inner_lite.block_merkle_root,
+        &proof.last_block_proof,
+        *last_header.hash(),
+    ) {
+        return Err(invalid_chain_segment_proof(format!(
+            "block {} is not in the block merkle tree of the head",
+            last_header.hash()
+        )));
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use near_metrics::{
-    try_create_histogram, try_create_histogram_vec, try_create_int_counter, try_create_int_gauge,
-    Histogram, HistogramVec, IntCounter, IntGauge,
+    try_create_histogram, try_create_histogram_vec, try_create_int_counter,
+    try_create_int_counter_vec, try_create_int_gauge, try_create_int_gauge_vec, Histogram,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -39,6 +40,64 @@ pub static VALIDATOR_ACTIVE_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
 });
 pub static NUM_ORPHANS: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_num_orphans", "Number of orphan blocks.").unwrap());
+pub static BLOCK_READINESS_DELAY: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram(
+        "near_block_readiness_delay",
+        "Time between receiving a block and having all of its chunks",
+    )
+    .unwrap()
+});
+pub static BLOCK_READINESS_DELAY_PERCENTILE_MS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_block_readiness_delay_percentile_ms",
+        "Percentiles of block readiness delays from the last block readiness SLO report",
+        &["percentile"],
+    )
+    .unwrap()
+});
+pub static BLOCK_READINESS_WITHIN_THRESHOLD_PERCENT: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_block_readiness_within_threshold_percent",
+        "Percentage of blocks ready within the threshold from the last block readiness SLO report",
+    )
+    .unwrap()
+});
+pub static BLOCK_FINALITY_LATENCY: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram(
+        "near_block_finality_latency",
+        "Time between accepting a block and the block becoming final",
+    )
+    .unwrap()
+});
+pub static BLOCK_FINALITY_LATENCY_PERCENTILE_MS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_block_finality_latency_percentile_ms",
+        "Percentiles of the time to finality of recently finalized blocks",
+        &["percentile"],
+    )
+    .unwrap()
+});
+pub static ORPHANS_DROPPED_PER_PREV_HASH_CAP: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_orphans_dropped_per_prev_hash_cap",
+        "Number of orphans dropped because too many orphans with the same prev block are stored",
+    )
+    .unwrap()
+});
+pub static ORPHAN_FANOUT_TRUNCATIONS: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_orphan_fanout_truncations",
+        "Number of times too many orphans were found within the depth from a block",
+    )
+    .unwrap()
+});
+pub static OUTGOING_RECEIPTS_ROOT_MISMATCHES: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_outgoing_receipts_root_mismatches",
+        "Number of new chunks claiming an outgoing receipts root different from the local one",
+    )
+    .unwrap()
+});
 pub static HEADER_HEAD_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_header_head_height", "Height of the header head").unwrap()
 });
@@ -71,3 +130,204 @@ pub static BLOCK_MISSING_CHUNKS_DELAY: Lazy<Histogram> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static SPLIT_STATE_BACKLOG_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_split_state_backlog_size",
+        "Number of (block, shard) pairs with state changes not yet applied to split states",
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_HEADER_GENERATIONS: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_state_sync_header_generations_total",
+        "Number of state sync headers generated to serve state requests",
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_LOCAL_DATA_CORRUPTION: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_state_sync_local_data_corruption",
+        "Number of state requests failed because the local data didn't pass verification",
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_REQUESTS_REJECTED_BUSY: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_state_sync_requests_rejected_busy",
+        "Number of state requests rejected because too many sync hashes were being served",
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_HEADER_DEDUP_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_header_dedup_hits_total",
+        "Number of state sync header requests served from memory instead of being generated",
+        &["source"],
+    )
+    .unwrap()
+});
+pub static RECEIPT_BACKLOG_PROOFS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_receipt_backlog_proofs",
+        "Number of incoming receipt proofs of a shard waiting for its next chunk, as of the head",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+pub static RECEIPT_BACKLOG_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_receipt_backlog_bytes",
+        "Size of the incoming receipt proofs of a shard waiting for its next chunk, as of the head",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_SERVED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_served_requests_total",
+        "Number of state sync headers and parts served to other nodes",
+        &["shard_id", "item"],
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_SERVED_CACHE_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_served_cache_hits_total",
+        "Number of state sync headers and parts served without being generated",
+        &["shard_id", "item"],
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_SERVED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_served_bytes_total",
+        "Total size of the state sync headers and parts served to other nodes",
+        &["shard_id", "item"],
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_SERVE_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_state_sync_serve_latency_seconds",
+        "Time it takes to serve a state sync header or part",
+        &["shard_id", "item"],
+        Some(prometheus::exponential_buckets(0.001, 2.0, 16).unwrap()),
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_SERVE_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_serve_failures_total",
+        "Number of state sync header and part requests which couldn't be served",
+        &["item"],
+    )
+    .unwrap()
+});
+pub static STATE_SYNC_FINAL_HEAD_SOURCE: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_final_head_source_total",
+        "Number of times the final head was reset after state sync, by the block it was reset to",
+        &["source"],
+    )
+    .unwrap()
+});
+pub static CHECK_BLOCKS_WITH_MISSING_CHUNKS_TIME: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram(
+        "near_check_blocks_with_missing_chunks_time",
+        "Time taken to process blocks whose missing chunks arrived, per check",
+    )
+    .unwrap()
+});
+pub static BLOCKS_WITH_MISSING_CHUNKS_DEFERRED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_blocks_with_missing_chunks_deferred_total",
+        "Number of ready blocks deferred to the next check after the time budget was used up",
+    )
+    .unwrap()
+});
+pub static BLOCKS_AHEAD_OF_CLOCK: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_blocks_ahead_of_clock_total",
+        "Number of blocks delayed because their timestamp is ahead of the local clock",
+    )
+    .unwrap()
+});
+pub static VALIDATORS_APPROVALS_INCLUDED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_validators_approvals_included",
+        "Number of accepted blocks of the latest epoch which include an approval of a validator",
+        &["account_id"],
+    )
+    .unwrap()
+});
+pub static VALIDATORS_APPROVALS_MISSING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_validators_approvals_missing",
+        "Number of accepted blocks of the latest epoch which miss an approval of a validator",
+        &["account_id"],
+    )
+    .unwrap()
+});
+pub static CHUNK_INCLUSION_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_inclusion_delay_heights",
+        "Number of heights between the height a new chunk was produced for and the height of the accepted block including it",
+        &["shard_id"],
+        Some(vec![0.0, 1.0, 2.0, 3.0, 5.0, 10.0, 20.0]),
+    )
+    .unwrap()
+});
+pub static CHUNK_APPLY_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_apply_time_seconds",
+        "Time spent applying the transactions and receipts of a chunk",
+        &["shard_id"],
+        Some(prometheus::exponential_buckets(0.001, 1.6, 20).unwrap()),
+    )
+    .unwrap()
+});
+pub static FALLING_BEHIND_PER_SHARD: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_falling_behind_per_shard",
+        "Whether applying the chunks of the shard takes longer on average than the interval between blocks",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+pub static CHUNKS_INCLUDED_LATE: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunks_included_late_total",
+        "Number of chunks of a chunk producer included in an accepted block after the height they were produced for",
+        &["account_id"],
+    )
+    .unwrap()
+});
+pub static CHAIN_STORE_NEGATIVE_CACHE_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chain_store_negative_cache_hits_total",
+        "Number of lookups of blocks and headers answered as missing without reading the database",
+        &["column"],
+    )
+    .unwrap()
+});
+pub static CATCHUP_SAVED_UPDATES_MEMORY_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_catchup_saved_updates_memory_bytes",
+        "Estimated size of the store updates of blocks being caught up which are held in memory",
+    )
+    .unwrap()
+});
+pub static CATCHUP_SAVED_UPDATES_SPILLED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_catchup_saved_updates_spilled_total",
+        "Number of store updates of blocks being caught up which were spilled to disk",
+    )
+    .unwrap()
+});
+pub static NON_DETERMINISTIC_APPLY: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_chain_non_deterministic_apply_total",
+        "Number of chunks which gave a different state root when applied again to build a challenge",
+    )
+    .unwrap()
+});
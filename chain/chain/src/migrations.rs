@@ -1,8 +1,125 @@
-use crate::store::ChainStoreAccess;
+use crate::store::{ChainStore, ChainStoreAccess};
 use crate::types::RuntimeAdapter;
-use near_chain_primitives::error::Error;
+use near_chain_primitives::error::{Error, ErrorKind};
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::ShardId;
+use near_primitives::types::{BlockHeight, ShardId};
+use tracing::info;
+
+/// Migration of the chain data from the version equal to its index in `CHAIN_MIGRATIONS` to the
+/// next version. A migration may be interrupted and run again, so it has to be idempotent.
+pub type ChainMigration = fn(&mut ChainStore) -> Result<(), Error>;
+
+/// Migrations of the chain data, applied in order on startup.
+pub const CHAIN_MIGRATIONS: &[ChainMigration] = &[backfill_prev_block_with_new_chunk];
+
+/// Current version of the chain data layout.
+pub const CHAIN_SCHEMA_VERSION: u32 = CHAIN_MIGRATIONS.len() as u32;
+
+/// Number of heights whose links are committed together by `backfill_prev_block_with_new_chunk`.
+const BACKFILL_BATCH_HEIGHTS: BlockHeight = 1000;
+
+/// Returns the version of the chain data in the store. Stores written before the version was
+/// recorded are at version 0, while a store without a head is new and at the current version.
+pub fn get_chain_schema_version(chain_store: &ChainStore) -> Result<u32, Error> {
+    if let Some(version) = chain_store.get_chain_schema_version()? {
+        return Ok(version);
+    }
+    match chain_store.head() {
+        Ok(_) => Ok(0),
+        Err(err) => match err.kind() {
+            ErrorKind::DBNotFoundErr(_) => Ok(CHAIN_SCHEMA_VERSION),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Applies the pending migrations of the chain data. The version is recorded after each of them,
+/// so an interrupted run continues from the last finished migration.
+pub fn migrate_chain_schema(chain_store: &mut ChainStore) -> Result<(), Error> {
+    let version = get_chain_schema_version(chain_store)?;
+    if version > CHAIN_SCHEMA_VERSION {
+        return Err(ErrorKind::Other(format!(
+            "Chain schema version {} is newer than the supported version {}",
+            version, CHAIN_SCHEMA_VERSION
+        ))
+        .into());
+    }
+    for (version, migration) in CHAIN_MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let to_version = version as u32 + 1;
+        info!(target: "chain", "Migrate chain data from version {} to {}", version, to_version);
+        migration(chain_store)?;
+        save_chain_schema_version(chain_store, to_version)?;
+    }
+    if chain_store.get_chain_schema_version()?.is_none() {
+        save_chain_schema_version(chain_store, CHAIN_SCHEMA_VERSION)?;
+    }
+    Ok(())
+}
+
+fn save_chain_schema_version(chain_store: &mut ChainStore, version: u32) -> Result<(), Error> {
+    let mut chain_store_update = chain_store.store_update();
+    chain_store_update.save_chain_schema_version(version)?;
+    chain_store_update.commit()
+}
+
+/// Version 0 => 1: saves the links to the previous block with a new chunk for the blocks
+/// processed before the links were introduced.
+/// Links are only derived from a previous block in the same epoch, so that shard ids don't have
+/// to be mapped between shard layouts. Lookups still walk the chain where links are missing.
+fn backfill_prev_block_with_new_chunk(chain_store: &mut ChainStore) -> Result<(), Error> {
+    let head_height = chain_store.head()?.height;
+    // The previous block of the tail block is already garbage collected.
+    let mut batch_start = chain_store.tail()?.max(chain_store.get_genesis_height()) + 1;
+    while batch_start <= head_height {
+        let batch_end = (batch_start + BACKFILL_BATCH_HEIGHTS - 1).min(head_height);
+        let mut block_hashes = vec![];
+        for height in batch_start..=batch_end {
+            match chain_store.get_all_block_hashes_by_height(height) {
+                Ok(blocks) => block_hashes.extend(blocks.values().flatten().copied()),
+                Err(err) => match err.kind() {
+                    ErrorKind::DBNotFoundErr(_) => {}
+                    _ => return Err(err),
+                },
+            }
+        }
+
+        let mut chain_store_update = chain_store.store_update();
+        for block_hash in block_hashes {
+            let header = chain_store_update.get_block_header(&block_hash)?.clone();
+            let prev_header = match chain_store_update.get_block_header(header.prev_hash()) {
+                Ok(prev_header) => prev_header.clone(),
+                Err(_) => continue,
+            };
+            // All chunks of the genesis block are new.
+            let prev_is_genesis = *prev_header.prev_hash() == CryptoHash::default();
+            if !prev_is_genesis && prev_header.epoch_id() != header.epoch_id() {
+                continue;
+            }
+            for shard_id in 0..header.chunk_mask().len() as ShardId {
+                if chain_store_update
+                    .get_prev_block_with_new_chunk(&block_hash, shard_id)?
+                    .is_some()
+                {
+                    continue;
+                }
+                let link = if prev_is_genesis
+                    || prev_header.chunk_mask().get(shard_id as usize).copied().unwrap_or(false)
+                {
+                    Some((*prev_header.hash(), shard_id))
+                } else {
+                    chain_store_update
+                        .get_prev_block_with_new_chunk(prev_header.hash(), shard_id)?
+                };
+                if let Some(link) = link {
+                    chain_store_update.save_prev_block_with_new_chunk(&block_hash, shard_id, link);
+                }
+            }
+        }
+        chain_store_update.commit()?;
+        batch_start = batch_end + 1;
+    }
+    Ok(())
+}
 
 /// Check that epoch of block with given prev_block_hash is the first one with current protocol version.
 fn is_first_epoch_with_protocol_version(
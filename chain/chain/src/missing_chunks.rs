@@ -82,6 +82,15 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         heap.into_sorted_vec().into_iter().map(|x| x.0).collect()
     }
 
+    /// Puts blocks taken with `ready_blocks` but not processed back into the pool.
+    pub fn return_ready_blocks(&mut self, blocks: Vec<Block>) {
+        self.blocks_ready_to_process.extend(blocks.into_iter().map(HeightOrdered));
+    }
+
+    pub fn has_ready_blocks(&self) -> bool {
+        !self.blocks_ready_to_process.is_empty()
+    }
+
     pub fn add_block_with_missing_chunks(&mut self, block: Block, missing_chunks: Vec<ChunkHash>) {
         let block_hash = block.hash();
         // This case can only happen when missing chunks are not being eventually received and
@@ -241,6 +250,26 @@ mod test {
         assert_eq!(pool.ready_blocks(), vec![block]);
     }
 
+    #[test]
+    fn should_return_unprocessed_ready_blocks() {
+        let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::default();
+        let chunk_hash = get_chunk_hash(100);
+        let blocks: Vec<MockBlock> = (0..4).map(MockBlock::new).collect();
+        for block in blocks.iter() {
+            pool.add_block_with_missing_chunks(*block, vec![chunk_hash.clone()]);
+        }
+        pool.accept_chunk(&chunk_hash);
+        assert!(pool.has_ready_blocks());
+
+        let mut ready_blocks = pool.ready_blocks();
+        assert_eq!(ready_blocks, blocks);
+        assert!(!pool.has_ready_blocks());
+        let unprocessed = ready_blocks.split_off(2);
+        pool.return_ready_blocks(unprocessed);
+        assert!(pool.has_ready_blocks());
+        assert_eq!(pool.ready_blocks(), blocks[2..].to_vec());
+    }
+
     #[test]
     fn should_not_add_new_blocks_after_size_limit() {
         let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::default();
@@ -0,0 +1,170 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use borsh::BorshDeserialize;
+use lru::LruCache;
+use near_store::{read_with_cache, DBCol, Store};
+
+/// Columns whose values never change once written. They are only deleted by garbage collection,
+/// which invalidates them, see `SharedChainCache::invalidate`.
+const SHARED_COLUMNS: [DBCol; 3] = [DBCol::ColBlockHeader, DBCol::ColBlock, DBCol::ColChunkExtra];
+
+type CacheKey = (DBCol, Vec<u8>);
+
+struct Entries {
+    values: LruCache<CacheKey, Arc<[u8]>>,
+    size_bytes: usize,
+}
+
+/// Serialized chain data shared by several `ChainStore`s, e.g. the ones of the view client
+/// threads, so that a hot block is read from the database once instead of once per thread.
+/// Only immutable-by-key columns are cached, and the total size of cached keys and values is
+/// bounded by the capacity.
+///
+/// The chain store which garbage collects must share the cache too, so that deleted values are
+/// invalidated. Every invalidation bumps the generation, which tells the other chain stores to
+/// drop their own copies of the shared columns.
+pub struct SharedChainCache {
+    entries: Mutex<Entries>,
+    capacity_bytes: usize,
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SharedChainCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries { values: LruCache::unbounded(), size_bytes: 0 }),
+            capacity_bytes,
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_capacity_mb(capacity_mb: u64) -> Self {
+        Self::new(capacity_mb as usize * 1024 * 1024)
+    }
+
+    pub fn is_shared_column(col: DBCol) -> bool {
+        SHARED_COLUMNS.contains(&col)
+    }
+
+    pub fn get(&self, col: DBCol, key: &[u8]) -> Option<Arc<[u8]>> {
+        let value = self.entries.lock().unwrap().values.get(&(col, key.to_vec())).cloned();
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    /// Caches the value, evicting the least recently used entries to stay within the capacity.
+    pub fn put(&self, col: DBCol, key: &[u8], value: Vec<u8>) -> Arc<[u8]> {
+        self.put_impl(col, key, value, None)
+    }
+
+    /// Same as `put`, unless something was invalidated since `generation`, in which case the
+    /// value may have been read before it was deleted.
+    fn put_if_generation(
+        &self,
+        col: DBCol,
+        key: &[u8],
+        value: Vec<u8>,
+        generation: u64,
+    ) -> Arc<[u8]> {
+        self.put_impl(col, key, value, Some(generation))
+    }
+
+    fn put_impl(
+        &self,
+        col: DBCol,
+        key: &[u8],
+        value: Vec<u8>,
+        generation: Option<u64>,
+    ) -> Arc<[u8]> {
+        let value: Arc<[u8]> = value.into();
+        let size = key.len() + value.len();
+        if size > self.capacity_bytes {
+            return value;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if generation.map_or(false, |generation| generation != self.generation()) {
+            return value;
+        }
+        if let Some(old_value) = entries.values.put((col, key.to_vec()), value.clone()) {
+            entries.size_bytes -= key.len() + old_value.len();
+        }
+        entries.size_bytes += size;
+        while entries.size_bytes > self.capacity_bytes {
+            match entries.values.pop_lru() {
+                Some(((_, key), value)) => entries.size_bytes -= key.len() + value.len(),
+                None => break,
+            }
+        }
+        value
+    }
+
+    /// Drops the values deleted by garbage collection, and bumps the generation once. Returns the
+    /// new generation.
+    pub fn invalidate(&self, keys: impl IntoIterator<Item = (DBCol, Vec<u8>)>) -> u64 {
+        let mut entries = self.entries.lock().unwrap();
+        for (col, key) in keys {
+            if let Some(value) = entries.values.pop(&(col, key.clone())) {
+                entries.size_bytes -= key.len() + value.len();
+            }
+        }
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Number of invalidations so far.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Number of lookups served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups which had to go to the database.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.entries.lock().unwrap().size_bytes
+    }
+}
+
+/// Same as `read_with_cache`, but values missing from the local cache are looked up in the
+/// shared cache before reading them from the database.
+pub(crate) fn read_with_shared_cache<'a, T: BorshDeserialize + 'a>(
+    storage: &Store,
+    shared_cache: Option<&SharedChainCache>,
+    col: DBCol,
+    cache: &'a mut LruCache<Vec<u8>, T>,
+    key: &[u8],
+) -> io::Result<Option<&'a T>> {
+    let shared_cache = match shared_cache {
+        Some(shared_cache) if SharedChainCache::is_shared_column(col) => shared_cache,
+        _ => return read_with_cache(storage, col, cache, key),
+    };
+    let key_vec = key.to_vec();
+    if cache.get(&key_vec).is_some() {
+        return Ok(cache.get(&key_vec));
+    }
+    let generation = shared_cache.generation();
+    let bytes = match shared_cache.get(col, key) {
+        Some(bytes) => bytes,
+        None => match storage.get(col, key)? {
+            Some(bytes) => shared_cache.put_if_generation(col, key, bytes, generation),
+            None => return Ok(None),
+        },
+    };
+    cache.put(key_vec.clone(), T::try_from_slice(&bytes)?);
+    Ok(cache.get(&key_vec))
+}
@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use near_primitives::hash::CryptoHash;
+use near_primitives::syncing::ShardStateSyncResponseHeader;
+use near_primitives::time::Clock;
+use near_primitives::types::ShardId;
+
+use crate::metrics;
+
+/// Number of recently served state sync headers kept in memory.
+const STATE_HEADER_CACHE_SIZE: usize = 16;
+/// Recently served state sync headers are dropped from memory after this long.
+const STATE_HEADER_CACHE_MAX_AGE: Duration = Duration::from_secs(600);
+
+type HeaderKey = (ShardId, CryptoHash);
+
+/// In-memory cache of state sync headers, shared by the chains serving state requests.
+/// A header is generated by a single request at a time, and repeated requests are served from a
+/// small LRU of recently served headers.
+pub struct StateHeaderCache {
+    headers: Mutex<LruCache<HeaderKey, (ShardStateSyncResponseHeader, Instant)>>,
+    in_flight: Mutex<HashSet<HeaderKey>>,
+    max_age: Duration,
+}
+
+/// Result of looking up a header in `StateHeaderCache`.
+pub enum HeaderLookup<'a> {
+    /// The header was served recently or generated by a concurrent request.
+    Found(ShardStateSyncResponseHeader),
+    /// The header is being generated by a concurrent request.
+    InFlight,
+    /// The header has to be generated by the caller. Other requests for it find it in flight
+    /// until the guard is finished or dropped.
+    Generate(GenerationGuard<'a>),
+}
+
+impl Default for StateHeaderCache {
+    fn default() -> Self {
+        Self::new(STATE_HEADER_CACHE_SIZE, STATE_HEADER_CACHE_MAX_AGE)
+    }
+}
+
+impl StateHeaderCache {
+    pub fn new(capacity: usize, max_age: Duration) -> Self {
+        Self {
+            headers: Mutex::new(LruCache::new(capacity)),
+            in_flight: Mutex::new(HashSet::new()),
+            max_age,
+        }
+    }
+
+    /// Returns the header if it is cached, or whether it is being generated by another request.
+    /// Never waits for the other request, so that it doesn't hold the calling thread. Otherwise
+    /// the caller becomes responsible for generating the header.
+    pub fn get_or_start(&self, shard_id: ShardId, sync_hash: CryptoHash) -> HeaderLookup<'_> {
+        let key = (shard_id, sync_hash);
+        if let Some(header) = self.get(&key) {
+            metrics::STATE_SYNC_HEADER_DEDUP_HITS.with_label_values(&["cache"]).inc();
+            return HeaderLookup::Found(header);
+        }
+        if !self.in_flight.lock().unwrap().insert(key) {
+            metrics::STATE_SYNC_HEADER_DEDUP_HITS.with_label_values(&["in_flight"]).inc();
+            return HeaderLookup::InFlight;
+        }
+        // The generation may have finished since the lookup above.
+        if let Some(header) = self.get(&key) {
+            self.in_flight.lock().unwrap().remove(&key);
+            metrics::STATE_SYNC_HEADER_DEDUP_HITS.with_label_values(&["cache"]).inc();
+            return HeaderLookup::Found(header);
+        }
+        HeaderLookup::Generate(GenerationGuard { cache: self, key })
+    }
+
+    fn get(&self, key: &HeaderKey) -> Option<ShardStateSyncResponseHeader> {
+        let mut headers = self.headers.lock().unwrap();
+        match headers.get(key) {
+            Some((header, added)) if Clock::instant().duration_since(*added) <= self.max_age => {
+                Some(header.clone())
+            }
+            Some(_) => {
+                headers.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+/// Marks a header as being generated, see `StateHeaderCache::get_or_start`.
+#[must_use]
+pub struct GenerationGuard<'a> {
+    cache: &'a StateHeaderCache,
+    key: HeaderKey,
+}
+
+impl GenerationGuard<'_> {
+    /// Caches the generated header for the following requests.
+    pub fn finish(self, header: &ShardStateSyncResponseHeader) {
+        self.cache.headers.lock().unwrap().put(self.key, (header.clone(), Clock::instant()));
+    }
+}
+
+impl Drop for GenerationGuard<'_> {
+    fn drop(&mut self) {
+        self.cache.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
@@ -0,0 +1,59 @@
+//! Adversarial hooks for tests: a chain with a `StateSyncCorruptor` set mutates the state sync
+//! responses it serves, so that the checks done by `Chain::set_state_header` and
+//! `Chain::set_state_part` on the receiving side can be exercised.
+
+use near_primitives::hash::hash;
+use near_primitives::syncing::{ReceiptProofResponse, RootProof, ShardStateSyncResponseHeader};
+
+/// The way served state sync responses are corrupted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateSyncCorruptor {
+    /// Replaces the outgoing receipts root of the first root proof with another hash.
+    FlipRootProof,
+    /// Reverses the order of the incoming receipts responses along with their root proofs.
+    ReorderIncomingReceipts,
+    /// Drops the second half of every state part.
+    TruncatePart,
+    /// In the first block with several receipt proofs, makes the second proof claim the
+    /// `from_shard_id` of the first one.
+    DuplicateFromShardId,
+}
+
+impl StateSyncCorruptor {
+    pub fn corrupt_header(&self, header: &mut ShardStateSyncResponseHeader) {
+        let (incoming_receipts_proofs, root_proofs) = match header {
+            ShardStateSyncResponseHeader::V1(header) => {
+                (&mut header.incoming_receipts_proofs, &mut header.root_proofs)
+            }
+            ShardStateSyncResponseHeader::V2(header) => {
+                (&mut header.incoming_receipts_proofs, &mut header.root_proofs)
+            }
+        };
+        match self {
+            StateSyncCorruptor::FlipRootProof => {
+                if let Some(RootProof(root, _)) = root_proofs.iter_mut().flatten().next() {
+                    *root = hash(&root.0);
+                }
+            }
+            StateSyncCorruptor::ReorderIncomingReceipts => {
+                incoming_receipts_proofs.reverse();
+                root_proofs.reverse();
+            }
+            StateSyncCorruptor::DuplicateFromShardId => {
+                if let Some(ReceiptProofResponse(_, receipt_proofs)) = incoming_receipts_proofs
+                    .iter_mut()
+                    .find(|ReceiptProofResponse(_, receipt_proofs)| receipt_proofs.len() > 1)
+                {
+                    receipt_proofs[1].1.from_shard_id = receipt_proofs[0].1.from_shard_id;
+                }
+            }
+            StateSyncCorruptor::TruncatePart => {}
+        }
+    }
+
+    pub fn corrupt_part(&self, part: &mut Vec<u8>) {
+        if let StateSyncCorruptor::TruncatePart = self {
+            part.truncate(part.len() / 2);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io;
+use std::ops::RangeInclusive;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use lru::LruCache;
@@ -27,32 +28,43 @@ use near_primitives::transaction::{
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{
-    AccountId, BlockExtra, BlockHeight, BlockHeightDelta, EpochId, GCCount, NumBlocks, ShardId,
-    StateChanges, StateChangesExt, StateChangesForSplitStates, StateChangesKinds,
+    AccountId, BlockExtra, BlockHeight, BlockHeightDelta, EpochHeight, EpochId, GCCount, NumBlocks,
+    ShardId, StateChanges, StateChangesExt, StateChangesForSplitStates, StateChangesKinds,
     StateChangesKindsExt, StateChangesRequest,
 };
-use near_primitives::utils::{get_block_shard_id, index_to_bytes, to_timestamp};
+use near_primitives::utils::{
+    get_block_shard_id, get_block_shard_id_rev, index_to_bytes, to_timestamp,
+};
 use near_primitives::views::LightClientBlockView;
 use near_store::{
-    read_with_cache, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight, ColBlockInfo,
-    ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight, ColBlockRefCount,
-    ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra, ColChunkHashesByHeight,
-    ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks, ColGCCount,
+    read_with_cache, ColApprovalAudit, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight,
+    ColBlockInfo, ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight,
+    ColBlockRefCount, ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra,
+    ColChunkHashesByHeight, ColChunkPerHeightShard, ColChunks, ColDiscardedForks,
+    ColEpochLightClientBlocks, ColEpochSummaries, ColEpochSummaryCounters, ColGCCount,
     ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks, ColNextBlockHashes,
-    ColOutcomeIds, ColOutgoingReceipts, ColPartialChunks, ColProcessedBlockHeights,
-    ColReceiptIdToShardId, ColReceipts, ColState, ColStateChanges, ColStateDlInfos,
-    ColStateHeaders, ColStateParts, ColTransactionResult, ColTransactions, ColTrieChanges, DBCol,
-    KeyForStateChanges, ShardTries, Store, StoreUpdate, TrieChanges, WrappedTrieChanges,
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
-    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC, TAIL_KEY,
+    ColOutcomeIds, ColOutcomeParent, ColOutcomesByBlockShard, ColOutgoingReceipts,
+    ColPartialChunks, ColPrevBlockWithNewChunk, ColProcessedBlockHeights, ColReceiptIdToShardId,
+    ColReceipts, ColRejectedBlocks, ColState, ColStateChanges, ColStateDlInfos, ColStateHeaders,
+    ColStateParts, ColStateSyncBoundaryBlocks, ColStateSyncFinalizeProgress, ColTransactionResult,
+    ColTransactions, ColTrieChanges, DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate,
+    TrieChanges, WrappedTrieChanges, CHAIN_SCHEMA_VERSION_KEY, CHUNK_TAIL_KEY, FINAL_HEAD_KEY,
+    FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY,
+    PARTIALLY_APPLIED_BLOCK_KEY, QUARANTINED_SHARD_KEY, REJECTED_BLOCKS_NEXT_INDEX_KEY,
+    SHOULD_COL_GC, SPILLED_STORE_UPDATE_KEY, TAIL_KEY,
 };
 
-use crate::types::{Block, BlockHeader, LatestKnown};
+use crate::metrics;
+use crate::shared_chain_cache::{read_with_shared_cache, SharedChainCache};
+use crate::types::{
+    ApprovalAuditRecord, Block, BlockHeader, DiscardedFork, EpochSummary, LatestKnown,
+    RejectedBlock, ShardQuarantine,
+};
 use crate::{byzantine_assert, RuntimeAdapter};
 use near_store::db::DBCol::ColStateChangesForSplitStates;
-use near_store::db::StoreStatistics;
-#[cfg(feature = "mock_network")]
-use std::sync::Arc;
+use near_store::db::{DBTransaction, StoreStatistics};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// lru cache size
 #[cfg(not(feature = "no_cache"))]
@@ -65,6 +77,12 @@ const CACHE_SIZE: usize = 1;
 #[cfg(feature = "no_cache")]
 const CHUNK_CACHE_SIZE: usize = 1;
 
+/// Number of hashes remembered as missing by the negative cache.
+const NEGATIVE_CACHE_SIZE: usize = 1024;
+
+/// Number of the latest rejected blocks kept in `ColRejectedBlocks`.
+pub const MAX_REJECTED_BLOCKS: u64 = 1000;
+
 #[derive(Clone)]
 pub enum GCMode {
     Fork(ShardTries),
@@ -79,6 +97,34 @@ fn get_height_shard_id(height: BlockHeight, shard_id: ShardId) -> Vec<u8> {
     res
 }
 
+fn shard_quarantine_key(shard_uid: &ShardUId) -> Vec<u8> {
+    let mut res = Vec::with_capacity(QUARANTINED_SHARD_KEY.len() + 8);
+    res.extend_from_slice(QUARANTINED_SHARD_KEY);
+    res.extend_from_slice(&shard_uid.to_bytes());
+    res
+}
+
+fn partially_applied_block_key(block_hash: &CryptoHash) -> Vec<u8> {
+    let mut res = Vec::with_capacity(PARTIALLY_APPLIED_BLOCK_KEY.len() + 32);
+    res.extend_from_slice(PARTIALLY_APPLIED_BLOCK_KEY);
+    res.extend_from_slice(block_hash.as_ref());
+    res
+}
+
+fn spilled_store_update_key(block_hash: &CryptoHash) -> Vec<u8> {
+    let mut res = Vec::with_capacity(SPILLED_STORE_UPDATE_KEY.len() + 32);
+    res.extend_from_slice(SPILLED_STORE_UPDATE_KEY);
+    res.extend_from_slice(block_hash.as_ref());
+    res
+}
+
+fn rejected_block_index(key: &[u8]) -> Result<u64, Error> {
+    let index = key
+        .try_into()
+        .map_err(|_| ErrorKind::Other(format!("Invalid key of a rejected block: {:?}", key)))?;
+    Ok(u64::from_le_bytes(index))
+}
+
 /// Accesses the chain store. Used to create atomic editable views that can be reverted.
 pub trait ChainStoreAccess {
     /// Returns underlaying store.
@@ -225,8 +271,120 @@ pub trait ChainStoreAccess {
     /// Returns whether the block with the given hash was challenged
     fn is_block_challenged(&mut self, hash: &CryptoHash) -> Result<bool, Error>;
 
+    /// Returns whether the block is already known to be in a challenged subtree.
+    fn is_known_challenged_descendant(&mut self, _hash: &CryptoHash) -> bool {
+        false
+    }
+
+    /// Remembers that given blocks are in a challenged subtree.
+    fn cache_challenged_descendants(&mut self, _hashes: &[CryptoHash]) {}
+
+    /// Returns whether the block with the given hash or any of its ancestors was challenged.
+    /// Walks back at most `max_depth` blocks and stops at the last final block of `hash`, since
+    /// nothing before it can be reverted. Blocks with unknown headers are treated as not
+    /// challenged.
+    fn is_descendant_of_challenged(
+        &mut self,
+        hash: &CryptoHash,
+        max_depth: NumBlocks,
+    ) -> Result<bool, Error> {
+        let mut visited = vec![];
+        let mut cur_hash = *hash;
+        let mut last_final_hash = None;
+        for _ in 0..=max_depth {
+            if self.is_known_challenged_descendant(&cur_hash)
+                || self.is_block_challenged(&cur_hash)?
+            {
+                visited.push(cur_hash);
+                self.cache_challenged_descendants(&visited);
+                return Ok(true);
+            }
+            if Some(cur_hash) == last_final_hash || cur_hash == CryptoHash::default() {
+                break;
+            }
+            let header = match self.get_block_header(&cur_hash) {
+                Ok(header) => header,
+                Err(e) => match e.kind() {
+                    ErrorKind::DBNotFoundErr(_) => break,
+                    _ => return Err(e),
+                },
+            };
+            let prev_hash = *header.prev_hash();
+            if last_final_hash.is_none() {
+                last_final_hash = Some(*header.last_final_block());
+            }
+            visited.push(cur_hash);
+            cur_hash = prev_hash;
+        }
+        Ok(false)
+    }
+
     fn get_blocks_to_catchup(&self, prev_hash: &CryptoHash) -> Result<Vec<CryptoHash>, Error>;
 
+    /// Returns the saved link from the block to the previous block with a new chunk for the
+    /// shard, if there is one.
+    fn get_prev_block_with_new_chunk(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Option<(CryptoHash, ShardId)>, Error>;
+
+    /// Returns the latest block at or before `prev_block_hash` which has a new chunk for the
+    /// shard, together with the id of the shard in the shard layout of that block.
+    /// `shard_id` is in the shard layout of the block after `prev_block_hash`. If the shard
+    /// layout changes on the way back, the shard is mapped with `get_prev_shard_ids`, the same
+    /// way `Chain::get_next_block_hash_with_new_chunk` maps it forward.
+    /// Saved links are used where they exist, otherwise blocks are walked one by one.
+    fn get_last_block_with_new_chunk(
+        &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
+        prev_block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<(CryptoHash, ShardId), Error> {
+        let mut shard_id = shard_id;
+        let mut block_hash = *prev_block_hash;
+        loop {
+            // Move the shard id from the shard layout of the next block to the one of the block.
+            shard_id = runtime_adapter.get_prev_shard_ids(&block_hash, vec![shard_id])?[0];
+            let header = self.get_block_header(&block_hash)?;
+            let prev_hash = *header.prev_hash();
+            let chunk_mask = header.chunk_mask().to_vec();
+            // All chunks of the genesis block are new.
+            if prev_hash == CryptoHash::default()
+                || chunk_mask.get(shard_id as usize).copied().unwrap_or(false)
+            {
+                return Ok((block_hash, shard_id));
+            }
+            if let Some(prev_block_with_new_chunk) =
+                self.get_prev_block_with_new_chunk(&block_hash, shard_id)?
+            {
+                return Ok(prev_block_with_new_chunk);
+            }
+            block_hash = prev_hash;
+        }
+    }
+
+    /// Returns the previous block before `block_hash` which has a new chunk for the shard,
+    /// together with the id of the shard in the shard layout of that block. Returns `None` for
+    /// the genesis block.
+    fn get_prev_block_hash_with_new_chunk(
+        &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Option<(CryptoHash, ShardId)>, Error> {
+        if let Some(prev_block_with_new_chunk) =
+            self.get_prev_block_with_new_chunk(block_hash, shard_id)?
+        {
+            return Ok(Some(prev_block_with_new_chunk));
+        }
+        let prev_hash = *self.get_block_header(block_hash)?.prev_hash();
+        if prev_hash == CryptoHash::default() {
+            return Ok(None);
+        }
+        self.get_last_block_with_new_chunk(runtime_adapter, &prev_hash, shard_id).map(Some)
+    }
+
     /// Returns encoded chunk if it's invalid otherwise None.
     fn is_invalid_chunk(
         &mut self,
@@ -350,6 +508,55 @@ pub struct ChainStore {
     block_ordinal_to_hash: LruCache<Vec<u8>, CryptoHash>,
     /// Processed block heights.
     processed_block_heights: LruCache<Vec<u8>, ()>,
+    /// Blocks known to be challenged or to descend from a challenged block.
+    challenged_descendants: LruCache<Vec<u8>, ()>,
+    /// Hashes by height of the blocks on the chain ending at a given block, as far back as it
+    /// was walked by `get_header_on_chain_by_height`.
+    headers_on_chain: LruCache<Vec<u8>, BTreeMap<BlockHeight, CryptoHash>>,
+    /// Same as `headers_on_chain` for the chains walked by `check_transaction_validity_period`,
+    /// which end at a different block for every new block and would evict the other chains.
+    tx_validity_headers_on_chain: LruCache<Vec<u8>, BTreeMap<BlockHeight, CryptoHash>>,
+    /// Headers, blocks and chunk extras shared with other chain stores, if any.
+    shared_cache: Option<Arc<SharedChainCache>>,
+    /// Generation of the shared cache the own caches of the shared columns are up to date with.
+    shared_cache_generation: u64,
+    /// Hashes of blocks and headers recently confirmed missing, if enabled.
+    negative_cache: Option<NegativeCache>,
+}
+
+/// Hashes of blocks and headers recently looked up and not found in the database, so that the
+/// same unknown hashes looked up again and again during sync don't hit the database each time.
+///
+/// Entries are only dropped when blocks and headers are saved through the chain store owning the
+/// cache, so it must only be enabled on a chain store which no other chain store writes behind.
+struct NegativeCache {
+    entries: Mutex<LruCache<(DBCol, Vec<u8>), ()>>,
+    hits: AtomicU64,
+}
+
+impl NegativeCache {
+    fn new() -> Self {
+        Self { entries: Mutex::new(LruCache::new(NEGATIVE_CACHE_SIZE)), hits: AtomicU64::new(0) }
+    }
+
+    fn contains(&self, col: DBCol, key: &[u8]) -> bool {
+        let found = self.entries.lock().unwrap().get(&(col, key.to_vec())).is_some();
+        if found {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            metrics::CHAIN_STORE_NEGATIVE_CACHE_HITS
+                .with_label_values(&[&format!("{:?}", col)])
+                .inc();
+        }
+        found
+    }
+
+    fn insert(&self, col: DBCol, key: &[u8]) {
+        self.entries.lock().unwrap().put((col, key.to_vec()), ());
+    }
+
+    fn remove(&self, col: DBCol, key: &[u8]) {
+        self.entries.lock().unwrap().pop(&(col, key.to_vec()));
+    }
 }
 
 pub fn option_to_not_found<T>(res: io::Result<Option<T>>, field_name: &str) -> Result<T, Error> {
@@ -391,9 +598,61 @@ impl ChainStore {
             block_merkle_tree: LruCache::new(CACHE_SIZE),
             block_ordinal_to_hash: LruCache::new(CACHE_SIZE),
             processed_block_heights: LruCache::new(CACHE_SIZE),
+            challenged_descendants: LruCache::new(CACHE_SIZE),
+            headers_on_chain: LruCache::new(CACHE_SIZE),
+            tx_validity_headers_on_chain: LruCache::new(CACHE_SIZE),
+            shared_cache: None,
+            shared_cache_generation: 0,
+            negative_cache: None,
+        }
+    }
+
+    /// Creates a chain store which falls back to `shared_cache` for headers, blocks and chunk
+    /// extras missing from its own caches.
+    pub fn with_shared_cache(
+        store: Store,
+        genesis_height: BlockHeight,
+        shared_cache: Arc<SharedChainCache>,
+    ) -> ChainStore {
+        let mut chain_store = ChainStore::new(store, genesis_height);
+        chain_store.set_shared_cache(shared_cache);
+        chain_store
+    }
+
+    pub fn set_shared_cache(&mut self, shared_cache: Arc<SharedChainCache>) {
+        self.shared_cache_generation = shared_cache.generation();
+        self.shared_cache = Some(shared_cache);
+    }
+
+    /// Drops the own cached headers, blocks and chunk extras if the shared cache was invalidated
+    /// since they were cached, as some of them may have been garbage collected by another store.
+    fn drop_invalidated_cache(&mut self) {
+        if let Some(shared_cache) = &self.shared_cache {
+            let generation = shared_cache.generation();
+            if generation != self.shared_cache_generation {
+                self.headers.clear();
+                self.blocks.clear();
+                self.chunk_extras.clear();
+                self.shared_cache_generation = generation;
+            }
         }
     }
 
+    /// Remembers the hashes of blocks and headers found missing, until they are saved. Only for
+    /// the chain store through which all blocks and headers are saved, see `NegativeCache`.
+    pub fn enable_negative_cache(&mut self) {
+        self.negative_cache = Some(NegativeCache::new());
+    }
+
+    /// Number of block and header lookups answered by the negative cache.
+    pub fn negative_cache_hits(&self) -> u64 {
+        self.negative_cache.as_ref().map_or(0, |cache| cache.hits.load(Ordering::Relaxed))
+    }
+
+    fn is_known_missing(&self, col: DBCol, key: &[u8]) -> bool {
+        self.negative_cache.as_ref().map_or(false, |cache| cache.contains(col, key))
+    }
+
     pub fn owned_store(&self) -> &Store {
         &self.store
     }
@@ -402,6 +661,18 @@ impl ChainStore {
         ChainStoreUpdate::new(self)
     }
 
+    /// Returns all records of blocks waiting to be caught up, keyed by their previous block.
+    pub fn get_all_blocks_to_catchup(&self) -> Result<Vec<(CryptoHash, Vec<CryptoHash>)>, Error> {
+        let mut result = vec![];
+        for (key, value) in self.store.iter(ColBlocksToCatchup) {
+            let prev_hash = CryptoHash::try_from(key.as_ref()).map_err(|_| {
+                ErrorKind::InconsistentCatchupBookkeeping(format!("invalid key {:?}", key))
+            })?;
+            result.push((prev_hash, Vec::<CryptoHash>::try_from_slice(&value)?));
+        }
+        Ok(result)
+    }
+
     pub fn iterate_state_sync_infos(&self) -> Vec<(CryptoHash, StateSyncInfo)> {
         self.store
             .iter(ColStateDlInfos)
@@ -414,6 +685,145 @@ impl ChainStore {
             .collect()
     }
 
+    /// Returns the recorded fork blocks deleted by garbage collection at the given heights,
+    /// ordered by height and hash.
+    pub fn get_discarded_forks(
+        &self,
+        heights: RangeInclusive<BlockHeight>,
+    ) -> Result<Vec<DiscardedFork>, Error> {
+        let mut result = vec![];
+        for (_, value) in self.store.iter(ColDiscardedForks) {
+            let forks = Vec::<DiscardedFork>::try_from_slice(&value)?;
+            result.extend(forks.into_iter().filter(|fork| heights.contains(&fork.height)));
+        }
+        result.sort_by_key(|fork| (fork.height, fork.hash));
+        Ok(result)
+    }
+
+    /// Returns up to `limit` of the latest blocks rejected as invalid, the latest first.
+    pub fn get_rejected_blocks(&self, limit: usize) -> Result<Vec<RejectedBlock>, Error> {
+        let mut result = vec![];
+        for (key, value) in self.store.iter(ColRejectedBlocks) {
+            result.push((rejected_block_index(&key)?, RejectedBlock::try_from_slice(&value)?));
+        }
+        result.sort_by_key(|(index, _)| std::cmp::Reverse(*index));
+        Ok(result.into_iter().take(limit).map(|(_, block)| block).collect())
+    }
+
+    /// Returns all quarantined shards, see `Chain::quarantine_shard_state`.
+    pub fn get_quarantined_shards(&self) -> Result<HashMap<ShardUId, ShardQuarantine>, Error> {
+        let mut result = HashMap::new();
+        for item in self.store.iter_prefix_ser(ColBlockMisc, QUARANTINED_SHARD_KEY) {
+            let (key, quarantine) = item?;
+            let shard_uid = ShardUId::try_from(&key[QUARANTINED_SHARD_KEY.len()..])
+                .map_err(|err| ErrorKind::Other(err.to_string()))?;
+            result.insert(shard_uid, quarantine);
+        }
+        Ok(result)
+    }
+
+    /// Returns the blocks applied while not caught up, with the shards deferred to catchup for
+    /// each of them, see `Chain::partially_applied_blocks`.
+    pub fn get_partially_applied_blocks(&self) -> Result<Vec<(CryptoHash, Vec<ShardId>)>, Error> {
+        let mut result = vec![];
+        for item in self.store.iter_prefix_ser(ColBlockMisc, PARTIALLY_APPLIED_BLOCK_KEY) {
+            let (key, deferred_shards) = item?;
+            let block_hash = CryptoHash::try_from(&key[PARTIALLY_APPLIED_BLOCK_KEY.len()..])
+                .map_err(|err| ErrorKind::Other(err.to_string()))?;
+            result.push((block_hash, deferred_shards));
+        }
+        Ok(result)
+    }
+
+    /// Flattens `saved_store_update` of a block being caught up to the database operations it
+    /// would commit and writes them to the store, so that it doesn't have to be held in memory
+    /// until the block is postprocessed. The operations are computed against the current state of
+    /// the store, as they would be on commit.
+    pub fn spill_saved_store_update(
+        &mut self,
+        block_hash: &CryptoHash,
+        saved_store_update: SavedStoreUpdate,
+    ) -> Result<(), Error> {
+        let finalized = saved_store_update.restore(self).finalize()?;
+        let spilled = SpilledStoreUpdate {
+            updates_tries: finalized.updates_tries(),
+            transaction: finalized.into_transaction(),
+        };
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColBlockMisc, &spilled_store_update_key(block_hash), &spilled)?;
+        store_update.commit()?;
+        Ok(())
+    }
+
+    /// Reads back the store update spilled by `spill_saved_store_update`. Committing the returned
+    /// update also removes the spilled copy.
+    pub fn restore_spilled_store_update(
+        &mut self,
+        block_hash: &CryptoHash,
+        tries: ShardTries,
+    ) -> Result<SavedStoreUpdate, Error> {
+        let key = spilled_store_update_key(block_hash);
+        let spilled: SpilledStoreUpdate =
+            self.store.get_ser(ColBlockMisc, &key)?.ok_or_else(|| {
+                ErrorKind::DBNotFoundErr(format!("SPILLED STORE UPDATE: {}", block_hash))
+            })?;
+        let mut store_update = if spilled.updates_tries {
+            StoreUpdate::new_with_tries(tries)
+        } else {
+            self.store().store_update()
+        };
+        store_update.merge_transaction(spilled.transaction);
+        store_update.delete(ColBlockMisc, &key);
+        let mut chain_store_update = self.store_update();
+        chain_store_update.merge(store_update);
+        Ok(chain_store_update.into())
+    }
+
+    /// Removes the store updates left spilled by catchup which was interrupted, e.g. by a
+    /// restart of the node.
+    pub fn clear_spilled_store_updates(&mut self) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        for (key, _) in self.store.iter_prefix(ColBlockMisc, SPILLED_STORE_UPDATE_KEY) {
+            store_update.delete(ColBlockMisc, &key);
+        }
+        store_update.commit()?;
+        Ok(())
+    }
+
+    /// Returns the version of the chain data layout, `None` if it was never recorded.
+    pub fn get_chain_schema_version(&self) -> Result<Option<u32>, Error> {
+        Ok(self.store.get_ser(ColBlockMisc, CHAIN_SCHEMA_VERSION_KEY)?)
+    }
+
+    /// Returns keys of all stored state sync headers.
+    pub fn iterate_state_header_keys(&self) -> Result<Vec<StateHeaderKey>, Error> {
+        self.store
+            .iter(ColStateHeaders)
+            .map(|(k, _)| Ok(StateHeaderKey::try_from_slice(k.as_ref())?))
+            .collect()
+    }
+
+    /// Returns all stored state changes which are not yet applied to split states.
+    pub fn iterate_state_changes_for_split_states(
+        &self,
+    ) -> Result<Vec<(CryptoHash, ShardId, StateChangesForSplitStates)>, Error> {
+        self.store
+            .iter(ColStateChangesForSplitStates)
+            .map(|(k, v)| {
+                let (block_hash, shard_id) = get_block_shard_id_rev(k.as_ref())
+                    .map_err(|err| ErrorKind::Other(err.to_string()))?;
+                let state_changes = StateChangesForSplitStates::try_from_slice(v.as_ref())?;
+                Ok((block_hash, shard_id, state_changes))
+            })
+            .collect()
+    }
+
+    /// Returns the number of stored state changes which are not yet applied to split states,
+    /// counting at most `limit` of them.
+    pub fn count_state_changes_for_split_states(&self, limit: usize) -> usize {
+        self.store.iter(ColStateChangesForSplitStates).take(limit).count()
+    }
+
     pub fn get_state_changes_for_split_states(
         &self,
         block_hash: &CryptoHash,
@@ -452,37 +862,27 @@ impl ChainStore {
         last_included_height: BlockHeight,
     ) -> Result<Vec<Receipt>, Error> {
         let shard_layout = runtime_adapter.get_shard_layout_from_prev_block(&prev_block_hash)?;
-        let mut receipts_block_hash = prev_block_hash;
-        loop {
-            let block_header = self.get_block_header(&receipts_block_hash)?;
+        // get the block and the shard from which the outgoing receipts were generated
+        let (receipts_block_hash, receipts_shard_id) =
+            self.get_last_block_with_new_chunk(runtime_adapter, &prev_block_hash, shard_id)?;
+        let receipts_block_header = self.get_block_header(&receipts_block_hash)?;
+        debug_assert_eq!(receipts_block_header.height(), last_included_height);
+        let receipts_shard_layout =
+            runtime_adapter.get_shard_layout(receipts_block_header.epoch_id())?;
 
-            if block_header.height() == last_included_height {
-                let receipts_shard_layout =
-                    runtime_adapter.get_shard_layout(block_header.epoch_id())?;
+        let mut receipts = self
+            .get_outgoing_receipts(&receipts_block_hash, receipts_shard_id)
+            .map(|v| v.clone())
+            .unwrap_or_default();
 
-                // get the shard from which the outgoing receipt were generated
-                let receipts_shard_id = if shard_layout != receipts_shard_layout {
-                    shard_layout.get_parent_shard_id(shard_id)?
-                } else {
-                    shard_id
-                };
-                let mut receipts = self
-                    .get_outgoing_receipts(&receipts_block_hash, receipts_shard_id)
-                    .map(|v| v.clone())
-                    .unwrap_or_default();
-
-                // filter to receipts that belong to `shard_id` in the current shard layout
-                if shard_layout != receipts_shard_layout {
-                    receipts.retain(|receipt| {
-                        account_id_to_shard_id(&receipt.receiver_id, &shard_layout) == shard_id
-                    });
-                }
-
-                return Ok(receipts);
-            } else {
-                receipts_block_hash = *block_header.prev_hash();
-            }
+        // filter to receipts that belong to `shard_id` in the current shard layout
+        if shard_layout != receipts_shard_layout {
+            receipts.retain(|receipt| {
+                account_id_to_shard_id(&receipt.receiver_id, &shard_layout) == shard_id
+            });
         }
+
+        Ok(receipts)
     }
 
     /// For a given transaction, it expires if the block that the chunk points to is more than `validity_period`
@@ -535,10 +935,14 @@ impl ChainStore {
                 Err(InvalidTxError::InvalidChain)
             }
         } else {
-            let header = self
-                .get_header_on_chain_by_height(prev_block_header.hash(), base_height)
+            let hash = self
+                .hash_on_chain_by_height(
+                    |store| &mut store.tx_validity_headers_on_chain,
+                    prev_block_header.hash(),
+                    base_height,
+                )
                 .map_err(|_| InvalidTxError::InvalidChain)?;
-            if header.hash() == base_block_hash {
+            if &hash == base_block_hash {
                 Ok(())
             } else {
                 Err(InvalidTxError::InvalidChain)
@@ -548,6 +952,51 @@ impl ChainStore {
 }
 
 impl ChainStore {
+    /// Returns the hash of the block with the given height on the chain ending at `last_hash`.
+    /// The walk back is memoized in the cache returned by `memo`.
+    fn hash_on_chain_by_height(
+        &mut self,
+        memo: fn(&mut ChainStore) -> &mut LruCache<Vec<u8>, BTreeMap<BlockHeight, CryptoHash>>,
+        last_hash: &CryptoHash,
+        height: BlockHeight,
+    ) -> Result<CryptoHash, Error> {
+        let key = last_hash.as_ref().to_vec();
+        let mut hashes = match memo(self).pop(&key) {
+            Some(hashes) => hashes,
+            None => {
+                let last_height = self.get_block_header(last_hash)?.height();
+                std::iter::once((last_height, *last_hash)).collect()
+            }
+        };
+        let hash = self.walk_back_to_height(&mut hashes, height);
+        memo(self).put(key, hashes);
+        hash
+    }
+
+    /// Walks back the chain from the lowest block in `hashes` down to `height`, recording the
+    /// visited blocks, and returns the hash of the block with the given height on that chain.
+    fn walk_back_to_height(
+        &mut self,
+        hashes: &mut BTreeMap<BlockHeight, CryptoHash>,
+        height: BlockHeight,
+    ) -> Result<CryptoHash, Error> {
+        let (mut lowest_height, mut hash) = hashes
+            .iter()
+            .next()
+            .map(|(height, hash)| (*height, *hash))
+            .expect("the last block of the chain is always recorded");
+        while lowest_height > height {
+            hash = *self.get_block_header(&hash)?.prev_hash();
+            lowest_height = self.get_block_header(&hash)?.height();
+            hashes.insert(lowest_height, hash);
+        }
+        match hashes.range(..=height).next_back() {
+            Some((header_height, hash)) if *header_height == height => Ok(*hash),
+            Some((header_height, _)) => Err(ErrorKind::InvalidBlockHeight(*header_height).into()),
+            None => unreachable!("the chain is walked back at least down to the given height"),
+        }
+    }
+
     /// Returns all outcomes generated by applying transaction or receipt with the given id.
     pub fn get_outcomes_by_id(
         &self,
@@ -556,6 +1005,55 @@ impl ChainStore {
         Ok(self.store.get_ser(ColTransactionResult, id.as_ref())?.unwrap_or_else(|| vec![]))
     }
 
+    /// Returns the last height up to which the state of the shard downloaded for `sync_hash` has
+    /// been finalized, if finalization has started and not completed yet.
+    pub fn get_state_sync_finalize_progress(
+        &self,
+        shard_id: ShardId,
+        sync_hash: &CryptoHash,
+    ) -> Result<Option<BlockHeight>, Error> {
+        let key = get_block_shard_id(sync_hash, shard_id);
+        Ok(self.store.get_ser(ColStateSyncFinalizeProgress, &key)?)
+    }
+
+    /// Whether the block was saved as the previous block of the sync block of state sync, see
+    /// `BlockSaveMode::StateSyncBoundary`.
+    pub fn is_state_sync_boundary_block(&self, block_hash: &CryptoHash) -> Result<bool, Error> {
+        Ok(self.store.exists(ColStateSyncBoundaryBlocks, block_hash.as_ref())?)
+    }
+
+    /// Returns the totals of the blocks of the epoch of the given block up to and including it,
+    /// if they have been accumulated since the start of the epoch.
+    pub fn get_epoch_summary_counters(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<EpochSummary>, Error> {
+        Ok(self.store.get_ser(ColEpochSummaryCounters, block_hash.as_ref())?)
+    }
+
+    /// Returns the approvals included in the given block, if it was produced by this node with
+    /// `Chain::record_approval_audit` set.
+    pub fn get_approval_audit(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<ApprovalAuditRecord>, Error> {
+        Ok(self.store.get_ser(ColApprovalAudit, block_hash.as_ref())?)
+    }
+
+    /// Returns the summary of the epoch at the given height, saved when the epoch ended.
+    pub fn get_epoch_summary(
+        &self,
+        epoch_height: EpochHeight,
+    ) -> Result<Option<EpochSummary>, Error> {
+        Ok(self.store.get_ser(ColEpochSummaries, &index_to_bytes(epoch_height))?)
+    }
+
+    /// Returns the id of the transaction or receipt whose execution produced the receipt with
+    /// the given id, if it is known.
+    pub fn get_outcome_parent(&self, id: &CryptoHash) -> Result<Option<CryptoHash>, Error> {
+        Ok(self.store.get_ser(ColOutcomeParent, id.as_ref())?)
+    }
+
     /// Returns a vector of Outcome ids for given block and shard id
     pub fn get_outcomes_by_block_hash_and_shard_id(
         &self,
@@ -568,6 +1066,46 @@ impl ChainStore {
             .unwrap_or_default())
     }
 
+    /// Returns the outcomes produced in the block by the shard, in execution order. Reads them
+    /// with a single lookup, falling back to reading every outcome by id for blocks saved before
+    /// `ColOutcomesByBlockShard` was introduced.
+    pub fn get_block_shard_outcomes(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Vec<ExecutionOutcomeWithIdAndProof>, Error> {
+        match self.get_outcomes_by_block_shard(block_hash, shard_id)? {
+            Some(outcomes) => Ok(outcomes),
+            None => self.get_block_shard_outcomes_by_ids(block_hash, shard_id),
+        }
+    }
+
+    fn get_outcomes_by_block_shard(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Option<Vec<ExecutionOutcomeWithIdAndProof>>, Error> {
+        Ok(self
+            .store
+            .get_ser(ColOutcomesByBlockShard, &get_block_shard_id(block_hash, shard_id))?)
+    }
+
+    fn get_block_shard_outcomes_by_ids(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Vec<ExecutionOutcomeWithIdAndProof>, Error> {
+        Ok(self
+            .get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?
+            .into_iter()
+            .flat_map(|id| {
+                let mut outcomes = self.get_outcomes_by_id(&id).unwrap_or_else(|_| vec![]);
+                outcomes.retain(|outcome| &outcome.block_hash == block_hash);
+                outcomes
+            })
+            .collect())
+    }
+
     /// Returns a hashmap of epoch id -> set of all blocks got for current (height, epoch_id)
     pub fn get_all_block_hashes_by_height(
         &mut self,
@@ -584,6 +1122,29 @@ impl ChainStore {
         )
     }
 
+    /// Returns all blocks at the given height without duplicates, in an order that doesn't depend
+    /// on the hash map iteration: the block on the canonical chain first, then the rest sorted by
+    /// hash.
+    pub fn get_ordered_block_hashes_by_height(
+        &mut self,
+        height: BlockHeight,
+    ) -> Result<Vec<CryptoHash>, Error> {
+        let mut hashes = self
+            .get_all_block_hashes_by_height(height)?
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        hashes.sort();
+        hashes.dedup();
+        if let Ok(canonical_hash) = self.get_block_hash_by_height(height) {
+            if let Some(position) = hashes.iter().position(|hash| hash == &canonical_hash) {
+                hashes[..=position].rotate_right(1);
+            }
+        }
+        Ok(hashes)
+    }
+
     /// Returns a HashSet of Chunk Hashes for current Height
     pub fn get_all_chunk_hashes_by_height(
         &mut self,
@@ -836,10 +1397,21 @@ impl ChainStoreAccess for ChainStore {
 
     /// Get full block.
     fn get_block(&mut self, h: &CryptoHash) -> Result<&Block, Error> {
-        option_to_not_found(
-            read_with_cache(&self.store, ColBlock, &mut self.blocks, h.as_ref()),
-            &format!("BLOCK: {}", h),
-        )
+        if self.is_known_missing(ColBlock, h.as_ref()) {
+            return Err(ErrorKind::DBNotFoundErr(format!("BLOCK: {}", h)).into());
+        }
+        self.drop_invalidated_cache();
+        let block = read_with_shared_cache(
+            &self.store,
+            self.shared_cache.as_deref(),
+            ColBlock,
+            &mut self.blocks,
+            h.as_ref(),
+        );
+        if let (Ok(None), Some(negative_cache)) = (&block, &self.negative_cache) {
+            negative_cache.insert(ColBlock, h.as_ref());
+        }
+        option_to_not_found(block, &format!("BLOCK: {}", h))
     }
 
     /// Get full chunk.
@@ -865,7 +1437,14 @@ impl ChainStoreAccess for ChainStore {
 
     /// Does this full block exist?
     fn block_exists(&self, h: &CryptoHash) -> Result<bool, Error> {
-        self.store.exists(ColBlock, h.as_ref()).map_err(|e| e.into())
+        if self.is_known_missing(ColBlock, h.as_ref()) {
+            return Ok(false);
+        }
+        let exists = self.store.exists(ColBlock, h.as_ref())?;
+        if let (false, Some(negative_cache)) = (exists, &self.negative_cache) {
+            negative_cache.insert(ColBlock, h.as_ref());
+        }
+        Ok(exists)
     }
 
     fn chunk_exists(&self, h: &ChunkHash) -> Result<bool, Error> {
@@ -896,9 +1475,11 @@ impl ChainStoreAccess for ChainStore {
         block_hash: &CryptoHash,
         shard_uid: &ShardUId,
     ) -> Result<&ChunkExtra, Error> {
+        self.drop_invalidated_cache();
         option_to_not_found(
-            read_with_cache(
+            read_with_shared_cache(
                 &self.store,
+                self.shared_cache.as_deref(),
                 ColChunkExtra,
                 &mut self.chunk_extras,
                 &get_block_shard_uid(block_hash, shard_uid),
@@ -909,10 +1490,33 @@ impl ChainStoreAccess for ChainStore {
 
     /// Get block header.
     fn get_block_header(&mut self, h: &CryptoHash) -> Result<&BlockHeader, Error> {
-        option_to_not_found(
-            read_with_cache(&self.store, ColBlockHeader, &mut self.headers, h.as_ref()),
-            &format!("BLOCK HEADER: {}", h),
-        )
+        if self.is_known_missing(ColBlockHeader, h.as_ref()) {
+            return Err(ErrorKind::DBNotFoundErr(format!("BLOCK HEADER: {}", h)).into());
+        }
+        self.drop_invalidated_cache();
+        let header = read_with_shared_cache(
+            &self.store,
+            self.shared_cache.as_deref(),
+            ColBlockHeader,
+            &mut self.headers,
+            h.as_ref(),
+        );
+        if let (Ok(None), Some(negative_cache)) = (&header, &self.negative_cache) {
+            negative_cache.insert(ColBlockHeader, h.as_ref());
+        }
+        option_to_not_found(header, &format!("BLOCK HEADER: {}", h))
+    }
+
+    /// The walk back from `sync_hash` is memoized, so that repeated lookups on the same chain
+    /// don't read the headers again.
+    fn get_header_on_chain_by_height(
+        &mut self,
+        sync_hash: &CryptoHash,
+        height: BlockHeight,
+    ) -> Result<&BlockHeader, Error> {
+        let hash =
+            self.hash_on_chain_by_height(|store| &mut store.headers_on_chain, sync_hash, height)?;
+        self.get_block_header(&hash)
     }
 
     /// Returns hash of the block on the main chain for given height.
@@ -1026,6 +1630,16 @@ impl ChainStoreAccess for ChainStore {
         Ok(self.store.get_ser(ColBlocksToCatchup, hash.as_ref())?.unwrap_or_else(|| vec![]))
     }
 
+    fn get_prev_block_with_new_chunk(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Option<(CryptoHash, ShardId)>, Error> {
+        Ok(self
+            .store
+            .get_ser(ColPrevBlockWithNewChunk, &get_block_shard_id(block_hash, shard_id))?)
+    }
+
     fn is_block_challenged(&mut self, hash: &CryptoHash) -> Result<bool, Error> {
         return Ok(self
             .store
@@ -1033,6 +1647,16 @@ impl ChainStoreAccess for ChainStore {
             .unwrap_or_else(|| false));
     }
 
+    fn is_known_challenged_descendant(&mut self, hash: &CryptoHash) -> bool {
+        self.challenged_descendants.contains(&hash.as_ref().to_vec())
+    }
+
+    fn cache_challenged_descendants(&mut self, hashes: &[CryptoHash]) {
+        for hash in hashes {
+            self.challenged_descendants.put(hash.as_ref().to_vec(), ());
+        }
+    }
+
     fn is_invalid_chunk(
         &mut self,
         chunk_hash: &ChunkHash,
@@ -1130,6 +1754,7 @@ struct ChainStoreCacheUpdate {
     chunk_hash_per_height_shard: HashMap<(BlockHeight, ShardId), ChunkHash>,
     height_to_hashes: HashMap<BlockHeight, Option<CryptoHash>>,
     next_block_hashes: HashMap<CryptoHash, CryptoHash>,
+    prev_blocks_with_new_chunk: HashMap<(CryptoHash, ShardId), (CryptoHash, ShardId)>,
     epoch_light_client_blocks: HashMap<CryptoHash, LightClientBlockView>,
     my_last_approvals: HashMap<CryptoHash, Approval>,
     last_approvals_per_account: HashMap<AccountId, Approval>,
@@ -1137,6 +1762,8 @@ struct ChainStoreCacheUpdate {
     incoming_receipts: HashMap<(CryptoHash, ShardId), Vec<ReceiptProof>>,
     outcomes: HashMap<CryptoHash, Vec<ExecutionOutcomeWithIdAndProof>>,
     outcome_ids: HashMap<(CryptoHash, ShardId), Vec<CryptoHash>>,
+    outcomes_by_block_shard: HashMap<(CryptoHash, ShardId), Vec<ExecutionOutcomeWithIdAndProof>>,
+    outcome_parents: HashMap<CryptoHash, CryptoHash>,
     invalid_chunks: HashMap<ChunkHash, EncodedShardChunk>,
     receipt_id_to_shard_id: HashMap<CryptoHash, ShardId>,
     transactions: HashSet<SignedTransaction>,
@@ -1146,6 +1773,8 @@ struct ChainStoreCacheUpdate {
     block_ordinal_to_hash: HashMap<NumBlocks, CryptoHash>,
     gc_count: HashMap<DBCol, GCCount>,
     processed_block_heights: HashSet<BlockHeight>,
+    /// Keys of the shared columns deleted by garbage collection, to invalidate in the shared cache.
+    shared_cache_garbage: Vec<(DBCol, Vec<u8>)>,
 }
 
 pub struct ChainStoreUpdateImpl<T> {
@@ -1374,18 +2003,40 @@ impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
         }
     }
 
-    /// Get block header from the current chain by height.
-    fn get_block_hash_by_height(&mut self, height: BlockHeight) -> Result<CryptoHash, Error> {
-        match self.chain_store_cache_update.height_to_hashes.get(&height) {
-            Some(Some(hash)) => Ok(*hash),
-            Some(None) => Err(ErrorKind::DBNotFoundErr(format!("BLOCK HEIGHT: {}", height)).into()),
-            None => self.chain_store.get_block_hash_by_height(height),
+    /// Chains ending at committed blocks are looked up in the memoized `ChainStore` walks.
+    fn get_header_on_chain_by_height(
+        &mut self,
+        sync_hash: &CryptoHash,
+        height: BlockHeight,
+    ) -> Result<&BlockHeader, Error> {
+        if !self.chain_store_cache_update.headers.contains_key(sync_hash) {
+            return self.chain_store.get_header_on_chain_by_height(sync_hash, height);
         }
-    }
-
-    fn get_block_refcount(&mut self, block_hash: &CryptoHash) -> Result<&u64, Error> {
-        if let Some(refcount) = self.chain_store_cache_update.block_refcounts.get(block_hash) {
-            Ok(refcount)
+        let mut header = self.get_block_header(sync_hash)?;
+        let mut hash = *sync_hash;
+        while header.height() > height {
+            hash = *header.prev_hash();
+            header = self.get_block_header(&hash)?;
+        }
+        let header_height = header.height();
+        if header_height < height {
+            return Err(ErrorKind::InvalidBlockHeight(header_height).into());
+        }
+        self.get_block_header(&hash)
+    }
+
+    /// Get block header from the current chain by height.
+    fn get_block_hash_by_height(&mut self, height: BlockHeight) -> Result<CryptoHash, Error> {
+        match self.chain_store_cache_update.height_to_hashes.get(&height) {
+            Some(Some(hash)) => Ok(*hash),
+            Some(None) => Err(ErrorKind::DBNotFoundErr(format!("BLOCK HEIGHT: {}", height)).into()),
+            None => self.chain_store.get_block_hash_by_height(height),
+        }
+    }
+
+    fn get_block_refcount(&mut self, block_hash: &CryptoHash) -> Result<&u64, Error> {
+        if let Some(refcount) = self.chain_store_cache_update.block_refcounts.get(block_hash) {
+            Ok(refcount)
         } else {
             let refcount = match self.chain_store.get_block_refcount(block_hash) {
                 Ok(refcount) => refcount,
@@ -1499,6 +2150,20 @@ impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
         self.chain_store.get_blocks_to_catchup(prev_hash)
     }
 
+    fn get_prev_block_with_new_chunk(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Option<(CryptoHash, ShardId)>, Error> {
+        if let Some(prev_block_with_new_chunk) =
+            self.chain_store_cache_update.prev_blocks_with_new_chunk.get(&(*block_hash, shard_id))
+        {
+            Ok(Some(*prev_block_with_new_chunk))
+        } else {
+            self.chain_store.get_prev_block_with_new_chunk(block_hash, shard_id)
+        }
+    }
+
     fn is_block_challenged(&mut self, hash: &CryptoHash) -> Result<bool, Error> {
         if self.challenged_blocks.contains(hash) {
             return Ok(true);
@@ -1506,6 +2171,17 @@ impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
         self.chain_store.is_block_challenged(hash)
     }
 
+    fn is_known_challenged_descendant(&mut self, hash: &CryptoHash) -> bool {
+        self.chain_store.is_known_challenged_descendant(hash)
+    }
+
+    fn cache_challenged_descendants(&mut self, hashes: &[CryptoHash]) {
+        // Challenges saved in this update are not committed yet, so the result may rely on them.
+        if self.challenged_blocks.is_empty() {
+            self.chain_store.cache_challenged_descendants(hashes)
+        }
+    }
+
     fn is_invalid_chunk(
         &mut self,
         chunk_hash: &ChunkHash,
@@ -1797,6 +2473,19 @@ impl<'a> ChainStoreUpdate<'a> {
         self.chain_store_cache_update.next_block_hashes.insert(*hash, next_hash);
     }
 
+    /// Save the previous block with a new chunk for the shard, see
+    /// `ChainStoreAccess::get_prev_block_hash_with_new_chunk`.
+    pub fn save_prev_block_with_new_chunk(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        prev_block_with_new_chunk: (CryptoHash, ShardId),
+    ) {
+        self.chain_store_cache_update
+            .prev_blocks_with_new_chunk
+            .insert((*block_hash, shard_id), prev_block_with_new_chunk);
+    }
+
     pub fn save_epoch_light_client_block(
         &mut self,
         epoch_hash: &CryptoHash,
@@ -1859,19 +2548,26 @@ impl<'a> ChainStoreUpdate<'a> {
         proofs: Vec<MerklePath>,
     ) {
         let mut outcome_ids = Vec::with_capacity(outcomes.len());
+        let mut block_shard_outcomes = Vec::with_capacity(outcomes.len());
         for (outcome_with_id, proof) in outcomes.into_iter().zip(proofs.into_iter()) {
-            outcome_ids.push(outcome_with_id.id);
+            let id = outcome_with_id.id;
+            outcome_ids.push(id);
+            for receipt_id in outcome_with_id.outcome.receipt_ids.iter() {
+                self.chain_store_cache_update.outcome_parents.insert(*receipt_id, id);
+            }
+            let outcome =
+                ExecutionOutcomeWithIdAndProof { outcome_with_id, proof, block_hash: *block_hash };
             self.chain_store_cache_update
                 .outcomes
-                .entry(outcome_with_id.id)
+                .entry(id)
                 .or_insert_with(Vec::new)
-                .push(ExecutionOutcomeWithIdAndProof {
-                    outcome_with_id,
-                    proof,
-                    block_hash: *block_hash,
-                })
+                .push(outcome.clone());
+            block_shard_outcomes.push(outcome);
         }
         self.chain_store_cache_update.outcome_ids.insert((*block_hash, shard_id), outcome_ids);
+        self.chain_store_cache_update
+            .outcomes_by_block_shard
+            .insert((*block_hash, shard_id), block_shard_outcomes);
     }
 
     pub fn save_trie_changes(&mut self, trie_changes: WrappedTrieChanges) {
@@ -1921,6 +2617,51 @@ impl<'a> ChainStoreUpdate<'a> {
         self.remove_state_dl_infos.push(hash);
     }
 
+    pub fn save_shard_quarantine(
+        &mut self,
+        shard_uid: &ShardUId,
+        quarantine: &ShardQuarantine,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColBlockMisc, &shard_quarantine_key(shard_uid), quarantine)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    pub fn remove_shard_quarantine(&mut self, shard_uid: &ShardUId) {
+        let mut store_update = self.store().store_update();
+        store_update.delete(ColBlockMisc, &shard_quarantine_key(shard_uid));
+        self.merge(store_update);
+    }
+
+    pub fn save_partially_applied_block(
+        &mut self,
+        block_hash: &CryptoHash,
+        deferred_shards: &Vec<ShardId>,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(
+            ColBlockMisc,
+            &partially_applied_block_key(block_hash),
+            deferred_shards,
+        )?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    pub fn remove_partially_applied_block(&mut self, block_hash: &CryptoHash) {
+        let mut store_update = self.store().store_update();
+        store_update.delete(ColBlockMisc, &partially_applied_block_key(block_hash));
+        self.merge(store_update);
+    }
+
+    pub fn save_chain_schema_version(&mut self, version: u32) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColBlockMisc, CHAIN_SCHEMA_VERSION_KEY, &version)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
     pub fn save_challenged_block(&mut self, hash: CryptoHash) {
         self.challenged_blocks.insert(hash);
     }
@@ -2181,6 +2922,9 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_outgoing_receipts(&block_hash, shard_id);
             self.gc_col(ColIncomingReceipts, &block_shard_id);
             self.gc_col(ColChunkPerHeightShard, &block_shard_id);
+            self.gc_col(ColPrevBlockWithNewChunk, &block_shard_id);
+            // Finalization progress of a sync to this block which was abandoned.
+            store_update.delete(ColStateSyncFinalizeProgress, &block_shard_id);
 
             // For incoming State Parts it's done in chain.clear_downloaded_parts()
             // The following code is mostly for outgoing State Parts.
@@ -2209,6 +2953,7 @@ impl<'a> ChainStoreUpdate<'a> {
         self.gc_col(ColNextBlockHashes, &block_hash_vec);
         self.gc_col(ColChallengedBlocks, &block_hash_vec);
         self.gc_col(ColBlocksToCatchup, &block_hash_vec);
+        self.remove_partially_applied_block(&block_hash);
         let storage_key = KeyForStateChanges::for_block(&block_hash);
         let stored_state_changes: Vec<Vec<u8>> = self
             .chain_store
@@ -2220,6 +2965,13 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_col(ColStateChanges, &key);
         }
         self.gc_col(ColBlockRefCount, &block_hash_vec);
+        if self.chain_store.is_state_sync_boundary_block(&block_hash)? {
+            store_update.delete(ColStateSyncBoundaryBlocks, &block_hash_vec);
+        }
+        store_update.delete(ColEpochSummaryCounters, &block_hash_vec);
+        if self.chain_store.store.exists(ColApprovalAudit, &block_hash_vec)? {
+            store_update.delete(ColApprovalAudit, &block_hash_vec);
+        }
         self.gc_outcomes(&block)?;
         match gc_mode {
             GCMode::StateSync { clear_block_info: false } => {}
@@ -2352,9 +3104,22 @@ impl<'a> ChainStoreUpdate<'a> {
                 self.chain_store.get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?;
             for outcome_id in outcome_ids {
                 let mut outcomes_with_id = self.chain_store.get_outcomes_by_id(&outcome_id)?;
+                let receipt_ids = outcomes_with_id
+                    .iter()
+                    .filter(|outcome| &outcome.block_hash == block_hash)
+                    .flat_map(|outcome| outcome.outcome_with_id.outcome.receipt_ids.clone())
+                    .collect::<Vec<_>>();
                 outcomes_with_id.retain(|outcome| &outcome.block_hash != block_hash);
                 if outcomes_with_id.is_empty() {
                     self.gc_col(ColTransactionResult, &outcome_id.as_ref().into());
+                    self.gc_outcome_parent(&outcome_id)?;
+                    // Receipts which were never executed, e.g. the ones produced on a fork, would
+                    // otherwise keep their links forever.
+                    for receipt_id in receipt_ids {
+                        if self.chain_store.get_outcomes_by_id(&receipt_id)?.is_empty() {
+                            self.gc_outcome_parent(&receipt_id)?;
+                        }
+                    }
                 } else {
                     store_update.set_ser(
                         ColTransactionResult,
@@ -2364,11 +3129,20 @@ impl<'a> ChainStoreUpdate<'a> {
                 }
             }
             self.gc_col(ColOutcomeIds, &get_block_shard_id(block_hash, shard_id));
+            self.gc_col(ColOutcomesByBlockShard, &get_block_shard_id(block_hash, shard_id));
         }
         self.merge(store_update);
         Ok(())
     }
 
+    /// Deletes the link from the receipt to its parent, if there is one.
+    fn gc_outcome_parent(&mut self, id: &CryptoHash) -> Result<(), Error> {
+        if self.chain_store.store.exists(ColOutcomeParent, id.as_ref())? {
+            self.gc_col(ColOutcomeParent, &id.as_ref().into());
+        }
+        Ok(())
+    }
+
     fn gc_col(&mut self, col: DBCol, key: &Vec<u8>) {
         assert!(SHOULD_COL_GC[col as usize]);
         let mut store_update = self.store().store_update();
@@ -2396,6 +3170,8 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColBlock => {
                 store_update.delete(col, key);
                 self.chain_store.blocks.pop(key);
+                self.chain_store.headers_on_chain.pop(key);
+                self.chain_store.tx_validity_headers_on_chain.pop(key);
             }
             DBCol::ColBlockExtra => {
                 store_update.delete(col, key);
@@ -2466,6 +3242,12 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColOutcomeIds => {
                 store_update.delete(col, key);
             }
+            DBCol::ColOutcomesByBlockShard => {
+                store_update.delete(col, key);
+            }
+            DBCol::ColOutcomeParent => {
+                store_update.delete(col, key);
+            }
             DBCol::ColStateDlInfos => {
                 store_update.delete(col, key);
             }
@@ -2479,6 +3261,9 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColHeaderHashesByHeight => {
                 store_update.delete(col, key);
             }
+            DBCol::ColPrevBlockWithNewChunk => {
+                store_update.delete(col, key);
+            }
             DBCol::ColDbVersion
             | DBCol::ColBlockMisc
             | DBCol::ColGCCount
@@ -2498,19 +3283,164 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::_ColLastBlockWithNewChunk
             | DBCol::_ColTransactionRefCount
             | DBCol::ColStateChangesForSplitStates
+            | DBCol::ColDiscardedForks
+            | DBCol::ColRejectedBlocks
+            | DBCol::ColApprovalAudit
+            | DBCol::ColStateSyncFinalizeProgress
+            | DBCol::ColStateSyncBoundaryBlocks
+            | DBCol::ColEpochSummaryCounters
+            | DBCol::ColEpochSummaries
             | DBCol::ColCachedContractCode => {
                 unreachable!();
             }
         }
+        if self.chain_store.shared_cache.is_some() && SharedChainCache::is_shared_column(col) {
+            self.chain_store_cache_update.shared_cache_garbage.push((col, key.clone()));
+        }
         self.inc_gc(col);
         self.merge(store_update);
     }
 
+    /// Records a fork block which is about to be deleted by garbage collection.
+    pub fn save_discarded_fork(&mut self, fork: DiscardedFork) -> Result<(), Error> {
+        let key = index_to_bytes(fork.height);
+        let mut forks: Vec<DiscardedFork> =
+            self.chain_store.store.get_ser(ColDiscardedForks, &key)?.unwrap_or_default();
+        if forks.iter().all(|recorded| recorded.hash != fork.hash) {
+            forks.push(fork);
+        }
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColDiscardedForks, &key, &forks)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    /// Deletes the records of fork blocks discarded at `height`.
+    pub fn clear_discarded_forks(&mut self, height: BlockHeight) -> Result<(), Error> {
+        let key = index_to_bytes(height);
+        if self.chain_store.store.exists(ColDiscardedForks, &key)? {
+            let mut store_update = self.store().store_update();
+            store_update.delete(ColDiscardedForks, &key);
+            self.merge(store_update);
+        }
+        Ok(())
+    }
+
+    /// Records a block rejected as invalid, dropping the oldest records above
+    /// `MAX_REJECTED_BLOCKS`.
+    pub fn save_rejected_block(&mut self, block: RejectedBlock) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        let index = match self
+            .chain_store
+            .store
+            .get_ser::<u64>(ColBlockMisc, REJECTED_BLOCKS_NEXT_INDEX_KEY)?
+        {
+            Some(index) => {
+                if index >= MAX_REJECTED_BLOCKS {
+                    store_update
+                        .delete(ColRejectedBlocks, &index_to_bytes(index - MAX_REJECTED_BLOCKS));
+                }
+                index
+            }
+            // The records saved before the next index was persisted are looked up once.
+            None => {
+                let mut indices = vec![];
+                for (key, _) in self.chain_store.store.iter(ColRejectedBlocks) {
+                    indices.push(rejected_block_index(&key)?);
+                }
+                let index = indices.iter().max().map_or(0, |index| index + 1);
+                for old_index in indices {
+                    if old_index + MAX_REJECTED_BLOCKS <= index {
+                        store_update.delete(ColRejectedBlocks, &index_to_bytes(old_index));
+                    }
+                }
+                index
+            }
+        };
+        store_update.set_ser(ColRejectedBlocks, &index_to_bytes(index), &block)?;
+        store_update.set_ser(ColBlockMisc, REJECTED_BLOCKS_NEXT_INDEX_KEY, &(index + 1))?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    pub fn save_epoch_summary_counters(
+        &mut self,
+        block_hash: &CryptoHash,
+        counters: &EpochSummary,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColEpochSummaryCounters, block_hash.as_ref(), counters)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    pub fn save_approval_audit(
+        &mut self,
+        block_hash: &CryptoHash,
+        record: &ApprovalAuditRecord,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColApprovalAudit, block_hash.as_ref(), record)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    pub fn save_epoch_summary(&mut self, summary: &EpochSummary) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColEpochSummaries, &index_to_bytes(summary.epoch_height), summary)?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    /// Marks the block as saved without increasing the refcount of its previous block, see
+    /// `BlockSaveMode::StateSyncBoundary`.
+    pub fn save_state_sync_boundary_block(&mut self, block_hash: &CryptoHash) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(ColStateSyncBoundaryBlocks, block_hash.as_ref(), &())?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    /// Records that the state of the shard downloaded for `sync_hash` has been finalized up to
+    /// `height`.
+    pub fn save_state_sync_finalize_progress(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: &CryptoHash,
+        height: BlockHeight,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(
+            ColStateSyncFinalizeProgress,
+            &get_block_shard_id(sync_hash, shard_id),
+            &height,
+        )?;
+        self.merge(store_update);
+        Ok(())
+    }
+
+    /// Deletes the state sync finalization progress of the shard once finalization is complete.
+    pub fn remove_state_sync_finalize_progress(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: &CryptoHash,
+    ) {
+        let mut store_update = self.store().store_update();
+        store_update.delete(ColStateSyncFinalizeProgress, &get_block_shard_id(sync_hash, shard_id));
+        self.merge(store_update);
+    }
+
     /// Merge another StoreUpdate into this one
     pub fn merge(&mut self, store_update: StoreUpdate) {
         self.store_updates.push(store_update);
     }
 
+    /// Whether `store_update` writes a key one of the merged store updates also writes, in
+    /// which case it can't be merged without overwriting it, see `StoreUpdate::overlaps`.
+    pub fn overlaps(&self, store_update: &StoreUpdate) -> bool {
+        self.store_updates.iter().any(|merged| merged.overlaps(store_update))
+    }
+
     fn write_col_misc<T: BorshSerialize>(
         store_update: &mut StoreUpdate,
         key: &[u8],
@@ -2608,6 +3538,14 @@ impl<'a> ChainStoreUpdate<'a> {
                 .chain_store_cache_update
                 .outcome_ids
                 .insert((*block_hash, shard_id), outcome_ids);
+            if let Some(outcomes) =
+                source_store.get_outcomes_by_block_shard(block_hash, shard_id)?
+            {
+                chain_store_update
+                    .chain_store_cache_update
+                    .outcomes_by_block_shard
+                    .insert((*block_hash, shard_id), outcomes);
+            }
         }
         chain_store_update
             .chain_store_cache_update
@@ -2766,6 +3704,15 @@ impl<'a> ChainStoreUpdate<'a> {
         for (block_hash, next_hash) in self.chain_store_cache_update.next_block_hashes.iter() {
             store_update.set_ser(ColNextBlockHashes, block_hash.as_ref(), next_hash)?;
         }
+        for ((block_hash, shard_id), prev_block_with_new_chunk) in
+            self.chain_store_cache_update.prev_blocks_with_new_chunk.iter()
+        {
+            store_update.set_ser(
+                ColPrevBlockWithNewChunk,
+                &get_block_shard_id(block_hash, *shard_id),
+                prev_block_with_new_chunk,
+            )?;
+        }
         for (epoch_hash, light_client_block) in
             self.chain_store_cache_update.epoch_light_client_blocks.iter()
         {
@@ -2805,6 +3752,18 @@ impl<'a> ChainStoreUpdate<'a> {
                 &ids,
             )?;
         }
+        for ((block_hash, shard_id), outcomes) in
+            self.chain_store_cache_update.outcomes_by_block_shard.iter()
+        {
+            store_update.set_ser(
+                ColOutcomesByBlockShard,
+                &get_block_shard_id(block_hash, *shard_id),
+                outcomes,
+            )?;
+        }
+        for (receipt_id, parent_id) in self.chain_store_cache_update.outcome_parents.iter() {
+            store_update.set_ser(ColOutcomeParent, receipt_id.as_ref(), parent_id)?;
+        }
         for (receipt_id, shard_id) in self.chain_store_cache_update.receipt_id_to_shard_id.iter() {
             let data = shard_id.try_to_vec()?;
             store_update.update_refcount(ColReceiptIdToShardId, receipt_id.as_ref(), &data, 1);
@@ -2843,11 +3802,11 @@ impl<'a> ChainStoreUpdate<'a> {
 
         let mut affected_catchup_blocks = HashSet::new();
         for (prev_hash, hash) in self.remove_blocks_to_catchup.drain(..) {
-            assert!(!affected_catchup_blocks.contains(&prev_hash));
             if affected_catchup_blocks.contains(&prev_hash) {
-                return Err(ErrorKind::Other(
-                    "Multiple changes to the store affect the same catchup block".to_string(),
-                )
+                return Err(ErrorKind::InconsistentCatchupBookkeeping(format!(
+                    "multiple changes to the store affect catchup block {}",
+                    prev_hash
+                ))
                 .into());
             }
             affected_catchup_blocks.insert(prev_hash);
@@ -2855,14 +3814,16 @@ impl<'a> ChainStoreUpdate<'a> {
             let mut prev_table =
                 self.chain_store.get_blocks_to_catchup(&prev_hash).unwrap_or_else(|_| vec![]);
 
-            let mut remove_idx = prev_table.len();
-            for (i, val) in prev_table.iter().enumerate() {
-                if *val == hash {
-                    remove_idx = i;
+            let remove_idx = match prev_table.iter().rposition(|val| *val == hash) {
+                Some(remove_idx) => remove_idx,
+                None => {
+                    return Err(ErrorKind::InconsistentCatchupBookkeeping(format!(
+                        "block {} is not queued for catchup after {}",
+                        hash, prev_hash
+                    ))
+                    .into())
                 }
-            }
-
-            assert_ne!(remove_idx, prev_table.len());
+            };
             prev_table.swap_remove(remove_idx);
 
             if prev_table.len() > 0 {
@@ -2872,11 +3833,11 @@ impl<'a> ChainStoreUpdate<'a> {
             }
         }
         for prev_hash in self.remove_prev_blocks_to_catchup.drain(..) {
-            assert!(!affected_catchup_blocks.contains(&prev_hash));
             if affected_catchup_blocks.contains(&prev_hash) {
-                return Err(ErrorKind::Other(
-                    "Multiple changes to the store affect the same catchup block".to_string(),
-                )
+                return Err(ErrorKind::InconsistentCatchupBookkeeping(format!(
+                    "multiple changes to the store affect catchup block {}",
+                    prev_hash
+                ))
                 .into());
             }
             affected_catchup_blocks.insert(prev_hash);
@@ -2884,11 +3845,11 @@ impl<'a> ChainStoreUpdate<'a> {
             store_update.delete(ColBlocksToCatchup, prev_hash.as_ref());
         }
         for (prev_hash, new_hash) in self.add_blocks_to_catchup.drain(..) {
-            assert!(!affected_catchup_blocks.contains(&prev_hash));
             if affected_catchup_blocks.contains(&prev_hash) {
-                return Err(ErrorKind::Other(
-                    "Multiple changes to the store affect the same catchup block".to_string(),
-                )
+                return Err(ErrorKind::InconsistentCatchupBookkeeping(format!(
+                    "multiple changes to the store affect catchup block {}",
+                    prev_hash
+                ))
                 .into());
             }
             affected_catchup_blocks.insert(prev_hash);
@@ -2963,8 +3924,30 @@ impl<'a> ChainStoreUpdate<'a> {
             block_merkle_tree,
             block_ordinal_to_hash,
             processed_block_heights,
+            shared_cache_garbage,
             ..
         } = self.chain_store_cache_update;
+        // Invalidated only once deleted from the database, so that they are not read back into
+        // the shared cache. The own caches already dropped them in `gc_col`, so they don't have to
+        // be cleared unless another store invalidated something in the meantime.
+        if let Some(shared_cache) = &self.chain_store.shared_cache {
+            if !shared_cache_garbage.is_empty() {
+                let generation = shared_cache.invalidate(shared_cache_garbage);
+                if self.chain_store.shared_cache_generation + 1 == generation {
+                    self.chain_store.shared_cache_generation = generation;
+                }
+            }
+        }
+        // Blocks and headers are readable from the database now, so they must not be reported
+        // missing any longer, even if they were looked up after being saved to this update.
+        if let Some(negative_cache) = &self.chain_store.negative_cache {
+            for hash in blocks.keys() {
+                negative_cache.remove(ColBlock, hash.as_ref());
+            }
+            for hash in headers.keys() {
+                negative_cache.remove(ColBlockHeader, hash.as_ref());
+            }
+        }
         for (hash, block) in blocks {
             self.chain_store.blocks.put(hash.into(), block);
         }
@@ -3084,7 +4067,46 @@ impl Into<SavedStoreUpdate> for ChainStoreUpdate<'_> {
 /// applying block in other thread
 pub type SavedStoreUpdate = ChainStoreUpdateImpl<()>;
 
+/// A `SavedStoreUpdate` flattened to the database operations it would commit, see
+/// `ChainStore::spill_saved_store_update`.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct SpilledStoreUpdate {
+    /// Whether the operations include trie changes, in which case the trie caches have to be
+    /// updated when they are committed.
+    updates_tries: bool,
+    transaction: DBTransaction,
+}
+
 impl SavedStoreUpdate {
+    /// Estimated size in bytes of the data held by the update: the operations of the merged store
+    /// updates, the serialized size of the cached blocks, chunks, receipts and outcomes, and the
+    /// trie and state changes.
+    pub fn size_bytes(&self) -> u64 {
+        fn borsh_size<T: BorshSerialize>(value: &T) -> u64 {
+            value.try_to_vec().map_or(0, |bytes| bytes.len() as u64)
+        }
+        let cache = &self.chain_store_cache_update;
+        let mut size = self.store_updates.iter().map(StoreUpdate::size_bytes).sum::<u64>();
+        size += cache.blocks.values().map(borsh_size).sum::<u64>();
+        size += cache.headers.values().map(borsh_size).sum::<u64>();
+        size += cache.block_extras.values().map(borsh_size).sum::<u64>();
+        size += cache.chunk_extras.values().map(borsh_size).sum::<u64>();
+        size += cache.chunks.values().map(borsh_size).sum::<u64>();
+        size += cache.partial_chunks.values().map(borsh_size).sum::<u64>();
+        size += cache.invalid_chunks.values().map(borsh_size).sum::<u64>();
+        size += cache.outgoing_receipts.values().map(borsh_size).sum::<u64>();
+        size += cache.incoming_receipts.values().map(borsh_size).sum::<u64>();
+        size += cache.outcomes.values().map(borsh_size).sum::<u64>();
+        size += cache.transactions.iter().map(borsh_size).sum::<u64>();
+        size += cache.receipts.values().map(borsh_size).sum::<u64>();
+        for wrapped_trie_changes in self.trie_changes.iter() {
+            size += borsh_size(wrapped_trie_changes.trie_changes());
+            size += wrapped_trie_changes.state_changes().iter().map(borsh_size).sum::<u64>();
+        }
+        size += self.add_state_changes_for_split_states.values().map(borsh_size).sum::<u64>();
+        size
+    }
+
     pub fn restore<'a>(self, chain_store: &'a mut ChainStore) -> ChainStoreUpdate<'a> {
         ChainStoreUpdate {
             chain_store,
@@ -3115,31 +4137,40 @@ mod tests {
     use std::sync::Arc;
 
     use borsh::BorshSerialize;
+    use chrono::{TimeZone, Utc};
     use strum::IntoEnumIterator;
 
-    use near_chain_configs::GenesisConfig;
+    use near_chain_configs::{GenesisConfig, DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE};
     use near_crypto::KeyType;
     use near_primitives::block::{Block, Tip};
     use near_primitives::epoch_manager::block_info::BlockInfo;
     use near_primitives::errors::InvalidTxError;
-    use near_primitives::hash::hash;
-    use near_primitives::types::{BlockHeight, EpochId, GCCount, NumBlocks};
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::merkle::PartialMerkleTree;
+    use near_primitives::transaction::{ExecutionOutcome, ExecutionOutcomeWithId};
+    use near_primitives::types::{BlockHeight, EpochId, GCCount, NumBlocks, ShardId};
     use near_primitives::utils::index_to_bytes;
     use near_primitives::validator_signer::InMemoryValidatorSigner;
     use near_store::test_utils::create_test_store;
-    use near_store::DBCol;
+    use near_store::{DBCol, Store, REJECTED_BLOCKS_NEXT_INDEX_KEY};
 
-    use crate::store::{ChainStoreAccess, GCMode};
+    use crate::shared_chain_cache::SharedChainCache;
+    use crate::store::{ChainStore, ChainStoreAccess, GCMode, MAX_REJECTED_BLOCKS};
     use crate::store_validator::StoreValidator;
     use crate::test_utils::KeyValueRuntime;
-    use crate::{Chain, ChainGenesis, DoomslugThresholdMode};
+    use crate::tests::CountingDB;
+    use crate::types::{DiscardedFork, RejectedBlock};
+    use crate::{Chain, ChainGenesis, DoomslugThresholdMode, ErrorKind};
 
     fn get_chain() -> Chain {
         get_chain_with_epoch_length(10)
     }
 
     fn get_chain_with_epoch_length(epoch_length: NumBlocks) -> Chain {
-        let store = create_test_store();
+        get_chain_with_store(create_test_store(), epoch_length)
+    }
+
+    fn get_chain_with_store(store: Store, epoch_length: NumBlocks) -> Chain {
         let chain_genesis = ChainGenesis::test();
         let validators = vec![vec!["test1"]];
         let runtime_adapter = Arc::new(KeyValueRuntime::new_with_validators(
@@ -3431,13 +4462,17 @@ mod tests {
             DBCol::ColChunkPerHeightShard,
             DBCol::ColBlockRefCount,
             DBCol::ColOutcomeIds,
+            DBCol::ColOutcomesByBlockShard,
             DBCol::ColChunkExtra,
         ];
         for col in DBCol::iter() {
             println!("current column is {:?}", col);
             if gced_cols.contains(&col) {
                 // only genesis block includes new chunk.
-                let count = if col == DBCol::ColOutcomeIds { Some(1) } else { Some(8) };
+                let count = match col {
+                    DBCol::ColOutcomeIds | DBCol::ColOutcomesByBlockShard => Some(1),
+                    _ => Some(8),
+                };
                 assert_eq!(
                     chain
                         .store()
@@ -3538,6 +4573,543 @@ mod tests {
         assert!(chain.mut_store().get_next_block_hash(blocks[6].hash()).is_ok());
     }
 
+    /// Builds a canonical chain of `length` blocks with `num_forks` one block forks at height 2.
+    /// Genesis and block timestamps are fixed, so every call produces an identical store.
+    /// Returns the chain and the hashes of the fork blocks.
+    fn get_chain_with_forks_at_height_2(
+        length: BlockHeight,
+        num_forks: u64,
+    ) -> (Chain, Vec<CryptoHash>) {
+        let chain_genesis =
+            ChainGenesis { time: Utc.ymd(2021, 1, 1).and_hms(0, 0, 0), ..ChainGenesis::test() };
+        let runtime_adapter = Arc::new(KeyValueRuntime::new_with_validators(
+            create_test_store(),
+            vec![vec!["test1".parse().unwrap()]],
+            1,
+            1,
+            10,
+        ));
+        let mut chain =
+            Chain::new(runtime_adapter.clone(), &chain_genesis, DoomslugThresholdMode::NoApprovals)
+                .unwrap();
+        let signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test1".parse().unwrap(),
+            KeyType::ED25519,
+            "test1",
+        ));
+        let save_block = |chain: &mut Chain, prev: &Block, timestamp: u64, canonical: bool| {
+            // This is a hack to make the KeyValueRuntime to have epoch information stored
+            runtime_adapter.get_next_epoch_id_from_prev_block(prev.hash()).unwrap();
+            let height = prev.header().height() + 1;
+            let mut block = Block::empty_with_height(prev, height, &*signer);
+            block.mut_header().get_mut().inner_lite.timestamp = timestamp;
+            block.mut_header().resign(&*signer);
+            let mut store_update = chain.mut_store().store_update();
+            store_update.save_block(block.clone());
+            store_update.inc_block_refcount(prev.hash()).unwrap();
+            store_update.save_block_header(block.header().clone()).unwrap();
+            if canonical {
+                store_update.save_head(&Tip::from_header(block.header())).unwrap();
+                store_update
+                    .chain_store_cache_update
+                    .height_to_hashes
+                    .insert(height, Some(*block.hash()));
+                store_update.save_next_block_hash(prev.hash(), *block.hash());
+            }
+            store_update.commit().unwrap();
+            block
+        };
+
+        let mut prev_block = chain.get_block_by_height(0).unwrap().clone();
+        let mut forks = vec![];
+        for height in 1..=length {
+            let block = save_block(&mut chain, &prev_block, height * 1000, true);
+            if height == 1 {
+                for i in 1..=num_forks {
+                    let fork = save_block(&mut chain, &block, 2000 + i, false);
+                    forks.push(*fork.hash());
+                }
+            }
+            prev_block = block;
+        }
+        (chain, forks)
+    }
+
+    #[test]
+    fn test_ordered_block_hashes_by_height() {
+        let (mut chain, mut forks) = get_chain_with_forks_at_height_2(5, 5);
+        let canonical_hash = chain.get_block_hash_by_height(2).unwrap();
+        forks.sort();
+        let mut expected = vec![canonical_hash];
+        expected.extend(forks);
+        assert_eq!(chain.mut_store().get_ordered_block_hashes_by_height(2).unwrap(), expected);
+        assert_eq!(
+            chain.mut_store().get_ordered_block_hashes_by_height(3).unwrap(),
+            vec![chain.get_block_hash_by_height(3).unwrap()]
+        );
+        assert!(chain.mut_store().get_ordered_block_hashes_by_height(6).is_err());
+    }
+
+    /// Forks at the same height are garbage collected in the same order on identical stores.
+    #[test]
+    fn test_clear_forks_data_deterministic_order() {
+        let gc_order = || {
+            let (mut chain, forks) = get_chain_with_forks_at_height_2(5, 5);
+            let tries = chain.runtime_adapter.get_tries();
+            let mut removed = vec![];
+            for _ in 0..forks.len() {
+                let mut gc_blocks_remaining = 1;
+                chain.clear_forks_data(tries.clone(), 2, &mut gc_blocks_remaining).unwrap();
+                assert_eq!(gc_blocks_remaining, 0);
+                let newly_removed = forks
+                    .iter()
+                    .filter(|hash| !removed.contains(*hash) && chain.get_block(hash).is_err())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                assert_eq!(newly_removed.len(), 1);
+                removed.extend(newly_removed);
+            }
+            let canonical_hash = chain.get_block_hash_by_height(2).unwrap();
+            assert!(chain.get_block(&canonical_hash).is_ok());
+            (forks, removed)
+        };
+        let (mut forks, removed) = gc_order();
+        assert_eq!(gc_order(), (forks.clone(), removed.clone()));
+        forks.sort();
+        assert_eq!(removed, forks);
+    }
+
+    /// Only the latest `MAX_REJECTED_BLOCKS` rejected blocks are kept.
+    #[test]
+    fn test_rejected_blocks_bounded() {
+        let mut chain = get_chain();
+        let rejected = |height: BlockHeight| RejectedBlock {
+            hash: hash(&height.to_le_bytes()),
+            height,
+            producer: None,
+            error: "InvalidGasPrice".to_string(),
+            timestamp: height,
+        };
+        for height in 0..MAX_REJECTED_BLOCKS + 10 {
+            let mut store_update = chain.mut_store().store_update();
+            store_update.save_rejected_block(rejected(height)).unwrap();
+            store_update.commit().unwrap();
+        }
+        let kept = chain.rejected_blocks(usize::MAX).unwrap();
+        assert_eq!(kept.len() as u64, MAX_REJECTED_BLOCKS);
+        assert_eq!(kept[0], rejected(MAX_REJECTED_BLOCKS + 9));
+        assert_eq!(kept[kept.len() - 1], rejected(10));
+        let stored = chain.store().store().iter(DBCol::ColRejectedBlocks).count();
+        assert_eq!(stored as u64, MAX_REJECTED_BLOCKS);
+
+        // Databases without the next index persisted continue after the stored records.
+        let mut store_update = chain.store().store().store_update();
+        store_update.delete(DBCol::ColBlockMisc, REJECTED_BLOCKS_NEXT_INDEX_KEY);
+        store_update.commit().unwrap();
+        let mut store_update = chain.mut_store().store_update();
+        store_update.save_rejected_block(rejected(MAX_REJECTED_BLOCKS + 10)).unwrap();
+        store_update.commit().unwrap();
+        let kept = chain.rejected_blocks(usize::MAX).unwrap();
+        assert_eq!(kept.len() as u64, MAX_REJECTED_BLOCKS);
+        assert_eq!(kept[0], rejected(MAX_REJECTED_BLOCKS + 10));
+        assert_eq!(kept[kept.len() - 1], rejected(11));
+    }
+
+    /// Fork blocks deleted by garbage collection are recorded only if requested.
+    #[test]
+    fn test_discarded_forks() {
+        let (mut chain, forks) = get_chain_with_forks_at_height_2(5, 2);
+        let tries = chain.runtime_adapter.get_tries();
+        chain.clear_forks_data(tries, 2, &mut 10).unwrap();
+        assert!(forks.iter().all(|hash| chain.get_block(hash).is_err()));
+        assert_eq!(chain.discarded_forks(0..=5).unwrap(), vec![]);
+
+        let (mut chain, forks) = get_chain_with_forks_at_height_2(5, 2);
+        chain.record_discarded_forks = true;
+        let tries = chain.runtime_adapter.get_tries();
+        let timestamps = forks
+            .iter()
+            .map(|hash| chain.get_block_header(hash).unwrap().raw_timestamp())
+            .collect::<Vec<_>>();
+        chain.clear_forks_data(tries, 2, &mut 10).unwrap();
+        let prev_hash = chain.get_block_hash_by_height(1).unwrap();
+        let discarded = chain.discarded_forks(2..=2).unwrap();
+        let mut expected = forks
+            .iter()
+            .zip(timestamps)
+            .map(|(hash, timestamp)| DiscardedFork {
+                height: 2,
+                hash: *hash,
+                prev_hash,
+                producer: Some("test1".parse().unwrap()),
+                timestamp,
+            })
+            .collect::<Vec<_>>();
+        expected.sort_by_key(|fork| fork.hash);
+        assert_eq!(discarded, expected);
+        assert_eq!(chain.discarded_forks(3..=5).unwrap(), vec![]);
+
+        let mut store_update = chain.mut_store().store_update();
+        store_update.clear_discarded_forks(2).unwrap();
+        store_update.commit().unwrap();
+        assert_eq!(chain.discarded_forks(0..=5).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_header_on_chain_by_height_memoized() {
+        let mut chain = get_chain();
+        let genesis = chain.get_block_by_height(0).unwrap().clone();
+        let signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test1".parse().unwrap(),
+            KeyType::ED25519,
+            "test1",
+        ));
+        // The main chain has blocks at all heights, the fork only at even ones.
+        let mut blocks = vec![genesis.clone()];
+        let mut fork = vec![genesis];
+        let mut store_update = chain.mut_store().store_update();
+        for height in 1..=10 {
+            let block = Block::empty_with_height(blocks.last().unwrap(), height, &*signer);
+            store_update.save_block_header(block.header().clone()).unwrap();
+            blocks.push(block);
+            if height % 2 == 0 {
+                let block = Block::empty_with_height(fork.last().unwrap(), height, &*signer);
+                store_update.save_block_header(block.header().clone()).unwrap();
+                fork.push(block);
+            }
+        }
+        store_update.commit().unwrap();
+        let sync_hash = *blocks[10].hash();
+        let fork_hash = *fork[5].hash();
+
+        let chain_store = chain.mut_store();
+        assert_eq!(
+            chain_store.get_header_on_chain_by_height(&sync_hash, 3).unwrap().hash(),
+            blocks[3].hash()
+        );
+        // Remove the headers above height 3 from the store and the cache, so that any other walk
+        // back from the sync hash would fail.
+        let mut store_update = chain_store.store().store_update();
+        for block in &blocks[4..10] {
+            store_update.delete(DBCol::ColBlockHeader, block.hash().as_ref());
+            chain_store.headers.pop(block.hash().as_ref());
+        }
+        store_update.commit().unwrap();
+        for i in 0..50 {
+            let height = 3 + i % 8;
+            assert_eq!(
+                chain_store.get_header_on_chain_by_height(&sync_hash, height).unwrap().hash(),
+                blocks[height as usize].hash()
+            );
+        }
+        assert_eq!(
+            chain_store.get_header_on_chain_by_height(&sync_hash, 11).unwrap_err().kind(),
+            ErrorKind::InvalidBlockHeight(10)
+        );
+        // Lower heights continue the walk from the lowest visited block.
+        assert_eq!(
+            chain_store.get_header_on_chain_by_height(&sync_hash, 1).unwrap().hash(),
+            blocks[1].hash()
+        );
+
+        // Lookups are relative to the chain of the given sync hash.
+        assert_eq!(
+            chain_store.get_header_on_chain_by_height(&fork_hash, 4).unwrap().hash(),
+            fork[2].hash()
+        );
+        assert_eq!(
+            chain_store.get_header_on_chain_by_height(&fork_hash, 3).unwrap_err().kind(),
+            ErrorKind::InvalidBlockHeight(2)
+        );
+
+        // Garbage collecting the sync hash block drops its memoized chain.
+        let key = sync_hash.as_ref().to_vec();
+        assert!(chain_store.headers_on_chain.contains(&key));
+        let mut store_update = chain_store.store_update();
+        store_update.gc_col(DBCol::ColBlock, &key);
+        drop(store_update);
+        assert!(!chain_store.headers_on_chain.contains(&key));
+        assert!(chain_store.headers_on_chain.contains(&fork_hash.as_ref().to_vec()));
+    }
+
+    /// Builds a canonical chain of `num_blocks` empty blocks on top of genesis.
+    fn get_linear_chain(num_blocks: BlockHeight) -> Chain {
+        let mut chain = get_chain_with_epoch_length(1);
+        let runtime_adapter = chain.runtime_adapter.clone();
+        let genesis = chain.get_block_by_height(0).unwrap().clone();
+        let signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test1".parse().unwrap(),
+            KeyType::ED25519,
+            "test1",
+        ));
+        let mut prev_block = genesis;
+        for i in 1..=num_blocks {
+            runtime_adapter
+                .get_next_epoch_id_from_prev_block(prev_block.hash())
+                .expect("block must exist");
+            let block = Block::empty_with_height(&prev_block, i, &*signer);
+            let mut store_update = chain.mut_store().store_update();
+            store_update.save_block(block.clone());
+            store_update.inc_block_refcount(block.header().prev_hash()).unwrap();
+            store_update.save_block_header(block.header().clone()).unwrap();
+            store_update.save_head(&Tip::from_header(block.header())).unwrap();
+            store_update
+                .chain_store_cache_update
+                .height_to_hashes
+                .insert(i, Some(*block.header().hash()));
+            store_update.save_next_block_hash(prev_block.hash(), *block.hash());
+            store_update.commit().unwrap();
+            prev_block = block;
+        }
+        chain
+    }
+
+    /// Blocks, outcomes and state follow the tail, while chunks from genesis are still referenced
+    /// by the remaining blocks and headers are never garbage collected.
+    #[test]
+    fn test_data_availability_after_gc() {
+        let mut chain = get_linear_chain(14);
+        let availability = chain.data_availability_summary().unwrap();
+        assert_eq!(availability.blocks, 0);
+        assert_eq!(availability.chunks, 0);
+
+        let tries = chain.runtime_adapter.get_tries();
+        chain.clear_data(tries, 100).unwrap();
+
+        let availability = chain.data_availability_summary().unwrap();
+        assert_eq!(availability.headers, 0);
+        assert_eq!(availability.blocks, 8);
+        assert_eq!(availability.execution_outcomes, 8);
+        assert_eq!(availability.state, 8);
+        // Empty blocks only include chunks produced at genesis.
+        assert_eq!(availability.chunks, 0);
+        assert_eq!(availability.partial_chunks, 0);
+        assert!(chain.get_block_by_height(7).is_err());
+        assert!(chain.get_block_by_height(8).is_ok());
+    }
+
+    /// Archival garbage collection only drops partial chunks, so only their horizon moves.
+    #[test]
+    fn test_data_availability_after_archive_gc() {
+        let mut chain = get_linear_chain(14);
+        chain.clear_archive_data(100).unwrap();
+
+        let availability = chain.data_availability_summary().unwrap();
+        assert_eq!(availability.headers, 0);
+        assert_eq!(availability.blocks, 0);
+        assert_eq!(availability.execution_outcomes, 0);
+        assert_eq!(availability.state, 0);
+        assert_eq!(availability.chunks, 0);
+        assert_eq!(availability.partial_chunks, 9);
+        assert!(chain.get_block_by_height(0).is_ok());
+    }
+
+    /// A header or block read through one chain store is served from the shared cache to another.
+    #[test]
+    fn test_shared_chain_cache() {
+        let mut chain = get_linear_chain(3);
+        let store = chain.store().owned_store().clone();
+        let block_hash = *chain.get_block_by_height(2).unwrap().hash();
+        let shared_cache = Arc::new(SharedChainCache::new(1024 * 1024));
+        let mut first = ChainStore::with_shared_cache(store.clone(), 0, shared_cache.clone());
+        let mut second = ChainStore::with_shared_cache(store, 0, shared_cache.clone());
+
+        let header = first.get_block_header(&block_hash).unwrap().clone();
+        assert_eq!((shared_cache.hits(), shared_cache.misses()), (0, 1));
+        assert_eq!(second.get_block_header(&block_hash).unwrap(), &header);
+        assert_eq!((shared_cache.hits(), shared_cache.misses()), (1, 1));
+        // The second read through the same store is served from its own cache.
+        second.get_block_header(&block_hash).unwrap();
+        assert_eq!((shared_cache.hits(), shared_cache.misses()), (1, 1));
+
+        let block = second.get_block(&block_hash).unwrap().clone();
+        assert_eq!(first.get_block(&block_hash).unwrap(), &block);
+        assert_eq!((shared_cache.hits(), shared_cache.misses()), (2, 2));
+
+        // Heads and columns which may change are never shared.
+        assert_eq!(first.head().unwrap().last_block_hash, second.head().unwrap().last_block_hash);
+        first.get_block_hash_by_height(2).unwrap();
+        assert_eq!((shared_cache.hits(), shared_cache.misses()), (2, 2));
+    }
+
+    /// Blocks garbage collected through the chain store sharing the cache are no longer served
+    /// from the shared cache or the own caches of the other chain stores.
+    #[test]
+    fn test_shared_chain_cache_invalidated_by_gc() {
+        let mut chain = get_linear_chain(14);
+        let store = chain.store().owned_store().clone();
+        let block_hash = *chain.get_block_by_height(2).unwrap().hash();
+        let shared_cache = Arc::new(SharedChainCache::new(1024 * 1024));
+        chain.mut_store().set_shared_cache(shared_cache.clone());
+        let mut view_store = ChainStore::with_shared_cache(store, 0, shared_cache.clone());
+        assert!(view_store.get_block(&block_hash).is_ok());
+        assert!(view_store.get_block_header(&block_hash).is_ok());
+        assert!(shared_cache.get(DBCol::ColBlock, block_hash.as_ref()).is_some());
+
+        let tries = chain.runtime_adapter.get_tries();
+        chain.clear_data(tries, 100).unwrap();
+
+        assert!(shared_cache.generation() > 0);
+        assert!(shared_cache.get(DBCol::ColBlock, block_hash.as_ref()).is_none());
+        assert!(view_store.get_block(&block_hash).is_err());
+        // Headers are never garbage collected.
+        assert!(view_store.get_block_header(&block_hash).is_ok());
+        let mut other_view_store =
+            ChainStore::with_shared_cache(chain.store().owned_store().clone(), 0, shared_cache);
+        assert!(other_view_store.get_block(&block_hash).is_err());
+    }
+
+    /// A block or header looked up before it was saved is readable as soon as it is saved.
+    #[test]
+    fn test_negative_cache_invalidated_on_save() {
+        let mut chain = get_chain();
+        let genesis = chain.get_block_by_height(0).unwrap().clone();
+        let signer =
+            InMemoryValidatorSigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let block = Block::empty_with_height(&genesis, 1, &signer);
+        let hash = *block.hash();
+
+        let chain_store = chain.mut_store();
+        assert!(chain_store.get_block(&hash).is_err());
+        assert!(chain_store.get_block_header(&hash).is_err());
+        assert_eq!(chain_store.negative_cache_hits(), 0);
+        assert!(!chain_store.block_exists(&hash).unwrap());
+        assert!(chain_store.get_block(&hash).is_err());
+        assert!(chain_store.get_block_header(&hash).is_err());
+        assert_eq!(chain_store.negative_cache_hits(), 3);
+
+        let mut store_update = chain_store.store_update();
+        store_update.save_block_header(block.header().clone()).unwrap();
+        store_update.save_block(block.clone());
+        store_update.commit().unwrap();
+        assert!(chain_store.block_exists(&hash).unwrap());
+        assert_eq!(chain_store.get_block(&hash).unwrap(), &block);
+        assert_eq!(chain_store.get_block_header(&hash).unwrap(), block.header());
+        assert_eq!(chain_store.negative_cache_hits(), 3);
+    }
+
+    /// The shared cache evicts the least recently used values to stay within its capacity.
+    #[test]
+    fn test_shared_chain_cache_capacity() {
+        let shared_cache = SharedChainCache::new(10);
+        shared_cache.put(DBCol::ColBlock, &[1], vec![0; 4]);
+        shared_cache.put(DBCol::ColBlock, &[2], vec![0; 4]);
+        assert_eq!(shared_cache.size_bytes(), 10);
+        assert!(shared_cache.get(DBCol::ColBlock, &[1]).is_some());
+        shared_cache.put(DBCol::ColBlock, &[3], vec![0; 4]);
+        assert_eq!(shared_cache.size_bytes(), 10);
+        assert!(shared_cache.get(DBCol::ColBlock, &[2]).is_none());
+        assert!(shared_cache.get(DBCol::ColBlock, &[1]).is_some());
+        // Values larger than the whole cache are not cached at all.
+        shared_cache.put(DBCol::ColBlock, &[4], vec![0; 10]);
+        assert!(shared_cache.get(DBCol::ColBlock, &[4]).is_none());
+        assert_eq!(shared_cache.size_bytes(), 10);
+    }
+
+    /// Saves outcomes with the given ids, which differ between blocks, as produced in the block.
+    fn save_outcomes(
+        chain_store: &mut ChainStore,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        ids: &[CryptoHash],
+    ) {
+        let outcomes = ids
+            .iter()
+            .map(|id| ExecutionOutcomeWithId {
+                id: *id,
+                outcome: ExecutionOutcome {
+                    gas_burnt: block_hash.as_ref()[0] as u64,
+                    ..Default::default()
+                },
+            })
+            .collect::<Vec<_>>();
+        let proofs = vec![vec![]; ids.len()];
+        let mut store_update = chain_store.store_update();
+        store_update.save_outcomes_with_proofs(block_hash, shard_id, outcomes, proofs);
+        store_update.commit().unwrap();
+    }
+
+    /// Outcomes of a block read with a single lookup are the same as the ones read by id, also
+    /// when the same ids have outcomes in another fork.
+    #[test]
+    fn test_block_shard_outcomes_with_forks() {
+        let mut chain_store = ChainStore::new(create_test_store(), 0);
+        let block_hash = hash(b"block");
+        let fork_hash = hash(b"fork");
+        let fork_ids = (0..5u8).map(|i| hash(&[i])).collect::<Vec<_>>();
+        let mut block_ids = fork_ids.clone();
+        block_ids.push(hash(&[5]));
+        save_outcomes(&mut chain_store, &fork_hash, 0, &fork_ids);
+        save_outcomes(&mut chain_store, &block_hash, 0, &block_ids);
+        save_outcomes(&mut chain_store, &block_hash, 1, &[hash(&[6])]);
+
+        for (block, shard_id, num_outcomes) in
+            [(block_hash, 0, 6), (block_hash, 1, 1), (fork_hash, 0, 5), (block_hash, 2, 0)]
+        {
+            let outcomes = chain_store.get_block_shard_outcomes(&block, shard_id).unwrap();
+            let outcomes_by_ids =
+                chain_store.get_block_shard_outcomes_by_ids(&block, shard_id).unwrap();
+            assert_eq!(outcomes.try_to_vec().unwrap(), outcomes_by_ids.try_to_vec().unwrap());
+            assert_eq!(outcomes.len(), num_outcomes);
+            assert!(outcomes.iter().all(|outcome| outcome.block_hash == block));
+        }
+    }
+
+    #[test]
+    fn test_block_shard_outcomes_reads() {
+        let db = Arc::new(CountingDB::new());
+        let mut chain_store = ChainStore::new(Store::new(db.clone()), 0);
+        let block_hash = hash(b"block");
+        let ids = (0..10u8).map(|i| hash(&[i])).collect::<Vec<_>>();
+        save_outcomes(&mut chain_store, &block_hash, 0, &ids);
+
+        db.take_reads();
+        chain_store.get_block_shard_outcomes_by_ids(&block_hash, 0).unwrap();
+        assert_eq!(db.take_reads(), 1 + ids.len());
+        chain_store.get_block_shard_outcomes(&block_hash, 0).unwrap();
+        assert_eq!(db.take_reads(), 1);
+    }
+
+    /// Number of write batches header sync takes to save `num_headers` headers, committing them
+    /// in batches of `batch_size`.
+    fn header_sync_writes(num_headers: usize, batch_size: usize) -> usize {
+        let db = Arc::new(CountingDB::new());
+        let mut chain = get_chain_with_store(Store::new(db.clone()), 1000);
+        chain.header_sync_commit_batch_size = batch_size;
+        let signer =
+            InMemoryValidatorSigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let mut blocks = vec![chain.genesis_block().clone()];
+        let mut block_merkle_tree = PartialMerkleTree::default();
+        for i in 0..num_headers {
+            blocks.push(Block::empty_with_block_merkle_tree(
+                &blocks[i],
+                &signer,
+                &mut block_merkle_tree,
+            ));
+        }
+
+        db.take_writes();
+        chain
+            .sync_block_headers(
+                blocks[1..].iter().map(|block| block.header().clone()).collect(),
+                &mut |_| panic!("Unexpected challenge"),
+            )
+            .unwrap();
+        assert_eq!(chain.header_head().unwrap().height, num_headers as BlockHeight);
+        for block in &blocks[1..] {
+            assert!(chain.get_block_header(block.hash()).is_ok());
+        }
+        db.take_writes()
+    }
+
+    #[test]
+    fn test_header_sync_write_batches() {
+        // Committing every header on its own, as header sync used to do.
+        assert_eq!(header_sync_writes(100, 1), 100);
+        assert_eq!(header_sync_writes(100, 30), 4);
+        assert_eq!(header_sync_writes(100, DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE), 1);
+    }
+
     /// Test that `gc_blocks_limit` works properly
     #[test]
     #[cfg_attr(not(feature = "expensive_tests"), ignore)]
@@ -19,8 +19,9 @@ use near_primitives::utils::{get_block_shard_id, index_to_bytes};
 use near_store::{
     ColBlock, ColBlockHeader, ColBlockHeight, ColBlockInfo, ColBlockMisc, ColBlockPerHeight,
     ColChunkExtra, ColChunkHashesByHeight, ColChunks, ColHeaderHashesByHeight, ColOutcomeIds,
-    ColStateHeaders, ColTransactionResult, DBCol, TrieChanges, TrieIterator, CHUNK_TAIL_KEY,
-    FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, SHOULD_COL_GC, TAIL_KEY,
+    ColStateHeaders, ColStateSyncBoundaryBlocks, ColTransactionResult, DBCol, TrieChanges,
+    TrieIterator, CHUNK_TAIL_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, SHOULD_COL_GC,
+    TAIL_KEY,
 };
 
 use crate::StoreValidator;
@@ -453,10 +454,13 @@ pub(crate) fn block_epoch_exists(
 
 pub(crate) fn block_increase_refcount(
     sv: &mut StoreValidator,
-    _block_hash: &CryptoHash,
+    block_hash: &CryptoHash,
     block: &Block,
 ) -> Result<(), StoreValidatorError> {
-    if block.header().height() != sv.config.genesis_height {
+    // Blocks saved before state sync don't count in the refcount of their previous block.
+    if block.header().height() != sv.config.genesis_height
+        && !sv.store.exists(ColStateSyncBoundaryBlocks, block_hash.as_ref())?
+    {
         let prev_hash = block.header().prev_hash();
         sv.inner.block_refcount.entry(*prev_hash).and_modify(|x| *x += 1).or_insert(1);
     }
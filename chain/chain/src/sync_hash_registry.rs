@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use near_chain_configs::{
+    DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES, DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW,
+};
+use near_primitives::hash::CryptoHash;
+use near_primitives::time::Clock;
+
+/// Distinct sync hashes state was recently requested for, shared by the chains serving state
+/// requests. Bounds the number of sync hashes served at once, so that peers can't make the node
+/// do the work of serving state for arbitrarily many of them.
+pub struct SyncHashRegistry {
+    /// Sync hashes with the time of their latest request.
+    sync_hashes: Mutex<HashMap<CryptoHash, Instant>>,
+    max_sync_hashes: usize,
+    /// Sync hashes not requested for this long no longer count towards the limit.
+    window: Duration,
+}
+
+impl Default for SyncHashRegistry {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES,
+            DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW,
+        )
+    }
+}
+
+impl SyncHashRegistry {
+    pub fn new(max_sync_hashes: usize, window: Duration) -> Self {
+        Self { sync_hashes: Mutex::new(HashMap::new()), max_sync_hashes, window }
+    }
+
+    /// Records a state request for the sync hash. Returns false if the sync hash is not served
+    /// yet and the limit of served sync hashes is reached.
+    pub fn admit(&self, sync_hash: &CryptoHash) -> bool {
+        let now = Clock::instant();
+        let mut sync_hashes = self.sync_hashes.lock().unwrap();
+        sync_hashes.retain(|_, last_request| now.duration_since(*last_request) < self.window);
+        if let Some(last_request) = sync_hashes.get_mut(sync_hash) {
+            *last_request = now;
+            return true;
+        }
+        if sync_hashes.len() >= self.max_sync_hashes {
+            return false;
+        }
+        sync_hashes.insert(*sync_hash, now);
+        true
+    }
+
+    /// Sync hashes state is currently served for, most recently requested first.
+    pub fn served_sync_hashes(&self) -> Vec<CryptoHash> {
+        let now = Clock::instant();
+        let sync_hashes = self.sync_hashes.lock().unwrap();
+        let mut served: Vec<_> = sync_hashes
+            .iter()
+            .filter(|(_, last_request)| now.duration_since(**last_request) < self.window)
+            .map(|(sync_hash, last_request)| (*last_request, *sync_hash))
+            .collect();
+        served.sort_by(|a, b| b.cmp(a));
+        served.into_iter().map(|(_, sync_hash)| sync_hash).collect()
+    }
+}
@@ -1,6 +1,8 @@
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
@@ -8,21 +10,22 @@ use near_primitives::state_part::PartId;
 use num_rational::Rational;
 use tracing::debug;
 
-use near_chain_configs::ProtocolConfig;
+use near_chain_configs::{GenesisConfig, ProtocolConfig};
 use near_chain_primitives::{Error, ErrorKind};
 use near_crypto::{KeyType, PublicKey, SecretKey, Signature};
 use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::block_header::{Approval, ApprovalInner};
-use near_primitives::challenge::ChallengesResult;
+use near_primitives::challenge::{ChallengesResult, PartialState};
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::errors::{EpochError, InvalidTxError};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
+use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::serialize::to_base;
 use near_primitives::shard_layout;
-use near_primitives::shard_layout::{ShardLayout, ShardUId};
+use near_primitives::shard_layout::{ShardLayout, ShardLayoutError, ShardUId};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{
@@ -49,14 +52,18 @@ use near_store::{
 use crate::chain::{Chain, NUM_EPOCHS_TO_KEEP_STORE_DATA};
 use crate::store::ChainStoreAccess;
 use crate::types::{
-    ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, ChainGenesis,
-    ValidatorInfoIdentifier,
+    ApplySplitStateResult, ApplyTransactionResult, BlockApprovals, BlockHeaderInfo, ChainGenesis,
+    ShardCareMatrix, ValidatorInfoIdentifier,
 };
 use crate::Doomslug;
 use crate::{BlockHeader, DoomslugThresholdMode, RuntimeAdapter};
 use near_primitives::epoch_manager::ShardConfig;
 use near_primitives::time::Clock;
 
+/// Genesis gas limit of the test chain genesis and of `KeyValueRuntime`. Test genesis configs use
+/// the same initial gas limit.
+pub const TEST_GENESIS_GAS_LIMIT: Gas = 1_000_000_000_000_000;
+
 #[derive(BorshSerialize, BorshDeserialize, Hash, PartialEq, Eq, Ord, PartialOrd, Clone, Debug)]
 struct AccountNonce(AccountId, Nonce);
 
@@ -82,11 +89,45 @@ pub struct KeyValueRuntime {
     state_size: RwLock<HashMap<StateRoot, u64>>,
 
     headers_cache: RwLock<HashMap<CryptoHash, BlockHeader>>,
+    /// Headers recorded by `add_validator_proposals`, which may not be committed yet.
+    recorded_headers: RwLock<HashMap<CryptoHash, BlockHeaderInfo>>,
     hash_to_epoch: RwLock<HashMap<CryptoHash, EpochId>>,
     hash_to_next_epoch_approvals_req: RwLock<HashMap<CryptoHash, bool>>,
     hash_to_next_epoch: RwLock<HashMap<CryptoHash, EpochId>>,
     hash_to_valset: RwLock<HashMap<EpochId, u64>>,
     epoch_start: RwLock<HashMap<CryptoHash, u64>>,
+    /// Protocol versions of epochs which don't run `PROTOCOL_VERSION`.
+    epoch_protocol_versions: RwLock<HashMap<EpochId, ProtocolVersion>>,
+    /// Shard layouts of epochs which don't use the version 0 layout, see `set_shard_layout`.
+    shard_layouts: RwLock<HashMap<EpochId, ShardLayout>>,
+    /// Delay of `get_state_root_node`, to simulate slow generation of state sync headers.
+    state_root_node_delay: RwLock<Duration>,
+    /// Number of `get_state_root_node` calls.
+    state_root_node_calls: AtomicUsize,
+    /// Delay of `verify_block_vrf`, which is called once per processed block, to simulate slow
+    /// block processing.
+    block_processing_delay: RwLock<Duration>,
+    /// Delays of `apply_transactions` per shard, to simulate slow chunk application.
+    apply_delays: RwLock<HashMap<ShardId, Duration>>,
+    /// Number of `cares_about_shard` and `will_care_about_shard` calls.
+    cares_about_shard_calls: AtomicUsize,
+    /// Number of `cares_about_shards` calls.
+    cares_about_shards_calls: AtomicUsize,
+    /// Whether approvals are checked against the approvers, instead of being accepted as they are.
+    verify_approval_signatures: AtomicBool,
+    /// Number of `get_epoch_block_approvers_ordered` calls.
+    approvers_fetches: AtomicUsize,
+    /// Number of `get_epoch_block_producers_ordered` calls.
+    block_producers_calls: AtomicUsize,
+    /// Arguments of every `prefetch_state_keys` call.
+    prefetch_state_keys_calls: RwLock<Vec<(ShardUId, StateRoot, Vec<AccountId>)>>,
+    /// Chunks applied so far, by block hash and shard id.
+    applied_chunks: RwLock<HashSet<(CryptoHash, ShardId)>>,
+    /// Whether applying a chunk again for the same block gives a different state root than the
+    /// first time, to simulate a nondeterministic runtime.
+    nondeterministic_apply: AtomicBool,
+    /// Whether applying chunks fails, to simulate a block whose body can't be applied.
+    failing_apply: AtomicBool,
 }
 
 pub fn account_id_to_shard_id(account_id: &AccountId, num_shards: NumShards) -> ShardId {
@@ -194,15 +235,150 @@ impl KeyValueRuntime {
             state: RwLock::new(state),
             state_size: RwLock::new(state_size),
             headers_cache: RwLock::new(HashMap::new()),
+            recorded_headers: RwLock::new(HashMap::new()),
             hash_to_epoch: RwLock::new(HashMap::new()),
             hash_to_next_epoch_approvals_req: RwLock::new(HashMap::new()),
             hash_to_next_epoch: RwLock::new(map_with_default_hash1),
             hash_to_valset: RwLock::new(map_with_default_hash3),
             epoch_start: RwLock::new(map_with_default_hash2),
+            epoch_protocol_versions: RwLock::new(HashMap::new()),
+            shard_layouts: RwLock::new(HashMap::new()),
+            state_root_node_delay: RwLock::new(Duration::ZERO),
+            state_root_node_calls: AtomicUsize::new(0),
+            block_processing_delay: RwLock::new(Duration::ZERO),
+            apply_delays: RwLock::new(HashMap::new()),
+            cares_about_shard_calls: AtomicUsize::new(0),
+            cares_about_shards_calls: AtomicUsize::new(0),
+            verify_approval_signatures: AtomicBool::new(false),
+            approvers_fetches: AtomicUsize::new(0),
+            block_producers_calls: AtomicUsize::new(0),
+            prefetch_state_keys_calls: RwLock::new(vec![]),
+            applied_chunks: RwLock::new(HashSet::new()),
+            nondeterministic_apply: AtomicBool::new(false),
+            failing_apply: AtomicBool::new(false),
             no_gc,
         }
     }
 
+    /// Makes the epoch report the given protocol version instead of `PROTOCOL_VERSION`.
+    pub fn set_epoch_protocol_version(&self, epoch_id: EpochId, protocol_version: ProtocolVersion) {
+        self.epoch_protocol_versions.write().unwrap().insert(epoch_id, protocol_version);
+    }
+
+    /// Makes the epoch use the given shard layout instead of the version 0 layout. If it differs
+    /// from the layout of the previous epoch, it must split the shards of that layout.
+    pub fn set_shard_layout(&self, epoch_id: EpochId, shard_layout: ShardLayout) {
+        self.shard_layouts.write().unwrap().insert(epoch_id, shard_layout);
+    }
+
+    /// Makes every `get_state_root_node` call sleep for `delay`.
+    pub fn set_state_root_node_delay(&self, delay: Duration) {
+        *self.state_root_node_delay.write().unwrap() = delay;
+    }
+
+    /// Number of `get_state_root_node` calls so far.
+    pub fn num_state_root_node_calls(&self) -> usize {
+        self.state_root_node_calls.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Makes processing of every block take at least `delay`.
+    pub fn set_block_processing_delay(&self, delay: Duration) {
+        *self.block_processing_delay.write().unwrap() = delay;
+    }
+
+    /// Makes applying every chunk of the shard take at least `delay`.
+    pub fn set_apply_delay(&self, shard_id: ShardId, delay: Duration) {
+        self.apply_delays.write().unwrap().insert(shard_id, delay);
+    }
+
+    /// Number of `cares_about_shard` and `will_care_about_shard` calls so far.
+    pub fn num_cares_about_shard_calls(&self) -> usize {
+        self.cares_about_shard_calls.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Number of `cares_about_shards` calls so far.
+    pub fn num_cares_about_shards_calls(&self) -> usize {
+        self.cares_about_shards_calls.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Makes `verify_approval` check the approval signatures against the approvers.
+    pub fn set_verify_approval_signatures(&self, verify_approval_signatures: bool) {
+        self.verify_approval_signatures.store(verify_approval_signatures, AtomicOrdering::SeqCst);
+    }
+
+    /// Number of `get_epoch_block_approvers_ordered` calls so far.
+    pub fn num_approvers_fetches(&self) -> usize {
+        self.approvers_fetches.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Number of `get_epoch_block_producers_ordered` calls so far.
+    pub fn num_block_producers_calls(&self) -> usize {
+        self.block_producers_calls.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Arguments of the `prefetch_state_keys` calls so far.
+    pub fn prefetch_state_keys_calls(&self) -> Vec<(ShardUId, StateRoot, Vec<AccountId>)> {
+        self.prefetch_state_keys_calls.read().unwrap().clone()
+    }
+
+    /// Makes applying a chunk again for the same block give a different state root.
+    pub fn set_nondeterministic_apply(&self, nondeterministic_apply: bool) {
+        self.nondeterministic_apply.store(nondeterministic_apply, AtomicOrdering::SeqCst);
+    }
+
+    /// Makes applying any chunk fail.
+    pub fn set_failing_apply(&self, failing_apply: bool) {
+        self.failing_apply.store(failing_apply, AtomicOrdering::SeqCst);
+    }
+
+    /// Checks the approvals of a block against its approvers, like `NightshadeRuntime` does.
+    fn check_approval_signatures(
+        approvers: &[(ApprovalStake, bool)],
+        prev_block_hash: &CryptoHash,
+        prev_block_height: BlockHeight,
+        block_height: BlockHeight,
+        approvals: &[Option<Signature>],
+    ) -> bool {
+        let message_to_sign = Approval::get_data_for_sig(
+            &if prev_block_height + 1 == block_height {
+                ApprovalInner::Endorsement(*prev_block_hash)
+            } else {
+                ApprovalInner::Skip(prev_block_height)
+            },
+            block_height,
+        );
+        approvals.len() <= approvers.len()
+            && approvers.iter().zip(approvals.iter()).all(|((approver, is_slashed), approval)| {
+                approval.as_ref().map_or(true, |signature| {
+                    !is_slashed && signature.verify(message_to_sign.as_ref(), &approver.public_key)
+                })
+            })
+    }
+
+    /// Whether `account_id` is assigned to the shard in the given validator set.
+    fn cares_about_shard_in_valset(
+        &self,
+        account_id: Option<&AccountId>,
+        valset: usize,
+        shard_id: ShardId,
+    ) -> bool {
+        let validators = &self.validators[valset];
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
+        assert_eq!(0, validators.len() as u64 % self.validator_groups);
+        let validators_per_shard = validators.len() as ShardId / self.validator_groups;
+        let coef = validators.len() as ShardId / self.num_shards;
+        let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
+        assert!(offset + validators_per_shard as usize <= validators.len());
+        if let Some(account_id) = account_id {
+            for validator in validators[offset..offset + (validators_per_shard as usize)].iter() {
+                if validator.account_id() == account_id {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     fn get_block_header(&self, hash: &CryptoHash) -> Result<Option<BlockHeader>, Error> {
         let mut headers_cache = self.headers_cache.write().unwrap();
         if headers_cache.get(hash).is_some() {
@@ -215,6 +391,21 @@ impl KeyValueRuntime {
         Ok(None)
     }
 
+    /// Prev hash, height and last final block of a block. Headers recorded by
+    /// `add_validator_proposals` are known before they are committed to the store, as they are
+    /// to the epoch manager.
+    fn get_block_links(
+        &self,
+        hash: &CryptoHash,
+    ) -> Result<Option<(CryptoHash, BlockHeight, CryptoHash)>, Error> {
+        if let Some(info) = self.recorded_headers.read().unwrap().get(hash) {
+            return Ok(Some((info.prev_hash, info.height, info.last_finalized_block_hash)));
+        }
+        Ok(self
+            .get_block_header(hash)?
+            .map(|header| (*header.prev_hash(), header.height(), *header.last_final_block())))
+    }
+
     fn get_epoch_and_valset(
         &self,
         prev_hash: CryptoHash,
@@ -222,8 +413,8 @@ impl KeyValueRuntime {
         if prev_hash == CryptoHash::default() {
             return Ok((EpochId(prev_hash), 0, EpochId(prev_hash)));
         }
-        let prev_block_header = self
-            .get_block_header(&prev_hash)?
+        let (prev_prev_hash, prev_height, prev_last_final_block) = self
+            .get_block_links(&prev_hash)?
             .ok_or_else(|| ErrorKind::DBNotFoundErr(to_base(&prev_hash)))?;
 
         let mut hash_to_epoch = self.hash_to_epoch.write().unwrap();
@@ -233,7 +424,6 @@ impl KeyValueRuntime {
         let mut hash_to_valset = self.hash_to_valset.write().unwrap();
         let mut epoch_start_map = self.epoch_start.write().unwrap();
 
-        let prev_prev_hash = *prev_block_header.prev_hash();
         let prev_epoch = hash_to_epoch.get(&prev_prev_hash);
         let prev_next_epoch = hash_to_next_epoch.get(&prev_prev_hash).unwrap();
         let prev_valset = match prev_epoch {
@@ -243,10 +433,10 @@ impl KeyValueRuntime {
 
         let prev_epoch_start = *epoch_start_map.get(&prev_prev_hash).unwrap();
 
-        let last_final_height = if prev_block_header.last_final_block() == &CryptoHash::default() {
+        let last_final_height = if prev_last_final_block == CryptoHash::default() {
             0
         } else {
-            self.get_block_header(prev_block_header.last_final_block()).unwrap().unwrap().height()
+            self.get_block_links(&prev_last_final_block).unwrap().unwrap().1
         };
 
         let increment_epoch = prev_prev_hash == CryptoHash::default() // genesis is in its own epoch
@@ -254,19 +444,14 @@ impl KeyValueRuntime {
 
         let needs_next_epoch_approvals = !increment_epoch
             && last_final_height + 3 < prev_epoch_start + self.epoch_length
-            && prev_block_header.height() + 3 >= prev_epoch_start + self.epoch_length;
+            && prev_height + 3 >= prev_epoch_start + self.epoch_length;
 
         let (epoch, next_epoch, valset, epoch_start) = if increment_epoch {
             let new_valset = match prev_valset {
                 None => 0,
                 Some(prev_valset) => prev_valset + 1,
             };
-            (
-                prev_next_epoch.clone(),
-                EpochId(prev_hash),
-                new_valset,
-                prev_block_header.height() + 1,
-            )
+            (prev_next_epoch.clone(), EpochId(prev_hash), new_valset, prev_height + 1)
         } else {
             (
                 prev_epoch.unwrap().clone(),
@@ -337,6 +522,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _vrf_value: &near_crypto::vrf::Value,
         _vrf_proof: &near_crypto::vrf::Proof,
     ) -> Result<(), Error> {
+        std::thread::sleep(*self.block_processing_delay.read().unwrap());
         Ok(())
     }
 
@@ -372,12 +558,67 @@ impl RuntimeAdapter for KeyValueRuntime {
 
     fn verify_approval(
         &self,
-        _prev_block_hash: &CryptoHash,
-        _prev_block_height: BlockHeight,
-        _block_height: BlockHeight,
-        _approvals: &[Option<Signature>],
+        prev_block_hash: &CryptoHash,
+        prev_block_height: BlockHeight,
+        block_height: BlockHeight,
+        approvals: &[Option<Signature>],
     ) -> Result<bool, Error> {
-        Ok(true)
+        if !self.verify_approval_signatures.load(AtomicOrdering::SeqCst) {
+            return Ok(true);
+        }
+        let approvers = self.get_epoch_block_approvers_ordered(prev_block_hash)?;
+        Ok(Self::check_approval_signatures(
+            &approvers,
+            prev_block_hash,
+            prev_block_height,
+            block_height,
+            approvals,
+        ))
+    }
+
+    fn verify_approvals_and_threshold_batch(
+        &self,
+        doomslug_threshold_mode: DoomslugThresholdMode,
+        blocks: &[BlockApprovals],
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        let mut approvers_by_epochs = HashMap::new();
+        let mut result = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            let needs_next_epoch_approvals = *self
+                .hash_to_next_epoch_approvals_req
+                .read()
+                .unwrap()
+                .get(&block.prev_block_hash)
+                .unwrap();
+            let epochs = (&block.epoch_id, &block.next_epoch_id, needs_next_epoch_approvals);
+            if !approvers_by_epochs.contains_key(&epochs) {
+                let approvers = self.get_epoch_block_approvers_ordered(&block.prev_block_hash)?;
+                approvers_by_epochs.insert(epochs, approvers);
+            }
+            let approvers = &approvers_by_epochs[&epochs];
+            result.push(
+                if self.verify_approval_signatures.load(AtomicOrdering::SeqCst)
+                    && !Self::check_approval_signatures(
+                        approvers,
+                        &block.prev_block_hash,
+                        block.prev_block_height,
+                        block.block_height,
+                        &block.approvals,
+                    )
+                {
+                    Err(ErrorKind::InvalidApprovals.into())
+                } else if !Doomslug::can_block_be_produced_with_approvers(
+                    doomslug_threshold_mode,
+                    &block.approvals,
+                    approvers,
+                ) {
+                    Err(ErrorKind::NotEnoughApprovals.into())
+                } else {
+                    Ok(())
+                },
+            );
+        }
+        Ok(result)
     }
 
     fn verify_approvals_and_threshold_orphan(
@@ -419,6 +660,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         epoch_id: &EpochId,
         _last_known_block_hash: &CryptoHash,
     ) -> Result<Vec<(ValidatorStake, bool)>, Error> {
+        self.block_producers_calls.fetch_add(1, AtomicOrdering::SeqCst);
         let validators = &self.validators[self.get_valset_for_epoch(epoch_id)?];
         Ok(validators.iter().map(|x| (x.clone(), false)).collect())
     }
@@ -427,6 +669,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         &self,
         parent_hash: &CryptoHash,
     ) -> Result<Vec<(ApprovalStake, bool)>, Error> {
+        self.approvers_fetches.fetch_add(1, AtomicOrdering::SeqCst);
         let (_cur_epoch, cur_valset, next_epoch) = self.get_epoch_and_valset(*parent_hash)?;
         let mut validators = self.validators[cur_valset]
             .iter()
@@ -476,8 +719,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(self.num_shards)
     }
 
-    fn get_shard_layout(&self, _epoch_id: &EpochId) -> Result<ShardLayout, Error> {
-        Ok(ShardLayout::v0(self.num_shards, 0))
+    fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, Error> {
+        Ok(self
+            .shard_layouts
+            .read()
+            .unwrap()
+            .get(epoch_id)
+            .cloned()
+            .unwrap_or_else(|| ShardLayout::v0(self.num_shards, 0)))
     }
 
     fn get_shard_config(&self, _epoch_id: &EpochId) -> Result<ShardConfig, Error> {
@@ -486,17 +735,35 @@ impl RuntimeAdapter for KeyValueRuntime {
 
     fn get_prev_shard_ids(
         &self,
-        _prev_hash: &CryptoHash,
+        prev_hash: &CryptoHash,
         shard_ids: Vec<ShardId>,
     ) -> Result<Vec<ShardId>, Error> {
-        Ok(shard_ids)
+        if self.shard_layouts.read().unwrap().is_empty() {
+            return Ok(shard_ids);
+        }
+        let shard_layout = self.get_shard_layout_from_prev_block(prev_hash)?;
+        let (prev_prev_hash, _, _) = self
+            .get_block_links(prev_hash)?
+            .ok_or_else(|| ErrorKind::DBNotFoundErr(to_base(prev_hash)))?;
+        let prev_shard_layout =
+            self.get_shard_layout(&self.get_epoch_and_valset(prev_prev_hash)?.0)?;
+        if shard_layout == prev_shard_layout {
+            return Ok(shard_ids);
+        }
+        Ok(shard_ids
+            .into_iter()
+            .map(|shard_id| shard_layout.get_parent_shard_id(shard_id))
+            .collect::<Result<_, ShardLayoutError>>()?)
     }
 
     fn get_shard_layout_from_prev_block(
         &self,
-        _parent_hash: &CryptoHash,
+        parent_hash: &CryptoHash,
     ) -> Result<ShardLayout, Error> {
-        Ok(ShardLayout::v0(self.num_shards, 0))
+        if self.shard_layouts.read().unwrap().is_empty() {
+            return Ok(ShardLayout::v0(self.num_shards, 0));
+        }
+        self.get_shard_layout(&self.get_epoch_id_from_prev_block(parent_hash)?)
     }
 
     fn shard_id_to_uid(&self, shard_id: ShardId, _epoch_id: &EpochId) -> Result<ShardUId, Error> {
@@ -540,25 +807,12 @@ impl RuntimeAdapter for KeyValueRuntime {
         shard_id: ShardId,
         _is_me: bool,
     ) -> bool {
+        self.cares_about_shard_calls.fetch_add(1, AtomicOrdering::SeqCst);
         // This `unwrap` here tests that in all code paths we check that the epoch exists before
         //    we check if we care about a shard. Please do not remove the unwrap, fix the logic of
         //    the calling function.
         let epoch_valset = self.get_epoch_and_valset(*parent_hash).unwrap();
-        let validators = &self.validators[epoch_valset.1];
-        assert_eq!((validators.len() as u64) % self.num_shards, 0);
-        assert_eq!(0, validators.len() as u64 % self.validator_groups);
-        let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards;
-        let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
-        assert!(offset + validators_per_shard as usize <= validators.len());
-        if let Some(account_id) = account_id {
-            for validator in validators[offset..offset + (validators_per_shard as usize)].iter() {
-                if validator.account_id() == account_id {
-                    return true;
-                }
-            }
-        }
-        false
+        self.cares_about_shard_in_valset(account_id, epoch_valset.1, shard_id)
     }
 
     fn will_care_about_shard(
@@ -568,24 +822,41 @@ impl RuntimeAdapter for KeyValueRuntime {
         shard_id: ShardId,
         _is_me: bool,
     ) -> bool {
+        self.cares_about_shard_calls.fetch_add(1, AtomicOrdering::SeqCst);
         // This `unwrap` here tests that in all code paths we check that the epoch exists before
         //    we check if we care about a shard. Please do not remove the unwrap, fix the logic of
         //    the calling function.
         let epoch_valset = self.get_epoch_and_valset(*parent_hash).unwrap();
-        let validators = &self.validators[(epoch_valset.1 + 1) % self.validators.len()];
-        assert_eq!((validators.len() as u64) % self.num_shards, 0);
-        assert_eq!(0, validators.len() as u64 % self.validator_groups);
-        let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards;
-        let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
-        if let Some(account_id) = account_id {
-            for validator in validators[offset..offset + (validators_per_shard as usize)].iter() {
-                if validator.account_id() == account_id {
-                    return true;
-                }
-            }
-        }
-        false
+        let next_valset = (epoch_valset.1 + 1) % self.validators.len();
+        self.cares_about_shard_in_valset(account_id, next_valset, shard_id)
+    }
+
+    fn prefetch_state_keys(
+        &self,
+        shard_uid: ShardUId,
+        state_root: StateRoot,
+        accounts: Vec<AccountId>,
+    ) -> Result<(), Error> {
+        self.prefetch_state_keys_calls.write().unwrap().push((shard_uid, state_root, accounts));
+        Ok(())
+    }
+
+    fn cares_about_shards(
+        &self,
+        me: Option<&AccountId>,
+        parent_hash: &CryptoHash,
+    ) -> Result<ShardCareMatrix, Error> {
+        self.cares_about_shards_calls.fetch_add(1, AtomicOrdering::SeqCst);
+        let valset = self.get_epoch_and_valset(*parent_hash)?.1;
+        let next_valset = (valset + 1) % self.validators.len();
+        Ok(ShardCareMatrix {
+            this_epoch: (0..self.num_shards)
+                .map(|shard_id| self.cares_about_shard_in_valset(me, valset, shard_id))
+                .collect(),
+            next_epoch: (0..self.num_shards)
+                .map(|shard_id| self.cares_about_shard_in_valset(me, next_valset, shard_id))
+                .collect(),
+        })
     }
 
     fn validate_tx(
@@ -636,8 +907,9 @@ impl RuntimeAdapter for KeyValueRuntime {
 
     fn add_validator_proposals(
         &self,
-        _block_header_info: BlockHeaderInfo,
+        block_header_info: BlockHeaderInfo,
     ) -> Result<StoreUpdate, Error> {
+        self.recorded_headers.write().unwrap().insert(block_header_info.hash, block_header_info);
         Ok(self.store.store_update())
     }
 
@@ -662,7 +934,13 @@ impl RuntimeAdapter for KeyValueRuntime {
         states_to_patch: Option<Vec<StateRecord>>,
     ) -> Result<ApplyTransactionResult, Error> {
         assert!(states_to_patch.is_none(), "KeyValueRuntime does not support patch states.");
-        assert!(!generate_storage_proof);
+        if self.failing_apply.load(AtomicOrdering::SeqCst) {
+            let msg = format!("Failed to apply chunk of shard {}", shard_id);
+            return Err(ErrorKind::Other(msg).into());
+        }
+        if let Some(delay) = self.apply_delays.read().unwrap().get(&shard_id) {
+            std::thread::sleep(*delay);
+        }
         let mut tx_results = vec![];
 
         let mut state = self.state.read().unwrap().get(state_root).cloned().unwrap();
@@ -810,7 +1088,12 @@ impl RuntimeAdapter for KeyValueRuntime {
 
         let data = state.try_to_vec()?;
         let state_size = data.len() as u64;
-        let state_root = hash(&data);
+        let mut state_root = hash(&data);
+        let first_application =
+            self.applied_chunks.write().unwrap().insert((*block_hash, shard_id));
+        if !first_application && self.nondeterministic_apply.load(AtomicOrdering::SeqCst) {
+            state_root = hash(state_root.as_ref());
+        }
         self.state.write().unwrap().insert(state_root, state);
         self.state_size.write().unwrap().insert(state_root, state_size);
 
@@ -828,7 +1111,8 @@ impl RuntimeAdapter for KeyValueRuntime {
             validator_proposals: vec![],
             total_gas_burnt: 0,
             total_balance_burnt: 0,
-            proof: None,
+            // The state is not a trie, so there are no nodes to prove it with.
+            proof: generate_storage_proof.then(|| PartialStorage { nodes: PartialState(vec![]) }),
             processed_delayed_receipts: vec![],
         })
     }
@@ -975,6 +1259,8 @@ impl RuntimeAdapter for KeyValueRuntime {
         _block_hash: &CryptoHash,
         state_root: &StateRoot,
     ) -> Result<StateRootNode, Error> {
+        self.state_root_node_calls.fetch_add(1, AtomicOrdering::SeqCst);
+        std::thread::sleep(*self.state_root_node_delay.read().unwrap());
         Ok(StateRootNode {
             data: self
                 .state
@@ -1002,13 +1288,12 @@ impl RuntimeAdapter for KeyValueRuntime {
         if parent_hash == &CryptoHash::default() {
             return Ok(true);
         }
-        let prev_block_header = self.get_block_header(parent_hash)?.ok_or_else(|| {
+        let (prev_prev_hash, _, _) = self.get_block_links(parent_hash)?.ok_or_else(|| {
             Error::from(ErrorKind::Other(format!(
                 "Missing block {} when computing the epoch",
                 parent_hash
             )))
         })?;
-        let prev_prev_hash = *prev_block_header.prev_hash();
         Ok(self.get_epoch_and_valset(*parent_hash)?.0
             != self.get_epoch_and_valset(prev_prev_hash)?.0)
     }
@@ -1026,8 +1311,8 @@ impl RuntimeAdapter for KeyValueRuntime {
 
     fn get_epoch_start_height(&self, block_hash: &CryptoHash) -> Result<BlockHeight, Error> {
         let epoch_id = self.get_epoch_and_valset(*block_hash)?.0;
-        match self.get_block_header(&epoch_id.0)? {
-            Some(block_header) => Ok(block_header.height()),
+        match self.get_block_links(&epoch_id.0)? {
+            Some((_, height, _)) => Ok(height),
             None => Ok(0),
         }
     }
@@ -1078,8 +1363,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(CryptoHash::default())
     }
 
-    fn get_epoch_protocol_version(&self, _epoch_id: &EpochId) -> Result<ProtocolVersion, Error> {
-        Ok(PROTOCOL_VERSION)
+    fn get_epoch_protocol_version(&self, epoch_id: &EpochId) -> Result<ProtocolVersion, Error> {
+        Ok(self
+            .epoch_protocol_versions
+            .read()
+            .unwrap()
+            .get(epoch_id)
+            .copied()
+            .unwrap_or(PROTOCOL_VERSION))
     }
 
     fn get_validator_info(
@@ -1155,8 +1446,16 @@ impl RuntimeAdapter for KeyValueRuntime {
         Err(ErrorKind::NotAValidator.into())
     }
 
-    fn get_protocol_config(&self, _epoch_id: &EpochId) -> Result<ProtocolConfig, Error> {
-        unreachable!("get_protocol_config should not be called in KeyValueRuntime");
+    fn get_protocol_config(&self, epoch_id: &EpochId) -> Result<ProtocolConfig, Error> {
+        let genesis_config = GenesisConfig {
+            protocol_version: self.get_epoch_protocol_version(epoch_id)?,
+            genesis_height: 0,
+            epoch_length: self.epoch_length,
+            gas_limit: TEST_GENESIS_GAS_LIMIT,
+            shard_layout: self.get_shard_layout(epoch_id)?,
+            ..GenesisConfig::default()
+        };
+        Ok(ProtocolConfig { genesis_config, runtime_config: RuntimeConfig::test() })
     }
 
     fn get_prev_epoch_id_from_prev_block(
@@ -1231,7 +1530,7 @@ pub fn setup_with_tx_validity_period(
         &ChainGenesis {
             time: Clock::utc(),
             height: 0,
-            gas_limit: 1_000_000,
+            gas_limit: TEST_GENESIS_GAS_LIMIT,
             min_gas_price: 100,
             max_gas_price: 1_000_000_000,
             total_supply: 1_000_000_000,
@@ -1278,7 +1577,7 @@ pub fn setup_with_validators(
         &ChainGenesis {
             time: Clock::utc(),
             height: 0,
-            gas_limit: 1_000_000,
+            gas_limit: TEST_GENESIS_GAS_LIMIT,
             min_gas_price: 100,
             max_gas_price: 1_000_000_000,
             total_supply: 1_000_000_000,
@@ -1397,7 +1696,7 @@ impl ChainGenesis {
         ChainGenesis {
             time: Clock::utc(),
             height: 0,
-            gas_limit: 1_000_000,
+            gas_limit: TEST_GENESIS_GAS_LIMIT,
             min_gas_price: 0,
             max_gas_price: 1_000_000_000,
             total_supply: 1_000_000_000,
@@ -1422,6 +1721,7 @@ mod test {
     use near_primitives::time::Clock;
     use near_primitives::types::{AccountId, NumShards};
 
+    use crate::chain::AccountShardCache;
     use crate::Chain;
 
     use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
@@ -1482,4 +1782,34 @@ mod test {
             test_build_receipt_hashes_with_num_shard(num_shards);
         }
     }
+
+    /// Receipts hashes of random receipts match the naive implementation, including the single
+    /// shard fast path, both with a fresh cache and with one reused across calls and layouts.
+    #[test]
+    fn test_build_receipt_hashes_matches_naive() {
+        let mut rng = rand::thread_rng();
+        let mut cache = AccountShardCache::default();
+        let shard_layouts = [1, 3, 4, 3, 1]
+            .into_iter()
+            .map(|num_shards| ShardLayout::v0(num_shards, 0))
+            .chain([ShardLayout::v1_test()]);
+        for shard_layout in shard_layouts {
+            for _ in 0..3 {
+                let num_receipts = rng.gen_range(0, 500);
+                let receipts = (0..num_receipts)
+                    .map(|_| {
+                        let receiver_id =
+                            AccountId::try_from(format!("test{}", rng.gen_range(0, 100))).unwrap();
+                        Receipt::new_balance_refund(&receiver_id, 0)
+                    })
+                    .collect::<Vec<_>>();
+                let expected = naive_build_receipt_hashes(&receipts, &shard_layout);
+                assert_eq!(Chain::build_receipts_hashes(&receipts, &shard_layout), expected);
+                assert_eq!(
+                    Chain::build_receipts_hashes_with_cache(&receipts, &shard_layout, &mut cache),
+                    expected
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,187 @@
+use crate::blocks_delay_tracker::{BlocksDelayTracker, FinalityLatencyStats, SloReport};
+use crate::test_utils::setup;
+use crate::Block;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::time::Clock;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Feeds 100 blocks through the tracker, the block at height `i` becomes ready `i` ms after it
+/// was received. Blocks at even heights wait for chunks, blocks at odd heights don't.
+fn feed_blocks(tracker: &mut BlocksDelayTracker, blocks: &[Block]) {
+    let start = Clock::instant();
+    for block in blocks {
+        let height = block.header().height();
+        let received = start + Duration::from_secs(height);
+        let ready = received + Duration::from_millis(height);
+        tracker.mark_block_received(block, received);
+        let chunks = block.chunks().iter().map(|chunk| chunk.chunk_hash()).collect::<Vec<_>>();
+        if height % 2 == 0 {
+            tracker.mark_block_has_missing_chunks(block.hash(), received);
+            tracker.mark_block_completed_missing_chunks(block.hash(), ready);
+            // Processing after the chunks arrived doesn't count towards readiness.
+            tracker.finish_block_processing(block.hash(), &chunks, ready + Duration::from_secs(1));
+        } else {
+            tracker.finish_block_processing(block.hash(), &chunks, ready);
+        }
+    }
+}
+
+#[test]
+fn block_readiness_percentiles() {
+    let (mut chain, _, signer) = setup();
+    let mut prev = chain.genesis_block().clone();
+    let mut blocks = vec![];
+    for _ in 0..100 {
+        let block = Block::empty(&prev, &*signer);
+        blocks.push(block.clone());
+        prev = block;
+    }
+
+    let mut tracker = BlocksDelayTracker::default();
+    feed_blocks(&mut tracker, &blocks);
+    assert!(tracker.blocks_in_progress.is_empty());
+    let report = tracker.block_readiness_report(0, Duration::from_millis(80));
+    assert_eq!(
+        report,
+        SloReport {
+            num_blocks: 100,
+            num_within_threshold: 80,
+            percent_within_threshold: 80.,
+            p50: Duration::from_millis(50),
+            p90: Duration::from_millis(90),
+            p99: Duration::from_millis(99),
+            max: Duration::from_millis(100),
+        }
+    );
+
+    // Samples below the height are pruned, the percentiles are computed over the rest.
+    tracker.prune_readiness_samples(51);
+    let report = tracker.block_readiness_report(0, Duration::from_millis(80));
+    assert_eq!(
+        report,
+        SloReport {
+            num_blocks: 50,
+            num_within_threshold: 30,
+            percent_within_threshold: 60.,
+            p50: Duration::from_millis(75),
+            p90: Duration::from_millis(95),
+            p99: Duration::from_millis(100),
+            max: Duration::from_millis(100),
+        }
+    );
+
+    let empty_report = BlocksDelayTracker::default().block_readiness_report(0, Duration::ZERO);
+    assert_eq!(empty_report.num_blocks, 0);
+    assert_eq!(empty_report.percent_within_threshold, 100.);
+
+    // The whole chain is in the genesis epoch, so all samples are included.
+    feed_blocks(&mut chain.blocks_delay_tracker, &blocks);
+    let report = chain.block_readiness_slo(1, Duration::from_millis(80)).unwrap();
+    assert_eq!(report.num_blocks, 100);
+    assert_eq!(report.p50, Duration::from_millis(50));
+}
+
+#[test]
+fn finality_latency_percentiles() {
+    let start = Clock::instant();
+    let hashes = (1..=100u8).map(|i| hash(&[i])).collect::<Vec<_>>();
+    let mut tracker = BlocksDelayTracker::default();
+    for (i, hash) in hashes.iter().enumerate() {
+        tracker.mark_block_accepted(hash, i as u64 + 1, start + Duration::from_millis(i as u64));
+    }
+    // All blocks become final at once, 100ms after the first one was accepted.
+    for hash in hashes.iter().rev() {
+        tracker.mark_block_final(hash, start + Duration::from_millis(100));
+    }
+    // Blocks which weren't accepted or are already final are ignored.
+    tracker.mark_block_final(&CryptoHash::default(), start + Duration::from_secs(10));
+    tracker.mark_block_final(&hashes[0], start + Duration::from_secs(10));
+    assert!(tracker.accepted_blocks.is_empty());
+    assert_eq!(
+        tracker.finality_latency_stats(),
+        FinalityLatencyStats {
+            num_blocks: 100,
+            p50: Duration::from_millis(50),
+            p90: Duration::from_millis(90),
+            p99: Duration::from_millis(99),
+            max: Duration::from_millis(100),
+        }
+    );
+}
+
+/// Heights 4 and 5 are skipped, so finality stalls at height 1 until the block at height 8
+/// finalizes the blocks at heights 2, 3 and 6 at once. Each of them is recorded with its own
+/// latency.
+#[test]
+fn finality_latency_with_stall() {
+    let (mut chain, _, signer) = setup();
+    let step = Duration::from_millis(20);
+    let mut prev = chain.genesis_block().clone();
+    let mut hashes = HashMap::new();
+    for height in [1, 2, 3, 6, 7, 8] {
+        let block = Block::empty_with_height(&prev, height, &*signer);
+        hashes.insert(height, *block.hash());
+        chain.process_block_test(&None, block.clone()).unwrap();
+        std::thread::sleep(step);
+        prev = block;
+    }
+    assert_eq!(chain.final_head().unwrap().last_block_hash, hashes[&6]);
+
+    let stats = chain.finality_latency_stats();
+    assert_eq!(stats.num_blocks, 4);
+    // The block at height 2 waited for the blocks at heights 3, 6, 7 and 8.
+    assert!(stats.max >= 4 * step);
+    assert!(stats.p50 >= 2 * step);
+    for height in [1, 2, 3, 6] {
+        assert!(!chain.blocks_delay_tracker.accepted_blocks.contains_key(&hashes[&height]));
+    }
+    for height in [7, 8] {
+        assert!(chain.blocks_delay_tracker.accepted_blocks.contains_key(&hashes[&height]));
+    }
+}
+
+#[test]
+fn blocks_applied_per_second() {
+    let mut tracker = BlocksDelayTracker::default();
+    let start = Clock::instant();
+    assert_eq!(tracker.blocks_applied_per_second(), None);
+    tracker.mark_block_accepted(&hash(&[0]), 1, start);
+    assert_eq!(tracker.blocks_applied_per_second(), None);
+    tracker.mark_block_accepted(&hash(&[1]), 2, start);
+    assert_eq!(tracker.blocks_applied_per_second(), None);
+
+    // Only the last 100 blocks count, accepted 100ms apart after a slow start.
+    for i in 0..150u64 {
+        let timestamp = start + Duration::from_secs(10) + Duration::from_millis(100 * i);
+        tracker.mark_block_accepted(&hash(&i.to_le_bytes()), 3 + i, timestamp);
+    }
+    assert_eq!(tracker.applied_blocks.len(), 100);
+    let rate = tracker.blocks_applied_per_second().unwrap();
+    assert!((rate - 10.).abs() < 1e-9, "{}", rate);
+}
+
+#[test]
+fn shards_falling_behind() {
+    let mut tracker = BlocksDelayTracker::default();
+    for _ in 0..30 {
+        tracker.mark_chunk_applied(0, Duration::from_millis(300));
+        tracker.mark_chunk_applied(1, Duration::from_millis(1500));
+    }
+    assert_eq!(tracker.chunk_apply_times[&1].len(), 20);
+    assert_eq!(tracker.average_chunk_apply_time(1), Some(Duration::from_millis(1500)));
+    assert_eq!(tracker.average_chunk_apply_time(2), None);
+    // Nothing is falling behind as long as the interval between blocks is unknown.
+    assert!(tracker.shards_falling_behind().is_empty());
+
+    tracker.block_production_interval = Some(Duration::from_secs(1));
+    assert_eq!(tracker.shards_falling_behind(), vec![1]);
+
+    // Shard 1 catches up once the slow chunks leave the window.
+    for _ in 0..10 {
+        tracker.mark_chunk_applied(1, Duration::from_millis(200));
+    }
+    assert_eq!(tracker.average_chunk_apply_time(1), Some(Duration::from_millis(850)));
+    assert!(!tracker.is_falling_behind(1));
+    assert!(tracker.shards_falling_behind().is_empty());
+}
@@ -1,6 +1,11 @@
 use crate::test_utils::setup;
-use crate::{Block, ErrorKind};
+use crate::{Block, ChainStoreAccess, ErrorKind};
+use borsh::BorshSerialize;
 use near_logger_utils::init_test_logger;
+use near_primitives::challenge::{Challenge, ChallengeBody, ChunkProofs, MaybeEncodedShardChunk};
+use near_primitives::merkle::PartialMerkleTree;
+use near_primitives::sharding::ShardChunk;
+use near_primitives::transaction::SignedTransaction;
 
 #[test]
 fn challenges_new_head_prev() {
@@ -44,7 +49,7 @@ fn challenges_new_head_prev() {
     // Try to add a block on top of the fifth block.
 
     if let Err(e) = chain.process_block_test(&None, last_block) {
-        assert_eq!(e.kind(), ErrorKind::ChallengedBlockOnChain)
+        assert_eq!(e.kind(), ErrorKind::ChallengedBlock)
     } else {
         assert!(false);
     }
@@ -94,3 +99,126 @@ fn test_no_challenge_on_same_header() {
         panic!("Process the same header twice should produce error");
     }
 }
+
+#[test]
+fn test_header_head_does_not_advance_on_challenged_branch() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let mut block_merkle_tree = PartialMerkleTree::default();
+    let b1 = Block::empty_with_block_merkle_tree(&genesis, &*signer, &mut block_merkle_tree);
+    chain.process_block_test(&None, b1.clone()).unwrap();
+
+    // A branch built on top of `b1`, whose first block is going to be challenged.
+    let mut fork = vec![];
+    let mut prev = b1.clone();
+    let mut fork_merkle_tree = block_merkle_tree.clone();
+    for _ in 0..4 {
+        let block = Block::empty_with_block_merkle_tree(&prev, &*signer, &mut fork_merkle_tree);
+        fork.push(block.clone());
+        prev = block;
+    }
+    chain
+        .sync_block_headers(vec![fork[0].header().clone(), fork[1].header().clone()], &mut |_| {
+            panic!("Unexpected Challenge")
+        })
+        .unwrap();
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *fork[1].hash());
+
+    chain.mark_block_as_challenged(fork[0].hash(), b1.hash()).unwrap();
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b1.hash());
+    assert!(chain.mut_store().is_descendant_of_challenged(fork[1].hash(), 10).unwrap());
+    assert!(!chain.mut_store().is_descendant_of_challenged(b1.hash(), 10).unwrap());
+
+    // Headers building on the challenged block are rejected.
+    match chain
+        .sync_block_headers(vec![fork[2].header().clone(), fork[3].header().clone()], &mut |_| {
+            panic!("Unexpected Challenge")
+        }) {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::ChallengedBlock),
+        Ok(_) => panic!("Headers on top of a challenged block should be rejected"),
+    }
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b1.hash());
+
+    // Other branches are not affected.
+    let b2 =
+        Block::empty_with_height_and_block_merkle_tree(&b1, 3, &*signer, &mut block_merkle_tree);
+    let b3 = Block::empty_with_block_merkle_tree(&b2, &*signer, &mut block_merkle_tree);
+    chain.sync_block_headers(vec![b2.header().clone(), b3.header().clone()], &mut |_| {}).unwrap();
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b3.hash());
+}
+
+#[test]
+fn test_validate_challenges_leaves_no_marks() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    chain.process_block_test(&None, b1.clone()).unwrap();
+
+    // A chunk whose transactions don't match its header makes a valid challenge against `b1`.
+    let mut chunk = chain.get_chunk(&b1.chunks()[0].chunk_hash()).unwrap().clone();
+    let tx = SignedTransaction::empty(*genesis.hash());
+    match &mut chunk {
+        ShardChunk::V1(chunk) => chunk.transactions = vec![tx],
+        ShardChunk::V2(chunk) => chunk.transactions = vec![tx],
+    }
+    let challenge = Challenge::produce(
+        ChallengeBody::ChunkProofs(ChunkProofs {
+            block_header: b1.header().try_to_vec().unwrap(),
+            merkle_proof: Block::compute_chunk_headers_root(b1.chunks().iter()).1[0].clone(),
+            chunk: MaybeEncodedShardChunk::Decoded(chunk),
+        }),
+        &*signer,
+    );
+
+    let mut chain_update = chain.chain_update();
+    let validated_challenges =
+        chain_update.validate_challenges(&[challenge], b1.header().epoch_id(), b1.hash()).unwrap();
+    assert_eq!(validated_challenges.len(), 1);
+    assert_eq!(validated_challenges[0].challenged_block, Some(*b1.hash()));
+    // Validation alone, e.g. for a block which ends up rejected, doesn't mark anything.
+    chain_update.commit().unwrap();
+    assert!(!chain.mut_store().is_block_challenged(b1.hash()).unwrap());
+
+    let mut chain_update = chain.chain_update();
+    chain_update.apply_challenge_results(&validated_challenges, None).unwrap();
+    chain_update.commit().unwrap();
+    assert!(chain.mut_store().is_block_challenged(b1.hash()).unwrap());
+}
+
+#[test]
+fn test_blocks_at_height_info() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    let b2 = Block::empty(&b1, &*signer);
+    let b3 = Block::empty(&b2, &*signer);
+    // Two forks ending at the same height as the canonical `b3`.
+    let fork1 = Block::empty_with_height(&b1, 3, &*signer);
+    let fork2 = Block::empty_with_height(&genesis, 3, &*signer);
+    for block in [&b1, &b2, &b3, &fork1, &fork2] {
+        chain.process_block_test(&None, block.clone()).unwrap();
+    }
+    assert_eq!(chain.head().unwrap().last_block_hash, *b3.hash());
+    chain.mark_block_as_challenged(fork1.hash(), b3.hash()).unwrap();
+    assert_eq!(chain.head().unwrap().last_block_hash, *b3.hash());
+
+    let infos = chain.blocks_at_height_info(3).unwrap();
+    assert_eq!(infos.len(), 3);
+    assert_eq!(infos[0].hash, *b3.hash());
+    assert!(infos[0].is_canonical);
+    for info in infos.iter() {
+        let block = [&b3, &fork1, &fork2].into_iter().find(|b| b.hash() == &info.hash).unwrap();
+        assert_eq!(info.prev_hash, Some(*block.header().prev_hash()));
+        assert_eq!(&info.epoch_id, block.header().epoch_id());
+        assert_eq!(info.producer, Some("test".parse().unwrap()));
+        assert_eq!(info.is_canonical, block.hash() == b3.hash());
+        assert_eq!(info.is_challenged, block.hash() == fork1.hash());
+        assert!(info.has_body);
+    }
+
+    // Nothing is known above the head.
+    assert!(chain.blocks_at_height_info(4).unwrap().is_empty());
+}
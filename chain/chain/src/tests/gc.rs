@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use crate::chain::Chain;
@@ -547,3 +548,87 @@ fn test_gc_star_small() {
 fn test_gc_star_large() {
     test_gc_star_common(20)
 }
+
+// Compares the plan of `simulate_gc` with the blocks removed by `clear_data` run afterwards with
+// the same budget on the same store.
+fn gc_simulation_common(simple_chains: Vec<SimpleChain>, gc_blocks_limit: NumBlocks) {
+    let mut chain = get_chain(1);
+    let tries = chain.runtime_adapter.get_tries();
+    let genesis = chain.get_block_by_height(0).unwrap().clone();
+    let mut states = vec![(genesis, vec![Trie::empty_root()], vec![Vec::new()])];
+    for simple_chain in simple_chains.iter() {
+        let (source_block, state_root, _) = states[simple_chain.from as usize].clone();
+        do_fork(
+            source_block,
+            state_root,
+            tries.clone(),
+            &mut chain,
+            simple_chain.length,
+            &mut states,
+            1,
+            false,
+        );
+    }
+
+    let plan = chain.simulate_gc(gc_blocks_limit).unwrap();
+    // The simulation doesn't change the store.
+    assert_eq!(chain.simulate_gc(gc_blocks_limit).unwrap(), plan);
+    assert_eq!(chain.tail().unwrap(), plan.canonical_range.start);
+    assert_eq!(chain.sync_diagnostics().unwrap().last_gc_run, None);
+
+    chain.clear_data(tries, gc_blocks_limit).unwrap();
+    let removed = states
+        .iter()
+        .map(|(block, _, _)| *block.hash())
+        .filter(|hash| chain.get_block(hash).is_err())
+        .collect::<HashSet<_>>();
+    let mut expected = plan.fork_blocks.iter().cloned().collect::<HashSet<_>>();
+    assert_eq!(expected.len(), plan.fork_blocks.len());
+    // The first simple chain, after the genesis, is the canonical chain.
+    expected.extend(
+        states[..=simple_chains[0].length as usize]
+            .iter()
+            .filter(|(block, _, _)| plan.canonical_range.contains(&block.header().height()))
+            .map(|(block, _, _)| *block.hash()),
+    );
+    assert_eq!(removed, expected);
+    assert_eq!(chain.tail().unwrap(), plan.canonical_range.end);
+    assert_eq!(plan.estimated_keys == 0, removed.is_empty());
+    let last_gc_run = chain.sync_diagnostics().unwrap().last_gc_run.unwrap();
+    assert_eq!(last_gc_run.tail, plan.canonical_range.end);
+    assert_eq!(last_gc_run.num_blocks_collected, removed.len() as NumBlocks);
+}
+
+#[test]
+fn test_gc_simulation() {
+    for gc_blocks_limit in [1, 5, 30, 100] {
+        gc_simulation_common(
+            vec![SimpleChain { from: 0, length: 101, is_removed: false }],
+            gc_blocks_limit,
+        );
+        gc_simulation_common(
+            vec![
+                SimpleChain { from: 0, length: 101, is_removed: false },
+                SimpleChain { from: 10, length: 35, is_removed: true },
+            ],
+            gc_blocks_limit,
+        );
+        gc_simulation_common(
+            vec![
+                SimpleChain { from: 0, length: 101, is_removed: false },
+                SimpleChain { from: 10, length: 80, is_removed: false },
+            ],
+            gc_blocks_limit,
+        );
+        gc_simulation_common(
+            vec![
+                SimpleChain { from: 0, length: 101, is_removed: false },
+                SimpleChain { from: 10, length: 70, is_removed: false },
+                SimpleChain { from: 20, length: 25, is_removed: true },
+                SimpleChain { from: 30, length: 30, is_removed: false },
+                SimpleChain { from: 40, length: 1, is_removed: true },
+            ],
+            gc_blocks_limit,
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod blocks_delay_tracker;
 mod challenges;
 mod doomslug;
 mod gc;
@@ -8,6 +9,9 @@ use crate::types::Tip;
 use crate::{Block, Chain, Error, Provenance};
 use near_primitives::account::id::AccountId;
 use near_primitives::utils::MaybeValidated;
+use near_store::db::{DBError, DBTransaction, Database, TestDB};
+use near_store::DBCol;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 impl Chain {
     /// A wrapper function around process_block that doesn't trigger all the callbacks
@@ -28,3 +32,55 @@ impl Chain {
         )
     }
 }
+
+/// Database counting the reads, to check how many lookups a query takes.
+pub(crate) struct CountingDB {
+    db: TestDB,
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+}
+
+impl CountingDB {
+    pub(crate) fn new() -> Self {
+        Self { db: TestDB::new(), reads: AtomicUsize::new(0), writes: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn take_reads(&self) -> usize {
+        self.reads.swap(0, Ordering::Relaxed)
+    }
+
+    pub(crate) fn take_writes(&self) -> usize {
+        self.writes.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl Database for CountingDB {
+    fn get(&self, col: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, DBError> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.db.get(col, key)
+    }
+
+    fn iter<'a>(&'a self, col: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.db.iter(col)
+    }
+
+    fn iter_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.db.iter_without_rc_logic(col)
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.db.iter_prefix(col, key_prefix)
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.db.write(transaction)
+    }
+}
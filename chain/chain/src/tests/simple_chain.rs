@@ -1,15 +1,61 @@
-use crate::near_chain_primitives::error::BlockKnownError;
-use crate::test_utils::setup;
-use crate::{Block, ChainStoreAccess, ErrorKind};
+use crate::chain::{
+    ApprovalParticipation, CatchupBookkeepingIssue, CatchupDiagnostics, ChainAccess, HeightStatus,
+    Orphan, OrphanBlockPool, ReplayDivergence, SplitStateBacklogEntry, SyncStallCause,
+    MAX_ORPHAN_SIZE, NUM_EPOCHS_TO_KEEP_STORE_DATA,
+};
+use crate::events::{
+    BlockAcceptedEvent, ChainEventHandler, ChainEventSubscription, ChunkAppliedEvent, ReorgEvent,
+};
+use crate::genesis_block_info::GenesisBlockInfo;
+use crate::migrations::{get_chain_schema_version, migrate_chain_schema, CHAIN_SCHEMA_VERSION};
+use crate::near_chain_primitives::error::{BlockKnownError, GenesisInconsistency};
+use crate::store::GCMode;
+use crate::test_utils::{
+    setup, setup_with_tx_validity_period, setup_with_validators, KeyValueRuntime,
+    TEST_GENESIS_GAS_LIMIT,
+};
+use crate::tests::CountingDB;
+use crate::types::Tip;
+use crate::{
+    metrics, Block, BlockStatus, Chain, ChainGenesis, ChainStore, ChainStoreAccess,
+    DoomslugThresholdMode, Error, ErrorKind, Provenance, RequiredChunks, RuntimeAdapter,
+};
+use borsh::BorshSerialize;
 use chrono;
 use chrono::TimeZone;
+use near_chain_configs::{UnsupportedProtocolVersionPolicy, DEFAULT_MAX_ORPHANS_PER_PREV_HASH};
+use near_crypto::{KeyType, Signature};
 use near_logger_utils::init_test_logger;
-use near_primitives::hash::CryptoHash;
+use near_primitives::block_header::{
+    ApprovalInner, BlockHeader, BlockHeaderInnerRestV2, BlockHeaderV2, HeaderDescription,
+};
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::merkle::{verify_hash, PartialMerkleTree};
+use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout, ShardUId};
+use near_primitives::sharding::{
+    ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderV3, ShardChunkV2, ShardInfo,
+    StateSyncInfo,
+};
 use near_primitives::time::MockClockGuard;
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{
+    AccountId, BlockExtra, BlockHeight, ConsolidatedStateChange, EpochId, Finality, ShardId,
+    StateChangesForSplitStates,
+};
+use near_primitives::utils::{get_block_shard_id, to_timestamp, MaybeValidated};
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+use near_primitives::version::ProtocolFeature;
 use near_primitives::version::PROTOCOL_VERSION;
+use near_store::test_utils::create_test_store;
+use near_store::{
+    ColBlockMisc, ColPrevBlockWithNewChunk, ColState, Store, CHAIN_SCHEMA_VERSION_KEY,
+};
 use num_rational::Rational;
+use std::collections::BTreeMap;
 use std::str::FromStr;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[test]
 fn empty_chain() {
@@ -47,6 +93,8 @@ fn build_chain() {
         mock_clock_guard.add_instant(Instant::now());
         mock_clock_guard.add_instant(Instant::now());
         mock_clock_guard.add_instant(Instant::now());
+        // Instant call for recording the block acceptance for finality latency.
+        mock_clock_guard.add_instant(Instant::now());
     }
 
     let (mut chain, _, signer) = setup();
@@ -74,7 +122,7 @@ fn build_chain() {
     let count_instant = mock_clock_guard.instant_call_count();
     let count_utc = mock_clock_guard.utc_call_count();
     assert_eq!(count_utc, 9);
-    assert_eq!(count_instant, 12);
+    assert_eq!(count_instant, 16);
     #[cfg(feature = "nightly_protocol")]
     assert_eq!(
         chain.head().unwrap().last_block_hash,
@@ -136,6 +184,357 @@ fn build_chain_with_orhpans() {
     );
 }
 
+#[test]
+fn orphan_siblings_cap() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.get_block(&chain.genesis().hash().clone()).unwrap().clone();
+    let parent = Block::empty(&genesis, &*signer);
+    let children =
+        (0..500).map(|i| Block::empty_with_height(&parent, 2 + i, &*signer)).collect::<Vec<_>>();
+    for child in children.iter() {
+        assert_eq!(
+            chain.process_block_test(&None, child.clone()).unwrap_err().kind(),
+            ErrorKind::Orphan
+        );
+    }
+    assert_eq!(chain.orphans_len(), DEFAULT_MAX_ORPHANS_PER_PREV_HASH);
+    assert_eq!(
+        chain.orphans().get_orphans_within_depth(*parent.hash(), 1).len(),
+        DEFAULT_MAX_ORPHANS_PER_PREV_HASH
+    );
+
+    // The stored orphans are processed once their parent is accepted.
+    chain.process_block_test(&None, parent).unwrap();
+    assert_eq!(chain.orphans_len(), 0);
+    for (i, child) in children.iter().enumerate() {
+        assert_eq!(chain.get_block(child.hash()).is_ok(), i < DEFAULT_MAX_ORPHANS_PER_PREV_HASH);
+    }
+    assert_eq!(chain.head().unwrap().height, 1 + DEFAULT_MAX_ORPHANS_PER_PREV_HASH as u64);
+}
+
+#[test]
+fn orphans_within_depth_truncated() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    chain.set_max_orphans_per_prev_hash(500);
+    let genesis = chain.get_block(&chain.genesis().hash().clone()).unwrap().clone();
+    let parent = Block::empty(&genesis, &*signer);
+    for i in 0..500 {
+        let child = Block::empty_with_height(&parent, 2 + i, &*signer);
+        assert_eq!(chain.process_block_test(&None, child).unwrap_err().kind(), ErrorKind::Orphan);
+    }
+    assert_eq!(chain.orphans_len(), 500);
+    // Too wide fan-out is truncated instead of crashing the node.
+    assert_eq!(chain.orphans().get_orphans_within_depth(*parent.hash(), 1).len(), 100);
+}
+
+/// Returns an orphan pool without a limit per previous block, the genesis block and one block
+/// more than the pool can hold, all children of genesis at increasing heights.
+fn setup_orphan_pool_above_limit() -> (OrphanBlockPool, Block, Vec<Block>) {
+    init_test_logger();
+    let (chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let mut pool = OrphanBlockPool::new();
+    pool.set_max_orphans_per_prev_hash(usize::MAX);
+    let blocks = (0..MAX_ORPHAN_SIZE as u64 + 1)
+        .map(|i| Block::empty_with_height(&genesis, 1 + i, &*signer))
+        .collect();
+    (pool, genesis, blocks)
+}
+
+#[test]
+fn orphan_pool_eviction() {
+    let (mut pool, genesis, blocks) = setup_orphan_pool_above_limit();
+    for block in &blocks {
+        pool.add(Orphan::new(MaybeValidated::from(block.clone()), Provenance::NONE), false);
+    }
+    pool.debug_check_consistency();
+    // The highest orphans are evicted until the pool is below its limit.
+    assert_eq!(pool.len(), MAX_ORPHAN_SIZE - 1);
+    for (i, block) in blocks.iter().enumerate() {
+        assert_eq!(pool.contains(block.hash()), i < MAX_ORPHAN_SIZE - 1);
+    }
+    assert_eq!(pool.remove_by_prev_hash(*genesis.hash()).unwrap().len(), MAX_ORPHAN_SIZE - 1);
+    pool.debug_check_consistency();
+    assert_eq!(pool.len(), 0);
+}
+
+/// Orphans extending the head get their missing chunks requested up to a limit growing with the
+/// number of shards, while orphans on forks are capped at the small limit of their own.
+#[test]
+fn orphan_missing_chunks_request_limits() {
+    init_test_logger();
+    let (chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let mut pool = OrphanBlockPool::new();
+    let request = |pool: &mut OrphanBlockPool, block: &Block, extends_head: bool| {
+        pool.add(Orphan::new(MaybeValidated::from(block.clone()), Provenance::NONE), false);
+        let can_request = pool.can_request_missing_chunks_for_orphan(block.hash(), extends_head, 1);
+        if can_request {
+            pool.mark_missing_chunks_requested_for_orphan(*block.hash(), extends_head);
+        }
+        can_request
+    };
+
+    // Six orphans on a fork.
+    let mut prev = Block::empty_with_height(&genesis, 10, &*signer);
+    let mut fork_requests = vec![];
+    for _ in 0..6 {
+        let block = Block::empty(&prev, &*signer);
+        fork_requests.push(request(&mut pool, &block, false));
+        prev = block;
+    }
+    assert_eq!(fork_requests, vec![true, true, true, true, true, false]);
+
+    // Orphans extending the head are allowed at least as many requests as the orphans on forks.
+    let mut prev = genesis;
+    for _ in 0..5 {
+        let block = Block::empty(&prev, &*signer);
+        assert!(request(&mut pool, &block, true));
+        prev = block;
+    }
+    // With more shards more of them can be requested.
+    let block = Block::empty(&prev, &*signer);
+    pool.add(Orphan::new(MaybeValidated::from(block.clone()), Provenance::NONE), false);
+    assert!(!pool.can_request_missing_chunks_for_orphan(block.hash(), true, 1));
+    assert!(pool.can_request_missing_chunks_for_orphan(block.hash(), true, 4));
+    assert!(!pool.can_request_missing_chunks_for_orphan(block.hash(), false, 4));
+    pool.debug_check_consistency();
+}
+
+/// Orphans descending from the head through other orphans and stored headers extend the head,
+/// orphans on forks from below the head don't.
+#[test]
+fn orphans_extending_head() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    chain.process_block_test(&None, b1.clone()).unwrap();
+    // Only the header of the child of the head is known.
+    let b2 = Block::empty_with_height(&b1, 3, &*signer);
+    chain.sync_block_headers(vec![b2.header().clone()], &mut |_| {}).unwrap();
+    let b3 = Block::empty(&b2, &*signer);
+    let b4 = Block::empty(&b3, &*signer);
+    // A fork from genesis, only the header of its first block is known.
+    let f1 = Block::empty_with_height(&genesis, 2, &*signer);
+    chain.sync_block_headers(vec![f1.header().clone()], &mut |_| {}).unwrap();
+    let f2 = Block::empty(&f1, &*signer);
+    let f3 = Block::empty(&f2, &*signer);
+    for block in [&b3, &b4, &f2, &f3] {
+        assert_eq!(
+            chain.process_block_test(&None, block.clone()).unwrap_err().kind(),
+            ErrorKind::Orphan
+        );
+    }
+    assert_eq!(chain.head().unwrap().last_block_hash, *b1.hash());
+    assert!(chain.orphan_extends_head(&b3));
+    assert!(chain.orphan_extends_head(&b4));
+    assert!(!chain.orphan_extends_head(&f2));
+    assert!(!chain.orphan_extends_head(&f3));
+
+    // Only the `NUM_ORPHAN_ANCESTORS_CHECK` immediate parents of the orphan are checked.
+    let b5 = Block::empty(&b4, &*signer);
+    let b6 = Block::empty(&b5, &*signer);
+    for block in [&b5, &b6] {
+        assert_eq!(
+            chain.process_block_test(&None, block.clone()).unwrap_err().kind(),
+            ErrorKind::Orphan
+        );
+    }
+    assert!(chain.orphan_extends_head(&b5));
+    assert!(!chain.orphan_extends_head(&b6));
+
+    // The parent of the orphan is unknown.
+    let u1 = Block::empty_with_height(&b1, 10, &*signer);
+    let u2 = Block::empty(&u1, &*signer);
+    assert!(!chain.orphan_extends_head(&u2));
+}
+
+#[test]
+fn orphan_pool_summary() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    let b2 = Block::empty(&b1, &*signer);
+    let c1 = Block::empty_with_height(&b1, 3, &*signer);
+    let c2 = Block::empty_with_height(&b1, 4, &*signer);
+    let c3 = Block::empty_with_height(&b2, 4, &*signer);
+    for block in [&c1, &c2, &c3] {
+        assert_eq!(
+            chain.process_block_test(&None, block.clone()).unwrap_err().kind(),
+            ErrorKind::Orphan
+        );
+    }
+
+    let summary = chain.orphan_pool_summary();
+    assert_eq!(summary.num_orphans, 3);
+    assert_eq!(summary.orphans_per_height, BTreeMap::from([(3, 1), (4, 2)]));
+    let mut expected =
+        vec![(4, *c2.hash(), *b1.hash()), (4, *c3.hash(), *b2.hash()), (3, *c1.hash(), *b1.hash())];
+    expected.sort();
+    assert_eq!(
+        summary.orphans.iter().map(|x| (x.height, x.hash, x.prev_hash)).collect::<Vec<_>>(),
+        expected
+    );
+    let oldest_age = summary.oldest_orphan_age_ms.unwrap();
+    assert!(summary.orphans.iter().all(|x| !x.requested_missing_chunks && x.age_ms <= oldest_age));
+    assert_eq!(summary.num_evicted, 0);
+
+    // The children of the accepted block are adopted, the child of `b2` keeps waiting.
+    chain.process_block_test(&None, b1.clone()).unwrap();
+    let summary = chain.orphan_pool_summary();
+    assert_eq!(summary.orphans_per_height, BTreeMap::from([(4, 1)]));
+    assert_eq!(
+        summary.orphans.iter().map(|x| (x.hash, x.prev_hash)).collect::<Vec<_>>(),
+        vec![(*c3.hash(), *b2.hash())]
+    );
+}
+
+#[test]
+fn orphan_pool_eviction_reasons() {
+    let (mut pool, _, blocks) = setup_orphan_pool_above_limit();
+    {
+        let mock_clock_guard = MockClockGuard::default();
+        mock_clock_guard.add_instant(Instant::now() - Duration::from_secs(600));
+        pool.add(Orphan::new(MaybeValidated::from(blocks[0].clone()), Provenance::NONE), true);
+    }
+    for block in &blocks[1..MAX_ORPHAN_SIZE] {
+        pool.add(Orphan::new(MaybeValidated::from(block.clone()), Provenance::NONE), false);
+    }
+    let summary = pool.summary();
+    assert_eq!(summary.num_orphans, MAX_ORPHAN_SIZE);
+    assert!(summary.orphans[0].requested_missing_chunks);
+    assert!(summary.orphans[1..].iter().all(|x| !x.requested_missing_chunks));
+    assert!(summary.oldest_orphan_age_ms.unwrap() >= 600 * 1000);
+    assert_eq!(summary.num_evicted, 0);
+
+    // The expired orphan is evicted first, then the highest ones until the pool is below its
+    // limit.
+    let last = blocks.last().unwrap().clone();
+    pool.add(Orphan::new(MaybeValidated::from(last), Provenance::NONE), false);
+    let summary = pool.summary();
+    assert_eq!(summary.num_orphans, MAX_ORPHAN_SIZE - 1);
+    assert_eq!(summary.num_evicted_by_age, 1);
+    assert_eq!(summary.num_evicted_by_height, 1);
+    assert_eq!(summary.num_evicted, 2);
+    assert_eq!(summary.orphans.first().unwrap().height, 2);
+    assert_eq!(summary.orphans.last().unwrap().height, MAX_ORPHAN_SIZE as u64);
+    assert!(summary.orphans.iter().all(|x| !x.requested_missing_chunks));
+}
+
+#[test]
+fn orphan_pool_prune_below_height() {
+    init_test_logger();
+    let (chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let mut pool = OrphanBlockPool::new();
+    let blocks: Vec<_> =
+        (1..=6).map(|height| Block::empty_with_height(&genesis, height, &*signer)).collect();
+    let children: Vec<_> = blocks.iter().map(|block| Block::empty(block, &*signer)).collect();
+    for block in blocks.iter().chain(&children) {
+        let orphan = Orphan::new(MaybeValidated::from(block.clone()), Provenance::NONE);
+        pool.add(orphan, block.header().height() % 2 == 0);
+    }
+
+    // The orphans are pruned lowest first, in the order they were added at the same height.
+    assert_eq!(
+        pool.prune_below_height(4),
+        vec![
+            *blocks[0].hash(),
+            *blocks[1].hash(),
+            *children[0].hash(),
+            *blocks[2].hash(),
+            *children[1].hash()
+        ]
+    );
+    pool.debug_check_consistency();
+    for block in blocks.iter().chain(&children) {
+        assert_eq!(pool.contains(block.hash()), block.header().height() >= 4);
+    }
+    let summary = pool.summary();
+    assert_eq!(summary.orphans_per_height, BTreeMap::from([(4, 2), (5, 2), (6, 2), (7, 1)]));
+    // The orphans at even heights, both among the blocks and among their children.
+    assert_eq!(summary.orphans.iter().filter(|x| x.requested_missing_chunks).count(), 4);
+    // The child of a pruned orphan is gone from the prev hash index too.
+    assert!(pool.remove_by_prev_hash(*blocks[1].hash()).is_none());
+    assert!(pool.prune_below_height(4).is_empty());
+}
+
+/// Orphans below the heights cleared before state sync are pruned from the chain together with
+/// their delay tracking, those above survive.
+#[test]
+fn prune_orphans_before_state_sync() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let mut blocks = vec![Block::empty(&genesis, &*signer)];
+    for _ in 0..3 {
+        blocks.push(Block::empty(blocks.last().unwrap(), &*signer));
+    }
+    for block in &blocks {
+        chain.process_block_test(&None, block.clone()).unwrap();
+    }
+    // Orphans on a fork from a missing block at height 2, and above the head.
+    let missing_fork_block = Block::empty_with_height(&genesis, 2, &*signer);
+    let fork_orphan = Block::empty(&missing_fork_block, &*signer);
+    let fork_orphan_child = Block::empty(&fork_orphan, &*signer);
+    let missing_block = Block::empty(&blocks[3], &*signer);
+    let orphan = Block::empty(&missing_block, &*signer);
+    let orphans = [&fork_orphan, &fork_orphan_child, &orphan];
+    for block in orphans.iter() {
+        assert_eq!(
+            chain.process_block_test(&None, (*block).clone()).unwrap_err().kind(),
+            ErrorKind::Orphan
+        );
+    }
+    assert_eq!(chain.orphans_len(), 3);
+
+    // Syncing to the head clears the blocks below its height.
+    chain.reset_data_pre_state_sync(*blocks[3].hash()).unwrap();
+    assert_eq!(chain.orphans_len(), 2);
+    for block in orphans.iter() {
+        let kept = block.header().height() >= 4;
+        assert_eq!(chain.orphans().contains(block.hash()), kept);
+        assert_eq!(chain.blocks_delay_tracker.blocks_in_progress.contains_key(block.hash()), kept);
+    }
+    assert!(chain.prune_orphans_below_height(4).is_empty());
+}
+
+/// Removing the children of an accepted block scans the same index entries regardless of how
+/// many unrelated orphans are in the pool.
+#[test]
+fn orphan_removal_cost_independent_of_pool_size() {
+    init_test_logger();
+    let (chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let parents: Vec<_> =
+        (0..100).map(|i| Block::empty_with_height(&genesis, 1 + i, &*signer)).collect();
+    let children: Vec<_> = parents.iter().map(|parent| Block::empty(parent, &*signer)).collect();
+    let unrelated: Vec<_> =
+        (0..800).map(|i| Block::empty_with_height(&genesis, 1000 + i, &*signer)).collect();
+    let count_removal_scans = |num_unrelated: usize| {
+        let mut pool = OrphanBlockPool::new();
+        pool.set_max_orphans_per_prev_hash(usize::MAX);
+        for block in children.iter().chain(&unrelated[..num_unrelated]) {
+            let orphan = Orphan::new(MaybeValidated::from(block.clone()), Provenance::NONE);
+            pool.add(orphan, false);
+        }
+        pool.debug_check_consistency();
+        for parent in &parents {
+            assert_eq!(pool.remove_by_prev_hash(*parent.hash()).unwrap().len(), 1);
+        }
+        pool.debug_check_consistency();
+        assert_eq!(pool.len(), num_unrelated);
+        pool.index_entries_scanned
+    };
+    // Each child is alone in the buckets of its height and of its prev block.
+    assert_eq!(count_removal_scans(0), 2 * children.len());
+    assert_eq!(count_removal_scans(unrelated.len()), 2 * children.len());
+}
+
 #[test]
 fn build_chain_with_skips_and_forks() {
     init_test_logger();
@@ -155,6 +554,216 @@ fn build_chain_with_skips_and_forks() {
     assert_eq!(chain.get_header_by_height(5).unwrap().height(), 5);
 }
 
+#[test]
+fn block_header_by_ordinal() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    // The fork block takes ordinal 3 until the canonical chain overtakes it.
+    let fork = Block::empty(&b1, &*signer);
+    let b3 = Block::empty_with_height(&b1, 3, &*signer);
+    let b4 = Block::empty(&b3, &*signer);
+    let b7 = Block::empty_with_height(&b4, 7, &*signer);
+    for block in [&b1, &fork, &b3, &b4, &b7] {
+        chain.process_block_test(&None, block.clone()).unwrap();
+    }
+    assert_eq!(chain.head().unwrap().last_block_hash, *b7.hash());
+
+    for (ordinal, block) in [&genesis, &b1, &b3, &b4, &b7].into_iter().enumerate() {
+        let ordinal = ordinal as u64 + 1;
+        let header = chain.get_block_header_by_ordinal(ordinal).unwrap();
+        assert_eq!(header.hash(), block.hash());
+        assert_eq!(header.height(), block.header().height());
+        assert_eq!(header.block_ordinal(), ordinal);
+    }
+    for ordinal in [0, 6] {
+        match chain.get_block_header_by_ordinal(ordinal).unwrap_err().kind() {
+            ErrorKind::BlockOrdinalOutOfRange { ordinal: actual, max_ordinal } => {
+                assert_eq!(actual, ordinal);
+                assert_eq!(max_ordinal, 5);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
+
+#[test]
+fn execution_outcome_block_proof_finality() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let mut blocks = vec![chain.genesis_block().clone()];
+    for i in 1..5 {
+        let block = Block::empty(&blocks[i - 1], &*signer);
+        chain.process_block_test(&None, block.clone()).unwrap();
+        blocks.push(block);
+    }
+    // The last final block is the one at height 2 and the last doomslug final one at height 3.
+    let hashes = blocks.iter().map(|block| *block.hash()).collect::<Vec<_>>();
+    assert_eq!(chain.head().unwrap().last_block_hash, hashes[4]);
+
+    // A final block is proven against the light client head in both modes.
+    for allow_non_final in [false, true] {
+        let proof = chain
+            .get_execution_outcome_with_block_proof(&hashes[1], &hashes[2], allow_non_final)
+            .unwrap();
+        assert_eq!(proof.head_block_hash, hashes[2]);
+        assert_eq!(proof.finality, Finality::Final);
+        assert_eq!(proof.proof, chain.get_block_proof(&hashes[1], &hashes[2]).unwrap());
+    }
+
+    // Blocks which are not final yet are rejected in the strict mode.
+    for i in [3, 4] {
+        match chain.get_execution_outcome_with_block_proof(&hashes[i], &hashes[2], false) {
+            Err(err) => match err.kind() {
+                ErrorKind::NotFinalYet { block_hash, current_final_height } => {
+                    assert_eq!(block_hash, hashes[i]);
+                    assert_eq!(current_final_height, 2);
+                }
+                kind => panic!("unexpected error: {:?}", kind),
+            },
+            Ok(proof) => panic!("unexpected proof: {:?}", proof),
+        }
+    }
+
+    // In the relaxed mode they are proven against the header head, with their finality.
+    for (i, finality) in [(3, Finality::DoomSlug), (4, Finality::None)] {
+        let proof =
+            chain.get_execution_outcome_with_block_proof(&hashes[i], &hashes[2], true).unwrap();
+        assert_eq!(proof.head_block_hash, hashes[4]);
+        assert_eq!(proof.finality, finality);
+        assert_eq!(proof.proof, chain.get_block_proof(&hashes[i], &hashes[4]).unwrap());
+    }
+}
+
+#[test]
+fn block_proof_against_older_head() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let mut blocks = vec![chain.genesis_block().clone()];
+    for i in 1..10 {
+        let block = Block::empty(&blocks[i - 1], &*signer);
+        chain.process_block_test(&None, block.clone()).unwrap();
+        blocks.push(block);
+    }
+    let hashes = blocks.iter().map(|block| *block.hash()).collect::<Vec<_>>();
+    let root = *blocks[9].header().block_merkle_root();
+
+    // Repeated proofs against the same head only use the cached merkle tree nodes.
+    let proofs = hashes[..9]
+        .iter()
+        .map(|hash| chain.get_block_proof(hash, &hashes[9]).unwrap())
+        .collect::<Vec<_>>();
+    let cache_misses = chain.block_merkle_nodes_cache_misses;
+    assert!(cache_misses > 0);
+    for (hash, proof) in hashes.iter().zip(&proofs) {
+        assert!(verify_hash(root, proof, *hash));
+        assert_eq!(&chain.get_block_proof(hash, &hashes[9]).unwrap(), proof);
+    }
+    assert_eq!(chain.block_merkle_nodes_cache_misses, cache_misses);
+
+    // The nodes of another head are computed separately.
+    let proof = chain.get_block_proof(&hashes[1], &hashes[5]).unwrap();
+    assert!(verify_hash(*blocks[5].header().block_merkle_root(), &proof, hashes[1]));
+    assert!(chain.block_merkle_nodes_cache_misses > cache_misses);
+
+    // Blocks ahead of the head can't be proven against it.
+    assert!(chain.can_prove_block(&hashes[4], &hashes[5]).unwrap());
+    assert!(chain.can_prove_block(&hashes[5], &hashes[5]).unwrap());
+    assert!(!chain.can_prove_block(&hashes[6], &hashes[5]).unwrap());
+    match chain.get_block_proof(&hashes[6], &hashes[5]).unwrap_err().kind() {
+        ErrorKind::BlockAheadOfHead { block_height, head_height } => {
+            assert_eq!(block_height, 6);
+            assert_eq!(head_height, 5);
+        }
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+#[test]
+fn height_status() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    let b2 = Block::empty(&b1, &*signer);
+    // Height 3 is skipped on the canonical chain, but a fork has a block there.
+    let b4 = Block::empty_with_height(&b2, 4, &*signer);
+    let b5 = Block::empty(&b4, &*signer);
+    let fork = Block::empty_with_height(&b1, 3, &*signer);
+    for block in [&b1, &b2, &fork, &b4, &b5] {
+        chain.process_block_test(&None, block.clone()).unwrap();
+    }
+    assert_eq!(chain.head().unwrap().last_block_hash, *b5.hash());
+
+    assert_eq!(chain.height_status(0).unwrap(), HeightStatus::Block(*genesis.hash()));
+    assert_eq!(chain.height_status(2).unwrap(), HeightStatus::Block(*b2.hash()));
+    assert_eq!(chain.height_status(3).unwrap(), HeightStatus::Skipped);
+    assert_eq!(chain.height_status(4).unwrap(), HeightStatus::Block(*b4.hash()));
+    assert_eq!(chain.height_status(5).unwrap(), HeightStatus::Block(*b5.hash()));
+    assert_eq!(chain.height_status(6).unwrap(), HeightStatus::NotYetProduced);
+
+    let mut store_update = chain.mut_store().store_update();
+    store_update.update_tail(2);
+    store_update.commit().unwrap();
+    assert_eq!(chain.height_status(1).unwrap(), HeightStatus::GarbageCollected);
+    assert_eq!(chain.height_status(2).unwrap(), HeightStatus::Block(*b2.hash()));
+    assert_eq!(chain.height_status(3).unwrap(), HeightStatus::Skipped);
+}
+
+/// Iterating over the canonical blocks yields exactly the blocks of the canonical chain, skipping
+/// the heights without a canonical block, up to the head as of the creation of the iterator.
+#[test]
+fn iter_canonical_blocks() {
+    fn canonical_heights(chain: &Chain, from: BlockHeight, to: BlockHeight) -> Vec<BlockHeight> {
+        chain
+            .iter_canonical_blocks(from, to)
+            .map(|block| block.unwrap().header().height())
+            .collect()
+    }
+
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let mut canonical = vec![*genesis.hash()];
+    let mut prev = genesis;
+    // Every third height is skipped on the canonical chain, with a fork block there.
+    for height in (1..60).filter(|height| height % 3 != 0) {
+        if height % 3 == 1 && height > 1 {
+            let fork = Block::empty_with_height(&prev, height - 1, &*signer);
+            chain.process_block_test(&None, fork).unwrap();
+        }
+        let block = Block::empty_with_height(&prev, height, &*signer);
+        chain.process_block_test(&None, block.clone()).unwrap();
+        canonical.push(*block.hash());
+        prev = block;
+    }
+    assert_eq!(chain.head().unwrap().height, 59);
+
+    let hashes = chain
+        .iter_canonical_blocks(0, BlockHeight::MAX)
+        .map(|block| *block.unwrap().hash())
+        .collect::<Vec<_>>();
+    assert_eq!(hashes, canonical);
+    assert_eq!(canonical_heights(&chain, 3, 9), vec![4, 5, 7, 8]);
+    assert_eq!(canonical_heights(&chain, 57, 100), vec![58, 59]);
+    assert!(canonical_heights(&chain, 60, 100).is_empty());
+
+    let iter = chain.iter_canonical_blocks(58, 100);
+    chain.process_block_test(&None, Block::empty_with_height(&prev, 60, &*signer)).unwrap();
+    let heights = iter.map(|block| block.unwrap().header().height()).collect::<Vec<_>>();
+    assert_eq!(heights, vec![58, 59]);
+
+    let mut store_update = chain.mut_store().store_update();
+    store_update.update_tail(10);
+    store_update.commit().unwrap();
+    assert_eq!(canonical_heights(&chain, 10, 12), vec![10, 11]);
+    let mut iter = chain.iter_canonical_blocks(5, 20);
+    let err = iter.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BlockHeightBelowTail { height: 5, tail_height: 10 });
+    assert!(iter.next().is_none());
+}
+
 /// Verifies that the block at height are updated correctly when blocks from different forks are
 /// processed, especially when certain heights are skipped
 #[test]
@@ -257,3 +866,1648 @@ fn next_blocks() {
     assert_eq!(chain.mut_store().get_next_block_hash(&b1_hash).unwrap(), &b3_hash);
     assert_eq!(chain.mut_store().get_next_block_hash(&b3_hash).unwrap(), &b4_hash);
 }
+
+#[test]
+fn reject_oversized_block() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let block = Block::empty(&genesis, &*signer);
+    let block_size = block.try_to_vec().unwrap().len();
+
+    chain.block_limits_config.max_block_serialized_bytes = block_size - 1;
+    match chain.process_block_test(&None, block.clone()) {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::BlockTooLarge(block_size, block_size - 1)),
+        Ok(_) => panic!("Oversized block should be rejected"),
+    }
+    // Nothing about the block was stored, chunks were never applied.
+    assert_eq!(chain.head().unwrap().height, 0);
+    assert!(chain.get_block(block.hash()).is_err());
+    assert!(chain.get_chunk_extra(block.hash(), &ShardUId::single_shard()).is_err());
+
+    chain.block_limits_config.max_block_serialized_bytes = block_size;
+    chain.process_block_test(&None, block).unwrap();
+    assert_eq!(chain.head().unwrap().height, 1);
+}
+
+#[test]
+fn unsupported_protocol_version() {
+    init_test_logger();
+    let (mut chain, runtime, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    runtime.set_epoch_protocol_version(b1.header().epoch_id().clone(), PROTOCOL_VERSION + 1);
+    let expected_err = ErrorKind::UnsupportedProtocolVersion {
+        epoch_version: PROTOCOL_VERSION + 1,
+        binary_version: PROTOCOL_VERSION,
+    };
+
+    assert_eq!(chain.unsupported_protocol_version_policy, UnsupportedProtocolVersionPolicy::Halt);
+    match chain.process_block_test(&None, b1.clone()) {
+        Err(e) => assert_eq!(e.kind(), expected_err),
+        Ok(_) => panic!("Block with unsupported protocol version should be rejected"),
+    }
+    assert_eq!(chain.head().unwrap().height, 0);
+    assert_eq!(chain.header_head().unwrap().height, 0);
+    assert!(chain.get_block_header(b1.hash()).is_err());
+
+    chain.unsupported_protocol_version_policy = UnsupportedProtocolVersionPolicy::FollowHeadersOnly;
+    match chain.process_block_test(&None, b1.clone()) {
+        Err(e) => assert_eq!(e.kind(), expected_err),
+        Ok(_) => panic!("Block with unsupported protocol version should be rejected"),
+    }
+    // Only the header is accepted.
+    assert_eq!(chain.head().unwrap().height, 0);
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b1.hash());
+    assert!(chain.get_block_header(b1.hash()).is_ok());
+    assert!(chain.get_block(b1.hash()).is_err());
+}
+
+/// Processing of the blocks whose missing chunks arrived stops once the time budget is used up,
+/// the remaining blocks are processed by the following calls.
+#[test]
+fn check_blocks_with_missing_chunks_time_budget() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let chunk_hash = ChunkHash(hash(b"missing chunk"));
+    let mut prev_block = chain.genesis_block().clone();
+    for _ in 0..5 {
+        let block = Block::empty(&prev_block, &*signer);
+        chain.blocks_with_missing_chunks.add_block_with_missing_chunks(
+            Orphan::new(MaybeValidated::from(block.clone()), Provenance::PRODUCED),
+            vec![chunk_hash.clone()],
+        );
+        prev_block = block;
+    }
+    chain.blocks_with_missing_chunks.accept_chunk(&chunk_hash);
+
+    let mock_clock_guard = MockClockGuard::default();
+    mock_clock_guard.set_now(chrono::Utc::now(), Instant::now());
+    let time_budget = Duration::from_millis(200);
+    let mut check_blocks = |chain: &mut Chain| {
+        chain.check_blocks_with_missing_chunks(
+            &None,
+            // Processing of every block takes 100ms.
+            &mut |_| mock_clock_guard.advance(Duration::from_millis(100)),
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+            time_budget,
+        )
+    };
+    assert_eq!(check_blocks(&mut chain), 3);
+    assert_eq!(chain.head().unwrap().height, 2);
+    assert_eq!(check_blocks(&mut chain), 1);
+    assert_eq!(chain.head().unwrap().height, 4);
+    assert_eq!(check_blocks(&mut chain), 0);
+    assert_eq!(chain.head().unwrap().height, 5);
+    assert!(!chain.blocks_with_missing_chunks.has_ready_blocks());
+}
+
+#[test]
+fn split_state_backlog() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let block = Block::empty(&genesis, &*signer);
+    assert!(chain.split_state_backlog().unwrap().is_empty());
+    assert!(!chain.split_state_backlog_exceeds(0));
+
+    let state_changes = |num_changes: usize| StateChangesForSplitStates {
+        changes: (0..num_changes)
+            .map(|i| ConsolidatedStateChange {
+                trie_key: TrieKey::Account { account_id: format!("test{}", i).parse().unwrap() },
+                value: Some(vec![i as u8]),
+            })
+            .collect(),
+        processed_delayed_receipts: vec![],
+    };
+    let mut store_update = chain.mut_store().store_update();
+    store_update.add_state_changes_for_split_states(*block.hash(), 0, state_changes(3));
+    store_update.add_state_changes_for_split_states(*block.hash(), 1, state_changes(1));
+    store_update.commit().unwrap();
+
+    assert_eq!(
+        chain.split_state_backlog().unwrap(),
+        vec![
+            SplitStateBacklogEntry { block_hash: *block.hash(), shard_id: 0, num_changes: 3 },
+            SplitStateBacklogEntry { block_hash: *block.hash(), shard_id: 1, num_changes: 1 },
+        ]
+    );
+    assert!(chain.split_state_backlog_exceeds(1));
+    assert!(!chain.split_state_backlog_exceeds(2));
+
+    // Applying the changes during catchup removes them from the backlog.
+    let mut store_update = chain.mut_store().store_update();
+    store_update.remove_state_changes_for_split_states(*block.hash(), 0);
+    store_update.remove_state_changes_for_split_states(*block.hash(), 1);
+    store_update.commit().unwrap();
+    assert!(chain.split_state_backlog().unwrap().is_empty());
+}
+
+#[test]
+fn replay_blocks() {
+    init_test_logger();
+    let (mut chain, runtime, signer) = setup();
+    let me = Some(signer.validator_id().clone());
+    let mut hashes = vec![];
+    for _ in 0..5 {
+        let prev_hash = *chain.head_header().unwrap().hash();
+        let prev = chain.get_block(&prev_hash).unwrap();
+        let block = Block::empty(prev, &*signer);
+        hashes.push(*block.hash());
+        chain.process_block_test(&me, block).unwrap();
+    }
+
+    let report = chain.replay_blocks(&me, 0, 5, None).unwrap();
+    assert_eq!(report.blocks_replayed, 5);
+    assert_eq!(report.chunks_compared, 5);
+    assert!(report.divergences.is_empty());
+
+    // Corrupt the chunk extra stored for the block at height 3.
+    let shard_uid = ShardUId::single_shard();
+    let stored_extra = chain.mut_store().get_chunk_extra(&hashes[2], &shard_uid).unwrap().clone();
+    let mut corrupted_extra = stored_extra.clone();
+    *corrupted_extra.state_root_mut() = hash(b"corrupted");
+    let mut store_update = chain.mut_store().store_update();
+    store_update.save_chunk_extra(&hashes[2], &shard_uid, corrupted_extra.clone());
+    store_update.commit().unwrap();
+
+    // Only the corrupted block is reported, the following ones are replayed on top of the
+    // replayed results.
+    let report = chain.replay_blocks(&me, 1, 5, Some(vec![0])).unwrap();
+    assert_eq!(report.blocks_replayed, 5);
+    assert_eq!(
+        report.divergences.into_iter().collect::<Vec<_>>(),
+        vec![(
+            0,
+            ReplayDivergence {
+                height: 3,
+                block_hash: hashes[2],
+                shard_id: 0,
+                stored: Some(corrupted_extra),
+                replayed: stored_extra,
+            }
+        )]
+    );
+    // Replaying doesn't write anything.
+    assert_eq!(
+        chain.mut_store().get_chunk_extra(&hashes[2], &shard_uid).unwrap().state_root(),
+        &hash(b"corrupted")
+    );
+
+    let report = chain.replay_blocks(&me, 1, 5, Some(vec![1])).unwrap();
+    assert_eq!(report.chunks_compared, 0);
+    assert!(report.divergences.is_empty());
+
+    // If the replayed state diverges, the following blocks are applied on top of the replayed
+    // state, and only the first divergence of the shard is reported.
+    runtime.set_nondeterministic_apply(true);
+    let num_state_entries = chain.store().store().iter(ColState).count();
+    let report = chain.replay_blocks(&me, 1, 5, None).unwrap();
+    assert_eq!(chain.store().store().iter(ColState).count(), num_state_entries);
+    assert_eq!(report.blocks_replayed, 5);
+    assert_eq!(report.chunks_compared, 5);
+    let divergence = &report.divergences[&0];
+    assert_eq!(divergence.height, 1);
+    assert_eq!(divergence.block_hash, hashes[0]);
+    let stored_root = *divergence.stored.as_ref().unwrap().state_root();
+    assert_ne!(divergence.replayed.state_root(), &stored_root);
+}
+
+#[test]
+fn prev_block_with_new_chunk() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let me = Some(signer.validator_id().clone());
+    let genesis_hash = *chain.genesis().hash();
+    assert_eq!(chain.get_prev_block_hash_with_new_chunk(&genesis_hash, 0).unwrap(), None);
+
+    // None of the blocks have new chunks, so all of them link back to the genesis.
+    let mut hashes = vec![];
+    for _ in 0..20 {
+        let prev_hash = *chain.head_header().unwrap().hash();
+        let prev = chain.get_block(&prev_hash).unwrap();
+        let block = Block::empty(prev, &*signer);
+        hashes.push(*block.hash());
+        chain.process_block_test(&me, block).unwrap();
+    }
+    for hash in hashes.iter() {
+        assert_eq!(
+            chain.mut_store().get_prev_block_with_new_chunk(hash, 0).unwrap(),
+            Some((genesis_hash, 0))
+        );
+        assert_eq!(
+            chain.get_prev_block_hash_with_new_chunk(hash, 0).unwrap(),
+            Some((genesis_hash, 0))
+        );
+    }
+
+    // Blocks processed without tracking the shard have no links and are walked instead.
+    let mut untracked_hashes = vec![];
+    for _ in 0..5 {
+        let prev_hash = *chain.head_header().unwrap().hash();
+        let prev = chain.get_block(&prev_hash).unwrap();
+        let block = Block::empty(prev, &*signer);
+        untracked_hashes.push(*block.hash());
+        chain.process_block_test(&None, block).unwrap();
+    }
+    for hash in untracked_hashes.iter() {
+        assert_eq!(chain.mut_store().get_prev_block_with_new_chunk(hash, 0).unwrap(), None);
+        assert_eq!(
+            chain.get_prev_block_hash_with_new_chunk(hash, 0).unwrap(),
+            Some((genesis_hash, 0))
+        );
+    }
+    let head_hash = chain.head().unwrap().last_block_hash;
+    assert!(chain.get_outgoing_receipts_for_shard(head_hash, 0, 0).unwrap().is_empty());
+}
+
+/// Outgoing receipts are found from both sides of a resharding boundary. The receipts of the
+/// last chunk of the parent shard are each kept by exactly one of the shards split from it.
+#[test]
+fn outgoing_receipts_across_resharding() {
+    init_test_logger();
+    // With an epoch length of 1 the first heights start new epochs.
+    let (mut chain, runtime, signers) =
+        setup_with_validators(vec!["test0".parse().unwrap()], 1, 1, 1, 100);
+    let signer = &*signers[0];
+    let shard_layout = ShardLayout::v1_test();
+    let receipts = |receivers: &[&str]| {
+        receivers
+            .iter()
+            .map(|receiver| Receipt::new_balance_refund(&receiver.parse().unwrap(), 0))
+            .collect::<Vec<_>>()
+    };
+    let receivers = |receipts: Vec<Receipt>| {
+        let mut receivers =
+            receipts.into_iter().map(|receipt| receipt.receiver_id).collect::<Vec<_>>();
+        receivers.sort();
+        receivers
+    };
+    let parent_receipts = receipts(&["test0", "aa", "bob", "near", "zoo"]);
+    let child_receipts = receipts(&["bob", "near"]);
+
+    // The blocks are saved directly, with new chunks for shard 0 of the single shard layout at
+    // height 1 and for shard 1 of the split layout, starting at height 2, at height 3.
+    let mut blocks = vec![chain.genesis_block().clone()];
+    for height in 1..=4 {
+        let prev = blocks.last().unwrap().clone();
+        let epoch_id = runtime.get_epoch_id_from_prev_block(prev.hash()).unwrap();
+        let next_epoch_id = runtime.get_next_epoch_id_from_prev_block(prev.hash()).unwrap();
+        let (chunk_mask, new_chunk) = match height {
+            1 => (vec![true], Some((0, parent_receipts.clone()))),
+            2 => {
+                assert_ne!(&epoch_id, prev.header().epoch_id());
+                (vec![false; 4], None)
+            }
+            3 => (vec![false, true, false, false], Some((1, child_receipts.clone()))),
+            _ => (vec![false; 4], None),
+        };
+        if height >= 2 {
+            runtime.set_shard_layout(epoch_id.clone(), shard_layout.clone());
+        }
+        let mut block = Block::empty_with_epoch(
+            &prev,
+            height,
+            epoch_id,
+            next_epoch_id,
+            *prev.header().next_bp_hash(),
+            signer,
+            &mut PartialMerkleTree::default(),
+        );
+        block.mut_header().get_mut().inner_rest.chunk_mask = chunk_mask;
+        block.mut_header().resign(signer);
+        let mut store_update = chain.mut_store().store_update();
+        store_update.save_block_header(block.header().clone()).unwrap();
+        store_update.save_block(block.clone());
+        if let Some((shard_id, receipts)) = new_chunk {
+            store_update.save_outgoing_receipt(block.hash(), shard_id, receipts);
+        }
+        store_update.commit().unwrap();
+        blocks.push(block);
+    }
+
+    // Right after the boundary, every shard finds the last chunk of the parent shard.
+    let mut found = vec![];
+    for shard_id in 0..shard_layout.num_shards() {
+        let receipts =
+            chain.get_outgoing_receipts_for_shard(*blocks[2].hash(), shard_id, 1).unwrap();
+        for receipt in receipts.iter() {
+            assert_eq!(account_id_to_shard_id(&receipt.receiver_id, &shard_layout), shard_id);
+        }
+        found.extend(receivers(receipts));
+    }
+    found.sort();
+    assert_eq!(found, receivers(parent_receipts.clone()));
+
+    // Later, a shard with a new chunk after the boundary finds it, the others still find the
+    // parent chunk.
+    assert_eq!(
+        receivers(chain.get_outgoing_receipts_for_shard(*blocks[4].hash(), 1, 3).unwrap()),
+        receivers(child_receipts)
+    );
+    assert_eq!(
+        receivers(chain.get_outgoing_receipts_for_shard(*blocks[4].hash(), 2, 1).unwrap()),
+        receivers(chain.get_outgoing_receipts_for_shard(*blocks[2].hash(), 2, 1).unwrap())
+    );
+}
+
+#[test]
+fn chunk_by_height_and_shard_across_resharding() {
+    init_test_logger();
+    // With an epoch length of 1 the first heights start new epochs.
+    let (mut chain, runtime, signers) =
+        setup_with_validators(vec!["test0".parse().unwrap()], 1, 1, 1, 100);
+    let signer = &*signers[0];
+    let shard_layout = ShardLayout::v1_test();
+    let make_chunk = |prev: &Block, height: BlockHeight, shard_id: ShardId| {
+        let mut header = ShardChunkHeader::V3(ShardChunkHeaderV3::new(
+            *prev.hash(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            0,
+            height,
+            shard_id,
+            0,
+            0,
+            0,
+            CryptoHash::default(),
+            CryptoHash::default(),
+            vec![],
+            signer,
+        ));
+        *header.height_included_mut() = height;
+        header
+    };
+
+    // The blocks are saved directly, with new chunks for shard 0 of the single shard layout at
+    // height 1 and for shard 1 of the split layout, starting at height 2, at height 3. The other
+    // shards carry over their last chunk, which is the chunk of the parent shard after the split.
+    let mut blocks = vec![chain.genesis_block().clone()];
+    let mut new_chunks = vec![];
+    for height in 1..=4 {
+        let prev = blocks.last().unwrap().clone();
+        let epoch_id = runtime.get_epoch_id_from_prev_block(prev.hash()).unwrap();
+        let next_epoch_id = runtime.get_next_epoch_id_from_prev_block(prev.hash()).unwrap();
+        if height >= 2 {
+            runtime.set_shard_layout(epoch_id.clone(), shard_layout.clone());
+        }
+        let mut chunk_headers = prev.chunks().iter().cloned().collect::<Vec<_>>();
+        let new_chunk = match height {
+            1 => Some(make_chunk(&prev, height, 0)),
+            2 => {
+                chunk_headers = vec![chunk_headers[0].clone(); 4];
+                None
+            }
+            3 => Some(make_chunk(&prev, height, 1)),
+            _ => None,
+        };
+        if let Some(chunk_header) = &new_chunk {
+            chunk_headers[chunk_header.shard_id() as usize] = chunk_header.clone();
+        }
+        let mut block = Block::empty_with_epoch(
+            &prev,
+            height,
+            epoch_id,
+            next_epoch_id,
+            *prev.header().next_bp_hash(),
+            signer,
+            &mut PartialMerkleTree::default(),
+        );
+        block.set_chunks(chunk_headers);
+        let mut store_update = chain.mut_store().store_update();
+        store_update.save_block_header(block.header().clone()).unwrap();
+        store_update.save_block(block.clone());
+        if let Some(chunk_header) = new_chunk {
+            store_update.save_chunk(ShardChunk::V2(ShardChunkV2 {
+                chunk_hash: chunk_header.chunk_hash(),
+                header: chunk_header.clone(),
+                transactions: vec![],
+                receipts: vec![],
+            }));
+            new_chunks.push(chunk_header);
+        }
+        store_update.commit().unwrap();
+        blocks.push(block);
+    }
+    let mut store_update = chain.mut_store().store_update();
+    store_update.save_head(&Tip::from_header(blocks[4].header())).unwrap();
+    store_update.commit().unwrap();
+
+    let chunk = chain.get_chunk_by_height_and_shard(3, 1).unwrap();
+    assert_eq!(chunk.chunk_hash(), new_chunks[1].chunk_hash());
+    // Before the split, every shard of the split layout finds the chunk of the parent shard.
+    for shard_id in 0..shard_layout.num_shards() {
+        let chunk = chain.get_chunk_by_height_and_shard(1, shard_id).unwrap();
+        assert_eq!(chunk.chunk_hash(), new_chunks[0].chunk_hash());
+    }
+    assert_eq!(
+        chain.get_chunk_by_height_and_shard(4, 1).unwrap_err().kind(),
+        ErrorKind::ChunkNotIncluded { height: 4, shard_id: 1, included_height: 3 }
+    );
+    // Right after the split, the parent chunk is carried over.
+    assert_eq!(
+        chain.get_chunk_by_height_and_shard(2, 3).unwrap_err().kind(),
+        ErrorKind::ChunkNotIncluded { height: 2, shard_id: 3, included_height: 1 }
+    );
+}
+
+#[test]
+fn chain_schema_migration() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let me = Some(signer.validator_id().clone());
+    let genesis_hash = *chain.genesis().hash();
+    assert_eq!(chain.store().get_chain_schema_version().unwrap(), Some(CHAIN_SCHEMA_VERSION));
+
+    let mut hashes = vec![];
+    for _ in 0..10 {
+        let prev_hash = *chain.head_header().unwrap().hash();
+        let prev = chain.get_block(&prev_hash).unwrap();
+        let block = Block::empty(prev, &*signer);
+        hashes.push(*block.hash());
+        chain.process_block_test(&me, block).unwrap();
+    }
+
+    // Make the store look like it was written by the previous version, without the links.
+    let mut store_update = chain.store().store().store_update();
+    for hash in hashes.iter() {
+        store_update.delete(ColPrevBlockWithNewChunk, &get_block_shard_id(hash, 0));
+    }
+    store_update
+        .set_ser(ColBlockMisc, CHAIN_SCHEMA_VERSION_KEY, &(CHAIN_SCHEMA_VERSION - 1))
+        .unwrap();
+    store_update.commit().unwrap();
+    assert_eq!(get_chain_schema_version(chain.store()).unwrap(), CHAIN_SCHEMA_VERSION - 1);
+
+    migrate_chain_schema(chain.mut_store()).unwrap();
+    assert_eq!(chain.store().get_chain_schema_version().unwrap(), Some(CHAIN_SCHEMA_VERSION));
+    for hash in hashes.iter() {
+        assert_eq!(
+            chain.mut_store().get_prev_block_with_new_chunk(hash, 0).unwrap(),
+            Some((genesis_hash, 0))
+        );
+    }
+
+    // The migration is recorded, so running it again doesn't backfill anything.
+    let mut store_update = chain.store().store().store_update();
+    store_update.delete(ColPrevBlockWithNewChunk, &get_block_shard_id(&hashes[5], 0));
+    store_update.commit().unwrap();
+    migrate_chain_schema(chain.mut_store()).unwrap();
+    assert_eq!(chain.mut_store().get_prev_block_with_new_chunk(&hashes[5], 0).unwrap(), None);
+    assert_eq!(chain.store().get_chain_schema_version().unwrap(), Some(CHAIN_SCHEMA_VERSION));
+
+    // Data written by a newer version is rejected.
+    let mut store_update = chain.store().store().store_update();
+    store_update
+        .set_ser(ColBlockMisc, CHAIN_SCHEMA_VERSION_KEY, &(CHAIN_SCHEMA_VERSION + 1))
+        .unwrap();
+    store_update.commit().unwrap();
+    assert!(migrate_chain_schema(chain.mut_store()).is_err());
+}
+
+#[test]
+fn sync_diagnostics_pending_catchup() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let mut blocks = vec![];
+    for _ in 0..3 {
+        let prev_hash = *chain.head_header().unwrap().hash();
+        let prev = chain.get_block(&prev_hash).unwrap();
+        let block = Block::empty(prev, &*signer);
+        blocks.push(block.clone());
+        chain.process_block_test(&None, block).unwrap();
+    }
+
+    let diagnostics = chain.sync_diagnostics().unwrap();
+    assert_eq!(diagnostics.head_height, 3);
+    assert_eq!(diagnostics.header_head_height, 3);
+    assert!(diagnostics.head_epoch_known);
+    assert!(diagnostics.likely_causes().is_empty());
+
+    // Catchup started at the first block was never finished, the following blocks wait for it.
+    let mut store_update = chain.mut_store().store_update();
+    store_update.add_state_dl_info(StateSyncInfo {
+        epoch_tail_hash: *blocks[0].hash(),
+        shards: vec![ShardInfo(0, blocks[0].chunks()[0].chunk_hash())],
+    });
+    store_update.add_block_to_catchup(*blocks[0].header().prev_hash(), *blocks[0].hash());
+    store_update.add_block_to_catchup(*blocks[0].hash(), *blocks[1].hash());
+    store_update.add_block_to_catchup(*blocks[1].hash(), *blocks[2].hash());
+    store_update.commit().unwrap();
+
+    let diagnostics = chain.sync_diagnostics().unwrap();
+    assert_eq!(diagnostics.catchup_bookkeeping_issues, vec![]);
+    assert_eq!(
+        diagnostics.pending_catchups,
+        vec![CatchupDiagnostics {
+            sync_hash: *blocks[0].hash(),
+            num_shards: 1,
+            num_blocks_to_catchup: 2,
+        }]
+    );
+    assert_eq!(diagnostics.likely_causes(), vec![SyncStallCause::PendingCatchup]);
+}
+
+#[test]
+fn catchup_bookkeeping_issues() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let mut blocks = vec![];
+    for _ in 0..4 {
+        let prev_hash = *chain.head_header().unwrap().hash();
+        let prev = chain.get_block(&prev_hash).unwrap();
+        let block = Block::empty(prev, &*signer);
+        blocks.push(block.clone());
+        chain.process_block_test(&None, block).unwrap();
+    }
+    let mut store_update = chain.mut_store().store_update();
+    store_update.add_state_dl_info(StateSyncInfo {
+        epoch_tail_hash: *blocks[0].hash(),
+        shards: vec![ShardInfo(0, blocks[0].chunks()[0].chunk_hash())],
+    });
+    store_update.add_block_to_catchup(*blocks[0].header().prev_hash(), *blocks[0].hash());
+    store_update.add_block_to_catchup(*blocks[0].hash(), *blocks[1].hash());
+    store_update.commit().unwrap();
+    assert_eq!(chain.validate_catchup_bookkeeping().unwrap(), vec![]);
+
+    // A block queued after the wrong previous block, a block which doesn't exist and a state
+    // sync which never queued its block.
+    let missing_hash = hash(&[1]);
+    let mut store_update = chain.mut_store().store_update();
+    store_update.add_block_to_catchup(*blocks[2].hash(), *blocks[1].hash());
+    store_update.add_block_to_catchup(*blocks[3].hash(), missing_hash);
+    store_update.add_state_dl_info(StateSyncInfo {
+        epoch_tail_hash: *blocks[3].hash(),
+        shards: vec![ShardInfo(0, blocks[3].chunks()[0].chunk_hash())],
+    });
+    store_update.commit().unwrap();
+    let issues = chain.validate_catchup_bookkeeping().unwrap();
+    assert_eq!(issues.len(), 3);
+    assert!(issues.contains(&CatchupBookkeepingIssue::PrevHashMismatch {
+        prev_hash: *blocks[2].hash(),
+        hash: *blocks[1].hash(),
+    }));
+    assert!(issues.contains(&CatchupBookkeepingIssue::MissingBlock {
+        prev_hash: *blocks[3].hash(),
+        hash: missing_hash,
+    }));
+    assert!(issues.contains(&CatchupBookkeepingIssue::UnqueuedStateSyncInfo {
+        sync_hash: *blocks[3].hash(),
+    }));
+    let diagnostics = chain.sync_diagnostics().unwrap();
+    assert_eq!(diagnostics.catchup_bookkeeping_issues, issues);
+    assert!(diagnostics.likely_causes().contains(&SyncStallCause::InconsistentCatchupBookkeeping));
+
+    // Finishing the catchup of a block which was never queued is an error, not a panic.
+    let err = chain
+        .finish_catchup_blocks(
+            &None,
+            blocks[3].hash(),
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+            &vec![],
+        )
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InconsistentCatchupBookkeeping(_)));
+    assert_eq!(chain.validate_catchup_bookkeeping().unwrap(), issues);
+}
+
+#[test]
+fn chain_init_with_partial_genesis() {
+    init_test_logger();
+    let chain_genesis = ChainGenesis {
+        time: chrono::Utc.ymd(2020, 10, 1).and_hms(0, 0, 0),
+        height: 0,
+        gas_limit: TEST_GENESIS_GAS_LIMIT,
+        min_gas_price: 100,
+        max_gas_price: 1_000_000_000,
+        total_supply: 1_000_000_000,
+        gas_price_adjustment_rate: Rational::from_integer(0),
+        transaction_validity_period: 100,
+        epoch_length: 1000,
+        protocol_version: PROTOCOL_VERSION,
+    };
+    let new_chain = |store: Store| {
+        let runtime = Arc::new(KeyValueRuntime::new(store, chain_genesis.epoch_length));
+        Chain::new(runtime, &chain_genesis, DoomslugThresholdMode::NoApprovals).unwrap()
+    };
+
+    // Reference chain initialized from scratch, used as the source of the genesis data.
+    let mut reference = new_chain(create_test_store());
+    let genesis = reference.genesis_block().clone();
+    let shard_uid = ShardUId::single_shard();
+    let chunks = genesis
+        .chunks()
+        .iter()
+        .map(|header| reference.mut_store().get_chunk(&header.chunk_hash()).unwrap().clone())
+        .collect::<Vec<_>>();
+    let chunk_extra =
+        reference.mut_store().get_chunk_extra(genesis.hash(), &shard_uid).unwrap().clone();
+
+    // (header, block, chunks, block extra, chunk extra) already in the store.
+    let partial_subsets = [
+        (true, false, false, false, false),
+        (false, false, true, true, false),
+        (true, true, true, false, false),
+        (true, true, true, true, true),
+        (false, true, false, false, true),
+    ];
+    for (header, block, with_chunks, block_extra, with_chunk_extra) in partial_subsets {
+        let store = create_test_store();
+        let mut chain_store = ChainStore::new(store.clone(), chain_genesis.height);
+        let mut store_update = chain_store.store_update();
+        if header {
+            store_update.save_block_header(genesis.header().clone()).unwrap();
+        }
+        if block {
+            store_update.save_block(genesis.clone());
+        }
+        if with_chunks {
+            for chunk in chunks.iter() {
+                store_update.save_chunk(chunk.clone());
+            }
+        }
+        if block_extra {
+            store_update.save_block_extra(genesis.hash(), BlockExtra { challenges_result: vec![] });
+        }
+        if with_chunk_extra {
+            store_update.save_chunk_extra(genesis.hash(), &shard_uid, chunk_extra.clone());
+        }
+        store_update.commit().unwrap();
+
+        let mut chain = new_chain(store.clone());
+        assert_eq!(chain.head().unwrap(), reference.head().unwrap());
+        assert_eq!(chain.header_head().unwrap(), reference.header_head().unwrap());
+        assert_eq!(chain.final_head().unwrap(), reference.final_head().unwrap());
+        assert_eq!(chain.get_block(genesis.hash()).unwrap(), &genesis);
+        assert!(chain.mut_store().get_block_extra(genesis.hash()).is_ok());
+        assert_eq!(
+            chain.mut_store().get_chunk_extra(genesis.hash(), &shard_uid).unwrap(),
+            &chunk_extra
+        );
+        for chunk in chunks.iter() {
+            assert!(chain.get_chunk(&chunk.chunk_hash()).is_ok());
+        }
+
+        // Restarting on top of the now complete store takes the regular path.
+        let chain = new_chain(store);
+        assert_eq!(chain.head().unwrap(), reference.head().unwrap());
+    }
+}
+
+/// A chain genesis disagreeing with the genesis config of the runtime is rejected before anything
+/// is written to the store.
+#[test]
+fn inconsistent_chain_genesis() {
+    init_test_logger();
+    let store = create_test_store();
+    let runtime = Arc::new(KeyValueRuntime::new(store.clone(), 1000));
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.gas_limit = TEST_GENESIS_GAS_LIMIT / 2;
+    chain_genesis.protocol_version = PROTOCOL_VERSION - 1;
+    let expected_err = ErrorKind::InconsistentGenesis(vec![
+        GenesisInconsistency::GasLimit {
+            chain_genesis: TEST_GENESIS_GAS_LIMIT / 2,
+            runtime: TEST_GENESIS_GAS_LIMIT,
+        },
+        GenesisInconsistency::ProtocolVersion {
+            chain_genesis: PROTOCOL_VERSION - 1,
+            runtime: PROTOCOL_VERSION,
+        },
+    ]);
+    let err = Chain::new(runtime.clone(), &chain_genesis, DoomslugThresholdMode::NoApprovals)
+        .unwrap_err();
+    assert_eq!(err.kind(), expected_err);
+    let err = Chain::new_for_view_client(
+        runtime.clone(),
+        &chain_genesis,
+        DoomslugThresholdMode::NoApprovals,
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), expected_err);
+    assert!(store.get(ColBlockMisc, CHAIN_SCHEMA_VERSION_KEY).unwrap().is_none());
+    assert!(ChainStore::new(store.clone(), chain_genesis.height).head().is_err());
+
+    chain_genesis.gas_limit = TEST_GENESIS_GAS_LIMIT;
+    chain_genesis.protocol_version = PROTOCOL_VERSION;
+    let chain = Chain::new(runtime, &chain_genesis, DoomslugThresholdMode::NoApprovals).unwrap();
+    assert_eq!(chain.head().unwrap().height, chain_genesis.height);
+}
+
+/// The genesis block is computed by the first chain constructed with a runtime adapter, and the
+/// view client chains constructed with it afterwards share it.
+#[test]
+fn genesis_block_info_is_shared() {
+    init_test_logger();
+    let runtime = Arc::new(KeyValueRuntime::new(create_test_store(), 1000));
+    let runtime_adapter: Arc<dyn RuntimeAdapter> = runtime.clone();
+    let chain_genesis = ChainGenesis::test();
+    let chains = (0..4)
+        .map(|_| {
+            Chain::new_for_view_client(
+                runtime_adapter.clone(),
+                &chain_genesis,
+                DoomslugThresholdMode::NoApprovals,
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(runtime.num_block_producers_calls(), 1);
+    for chain in chains.iter() {
+        assert_eq!(chain.genesis().hash(), chains[0].genesis().hash());
+    }
+    let state_roots = runtime.genesis_state().1;
+    let info =
+        GenesisBlockInfo::get_or_compute(&runtime_adapter, &chain_genesis, &state_roots).unwrap();
+    assert_eq!(info.block.hash(), chains[0].genesis().hash());
+    assert_eq!(runtime.num_block_producers_calls(), 1);
+
+    // Building it explicitly bypasses the cache.
+    let built = GenesisBlockInfo::new(&*runtime, &chain_genesis, state_roots).unwrap();
+    assert_eq!(built.block.hash(), info.block.hash());
+    assert_eq!(built.bp_hash, info.bp_hash);
+    assert_eq!(runtime.num_block_producers_calls(), 2);
+}
+
+/// Rebuilds a `BlockHeaderV3` as a `BlockHeaderV2`, which doesn't carry `prev_height`.
+fn header_v2(header: &BlockHeader, signer: &dyn ValidatorSigner) -> BlockHeader {
+    let header = match header {
+        BlockHeader::BlockHeaderV3(header) => header,
+        _ => panic!("expected header V3"),
+    };
+    let inner_rest = &header.inner_rest;
+    let mut header_v2 = BlockHeader::BlockHeaderV2(Box::new(BlockHeaderV2 {
+        prev_hash: header.prev_hash,
+        inner_lite: header.inner_lite.clone(),
+        inner_rest: BlockHeaderInnerRestV2 {
+            chunk_receipts_root: inner_rest.chunk_receipts_root,
+            chunk_headers_root: inner_rest.chunk_headers_root,
+            chunk_tx_root: inner_rest.chunk_tx_root,
+            challenges_root: inner_rest.challenges_root,
+            random_value: inner_rest.random_value,
+            validator_proposals: vec![],
+            chunk_mask: inner_rest.chunk_mask.clone(),
+            gas_price: inner_rest.gas_price,
+            total_supply: inner_rest.total_supply,
+            challenges_result: inner_rest.challenges_result.clone(),
+            last_final_block: inner_rest.last_final_block,
+            last_ds_final_block: inner_rest.last_ds_final_block,
+            approvals: inner_rest.approvals.clone(),
+            latest_protocol_version: inner_rest.latest_protocol_version,
+        },
+        signature: header.signature.clone(),
+        hash: header.hash,
+    }));
+    header_v2.resign(signer);
+    header_v2
+}
+
+#[test]
+fn describe_and_validate_header_versions() {
+    init_test_logger();
+    let (mut chain, runtime, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let block = Block::empty(&genesis, &*signer);
+    chain.process_block_test(&None, block.clone()).unwrap();
+    assert_eq!(
+        chain.describe_header(block.hash()).unwrap(),
+        HeaderDescription {
+            hash: *block.hash(),
+            height: 1,
+            version: 3,
+            latest_protocol_version: block.header().latest_protocol_version(),
+            prev_height: Some(genesis.header().height()),
+            block_ordinal: Some(2),
+            has_epoch_sync_fields: true,
+            epoch_sync_data_hash: block.header().epoch_sync_data_hash(),
+        }
+    );
+
+    let header = header_v2(Block::empty(&genesis, &*signer).header(), &*signer);
+    let description = header.describe();
+    assert_eq!(description.version, 2);
+    assert_eq!(description.prev_height, None);
+    assert_eq!(description.block_ordinal, None);
+    assert!(!description.has_epoch_sync_fields);
+
+    // The epoch has `BlockHeaderV3` enabled, so `prev_height` is required.
+    let err = chain.process_block_header(&header, &mut |_| {}).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Other(msg) if msg.contains("Missing prev_height")));
+
+    // Before `BlockHeaderV3` headers without `prev_height` are valid.
+    runtime.set_epoch_protocol_version(
+        header.epoch_id().clone(),
+        ProtocolFeature::BlockHeaderV3.protocol_version() - 1,
+    );
+    chain.process_block_header(&header, &mut |_| {}).unwrap();
+}
+
+#[test]
+fn shutdown_during_block_processing() {
+    init_test_logger();
+    let (mut chain, runtime, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let block1 = Block::empty(&genesis, &*signer);
+    let block2 = Block::empty(&block1, &*signer);
+
+    runtime.set_block_processing_delay(Duration::from_millis(300));
+    let handle = chain.shutdown_handle();
+    let shutdown = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        handle.request_shutdown();
+        handle.wait_until_idle(Duration::from_secs(5))
+    });
+    // The block was already being processed when the shutdown was requested, so it is committed.
+    chain.process_block_test(&None, block1.clone()).unwrap();
+    assert!(shutdown.join().unwrap());
+    assert_eq!(chain.head().unwrap().last_block_hash, *block1.hash());
+
+    // New blocks are rejected without leaving anything in the store.
+    assert_eq!(
+        chain.process_block_test(&None, block2.clone()).unwrap_err().kind(),
+        ErrorKind::ShuttingDown
+    );
+    assert!(!chain.block_exists(block2.hash()).unwrap());
+    assert!(chain.get_block_header(block2.hash()).is_err());
+    assert_eq!(chain.head().unwrap().last_block_hash, *block1.hash());
+
+    let report = chain.prepare_shutdown(Duration::from_secs(1));
+    assert!(report.is_clean(), "{:?}", report);
+}
+
+/// The shutdown waits for the sync jobs running on other threads, and the orphans are no longer
+/// processed once it is requested.
+#[test]
+fn shutdown_waits_for_sync_jobs() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let block1 = Block::empty(&genesis, &*signer);
+    let block2 = Block::empty(&block1, &*signer);
+    assert_eq!(
+        chain.process_block_test(&None, block2.clone()).unwrap_err().kind(),
+        ErrorKind::Orphan
+    );
+
+    let handle = chain.shutdown_handle();
+    let (started_sender, started_receiver) = std::sync::mpsc::channel();
+    let sync_job = std::thread::spawn(move || {
+        let _in_flight = handle.enter().unwrap();
+        started_sender.send(()).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+    });
+    started_receiver.recv().unwrap();
+    assert!(!chain.prepare_shutdown(Duration::from_millis(10)).drained);
+    let report = chain.prepare_shutdown(Duration::from_secs(5));
+    assert!(report.drained);
+    sync_job.join().unwrap();
+    // Sync jobs scheduled after the shutdown are skipped.
+    assert_eq!(chain.shutdown_handle().enter().err().unwrap().kind(), ErrorKind::ShuttingDown);
+
+    // The orphan stays in the pool instead of being processed.
+    assert_eq!(report.orphans, 1);
+    let new_head = chain.check_orphans(
+        &None,
+        *block1.hash(),
+        &mut |_| {},
+        &mut |_| {},
+        &mut |_| {},
+        &mut |_| {},
+    );
+    assert!(new_head.is_none());
+    assert!(chain.orphans().contains(block2.hash()));
+}
+
+/// A copy of a block received while the block is being processed is rejected before any
+/// validation, and the block is no longer marked as being processed once its processing completes,
+/// whether it succeeded or not.
+#[test]
+fn block_in_processing_rejected() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let block1 = Block::empty(&genesis, &*signer);
+    let block2 = Block::empty(&block1, &*signer);
+
+    let validations = Arc::new(Mutex::new(vec![]));
+    let duplicate_errors = Arc::new(Mutex::new(vec![]));
+    chain.block_validation_hook = Some(Box::new({
+        let validations = validations.clone();
+        let duplicate_errors = duplicate_errors.clone();
+        move |chain: &mut Chain, block: &Block| {
+            validations.lock().unwrap().push(*block.hash());
+            let err = chain.process_block_test(&None, block.clone()).unwrap_err();
+            duplicate_errors.lock().unwrap().push(err.kind());
+        }
+    }));
+    let known_in_processing = ErrorKind::BlockKnown(BlockKnownError::KnownInProcessing);
+
+    // The processing of an orphan fails.
+    assert_eq!(
+        chain.process_block_test(&None, block2.clone()).unwrap_err().kind(),
+        ErrorKind::Orphan
+    );
+    chain.process_block_test(&None, block1.clone()).unwrap();
+    assert_eq!(chain.head().unwrap().last_block_hash, *block2.hash());
+    // The orphan was validated once more when it was unrolled after its parent.
+    assert_eq!(*validations.lock().unwrap(), vec![*block2.hash(), *block1.hash(), *block2.hash()]);
+    assert_eq!(*duplicate_errors.lock().unwrap(), vec![known_in_processing; 3]);
+
+    chain.block_validation_hook = None;
+    assert_eq!(
+        chain.process_block_test(&None, block1).unwrap_err().kind(),
+        ErrorKind::BlockKnown(BlockKnownError::KnownInStore)
+    );
+}
+
+#[test]
+fn cares_about_shards_once_per_block() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1", "test2", "test3"].iter().map(|x| x.parse().unwrap()).collect();
+    let (mut chain, runtime, signers) = setup_with_validators(validators.clone(), 4, 4, 1000, 100);
+    let me = Some(validators[0].clone());
+    let mut prev_block = chain.genesis_block().clone();
+    for height in 1..=3 {
+        let block = Block::empty(&prev_block, &*signers[height % signers.len()]);
+        let shard_care = runtime.cares_about_shards(me.as_ref(), prev_block.hash()).unwrap();
+        assert_eq!(shard_care.this_epoch, vec![true, false, false, false]);
+        for shard_id in 0..4 {
+            assert_eq!(
+                shard_care.cares_about_shard(shard_id),
+                runtime.cares_about_shard(me.as_ref(), prev_block.hash(), shard_id, true)
+            );
+            assert_eq!(
+                shard_care.will_care_about_shard(shard_id),
+                runtime.will_care_about_shard(me.as_ref(), prev_block.hash(), shard_id, true)
+            );
+        }
+
+        let num_shard_calls = runtime.num_cares_about_shard_calls();
+        let num_batch_calls = runtime.num_cares_about_shards_calls();
+        chain.process_block_test(&me, block.clone()).unwrap();
+        assert_eq!(runtime.num_cares_about_shard_calls(), num_shard_calls);
+        assert_eq!(runtime.num_cares_about_shards_calls(), num_batch_calls + 1);
+        prev_block = block;
+    }
+}
+
+/// The time it took to apply the chunks is reported per shard, and the slowest shard is passed
+/// along with the accepted block.
+#[test]
+fn slowest_shard_of_accepted_block() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1"].iter().map(|x| x.parse().unwrap()).collect();
+    let (mut chain, runtime, signers) = setup_with_validators(validators.clone(), 1, 2, 1000, 100);
+    let me = Some(validators[0].clone());
+    let delay = Duration::from_millis(100);
+    runtime.set_apply_delay(1, delay);
+    let apply_time = metrics::CHUNK_APPLY_TIME.with_label_values(&["1"]);
+    let (prev_count, prev_sum) = (apply_time.get_sample_count(), apply_time.get_sample_sum());
+
+    let mut accepted = vec![];
+    let block = Block::empty(chain.genesis_block(), &*signers[1]);
+    chain
+        .process_block(
+            &me,
+            MaybeValidated::from(block.clone()),
+            Provenance::PRODUCED,
+            &mut |accepted_block| accepted.push(accepted_block),
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+        )
+        .unwrap();
+    assert_eq!(accepted.len(), 1);
+    assert_eq!(accepted[0].hash, *block.hash());
+    let (shard_id, duration) = accepted[0].slowest_shard.unwrap();
+    assert_eq!(shard_id, 1);
+    assert!(duration >= delay);
+    assert!(apply_time.get_sample_count() > prev_count);
+    assert!(apply_time.get_sample_sum() - prev_sum >= delay.as_secs_f64());
+
+    // Without tracking any shards no chunks are applied.
+    let mut accepted = vec![];
+    let block = Block::empty(&block, &*signers[0]);
+    chain
+        .process_block(
+            &None,
+            MaybeValidated::from(block),
+            Provenance::PRODUCED,
+            &mut |accepted_block| accepted.push(accepted_block),
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+        )
+        .unwrap();
+    assert_eq!(accepted.len(), 1);
+    assert_eq!(accepted[0].slowest_shard, None);
+}
+
+/// A node tracking one of two shards needs the full chunk of its shard and the partial chunk of
+/// the other one, and only for the chunks new in the block.
+#[test]
+fn required_chunks_for_block() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1"].iter().map(|x| x.parse().unwrap()).collect();
+    let (mut chain, _, signers) = setup_with_validators(validators.clone(), 2, 2, 1000, 100);
+    let me = Some(validators[0].clone());
+    let genesis = chain.genesis_block().clone();
+    let mut block = Block::empty(&genesis, &*signers[0]);
+    assert_eq!(chain.required_chunks_for_block(&me, &block).unwrap(), RequiredChunks::default());
+
+    let new_chunks: Vec<_> = genesis
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(shard_id, genesis_chunk)| {
+            let mut chunk_header = ShardChunkHeader::V3(ShardChunkHeaderV3::new(
+                *genesis.hash(),
+                genesis_chunk.prev_state_root(),
+                CryptoHash::default(),
+                CryptoHash::default(),
+                0,
+                block.header().height(),
+                shard_id as ShardId,
+                0,
+                0,
+                0,
+                CryptoHash::default(),
+                CryptoHash::default(),
+                vec![],
+                &*signers[0],
+            ));
+            *chunk_header.height_included_mut() = block.header().height();
+            chunk_header
+        })
+        .collect();
+    block.set_chunks(new_chunks.clone());
+    assert_eq!(
+        chain.required_chunks_for_block(&me, &block).unwrap(),
+        RequiredChunks {
+            need_full: vec![new_chunks[0].clone()],
+            need_partial: vec![new_chunks[1].clone()],
+        }
+    );
+    assert_eq!(
+        chain.required_chunks_for_block(&Some(validators[1].clone()), &block).unwrap(),
+        RequiredChunks {
+            need_full: vec![new_chunks[1].clone()],
+            need_partial: vec![new_chunks[0].clone()],
+        }
+    );
+
+    // The chunk of the second shard is carried over from genesis.
+    block.set_chunks(vec![new_chunks[0].clone(), genesis.chunks()[1].clone()]);
+    assert_eq!(
+        chain.required_chunks_for_block(&me, &block).unwrap(),
+        RequiredChunks { need_full: vec![new_chunks[0].clone()], need_partial: vec![] }
+    );
+}
+
+/// A block whose chunk mask disagrees with the chunks it includes is rejected even if the body
+/// skipped the usual validity checks.
+#[test]
+fn chunk_mask_mismatch() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+
+    // The mask claims a new chunk, but the block carries the genesis chunk header.
+    let mut block = Block::empty(&genesis, &*signer);
+    block.mut_header().get_mut().inner_rest.chunk_mask = vec![true];
+    block.mut_header().resign(&*signer);
+    assert_eq!(
+        chain
+            .process_block(
+                &None,
+                MaybeValidated::from_validated(block),
+                Provenance::PRODUCED,
+                &mut |_| {},
+                &mut |_| {},
+                &mut |_| {},
+                &mut |_| {},
+            )
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidChunkMask(Some(0))
+    );
+
+    // The block includes a new chunk, but the mask says it doesn't.
+    let mut block = Block::empty(&genesis, &*signer);
+    let genesis_chunk = &genesis.chunks()[0];
+    let mut chunk_header = ShardChunkHeader::V3(ShardChunkHeaderV3::new(
+        *genesis.hash(),
+        genesis_chunk.prev_state_root(),
+        CryptoHash::default(),
+        CryptoHash::default(),
+        0,
+        block.header().height(),
+        0,
+        0,
+        0,
+        0,
+        CryptoHash::default(),
+        CryptoHash::default(),
+        vec![],
+        &*signer,
+    ));
+    *chunk_header.height_included_mut() = block.header().height();
+    block.set_chunks(vec![chunk_header]);
+    block.mut_header().get_mut().inner_rest.chunk_mask = vec![false];
+    block.mut_header().resign(&*signer);
+    assert_eq!(
+        chain
+            .process_block(
+                &None,
+                MaybeValidated::from_validated(block),
+                Provenance::PRODUCED,
+                &mut |_| {},
+                &mut |_| {},
+                &mut |_| {},
+                &mut |_| {},
+            )
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidChunkMask(Some(0))
+    );
+}
+
+/// Blocks failing validation during block processing are recorded with the kind of the error,
+/// the latest first, while orphans are not.
+#[test]
+fn rejected_blocks() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+
+    let mut mask_mismatch = Block::empty(&genesis, &*signer);
+    mask_mismatch.mut_header().get_mut().inner_rest.chunk_mask = vec![true];
+    mask_mismatch.mut_header().resign(&*signer);
+    let mut bad_randomness = Block::empty_with_height(&genesis, 2, &*signer);
+    bad_randomness.mut_header().get_mut().inner_rest.random_value = CryptoHash::default();
+    bad_randomness.mut_header().resign(&*signer);
+    let orphan = Block::empty(&mask_mismatch, &*signer);
+
+    assert_eq!(
+        chain.process_block_test(&None, mask_mismatch.clone()).unwrap_err().kind(),
+        ErrorKind::InvalidChunkMask(Some(0))
+    );
+    assert_eq!(
+        chain.process_block_test(&None, bad_randomness.clone()).unwrap_err().kind(),
+        ErrorKind::InvalidRandomnessBeaconOutput
+    );
+    assert_eq!(chain.process_block_test(&None, orphan).unwrap_err().kind(), ErrorKind::Orphan);
+
+    let rejected = chain.rejected_blocks(10).unwrap();
+    assert_eq!(
+        rejected
+            .iter()
+            .map(|block| (block.hash, block.height, block.error.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (*bad_randomness.hash(), 2, format!("{:?}", ErrorKind::InvalidRandomnessBeaconOutput)),
+            (*mask_mismatch.hash(), 1, format!("{:?}", ErrorKind::InvalidChunkMask(Some(0)))),
+        ]
+    );
+    assert!(rejected.iter().all(|block| block.producer.as_ref() == Some(signer.validator_id())));
+    assert_eq!(chain.rejected_blocks(1).unwrap(), rejected[..1].to_vec());
+}
+
+/// A block whose chunks fail to be applied doesn't move the header head, and nothing of it is saved.
+#[test]
+fn header_head_not_advanced_by_failed_block() {
+    init_test_logger();
+    let (mut chain, runtime, signer) = setup();
+    let me = Some(signer.validator_id().clone());
+    let genesis = chain.genesis_block().clone();
+    let mut block_merkle_tree = PartialMerkleTree::default();
+    let b1 = Block::empty_with_block_merkle_tree(&genesis, &*signer, &mut block_merkle_tree);
+    chain.process_block_test(&me, b1.clone()).unwrap();
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b1.hash());
+
+    let b2 = Block::empty_with_block_merkle_tree(&b1, &*signer, &mut block_merkle_tree);
+    runtime.set_failing_apply(true);
+    match chain.process_block_test(&me, b2.clone()).unwrap_err().kind() {
+        ErrorKind::Other(_) => {}
+        kind => panic!("unexpected error {:?}", kind),
+    }
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b1.hash());
+    assert_eq!(chain.head().unwrap().last_block_hash, *b1.hash());
+    assert!(chain.get_block_header(b2.hash()).is_err());
+    assert!(chain.get_block(b2.hash()).is_err());
+
+    runtime.set_failing_apply(false);
+    chain.process_block_test(&me, b2.clone()).unwrap();
+    assert_eq!(chain.head().unwrap().last_block_hash, *b2.hash());
+    assert_eq!(chain.header_head().unwrap().last_block_hash, *b2.hash());
+}
+
+/// A block with new chunks stored at the positions of other shards is rejected by the basic
+/// validation, before any of its chunks is looked up or applied.
+#[test]
+fn chunk_shard_id_mismatch() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1"].iter().map(|x| x.parse().unwrap()).collect();
+    let (mut chain, _, signers) = setup_with_validators(validators, 2, 2, 1000, 100);
+    let genesis = chain.genesis_block().clone();
+    let mut block = Block::empty(&genesis, &*signers[0]);
+    let new_chunks: Vec<_> = genesis
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(shard_id, genesis_chunk)| {
+            let mut chunk_header = ShardChunkHeader::V3(ShardChunkHeaderV3::new(
+                *genesis.hash(),
+                genesis_chunk.prev_state_root(),
+                CryptoHash::default(),
+                CryptoHash::default(),
+                0,
+                block.header().height(),
+                shard_id as ShardId,
+                0,
+                0,
+                0,
+                CryptoHash::default(),
+                CryptoHash::default(),
+                vec![],
+                &*signers[0],
+            ));
+            *chunk_header.height_included_mut() = block.header().height();
+            chunk_header
+        })
+        .collect();
+    block.set_chunks(vec![new_chunks[1].clone(), new_chunks[0].clone()]);
+
+    assert_eq!(
+        chain.validate_block(&MaybeValidated::from(block.clone())).unwrap_err().kind(),
+        ErrorKind::InvalidChunkShardId { position: 0, shard_id: 1 }
+    );
+    assert_eq!(
+        Error::from(block.chunk_for_shard(1).unwrap_err()).kind(),
+        ErrorKind::InvalidChunkShardId { position: 1, shard_id: 0 }
+    );
+    assert_eq!(
+        Error::from(block.chunk_for_shard(2).unwrap_err()).kind(),
+        ErrorKind::InvalidShardId(2)
+    );
+    assert_eq!(chain.head().unwrap().last_block_hash, *genesis.hash());
+}
+
+/// The parts listed as owned by an account and the listed owners match `get_part_owner`.
+#[test]
+fn part_ownership() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1", "test2"].iter().map(|x| x.parse().unwrap()).collect();
+    let (chain, runtime, _) = setup_with_validators(validators.clone(), 1, 2, 1000, 100);
+    let genesis_hash = *chain.genesis_block().hash();
+    let num_total_parts = runtime.num_total_parts() as u64;
+    let part_owners: Vec<_> = (0..num_total_parts)
+        .map(|part_id| runtime.get_part_owner(&genesis_hash, part_id).unwrap())
+        .collect();
+
+    for account_id in validators {
+        let view = chain.part_ownership(&genesis_hash, &account_id).unwrap();
+        assert_eq!(view.epoch_id, runtime.get_epoch_id_from_prev_block(&genesis_hash).unwrap());
+        assert_eq!(view.num_total_parts, num_total_parts);
+        assert_eq!(view.num_data_parts, runtime.num_data_parts() as u64);
+        assert_eq!(view.part_owners, part_owners);
+        let owned_parts: Vec<_> = (0..num_total_parts)
+            .filter(|&part_id| part_owners[part_id as usize] == account_id)
+            .collect();
+        assert!(!owned_parts.is_empty());
+        assert_eq!(view.owned_parts, owned_parts);
+    }
+}
+
+/// Approvals missing from accepted blocks are counted per validator.
+#[test]
+fn approval_participation() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1", "test2"].iter().map(|x| x.parse().unwrap()).collect();
+    let (mut chain, _, signers) = setup_with_validators(validators.clone(), 1, 1, 1000, 100);
+    let mut prev_block = chain.genesis_block().clone();
+    for height in 1..=4 {
+        let signer = &*signers[height % signers.len()];
+        let mut block = Block::empty(&prev_block, signer);
+        // The approval of test1 never makes it into a block, the one of test2 only every other
+        // block.
+        block.mut_header().get_mut().inner_rest.approvals = vec![
+            Some(Signature::empty(KeyType::ED25519)),
+            None,
+            (height % 2 == 0).then(|| Signature::empty(KeyType::ED25519)),
+        ];
+        block.mut_header().resign(signer);
+        chain.process_block_test(&None, block.clone()).unwrap();
+        prev_block = block;
+    }
+
+    let participation = chain.approval_participation(prev_block.header().epoch_id());
+    assert_eq!(participation.len(), 3);
+    assert_eq!(
+        participation[&validators[0]],
+        ApprovalParticipation { num_included: 4, num_missing: 0 }
+    );
+    assert_eq!(
+        participation[&validators[1]],
+        ApprovalParticipation { num_included: 0, num_missing: 4 }
+    );
+    assert_eq!(
+        participation[&validators[2]],
+        ApprovalParticipation { num_included: 2, num_missing: 2 }
+    );
+    assert!(chain.approval_participation(&EpochId(hash(&[1]))).is_empty());
+}
+
+/// The approvals included in the blocks produced by this node are recorded, and garbage
+/// collected with the block.
+#[test]
+fn approval_audit() {
+    init_test_logger();
+    let validators: Vec<AccountId> =
+        ["test0", "test1", "test2"].iter().map(|x| x.parse().unwrap()).collect();
+    let (mut chain, runtime, signers) = setup_with_validators(validators, 1, 1, 1000, 100);
+    let genesis = chain.genesis_block().clone();
+    let approvers = runtime.get_epoch_block_approvers_ordered(genesis.hash()).unwrap();
+    let with_approvals = |height: BlockHeight| {
+        let signer = &*signers[height as usize % signers.len()];
+        let mut block = Block::empty_with_height(&genesis, height, signer);
+        block.mut_header().get_mut().inner_rest.approvals =
+            vec![Some(Signature::empty(KeyType::ED25519)), None, None];
+        block.mut_header().resign(signer);
+        block
+    };
+
+    // Not recorded unless requested.
+    let block = with_approvals(1);
+    chain.process_block_test(&None, block.clone()).unwrap();
+    assert_eq!(chain.get_approval_audit(block.hash()).unwrap(), None);
+
+    chain.record_approval_audit = true;
+    let fork = with_approvals(2);
+    chain.process_block_test(&None, fork.clone()).unwrap();
+    let record = chain.get_approval_audit(fork.hash()).unwrap().unwrap();
+    assert_eq!(record.approval_inner, ApprovalInner::Skip(0));
+    assert_eq!(record.approvals, vec![approvers[0].0.clone()]);
+    let total_stake = approvers.iter().map(|(stake, _)| stake.stake_this_epoch).sum::<u128>();
+    assert_eq!(record.total_stake_this_epoch, total_stake);
+
+    // Only the blocks produced by this node are recorded.
+    let received = Block::empty(&block, &*signers[2]);
+    chain
+        .process_block(
+            &None,
+            MaybeValidated::from(received.clone()),
+            Provenance::NONE,
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+        )
+        .unwrap();
+    assert_eq!(chain.get_approval_audit(received.hash()).unwrap(), None);
+
+    let produced = Block::empty(&received, &*signers[0]);
+    chain.process_block_test(&None, produced.clone()).unwrap();
+    let record = chain.get_approval_audit(produced.hash()).unwrap().unwrap();
+    assert_eq!(record.approval_inner, ApprovalInner::Endorsement(*received.hash()));
+
+    let mut store_update = chain.mut_store().store_update();
+    store_update
+        .clear_block_data(&*runtime, *fork.hash(), GCMode::Fork(runtime.get_tries()))
+        .unwrap();
+    store_update.commit().unwrap();
+    assert_eq!(chain.get_approval_audit(fork.hash()).unwrap(), None);
+    assert!(chain.get_approval_audit(produced.hash()).unwrap().is_some());
+}
+
+/// The chain parameters view reflects the genesis the chain was built from, with the max gas
+/// price capped according to the protocol version.
+#[test]
+fn chain_parameters() {
+    let (chain, _, _) = setup_with_tx_validity_period(7);
+    let parameters = chain.chain_parameters().unwrap();
+    assert_eq!(parameters.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(parameters.transaction_validity_period, 7);
+    assert_eq!(parameters.epoch_length, 1000);
+    assert_eq!(parameters.min_gas_price, 100);
+    // Capped at 20 times the min gas price instead of the 10^9 in the genesis.
+    assert_eq!(parameters.max_gas_price, 2000);
+    assert_eq!(parameters.gas_price_adjustment_rate, Rational::from_integer(0));
+    assert_eq!(parameters.num_shards, 1);
+    assert_eq!(parameters.shard_layout, ShardLayout::v0(1, 0));
+    assert_eq!(parameters.gc_num_epochs_to_keep, NUM_EPOCHS_TO_KEEP_STORE_DATA);
+}
+
+#[derive(Debug, PartialEq)]
+enum RecordedEvent {
+    ChunkApplied { block_hash: CryptoHash, with_outcomes: bool },
+    Reorg { old_head: CryptoHash, new_head: CryptoHash },
+    BlockAccepted(CryptoHash, BlockStatus),
+}
+
+struct RecordingEventHandler(Arc<Mutex<Vec<RecordedEvent>>>);
+
+impl ChainEventHandler for RecordingEventHandler {
+    fn on_block_accepted(&mut self, event: BlockAcceptedEvent) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(RecordedEvent::BlockAccepted(*event.block.hash(), event.status));
+    }
+
+    fn on_reorg(&mut self, event: ReorgEvent) {
+        self.0.lock().unwrap().push(RecordedEvent::Reorg {
+            old_head: event.old_head.last_block_hash,
+            new_head: event.new_head.last_block_hash,
+        });
+    }
+
+    fn on_chunk_applied(&mut self, event: ChunkAppliedEvent) {
+        assert!(event.state_changes.is_none());
+        self.0.lock().unwrap().push(RecordedEvent::ChunkApplied {
+            block_hash: event.block_hash,
+            with_outcomes: event.outcomes.is_some(),
+        });
+    }
+}
+
+/// The event handlers see the chunks applied by each block, then the switch to another fork, and
+/// then the block, with the outcomes only for the handler subscribed to them.
+#[test]
+fn chain_events_with_reorg() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let me = Some("test".parse().unwrap());
+    let events = Arc::new(Mutex::new(vec![]));
+    let events_with_outcomes = Arc::new(Mutex::new(vec![]));
+    chain.register_event_handler(
+        Box::new(RecordingEventHandler(events.clone())),
+        ChainEventSubscription::default(),
+    );
+    chain.register_event_handler(
+        Box::new(RecordingEventHandler(events_with_outcomes.clone())),
+        ChainEventSubscription { outcomes: true, state_changes: false },
+    );
+
+    let genesis = chain.genesis_block().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    let b2 = Block::empty(&b1, &*signer);
+    // Switches the head to another fork.
+    let b3 = Block::empty_with_height(&b1, 3, &*signer);
+    // Doesn't overtake the head.
+    let b4 = Block::empty_with_height(&b2, 3, &*signer);
+    for block in [&b1, &b2, &b3, &b4] {
+        chain.process_block_test(&me, block.clone()).unwrap();
+    }
+    assert_eq!(chain.head().unwrap().last_block_hash, *b3.hash());
+
+    for (events, with_outcomes) in [(events, false), (events_with_outcomes, true)] {
+        let chunk_applied = |block: &Block| RecordedEvent::ChunkApplied {
+            block_hash: *block.hash(),
+            with_outcomes,
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                chunk_applied(&b1),
+                RecordedEvent::BlockAccepted(*b1.hash(), BlockStatus::Next),
+                chunk_applied(&b2),
+                RecordedEvent::BlockAccepted(*b2.hash(), BlockStatus::Next),
+                chunk_applied(&b3),
+                RecordedEvent::Reorg { old_head: *b2.hash(), new_head: *b3.hash() },
+                RecordedEvent::BlockAccepted(*b3.hash(), BlockStatus::Reorg(*b2.hash())),
+                chunk_applied(&b4),
+                RecordedEvent::BlockAccepted(*b4.hash(), BlockStatus::Fork),
+            ]
+        );
+    }
+}
+
+/// Blocks slightly ahead of the local clock are held back until the clock catches up with them,
+/// while blocks beyond the grace period are still rejected.
+#[cfg(not(feature = "sandbox"))]
+#[test]
+fn time_delayed_blocks() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.genesis_block().clone();
+    let now = chrono::Utc::now();
+    let block_at = |seconds_ahead| {
+        let mut block = Block::empty(&genesis, &*signer);
+        block.mut_header().get_mut().inner_lite.timestamp =
+            to_timestamp(now + chrono::Duration::seconds(seconds_ahead));
+        block.mut_header().resign(&*signer);
+        block
+    };
+    // The acceptable time difference is 120s and the grace period on top of it is 60s.
+    let far_block = block_at(200);
+    let near_block = block_at(150);
+
+    let mock_clock_guard = MockClockGuard::default();
+    for _ in 0..100 {
+        mock_clock_guard.add_instant(Instant::now());
+    }
+    // Validating the header and recording the rejected block.
+    mock_clock_guard.add_utc(now);
+    mock_clock_guard.add_utc(now);
+    let err = chain.process_block_test(&None, far_block).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidBlockFutureTime(_)));
+    assert!(!chain.has_time_delayed_blocks());
+
+    mock_clock_guard.add_utc(now);
+    let err = chain.process_block_test(&None, near_block.clone()).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::BlockAheadOfClock(_)));
+    assert!(chain.has_time_delayed_blocks());
+    assert_eq!(chain.head().unwrap().height, 0);
+
+    // The clock hasn't caught up with the block yet.
+    mock_clock_guard.add_utc(now + chrono::Duration::seconds(10));
+    assert_eq!(
+        chain.retry_time_delayed_blocks(&None, &mut |_| {}, &mut |_| {}, &mut |_| {}, &mut |_| {}),
+        1
+    );
+    assert_eq!(chain.head().unwrap().height, 0);
+
+    let mut accepted = vec![];
+    mock_clock_guard.add_utc(now + chrono::Duration::seconds(40));
+    mock_clock_guard.add_utc(now + chrono::Duration::seconds(40));
+    assert_eq!(
+        chain.retry_time_delayed_blocks(
+            &None,
+            &mut |accepted_block| accepted.push(accepted_block.hash),
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+        ),
+        0
+    );
+    assert_eq!(accepted, vec![*near_block.hash()]);
+    assert!(!chain.has_time_delayed_blocks());
+    assert_eq!(chain.head().unwrap().last_block_hash, *near_block.hash());
+}
+
+/// An invalid block submitted again is rejected from the cache of invalid blocks without
+/// touching the store, while transient rejections and forged signatures are not cached.
+#[test]
+fn test_invalid_block_rejected_from_cache() {
+    let db = Arc::new(CountingDB::new());
+    let runtime_adapter = Arc::new(KeyValueRuntime::new_with_validators(
+        Store::new(db.clone()),
+        vec![vec!["test1".parse().unwrap()]],
+        1,
+        1,
+        10,
+    ));
+    let mut chain =
+        Chain::new(runtime_adapter, &ChainGenesis::test(), DoomslugThresholdMode::NoApprovals)
+            .unwrap();
+    let genesis = chain.genesis_block().clone();
+    let signer =
+        InMemoryValidatorSigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+    let mut bad_randomness = Block::empty_with_height(&genesis, 2, &signer);
+    bad_randomness.mut_header().get_mut().inner_rest.random_value = CryptoHash::default();
+    bad_randomness.mut_header().resign(&signer);
+
+    let error = ErrorKind::InvalidRandomnessBeaconOutput;
+    assert_eq!(chain.process_block_test(&None, bad_randomness.clone()).unwrap_err().kind(), error);
+    assert_eq!(chain.is_block_invalid(bad_randomness.hash()), Some(error.clone()));
+    // Peers relaying the block could have checked its header too.
+    assert_eq!(chain.is_block_invalid_before_apply(bad_randomness.hash()), Some(error.clone()));
+    db.take_reads();
+    db.take_writes();
+    assert_eq!(chain.process_block_test(&None, bad_randomness.clone()).unwrap_err().kind(), error);
+    assert_eq!(db.take_reads(), 0);
+    assert_eq!(db.take_writes(), 0);
+
+    let block = Block::empty(&genesis, &signer);
+    let orphan = Block::empty(&block, &signer);
+    assert_eq!(
+        chain.process_block_test(&None, orphan.clone()).unwrap_err().kind(),
+        ErrorKind::Orphan
+    );
+    assert_eq!(chain.is_block_invalid(orphan.hash()), None);
+
+    // The signature isn't covered by the hash, so the forged block shares it with the genuine one.
+    let other_signer =
+        InMemoryValidatorSigner::from_seed("test2".parse().unwrap(), KeyType::ED25519, "test2");
+    let mut forged = block.clone();
+    forged.mut_header().resign(&other_signer);
+    assert_eq!(forged.hash(), block.hash());
+    assert_eq!(
+        chain.process_block_test(&None, forged).unwrap_err().kind(),
+        ErrorKind::InvalidSignature
+    );
+    assert_eq!(chain.is_block_invalid(block.hash()), None);
+    chain.process_block_test(&None, block).unwrap();
+}
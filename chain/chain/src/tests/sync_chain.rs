@@ -1,7 +1,12 @@
+use crate::chain::{SyncPhase, SyncProgressInputs};
 use crate::test_utils::setup;
-use crate::Block;
+use crate::{Block, ErrorKind, RuntimeAdapter};
+use near_crypto::KeyType;
 use near_logger_utils::init_test_logger;
+use near_primitives::block::ApprovalInner;
 use near_primitives::merkle::PartialMerkleTree;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+use std::time::Duration;
 
 #[test]
 fn chain_sync_headers() {
@@ -25,3 +30,248 @@ fn chain_sync_headers() {
         .unwrap();
     assert_eq!(chain.header_head().unwrap().height, 4);
 }
+
+#[test]
+fn chain_sync_headers_invalid_header_in_batch() {
+    init_test_logger();
+    let (mut chain, _, bls_signer) = setup();
+    let other_signer =
+        InMemoryValidatorSigner::from_seed("other".parse().unwrap(), KeyType::ED25519, "other");
+    let mut blocks = vec![chain.get_block(&chain.genesis().hash().clone()).unwrap().clone()];
+    let mut block_merkle_tree = PartialMerkleTree::default();
+    for i in 0..8 {
+        // The block at height 5 is not signed by its block producer.
+        let signer: &dyn ValidatorSigner = if i == 4 { &other_signer } else { &*bls_signer };
+        blocks.push(Block::empty_with_block_merkle_tree(
+            &blocks[i],
+            signer,
+            &mut block_merkle_tree,
+        ));
+    }
+    let err = chain
+        .sync_block_headers(
+            blocks[1..].iter().map(|block| block.header().clone()).collect(),
+            &mut |_| panic!("Unexpected"),
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidSignature);
+    assert!(err.to_string().contains(&format!("{} at height 5", blocks[5].hash())));
+
+    // The headers validated before the invalid one are saved.
+    for block in &blocks[1..5] {
+        assert!(chain.get_block_header(block.hash()).is_ok());
+    }
+    for block in &blocks[5..] {
+        assert!(chain.get_block_header(block.hash()).is_err());
+    }
+}
+
+/// Blocks on top of `genesis` whose prev block is endorsed by the approval of `signer`, except
+/// for the block at `invalid_height`, whose approval is signed by another validator.
+fn blocks_with_approvals(
+    genesis: &Block,
+    signer: &dyn ValidatorSigner,
+    num_blocks: usize,
+    invalid_height: Option<u64>,
+) -> Vec<Block> {
+    let other_signer =
+        InMemoryValidatorSigner::from_seed("other".parse().unwrap(), KeyType::ED25519, "other");
+    let mut blocks = vec![genesis.clone()];
+    let mut block_merkle_tree = PartialMerkleTree::default();
+    for i in 0..num_blocks {
+        let prev = &blocks[i];
+        let mut block = Block::empty_with_block_merkle_tree(prev, signer, &mut block_merkle_tree);
+        let height = block.header().height();
+        let approver: &dyn ValidatorSigner =
+            if Some(height) == invalid_height { &other_signer } else { signer };
+        let approval = approver.sign_approval(&ApprovalInner::Endorsement(*prev.hash()), height);
+        block.mut_header().get_mut().inner_rest.approvals = vec![Some(approval)];
+        block.mut_header().resign(signer);
+        blocks.push(block);
+    }
+    blocks.remove(0);
+    blocks
+}
+
+/// The approvals of the headers of a batch are verified with one fetch of the approvers per
+/// epoch, with the same result as verifying them one by one.
+#[test]
+fn chain_sync_headers_batch_approvals() {
+    init_test_logger();
+    let (mut chain, runtime, signer) = setup();
+    runtime.set_verify_approval_signatures(true);
+    let genesis = chain.genesis_block().clone();
+    let blocks = blocks_with_approvals(&genesis, &*signer, 200, None);
+    chain
+        .sync_block_headers(
+            blocks.iter().map(|block| block.header().clone()).collect(),
+            &mut |_| panic!("Unexpected"),
+        )
+        .unwrap();
+    assert_eq!(chain.header_head().unwrap().height, 200);
+    assert_eq!(runtime.num_approvers_fetches(), 1);
+
+    let (mut chain, runtime, signer) = setup();
+    runtime.set_verify_approval_signatures(true);
+    let genesis = chain.genesis_block().clone();
+    let blocks = blocks_with_approvals(&genesis, &*signer, 200, Some(120));
+    let err = chain
+        .sync_block_headers(
+            blocks.iter().map(|block| block.header().clone()).collect(),
+            &mut |_| panic!("Unexpected"),
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidApprovals);
+    assert!(err.to_string().contains(&format!("{} at height 120", blocks[119].hash())));
+
+    // Only the headers before the one with invalid approvals are saved, and verifying the
+    // approvals one by one gives the same result.
+    for (i, block) in blocks.iter().enumerate() {
+        assert_eq!(chain.get_block_header(block.hash()).is_ok(), i < 119);
+    }
+    for (i, block) in blocks[..120].iter().enumerate() {
+        let prev_header = if i == 0 { genesis.header() } else { blocks[i - 1].header() };
+        let header = block.header();
+        let verified = runtime
+            .verify_approval(
+                prev_header.hash(),
+                prev_header.height(),
+                header.height(),
+                header.approvals(),
+            )
+            .unwrap();
+        assert_eq!(verified, i < 119);
+    }
+}
+
+/// A node 2000 blocks behind the network, downloading 100 headers and applying 10 blocks per
+/// second.
+fn sync_progress_inputs() -> SyncProgressInputs {
+    SyncProgressInputs {
+        genesis_height: 1000,
+        head_height: 1000,
+        header_head_height: 1000,
+        network_height: 3000,
+        headers_per_second: 100,
+        blocks_applied_per_second: Some(10.),
+        state_parts_downloaded: 0,
+        state_parts_total: 0,
+    }
+}
+
+#[test]
+fn sync_progress_per_phase() {
+    let estimate =
+        SyncProgressInputs { header_head_height: 1500, ..sync_progress_inputs() }.estimate();
+    assert_eq!(estimate.phase, SyncPhase::HeaderSync);
+    assert_eq!(estimate.percent, 25.);
+    // 1500 headers left to download, then 2000 blocks to apply.
+    assert_eq!(estimate.eta, Some(Duration::from_secs(15 + 200)));
+
+    let estimate = SyncProgressInputs {
+        header_head_height: 3000,
+        state_parts_downloaded: 3,
+        state_parts_total: 4,
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::StateSync);
+    assert_eq!(estimate.percent, 75.);
+    assert_eq!(estimate.eta, None);
+
+    let estimate = SyncProgressInputs {
+        head_height: 2000,
+        header_head_height: 3000,
+        state_parts_downloaded: 4,
+        state_parts_total: 4,
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::BlockSync);
+    assert_eq!(estimate.percent, 50.);
+    assert_eq!(estimate.eta, Some(Duration::from_secs(100)));
+
+    // Headers can be ahead of the peers known to the node.
+    let estimate = SyncProgressInputs {
+        head_height: 2600,
+        header_head_height: 3500,
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::BlockSync);
+    assert_eq!(estimate.percent, 64.);
+    assert_eq!(estimate.eta, Some(Duration::from_secs(90)));
+
+    let estimate = SyncProgressInputs {
+        head_height: 3000,
+        header_head_height: 3000,
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::Synced);
+    assert_eq!(estimate.percent, 100.);
+    assert_eq!(estimate.eta, Some(Duration::from_secs(0)));
+}
+
+/// Unknown or zero throughput leaves the ETA unknown, and heights at or below genesis don't
+/// break the percentage.
+#[test]
+fn sync_progress_missing_data() {
+    let estimate =
+        SyncProgressInputs { blocks_applied_per_second: None, ..sync_progress_inputs() }.estimate();
+    assert_eq!(estimate.phase, SyncPhase::HeaderSync);
+    assert_eq!(estimate.percent, 0.);
+    assert_eq!(estimate.eta, None);
+
+    let estimate =
+        SyncProgressInputs { headers_per_second: 0, ..sync_progress_inputs() }.estimate();
+    assert_eq!(estimate.eta, None);
+
+    let estimate = SyncProgressInputs {
+        header_head_height: 3000,
+        blocks_applied_per_second: Some(0.),
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::BlockSync);
+    assert_eq!(estimate.eta, None);
+
+    // No peers yet.
+    let estimate = SyncProgressInputs {
+        network_height: 0,
+        blocks_applied_per_second: None,
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::Synced);
+    assert_eq!(estimate.percent, 100.);
+
+    let estimate = SyncProgressInputs { network_height: 900, ..sync_progress_inputs() }.estimate();
+    assert_eq!(estimate.phase, SyncPhase::Synced);
+
+    let estimate = SyncProgressInputs {
+        genesis_height: 3000,
+        head_height: 0,
+        header_head_height: 0,
+        ..sync_progress_inputs()
+    }
+    .estimate();
+    assert_eq!(estimate.phase, SyncPhase::HeaderSync);
+    assert_eq!(estimate.percent, 100.);
+}
+
+#[test]
+fn chain_sync_progress() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let mut prev = chain.genesis_block().clone();
+    for _ in 0..5 {
+        let block = Block::empty(&prev, &*signer);
+        chain.process_block_test(&None, block.clone()).unwrap();
+        prev = block;
+    }
+    assert_eq!(chain.estimate_sync_progress(5).unwrap().phase, SyncPhase::Synced);
+    let estimate = chain.estimate_sync_progress(20).unwrap();
+    assert_eq!(estimate.phase, SyncPhase::HeaderSync);
+    assert_eq!(estimate.percent, 25.);
+}
@@ -1,22 +1,30 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::time::Duration;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use chrono::DateTime;
 use near_primitives::time::Utc;
 use num_rational::Rational;
+use sha2::Digest;
 
-use near_chain_configs::{GenesisConfig, ProtocolConfig};
-use near_chain_primitives::Error;
+use near_chain_configs::{
+    ClientConfig, EconomicsOverrides, GenesisConfig, ProtocolConfig,
+    DEFAULT_MAX_BLOCK_SERIALIZED_BYTES, DEFAULT_MAX_CHUNK_TRANSACTIONS,
+};
+use near_chain_primitives::error::GenesisInconsistency;
+use near_chain_primitives::{Error, ErrorKind};
 use near_crypto::Signature;
 use near_pool::types::PoolIterator;
 pub use near_primitives::block::{Block, BlockHeader, Tip};
+use near_primitives::block_header::ApprovalInner;
 use near_primitives::challenge::{ChallengesResult, SlashedValidator};
 use near_primitives::checked_feature;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::errors::{EpochError, InvalidTxError};
-use near_primitives::hash::CryptoHash;
+use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
@@ -25,7 +33,8 @@ use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta, EpochHeight, EpochId, Gas,
-    MerkleHash, NumBlocks, ShardId, StateChangesForSplitStates, StateRoot, StateRootNode,
+    MerkleHash, NumBlocks, NumShards, ShardId, StateChangesForSplitStates, StateRoot,
+    StateRootNode,
 };
 use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
@@ -34,7 +43,7 @@ use near_primitives::version::{
 use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
 use near_store::{PartialStorage, ShardTries, Store, StoreUpdate, Trie, WrappedTrieChanges};
 
-use crate::DoomslugThresholdMode;
+use crate::{Doomslug, DoomslugThresholdMode};
 use near_primitives::epoch_manager::ShardConfig;
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::state_record::StateRecord;
@@ -71,12 +80,26 @@ pub enum Provenance {
     PRODUCED,
 }
 
+/// How `Chain::save_block` accounts for the saved block in the refcount of its previous block.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum BlockSaveMode {
+    /// The previous block of the sync block, saved during state sync. Its own previous block is
+    /// never downloaded, so no refcount is increased and the block is marked instead, letting
+    /// garbage collection and the store validator account for the missing reference.
+    StateSyncBoundary,
+    /// Increases the refcount of the previous block, like processing the block does.
+    Normal,
+}
+
 /// Information about processed block.
 #[derive(Debug, Clone)]
 pub struct AcceptedBlock {
     pub hash: CryptoHash,
     pub status: BlockStatus,
     pub provenance: Provenance,
+    /// Shard whose chunk took the longest to apply, with the time it took. `None` if no chunks
+    /// were applied for the block.
+    pub slowest_shard: Option<(ShardId, Duration)>,
 }
 
 pub struct ApplySplitStateResult {
@@ -157,19 +180,59 @@ impl BlockHeaderInfo {
     }
 }
 
-/// Block economics config taken from genesis config
+/// Approvals of a block, verified with `RuntimeAdapter::verify_approvals_and_threshold_batch`.
+#[derive(Clone, Debug)]
+pub struct BlockApprovals {
+    pub prev_block_hash: CryptoHash,
+    pub prev_block_height: BlockHeight,
+    pub block_height: BlockHeight,
+    pub epoch_id: EpochId,
+    pub next_epoch_id: EpochId,
+    pub approvals: Vec<Option<Signature>>,
+}
+
+impl BlockApprovals {
+    pub fn new(header: &BlockHeader, prev_header: &BlockHeader) -> Self {
+        Self {
+            prev_block_hash: *prev_header.hash(),
+            prev_block_height: prev_header.height(),
+            block_height: header.height(),
+            epoch_id: header.epoch_id().clone(),
+            next_epoch_id: header.next_epoch_id().clone(),
+            approvals: header.approvals().to_vec(),
+        }
+    }
+}
+
+/// Block economics config taken from genesis config, with optional node-configured overrides
+/// applying from a given height on.
 pub struct BlockEconomicsConfig {
     gas_price_adjustment_rate: Rational,
     min_gas_price: Balance,
     max_gas_price: Balance,
     genesis_protocol_version: ProtocolVersion,
+    overrides: Option<EconomicsOverrides>,
 }
 
 impl BlockEconomicsConfig {
     /// Set max gas price to be this multiplier * min_gas_price
     const MAX_GAS_MULTIPLIER: u128 = 20;
-    /// Compute min gas price according to protocol version and genesis protocol version.
-    pub fn min_gas_price(&self, protocol_version: ProtocolVersion) -> Balance {
+
+    pub fn set_overrides(&mut self, overrides: Option<EconomicsOverrides>) {
+        self.overrides = overrides;
+    }
+
+    /// Overrides in effect for the block at the given height, if any.
+    fn active_overrides(&self, height: BlockHeight) -> Option<&EconomicsOverrides> {
+        self.overrides.as_ref().filter(|overrides| height >= overrides.activation_height)
+    }
+
+    /// Compute min gas price of the block at the given height according to protocol version and
+    /// genesis protocol version.
+    pub fn min_gas_price(&self, protocol_version: ProtocolVersion, height: BlockHeight) -> Balance {
+        if let Some(overrides) = self.active_overrides(height) {
+            return overrides.min_gas_price;
+        }
         if self.genesis_protocol_version < MIN_PROTOCOL_VERSION_NEP_92 {
             if protocol_version >= MIN_PROTOCOL_VERSION_NEP_92_FIX {
                 MIN_GAS_PRICE_NEP_92_FIX
@@ -189,19 +252,30 @@ impl BlockEconomicsConfig {
         }
     }
 
-    pub fn max_gas_price(&self, protocol_version: ProtocolVersion) -> Balance {
+    pub fn max_gas_price(&self, protocol_version: ProtocolVersion, height: BlockHeight) -> Balance {
+        let max_gas_price = match self.active_overrides(height) {
+            Some(overrides) => overrides.max_gas_price,
+            None => self.max_gas_price,
+        };
         if checked_feature!("stable", CapMaxGasPrice, protocol_version) {
             std::cmp::min(
-                self.max_gas_price,
-                Self::MAX_GAS_MULTIPLIER * self.min_gas_price(protocol_version),
+                max_gas_price,
+                Self::MAX_GAS_MULTIPLIER * self.min_gas_price(protocol_version, height),
             )
         } else {
-            self.max_gas_price
+            max_gas_price
         }
     }
 
-    pub fn gas_price_adjustment_rate(&self, _protocol_version: ProtocolVersion) -> Rational {
-        self.gas_price_adjustment_rate
+    pub fn gas_price_adjustment_rate(
+        &self,
+        _protocol_version: ProtocolVersion,
+        height: BlockHeight,
+    ) -> Rational {
+        match self.active_overrides(height) {
+            Some(overrides) => overrides.gas_price_adjustment_rate,
+            None => self.gas_price_adjustment_rate,
+        }
     }
 }
 
@@ -212,12 +286,83 @@ impl From<&ChainGenesis> for BlockEconomicsConfig {
             min_gas_price: chain_genesis.min_gas_price,
             max_gas_price: chain_genesis.max_gas_price,
             genesis_protocol_version: chain_genesis.protocol_version,
+            overrides: None,
+        }
+    }
+}
+
+/// Node-local limits on blocks and chunks, checked before expensive processing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockLimitsConfig {
+    /// Maximum size of a borsh-serialized block.
+    pub max_block_serialized_bytes: usize,
+    /// Maximum number of transactions in a single chunk.
+    pub max_chunk_transactions: usize,
+}
+
+impl Default for BlockLimitsConfig {
+    fn default() -> Self {
+        BlockLimitsConfig {
+            max_block_serialized_bytes: DEFAULT_MAX_BLOCK_SERIALIZED_BYTES,
+            max_chunk_transactions: DEFAULT_MAX_CHUNK_TRANSACTIONS,
+        }
+    }
+}
+
+impl From<&ClientConfig> for BlockLimitsConfig {
+    fn from(config: &ClientConfig) -> Self {
+        BlockLimitsConfig {
+            max_block_serialized_bytes: config.max_block_serialized_bytes,
+            max_chunk_transactions: config.max_chunk_transactions,
+        }
+    }
+}
+
+/// What to do with outgoing receipts roots which don't match the receipts produced locally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiptsRootCheckMode {
+    /// Rely on the regular chunk validation only.
+    Disabled,
+    /// Log and count the mismatches found by the regular chunk validation.
+    LogMismatch,
+    /// Log and count the mismatches, and reject the block with
+    /// `ErrorKind::OutgoingReceiptsRootMismatch` instead of a chunk state challenge.
+    FailOnMismatch,
+}
+
+impl Default for ReceiptsRootCheckMode {
+    fn default() -> Self {
+        ReceiptsRootCheckMode::Disabled
+    }
+}
+
+impl From<&ClientConfig> for ReceiptsRootCheckMode {
+    fn from(config: &ClientConfig) -> Self {
+        match (
+            config.strict_receipt_root_checks,
+            config.strict_receipt_root_checks_fail_on_mismatch,
+        ) {
+            (false, _) => ReceiptsRootCheckMode::Disabled,
+            (true, false) => ReceiptsRootCheckMode::LogMismatch,
+            (true, true) => ReceiptsRootCheckMode::FailOnMismatch,
         }
     }
 }
 
+/// How strictly the blocks processed by a `ChainUpdate` are validated, see the fields of `Chain`
+/// with the same names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockValidationConfig {
+    pub block_limits_config: BlockLimitsConfig,
+    pub receipts_root_check_mode: ReceiptsRootCheckMode,
+    /// Skip most of the chunk validation, see `Chain::enable_trusted_chain_replay`.
+    pub trusted_chain_replay: bool,
+    /// Verify the merkle proofs of incoming receipts before saving them.
+    pub verify_incoming_receipts: bool,
+}
+
 /// Chain genesis configuration.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct ChainGenesis {
     pub time: DateTime<Utc>,
     pub height: BlockHeight,
@@ -252,6 +397,182 @@ where
     }
 }
 
+impl ChainGenesis {
+    /// Checks that the genesis block built from this config agrees with the genesis config of
+    /// `runtime_adapter`, listing every mismatch.
+    pub fn check_consistency(&self, runtime_adapter: &dyn RuntimeAdapter) -> Result<(), Error> {
+        let genesis_config =
+            runtime_adapter.get_protocol_config(&EpochId::default())?.genesis_config;
+        let mut inconsistencies = vec![];
+        if self.height != genesis_config.genesis_height {
+            inconsistencies.push(GenesisInconsistency::Height {
+                chain_genesis: self.height,
+                runtime: genesis_config.genesis_height,
+            });
+        }
+        if self.gas_limit != genesis_config.gas_limit {
+            inconsistencies.push(GenesisInconsistency::GasLimit {
+                chain_genesis: self.gas_limit,
+                runtime: genesis_config.gas_limit,
+            });
+        }
+        if self.protocol_version != genesis_config.protocol_version {
+            inconsistencies.push(GenesisInconsistency::ProtocolVersion {
+                chain_genesis: self.protocol_version,
+                runtime: genesis_config.protocol_version,
+            });
+        }
+        if inconsistencies.is_empty() {
+            Ok(())
+        } else {
+            Err(ErrorKind::InconsistentGenesis(inconsistencies).into())
+        }
+    }
+}
+
+/// Which shards the client cares about in the epoch of the block after some `parent_hash` and in
+/// the next epoch. Both are indexed by the shard ids of the current epoch, see
+/// `RuntimeAdapter::will_care_about_shard`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardCareMatrix {
+    pub this_epoch: Vec<bool>,
+    pub next_epoch: Vec<bool>,
+}
+
+impl ShardCareMatrix {
+    pub fn num_shards(&self) -> NumShards {
+        self.this_epoch.len() as NumShards
+    }
+
+    pub fn cares_about_shard(&self, shard_id: ShardId) -> bool {
+        self.this_epoch.get(shard_id as usize).copied().unwrap_or(false)
+    }
+
+    pub fn will_care_about_shard(&self, shard_id: ShardId) -> bool {
+        self.next_epoch.get(shard_id as usize).copied().unwrap_or(false)
+    }
+
+    pub fn cares_about_any_shard(&self) -> bool {
+        self.this_epoch.iter().chain(self.next_epoch.iter()).any(|cares| *cares)
+    }
+}
+
+/// Chunks which have to be available locally before a block can be processed, see
+/// `Chain::required_chunks_for_block`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequiredChunks {
+    /// New chunks of the shards tracked in this or the next epoch, whose full bodies are needed.
+    pub need_full: Vec<ShardChunkHeader>,
+    /// New chunks of the other shards, for which the partial encoded chunks are enough.
+    pub need_partial: Vec<ShardChunkHeader>,
+}
+
+/// Size of the pieces a streamed state part is written out in.
+pub const STATE_PART_STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// State part generated as it's written out, so that the data it's made of doesn't have to be
+/// held in memory next to it.
+pub struct StatePartStream {
+    len: usize,
+    reader: Box<dyn Read + Send>,
+}
+
+impl StatePartStream {
+    /// `reader` has to yield exactly `len` bytes.
+    pub fn new(len: usize, reader: Box<dyn Read + Send>) -> Self {
+        Self { len, reader }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Writes the part out in pieces of `STATE_PART_STREAM_CHUNK_SIZE` and returns its hash,
+    /// computed along the way.
+    pub fn write_to(mut self, writer: &mut dyn Write) -> io::Result<CryptoHash> {
+        let mut hasher = sha2::Sha256::default();
+        let mut buf = vec![0; STATE_PART_STREAM_CHUNK_SIZE];
+        let mut written = 0;
+        loop {
+            let n = match self.reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            hasher.update(&buf[..n]);
+            writer.write_all(&buf[..n])?;
+            written += n;
+        }
+        if written != self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("state part has {} bytes instead of {}", written, self.len),
+            ));
+        }
+        Ok(CryptoHash(hasher.finalize().into()))
+    }
+}
+
+/// Called with a streamed state part and its hash once the part is written out, to save it for
+/// later requests.
+pub type StatePartCacheSink = Box<dyn FnOnce(&[u8], &CryptoHash) -> io::Result<()> + Send>;
+
+/// State part served to a peer, see `Chain::get_state_response_part`.
+pub enum StatePartPayload {
+    /// Part held in memory, e.g. read from the store.
+    Owned(Vec<u8>),
+    /// Part generated as it's written out, saved afterwards by the sink if there is one.
+    Stream(StatePartStream, Option<StatePartCacheSink>),
+}
+
+impl StatePartPayload {
+    pub fn len(&self) -> usize {
+        match self {
+            StatePartPayload::Owned(part) => part.len(),
+            StatePartPayload::Stream(stream, _) => stream.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the part to `out`, in pieces of `STATE_PART_STREAM_CHUNK_SIZE` if it's streamed,
+    /// and returns its hash. A streamed part is saved from `out`, so that it's never copied.
+    pub fn write_to(self, out: &mut Vec<u8>) -> io::Result<CryptoHash> {
+        match self {
+            StatePartPayload::Owned(part) => {
+                out.extend_from_slice(&part);
+                Ok(hash(&part))
+            }
+            StatePartPayload::Stream(stream, cache_sink) => {
+                let start = out.len();
+                let part_hash = stream.write_to(out)?;
+                if let Some(cache_sink) = cache_sink {
+                    cache_sink(&out[start..], &part_hash)?;
+                }
+                Ok(part_hash)
+            }
+        }
+    }
+
+    pub fn into_vec(self) -> io::Result<Vec<u8>> {
+        match self {
+            StatePartPayload::Owned(part) => Ok(part),
+            payload => {
+                let mut part = Vec::with_capacity(payload.len());
+                payload.write_to(&mut part)?;
+                Ok(part)
+            }
+        }
+    }
+}
+
 /// Bridge between the chain and the runtime.
 /// Main function is to update state given transactions.
 /// Additionally handles validators.
@@ -387,6 +708,38 @@ pub trait RuntimeAdapter: Send + Sync {
         approvals: &[Option<Signature>],
     ) -> Result<bool, Error>;
 
+    /// Verify aggregated bls signatures of a batch of blocks and check that they reach the
+    /// approval threshold. Returns for each block `ErrorKind::InvalidApprovals` if
+    /// `verify_approval` fails, `ErrorKind::NotEnoughApprovals` if the threshold isn't reached.
+    fn verify_approvals_and_threshold_batch(
+        &self,
+        doomslug_threshold_mode: DoomslugThresholdMode,
+        blocks: &[BlockApprovals],
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        blocks
+            .iter()
+            .map(|block| {
+                if !self.verify_approval(
+                    &block.prev_block_hash,
+                    block.prev_block_height,
+                    block.block_height,
+                    &block.approvals,
+                )? {
+                    return Ok(Err(ErrorKind::InvalidApprovals.into()));
+                }
+                let approvers = self.get_epoch_block_approvers_ordered(&block.prev_block_hash)?;
+                if !Doomslug::can_block_be_produced_with_approvers(
+                    doomslug_threshold_mode,
+                    &block.approvals,
+                    &approvers,
+                ) {
+                    return Ok(Err(ErrorKind::NotEnoughApprovals.into()));
+                }
+                Ok(Ok(()))
+            })
+            .collect()
+    }
+
     /// Verify approvals and check threshold, but ignore next epoch approvals and slashing
     fn verify_approvals_and_threshold_orphan(
         &self,
@@ -510,6 +863,38 @@ pub trait RuntimeAdapter: Send + Sync {
         is_me: bool,
     ) -> bool;
 
+    /// Whether the client cares about each shard in this and in the next epoch, same as calling
+    /// `cares_about_shard` and `will_care_about_shard` with `is_me` for every shard.
+    /// Runtimes should override it to look up the epochs and validator assignments only once.
+    fn cares_about_shards(
+        &self,
+        me: Option<&AccountId>,
+        parent_hash: &CryptoHash,
+    ) -> Result<ShardCareMatrix, Error> {
+        let epoch_id = self.get_epoch_id_from_prev_block(parent_hash)?;
+        let shard_ids = 0..self.num_shards(&epoch_id)?;
+        Ok(ShardCareMatrix {
+            this_epoch: shard_ids
+                .clone()
+                .map(|shard_id| self.cares_about_shard(me, parent_hash, shard_id, true))
+                .collect(),
+            next_epoch: shard_ids
+                .map(|shard_id| self.will_care_about_shard(me, parent_hash, shard_id, true))
+                .collect(),
+        })
+    }
+
+    /// Warms up the state of `accounts` at `state_root` of the shard, so that the following
+    /// application of the chunk finds it in the trie cache. Does nothing by default.
+    fn prefetch_state_keys(
+        &self,
+        _shard_uid: ShardUId,
+        _state_root: StateRoot,
+        _accounts: Vec<AccountId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Returns true, if given hash is last block in it's epoch.
     fn is_next_block_epoch_start(&self, parent_hash: &CryptoHash) -> Result<bool, Error>;
 
@@ -690,6 +1075,19 @@ pub trait RuntimeAdapter: Send + Sync {
         part_id: PartId,
     ) -> Result<Vec<u8>, Error>;
 
+    /// Same as `obtain_state_part`, but the serialized part is produced as it's read, so that
+    /// it isn't held in memory next to the data it's made of.
+    fn obtain_state_part_stream(
+        &self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        state_root: &StateRoot,
+        part_id: PartId,
+    ) -> Result<StatePartStream, Error> {
+        let part = self.obtain_state_part(shard_id, block_hash, state_root, part_id)?;
+        Ok(StatePartStream::new(part.len(), Box::new(io::Cursor::new(part))))
+    }
+
     /// Validate state part that expected to be given state root with provided data.
     /// Returns false if the resulting part doesn't match the expected one.
     fn validate_state_part(&self, state_root: &StateRoot, part_id: PartId, data: &Vec<u8>) -> bool;
@@ -771,6 +1169,131 @@ pub struct LatestKnown {
     pub seen: u64,
 }
 
+/// Record of a shard whose local state is known to be corrupted. Chunks of a quarantined shard are
+/// not applied until its state is downloaded again at the start of a later epoch.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardQuarantine {
+    /// Why the state was quarantined.
+    pub reason: String,
+    /// Epoch of the head at the moment the shard was quarantined.
+    pub epoch_id: EpochId,
+    /// Head at the moment the shard was quarantined.
+    pub head_hash: CryptoHash,
+}
+
+/// Record of a fork block deleted by garbage collection, kept for post-incident analysis.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiscardedFork {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+    pub prev_hash: CryptoHash,
+    /// `None` if the block producer for the height couldn't be determined.
+    pub producer: Option<AccountId>,
+    /// Block timestamp in nanoseconds.
+    pub timestamp: u64,
+}
+
+/// Approvals included by this node in a block it produced, kept to audit which approvals made
+/// the block reach the doomslug threshold, see `Chain::get_approval_audit`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalAuditRecord {
+    /// The message the approvals sign: an endorsement of the previous block, or a skip from its
+    /// height.
+    pub approval_inner: ApprovalInner,
+    /// Approvers whose approvals are included, in the order of the approvals in the header.
+    pub approvals: Vec<ApprovalStake>,
+    /// Stake of all the approvers which aren't slashed, which the threshold is relative to.
+    pub total_stake_this_epoch: Balance,
+    pub total_stake_next_epoch: Balance,
+}
+
+impl ApprovalAuditRecord {
+    /// Record of the approvals of `header`, given its approvers ordered as its approvals.
+    pub fn new(
+        header: &BlockHeader,
+        prev_height: BlockHeight,
+        approvers: &[(ApprovalStake, bool)],
+    ) -> Self {
+        let mut record = ApprovalAuditRecord {
+            approval_inner: ApprovalInner::new(header.prev_hash(), prev_height, header.height()),
+            approvals: vec![],
+            total_stake_this_epoch: 0,
+            total_stake_next_epoch: 0,
+        };
+        for (index, (approver, is_slashed)) in approvers.iter().enumerate() {
+            if !*is_slashed {
+                record.total_stake_this_epoch += approver.stake_this_epoch;
+                record.total_stake_next_epoch += approver.stake_next_epoch;
+            }
+            if header.approvals().get(index).map_or(false, Option::is_some) {
+                record.approvals.push(approver.clone());
+            }
+        }
+        record
+    }
+}
+
+/// Totals of the blocks of an epoch, accumulated block by block as they are accepted.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochSummary {
+    pub epoch_height: EpochHeight,
+    pub blocks_produced: NumBlocks,
+    /// Number of chunks included for the first time in a block of the epoch, by shard id.
+    pub chunks_produced_per_shard: Vec<NumBlocks>,
+    /// Number of transactions in these chunks. Only the chunks of the shards tracked by this
+    /// node are counted.
+    pub total_txs: u64,
+    /// Gas used by applying these chunks.
+    pub total_gas_used: Gas,
+    pub first_block_hash: CryptoHash,
+    pub last_block_hash: CryptoHash,
+}
+
+impl EpochSummary {
+    /// Summary of an epoch before its first block.
+    pub fn new(epoch_height: EpochHeight, first_block_hash: CryptoHash) -> Self {
+        EpochSummary {
+            epoch_height,
+            blocks_produced: 0,
+            chunks_produced_per_shard: vec![],
+            total_txs: 0,
+            total_gas_used: 0,
+            first_block_hash,
+            last_block_hash: first_block_hash,
+        }
+    }
+
+    /// Adds the next block of the epoch, which has `num_txs` transactions in its new chunks and
+    /// used `gas_used` to apply them.
+    pub fn add_block(&mut self, block: &Block, num_txs: u64, gas_used: Gas) {
+        let height = block.header().height();
+        self.blocks_produced += 1;
+        for chunk in block.chunks().iter().filter(|chunk| chunk.height_included() == height) {
+            let shard_id = chunk.shard_id() as usize;
+            if self.chunks_produced_per_shard.len() <= shard_id {
+                self.chunks_produced_per_shard.resize(shard_id + 1, 0);
+            }
+            self.chunks_produced_per_shard[shard_id] += 1;
+        }
+        self.total_txs += num_txs;
+        self.total_gas_used += gas_used;
+        self.last_block_hash = *block.hash();
+    }
+}
+
+/// Record of a block rejected by block processing as invalid, kept for operator review.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RejectedBlock {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    /// `None` if the block producer for the height couldn't be determined.
+    pub producer: Option<AccountId>,
+    /// Debug representation of the kind of the error the block was rejected with.
+    pub error: String,
+    /// Time of the rejection in nanoseconds.
+    pub timestamp: u64,
+}
+
 /// Either an epoch id or latest block hash
 #[derive(Debug)]
 pub enum ValidatorInfoIdentifier {
@@ -780,11 +1303,12 @@ pub enum ValidatorInfoIdentifier {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
     use near_primitives::time::Utc;
 
     use near_crypto::KeyType;
     use near_primitives::block::{genesis_chunks, Approval};
-    use near_primitives::hash::hash;
     use near_primitives::merkle::verify_path;
     use near_primitives::transaction::{ExecutionMetadata, ExecutionOutcome, ExecutionStatus};
     use near_primitives::validator_signer::InMemoryValidatorSigner;
@@ -864,4 +1388,45 @@ mod tests {
             assert!(verify_path(outcome_root, &path, &outcome_with_id.to_hashes()));
         }
     }
+
+    #[test]
+    fn test_state_part_streaming() {
+        let part: Vec<u8> = (0..64 * 1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+        let owned = StatePartPayload::Owned(part.clone());
+        let saved = Arc::new(Mutex::new(None));
+        let cache_sink: StatePartCacheSink = Box::new({
+            let saved = saved.clone();
+            move |part: &[u8], part_hash: &CryptoHash| {
+                *saved.lock().unwrap() = Some((part.to_vec(), *part_hash));
+                Ok(())
+            }
+        });
+        let stream = StatePartPayload::Stream(
+            StatePartStream::new(part.len(), Box::new(io::Cursor::new(part.clone()))),
+            Some(cache_sink),
+        );
+        assert_eq!(stream.len(), part.len());
+
+        let mut owned_out = vec![];
+        let owned_hash = owned.write_to(&mut owned_out).unwrap();
+        // The streamed part is appended after what's already written out, and only the part is
+        // saved.
+        let mut streamed_out = vec![1, 2, 3];
+        let streamed_hash = stream.write_to(&mut streamed_out).unwrap();
+        assert!(owned_out == part);
+        assert!(streamed_out[3..] == part[..]);
+        assert_eq!(owned_hash, hash(&part));
+        assert_eq!(streamed_hash, owned_hash);
+        let (saved_part, saved_hash) = saved.lock().unwrap().take().unwrap();
+        assert!(saved_part == part);
+        assert_eq!(saved_hash, streamed_hash);
+
+        // A stream shorter than announced is an error, and the part isn't saved.
+        let cache_sink: StatePartCacheSink = Box::new(|_: &[u8], _: &CryptoHash| unreachable!());
+        let stream = StatePartPayload::Stream(
+            StatePartStream::new(part.len() + 1, Box::new(io::Cursor::new(part))),
+            Some(cache_sink),
+        );
+        assert!(stream.into_vec().is_err());
+    }
 }
@@ -13,16 +13,18 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockReference, EpochHeight, EpochId, EpochReference, Finality,
+    MaybeBlockId, NumBlocks, ShardId, TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView,
+    AccountShardView, BlockAtHeightView, BlockView, ChainParametersView, ChunkView,
+    DiscardedForkView, EpochSummaryView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
+    FinalExecutionOutcomeViewEnum, GasPriceView, GcPlanView, LightClientBlockLiteView,
+    LightClientBlockView, PartOwnershipView, PartialFinalExecutionOutcomeView, QueryRequest,
+    QueryResponse, ReceiptView, RejectedBlockView, ShardLayoutView, StateChangesKindsView,
+    StateChangesRequestView, StateChangesView, SyncDiagnosticsView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -179,6 +181,14 @@ pub enum GetBlockError {
     UnknownBlock { error_message: String },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
+    #[error("No block was produced at height {height}")]
+    SkippedHeight { height: BlockHeight },
+    #[error("Block at height {height} has not been produced yet")]
+    NotYetProduced { height: BlockHeight },
+    #[error("Block at height {height} has been garbage collected")]
+    GarbageCollected { height: BlockHeight },
+    #[error("Block ordinal {ordinal} is out of range, the maximum known ordinal is {max_ordinal}")]
+    OrdinalOutOfRange { ordinal: NumBlocks, max_ordinal: NumBlocks },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
     // expected cases, we cannot statically guarantee that no other errors will be returned
     // in the future.
@@ -196,6 +206,9 @@ impl From<near_chain_primitives::Error> for GetBlockError {
             near_chain_primitives::ErrorKind::DBNotFoundErr(error_message) => {
                 Self::UnknownBlock { error_message }
             }
+            near_chain_primitives::ErrorKind::BlockOrdinalOutOfRange { ordinal, max_ordinal } => {
+                Self::OrdinalOutOfRange { ordinal, max_ordinal }
+            }
             _ => Self::Unreachable { error_message: error.to_string() },
         }
     }
@@ -211,6 +224,15 @@ impl Message for GetBlock {
     type Result = Result<BlockView, GetBlockError>;
 }
 
+/// Actor message requesting a block of the canonical chain by its ordinal.
+pub struct GetBlockByOrdinal {
+    pub ordinal: NumBlocks,
+}
+
+impl Message for GetBlockByOrdinal {
+    type Result = Result<BlockView, GetBlockError>;
+}
+
 /// Actor message requesting block hash by id, hash or sync state.
 pub struct GetBlockHash(pub BlockReference);
 
@@ -407,6 +429,60 @@ impl Message for Status {
     type Result = Result<StatusResponse, StatusError>;
 }
 
+/// Collects chain conditions explaining why the node may be not syncing.
+pub struct GetSyncDiagnostics {}
+
+impl Message for GetSyncDiagnostics {
+    type Result = Result<SyncDiagnosticsView, StatusError>;
+}
+
+/// Lists all blocks known at the given height.
+pub struct GetBlocksAtHeight {
+    pub height: BlockHeight,
+}
+
+impl Message for GetBlocksAtHeight {
+    type Result = Result<Vec<BlockAtHeightView>, StatusError>;
+}
+
+/// Lists fork blocks deleted by garbage collection at heights in the inclusive range.
+pub struct GetDiscardedForks {
+    pub start_height: BlockHeight,
+    pub end_height: BlockHeight,
+}
+
+impl Message for GetDiscardedForks {
+    type Result = Result<Vec<DiscardedForkView>, StatusError>;
+}
+
+/// Lists up to `limit` of the latest blocks rejected by block processing as invalid.
+pub struct GetRejectedBlocks {
+    pub limit: usize,
+}
+
+impl Message for GetRejectedBlocks {
+    type Result = Result<Vec<RejectedBlockView>, StatusError>;
+}
+
+/// Reports what garbage collection removing at most `gc_blocks_limit` blocks would delete. The
+/// limit is capped by the client.
+pub struct GetGcPlan {
+    pub gc_blocks_limit: NumBlocks,
+}
+
+impl Message for GetGcPlan {
+    type Result = Result<GcPlanView, StatusError>;
+}
+
+/// Lists the chunk parts of the current epoch owned by the account and the owners of the parts.
+pub struct GetPartOwnership {
+    pub account_id: AccountId,
+}
+
+impl Message for GetPartOwnership {
+    type Result = Result<PartOwnershipView, StatusError>;
+}
+
 pub struct GetNextLightClientBlock {
     pub last_block_hash: CryptoHash,
 }
@@ -712,6 +788,8 @@ impl Message for GetExecutionOutcome {
 
 pub struct GetExecutionOutcomesForBlock {
     pub block_hash: CryptoHash,
+    /// Check the outcomes of each shard against the outcome root of its chunk.
+    pub verified: bool,
 }
 
 impl Message for GetExecutionOutcomesForBlock {
@@ -721,17 +799,29 @@ impl Message for GetExecutionOutcomesForBlock {
 pub struct GetBlockProof {
     pub block_hash: CryptoHash,
     pub head_block_hash: CryptoHash,
+    /// Make the proof for a block which is not final yet against the header head if needed,
+    /// instead of failing with `GetBlockProofError::NotFinalYet`.
+    pub allow_non_final: bool,
 }
 
 pub struct GetBlockProofResponse {
     pub block_header_lite: LightClientBlockLiteView,
     pub proof: MerklePath,
+    /// Block the proof is made against, differs from the requested head if the block isn't final.
+    pub head_block_hash: CryptoHash,
+    pub finality: Finality,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum GetBlockProofError {
     #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
     UnknownBlock { error_message: String },
+    #[error(
+        "Block {block_hash} is not final yet, the current final height is {current_final_height}"
+    )]
+    NotFinalYet { block_hash: CryptoHash, current_final_height: BlockHeight },
+    #[error("Block at height {block_height} is ahead of the head block at height {head_height}")]
+    BlockAheadOfHead { block_height: BlockHeight, head_height: BlockHeight },
     #[error("Internal error: {error_message}")]
     InternalError { error_message: String },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
@@ -748,6 +838,14 @@ impl From<near_chain_primitives::error::Error> for GetBlockProofError {
             near_chain_primitives::error::ErrorKind::DBNotFoundErr(error_message) => {
                 Self::UnknownBlock { error_message }
             }
+            near_chain_primitives::error::ErrorKind::NotFinalYet {
+                block_hash,
+                current_final_height,
+            } => Self::NotFinalYet { block_hash, current_final_height },
+            near_chain_primitives::error::ErrorKind::BlockAheadOfHead {
+                block_height,
+                head_height,
+            } => Self::BlockAheadOfHead { block_height, head_height },
             near_chain_primitives::error::ErrorKind::Other(error_message) => {
                 Self::InternalError { error_message }
             }
@@ -791,6 +889,181 @@ impl Message for GetReceipt {
     type Result = Result<Option<ReceiptView>, GetReceiptError>;
 }
 
+/// Outcome of a transaction and of the receipts whose outcome this node has, see
+/// `Chain::get_final_transaction_result_opts`.
+pub struct GetPartialTransactionResult {
+    pub tx_hash: CryptoHash,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetPartialTransactionResultError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("Transaction {0} or its outcome is not found on this node")]
+    UnknownTransaction(near_primitives::hash::CryptoHash),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetPartialTransactionResultError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetPartialTransactionResult {
+    type Result = Result<PartialFinalExecutionOutcomeView, GetPartialTransactionResultError>;
+}
+
+/// Ids of the outcome and of the receipts and transaction which led to it, see
+/// `Chain::get_outcome_ancestry`.
+pub struct GetOutcomeAncestry {
+    pub id: CryptoHash,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetOutcomeAncestryError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("Outcome with id {0} is not found on the canonical chain of this node")]
+    UnknownOutcome(near_primitives::hash::CryptoHash),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetOutcomeAncestryError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetOutcomeAncestry {
+    type Result = Result<Vec<CryptoHash>, GetOutcomeAncestryError>;
+}
+
+/// Totals of the blocks of an epoch, see `Chain::get_epoch_summary`.
+pub struct GetEpochSummary {
+    pub epoch_height: EpochHeight,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetEpochSummaryError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("Summary of epoch at height {0} is not computed")]
+    NotComputed(EpochHeight),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetEpochSummaryError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            near_chain_primitives::ErrorKind::EpochSummaryNotComputed(epoch_height) => {
+                Self::NotComputed(epoch_height)
+            }
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetEpochSummary {
+    type Result = Result<EpochSummaryView, GetEpochSummaryError>;
+}
+
+/// Shard layout of an epoch, see `Chain::get_shard_layout_view`.
+pub struct GetShardLayout {
+    /// Epoch of the head if not given.
+    pub epoch_id: Option<EpochId>,
+}
+
+/// Shard of an account in an epoch, see `Chain::resolve_account_shard`.
+pub struct GetAccountShard {
+    pub account_id: AccountId,
+    /// Epoch of the head if not given.
+    pub epoch_id: Option<EpochId>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetShardLayoutError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("Unknown epoch")]
+    UnknownEpoch,
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetShardLayoutError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::DBNotFoundErr(_)
+            | near_chain_primitives::ErrorKind::EpochOutOfBounds(_) => Self::UnknownEpoch,
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetShardLayout {
+    type Result = Result<ShardLayoutView, GetShardLayoutError>;
+}
+
+impl Message for GetAccountShard {
+    type Result = Result<AccountShardView, GetShardLayoutError>;
+}
+
+/// Chain parameters in effect at the head, see `Chain::chain_parameters`.
+pub struct GetChainParameters {}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetChainParametersError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetChainParametersError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetChainParameters {
+    type Result = Result<ChainParametersView, GetChainParametersError>;
+}
+
 pub struct GetProtocolConfig(pub BlockReference);
 
 impl Message for GetProtocolConfig {
@@ -9,16 +9,16 @@ use near_primitives::time::Clock;
 use tracing::{debug, error, info, warn};
 
 use near_chain::chain::{
-    ApplyStatePartsRequest, BlockCatchUpRequest, BlockMissingChunks, BlocksCatchUpState,
-    OrphanMissingChunks, StateSplitRequest, TX_ROUTING_HEIGHT_HORIZON,
+    AccountShardCache, ApplyStatePartsRequest, BlockCatchUpRequest, BlockMissingChunks,
+    BlocksCatchUpState, OrphanMissingChunks, StateSplitRequest, TX_ROUTING_HEIGHT_HORIZON,
 };
 use near_chain::test_utils::format_hash;
-use near_chain::types::{AcceptedBlock, LatestKnown};
+use near_chain::types::{AcceptedBlock, BlockLimitsConfig, LatestKnown, ReceiptsRootCheckMode};
 use near_chain::{
     BlockStatus, Chain, ChainGenesis, ChainStoreAccess, Doomslug, DoomslugThresholdMode, ErrorKind,
     Provenance, RuntimeAdapter,
 };
-use near_chain_configs::{ClientConfig, LogSummaryStyle};
+use near_chain_configs::{ClientConfig, LogSummaryStyle, UnsupportedProtocolVersionPolicy};
 use near_chunks::{ProcessPartialEncodedChunkResult, ShardsManager};
 use near_network::types::{
     FullPeerInfo, NetworkClientResponses, NetworkRequests, PeerManagerAdapter,
@@ -106,6 +106,12 @@ pub struct Client {
     /// Last time the head was updated, or our head was rebroadcasted. Used to re-broadcast the head
     /// again to prevent network from stalling if a large percentage of the network missed a block
     last_time_head_progress_made: Instant,
+    /// Number of head updates since garbage collection last ran, used by `GcSchedule`.
+    heads_since_gc: NumBlocks,
+    /// Last time the head was updated, used by `GcSchedule`.
+    last_head_update_time: Instant,
+    /// Shards of the receivers of the outgoing receipts of the chunks produced so far.
+    account_shard_cache: AccountShardCache,
 }
 
 // Debug information about the upcoming block.
@@ -149,7 +155,30 @@ impl Client {
         } else {
             DoomslugThresholdMode::NoApprovals
         };
-        let chain = Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        let mut chain =
+            Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        chain.block_limits_config = BlockLimitsConfig::from(&config);
+        chain.receipts_root_check_mode = ReceiptsRootCheckMode::from(&config);
+        chain.unsupported_protocol_version_policy = config.on_unsupported_protocol_version;
+        chain.record_discarded_forks = config.record_discarded_forks;
+        chain.record_approval_audit = config.record_approval_audit && validator_signer.is_some();
+        chain.retain_partial_chunk_parts_epochs = config.retain_partial_chunk_parts_epochs;
+        chain.allow_aggressive_gc = config.allow_aggressive_gc;
+        chain.header_sync_commit_batch_size = config.header_sync_commit_batch_size;
+        chain.verify_incoming_receipts = config.verify_incoming_receipts;
+        chain.future_block_grace_period = config.future_block_grace_period;
+        chain.blocks_delay_tracker.block_production_interval =
+            Some(config.min_block_production_delay);
+        if config.trusted_chain_replay {
+            chain.enable_trusted_chain_replay(&config.chain_id)?;
+        }
+        if let Some(overrides) = config.economics_overrides.clone() {
+            chain.set_economics_overrides(&config.chain_id, overrides)?;
+        }
+        chain.set_max_orphans_per_prev_hash(config.max_orphans_per_prev_hash);
+        chain.receipt_backlog_warn_bytes = config.receipt_backlog_warn_bytes;
+        chain.header_sync_expected_height_per_second =
+            config.header_sync_expected_height_per_second;
         let shards_mgr = ShardsManager::new(
             validator_signer.as_ref().map(|x| x.validator_id().clone()),
             runtime_adapter.clone(),
@@ -221,6 +250,9 @@ impl Client {
             rs: ReedSolomonWrapper::new(data_parts, parity_parts),
             rebroadcasted_blocks: lru::LruCache::new(NUM_REBROADCAST_BLOCKS),
             last_time_head_progress_made: Clock::instant(),
+            heads_since_gc: 0,
+            last_head_update_time: Clock::instant(),
+            account_shard_cache: AccountShardCache::default(),
         })
     }
 
@@ -459,10 +491,11 @@ impl Client {
             .expect("Epoch hash should exist at this point");
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
+        let economics = &self.chain.block_economics_config;
         let gas_price_adjustment_rate =
-            self.chain.block_economics_config.gas_price_adjustment_rate(protocol_version);
-        let min_gas_price = self.chain.block_economics_config.min_gas_price(protocol_version);
-        let max_gas_price = self.chain.block_economics_config.max_gas_price(protocol_version);
+            economics.gas_price_adjustment_rate(protocol_version, next_height);
+        let min_gas_price = economics.min_gas_price(protocol_version, next_height);
+        let max_gas_price = economics.max_gas_price(protocol_version, next_height);
 
         let next_bp_hash = if prev_epoch_id != epoch_id {
             Chain::compute_bp_hash(
@@ -634,8 +667,11 @@ impl Client {
         // will receive a piece of incoming receipts only
         // with merkle receipts proofs which can be checked locally
         let shard_layout = self.runtime_adapter.get_shard_layout(epoch_id)?;
-        let outgoing_receipts_hashes =
-            Chain::build_receipts_hashes(&outgoing_receipts, &shard_layout);
+        let outgoing_receipts_hashes = Chain::build_receipts_hashes_with_cache(
+            &outgoing_receipts,
+            &shard_layout,
+            &mut self.account_shard_cache,
+        );
         let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(epoch_id)?;
@@ -755,7 +791,20 @@ impl Client {
                 Err(e) => return (vec![], Err(e)),
             }
         }
+        self.process_block_unchecked(block, provenance)
+    }
 
+    /// Processes the block like `process_block`, even if a block at its height was processed
+    /// already.
+    fn process_block_unchecked(
+        &mut self,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+    ) -> (Vec<AcceptedBlock>, Result<Option<Tip>, near_chain::Error>) {
+        // The block is processed again if it's rejected because of a corrupted state, which is
+        // quarantined instead.
+        let quarantine_retry =
+            self.config.quarantine_shard_on_state_mismatch.then(|| block.clone());
         let mut accepted_blocks = vec![];
         let mut blocks_missing_chunks = vec![];
         let mut orphans_missing_chunks = vec![];
@@ -766,10 +815,13 @@ impl Client {
                 .validator_signer
                 .as_ref()
                 .map(|validator_signer| validator_signer.validator_id().clone());
+            if let Err(err) = self.chain.prefetch_for_block(&me, block.get_inner()) {
+                debug!(target: "client", "Failed to prefetch state for block {}: {:?}", block.hash(), err);
+            }
             self.chain.process_block(
                 &me,
                 block,
-                provenance,
+                provenance.clone(),
                 &mut |accepted_block| {
                     accepted_blocks.push(accepted_block);
                 },
@@ -784,6 +836,22 @@ impl Client {
         // Send out challenges that accumulated via on_challenge.
         self.send_challenges(challenges);
 
+        // A chunk we produced ourselves not matching our own chunk extra means that our state of
+        // the shard is corrupted, rather than that the chunk is invalid.
+        let mut state_quarantined = false;
+        if self.config.quarantine_shard_on_state_mismatch {
+            if let Err(e) = &result {
+                if let near_chain::ErrorKind::InvalidChunkState(chunk_state) = e.kind() {
+                    match self.quarantine_shard_of_own_chunk(&chunk_state.chunk_header) {
+                        Ok(quarantined) => state_quarantined = quarantined,
+                        Err(err) => {
+                            error!(target: "client", "Failed to quarantine shard state: {:?}", err)
+                        }
+                    }
+                }
+            }
+        }
+
         // Send out challenge if the block was found to be invalid.
         if let Some(validator_signer) = self.validator_signer.as_ref() {
             match &result {
@@ -796,7 +864,7 @@ impl Client {
                             )),
                         ));
                     }
-                    near_chain::ErrorKind::InvalidChunkState(chunk_state) => {
+                    near_chain::ErrorKind::InvalidChunkState(chunk_state) if !state_quarantined => {
                         self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
                             NetworkRequests::Challenge(Challenge::produce(
                                 ChallengeBody::ChunkState(*chunk_state),
@@ -817,9 +885,48 @@ impl Client {
         // Request any missing chunks
         self.request_missing_chunks(blocks_missing_chunks, orphans_missing_chunks);
 
+        // The chunks of the quarantined shard are no longer applied, so the block can be accepted.
+        if let Some(block) = quarantine_retry.filter(|_| state_quarantined) {
+            let (retry_accepted_blocks, result) = self.process_block_unchecked(block, provenance);
+            accepted_blocks.extend(retry_accepted_blocks);
+            return (accepted_blocks, result);
+        }
+
         (accepted_blocks, result)
     }
 
+    /// Quarantines the state of the chunk's shard if the chunk was produced by us.
+    /// Returns whether the shard was quarantined by this call.
+    fn quarantine_shard_of_own_chunk(
+        &mut self,
+        chunk_header: &ShardChunkHeader,
+    ) -> Result<bool, Error> {
+        let me = match self.validator_signer.as_ref() {
+            Some(validator_signer) => validator_signer.validator_id().clone(),
+            None => return Ok(false),
+        };
+        let shard_id = chunk_header.shard_id();
+        let epoch_id = self
+            .runtime_adapter
+            .get_epoch_id_from_prev_block(chunk_header.prev_block_hash_ref())?;
+        let chunk_producer = self.runtime_adapter.get_chunk_producer(
+            &epoch_id,
+            chunk_header.height_created(),
+            shard_id,
+        )?;
+        if chunk_producer != me {
+            return Ok(false);
+        }
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+        Ok(self.chain.quarantine_shard_state(
+            shard_uid,
+            format!(
+                "chunk {:?} produced by us doesn't match our chunk extra",
+                chunk_header.chunk_hash()
+            ),
+        )?)
+    }
+
     pub fn rebroadcast_block(&mut self, block: &Block) {
         if self.rebroadcasted_blocks.get(block.hash()).is_none() {
             self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
@@ -1097,26 +1204,40 @@ impl Client {
             };
             self.chain.blocks_with_missing_chunks.prune_blocks_below_height(last_finalized_height);
 
-            let timer = metrics::GC_TIME.start_timer();
-            let gc_blocks_limit = self.config.gc_blocks_limit;
-            let result = if self.config.archive {
-                self.chain.clear_archive_data(gc_blocks_limit)
-            } else {
-                let tries = self.runtime_adapter.get_tries();
-                self.chain.clear_data(tries, gc_blocks_limit)
-            };
-            if let Err(err) = result {
-                error!(target: "client", "Can't clear old data, {:?}", err);
-                debug_assert!(false);
-            };
-            timer.observe_duration();
+            self.heads_since_gc += 1;
+            let now = Clock::instant();
+            let idle = now.saturating_duration_since(self.last_head_update_time);
+            self.last_head_update_time = now;
+            if self.config.gc_schedule.should_run(self.heads_since_gc, idle) {
+                self.heads_since_gc = 0;
+                let timer = metrics::GC_TIME.start_timer();
+                let gc_blocks_limit = self.config.gc_blocks_limit;
+                let result = if self.config.archive {
+                    self.chain.clear_archive_data(gc_blocks_limit)
+                } else {
+                    let tries = self.runtime_adapter.get_tries();
+                    self.chain.clear_data(tries, gc_blocks_limit)
+                };
+                if let Err(err) = result {
+                    error!(target: "client", "Can't clear old data, {:?}", err);
+                    debug_assert!(false);
+                };
+                timer.observe_duration();
+            }
 
             if self.runtime_adapter.is_next_block_epoch_start(block.hash()).unwrap_or(false) {
                 let next_epoch_protocol_version = unwrap_or_return!(self
                     .runtime_adapter
                     .get_epoch_protocol_version(block.header().next_epoch_id()));
                 if next_epoch_protocol_version > PROTOCOL_VERSION {
-                    panic!("The client protocol version is older than the protocol version of the network. Please update nearcore");
+                    match self.config.on_unsupported_protocol_version {
+                        UnsupportedProtocolVersionPolicy::Halt => {
+                            panic!("The client protocol version is older than the protocol version of the network. Please update nearcore");
+                        }
+                        UnsupportedProtocolVersionPolicy::FollowHeadersOnly => {
+                            error!(target: "client", "The client protocol version is older than the protocol version of the network, only block headers will be followed from the next epoch. Please update nearcore");
+                        }
+                    }
                 }
             }
         }
@@ -1245,7 +1366,11 @@ impl Client {
 
         let chunk_hashes: Vec<ChunkHash> =
             block.chunks().iter().map(|chunk| chunk.chunk_hash()).collect();
-        self.chain.blocks_delay_tracker.finish_block_processing(&block_hash, &chunk_hashes);
+        self.chain.blocks_delay_tracker.finish_block_processing(
+            &block_hash,
+            &chunk_hashes,
+            Clock::instant(),
+        );
     }
 
     pub fn request_missing_chunks(
@@ -1311,7 +1436,33 @@ impl Client {
             },
             &mut |missing_chunks| blocks_missing_chunks.push(missing_chunks),
             &mut |orphan_missing_chunks| orphans_missing_chunks.push(orphan_missing_chunks),
-            &mut |challenge| challenges.push(challenge));
+            &mut |challenge| challenges.push(challenge),
+            self.config.missing_chunks_processing_budget);
+        self.send_challenges(challenges);
+
+        self.request_missing_chunks(blocks_missing_chunks, orphans_missing_chunks);
+        accepted_blocks
+    }
+
+    /// Check if any block held back because it was ahead of the local clock can be processed now
+    #[must_use]
+    pub fn process_time_delayed_blocks(&mut self) -> Vec<AcceptedBlock> {
+        let mut accepted_blocks = vec![];
+        let mut blocks_missing_chunks = vec![];
+        let mut orphans_missing_chunks = vec![];
+        let mut challenges = vec![];
+        let me =
+            self.validator_signer.as_ref().map(|validator_signer| validator_signer.validator_id());
+        self.chain.retry_time_delayed_blocks(
+            &me.map(|x| x.clone()),
+            &mut |accepted_block| {
+                debug!(target: "client", "Block {} was ahead of the local clock but now is processed", accepted_block.hash);
+                accepted_blocks.push(accepted_block);
+            },
+            &mut |missing_chunks| blocks_missing_chunks.push(missing_chunks),
+            &mut |orphan_missing_chunks| orphans_missing_chunks.push(orphan_missing_chunks),
+            &mut |challenge| challenges.push(challenge),
+        );
         self.send_challenges(challenges);
 
         self.request_missing_chunks(blocks_missing_chunks, orphans_missing_chunks);
@@ -1576,6 +1727,18 @@ impl Client {
         Ok(())
     }
 
+    /// Whether the signer of the transaction conforms to the configured routing policy.
+    /// Only applies to transactions submitted to this node directly.
+    fn check_tx_routing_policy(&self, tx: &SignedTransaction) -> bool {
+        let (policy, allowed) = match &self.config.tx_routing_policy {
+            Some(policy) => (policy.name(), policy.allows(&tx.transaction.signer_id)),
+            None => ("unrestricted", true),
+        };
+        let decision = if allowed { "accepted" } else { "rejected" };
+        metrics::TX_ROUTING_POLICY_DECISIONS.with_label_values(&[policy, decision]).inc();
+        allowed
+    }
+
     /// Process transaction and either add it to the mempool or return to redirect to another validator.
     fn process_tx_internal(
         &mut self,
@@ -1583,6 +1746,12 @@ impl Client {
         is_forwarded: bool,
         check_only: bool,
     ) -> Result<NetworkClientResponses, Error> {
+        if !is_forwarded && !check_only && !self.check_tx_routing_policy(tx) {
+            debug!(target: "client", "Tx rejected by the routing policy: {:?}", tx);
+            return Ok(NetworkClientResponses::TxRejectedByRoutingPolicy {
+                signer_id: tx.transaction.signer_id.clone(),
+            });
+        }
         let head = self.chain.head()?;
         let me = self.validator_signer.as_ref().map(|vs| vs.validator_id());
         let cur_block_header = self.chain.head_header()?.clone();
@@ -1758,13 +1927,14 @@ impl Client {
                 }
             };
             let state_sync_timeout = self.config.state_sync_timeout;
+            let memory_limit_bytes = self.config.catchup_saved_updates_memory_limit_bytes;
             let epoch_id = self.chain.get_block(&sync_hash)?.header().epoch_id().clone();
             let (state_sync, new_shard_sync, blocks_catch_up_state) =
                 self.catchup_state_syncs.entry(sync_hash).or_insert_with(|| {
                     (
                         StateSync::new(network_adapter1, state_sync_timeout),
                         new_shard_sync,
-                        BlocksCatchUpState::new(sync_hash, epoch_id),
+                        BlocksCatchUpState::new(sync_hash, epoch_id, memory_limit_bytes),
                     )
                 });
 
@@ -8,31 +8,33 @@ use crate::metrics::PARTIAL_ENCODED_CHUNK_RESPONSE_DELAY;
 use crate::sync::{StateSync, StateSyncResult};
 use crate::{metrics, StatusResponse};
 use actix::dev::SendError;
-use actix::{Actor, Addr, Arbiter, AsyncContext, Context, Handler, Message};
+use actix::{Actor, Addr, Arbiter, AsyncContext, Context, Handler, Message, MessageResult};
 use actix_rt::ArbiterHandle;
 use borsh::BorshSerialize;
 use chrono::DateTime;
 use near_chain::chain::{
     do_apply_chunks, ApplyStatePartsRequest, ApplyStatePartsResponse, BlockCatchUpRequest,
-    BlockCatchUpResponse, StateSplitRequest, StateSplitResponse,
+    BlockCatchUpResponse, PrepareShutdown, ShutdownHandle, StateSplitRequest, StateSplitResponse,
 };
 use near_chain::crypto_hash_timer::CryptoHashTimer;
+use near_chain::shared_chain_cache::SharedChainCache;
 use near_chain::test_utils::format_hash;
 use near_chain::types::{AcceptedBlock, ValidatorInfoIdentifier};
 use near_chain::{
-    byzantine_assert, near_chain_primitives, Block, BlockHeader, ChainGenesis, ChainStoreAccess,
-    Provenance, RuntimeAdapter,
+    byzantine_assert, near_chain_primitives, Block, BlockHeader, BlockSaveMode, ChainGenesis,
+    ChainMisbehavior, ChainStoreAccess, PartProvenance, Provenance, RuntimeAdapter,
 };
 use near_chain_configs::ClientConfig;
 use near_client_primitives::types::{
-    Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
-    StatusError, StatusSyncInfo, SyncStatus,
+    Error, GetBlocksAtHeight, GetDiscardedForks, GetGcPlan, GetNetworkInfo, GetPartOwnership,
+    GetRejectedBlocks, GetSyncDiagnostics, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus,
+    Status, StatusError, StatusSyncInfo, SyncStatus,
 };
 use near_network::types::{
     NetworkClientMessages, NetworkClientResponses, NetworkInfo, NetworkRequests,
     PeerManagerAdapter, PeerManagerMessageRequest,
 };
-use near_network_primitives::types::ReasonForBan;
+use near_network_primitives::types::{AccountOrPeerIdOrHash, ReasonForBan};
 use near_performance_metrics;
 use near_performance_metrics_macros::{perf, perf_with_debug};
 use near_primitives::block_header::ApprovalType;
@@ -42,19 +44,22 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::state_part::PartId;
 use near_primitives::syncing::StatePartKey;
 use near_primitives::time::{Clock, Utc};
-use near_primitives::types::BlockHeight;
+use near_primitives::types::{BlockHeight, NumBlocks, ShardId};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{from_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
-    DebugBlockStatus, DebugChunkStatus, DetailedDebugStatus, ValidatorInfo,
+    BlockAtHeightView, DebugBlockStatus, DebugChunkStatus, DetailedDebugStatus, DiscardedForkView,
+    GcPlanView, PartOwnershipView, RejectedBlockView, StoreColumnTuningView, SyncDiagnosticsView,
+    ValidatorInfo,
 };
 use near_store::db::DBCol::ColStateParts;
 use near_telemetry::TelemetryActor;
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
@@ -69,6 +74,15 @@ const BLOCK_HORIZON: u64 = 500;
 /// the current `head`
 const HEAD_STALL_MULTIPLIER: u32 = 4;
 
+/// Number of (block, shard) pairs waiting to be applied to split states above which catchup
+/// runs before new blocks are processed, if `prioritize_split_state_catchup` is enabled.
+const SPLIT_STATE_BACKLOG_PRIORITY_THRESHOLD: usize = 10;
+/// Number of most recent epochs the block readiness SLO is reported over in the log summary.
+const BLOCK_READINESS_SLO_WINDOW_EPOCHS: u64 = 1;
+/// Maximum number of blocks the garbage collection plan requested by the debug endpoint covers,
+/// since it's computed on the client thread.
+const MAX_GC_PLAN_BLOCKS: NumBlocks = 1000;
+
 pub struct ClientActor {
     /// Adversarial controls
     #[cfg(feature = "test_features")]
@@ -90,6 +104,8 @@ pub struct ClientActor {
 
     // Last time when log_summary method was called.
     log_summary_timer_next_attempt: DateTime<Utc>,
+    /// Head height seen by log_summary and the time it was first seen.
+    last_seen_head: Option<(BlockHeight, Instant)>,
 
     block_production_started: bool,
     doomslug_timer_next_attempt: DateTime<Utc>,
@@ -99,6 +115,9 @@ pub struct ClientActor {
     block_catch_up_scheduler: Box<dyn Fn(BlockCatchUpRequest)>,
     state_split_scheduler: Box<dyn Fn(StateSplitRequest)>,
     state_parts_client_arbiter: Arbiter,
+    /// Invalid data detected by the chain, the peers that sent it are banned, see
+    /// `take_misbehavior`.
+    misbehavior_receiver: Receiver<ChainMisbehavior>,
 
     #[cfg(feature = "sandbox")]
     fastforward_delta: near_primitives::types::BlockHeightDelta,
@@ -144,20 +163,12 @@ impl ClientActor {
         #[cfg(feature = "test_features")] adv: Arc<std::sync::RwLock<crate::AdversarialControls>>,
     ) -> Result<Self, Error> {
         let state_parts_arbiter = Arbiter::new();
-        let self_addr = ctx.address();
-        let sync_jobs_actor_addr = SyncJobsActor::start_in_arbiter(
-            &state_parts_arbiter.handle(),
-            move |ctx: &mut Context<SyncJobsActor>| -> SyncJobsActor {
-                ctx.set_mailbox_capacity(SyncJobsActor::MAILBOX_CAPACITY);
-                SyncJobsActor { client_addr: self_addr }
-            },
-        );
         wait_until_genesis(&chain_genesis.time);
         if let Some(vs) = &validator_signer {
             info!(target: "client", "Starting validator node: {}", vs.validator_id());
         }
         let info_helper = InfoHelper::new(telemetry_actor, &config, validator_signer.clone());
-        let client = Client::new(
+        let mut client = Client::new(
             config,
             chain_genesis,
             runtime_adapter,
@@ -166,6 +177,17 @@ impl ClientActor {
             enable_doomslug,
             rng_seed,
         )?;
+        let (misbehavior_sender, misbehavior_receiver) = mpsc::channel();
+        client.chain.malicious_behavior_sink = Some(misbehavior_sender);
+        let self_addr = ctx.address();
+        let shutdown = client.chain.shutdown_handle();
+        let sync_jobs_actor_addr = SyncJobsActor::start_in_arbiter(
+            &state_parts_arbiter.handle(),
+            move |ctx: &mut Context<SyncJobsActor>| -> SyncJobsActor {
+                ctx.set_mailbox_capacity(SyncJobsActor::MAILBOX_CAPACITY);
+                SyncJobsActor { client_addr: self_addr, shutdown }
+            },
+        );
 
         let now = Utc::now();
         Ok(ClientActor {
@@ -188,6 +210,7 @@ impl ClientActor {
             info_helper,
             block_production_next_attempt: now,
             log_summary_timer_next_attempt: now,
+            last_seen_head: None,
             block_production_started: false,
             doomslug_timer_next_attempt: now,
             chunk_request_retry_next_attempt: now,
@@ -202,6 +225,7 @@ impl ClientActor {
                 sync_jobs_actor_addr,
             ),
             state_parts_client_arbiter: state_parts_arbiter,
+            misbehavior_receiver,
 
             #[cfg(feature = "sandbox")]
             fastforward_delta: 0,
@@ -210,6 +234,29 @@ impl ClientActor {
     }
 }
 
+/// Takes the misbehavior reported by the chain so far and returns the reason to ban a peer for,
+/// if any of it is about data the peer sent, as told by `sent_by_peer` from the hash of the
+/// offending object. The rest was found in data received earlier from other peers, whose
+/// responsibility can no longer be told, and is dropped.
+fn take_misbehavior(
+    receiver: &Receiver<ChainMisbehavior>,
+    sent_by_peer: impl Fn(&CryptoHash) -> bool,
+) -> Option<ReasonForBan> {
+    let mut ban_reason = None;
+    for misbehavior in receiver.try_iter() {
+        if !sent_by_peer(misbehavior.hash()) {
+            debug!(target: "client", "Dropping misbehavior of an unknown peer: {:?}", misbehavior);
+            continue;
+        }
+        ban_reason.get_or_insert(match misbehavior {
+            ChainMisbehavior::InvalidBlock { .. } => ReasonForBan::BadBlock,
+            ChainMisbehavior::InvalidHeader { .. } => ReasonForBan::BadBlockHeader,
+            ChainMisbehavior::InvalidStateHeader { .. } => ReasonForBan::BadStateHeader,
+        });
+    }
+    ban_reason
+}
+
 fn create_sync_job_scheduler<M>(address: Addr<SyncJobsActor>) -> Box<dyn Fn(M)>
 where
     M: Message + Send + 'static,
@@ -406,6 +453,17 @@ impl ClientActor {
                 self.client.process_tx(transaction, is_forwarded, check_only)
             }
             NetworkClientMessages::Block(block, peer_id, was_requested) => {
+                // Peers pushing a block already known to be invalid are banned, if they could have
+                // found it invalid before relaying it. Requested blocks are exempt since the peer
+                // only answered our request.
+                if !was_requested {
+                    if let Some(error) =
+                        self.client.chain.is_block_invalid_before_apply(block.hash())
+                    {
+                        warn!(target: "client", "Peer {} sent block {} known to be invalid: {:?}", peer_id, block.hash(), error);
+                        return NetworkClientResponses::Ban { ban_reason: ReasonForBan::BadBlock };
+                    }
+                }
                 let blocks_at_height = self
                     .client
                     .chain
@@ -415,7 +473,11 @@ impl ClientActor {
                     if let SyncStatus::StateSync(sync_hash, _) = &mut self.client.sync_status {
                         if let Ok(header) = self.client.chain.get_block_header(sync_hash) {
                             if block.hash() == header.prev_hash() {
-                                if let Err(e) = self.client.chain.save_block(block.into()) {
+                                if let Err(e) = self
+                                    .client
+                                    .chain
+                                    .save_block(block.into(), BlockSaveMode::StateSyncBoundary)
+                                {
                                     error!(target: "client", "Failed to save a block during state sync: {}", e);
                                 }
                             } else if block.hash() == sync_hash {
@@ -449,11 +511,17 @@ impl ClientActor {
                 }
             }
             NetworkClientMessages::BlockHeaders(headers, peer_id) => {
+                let header_hashes =
+                    headers.iter().map(|header| *header.hash()).collect::<HashSet<_>>();
                 if self.receive_headers(headers, peer_id) {
                     NetworkClientResponses::NoResponse
                 } else {
                     warn!(target: "client", "Banning node for sending invalid block headers");
-                    NetworkClientResponses::Ban { ban_reason: ReasonForBan::BadBlockHeader }
+                    let ban_reason = take_misbehavior(&self.misbehavior_receiver, |hash| {
+                        header_hashes.contains(hash)
+                    })
+                    .unwrap_or(ReasonForBan::BadBlockHeader);
+                    NetworkClientResponses::Ban { ban_reason }
                 }
             }
             NetworkClientMessages::BlockApproval(approval, peer_id) => {
@@ -461,9 +529,14 @@ impl ClientActor {
                 self.client.collect_block_approval(&approval, ApprovalType::PeerApproval(peer_id));
                 NetworkClientResponses::NoResponse
             }
-            NetworkClientMessages::StateResponse(state_response_info) => {
+            NetworkClientMessages::StateResponse(state_response_info, peer_id) => {
                 let shard_id = state_response_info.shard_id();
                 let hash = state_response_info.sync_hash();
+                if let Some(suggested_sync_hash) = state_response_info.suggested_sync_hash() {
+                    warn!(target: "sync", "Peer suggested sync hash {} instead of {} for shard {}", suggested_sync_hash, hash, shard_id);
+                    self.retry_state_sync(shard_id, hash, suggested_sync_hash);
+                    return NetworkClientResponses::NoResponse;
+                }
                 let state_response = state_response_info.take_state_response();
 
                 trace!(target: "sync", "Received state response shard_id: {} sync_hash: {:?} part(id/size): {:?}",
@@ -532,6 +605,19 @@ impl ClientActor {
                                         Err(err) => {
                                             error!(target: "sync", "State sync set_state_header error, shard = {}, hash = {}: {:?}", shard_id, hash, err);
                                             shard_sync_download.downloads[0].error = true;
+                                            if let Some(ban_reason) = take_misbehavior(
+                                                &self.misbehavior_receiver,
+                                                |sync_hash| sync_hash == &hash,
+                                            ) {
+                                                self.network_adapter.do_send(
+                                                    PeerManagerMessageRequest::NetworkRequests(
+                                                        NetworkRequests::BanPeer {
+                                                            peer_id: peer_id.clone(),
+                                                            ban_reason,
+                                                        },
+                                                    ),
+                                                );
+                                            }
                                         }
                                     }
                                 }
@@ -552,12 +638,31 @@ impl ClientActor {
                                     error!(target: "sync", "State sync received incorrect part_id # {:?} for hash {:?}, potential malicious peer", part_id, hash);
                                     return NetworkClientResponses::NoResponse;
                                 }
-                                if !shard_sync_download.downloads[part_id as usize].done {
+                                let part_download =
+                                    &shard_sync_download.downloads[part_id as usize];
+                                if !part_download.done {
+                                    // A part received from another peer than the one it was
+                                    // last requested from answers an earlier request, which is
+                                    // only known to be sent after the download started.
+                                    let request_sent = match &part_download.last_target {
+                                        Some(AccountOrPeerIdOrHash::PeerId(target))
+                                            if target == &peer_id =>
+                                        {
+                                            part_download.prev_update_time
+                                        }
+                                        _ => part_download.start_time,
+                                    };
+                                    let provenance = Some(PartProvenance {
+                                        peer_id,
+                                        request_sent,
+                                        received: Clock::utc(),
+                                    });
                                     match self.client.chain.set_state_part(
                                         shard_id,
                                         hash,
                                         PartId::new(part_id, num_parts),
                                         &data,
+                                        provenance,
                                     ) {
                                         Ok(()) => {
                                             shard_sync_download.downloads[part_id as usize].done =
@@ -800,6 +905,20 @@ impl Handler<Status> for ClientActor {
                 ),
                 current_head_status: self.client.chain.head()?.clone().into(),
                 current_header_head_status: self.client.chain.header_head()?.clone().into(),
+                store_tuning: self
+                    .client
+                    .chain
+                    .store()
+                    .store()
+                    .get_column_tuning()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(col, tuning)| StoreColumnTuningView {
+                        column: format!("{:?}", col),
+                        block_cache_size: tuning.block_cache_size.as_u64(),
+                        write_buffer_size: tuning.write_buffer_size.as_u64(),
+                    })
+                    .collect(),
             })
         } else {
             None
@@ -825,10 +944,113 @@ impl Handler<Status> for ClientActor {
             },
             validator_account_id,
             detailed_debug_status,
+            data_availability: self.client.chain.data_availability_summary().ok().map(Into::into),
+            sync_progress: self
+                .client
+                .chain
+                .estimate_sync_progress(self.network_height())
+                .ok()
+                .map(Into::into),
+            state_downloads: self
+                .client
+                .chain
+                .state_download_status()
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
         })
     }
 }
 
+impl Handler<GetSyncDiagnostics> for ClientActor {
+    type Result = Result<SyncDiagnosticsView, StatusError>;
+
+    #[perf]
+    fn handle(&mut self, _msg: GetSyncDiagnostics, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client sync diagnostics".into());
+        Ok(self.client.chain.sync_diagnostics()?.into())
+    }
+}
+
+impl Handler<GetBlocksAtHeight> for ClientActor {
+    type Result = Result<Vec<BlockAtHeightView>, StatusError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetBlocksAtHeight, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client blocks at height".into());
+        Ok(self
+            .client
+            .chain
+            .blocks_at_height_info(msg.height)?
+            .into_iter()
+            .map(BlockAtHeightView::from)
+            .collect())
+    }
+}
+
+impl Handler<GetDiscardedForks> for ClientActor {
+    type Result = Result<Vec<DiscardedForkView>, StatusError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetDiscardedForks, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client discarded forks".into());
+        Ok(self
+            .client
+            .chain
+            .discarded_forks(msg.start_height..=msg.end_height)?
+            .into_iter()
+            .map(DiscardedForkView::from)
+            .collect())
+    }
+}
+
+impl Handler<GetRejectedBlocks> for ClientActor {
+    type Result = Result<Vec<RejectedBlockView>, StatusError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetRejectedBlocks, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client rejected blocks".into());
+        Ok(self
+            .client
+            .chain
+            .rejected_blocks(msg.limit)?
+            .into_iter()
+            .map(RejectedBlockView::from)
+            .collect())
+    }
+}
+
+impl Handler<GetGcPlan> for ClientActor {
+    type Result = Result<GcPlanView, StatusError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetGcPlan, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client gc plan".into());
+        let gc_blocks_limit = std::cmp::min(msg.gc_blocks_limit, MAX_GC_PLAN_BLOCKS);
+        Ok(self.client.chain.simulate_gc(gc_blocks_limit)?.into())
+    }
+}
+
+impl Handler<GetPartOwnership> for ClientActor {
+    type Result = Result<PartOwnershipView, StatusError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetPartOwnership, _ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client part ownership".into());
+        let head = self.client.chain.head()?;
+        Ok(self.client.chain.part_ownership(&head.last_block_hash, &msg.account_id)?)
+    }
+}
+
+impl Handler<PrepareShutdown> for ClientActor {
+    type Result = MessageResult<PrepareShutdown>;
+
+    fn handle(&mut self, msg: PrepareShutdown, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.client.chain.prepare_shutdown(msg.timeout))
+    }
+}
+
 impl Handler<GetNetworkInfo> for ClientActor {
     type Result = Result<NetworkInfoResponse, String>;
 
@@ -1057,6 +1279,16 @@ impl ClientActor {
         let now = Utc::now();
 
         let timer = metrics::CHECK_TRIGGERS_TIME.start_timer();
+        // Blocks which were missing chunks and were deferred due to the processing time budget.
+        if self.client.chain.blocks_with_missing_chunks.has_ready_blocks() {
+            let accepted_blocks = self.client.process_blocks_with_missing_chunks();
+            self.process_accepted_blocks(accepted_blocks);
+        }
+        // Blocks which were ahead of the local clock and may be processed once it caught up.
+        if self.client.chain.has_time_delayed_blocks() {
+            let accepted_blocks = self.client.process_time_delayed_blocks();
+            self.process_accepted_blocks(accepted_blocks);
+        }
         if self.sync_started {
             self.doomslug_timer_next_attempt = self.run_timer(
                 self.client.config.doosmslug_step_period,
@@ -1202,6 +1434,9 @@ impl ClientActor {
     /// Process all blocks that were accepted by calling other relevant services.
     fn process_accepted_blocks(&mut self, accepted_blocks: Vec<AcceptedBlock>) {
         for accepted_block in accepted_blocks {
+            if let Some((shard_id, duration)) = accepted_block.slowest_shard {
+                debug!(target: "client", "Block {} spent the longest applying the chunk of shard {}: {:?}", accepted_block.hash, shard_id, duration);
+            }
             self.client.on_block_accepted(
                 accepted_block.hash,
                 accepted_block.status,
@@ -1261,11 +1496,11 @@ impl ClientActor {
                     }
                 }
                 Err(e) if e.is_bad_data() => {
+                    let ban_reason =
+                        take_misbehavior(&self.misbehavior_receiver, |hash| hash == block.hash())
+                            .unwrap_or(ReasonForBan::BadBlockHeader);
                     self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
-                        NetworkRequests::BanPeer {
-                            peer_id: peer_id.clone(),
-                            ban_reason: ReasonForBan::BadBlockHeader,
-                        },
+                        NetworkRequests::BanPeer { peer_id: peer_id.clone(), ban_reason },
                     ));
                     return Err(e);
                 }
@@ -1278,6 +1513,12 @@ impl ClientActor {
                 }
             }
         }
+        if self.client.config.prioritize_split_state_catchup
+            && self.client.chain.split_state_backlog_exceeds(SPLIT_STATE_BACKLOG_PRIORITY_THRESHOLD)
+        {
+            debug!(target: "client", "Split state backlog exceeds {}, running catchup before processing block {}", SPLIT_STATE_BACKLOG_PRIORITY_THRESHOLD, block.hash());
+            self.run_catchup_step();
+        }
         let (accepted_blocks, result) = self.client.process_block(block, provenance);
         self.process_accepted_blocks(accepted_blocks);
         result.map(|_| ())
@@ -1369,6 +1610,16 @@ impl ClientActor {
         }
     }
 
+    /// Highest height reported by the peers, 0 if there are none.
+    fn network_height(&self) -> BlockHeight {
+        self.network_info
+            .highest_height_peers
+            .iter()
+            .map(|peer| peer.chain_info.height)
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Check whether need to (continue) sync.
     /// Also return higher height with known peers at that height.
     fn syncing_info(&self) -> Result<(bool, u64), near_chain::Error> {
@@ -1449,6 +1700,69 @@ impl ClientActor {
     /// The selected block will always be the first block on a new epoch:
     /// https://github.com/nearprotocol/nearcore/issues/2021#issuecomment-583039862
     ///
+    /// Acts on a peer rejecting `sync_hash` in favour of `suggested_sync_hash`. The state sync
+    /// restarts from the suggested hash if it starts an epoch on our header chain, otherwise the
+    /// pending downloads of the shard are re-requested, possibly from other peers.
+    fn retry_state_sync(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        suggested_sync_hash: CryptoHash,
+    ) {
+        let is_current_state_sync = matches!(
+            &self.client.sync_status,
+            SyncStatus::StateSync(current_sync_hash, _) if *current_sync_hash == sync_hash
+        );
+        if is_current_state_sync && self.is_epoch_start_on_header_chain(&suggested_sync_hash) {
+            info!(target: "sync", "Restarting state sync from suggested sync hash {}", suggested_sync_hash);
+            if !self.client.config.archive {
+                if let Err(err) = self.client.chain.reset_data_pre_state_sync(suggested_sync_hash) {
+                    error!(target: "sync", "Failed to restart state sync from {}: {:?}", suggested_sync_hash, err);
+                    return;
+                }
+            }
+            self.client.sync_status =
+                SyncStatus::StateSync(suggested_sync_hash, HashMap::default());
+            return;
+        }
+
+        let shard_download = match &mut self.client.sync_status {
+            SyncStatus::StateSync(_, shards_to_download) if is_current_state_sync => {
+                shards_to_download.get_mut(&shard_id)
+            }
+            _ => self
+                .client
+                .catchup_state_syncs
+                .get_mut(&sync_hash)
+                .and_then(|(_, shards_to_download, _)| shards_to_download.get_mut(&shard_id)),
+        };
+        if let Some(shard_download) = shard_download {
+            for download in shard_download.downloads.iter_mut().filter(|download| !download.done) {
+                download.error = true;
+            }
+        }
+    }
+
+    /// Whether `hash` is the first block of an epoch on the chain ending at the header head.
+    fn is_epoch_start_on_header_chain(&mut self, hash: &CryptoHash) -> bool {
+        let height = match self.client.chain.get_block_header(hash) {
+            Ok(header) => header.height(),
+            Err(_) => return false,
+        };
+        let header_head = match self.client.chain.header_head() {
+            Ok(header_head) => header_head,
+            Err(_) => return false,
+        };
+        let on_header_chain = self
+            .client
+            .chain
+            .get_header_on_chain_by_height(&header_head.last_block_hash, height)
+            .map_or(false, |header| header.hash() == hash);
+        on_header_chain
+            && StateSync::get_epoch_start_sync_hash(&mut self.client.chain, hash)
+                .map_or(false, |epoch_start_hash| epoch_start_hash == *hash)
+    }
+
     /// To prevent syncing from a fork, we move `state_fetch_horizon` steps backwards and use that epoch.
     /// Usually `state_fetch_horizon` is much less than the expected number of produced blocks on an epoch,
     /// so this is only relevant on epoch boundaries.
@@ -1479,6 +1793,19 @@ impl ClientActor {
     /// Schedules itself again if it was not ran as response to state parts job result
     fn catchup(&mut self, ctx: &mut Context<ClientActor>) {
         let _d = delay_detector::DelayDetector::new(|| "client catchup".into());
+        self.run_catchup_step();
+
+        near_performance_metrics::actix::run_later(
+            ctx,
+            self.client.config.catchup_step_period,
+            move |act, ctx| {
+                act.catchup(ctx);
+            },
+        );
+    }
+
+    /// Runs a single catchup step and processes blocks accepted during it.
+    fn run_catchup_step(&mut self) {
         match self.client.run_catchup(
             &self.network_info.highest_height_peers,
             &self.state_parts_task_scheduler,
@@ -1492,14 +1819,6 @@ impl ClientActor {
                 error!(target: "client", "{:?} Error occurred during catchup for the next epoch: {:?}", self.client.validator_signer.as_ref().map(|vs| vs.validator_id()), err);
             }
         }
-
-        near_performance_metrics::actix::run_later(
-            ctx,
-            self.client.config.catchup_step_period,
-            move |act, ctx| {
-                act.catchup(ctx);
-            },
-        );
     }
 
     fn run_timer<F>(
@@ -1549,6 +1868,17 @@ impl ClientActor {
 
         let mut wait_period = self.client.config.sync_step_period;
 
+        // The provenance of the parts of abandoned state syncs isn't cleared with the parts.
+        let state_sync_hash = match &self.client.sync_status {
+            SyncStatus::StateSync(sync_hash, _) => Some(*sync_hash),
+            _ => None,
+        };
+        let catchup_state_syncs = &self.client.catchup_state_syncs;
+        self.client.chain.retain_state_part_provenance(|sync_hash| {
+            state_sync_hash.as_ref() == Some(sync_hash)
+                || catchup_state_syncs.contains_key(sync_hash)
+        });
+
         let currently_syncing = self.client.sync_status.is_syncing();
         let (needs_syncing, highest_height) = unwrap_or_run_later!(self.syncing_info());
 
@@ -1720,7 +2050,25 @@ impl ClientActor {
             } else {
                 false
             };
-            Some(ValidatorInfoHelper { is_validator, num_validators })
+            let approval_participation = account_id.and_then(|account_id| {
+                self.client.chain.approval_participation(&head.epoch_id).get(account_id).copied()
+            });
+            let num_chunks_included_late = account_id
+                .and_then(|account_id| {
+                    self.client
+                        .chain
+                        .chunk_inclusion_delay_stats()
+                        .delayed_chunks_per_producer
+                        .get(account_id)
+                        .copied()
+                })
+                .unwrap_or_default();
+            Some(ValidatorInfoHelper {
+                is_validator,
+                num_validators,
+                approval_participation,
+                num_chunks_included_late,
+            })
         } else {
             None
         };
@@ -1750,8 +2098,52 @@ impl ClientActor {
                 .unwrap_or(None)
                 .unwrap_or(0),
             self.client.chain.store().get_store_statistics(),
+            self.client.chain.estimate_sync_progress(self.network_height()).ok(),
+            self.client.chain.state_download_status().unwrap_or_default(),
         );
         debug!(target: "stats", "{}", self.client.detailed_upcoming_blocks_info().unwrap_or(String::from("Upcoming block info failed.")));
+        // Also refreshes the split state backlog metric.
+        if let Ok(backlog) = self.client.chain.split_state_backlog() {
+            if !backlog.is_empty() {
+                debug!(target: "stats", "Split state backlog: {} block and shard pairs, {} state changes", backlog.len(), backlog.iter().map(|entry| entry.num_changes).sum::<usize>());
+            }
+        }
+        let finality = self.client.chain.finality_latency_stats();
+        debug!(target: "stats", "Time to finality of the last {} final blocks: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}", finality.num_blocks, finality.p50, finality.p90, finality.p99, finality.max);
+        match self.client.chain.block_readiness_slo(
+            BLOCK_READINESS_SLO_WINDOW_EPOCHS,
+            self.client.config.min_block_production_delay,
+        ) {
+            Ok(slo) => {
+                debug!(target: "stats", "{} of {} blocks ready within {:?} ({:.1}%), p50 {:?}, p90 {:?}, p99 {:?}", slo.num_within_threshold, slo.num_blocks, self.client.config.min_block_production_delay, slo.percent_within_threshold, slo.p50, slo.p90, slo.p99)
+            }
+            Err(err) => debug!(target: "stats", "Failed to compute block readiness SLO: {}", err),
+        }
+        self.log_sync_stall(head.height);
+    }
+
+    /// Logs sync diagnostics if the head hasn't advanced for `sync_stall_log_threshold`.
+    fn log_sync_stall(&mut self, head_height: BlockHeight) {
+        let now = Clock::instant();
+        let since = match self.last_seen_head {
+            Some((height, since)) if height == head_height => since,
+            _ => {
+                self.last_seen_head = Some((head_height, now));
+                return;
+            }
+        };
+        let stalled_for = now.saturating_duration_since(since);
+        if stalled_for < self.client.config.sync_stall_log_threshold {
+            return;
+        }
+        match self.client.chain.sync_diagnostics() {
+            Ok(diagnostics) => {
+                warn!(target: "client", "Head is stuck at {} for {:?}, {}", head_height, stalled_for, diagnostics)
+            }
+            Err(err) => {
+                warn!(target: "client", "Head is stuck at {} for {:?}, failed to collect sync diagnostics: {}", head_height, stalled_for, err)
+            }
+        }
     }
 }
 
@@ -1763,6 +2155,9 @@ impl Drop for ClientActor {
 
 struct SyncJobsActor {
     client_addr: Addr<ClientActor>,
+    /// Jobs are skipped once the chain is shutting down, and the shutdown waits for the running
+    /// job to finish.
+    shutdown: ShutdownHandle,
 }
 
 impl SyncJobsActor {
@@ -1799,6 +2194,10 @@ impl Handler<ApplyStatePartsRequest> for SyncJobsActor {
     type Result = ();
 
     fn handle(&mut self, msg: ApplyStatePartsRequest, _: &mut Self::Context) -> Self::Result {
+        let _in_flight = match self.shutdown.enter() {
+            Ok(in_flight) => in_flight,
+            Err(_) => return,
+        };
         let result = self.apply_parts(&msg);
 
         self.client_addr.do_send(ApplyStatePartsResponse {
@@ -1826,6 +2225,10 @@ impl Handler<BlockCatchUpRequest> for SyncJobsActor {
     type Result = ();
 
     fn handle(&mut self, msg: BlockCatchUpRequest, _: &mut Self::Context) -> Self::Result {
+        let _in_flight = match self.shutdown.enter() {
+            Ok(in_flight) => in_flight,
+            Err(_) => return,
+        };
         let results = do_apply_chunks(msg.work);
 
         self.client_addr.do_send(BlockCatchUpResponse {
@@ -1860,6 +2263,10 @@ impl Handler<StateSplitRequest> for SyncJobsActor {
     type Result = ();
 
     fn handle(&mut self, msg: StateSplitRequest, _: &mut Self::Context) -> Self::Result {
+        let _in_flight = match self.shutdown.enter() {
+            Ok(in_flight) => in_flight,
+            Err(_) => return,
+        };
         let results = msg.runtime.build_state_for_split_shards(
             msg.shard_uid,
             &msg.state_root,
@@ -1904,12 +2311,13 @@ pub fn start_client(
     validator_signer: Option<Arc<dyn ValidatorSigner>>,
     telemetry_actor: Addr<TelemetryActor>,
     sender: Option<oneshot::Sender<()>>,
+    shared_chain_cache: Option<Arc<SharedChainCache>>,
     #[cfg(feature = "test_features")] adv: Arc<std::sync::RwLock<crate::AdversarialControls>>,
 ) -> (Addr<ClientActor>, ArbiterHandle) {
     let client_arbiter = Arbiter::new();
     let client_arbiter_handle = client_arbiter.handle();
     let client_addr = ClientActor::start_in_arbiter(&client_arbiter_handle, move |ctx| {
-        ClientActor::new(
+        let mut client_actor = ClientActor::new(
             client_config,
             chain_genesis,
             runtime_adapter,
@@ -1924,7 +2332,12 @@ pub fn start_client(
             #[cfg(feature = "test_features")]
             adv,
         )
-        .unwrap()
+        .unwrap();
+        // Blocks garbage collected by the client must be invalidated in the view client's cache.
+        if let Some(shared_chain_cache) = shared_chain_cache {
+            client_actor.client.chain.set_shared_chain_cache(shared_chain_cache);
+        }
+        client_actor
     });
     (client_addr, client_arbiter_handle)
 }
@@ -1,5 +1,6 @@
 use crate::{metrics, rocksdb_metrics, SyncStatus};
 use actix::Addr;
+use near_chain::chain::{ApprovalParticipation, EpochStateDownloadStatus, SyncProgressEstimate};
 use near_chain_configs::{ClientConfig, LogSummaryStyle};
 use near_client_primitives::types::ShardSyncStatus;
 use near_network::types::NetworkInfo;
@@ -19,6 +20,7 @@ use near_telemetry::{telemetry, TelemetryActor};
 use std::cmp::min;
 use std::fmt::Write;
 use std::sync::Arc;
+use std::time::Duration;
 use sysinfo::{get_current_pid, set_open_files_limit, Pid, ProcessExt, System, SystemExt};
 use tracing::info;
 
@@ -27,6 +29,10 @@ const TERAGAS: f64 = 1_000_000_000_000_f64;
 pub struct ValidatorInfoHelper {
     pub is_validator: bool,
     pub num_validators: usize,
+    /// How often the approvals of this node made it into the blocks of the current epoch.
+    pub approval_participation: Option<ApprovalParticipation>,
+    /// Number of chunks of this node included late since the node started.
+    pub num_chunks_included_late: u64,
 }
 
 /// A helper that prints information about current chain and reports to telemetry.
@@ -51,6 +57,8 @@ pub struct InfoHelper {
     telemetry_actor: Addr<TelemetryActor>,
     /// Log coloring enabled
     log_summary_style: LogSummaryStyle,
+    /// Number of chunks of this node included late as of the previous summary.
+    num_chunks_included_late: u64,
 }
 
 impl InfoHelper {
@@ -71,6 +79,7 @@ impl InfoHelper {
             telemetry_actor,
             validator_signer,
             log_summary_style: client_config.log_summary_style,
+            num_chunks_included_late: 0,
         }
     }
 
@@ -102,6 +111,8 @@ impl InfoHelper {
         epoch_height: EpochHeight,
         protocol_upgrade_block_height: BlockHeight,
         statistics: Option<StoreStatistics>,
+        sync_progress: Option<SyncProgressEstimate>,
+        state_downloads: Vec<EpochStateDownloadStatus>,
     ) {
         let use_colour = matches!(self.log_summary_style, LogSummaryStyle::Colored);
         let paint = |colour: ansi_term::Colour, text: Option<String>| match text {
@@ -112,14 +123,49 @@ impl InfoHelper {
 
         let s = |num| if num == 1 { "" } else { "s" };
 
-        let sync_status_log = Some(display_sync_status(sync_status, head, genesis_height));
+        let mut sync_status_log = display_sync_status(sync_status, head, genesis_height);
+        if let Some(eta) = sync_progress.and_then(|progress| progress.eta) {
+            if sync_status.is_syncing() {
+                write!(sync_status_log, " ETA {}", pretty_duration(eta)).unwrap();
+            }
+        }
+        for state_download in &state_downloads {
+            sync_status_log.push_str(&display_state_download(state_download));
+        }
+        let sync_status_log = Some(sync_status_log);
 
+        let prev_num_chunks_included_late = self.num_chunks_included_late;
+        if let Some(info) = validator_info.as_ref() {
+            self.num_chunks_included_late = info.num_chunks_included_late;
+        }
         let validator_info_log = validator_info.as_ref().map(|info| {
+            let approvals = match info.approval_participation {
+                Some(participation) if info.is_validator => format!(
+                    " {}/{} approvals included",
+                    participation.num_included,
+                    participation.num_included + participation.num_missing
+                ),
+                _ => String::new(),
+            };
+            // Flags the chunks of this node included late since the previous summary.
+            let num_new_chunks_included_late =
+                info.num_chunks_included_late.saturating_sub(prev_num_chunks_included_late);
+            let chunks_included_late = if num_new_chunks_included_late > 0 {
+                format!(
+                    " | {} own chunk{} included late",
+                    num_new_chunks_included_late,
+                    s(num_new_chunks_included_late as usize)
+                )
+            } else {
+                String::new()
+            };
             format!(
-                " {}{} validator{}",
+                " {}{} validator{}{}{}",
                 if info.is_validator { "Validator | " } else { "" },
                 info.num_validators,
-                s(info.num_validators)
+                s(info.num_validators),
+                approvals,
+                chunks_included_late
             )
         });
 
@@ -335,6 +381,37 @@ fn pretty_bytes(num: u64) -> String {
     }
 }
 
+fn display_state_download(status: &EpochStateDownloadStatus) -> String {
+    let shards = status
+        .shards
+        .iter()
+        .map(|shard| {
+            let stage = if shard.finalized {
+                "finalized".to_string()
+            } else if let Some(height) = shard.finalize_height {
+                format!("finalizing #{}", height)
+            } else if shard.header_stored {
+                format!("parts {}/{}", shard.parts_downloaded, shard.parts_total)
+            } else {
+                "header".to_string()
+            };
+            format!("shard {} {}", shard.shard_id, stage)
+        })
+        .collect::<Vec<_>>();
+    format!(" | catchup state of epoch {}: {}", status.epoch_id.0, shards.join(", "))
+}
+
+fn pretty_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / (60 * 60), secs / 60 % 60)
+    }
+}
+
 fn gas_used_per_sec(num: u64) -> String {
     if num < 1000 {
         format!("{} gas/s", num)
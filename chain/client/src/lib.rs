@@ -1,10 +1,13 @@
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockHash, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
-    GetChunk, GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock,
-    GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt,
-    GetStateChanges, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered, Query,
-    QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    Error, GetAccountShard, GetBlock, GetBlockByOrdinal, GetBlockHash, GetBlockProof,
+    GetBlockProofResponse, GetBlockWithMerkleTree, GetBlocksAtHeight, GetChainParameters, GetChunk,
+    GetDiscardedForks, GetEpochSummary, GetExecutionOutcome, GetExecutionOutcomeResponse,
+    GetExecutionOutcomesForBlock, GetGasPrice, GetGcPlan, GetNetworkInfo, GetNextLightClientBlock,
+    GetOutcomeAncestry, GetPartOwnership, GetPartialTransactionResult, GetProtocolConfig,
+    GetReceipt, GetRejectedBlocks, GetShardLayout, GetStateChanges, GetStateChangesInBlock,
+    GetStateChangesWithCauseInBlock, GetStateChangesWithCauseInBlockForTrackedShards,
+    GetSyncDiagnostics, GetValidatorInfo, GetValidatorOrdered, Query, QueryError, Status,
+    StatusResponse, SyncStatus, TxStatus, TxStatusError,
 };
 
 pub use crate::client::Client;
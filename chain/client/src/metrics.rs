@@ -174,3 +174,11 @@ pub static CLIENT_TRIGGER_TIME_BY_TYPE: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static TX_ROUTING_POLICY_DECISIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_tx_routing_policy_decisions",
+        "Number of transactions accepted and rejected by the transaction routing policy",
+        &["policy", "decision"],
+    )
+    .unwrap()
+});
@@ -14,7 +14,7 @@ use near_primitives::time::Utc;
 use num_rational::Rational;
 use rand::{thread_rng, Rng};
 
-use near_chain::test_utils::KeyValueRuntime;
+use near_chain::test_utils::{KeyValueRuntime, TEST_GENESIS_GAS_LIMIT};
 use near_chain::{
     Chain, ChainGenesis, ChainStoreAccess, DoomslugThresholdMode, Provenance, RuntimeAdapter,
 };
@@ -103,7 +103,7 @@ pub fn setup(
     let chain_genesis = ChainGenesis {
         time: genesis_time,
         height: 0,
-        gas_limit: 1_000_000,
+        gas_limit: TEST_GENESIS_GAS_LIMIT,
         min_gas_price: 100,
         max_gas_price: 1_000_000_000,
         total_supply: 3_000_000_000_000_000_000_000_000_000_000_000,
@@ -144,6 +144,7 @@ pub fn setup(
         runtime.clone(),
         network_adapter.clone(),
         config.clone(),
+        None,
         #[cfg(feature = "test_features")]
         adv.clone(),
     );
@@ -196,7 +197,7 @@ pub fn setup_only_view(
     let chain_genesis = ChainGenesis {
         time: genesis_time,
         height: 0,
-        gas_limit: 1_000_000,
+        gas_limit: TEST_GENESIS_GAS_LIMIT,
         min_gas_price: 100,
         max_gas_price: 1_000_000_000,
         total_supply: 3_000_000_000_000_000_000_000_000_000_000_000,
@@ -237,6 +238,7 @@ pub fn setup_only_view(
         runtime,
         network_adapter.clone(),
         config,
+        None,
         #[cfg(feature = "test_features")]
         adv.clone(),
     )
@@ -817,6 +819,7 @@ pub fn setup_mock_all_validators(
                             for (i, name) in validators_clone2.iter().flatten().enumerate() {
                                 if name == target_account_id {
                                     let connectors2 = connectors1.clone();
+                                    let peer_id = key_pairs[i].id.clone();
                                     actix::spawn(
                                         connectors1.read().unwrap()[i]
                                             .1
@@ -835,6 +838,7 @@ pub fn setup_mock_all_validators(
                                                             .do_send(
                                                             NetworkClientMessages::StateResponse(
                                                                 *response,
+                                                                peer_id,
                                                             ),
                                                         );
                                                     }
@@ -860,6 +864,7 @@ pub fn setup_mock_all_validators(
                             for (i, name) in validators_clone2.iter().flatten().enumerate() {
                                 if name == target_account_id {
                                     let connectors2 = connectors1.clone();
+                                    let peer_id = key_pairs[i].id.clone();
                                     actix::spawn(
                                         connectors1.read().unwrap()[i]
                                             .1
@@ -879,6 +884,7 @@ pub fn setup_mock_all_validators(
                                                             .do_send(
                                                             NetworkClientMessages::StateResponse(
                                                                 *response,
+                                                                peer_id,
                                                             ),
                                                         );
                                                     }
@@ -895,7 +901,10 @@ pub fn setup_mock_all_validators(
                             for (i, address) in addresses.iter().enumerate() {
                                 if route_back == address {
                                     connectors1.read().unwrap()[i].0.do_send(
-                                        NetworkClientMessages::StateResponse(response.clone()),
+                                        NetworkClientMessages::StateResponse(
+                                            response.clone(),
+                                            my_key_pair.id.clone(),
+                                        ),
                                     );
                                 }
                             }
@@ -163,11 +163,15 @@ fn test_execution_outcome_for_chunk() {
                 FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(outcome) => {
                     outcome.into()
                 }
+                FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeLite(_) => {
+                    panic!("the transaction should be known")
+                }
             };
 
             let mut execution_outcomes_in_block = view_client
                 .send(GetExecutionOutcomesForBlock {
                     block_hash: feo.transaction_outcome.block_hash,
+                    verified: true,
                 })
                 .await
                 .unwrap()
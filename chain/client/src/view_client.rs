@@ -11,6 +11,10 @@ use std::time::{Duration, Instant};
 use actix::{Actor, Addr, Handler, SyncArbiter, SyncContext};
 use tracing::{debug, error, info, trace, warn};
 
+use near_chain::chain::HeightStatus;
+use near_chain::shared_chain_cache::SharedChainCache;
+use near_chain::state_header_cache::StateHeaderCache;
+use near_chain::sync_hash_registry::SyncHashRegistry;
 use near_chain::types::ValidatorInfoIdentifier;
 use near_chain::{
     get_epoch_block_producers_view, Chain, ChainGenesis, ChainStoreAccess, DoomslugThresholdMode,
@@ -18,20 +22,23 @@ use near_chain::{
 };
 use near_chain_configs::{ClientConfig, ProtocolConfigView};
 use near_client_primitives::types::{
-    Error, GetBlock, GetBlockError, GetBlockHash, GetBlockProof, GetBlockProofError,
-    GetBlockProofResponse, GetBlockWithMerkleTree, GetChunkError, GetExecutionOutcome,
-    GetExecutionOutcomeError, GetExecutionOutcomesForBlock, GetGasPrice, GetGasPriceError,
-    GetNextLightClientBlockError, GetProtocolConfig, GetProtocolConfigError, GetReceipt,
-    GetReceiptError, GetStateChangesError, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfoError, Query, QueryError,
-    TxStatus, TxStatusError,
+    Error, GetAccountShard, GetBlock, GetBlockByOrdinal, GetBlockError, GetBlockHash,
+    GetBlockProof, GetBlockProofError, GetBlockProofResponse, GetBlockWithMerkleTree,
+    GetChainParameters, GetChainParametersError, GetChunkError, GetEpochSummary,
+    GetEpochSummaryError, GetExecutionOutcome, GetExecutionOutcomeError,
+    GetExecutionOutcomesForBlock, GetGasPrice, GetGasPriceError, GetNextLightClientBlockError,
+    GetOutcomeAncestry, GetOutcomeAncestryError, GetPartialTransactionResult,
+    GetPartialTransactionResultError, GetProtocolConfig, GetProtocolConfigError, GetReceipt,
+    GetReceiptError, GetShardLayout, GetShardLayoutError, GetStateChangesError,
+    GetStateChangesWithCauseInBlock, GetStateChangesWithCauseInBlockForTrackedShards,
+    GetValidatorInfoError, Query, QueryError, TxStatus, TxStatusError,
 };
 use near_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
 #[cfg(feature = "test_features")]
 use near_network_primitives::types::NetworkAdversarialMessage;
 use near_network_primitives::types::{
     NetworkViewClientMessages, NetworkViewClientResponses, ReasonForBan, StateResponseInfo,
-    StateResponseInfoV1, StateResponseInfoV2,
+    StateResponseInfoV1, StateResponseInfoV2, StateResponseInfoV3,
 };
 use near_performance_metrics_macros::{perf, perf_with_debug};
 use near_primitives::block::{Block, BlockHeader, GenesisId, Tip};
@@ -49,10 +56,11 @@ use near_primitives::types::{
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
-    LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView,
+    AccountShardView, BlockView, ChainParametersView, ChunkView, EpochSummaryView,
+    EpochValidatorInfo, ExecutionOutcomeWithIdView, FinalExecutionOutcomeView,
+    FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView, LightClientBlockView,
+    PartialFinalExecutionOutcomeView, QueryRequest, QueryResponse, ReceiptView, ShardLayoutView,
+    StateChangesKindsView, StateChangesView,
 };
 
 use crate::{
@@ -127,14 +135,25 @@ impl ViewClientActor {
         network_adapter: Arc<dyn PeerManagerAdapter>,
         config: ClientConfig,
         request_manager: Arc<RwLock<ViewClientRequestManager>>,
+        state_header_cache: Arc<StateHeaderCache>,
+        sync_hash_registry: Arc<SyncHashRegistry>,
+        shared_chain_cache: Option<Arc<SharedChainCache>>,
         #[cfg(feature = "test_features")] adv: Arc<RwLock<AdversarialControls>>,
     ) -> Result<Self, Error> {
         // TODO: should we create shared ChainStore that is passed to both Client and ViewClient?
-        let chain = Chain::new_for_view_client(
+        let mut chain = Chain::new_for_view_client(
             runtime_adapter.clone(),
             chain_genesis,
             DoomslugThresholdMode::TwoThirds,
         )?;
+        if let Some(overrides) = config.economics_overrides.clone() {
+            chain.set_economics_overrides(&config.chain_id, overrides)?;
+        }
+        chain.set_state_header_cache(state_header_cache);
+        chain.set_sync_hash_registry(sync_hash_registry);
+        if let Some(shared_chain_cache) = shared_chain_cache {
+            chain.set_shared_chain_cache(shared_chain_cache);
+        }
         Ok(ViewClientActor {
             #[cfg(feature = "test_features")]
             adv,
@@ -426,6 +445,28 @@ impl ViewClientActor {
                 }
                 Err(e) => match e.kind() {
                     ErrorKind::DBNotFoundErr(_) => {
+                        // The transaction may be gone while its outcomes are still kept.
+                        if let Ok(tx_result) =
+                            self.chain.get_final_transaction_result_lite(&tx_hash)
+                        {
+                            match &tx_result.status {
+                                FinalExecutionStatus::NotStarted
+                                | FinalExecutionStatus::Started => {
+                                    for receipt_view in tx_result.receipts_outcome.iter() {
+                                        self.request_receipt_outcome(
+                                            receipt_view.id,
+                                            &head.epoch_id,
+                                            &head.last_block_hash,
+                                        )?;
+                                    }
+                                }
+                                FinalExecutionStatus::SuccessValue(_)
+                                | FinalExecutionStatus::Failure(_) => {}
+                            }
+                            return Ok(Some(
+                                FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeLite(tx_result),
+                            ));
+                        }
                         if let Ok(execution_outcome) = self.chain.get_execution_outcome(&tx_hash) {
                             for receipt_id in execution_outcome.outcome_with_id.outcome.receipt_ids
                             {
@@ -519,6 +560,62 @@ impl ViewClientActor {
         cache.push_back(now);
         true
     }
+
+    /// Response to a state request with an invalid sync hash: an empty state response with the
+    /// sync hash to use instead if the requested one is next to an epoch boundary, no response
+    /// otherwise.
+    fn invalid_sync_hash_response(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> NetworkViewClientResponses {
+        match self.chain.suggest_sync_hash(&sync_hash) {
+            Ok(Some(suggested_sync_hash)) => {
+                debug!(target: "sync", "sync_hash {:?} is not on an epoch boundary, suggesting {:?}", sync_hash, suggested_sync_hash);
+                Self::sync_hash_suggestion_response(shard_id, sync_hash, suggested_sync_hash)
+            }
+            _ => {
+                warn!(target: "sync", "sync_hash {:?} didn't pass validation, possible malicious behavior", sync_hash);
+                NetworkViewClientResponses::NoResponse
+            }
+        }
+    }
+
+    /// Response to a state request rejected because state is served for too many sync hashes,
+    /// suggesting one of the sync hashes already served.
+    fn busy_sync_hash_response(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> NetworkViewClientResponses {
+        match self.chain.suggest_served_sync_hash(&sync_hash) {
+            Some(suggested_sync_hash) => {
+                debug!(target: "sync", "Too many sync hashes served, suggesting {:?} instead of {:?}", suggested_sync_hash, sync_hash);
+                Self::sync_hash_suggestion_response(shard_id, sync_hash, suggested_sync_hash)
+            }
+            None => {
+                debug!(target: "sync", "Too many sync hashes served, ignoring state request for {:?}", sync_hash);
+                NetworkViewClientResponses::NoResponse
+            }
+        }
+    }
+
+    fn sync_hash_suggestion_response(
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        suggested_sync_hash: CryptoHash,
+    ) -> NetworkViewClientResponses {
+        let info = StateResponseInfo::V3(StateResponseInfoV3 {
+            shard_id,
+            sync_hash,
+            state_response: ShardStateSyncResponse::V2(ShardStateSyncResponseV2 {
+                header: None,
+                part: None,
+            }),
+            suggested_sync_hash: Some(suggested_sync_hash),
+        });
+        NetworkViewClientResponses::StateResponse(Box::new(info))
+    }
 }
 
 impl Actor for ViewClientActor {
@@ -546,7 +643,18 @@ impl Handler<GetBlock> for ViewClientActor {
                 self.chain.get_block(&block_hash).map(Clone::clone)
             }
             BlockReference::BlockId(BlockId::Height(height)) => {
-                self.chain.get_block_by_height(height).map(Clone::clone)
+                match self.chain.height_status(height)? {
+                    HeightStatus::Block(block_hash) => {
+                        self.chain.get_block(&block_hash).map(Clone::clone)
+                    }
+                    HeightStatus::Skipped => return Err(GetBlockError::SkippedHeight { height }),
+                    HeightStatus::NotYetProduced => {
+                        return Err(GetBlockError::NotYetProduced { height })
+                    }
+                    HeightStatus::GarbageCollected => {
+                        return Err(GetBlockError::GarbageCollected { height })
+                    }
+                }
             }
             BlockReference::BlockId(BlockId::Hash(hash)) => {
                 self.chain.get_block(&hash).map(Clone::clone)
@@ -570,6 +678,22 @@ impl Handler<GetBlock> for ViewClientActor {
     }
 }
 
+/// Handles retrieving a block of the canonical chain by its ordinal.
+impl Handler<GetBlockByOrdinal> for ViewClientActor {
+    type Result = Result<BlockView, GetBlockError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetBlockByOrdinal, _: &mut Self::Context) -> Self::Result {
+        let block_hash = *self.chain.get_block_header_by_ordinal(msg.ordinal)?.hash();
+        let block = self.chain.get_block(&block_hash)?.clone();
+        let block_author = self
+            .runtime_adapter
+            .get_block_producer(block.header().epoch_id(), block.header().height())?;
+
+        Ok(BlockView::from_author_block(block_author, block))
+    }
+}
+
 /// Handles retrieving block header from the chain.
 impl Handler<GetBlockHash> for ViewClientActor {
     type Result = Result<CryptoHash, GetBlockError>;
@@ -851,8 +975,9 @@ impl Handler<GetNextLightClientBlock> for ViewClientActor {
                 Ok(Some(ret))
             }
         } else {
-            match self.chain.mut_store().get_epoch_light_client_block(&last_next_epoch_id.0) {
-                Ok(light_block) => Ok(Some(light_block.clone())),
+            // Regenerate the record if it's missing, e.g. in databases created before it was saved.
+            match self.chain.get_light_client_block_at_epoch(&last_next_epoch_id, true) {
+                Ok(light_block) => Ok(Some(light_block)),
                 Err(e) => {
                     if let ErrorKind::DBNotFoundErr(_) = e.kind() {
                         Ok(None)
@@ -954,7 +1079,7 @@ impl Handler<GetExecutionOutcomesForBlock> for ViewClientActor {
     fn handle(&mut self, msg: GetExecutionOutcomesForBlock, _: &mut Self::Context) -> Self::Result {
         Ok(self
             .chain
-            .get_block_execution_outcomes(&msg.block_hash)
+            .get_block_execution_outcomes(&msg.block_hash, msg.verified)
             .map_err(|e| e.to_string())?
             .into_iter()
             .map(|(k, v)| (k, v.into_iter().map(Into::into).collect()))
@@ -975,16 +1100,98 @@ impl Handler<GetReceipt> for ViewClientActor {
     }
 }
 
+impl Handler<GetOutcomeAncestry> for ViewClientActor {
+    type Result = Result<Vec<CryptoHash>, GetOutcomeAncestryError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetOutcomeAncestry, _: &mut Self::Context) -> Self::Result {
+        self.chain.get_outcome_ancestry(&msg.id).map_err(|e| match e.kind() {
+            ErrorKind::DBNotFoundErr(_) => GetOutcomeAncestryError::UnknownOutcome(msg.id),
+            _ => e.into(),
+        })
+    }
+}
+
+impl Handler<GetPartialTransactionResult> for ViewClientActor {
+    type Result = Result<PartialFinalExecutionOutcomeView, GetPartialTransactionResultError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetPartialTransactionResult, _: &mut Self::Context) -> Self::Result {
+        self.chain.get_final_transaction_result_opts(&msg.tx_hash, true).map_err(|e| {
+            match e.kind() {
+                ErrorKind::DBNotFoundErr(_) => {
+                    GetPartialTransactionResultError::UnknownTransaction(msg.tx_hash)
+                }
+                _ => e.into(),
+            }
+        })
+    }
+}
+
+impl Handler<GetEpochSummary> for ViewClientActor {
+    type Result = Result<EpochSummaryView, GetEpochSummaryError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetEpochSummary, _: &mut Self::Context) -> Self::Result {
+        Ok(self.chain.get_epoch_summary(msg.epoch_height)?.into())
+    }
+}
+
+impl Handler<GetShardLayout> for ViewClientActor {
+    type Result = Result<ShardLayoutView, GetShardLayoutError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetShardLayout, _: &mut Self::Context) -> Self::Result {
+        Ok(self.chain.get_shard_layout_view(msg.epoch_id)?)
+    }
+}
+
+impl Handler<GetAccountShard> for ViewClientActor {
+    type Result = Result<AccountShardView, GetShardLayoutError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetAccountShard, _: &mut Self::Context) -> Self::Result {
+        let epoch_id = match msg.epoch_id {
+            Some(epoch_id) => epoch_id,
+            None => self.chain.head()?.epoch_id,
+        };
+        let (shard_id, shard_uid) =
+            self.chain.resolve_account_shard(&msg.account_id, Some(epoch_id.clone()))?;
+        Ok(AccountShardView {
+            account_id: msg.account_id,
+            epoch_id,
+            shard_id,
+            shard_layout_version: shard_uid.version,
+        })
+    }
+}
+
+impl Handler<GetChainParameters> for ViewClientActor {
+    type Result = Result<ChainParametersView, GetChainParametersError>;
+
+    #[perf]
+    fn handle(&mut self, _msg: GetChainParameters, _: &mut Self::Context) -> Self::Result {
+        Ok(self.chain.chain_parameters()?)
+    }
+}
+
 impl Handler<GetBlockProof> for ViewClientActor {
     type Result = Result<GetBlockProofResponse, GetBlockProofError>;
 
     #[perf]
     fn handle(&mut self, msg: GetBlockProof, _: &mut Self::Context) -> Self::Result {
-        self.chain.check_block_final_and_canonical(&msg.block_hash)?;
-        self.chain.check_block_final_and_canonical(&msg.head_block_hash)?;
+        let block_proof = self.chain.get_execution_outcome_with_block_proof(
+            &msg.block_hash,
+            &msg.head_block_hash,
+            msg.allow_non_final,
+        )?;
         let block_header_lite = self.chain.get_block_header(&msg.block_hash)?.clone().into();
-        let block_proof = self.chain.get_block_proof(&msg.block_hash, &msg.head_block_hash)?;
-        Ok(GetBlockProofResponse { block_header_lite, proof: block_proof })
+        Ok(GetBlockProofResponse {
+            block_header_lite,
+            proof: block_proof.proof,
+            head_block_hash: block_proof.head_block_hash,
+            finality: block_proof.finality,
+        })
     }
 }
 
@@ -1068,6 +1275,10 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                         FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(
                             outcome,
                         ) => outcome.into(),
+                        // Peers expect the transaction in the response.
+                        FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeLite(_) => {
+                            return NetworkViewClientResponses::NoResponse;
+                        }
                     };
                     NetworkViewClientResponses::TxStatus(Box::new(result))
                 } else {
@@ -1204,11 +1415,21 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                     return NetworkViewClientResponses::NoResponse;
                 }
 
-                let state_response = match self.chain.check_sync_hash_validity(&sync_hash) {
+                let state_response = match self.chain.check_state_request_sync_hash(&sync_hash) {
                     Ok(true) => {
                         let header = match self.chain.get_state_response_header(shard_id, sync_hash)
                         {
                             Ok(header) => Some(header),
+                            Err(e)
+                                if matches!(
+                                    e.kind(),
+                                    ErrorKind::StateSyncHeaderInFlight { .. }
+                                ) =>
+                            {
+                                // The requester retries, by then the header is cached.
+                                debug!(target: "sync", "{}, ignoring state request header", e);
+                                return NetworkViewClientResponses::NoResponse;
+                            }
                             Err(e) => {
                                 error!(target: "sync", "Cannot build sync header (get_state_response_header): {}", e);
                                 None
@@ -1234,10 +1455,12 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                         }
                     }
                     Ok(false) => {
-                        warn!(target: "sync", "sync_hash {:?} didn't pass validation, possible malicious behavior", sync_hash);
-                        return NetworkViewClientResponses::NoResponse;
+                        return self.invalid_sync_hash_response(shard_id, sync_hash);
                     }
                     Err(e) => match e.kind() {
+                        ErrorKind::StateSyncBusy(_) => {
+                            return self.busy_sync_hash_response(shard_id, sync_hash);
+                        }
                         ErrorKind::DBNotFoundErr(_) => {
                             // This case may appear in case of latency in epoch switching.
                             // Request sender is ready to sync but we still didn't get the block.
@@ -1280,11 +1503,14 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                     return NetworkViewClientResponses::NoResponse;
                 }
                 trace!(target: "sync", "Computing state request part {} {} {}", shard_id, sync_hash, part_id);
-                let state_response = match self.chain.check_sync_hash_validity(&sync_hash) {
+                let state_response = match self.chain.check_state_request_sync_hash(&sync_hash) {
                     Ok(true) => {
+                        // A generated part is written into the response in pieces, straight from
+                        // the trie nodes it's made of.
                         let part = match self
                             .chain
                             .get_state_response_part(shard_id, part_id, sync_hash)
+                            .and_then(|part| Ok(part.into_vec()?))
                         {
                             Ok(part) => Some((part_id, part)),
                             Err(e) => {
@@ -1297,10 +1523,12 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                         ShardStateSyncResponseV1 { header: None, part }
                     }
                     Ok(false) => {
-                        warn!(target: "sync", "sync_hash {:?} didn't pass validation, possible malicious behavior", sync_hash);
-                        return NetworkViewClientResponses::NoResponse;
+                        return self.invalid_sync_hash_response(shard_id, sync_hash);
                     }
                     Err(e) => match e.kind() {
+                        ErrorKind::StateSyncBusy(_) => {
+                            return self.busy_sync_hash_response(shard_id, sync_hash);
+                        }
                         ErrorKind::DBNotFoundErr(_) => {
                             // This case may appear in case of latency in epoch switching.
                             // Request sender is ready to sync but we still didn't get the block.
@@ -1379,15 +1607,22 @@ impl Handler<GetGasPrice> for ViewClientActor {
 }
 
 /// Starts the View Client in a new arbiter (thread).
+/// `shared_chain_cache` must also be given to the client, which garbage collects the chain.
 pub fn start_view_client(
     validator_account_id: Option<AccountId>,
     chain_genesis: ChainGenesis,
     runtime_adapter: Arc<dyn RuntimeAdapter>,
     network_adapter: Arc<dyn PeerManagerAdapter>,
     config: ClientConfig,
+    shared_chain_cache: Option<Arc<SharedChainCache>>,
     #[cfg(feature = "test_features")] adv: Arc<RwLock<AdversarialControls>>,
 ) -> Addr<ViewClientActor> {
     let request_manager = Arc::new(RwLock::new(ViewClientRequestManager::new()));
+    let state_header_cache = Arc::new(StateHeaderCache::default());
+    let sync_hash_registry = Arc::new(SyncHashRegistry::new(
+        config.state_sync_max_served_sync_hashes,
+        config.state_sync_served_sync_hashes_window,
+    ));
     SyncArbiter::start(config.view_client_threads, move || {
         // ViewClientActor::start_in_arbiter(&Arbiter::current(), move |_ctx| {
         let validator_account_id1 = validator_account_id.clone();
@@ -1395,6 +1630,9 @@ pub fn start_view_client(
         let network_adapter1 = network_adapter.clone();
         let config1 = config.clone();
         let request_manager1 = request_manager.clone();
+        let state_header_cache1 = state_header_cache.clone();
+        let sync_hash_registry1 = sync_hash_registry.clone();
+        let shared_chain_cache1 = shared_chain_cache.clone();
         ViewClientActor::new(
             validator_account_id1,
             &chain_genesis,
@@ -1402,6 +1640,9 @@ pub fn start_view_client(
             network_adapter1,
             config1,
             request_manager1,
+            state_header_cache1,
+            sync_hash_registry1,
+            shared_chain_cache1,
             #[cfg(feature = "test_features")]
             adv.clone(),
         )
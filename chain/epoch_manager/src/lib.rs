@@ -723,6 +723,17 @@ impl EpochManager {
         Ok(result)
     }
 
+    /// Returns whether the block after the given parent needs approvals from the block producers
+    /// of the next epoch too. Blocks of the same epoch with the same result have the same
+    /// approvers, see `get_all_block_approvers_ordered`.
+    pub fn next_block_needs_next_epoch_approvals(
+        &mut self,
+        parent_hash: &CryptoHash,
+    ) -> Result<bool, EpochError> {
+        let block_info = self.get_block_info(parent_hash)?.clone();
+        self.next_block_need_approvals_from_next_epoch(&block_info)
+    }
+
     pub fn get_all_block_approvers_ordered(
         &mut self,
         parent_hash: &CryptoHash,
@@ -869,6 +880,24 @@ impl EpochManager {
         }
     }
 
+    /// For every shard of the epoch, whether `account_id` is one of its chunk producers.
+    pub fn shards_produced_by(
+        &mut self,
+        epoch_id: &EpochId,
+        account_id: &AccountId,
+    ) -> Result<Vec<bool>, EpochError> {
+        let epoch_info = self.get_epoch_info(epoch_id)?;
+        Ok(epoch_info
+            .chunk_producers_settlement()
+            .iter()
+            .map(|chunk_producers| {
+                chunk_producers.iter().any(|validator_id| {
+                    epoch_info.validator_account_id(*validator_id) == account_id
+                })
+            })
+            .collect())
+    }
+
     /// Returns true if next block after given block hash is in the new epoch.
     #[allow(clippy::wrong_self_convention)]
     pub fn is_next_block_epoch_start(
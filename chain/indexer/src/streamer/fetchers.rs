@@ -78,7 +78,7 @@ pub(crate) async fn fetch_outcomes(
     FailedToFetchData,
 > {
     let outcomes = client
-        .send(near_client::GetExecutionOutcomesForBlock { block_hash })
+        .send(near_client::GetExecutionOutcomesForBlock { block_hash, verified: false })
         .await?
         .map_err(FailedToFetchData::String)?;
 
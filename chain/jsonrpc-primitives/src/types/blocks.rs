@@ -14,14 +14,40 @@ pub enum RpcBlockError {
     },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
+    #[error("No block was produced at height {height}")]
+    SkippedHeight { height: near_primitives::types::BlockHeight },
+    #[error("Block at height {height} has not been produced yet")]
+    NotYetProduced { height: near_primitives::types::BlockHeight },
+    #[error("Block at height {height} has been garbage collected")]
+    GarbageCollected { height: near_primitives::types::BlockHeight },
+    #[error("Block ordinal {ordinal} is out of range, the maximum known ordinal is {max_ordinal}")]
+    OrdinalOutOfRange {
+        ordinal: near_primitives::types::NumBlocks,
+        max_ordinal: near_primitives::types::NumBlocks,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
 
+/// Identifies the requested block either by a regular `BlockReference` or by its ordinal on the
+/// canonical chain, e.g. `{"ordinal": 42}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RpcBlockReference {
+    BlockReference(near_primitives::types::BlockReference),
+    Ordinal { ordinal: near_primitives::types::NumBlocks },
+}
+
+impl From<near_primitives::types::BlockReference> for RpcBlockReference {
+    fn from(block_reference: near_primitives::types::BlockReference) -> Self {
+        Self::BlockReference(block_reference)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcBlockRequest {
     #[serde(flatten)]
-    pub block_reference: near_primitives::types::BlockReference,
+    pub block_reference: RpcBlockReference,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +63,19 @@ impl From<near_client_primitives::types::GetBlockError> for RpcBlockError {
                 Self::UnknownBlock { error_message }
             }
             near_client_primitives::types::GetBlockError::NotSyncedYet => Self::NotSyncedYet,
+            near_client_primitives::types::GetBlockError::SkippedHeight { height } => {
+                Self::SkippedHeight { height }
+            }
+            near_client_primitives::types::GetBlockError::NotYetProduced { height } => {
+                Self::NotYetProduced { height }
+            }
+            near_client_primitives::types::GetBlockError::GarbageCollected { height } => {
+                Self::GarbageCollected { height }
+            }
+            near_client_primitives::types::GetBlockError::OrdinalOutOfRange {
+                ordinal,
+                max_ordinal,
+            } => Self::OrdinalOutOfRange { ordinal, max_ordinal },
             near_client_primitives::types::GetBlockError::IOError { error_message } => {
                 Self::InternalError { error_message }
             }
@@ -64,9 +103,12 @@ impl From<RpcBlockError> for crate::errors::RpcError {
                 "DB Not Found Error: {} \n Cause: Unknown",
                 error_message
             ))),
-            RpcBlockError::NotSyncedYet | RpcBlockError::InternalError { .. } => {
-                Some(Value::String(error.to_string()))
-            }
+            RpcBlockError::NotSyncedYet
+            | RpcBlockError::SkippedHeight { .. }
+            | RpcBlockError::NotYetProduced { .. }
+            | RpcBlockError::GarbageCollected { .. }
+            | RpcBlockError::OrdinalOutOfRange { .. }
+            | RpcBlockError::InternalError { .. } => Some(Value::String(error.to_string())),
         };
 
         let error_data_value = match serde_json::to_value(error) {
@@ -88,9 +130,9 @@ impl RpcBlockRequest {
         let block_reference = if let Ok((block_id,)) =
             crate::utils::parse_params::<(near_primitives::types::BlockId,)>(value.clone())
         {
-            near_primitives::types::BlockReference::BlockId(block_id)
+            near_primitives::types::BlockReference::BlockId(block_id).into()
         } else {
-            crate::utils::parse_params::<near_primitives::types::BlockReference>(value)?
+            crate::utils::parse_params::<RpcBlockReference>(value)?
         };
         Ok(RpcBlockRequest { block_reference })
     }
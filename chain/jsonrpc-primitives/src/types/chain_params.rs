@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcChainParametersResponse {
+    #[serde(flatten)]
+    pub chain_parameters: near_primitives::views::ChainParametersView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcChainParametersError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<near_client_primitives::types::GetChainParametersError> for RpcChainParametersError {
+    fn from(error: near_client_primitives::types::GetChainParametersError) -> Self {
+        match error {
+            near_client_primitives::types::GetChainParametersError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::GetChainParametersError::Unreachable(
+                ref error_message,
+            ) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcChainParametersError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcChainParametersError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcChainParametersError> for crate::errors::RpcError {
+    fn from(error: RpcChainParametersError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcChainParametersError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
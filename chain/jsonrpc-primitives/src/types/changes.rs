@@ -60,6 +60,12 @@ impl From<near_client_primitives::types::GetBlockError> for RpcStateChangesError
                 Self::UnknownBlock { error_message }
             }
             near_client_primitives::types::GetBlockError::NotSyncedYet => Self::NotSyncedYet,
+            near_client_primitives::types::GetBlockError::SkippedHeight { .. }
+            | near_client_primitives::types::GetBlockError::NotYetProduced { .. }
+            | near_client_primitives::types::GetBlockError::GarbageCollected { .. }
+            | near_client_primitives::types::GetBlockError::OrdinalOutOfRange { .. } => {
+                Self::UnknownBlock { error_message: error.to_string() }
+            }
             near_client_primitives::types::GetBlockError::IOError { error_message } => {
                 Self::InternalError { error_message }
             }
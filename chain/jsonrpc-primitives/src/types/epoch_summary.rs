@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcEpochSummaryRequest {
+    pub epoch_height: near_primitives::types::EpochHeight,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcEpochSummaryResponse {
+    #[serde(flatten)]
+    pub epoch_summary: near_primitives::views::EpochSummaryView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcEpochSummaryError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Summary of epoch at height {epoch_height} is not computed")]
+    NotComputed { epoch_height: near_primitives::types::EpochHeight },
+}
+
+impl From<RpcEpochSummaryRequest> for near_client_primitives::types::GetEpochSummary {
+    fn from(request: RpcEpochSummaryRequest) -> Self {
+        Self { epoch_height: request.epoch_height }
+    }
+}
+
+impl RpcEpochSummaryRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl From<near_client_primitives::types::GetEpochSummaryError> for RpcEpochSummaryError {
+    fn from(error: near_client_primitives::types::GetEpochSummaryError) -> Self {
+        match error {
+            near_client_primitives::types::GetEpochSummaryError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::GetEpochSummaryError::NotComputed(epoch_height) => {
+                Self::NotComputed { epoch_height }
+            }
+            near_client_primitives::types::GetEpochSummaryError::Unreachable(ref error_message) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcEpochSummaryError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcEpochSummaryError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcEpochSummaryError> for crate::errors::RpcError {
+    fn from(error: RpcEpochSummaryError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcEpochSummaryError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
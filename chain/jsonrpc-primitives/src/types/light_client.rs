@@ -6,6 +6,11 @@ pub struct RpcLightClientExecutionProofRequest {
     #[serde(flatten)]
     pub id: near_primitives::types::TransactionOrReceiptId,
     pub light_client_head: near_primitives::hash::CryptoHash,
+    /// Return a proof for an outcome in a block which is not final yet, made against the current
+    /// header head if `light_client_head` doesn't include the block, instead of a `NOT_FINAL_YET`
+    /// error.
+    #[serde(default)]
+    pub allow_non_final: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,6 +24,10 @@ pub struct RpcLightClientExecutionProofResponse {
     pub outcome_root_proof: near_primitives::merkle::MerklePath,
     pub block_header_lite: near_primitives::views::LightClientBlockLiteView,
     pub block_proof: near_primitives::merkle::MerklePath,
+    /// Block `block_proof` is made against, either `light_client_head` or the header head.
+    pub block_proof_head: near_primitives::hash::CryptoHash,
+    /// Finality of the block with the outcome.
+    pub finality: near_primitives::types::Finality,
 }
 
 #[derive(Debug, Serialize)]
@@ -42,6 +51,18 @@ pub enum RpcLightClientProofError {
     },
     #[error("{transaction_or_receipt_id} has not been confirmed")]
     NotConfirmed { transaction_or_receipt_id: near_primitives::hash::CryptoHash },
+    #[error(
+        "Block {block_hash} is not final yet, the current final height is {current_final_height}"
+    )]
+    NotFinalYet {
+        block_hash: near_primitives::hash::CryptoHash,
+        current_final_height: near_primitives::types::BlockHeight,
+    },
+    #[error("Block at height {block_height} is ahead of the head block at height {head_height}")]
+    BlockAheadOfHead {
+        block_height: near_primitives::types::BlockHeight,
+        head_height: near_primitives::types::BlockHeight,
+    },
     #[error("{transaction_or_receipt_id} does not exist")]
     UnknownTransactionOrReceipt { transaction_or_receipt_id: near_primitives::hash::CryptoHash },
     #[error("Node doesn't track the shard where {transaction_or_receipt_id} is executed")]
@@ -132,6 +153,14 @@ impl From<near_client_primitives::types::GetBlockProofError> for RpcLightClientP
             near_client_primitives::types::GetBlockProofError::UnknownBlock { error_message } => {
                 Self::UnknownBlock { error_message }
             }
+            near_client_primitives::types::GetBlockProofError::NotFinalYet {
+                block_hash,
+                current_final_height,
+            } => Self::NotFinalYet { block_hash, current_final_height },
+            near_client_primitives::types::GetBlockProofError::BlockAheadOfHead {
+                block_height,
+                head_height,
+            } => Self::BlockAheadOfHead { block_height, head_height },
             near_client_primitives::types::GetBlockProofError::InternalError { error_message } => {
                 Self::InternalError { error_message }
             }
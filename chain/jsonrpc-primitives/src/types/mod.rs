@@ -1,13 +1,18 @@
 pub mod blocks;
+pub mod chain_params;
 pub mod changes;
 pub mod chunks;
 pub mod config;
+pub mod epoch_summary;
 pub mod gas_price;
 pub mod light_client;
 pub mod network_info;
+pub mod outcome_ancestry;
+pub mod partial_tx_status;
 pub mod query;
 pub mod receipts;
 pub mod sandbox;
+pub mod shard_layout;
 pub mod status;
 pub mod transactions;
 pub mod validator;
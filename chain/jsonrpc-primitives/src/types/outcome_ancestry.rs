@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcOutcomeAncestryRequest {
+    /// Id of the transaction or receipt whose outcome to start from.
+    pub id: near_primitives::hash::CryptoHash,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcOutcomeAncestryResponse {
+    /// The requested id followed by the ids of the receipts which led to it, ending with the
+    /// originating transaction unless some of them were garbage collected.
+    pub ancestry: Vec<near_primitives::hash::CryptoHash>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcOutcomeAncestryError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Outcome with id {id} is not found on the canonical chain of this node")]
+    UnknownOutcome { id: near_primitives::hash::CryptoHash },
+}
+
+impl From<RpcOutcomeAncestryRequest> for near_client_primitives::types::GetOutcomeAncestry {
+    fn from(request: RpcOutcomeAncestryRequest) -> Self {
+        Self { id: request.id }
+    }
+}
+
+impl RpcOutcomeAncestryRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl From<near_client_primitives::types::GetOutcomeAncestryError> for RpcOutcomeAncestryError {
+    fn from(error: near_client_primitives::types::GetOutcomeAncestryError) -> Self {
+        match error {
+            near_client_primitives::types::GetOutcomeAncestryError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::GetOutcomeAncestryError::UnknownOutcome(id) => {
+                Self::UnknownOutcome { id }
+            }
+            near_client_primitives::types::GetOutcomeAncestryError::Unreachable(
+                ref error_message,
+            ) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcOutcomeAncestryError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcOutcomeAncestryError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcOutcomeAncestryError> for crate::errors::RpcError {
+    fn from(error: RpcOutcomeAncestryError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcOutcomeAncestryError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
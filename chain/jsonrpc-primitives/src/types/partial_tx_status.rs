@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcPartialTransactionStatusRequest {
+    pub tx_hash: near_primitives::hash::CryptoHash,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcPartialTransactionStatusResponse {
+    #[serde(flatten)]
+    pub final_outcome: near_primitives::views::PartialFinalExecutionOutcomeView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcPartialTransactionStatusError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Transaction {tx_hash} or its outcome is not found on this node")]
+    UnknownTransaction { tx_hash: near_primitives::hash::CryptoHash },
+}
+
+impl From<RpcPartialTransactionStatusRequest>
+    for near_client_primitives::types::GetPartialTransactionResult
+{
+    fn from(request: RpcPartialTransactionStatusRequest) -> Self {
+        Self { tx_hash: request.tx_hash }
+    }
+}
+
+impl RpcPartialTransactionStatusRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl From<near_client_primitives::types::GetPartialTransactionResultError>
+    for RpcPartialTransactionStatusError
+{
+    fn from(error: near_client_primitives::types::GetPartialTransactionResultError) -> Self {
+        match error {
+            near_client_primitives::types::GetPartialTransactionResultError::IOError(
+                error_message,
+            ) => Self::InternalError { error_message },
+            near_client_primitives::types::GetPartialTransactionResultError::UnknownTransaction(
+                tx_hash,
+            ) => Self::UnknownTransaction { tx_hash },
+            near_client_primitives::types::GetPartialTransactionResultError::Unreachable(
+                ref error_message,
+            ) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcPartialTransactionStatusError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcPartialTransactionStatusError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcPartialTransactionStatusError> for crate::errors::RpcError {
+    fn from(error: RpcPartialTransactionStatusError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcPartialTransactionStatusError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
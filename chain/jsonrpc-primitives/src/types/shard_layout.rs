@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RpcShardLayoutRequest {
+    /// Epoch of the head if not given.
+    #[serde(default)]
+    pub epoch_id: Option<near_primitives::types::EpochId>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcShardLayoutResponse {
+    #[serde(flatten)]
+    pub shard_layout: near_primitives::views::ShardLayoutView,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcAccountShardRequest {
+    pub account_id: near_primitives::types::AccountId,
+    /// Epoch of the head if not given.
+    #[serde(default)]
+    pub epoch_id: Option<near_primitives::types::EpochId>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcAccountShardResponse {
+    #[serde(flatten)]
+    pub account_shard: near_primitives::views::AccountShardView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcShardLayoutError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Epoch not found")]
+    UnknownEpoch,
+}
+
+impl From<RpcShardLayoutRequest> for near_client_primitives::types::GetShardLayout {
+    fn from(request: RpcShardLayoutRequest) -> Self {
+        Self { epoch_id: request.epoch_id }
+    }
+}
+
+impl From<RpcAccountShardRequest> for near_client_primitives::types::GetAccountShard {
+    fn from(request: RpcAccountShardRequest) -> Self {
+        Self { account_id: request.account_id, epoch_id: request.epoch_id }
+    }
+}
+
+impl RpcShardLayoutRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        match value {
+            Some(value) => Ok(crate::utils::parse_params::<Self>(Some(value))?),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+impl RpcAccountShardRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl From<near_client_primitives::types::GetShardLayoutError> for RpcShardLayoutError {
+    fn from(error: near_client_primitives::types::GetShardLayoutError) -> Self {
+        match error {
+            near_client_primitives::types::GetShardLayoutError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::GetShardLayoutError::UnknownEpoch => Self::UnknownEpoch,
+            near_client_primitives::types::GetShardLayoutError::Unreachable(ref error_message) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcShardLayoutError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcShardLayoutError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcShardLayoutError> for crate::errors::RpcError {
+    fn from(error: RpcShardLayoutError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcShardLayoutError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -32,6 +32,8 @@ pub enum RpcTransactionError {
     },
     #[error("Node doesn't track this shard. Cannot determine whether the transaction is valid")]
     DoesNotTrackShard,
+    #[error("Transactions signed by {signer_id} are not accepted by this node")]
+    RejectedByRoutingPolicy { signer_id: near_primitives::types::AccountId },
     #[error("Transaction with hash {transaction_hash} was routed")]
     RequestRouted { transaction_hash: near_primitives::hash::CryptoHash },
     #[error("Transaction {requested_transaction_hash} doesn't exist")]
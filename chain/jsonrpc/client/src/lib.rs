@@ -7,12 +7,15 @@ use serde::Serialize;
 
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{from_slice, Message};
+use near_jsonrpc_primitives::types::chain_params::RpcChainParametersResponse;
 use near_jsonrpc_primitives::types::changes::{
     RpcStateChangesInBlockByTypeRequest, RpcStateChangesInBlockByTypeResponse,
 };
 use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::{AccountId, BlockId, BlockReference, MaybeBlockId, ShardId};
+use near_primitives::types::{
+    AccountId, BlockId, BlockReference, MaybeBlockId, NumBlocks, ShardId,
+};
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
     BlockView, ChunkView, EpochValidatorInfo, FinalExecutionOutcomeView, GasPriceView,
@@ -191,6 +194,8 @@ jsonrpc_client!(pub struct JsonRpcClient {
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_genesis_config(&self) -> RpcRequest<serde_json::Value>;
     #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_chain_params(&self) -> RpcRequest<RpcChainParametersResponse>;
+    #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_broadcast_tx_sync(&self, tx: String) -> RpcRequest<serde_json::Value>;
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_tx_status(&self, tx: String) -> RpcRequest<serde_json::Value>;
@@ -227,6 +232,11 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "block", request)
     }
 
+    pub fn block_by_ordinal(&self, ordinal: NumBlocks) -> RpcRequest<BlockView> {
+        let request = serde_json::json!({ "ordinal": ordinal });
+        call_method(&self.client, &self.server_addr, "block", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_changes(
         &self,
@@ -251,6 +261,49 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_receipt", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_shard_layout(
+        &self,
+        request: near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_shard_layout", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_account_shard(
+        &self,
+        request: near_jsonrpc_primitives::types::shard_layout::RpcAccountShardRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::shard_layout::RpcAccountShardResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_account_shard", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_epoch_summary(
+        &self,
+        request: near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_epoch_summary", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_partial_tx_status(
+        &self,
+        request: near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusRequest,
+    ) -> RpcRequest<
+        near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusResponse,
+    > {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_partial_tx_status", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_outcome_ancestry(
+        &self,
+        request: near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryResponse>
+    {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_outcome_ancestry", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_protocol_config(
         &self,
@@ -15,10 +15,12 @@ use tracing::info;
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    ClientActor, GetAccountShard, GetBlock, GetBlockByOrdinal, GetBlockProof, GetBlocksAtHeight,
+    GetChainParameters, GetChunk, GetDiscardedForks, GetEpochSummary, GetExecutionOutcome,
+    GetGasPrice, GetGcPlan, GetNetworkInfo, GetNextLightClientBlock, GetOutcomeAncestry,
+    GetPartOwnership, GetPartialTransactionResult, GetProtocolConfig, GetReceipt,
+    GetRejectedBlocks, GetShardLayout, GetStateChanges, GetStateChangesInBlock, GetSyncDiagnostics,
+    GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus, TxStatusError, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
@@ -126,6 +128,9 @@ impl near_jsonrpc_primitives::types::transactions::RpcTransactionError {
     pub fn from_network_client_responses(responses: NetworkClientResponses) -> Self {
         match responses {
             NetworkClientResponses::InvalidTx(context) => Self::InvalidTransaction { context },
+            NetworkClientResponses::TxRejectedByRoutingPolicy { signer_id } => {
+                Self::RejectedByRoutingPolicy { signer_id }
+            }
             NetworkClientResponses::NoResponse => Self::TimeoutError,
             NetworkClientResponses::DoesNotTrackShard | NetworkClientResponses::RequestRouted => {
                 Self::DoesNotTrackShard
@@ -475,6 +480,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(validator_info)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_account_shard" => {
+                let rpc_account_shard_request =
+                    near_jsonrpc_primitives::types::shard_layout::RpcAccountShardRequest::parse(
+                        request.params,
+                    )?;
+                let account_shard = self.account_shard(rpc_account_shard_request).await?;
+                serde_json::to_value(account_shard)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_broadcast_tx_sync" => {
                 let rpc_transaction_request =
                     near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest::parse(
@@ -484,6 +498,11 @@ impl JsonRpcHandler {
                 serde_json::to_value(broadcast_tx_sync_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_chain_params" => {
+                let chain_parameters = self.chain_parameters().await?;
+                serde_json::to_value(chain_parameters)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_changes" => {
                 let rpc_state_changes_request =
                     near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockByTypeRequest::parse(
@@ -512,6 +531,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(broadcast_tx_sync_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_epoch_summary" => {
+                let rpc_epoch_summary_request =
+                    near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryRequest::parse(
+                        request.params,
+                    )?;
+                let epoch_summary = self.epoch_summary(rpc_epoch_summary_request).await?;
+                serde_json::to_value(epoch_summary)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_genesis_config" => {
                 let genesis_config = self.genesis_config().await;
                 serde_json::to_value(genesis_config)
@@ -525,6 +553,19 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_light_client_execution_proof_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_outcome_ancestry" => {
+                let rpc_outcome_ancestry_request = near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryRequest::parse(request.params)?;
+                let ancestry = self.outcome_ancestry(rpc_outcome_ancestry_request).await?;
+                serde_json::to_value(ancestry)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_partial_tx_status" => {
+                let rpc_partial_tx_status_request = near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusRequest::parse(request.params)?;
+                let partial_tx_status =
+                    self.partial_tx_status(rpc_partial_tx_status_request).await?;
+                serde_json::to_value(partial_tx_status)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_protocol_config" => {
                 let rpc_protocol_config_request =
                     near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest::parse(
@@ -543,6 +584,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(receipt)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_shard_layout" => {
+                let rpc_shard_layout_request =
+                    near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutRequest::parse(
+                        request.params,
+                    )?;
+                let shard_layout = self.shard_layout(rpc_shard_layout_request).await?;
+                serde_json::to_value(shard_layout)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_tx_status" => {
                 let rpc_transaction_status_common_request = near_jsonrpc_primitives::types::transactions::RpcTransactionStatusCommonRequest::parse(request.params)?;
                 let rpc_transaction_response =
@@ -904,6 +954,90 @@ impl JsonRpcHandler {
         }
     }
 
+    pub async fn sync_diagnostics(
+        &self,
+    ) -> Result<
+        Option<near_primitives::views::SyncDiagnosticsView>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(GetSyncDiagnostics {}).await??))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn blocks_at_height(
+        &self,
+        height: near_primitives::types::BlockHeight,
+    ) -> Result<
+        Option<Vec<near_primitives::views::BlockAtHeightView>>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(GetBlocksAtHeight { height }).await??))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn discarded_forks(
+        &self,
+        start_height: near_primitives::types::BlockHeight,
+        end_height: near_primitives::types::BlockHeight,
+    ) -> Result<
+        Option<Vec<near_primitives::views::DiscardedForkView>>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(GetDiscardedForks { start_height, end_height }).await??))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn gc_plan(
+        &self,
+        gc_blocks_limit: near_primitives::types::NumBlocks,
+    ) -> Result<
+        Option<near_primitives::views::GcPlanView>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(GetGcPlan { gc_blocks_limit }).await??))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn rejected_blocks(
+        &self,
+        limit: usize,
+    ) -> Result<
+        Option<Vec<near_primitives::views::RejectedBlockView>>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(GetRejectedBlocks { limit }).await??))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn part_ownership(
+        &self,
+        account_id: AccountId,
+    ) -> Result<
+        Option<near_primitives::views::PartOwnershipView>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(GetPartOwnership { account_id }).await??))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Expose Genesis Config (with internal Runtime Config) without state records to keep the
     /// output at a reasonable size.
     ///
@@ -912,6 +1046,18 @@ impl JsonRpcHandler {
         &self.genesis_config
     }
 
+    async fn chain_parameters(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::chain_params::RpcChainParametersResponse,
+        near_jsonrpc_primitives::types::chain_params::RpcChainParametersError,
+    > {
+        let chain_parameters = self.view_client_addr.send(GetChainParameters {}).await??;
+        Ok(near_jsonrpc_primitives::types::chain_params::RpcChainParametersResponse {
+            chain_parameters,
+        })
+    }
+
     pub async fn protocol_config(
         &self,
         request_data: near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest,
@@ -955,8 +1101,14 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::blocks::RpcBlockResponse,
         near_jsonrpc_primitives::types::blocks::RpcBlockError,
     > {
-        let block_view =
-            self.view_client_addr.send(GetBlock(request_data.block_reference.into())).await??;
+        let block_view = match request_data.block_reference {
+            near_jsonrpc_primitives::types::blocks::RpcBlockReference::BlockReference(
+                block_reference,
+            ) => self.view_client_addr.send(GetBlock(block_reference)).await??,
+            near_jsonrpc_primitives::types::blocks::RpcBlockReference::Ordinal { ordinal } => {
+                self.view_client_addr.send(GetBlockByOrdinal { ordinal }).await??
+            }
+        };
         Ok(near_jsonrpc_primitives::types::blocks::RpcBlockResponse { block_view })
     }
 
@@ -972,6 +1124,68 @@ impl JsonRpcHandler {
         Ok(near_jsonrpc_primitives::types::chunks::RpcChunkResponse { chunk_view })
     }
 
+    async fn epoch_summary(
+        &self,
+        request_data: near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryResponse,
+        near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryError,
+    > {
+        let epoch_summary =
+            self.view_client_addr.send(GetEpochSummary::from(request_data)).await??;
+        Ok(near_jsonrpc_primitives::types::epoch_summary::RpcEpochSummaryResponse { epoch_summary })
+    }
+
+    async fn outcome_ancestry(
+        &self,
+        request_data: near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryResponse,
+        near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryError,
+    > {
+        let ancestry = self.view_client_addr.send(GetOutcomeAncestry::from(request_data)).await??;
+        Ok(near_jsonrpc_primitives::types::outcome_ancestry::RpcOutcomeAncestryResponse {
+            ancestry,
+        })
+    }
+
+    async fn partial_tx_status(
+        &self,
+        request_data: near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusResponse,
+        near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusError,
+    > {
+        let final_outcome =
+            self.view_client_addr.send(GetPartialTransactionResult::from(request_data)).await??;
+        Ok(near_jsonrpc_primitives::types::partial_tx_status::RpcPartialTransactionStatusResponse {
+            final_outcome,
+        })
+    }
+
+    async fn shard_layout(
+        &self,
+        request_data: near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutResponse,
+        near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutError,
+    > {
+        let shard_layout = self.view_client_addr.send(GetShardLayout::from(request_data)).await??;
+        Ok(near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutResponse { shard_layout })
+    }
+
+    async fn account_shard(
+        &self,
+        request_data: near_jsonrpc_primitives::types::shard_layout::RpcAccountShardRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::shard_layout::RpcAccountShardResponse,
+        near_jsonrpc_primitives::types::shard_layout::RpcShardLayoutError,
+    > {
+        let account_shard =
+            self.view_client_addr.send(GetAccountShard::from(request_data)).await??;
+        Ok(near_jsonrpc_primitives::types::shard_layout::RpcAccountShardResponse { account_shard })
+    }
+
     async fn receipt(
         &self,
         request_data: near_jsonrpc_primitives::types::receipts::RpcReceiptRequest,
@@ -1061,6 +1275,7 @@ impl JsonRpcHandler {
         let near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofRequest {
             id,
             light_client_head,
+            allow_non_final,
         } = request;
 
         let execution_outcome_proof =
@@ -1071,6 +1286,7 @@ impl JsonRpcHandler {
             .send(GetBlockProof {
                 block_hash: execution_outcome_proof.outcome_proof.block_hash,
                 head_block_hash: light_client_head,
+                allow_non_final,
             })
             .await??;
 
@@ -1079,6 +1295,8 @@ impl JsonRpcHandler {
             outcome_root_proof: execution_outcome_proof.outcome_root_proof,
             block_header_lite: block_proof.block_header_lite,
             block_proof: block_proof.proof,
+            block_proof_head: block_proof.head_block_hash,
+            finality: block_proof.finality,
         })
     }
 
@@ -1378,6 +1596,72 @@ async fn debug_handler(handler: web::Data<JsonRpcHandler>) -> Result<HttpRespons
     }
 }
 
+async fn sync_diagnostics_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.sync_diagnostics().await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn blocks_at_height_handler(
+    handler: web::Data<JsonRpcHandler>,
+    height: web::Path<near_primitives::types::BlockHeight>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.blocks_at_height(height.into_inner()).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn discarded_forks_handler(
+    handler: web::Data<JsonRpcHandler>,
+    heights: web::Path<(near_primitives::types::BlockHeight, near_primitives::types::BlockHeight)>,
+) -> Result<HttpResponse, HttpError> {
+    let (start_height, end_height) = heights.into_inner();
+    match handler.discarded_forks(start_height, end_height).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn part_ownership_handler(
+    handler: web::Data<JsonRpcHandler>,
+    account_id: web::Path<AccountId>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.part_ownership(account_id.into_inner()).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn gc_plan_handler(
+    handler: web::Data<JsonRpcHandler>,
+    gc_blocks_limit: web::Path<near_primitives::types::NumBlocks>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.gc_plan(gc_blocks_limit.into_inner()).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn rejected_blocks_handler(
+    handler: web::Data<JsonRpcHandler>,
+    limit: web::Path<usize>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.rejected_blocks(limit.into_inner()).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
 fn health_handler(
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
@@ -1516,6 +1800,27 @@ pub fn start_http(
             .service(web::resource("/network_info").route(web::get().to(network_info_handler)))
             .service(web::resource("/metrics").route(web::get().to(prometheus_handler)))
             .service(web::resource("/debug/api/status").route(web::get().to(debug_handler)))
+            .service(web::resource("/debug/sync").route(web::get().to(sync_diagnostics_handler)))
+            .service(
+                web::resource("/debug/blocks_at_height/{height}")
+                    .route(web::get().to(blocks_at_height_handler)),
+            )
+            .service(
+                web::resource("/debug/discarded_forks/{start_height}/{end_height}")
+                    .route(web::get().to(discarded_forks_handler)),
+            )
+            .service(
+                web::resource("/debug/gc_plan/{gc_blocks_limit}")
+                    .route(web::get().to(gc_plan_handler)),
+            )
+            .service(
+                web::resource("/debug/rejected_blocks/{limit}")
+                    .route(web::get().to(rejected_blocks_handler)),
+            )
+            .service(
+                web::resource("/debug/part_ownership/{account_id}")
+                    .route(web::get().to(part_ownership_handler)),
+            )
             .service(debug_html)
             .service(last_blocks_html)
             .service(sync_info_html)
@@ -6,6 +6,7 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::Signature;
 use near_primitives::block::{Approval, GenesisId};
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::combine_hash;
 use near_primitives::network::PeerId;
@@ -16,6 +17,7 @@ use near_primitives::sharding::{
 use near_primitives::syncing::{ShardStateSyncResponse, ShardStateSyncResponseV1};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockHeight, BlockReference, ShardId};
+use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{FinalExecutionOutcomeView, QueryRequest, QueryResponse};
 use std::collections::HashSet;
 use std::fmt;
@@ -430,11 +432,23 @@ pub struct StateResponseInfoV2 {
     pub state_response: ShardStateSyncResponse,
 }
 
+/// State response which also carries the sync hash the responding node would accept, when the
+/// requested one is close to, but not on, an epoch boundary.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(PartialEq, Eq, Clone, Debug, BorshSerialize, BorshDeserialize)]
+pub struct StateResponseInfoV3 {
+    pub shard_id: ShardId,
+    pub sync_hash: CryptoHash,
+    pub state_response: ShardStateSyncResponse,
+    pub suggested_sync_hash: Option<CryptoHash>,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(PartialEq, Eq, Clone, Debug, BorshSerialize, BorshDeserialize)]
 pub enum StateResponseInfo {
     V1(StateResponseInfoV1),
     V2(StateResponseInfoV2),
+    V3(StateResponseInfoV3),
 }
 
 impl StateResponseInfo {
@@ -442,6 +456,7 @@ impl StateResponseInfo {
         match self {
             Self::V1(info) => info.shard_id,
             Self::V2(info) => info.shard_id,
+            Self::V3(info) => info.shard_id,
         }
     }
 
@@ -449,6 +464,31 @@ impl StateResponseInfo {
         match self {
             Self::V1(info) => info.sync_hash,
             Self::V2(info) => info.sync_hash,
+            Self::V3(info) => info.sync_hash,
+        }
+    }
+
+    pub fn suggested_sync_hash(&self) -> Option<CryptoHash> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            Self::V3(info) => info.suggested_sync_hash,
+        }
+    }
+
+    /// Converts the response to a version a peer speaking `protocol_version` can read. Returns
+    /// `None` if the response only carries a sync hash suggestion and the peer can't read it.
+    pub fn for_protocol_version(self, protocol_version: ProtocolVersion) -> Option<Self> {
+        if checked_feature!("stable", StateResponseSyncHashSuggestion, protocol_version) {
+            return Some(self);
+        }
+        match self {
+            Self::V3(info) if info.suggested_sync_hash.is_some() => None,
+            Self::V3(info) => Some(Self::V2(StateResponseInfoV2 {
+                shard_id: info.shard_id,
+                sync_hash: info.sync_hash,
+                state_response: info.state_response,
+            })),
+            info => Some(info),
         }
     }
 
@@ -456,6 +496,7 @@ impl StateResponseInfo {
         match self {
             Self::V1(info) => ShardStateSyncResponse::V1(info.state_response),
             Self::V2(info) => info.state_response,
+            Self::V3(info) => info.state_response,
         }
     }
 }
@@ -33,6 +33,7 @@ pub use crate::network_protocol::{
     PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg,
     PeerChainInfo, PeerChainInfoV2, PeerIdOrHash, PeerInfo, Ping, Pong, RoutedMessage,
     RoutedMessageBody, StateResponseInfo, StateResponseInfoV1, StateResponseInfoV2,
+    StateResponseInfoV3,
 };
 
 pub use crate::blacklist::Blacklist;
@@ -226,6 +227,7 @@ pub enum ReasonForBan {
     EpochSyncInvalidResponse = 12,
     EpochSyncInvalidFinalizationResponse = 13,
     Blacklisted = 14,
+    BadStateHeader = 15,
 }
 
 /// Banning signal sent from Peer instance to PeerManager
@@ -379,7 +381,10 @@ pub struct PeerStatsResult {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use near_primitives::syncing::ShardStateSyncResponseV1;
+    use near_primitives::syncing::{
+        ShardStateSyncResponse, ShardStateSyncResponseV1, ShardStateSyncResponseV2,
+    };
+    use near_primitives::version::ProtocolFeature;
 
     // NOTE: this has it's counterpart in `near_network::types::tests`
     const ALLOWED_SIZE: usize = 1 << 20;
@@ -453,4 +458,34 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn state_response_for_old_peers() {
+        let v3 = |suggested_sync_hash| {
+            StateResponseInfo::V3(StateResponseInfoV3 {
+                shard_id: 1,
+                sync_hash: CryptoHash([1; 32]),
+                state_response: ShardStateSyncResponse::V2(ShardStateSyncResponseV2 {
+                    header: None,
+                    part: Some((0, vec![1, 2, 3])),
+                }),
+                suggested_sync_hash,
+            })
+        };
+        let new_version = ProtocolFeature::StateResponseSyncHashSuggestion.protocol_version();
+        let old_version = new_version - 1;
+
+        let suggestion = v3(Some(CryptoHash([2; 32])));
+        assert_eq!(suggestion.clone().for_protocol_version(new_version), Some(suggestion.clone()));
+        assert_eq!(suggestion.for_protocol_version(old_version), None);
+
+        let response = v3(None);
+        match response.clone().for_protocol_version(old_version) {
+            Some(StateResponseInfo::V2(info)) => {
+                assert_eq!(info.sync_hash, response.sync_hash());
+                assert_eq!(info.state_response, response.take_state_response());
+            }
+            other => panic!("expected a V2 response, got {:?}", other),
+        }
+    }
 }
@@ -342,11 +342,17 @@ impl PeerActor {
                         ));
                     }
                     Ok(NetworkViewClientResponses::StateResponse(state_response)) => {
-                        let body = match *state_response {
+                        let state_response =
+                            match state_response.for_protocol_version(act.protocol_version) {
+                                Some(state_response) => state_response,
+                                None => return actix::fut::ready(()),
+                            };
+                        let body = match state_response {
                             StateResponseInfo::V1(state_response) => {
                                 RoutedMessageBody::StateResponse(state_response)
                             }
-                            state_response @ StateResponseInfo::V2(_) => {
+                            state_response @ (StateResponseInfo::V2(_)
+                            | StateResponseInfo::V3(_)) => {
                                 RoutedMessageBody::VersionedStateResponse(state_response)
                             }
                         };
@@ -423,11 +429,12 @@ impl PeerActor {
                         }
                     }
 
-                    RoutedMessageBody::StateResponse(info) => {
-                        NetworkClientMessages::StateResponse(StateResponseInfo::V1(info))
-                    }
+                    RoutedMessageBody::StateResponse(info) => NetworkClientMessages::StateResponse(
+                        StateResponseInfo::V1(info),
+                        routed_message.author,
+                    ),
                     RoutedMessageBody::VersionedStateResponse(info) => {
-                        NetworkClientMessages::StateResponse(info)
+                        NetworkClientMessages::StateResponse(info, routed_message.author)
                     }
                     RoutedMessageBody::PartialEncodedChunkRequest(request) => {
                         NetworkClientMessages::PartialEncodedChunkRequest(request, msg_hash)
@@ -39,6 +39,7 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::time::Clock;
 use near_primitives::types::{AccountId, ProtocolVersion};
 use near_primitives::utils::from_timestamp;
+use near_primitives::version::PROTOCOL_VERSION;
 use near_rate_limiter::{
     ActixMessageResponse, ActixMessageWrapper, ThrottleController, ThrottleFramedRead,
     ThrottleToken,
@@ -112,6 +113,8 @@ struct ConnectedPeer {
     connection_established_time: Instant,
     /// Who started connection. Inbound (other) or Outbound (us).
     peer_type: PeerType,
+    /// Protocol version agreed with the peer during the handshake.
+    protocol_version: ProtocolVersion,
     /// A helper data structure for limiting reading, reporting stats.
     throttle_controller: ThrottleController,
 }
@@ -594,6 +597,7 @@ impl PeerManagerActor {
                 last_time_received_message: Clock::instant(),
                 connection_established_time: Clock::instant(),
                 peer_type,
+                protocol_version: peer_protocol_version,
                 throttle_controller: throttle_controller.clone(),
             },
         );
@@ -1546,9 +1550,19 @@ impl PeerManagerActor {
                 }
             }
             NetworkRequests::StateResponse { route_back, response } => {
+                // Old peers on the way back can't read a sync hash suggestion.
+                let protocol_version = self
+                    .routing_table_view
+                    .peek_route_back(&route_back)
+                    .and_then(|peer_id| self.connected_peers.get(peer_id))
+                    .map_or(PROTOCOL_VERSION, |peer| peer.protocol_version);
+                let response = match response.for_protocol_version(protocol_version) {
+                    Some(response) => response,
+                    None => return NetworkResponses::NoResponse,
+                };
                 let body = match response {
                     StateResponseInfo::V1(response) => RoutedMessageBody::StateResponse(response),
-                    response @ StateResponseInfo::V2(_) => {
+                    response @ (StateResponseInfo::V2(_) | StateResponseInfo::V3(_)) => {
                         RoutedMessageBody::VersionedStateResponse(response)
                     }
                 };
@@ -167,6 +167,11 @@ impl RoutingTableView {
         self.route_back.remove(&hash)
     }
 
+    /// Find route back with given hash without removing it from cache.
+    pub(crate) fn peek_route_back(&self, hash: &CryptoHash) -> Option<&PeerId> {
+        self.route_back.get(hash)
+    }
+
     pub(crate) fn compare_route_back(&self, hash: CryptoHash, peer_id: &PeerId) -> bool {
         self.route_back.get(&hash).map_or(false, |value| value == peer_id)
     }
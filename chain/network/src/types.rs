@@ -416,8 +416,8 @@ pub enum NetworkClientMessages {
     BlockHeaders(Vec<BlockHeader>, PeerId),
     /// Block approval.
     BlockApproval(Approval, PeerId),
-    /// State response.
-    StateResponse(StateResponseInfo),
+    /// State response, with the peer that sent it.
+    StateResponse(StateResponseInfo, PeerId),
     /// Epoch Sync response for light client block request
     EpochSyncResponse(PeerId, Box<EpochSyncResponse>),
     /// Epoch Sync response for finalization request
@@ -456,6 +456,8 @@ pub enum NetworkClientResponses {
     ValidTx,
     /// Invalid transaction inserted into mempool as response to Transaction.
     InvalidTx(InvalidTxError),
+    /// Transaction rejected because its signer doesn't conform to the node's routing policy.
+    TxRejectedByRoutingPolicy { signer_id: AccountId },
     /// The request is routed to other shards
     RequestRouted,
     /// The node being queried does not track the shard needed and therefore cannot provide userful
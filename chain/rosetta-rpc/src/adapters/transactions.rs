@@ -40,7 +40,7 @@ impl ExecutionToReceipts {
             }
         }
         let map = view_client_addr
-            .send(near_client::GetExecutionOutcomesForBlock { block_hash })
+            .send(near_client::GetExecutionOutcomesForBlock { block_hash, verified: false })
             .await?
             .map_err(crate::errors::ErrorKind::InternalInvariantError)?
             .into_values()
@@ -1,14 +1,53 @@
 //! Chain Client Configuration
 use std::cmp::min;
+use std::collections::HashSet;
 use std::time::Duration;
 
+use num_rational::Rational;
 use serde::{Deserialize, Serialize};
 
-use near_primitives::types::{AccountId, BlockHeightDelta, Gas, NumBlocks, NumSeats, ShardId};
+use near_primitives::serialize::u128_dec_format;
+use near_primitives::types::{
+    AccountId, Balance, BlockHeight, BlockHeightDelta, Gas, NumBlocks, NumSeats, ShardId,
+};
 use near_primitives::version::Version;
 
 pub const TEST_STATE_SYNC_TIMEOUT: u64 = 5;
 
+/// Default limit on the serialized size of a block. Way above anything a valid block can reach.
+pub const DEFAULT_MAX_BLOCK_SERIALIZED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Default limit on the number of transactions in a single chunk.
+pub const DEFAULT_MAX_CHUNK_TRANSACTIONS: usize = 100_000;
+
+/// Default limit on the number of stored orphans with the same prev block.
+pub const DEFAULT_MAX_ORPHANS_PER_PREV_HASH: usize = 50;
+
+/// Default size of the incoming receipts waiting for a new chunk of a shard above which a warning
+/// is logged.
+pub const DEFAULT_RECEIPT_BACKLOG_WARN_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Default expected rate of downloading headers during header sync.
+pub const DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND: u64 = 10;
+
+/// Default time by which a block may be ahead of the local clock, on top of the acceptable time
+/// difference, to be delayed until the clock catches up instead of being rejected.
+pub const DEFAULT_FUTURE_BLOCK_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Default number of synced headers committed to the store together.
+pub const DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE: usize = 500;
+
+/// Default size of the store updates of blocks being caught up which are held in memory, above
+/// which they are spilled to the store.
+pub const DEFAULT_CATCHUP_SAVED_UPDATES_MEMORY_LIMIT_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Default limit on the number of distinct sync hashes state is served for at once.
+pub const DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES: usize = 8;
+
+/// Default time after which a sync hash no longer counts towards the limit of served sync hashes
+/// if state wasn't requested for it.
+pub const DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum LogSummaryStyle {
     #[serde(rename = "plain")]
@@ -17,6 +56,107 @@ pub enum LogSummaryStyle {
     Colored,
 }
 
+/// When the client runs garbage collection after the head is updated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcSchedule {
+    /// Every time the head is updated.
+    Always,
+    /// Once per `n` head updates.
+    EveryNBlocks(NumBlocks),
+    /// Only if the previous head update happened at least `min_idle_ms` ago, or if it didn't
+    /// run for the last `max_deferred_blocks` head updates.
+    WhenIdle {
+        min_idle_ms: u64,
+        #[serde(default = "default_max_gc_deferred_blocks")]
+        max_deferred_blocks: NumBlocks,
+    },
+}
+
+fn default_max_gc_deferred_blocks() -> NumBlocks {
+    100
+}
+
+impl Default for GcSchedule {
+    fn default() -> Self {
+        GcSchedule::Always
+    }
+}
+
+impl GcSchedule {
+    /// Whether garbage collection should run, given the number of head updates since it last
+    /// ran (including the current one) and the time since the previous head update.
+    pub fn should_run(&self, heads_since_gc: NumBlocks, idle: Duration) -> bool {
+        match self {
+            GcSchedule::Always => true,
+            GcSchedule::EveryNBlocks(n) => heads_since_gc >= *n,
+            GcSchedule::WhenIdle { min_idle_ms, max_deferred_blocks } => {
+                idle >= Duration::from_millis(*min_idle_ms) || heads_since_gc > *max_deferred_blocks
+            }
+        }
+    }
+}
+
+/// What the node does with blocks from an epoch whose protocol version is newer than the one
+/// supported by the binary.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsupportedProtocolVersionPolicy {
+    /// Reject such blocks and log an error asking to update the binary.
+    Halt,
+    /// Reject such blocks but keep accepting their headers, so that the node follows the chain
+    /// and keeps serving the data it already has.
+    FollowHeadersOnly,
+}
+
+impl Default for UnsupportedProtocolVersionPolicy {
+    fn default() -> Self {
+        UnsupportedProtocolVersionPolicy::Halt
+    }
+}
+
+/// Restricts the signers whose transactions the node accepts for processing and forwarding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxRoutingPolicy {
+    /// Transactions signed by any of these accounts are rejected.
+    DenyList(HashSet<AccountId>),
+    /// Only transactions signed by one of these accounts are accepted.
+    AllowList(HashSet<AccountId>),
+}
+
+impl TxRoutingPolicy {
+    /// Whether transactions signed by `signer_id` conform to the policy.
+    pub fn allows(&self, signer_id: &AccountId) -> bool {
+        match self {
+            TxRoutingPolicy::DenyList(accounts) => !accounts.contains(signer_id),
+            TxRoutingPolicy::AllowList(accounts) => accounts.contains(signer_id),
+        }
+    }
+
+    /// Name of the policy, as used in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxRoutingPolicy::DenyList(_) => "deny_list",
+            TxRoutingPolicy::AllowList(_) => "allow_list",
+        }
+    }
+}
+
+/// Gas price parameters replacing the ones from genesis for blocks starting at a given height.
+/// Meant for private chains whose economics need tuning without regenerating genesis, so it's
+/// refused on mainnet and testnet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EconomicsOverrides {
+    /// Height of the first block the overrides apply to.
+    pub activation_height: BlockHeight,
+    #[serde(with = "u128_dec_format")]
+    pub min_gas_price: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub max_gas_price: Balance,
+    pub gas_price_adjustment_rate: Rational,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     /// Version of the binary.
@@ -51,6 +191,10 @@ pub struct ClientConfig {
     pub header_sync_stall_ban_timeout: Duration,
     /// Expected increase of header head weight per second during header sync
     pub header_sync_expected_height_per_second: u64,
+    /// Number of headers validated during header sync before they are committed to the store.
+    /// Uncommitted headers are held in the epoch manager caches, so it should stay well below
+    /// their size.
+    pub header_sync_commit_batch_size: usize,
     /// How long to wait for a response during state sync
     pub state_sync_timeout: Duration,
     /// Minimum number of peers to start syncing.
@@ -83,6 +227,21 @@ pub struct ClientConfig {
     pub block_header_fetch_horizon: BlockHeightDelta,
     /// Number of blocks to garbage collect at every gc call.
     pub gc_blocks_limit: NumBlocks,
+    /// When garbage collection runs, see `GcSchedule`.
+    pub gc_schedule: GcSchedule,
+    /// Whether to keep a record of every fork block deleted by garbage collection.
+    pub record_discarded_forks: bool,
+    /// Whether a validator keeps a record of the approvals included in the blocks it produces.
+    pub record_approval_audit: bool,
+    /// Partial encoded chunks of the blocks of this many latest epochs are not garbage collected
+    /// by archival nodes, so that their parts and merkle proofs remain available.
+    pub retain_partial_chunk_parts_epochs: u64,
+    /// Acknowledges that `neard gc_to_height` may prune data more aggressively than the regular
+    /// garbage collection, after which the node can no longer serve the removed history.
+    pub allow_aggressive_gc: bool,
+    /// Skip most of the chunk validation when replaying a trusted chain. Not allowed on mainnet
+    /// and testnet.
+    pub trusted_chain_replay: bool,
     /// Accounts that this client tracks
     pub tracked_accounts: Vec<AccountId>,
     /// Shards that this client tracks
@@ -95,12 +254,64 @@ pub struct ClientConfig {
     pub epoch_sync_enabled: bool,
     /// Number of seconds between state requests for view client.
     pub view_client_throttle_period: Duration,
+    /// Size of the cache of headers, blocks and chunk extras shared by the view client threads.
+    /// `None` means every thread only uses its own caches.
+    pub view_client_shared_cache_mb: Option<u64>,
+    /// Maximum number of distinct sync hashes the view client threads serve state for at once.
+    /// Only valid sync hashes are counted, requests for invalid ones aren't served anyway.
+    pub state_sync_max_served_sync_hashes: usize,
+    /// A served sync hash stops counting towards the limit if state isn't requested for it for
+    /// this long.
+    pub state_sync_served_sync_hashes_window: Duration,
     /// Upper bound of the byte size of contract state that is still viewable. None is no limit
     pub trie_viewer_state_size_limit: Option<u64>,
     /// Max burnt gas per view method.  If present, overrides value stored in
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
     pub max_gas_burnt_view: Option<Gas>,
+    /// Blocks with larger serialized size are rejected before any chunk is applied.
+    pub max_block_serialized_bytes: usize,
+    /// Chunks with more transactions are rejected before they are applied.
+    pub max_chunk_transactions: usize,
+    /// Run catchup before processing new blocks if too many state changes are waiting to be
+    /// applied to split states.
+    pub prioritize_split_state_catchup: bool,
+    /// Store updates saved from the preprocessing of blocks being caught up are spilled to the
+    /// store once the ones held in memory exceed this size in bytes.
+    pub catchup_saved_updates_memory_limit_bytes: u64,
+    /// Sync diagnostics are logged if the head doesn't advance for this long.
+    pub sync_stall_log_threshold: Duration,
+    /// Orphans are dropped if this many orphans with the same prev block are already stored.
+    pub max_orphans_per_prev_hash: usize,
+    /// A warning is logged once the incoming receipts waiting for a new chunk of a shard exceed
+    /// this size in bytes.
+    pub receipt_backlog_warn_bytes: u64,
+    /// Time after which processing of blocks whose missing chunks arrived is deferred to the next
+    /// check, so that a burst of such blocks doesn't block the client for too long.
+    pub missing_chunks_processing_budget: Duration,
+    /// Blocks ahead of the local clock by at most this much more than the acceptable time
+    /// difference are held back until the clock catches up, instead of being rejected.
+    pub future_block_grace_period: Duration,
+    /// Recompute the root of locally produced outgoing receipts and compare it against the root
+    /// claimed by the next chunk of the shard.
+    pub strict_receipt_root_checks: bool,
+    /// Reject the block on a receipts root mismatch found by the strict checks, instead of only
+    /// logging it.
+    pub strict_receipt_root_checks_fail_on_mismatch: bool,
+    /// Quarantine the state of a shard when a chunk we produced fails validation against our own
+    /// chunk extra, so that the shard is downloaded again from peers at the next epoch boundary.
+    pub quarantine_shard_on_state_mismatch: bool,
+    /// Check the merkle proofs of the incoming receipts of a block against the outgoing receipts
+    /// roots of its chunks before saving them.
+    pub verify_incoming_receipts: bool,
+    /// Transactions from signers which don't conform to the policy are rejected before any
+    /// routing work. `None` accepts transactions from all signers.
+    pub tx_routing_policy: Option<TxRoutingPolicy>,
+    /// What to do with blocks whose epoch protocol version isn't supported by the binary.
+    pub on_unsupported_protocol_version: UnsupportedProtocolVersionPolicy,
+    /// Gas price parameters overriding the genesis ones from a given height on. Only allowed on
+    /// chains other than mainnet and testnet.
+    pub economics_overrides: Option<EconomicsOverrides>,
 }
 
 impl ClientConfig {
@@ -133,6 +344,7 @@ impl ClientConfig {
             header_sync_stall_ban_timeout: Duration::from_secs(30),
             state_sync_timeout: Duration::from_secs(TEST_STATE_SYNC_TIMEOUT),
             header_sync_expected_height_per_second: 1,
+            header_sync_commit_batch_size: DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE,
             min_num_peers: 1,
             log_summary_period: Duration::from_secs(10),
             produce_empty_blocks: true,
@@ -150,6 +362,12 @@ impl ClientConfig {
             doosmslug_step_period: Duration::from_millis(100),
             block_header_fetch_horizon: 50,
             gc_blocks_limit: 100,
+            gc_schedule: GcSchedule::Always,
+            record_discarded_forks: false,
+            record_approval_audit: false,
+            retain_partial_chunk_parts_epochs: 0,
+            allow_aggressive_gc: false,
+            trusted_chain_replay: false,
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive,
@@ -157,8 +375,28 @@ impl ClientConfig {
             view_client_threads: 1,
             epoch_sync_enabled,
             view_client_throttle_period: Duration::from_secs(1),
+            view_client_shared_cache_mb: None,
+            state_sync_max_served_sync_hashes: DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES,
+            state_sync_served_sync_hashes_window: DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW,
             trie_viewer_state_size_limit: None,
             max_gas_burnt_view: None,
+            max_block_serialized_bytes: DEFAULT_MAX_BLOCK_SERIALIZED_BYTES,
+            max_chunk_transactions: DEFAULT_MAX_CHUNK_TRANSACTIONS,
+            prioritize_split_state_catchup: false,
+            catchup_saved_updates_memory_limit_bytes:
+                DEFAULT_CATCHUP_SAVED_UPDATES_MEMORY_LIMIT_BYTES,
+            sync_stall_log_threshold: Duration::from_secs(60),
+            max_orphans_per_prev_hash: DEFAULT_MAX_ORPHANS_PER_PREV_HASH,
+            receipt_backlog_warn_bytes: DEFAULT_RECEIPT_BACKLOG_WARN_BYTES,
+            missing_chunks_processing_budget: Duration::from_millis(200),
+            future_block_grace_period: DEFAULT_FUTURE_BLOCK_GRACE_PERIOD,
+            strict_receipt_root_checks: false,
+            strict_receipt_root_checks_fail_on_mismatch: false,
+            quarantine_shard_on_state_mismatch: false,
+            verify_incoming_receipts: true,
+            tx_routing_policy: None,
+            on_unsupported_protocol_version: UnsupportedProtocolVersionPolicy::Halt,
+            economics_overrides: None,
         }
     }
 }
@@ -694,6 +694,12 @@ pub fn get_initial_supply(records: &[StateRecord]) -> Balance {
     total_supply
 }
 
+/// Whether `chain_id` is one of the public networks, where settings changing how blocks are
+/// validated are refused.
+pub fn is_public_chain(chain_id: &str) -> bool {
+    matches!(chain_id, "mainnet" | "testnet")
+}
+
 #[cfg(test)]
 mod test {
     use crate::genesis_config::RecordsProcessor;
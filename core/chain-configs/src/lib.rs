@@ -2,8 +2,16 @@ mod client_config;
 mod genesis_config;
 pub mod genesis_validate;
 
-pub use client_config::{ClientConfig, LogSummaryStyle, TEST_STATE_SYNC_TIMEOUT};
+pub use client_config::{
+    ClientConfig, EconomicsOverrides, GcSchedule, LogSummaryStyle, TxRoutingPolicy,
+    UnsupportedProtocolVersionPolicy, DEFAULT_CATCHUP_SAVED_UPDATES_MEMORY_LIMIT_BYTES,
+    DEFAULT_FUTURE_BLOCK_GRACE_PERIOD, DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE,
+    DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND, DEFAULT_MAX_BLOCK_SERIALIZED_BYTES,
+    DEFAULT_MAX_CHUNK_TRANSACTIONS, DEFAULT_MAX_ORPHANS_PER_PREV_HASH,
+    DEFAULT_RECEIPT_BACKLOG_WARN_BYTES, DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES,
+    DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW, TEST_STATE_SYNC_TIMEOUT,
+};
 pub use genesis_config::{
-    get_initial_supply, Genesis, GenesisConfig, GenesisRecords, GenesisValidationMode,
-    ProtocolConfig, ProtocolConfigView,
+    get_initial_supply, is_public_chain, Genesis, GenesisConfig, GenesisRecords,
+    GenesisValidationMode, ProtocolConfig, ProtocolConfigView,
 };
@@ -9,8 +9,8 @@ use num_rational::Rational;
 use primitive_types::U256;
 
 use crate::block::BlockValidityError::{
-    InvalidChallengeRoot, InvalidChunkHeaderRoot, InvalidChunkMask, InvalidReceiptRoot,
-    InvalidStateRoot, InvalidTransactionRoot,
+    InvalidChallengeRoot, InvalidChunkHeaderRoot, InvalidChunkMask, InvalidChunkShardId,
+    InvalidReceiptRoot, InvalidStateRoot, InvalidTransactionRoot, MissingChunk,
 };
 pub use crate::block_header::*;
 use crate::challenge::{Challenges, ChallengesResult};
@@ -20,7 +20,7 @@ use crate::sharding::{
     ChunkHashHeight, EncodedShardChunk, ReedSolomonWrapper, ShardChunk, ShardChunkHeader,
     ShardChunkHeaderV1,
 };
-use crate::types::{Balance, BlockHeight, EpochId, Gas, NumBlocks, NumShards, StateRoot};
+use crate::types::{Balance, BlockHeight, EpochId, Gas, NumBlocks, NumShards, ShardId, StateRoot};
 use crate::utils::to_timestamp;
 use crate::validator_signer::{EmptyValidatorSigner, ValidatorSigner};
 use crate::version::{ProtocolVersion, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
@@ -44,6 +44,13 @@ pub enum BlockValidityError {
     InvalidTransactionRoot,
     InvalidChunkMask,
     InvalidChallengeRoot,
+    /// The block has no chunk for the shard.
+    MissingChunk(ShardId),
+    /// A chunk new in the block is stored at the position of another shard.
+    InvalidChunkShardId {
+        position: ShardId,
+        shard_id: ShardId,
+    },
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -459,6 +466,21 @@ impl Block {
         }
     }
 
+    /// Header of the chunk of `shard_id`, which is stored at the position of the shard id.
+    /// A chunk new in the block must be for that shard, while a chunk header carried over from
+    /// the previous block keeps the shard id of the parent shard after a resharding.
+    pub fn chunk_for_shard(
+        &self,
+        shard_id: ShardId,
+    ) -> Result<ShardChunkHeader, BlockValidityError> {
+        let chunks = self.chunks();
+        let chunk = chunks.get(shard_id as usize).ok_or(MissingChunk(shard_id))?;
+        if chunk.height_included() == self.header().height() && chunk.shard_id() != shard_id {
+            return Err(InvalidChunkShardId { position: shard_id, shard_id: chunk.shard_id() });
+        }
+        Ok(chunk.clone())
+    }
+
     #[inline]
     pub fn challenges(&self) -> &Challenges {
         match self {
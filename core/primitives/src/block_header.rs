@@ -318,6 +318,23 @@ impl BlockHeaderV3 {
     }
 }
 
+/// Layout version of a block header and the optional fields it carries.
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct HeaderDescription {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    /// 1, 2 or 3 for `BlockHeaderV1`, `BlockHeaderV2` and `BlockHeaderV3` respectively.
+    pub version: u8,
+    pub latest_protocol_version: ProtocolVersion,
+    /// Present since `BlockHeaderV3`.
+    pub prev_height: Option<BlockHeight>,
+    /// Present since `BlockHeaderV3`.
+    pub block_ordinal: Option<NumBlocks>,
+    /// Whether the header carries the epoch sync fields, present since `BlockHeaderV3`.
+    pub has_epoch_sync_fields: bool,
+    pub epoch_sync_data_hash: Option<CryptoHash>,
+}
+
 /// Versioned BlockHeader data structure.
 /// For each next version, document what are the changes between versions.
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -895,6 +912,30 @@ impl BlockHeader {
         }
     }
 
+    /// Layout version of the header: 1, 2 or 3.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        match self {
+            BlockHeader::BlockHeaderV1(_) => 1,
+            BlockHeader::BlockHeaderV2(_) => 2,
+            BlockHeader::BlockHeaderV3(_) => 3,
+        }
+    }
+
+    pub fn describe(&self) -> HeaderDescription {
+        let is_v3 = matches!(self, BlockHeader::BlockHeaderV3(_));
+        HeaderDescription {
+            hash: *self.hash(),
+            height: self.height(),
+            version: self.version(),
+            latest_protocol_version: self.latest_protocol_version(),
+            prev_height: self.prev_height(),
+            block_ordinal: is_v3.then(|| self.block_ordinal()),
+            has_epoch_sync_fields: is_v3,
+            epoch_sync_data_hash: self.epoch_sync_data_hash(),
+        }
+    }
+
     pub fn inner_lite_bytes(&self) -> Vec<u8> {
         match self {
             BlockHeader::BlockHeaderV1(header) => {
@@ -208,6 +208,24 @@ impl ShardLayout {
         }
     }
 
+    /// Accounts which are in the shard of their position together with all their sub-accounts.
+    /// Empty for V0.
+    pub fn fixed_shards(&self) -> &[AccountId] {
+        match self {
+            Self::V0(_) => &[],
+            Self::V1(v1) => &v1.fixed_shards,
+        }
+    }
+
+    /// Accounts dividing the accounts which are not in a fixed shard into ranges, each range
+    /// mapped to a shard. Empty for V0.
+    pub fn boundary_accounts(&self) -> &[AccountId] {
+        match self {
+            Self::V0(_) => &[],
+            Self::V1(v1) => &v1.boundary_accounts,
+        }
+    }
+
     /// Returns shard uids for all shards in the shard layout
     pub fn get_shard_uids(&self) -> Vec<ShardUId> {
         (0..self.num_shards()).map(|x| ShardUId::from_shard_id_and_layout(x, self)).collect()
@@ -43,6 +43,10 @@ struct MockClockPerState {
     utc_call_count: u64,
     /// Number of times `Clock::instant()` method was called since we started mocking.
     instant_call_count: u64,
+    /// Timestamp returned by `Clock::utc()` once `utc_list` is used up, if set.
+    utc_now: Option<DateTime<Utc>>,
+    /// Timestamp returned by `Clock::instant()` once `instant_list` is used up, if set.
+    instant_now: Option<Instant>,
 }
 
 /// Stores the mocking state.
@@ -90,6 +94,36 @@ impl MockClockGuard {
         });
     }
 
+    /// Makes `Clock::utc()` and `Clock::instant()` return the given timestamps once the queued
+    /// ones are used up, instead of panicking. They only change with `Self::advance`.
+    pub fn set_now(&self, utc_now: DateTime<chrono::Utc>, instant_now: Instant) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                clock.utc_now = Some(utc_now);
+                clock.instant_now = Some(instant_now);
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
+    /// Moves the timestamps set by `Self::set_now` forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                let utc_now = clock.utc_now.as_mut().expect("Use MockClockGuard::set_now first");
+                *utc_now = *utc_now + chrono::Duration::from_std(duration).unwrap();
+                let instant_now =
+                    clock.instant_now.as_mut().expect("Use MockClockGuard::set_now first");
+                *instant_now += duration;
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
     /// Returns number of calls  to `Self::utc` since `Self::mock()` was called.
     pub fn utc_call_count(&self) -> u64 {
         MockClockPerThread::with(|clock| match &mut clock.mock {
@@ -148,7 +182,7 @@ impl Clock {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.instant_call_count += 1;
-                let x = clock.instant_list.pop_front();
+                let x = clock.instant_list.pop_front().or(clock.instant_now);
                 match x {
                     Some(t) => t,
                     None => {
@@ -166,7 +200,7 @@ impl Clock {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.utc_call_count += 1;
-                let x = clock.utc_list.pop_front();
+                let x = clock.utc_list.pop_front().or(clock.utc_now);
                 match x {
                     Some(t) => t,
                     None => {
@@ -274,6 +308,25 @@ mod tests {
         assert_eq!(mock_clock_guard.instant_call_count(), 0);
     }
 
+    #[test]
+    fn test_clock_now() {
+        let mock_clock_guard = MockClockGuard::default();
+
+        let utc_now = Utc::now();
+        let instant_now = Instant::now();
+        mock_clock_guard.add_instant(instant_now.add(Duration::from_secs(5)));
+        mock_clock_guard.set_now(utc_now, instant_now);
+        assert_eq!(Clock::instant(), instant_now.add(Duration::from_secs(5)));
+        assert_eq!(Clock::instant(), instant_now);
+        assert_eq!(Clock::instant(), instant_now);
+        assert_eq!(Clock::utc(), utc_now);
+
+        mock_clock_guard.advance(Duration::from_secs(1));
+        assert_eq!(Clock::instant(), instant_now.add(Duration::from_secs(1)));
+        assert_eq!(Clock::utc(), utc_now + chrono::Duration::seconds(1));
+        assert_eq!(mock_clock_guard.instant_call_count(), 4);
+    }
+
     #[test]
     fn test_threading() {
         thread::spawn(|| {
@@ -17,7 +17,7 @@ pub struct Version {
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 31;
+pub const DB_VERSION: DbVersion = 40;
 
 use crate::upgrade_schedule::{get_protocol_version_internal, ProtocolUpgradeVotingSchedule};
 /// Protocol version type.
@@ -137,6 +137,9 @@ pub enum ProtocolFeature {
     CorrectStackLimit,
     /// Add `AccessKey` nonce range for implicit accounts, as in `AccessKeyNonceRange` feature.
     AccessKeyNonceForImplicitAccounts,
+    /// Peers understand `StateResponseInfo::V3`, which suggests a sync hash on an epoch
+    /// boundary when the requested one is not.
+    StateResponseSyncHashSuggestion,
 
     // nightly features
     #[cfg(feature = "protocol_feature_alt_bn128")]
@@ -161,7 +164,7 @@ pub const PEER_MIN_ALLOWED_PROTOCOL_VERSION: ProtocolVersion = STABLE_PROTOCOL_V
 /// Current protocol version used on the mainnet.
 /// Some features (e. g. FixStorageUsage) require that there is at least one epoch with exactly
 /// the corresponding version
-const STABLE_PROTOCOL_VERSION: ProtocolVersion = 53;
+const STABLE_PROTOCOL_VERSION: ProtocolVersion = 54;
 
 /// Version used by this binary.
 #[cfg(not(feature = "nightly_protocol"))]
@@ -218,6 +221,7 @@ impl ProtocolFeature {
             ProtocolFeature::SynchronizeBlockChunkProduction
             | ProtocolFeature::CorrectStackLimit => 50,
             ProtocolFeature::AccessKeyNonceForImplicitAccounts => 51,
+            ProtocolFeature::StateResponseSyncHashSuggestion => 54,
 
             // Nightly features
             #[cfg(feature = "protocol_feature_alt_bn128")]
@@ -3,6 +3,7 @@
 //! These types should only change when we cannot avoid this. Thus, when the counterpart internal
 //! type gets changed, the view should preserve the old shape and only re-map the necessary bits
 //! from the source structure in the relevant `From<SourceStruct>` impl.
+use std::collections::BTreeMap;
 use std::fmt;
 use std::sync::Arc;
 
@@ -24,12 +25,15 @@ use crate::errors::TxExecutionError;
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
+use crate::network::PeerId;
+use crate::num_rational::Rational;
 use crate::profile::Cost;
 use crate::receipt::{ActionReceipt, DataReceipt, DataReceiver, Receipt, ReceiptEnum};
 use crate::serialize::{
     base64_format, from_base64, option_base64_format, option_u128_dec_format, to_base64,
     u128_dec_format, u64_dec_format,
 };
+use crate::shard_layout::{ShardLayout, ShardVersion};
 use crate::sharding::{
     ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderInnerV2,
     ShardChunkHeaderV3,
@@ -40,10 +44,10 @@ use crate::transaction::{
     ExecutionStatus, FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
 };
 use crate::types::{
-    AccountId, AccountWithPublicKey, Balance, BlockHeight, CompiledContractCache, EpochHeight,
-    EpochId, FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
-    StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey,
-    StoreValue, ValidatorKickoutReason,
+    AccountId, AccountWithPublicKey, Balance, BlockHeight, BlockHeightDelta, CompiledContractCache,
+    EpochHeight, EpochId, FunctionArgs, Gas, Nonce, NumBlocks, NumShards, ShardId,
+    StateChangeCause, StateChangeKind, StateChangeValue, StateChangeWithCause, StateChangesRequest,
+    StateRoot, StorageUsage, StoreKey, StoreValue, ValidatorKickoutReason,
 };
 use crate::version::{ProtocolVersion, Version};
 use validator_stake_view::ValidatorStakeView;
@@ -386,6 +390,175 @@ pub struct DetailedDebugStatus {
     pub sync_status: String,
     pub current_head_status: BlockStatusView,
     pub current_header_head_status: BlockStatusView,
+    /// RocksDB settings of the columns of the store, empty if the store is not backed by RocksDB.
+    pub store_tuning: Vec<StoreColumnTuningView>,
+}
+
+/// Block cache and write buffer sizes a column of the store is opened with.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StoreColumnTuningView {
+    pub column: String,
+    pub block_cache_size: u64,
+    pub write_buffer_size: u64,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatchupDiagnosticsView {
+    pub sync_hash: CryptoHash,
+    pub num_shards: usize,
+    pub num_blocks_to_catchup: usize,
+}
+
+/// State parts downloaded from a peer for the state syncs in progress.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StatePartProviderView {
+    pub peer_id: PeerId,
+    pub num_parts: usize,
+    pub average_latency_ms: u64,
+    pub max_latency_ms: u64,
+}
+
+/// Outcome of the last garbage collection run.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GcRunView {
+    pub gc_stop_height: BlockHeight,
+    pub tail: BlockHeight,
+    pub num_blocks_collected: NumBlocks,
+    pub finished_at: DateTime<chrono::Utc>,
+}
+
+/// Chain conditions explaining why the node may be not syncing.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncDiagnosticsView {
+    pub likely_causes: Vec<String>,
+    pub head_height: BlockHeight,
+    pub header_head_height: BlockHeight,
+    pub final_head_height: BlockHeight,
+    pub num_orphans: usize,
+    pub num_orphans_evicted: usize,
+    pub num_blocks_with_missing_chunks: usize,
+    pub pending_catchups: Vec<CatchupDiagnosticsView>,
+    pub catchup_bookkeeping_issues: Vec<String>,
+    pub unfinished_state_syncs: Vec<(CryptoHash, ShardId)>,
+    pub num_partially_applied_blocks: usize,
+    pub state_part_providers: Vec<StatePartProviderView>,
+    pub tail: BlockHeight,
+    pub chunk_tail: BlockHeight,
+    pub fork_tail: BlockHeight,
+    pub last_gc_run: Option<GcRunView>,
+    pub head_epoch_known: bool,
+}
+
+/// Estimated progress of the sync.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncProgressView {
+    pub phase: String,
+    /// Progress of the current phase, between 0 and 100.
+    pub percent: f64,
+    /// Estimated number of seconds until the node is synced, if known.
+    pub eta_secs: Option<u64>,
+}
+
+/// Progress of the download of the state of a shard for the catchup of the next epoch.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardStateDownloadStatusView {
+    pub shard_id: ShardId,
+    pub header_stored: bool,
+    pub parts_downloaded: u64,
+    pub parts_total: u64,
+    /// Height the state has been finalized up to, while finalization is in progress.
+    pub finalize_height: Option<BlockHeight>,
+    pub finalized: bool,
+}
+
+/// State downloads for the catchup of an epoch, see `EpochStateDownloadStatus` in near-chain.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochStateDownloadStatusView {
+    pub epoch_id: EpochId,
+    pub sync_hash: CryptoHash,
+    pub shards: Vec<ShardStateDownloadStatusView>,
+}
+
+/// Earliest heights from which each class of data can be queried from the node.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DataAvailabilityView {
+    pub headers: BlockHeight,
+    pub blocks: BlockHeight,
+    pub chunks: BlockHeight,
+    pub partial_chunks: BlockHeight,
+    pub execution_outcomes: BlockHeight,
+    pub state: BlockHeight,
+}
+
+/// A block known at some height, see `BlockAtHeightInfo` in near-chain.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockAtHeightView {
+    pub hash: CryptoHash,
+    pub prev_hash: Option<CryptoHash>,
+    pub epoch_id: EpochId,
+    pub producer: Option<AccountId>,
+    pub is_canonical: bool,
+    pub is_challenged: bool,
+    pub has_body: bool,
+}
+
+/// A fork block deleted by garbage collection, see `DiscardedFork` in near-chain.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiscardedForkView {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+    pub prev_hash: CryptoHash,
+    pub producer: Option<AccountId>,
+    #[serde(with = "u64_dec_format")]
+    pub timestamp_nanosec: u64,
+}
+
+/// A block rejected by block processing as invalid, see `RejectedBlock` in near-chain.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RejectedBlockView {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    pub producer: Option<AccountId>,
+    pub error: String,
+    #[serde(with = "u64_dec_format")]
+    pub timestamp_nanosec: u64,
+}
+
+/// What garbage collection would delete, see `GcPlan` in near-chain.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GcPlanView {
+    pub fork_blocks: Vec<CryptoHash>,
+    /// Canonical blocks at heights from the start, inclusive, to the end, exclusive, are deleted.
+    pub canonical_start_height: BlockHeight,
+    pub canonical_end_height: BlockHeight,
+    pub estimated_keys: u64,
+}
+
+/// Totals of the blocks of an epoch on the canonical chain, see `EpochSummary` in near-chain.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochSummaryView {
+    pub epoch_height: EpochHeight,
+    pub blocks_produced: NumBlocks,
+    pub chunks_produced_per_shard: Vec<NumBlocks>,
+    /// Only counts the transactions of the shards tracked by the node.
+    pub total_txs: u64,
+    pub total_gas_used: Gas,
+    pub first_block_hash: CryptoHash,
+    pub last_block_hash: CryptoHash,
 }
 
 // TODO: add more information to status.
@@ -412,6 +585,15 @@ pub struct StatusResponse {
     /// Information about last blocks, sync info and chain info.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detailed_debug_status: Option<DetailedDebugStatus>,
+    /// Earliest heights from which blocks, chunks, outcomes and state can be queried.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_availability: Option<DataAvailabilityView>,
+    /// Estimated progress of the sync, relative to the highest height reported by the peers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_progress: Option<SyncProgressView>,
+    /// State downloads in progress for the catchup of the next epoch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub state_downloads: Vec<EpochStateDownloadStatusView>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -823,6 +1005,17 @@ impl ChunkView {
     }
 }
 
+/// Erasure coded part of a chunk with the proof of its inclusion in the encoded merkle root of
+/// the chunk header.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PartialChunkPartView {
+    pub header: ChunkHeaderView,
+    pub part_ord: u64,
+    #[serde(with = "base64_format")]
+    pub part: Vec<u8>,
+    pub merkle_proof: MerklePath,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(Serialize, Deserialize, Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
 pub enum ActionView {
@@ -1181,6 +1374,8 @@ impl From<ExecutionOutcomeWithIdAndProof> for ExecutionOutcomeWithIdView {
 pub enum FinalExecutionOutcomeViewEnum {
     FinalExecutionOutcome(FinalExecutionOutcomeView),
     FinalExecutionOutcomeWithReceipt(FinalExecutionOutcomeWithReceiptView),
+    /// Returned when the node has the outcomes, but not the transaction itself.
+    FinalExecutionOutcomeLite(FinalExecutionOutcomeLiteView),
 }
 
 /// Final execution outcome of the transaction and all of subsequent the receipts.
@@ -1211,6 +1406,17 @@ impl fmt::Debug for FinalExecutionOutcomeView {
     }
 }
 
+/// Final execution outcome of the transaction and of the receipts whose outcome the node has.
+/// The status is `Started` if the outcome of any receipt is missing, e.g. because it was garbage
+/// collected or the node doesn't track the shard of the receipt.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct PartialFinalExecutionOutcomeView {
+    #[serde(flatten)]
+    pub final_outcome: FinalExecutionOutcomeView,
+    /// Receipts whose outcome the node doesn't have. The receipts they produced are missing too.
+    pub missing_receipt_ids: Vec<CryptoHash>,
+}
+
 /// Final execution outcome of the transaction and all of subsequent the receipts. Also includes
 /// the generated receipt.
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -1229,6 +1435,33 @@ impl From<FinalExecutionOutcomeWithReceiptView> for FinalExecutionOutcomeView {
     }
 }
 
+/// Final execution outcome of the transaction and all of the subsequent receipts, without the
+/// signed transaction. Available even if the node doesn't have the transaction, e.g. because its
+/// chunk was garbage collected or the node doesn't track the shard of the signer.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct FinalExecutionOutcomeLiteView {
+    /// Execution status. Contains the result in case of successful execution.
+    pub status: FinalExecutionStatus,
+    /// Hash of the signed transaction.
+    pub transaction_hash: CryptoHash,
+    /// The execution outcome of the signed transaction.
+    pub transaction_outcome: ExecutionOutcomeWithIdView,
+    /// The execution outcome of receipts.
+    pub receipts_outcome: Vec<ExecutionOutcomeWithIdView>,
+}
+
+impl From<FinalExecutionOutcomeView> for FinalExecutionOutcomeLiteView {
+    fn from(final_outcome_view: FinalExecutionOutcomeView) -> Self {
+        Self {
+            status: final_outcome_view.status,
+            transaction_hash: final_outcome_view.transaction.hash,
+            transaction_outcome: final_outcome_view.transaction_outcome,
+            receipts_outcome: final_outcome_view.receipts_outcome,
+        }
+    }
+}
+
 pub mod validator_stake_view {
     use crate::types::validator_stake::ValidatorStake;
     use borsh::{BorshDeserialize, BorshSerialize};
@@ -1534,6 +1767,92 @@ pub struct GasPriceView {
     pub gas_price: Balance,
 }
 
+/// Chain parameters in effect at the head of the node's chain, for clients which would otherwise
+/// hardcode them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainParametersView {
+    /// Protocol version of the epoch of the head.
+    pub protocol_version: ProtocolVersion,
+    /// Number of blocks for which a transaction is valid.
+    pub transaction_validity_period: NumBlocks,
+    /// Epoch length counted in block heights.
+    pub epoch_length: BlockHeightDelta,
+    #[serde(with = "u128_dec_format")]
+    pub min_gas_price: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub max_gas_price: Balance,
+    /// Rate at which the gas price follows the fullness of blocks.
+    pub gas_price_adjustment_rate: Rational,
+    pub num_shards: NumShards,
+    pub shard_layout: ShardLayout,
+    /// Number of epochs for which blocks and state are kept before being garbage collected.
+    pub gc_num_epochs_to_keep: u64,
+}
+
+/// Shard layout in effect in an epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardLayoutView {
+    pub epoch_id: EpochId,
+    pub version: ShardVersion,
+    pub num_shards: NumShards,
+    /// Accounts which are in the shard of their position together with all their sub-accounts.
+    pub fixed_shards: Vec<AccountId>,
+    /// Accounts dividing the other accounts into ranges, each range mapped to the next shard.
+    pub boundary_accounts: Vec<AccountId>,
+}
+
+/// Shard of an account in an epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountShardView {
+    pub account_id: AccountId,
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    /// Version of the shard layout of the epoch, which tells apart shards with the same id before
+    /// and after a resharding.
+    pub shard_layout_version: ShardVersion,
+}
+
+/// Block in the orphan pool waiting for its prev block, see `Chain::orphan_pool_summary` in
+/// near-chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrphanView {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    pub prev_hash: CryptoHash,
+    /// Whether the missing chunks of the orphan were requested.
+    pub requested_missing_chunks: bool,
+    pub age_ms: u64,
+}
+
+/// Contents of the orphan pool and the number of orphans evicted from it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrphanPoolSummaryView {
+    pub num_orphans: usize,
+    pub orphans_per_height: BTreeMap<BlockHeight, usize>,
+    pub oldest_orphan_age_ms: Option<u64>,
+    /// Orphans ordered by height and hash.
+    pub orphans: Vec<OrphanView>,
+    pub num_evicted: usize,
+    /// Orphans evicted because they were in the pool for too long.
+    pub num_evicted_by_age: usize,
+    /// Orphans evicted because they were the highest ones in a full pool.
+    pub num_evicted_by_height: usize,
+}
+
+/// Erasure coded parts of the chunks of an epoch owned by an account, see
+/// `Chain::part_ownership` in near-chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartOwnershipView {
+    pub epoch_id: EpochId,
+    pub account_id: AccountId,
+    pub num_total_parts: u64,
+    pub num_data_parts: u64,
+    /// Ids of the parts owned by the account.
+    pub owned_parts: Vec<u64>,
+    /// Owners of the parts by part id, only of the first `MAX_PART_OWNERSHIP_OWNERS` parts.
+    pub part_owners: Vec<AccountId>,
+}
+
 /// It is a [serializable view] of [`StateChangesRequest`].
 ///
 /// [serializable view]: ./index.html
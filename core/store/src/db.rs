@@ -265,6 +265,47 @@ pub enum DBCol {
     /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
     /// - *Column type*: StateChangesForSplitStates
     ColStateChangesForSplitStates = 49,
+    /// Previous block with a new chunk for the shard, indexed by block hash and shard id
+    /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
+    /// - *Column type*: (CryptoHash, ShardId)
+    ColPrevBlockWithNewChunk = 50,
+    /// Records of fork blocks deleted by garbage collection, indexed by height
+    /// - *Rows*: height (u64)
+    /// - *Column type*: Vec<DiscardedFork>
+    ColDiscardedForks = 51,
+    /// Id of the transaction or receipt whose execution produced a receipt
+    /// - *Rows*: receipt id (CryptoHash)
+    /// - *Column type*: CryptoHash
+    ColOutcomeParent = 52,
+    /// Last height up to which the state downloaded by state sync has been finalized
+    /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
+    /// - *Column type*: BlockHeight
+    ColStateSyncFinalizeProgress = 53,
+    /// Blocks saved as the previous block of the sync block of state sync, whose previous block
+    /// is unknown and so doesn't count them in its refcount
+    /// - *Rows*: block hash (CryptoHash)
+    /// - *Column type*: ()
+    ColStateSyncBoundaryBlocks = 54,
+    /// Totals of the blocks of an epoch up to and including the given block
+    /// - *Rows*: block hash (CryptoHash)
+    /// - *Column type*: EpochSummary
+    ColEpochSummaryCounters = 55,
+    /// Totals of the blocks of an epoch on the canonical chain, saved when the epoch ends
+    /// - *Rows*: EpochHeight (u64)
+    /// - *Column type*: EpochSummary
+    ColEpochSummaries = 56,
+    /// Outcomes produced in a block by a shard, in the order of their ids in `ColOutcomeIds`
+    /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
+    /// - *Column type*: Vec<ExecutionOutcomeWithIdAndProof>
+    ColOutcomesByBlockShard = 57,
+    /// Latest blocks rejected by block processing as invalid, for operator review
+    /// - *Rows*: sequence number of the rejection (u64)
+    /// - *Column type*: RejectedBlock
+    ColRejectedBlocks = 58,
+    /// Approvals included in the blocks produced by this node, for auditing
+    /// - *Rows*: block hash (CryptoHash)
+    /// - *Column type*: ApprovalAuditRecord
+    ColApprovalAudit = 59,
 }
 
 impl std::fmt::Display for DBCol {
@@ -322,6 +363,16 @@ impl std::fmt::Display for DBCol {
             Self::ColStateChangesForSplitStates => {
                 "state changes indexed by block hash and shard id"
             }
+            Self::ColPrevBlockWithNewChunk => "previous block with new chunk",
+            Self::ColDiscardedForks => "discarded fork blocks",
+            Self::ColOutcomeParent => "parent of receipt",
+            Self::ColStateSyncFinalizeProgress => "state sync finalization progress",
+            Self::ColStateSyncBoundaryBlocks => "state sync boundary blocks",
+            Self::ColEpochSummaryCounters => "epoch summary counters",
+            Self::ColEpochSummaries => "epoch summaries",
+            Self::ColOutcomesByBlockShard => "outcomes by block hash and shard id",
+            Self::ColRejectedBlocks => "rejected blocks",
+            Self::ColApprovalAudit => "approval audit records",
         };
         write!(formatter, "{}", desc)
     }
@@ -355,6 +406,13 @@ pub static SHOULD_COL_GC: [bool; DBCol::COUNT] = {
     col_gc[DBCol::ColEpochValidatorInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
     col_gc[DBCol::ColEpochStart as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
     col_gc[DBCol::ColCachedContractCode as usize] = false;
+    col_gc[DBCol::ColDiscardedForks as usize] = false; // pruned by height in clear_data
+    col_gc[DBCol::ColStateSyncFinalizeProgress as usize] = false; // deleted once finalized or with the block
+    col_gc[DBCol::ColStateSyncBoundaryBlocks as usize] = false; // deleted with the block
+    col_gc[DBCol::ColEpochSummaryCounters as usize] = false; // deleted with the block
+    col_gc[DBCol::ColEpochSummaries as usize] = false;
+    col_gc[DBCol::ColRejectedBlocks as usize] = false; // pruned when new blocks are rejected
+    col_gc[DBCol::ColApprovalAudit as usize] = false; // deleted with the block
     col_gc
 };
 
@@ -388,14 +446,28 @@ pub const HEADER_HEAD_KEY: &[u8; 11] = b"HEADER_HEAD";
 pub const FINAL_HEAD_KEY: &[u8; 10] = b"FINAL_HEAD";
 pub const LATEST_KNOWN_KEY: &[u8; 12] = b"LATEST_KNOWN";
 pub const LARGEST_TARGET_HEIGHT_KEY: &[u8; 21] = b"LARGEST_TARGET_HEIGHT";
+/// Prefix of the keys of quarantined shards, followed by the `ShardUId` bytes.
+pub const QUARANTINED_SHARD_KEY: &[u8; 17] = b"QUARANTINED_SHARD";
+/// Prefix of the keys of blocks applied without the shards deferred to catchup, followed by the
+/// block hash.
+pub const PARTIALLY_APPLIED_BLOCK_KEY: &[u8; 23] = b"PARTIALLY_APPLIED_BLOCK";
+/// Prefix of the keys of store updates of blocks being caught up which were spilled to disk,
+/// followed by the block hash.
+pub const SPILLED_STORE_UPDATE_KEY: &[u8; 20] = b"SPILLED_STORE_UPDATE";
+/// Index of the next block rejected as invalid in `ColRejectedBlocks`.
+pub const REJECTED_BLOCKS_NEXT_INDEX_KEY: &[u8; 27] = b"REJECTED_BLOCKS_NEXT_INDEX";
+/// Version of the chain data layout, see `near_chain::migrations`.
+pub const CHAIN_SCHEMA_VERSION_KEY: &[u8; 20] = b"CHAIN_SCHEMA_VERSION";
 pub const VERSION_KEY: &[u8; 7] = b"VERSION";
 pub const GENESIS_JSON_HASH_KEY: &[u8; 17] = b"GENESIS_JSON_HASH";
 pub const GENESIS_STATE_ROOTS_KEY: &[u8; 19] = b"GENESIS_STATE_ROOTS";
 
+#[derive(BorshSerialize, BorshDeserialize)]
 pub struct DBTransaction {
     pub ops: Vec<DBOp>,
 }
 
+#[derive(BorshSerialize, BorshDeserialize)]
 pub enum DBOp {
     Insert { col: DBCol, key: Vec<u8>, value: Vec<u8> },
     UpdateRefcount { col: DBCol, key: Vec<u8>, value: Vec<u8> },
@@ -442,6 +514,10 @@ pub struct RocksDB {
     check_free_space_counter: std::sync::atomic::AtomicU16,
     check_free_space_interval: u16,
     free_space_threshold: bytesize::ByteSize,
+    /// Whether the database was opened with `RocksDBOptions::read_only`.
+    read_only: bool,
+    /// Settings the columns were opened with, indexed by column.
+    column_tuning: Vec<ColumnTuning>,
 
     // RAII-style of keeping track of the number of instances of RocksDB in a global variable.
     _instance_counter: InstanceCounter,
@@ -471,6 +547,7 @@ pub struct RocksDBOptions {
     free_space_threshold: bytesize::ByteSize,
     warn_treshold: bytesize::ByteSize,
     enable_statistics: bool,
+    column_tuning: HashMap<DBCol, ColumnTuning>,
 }
 
 /// Sets [`RocksDBOptions::check_free_space_interval`] to 256,
@@ -486,6 +563,7 @@ impl Default for RocksDBOptions {
             free_space_threshold: bytesize::ByteSize::mb(16),
             warn_treshold: bytesize::ByteSize::mb(256),
             enable_statistics: false,
+            column_tuning: HashMap::new(),
         }
     }
 }
@@ -528,11 +606,33 @@ impl RocksDBOptions {
         self
     }
 
+    /// Overrides the block cache and write buffer sizes of the given columns, the other columns
+    /// keep `ColumnTuning::default_for`.
+    pub fn column_tuning(mut self, column_tuning: HashMap<DBCol, ColumnTuning>) -> Self {
+        self.column_tuning = column_tuning;
+        self
+    }
+
+    /// Settings of every column, indexed by column.
+    fn all_column_tuning(&self) -> Vec<ColumnTuning> {
+        use strum::IntoEnumIterator;
+        DBCol::iter()
+            .map(|col| {
+                self.column_tuning
+                    .get(&col)
+                    .copied()
+                    .unwrap_or_else(|| ColumnTuning::default_for(col))
+            })
+            .collect()
+    }
+
     /// Opens a read only database.
     pub fn read_only<P: AsRef<std::path::Path>>(self, path: P) -> Result<RocksDB, DBError> {
         use strum::IntoEnumIterator;
+        let column_tuning = self.all_column_tuning();
         let options = self.rocksdb_options.unwrap_or_else(rocksdb_options);
-        let cf_with_opts = DBCol::iter().map(|col| (col_name(col), rocksdb_column_options(col)));
+        let cf_with_opts = DBCol::iter()
+            .map(|col| (col_name(col), rocksdb_column_options(col, &column_tuning[col as usize])));
         let db = DB::open_cf_with_opts_for_read_only(&options, path, cf_with_opts, false)?;
         let cfs = DBCol::iter()
             .map(|col| db.cf_handle(&col_name(col)).unwrap() as *const ColumnFamily)
@@ -545,6 +645,8 @@ impl RocksDBOptions {
             check_free_space_interval: self.check_free_space_interval,
             check_free_space_counter: std::sync::atomic::AtomicU16::new(0),
             free_space_threshold: self.free_space_threshold,
+            read_only: true,
+            column_tuning,
             _instance_counter: InstanceCounter::new(),
         })
     }
@@ -552,6 +654,7 @@ impl RocksDBOptions {
     /// Opens the database in read/write mode.
     pub fn read_write<P: AsRef<std::path::Path>>(self, path: P) -> Result<RocksDB, DBError> {
         use strum::IntoEnumIterator;
+        let column_tuning = self.all_column_tuning();
         let mut options = self.rocksdb_options.unwrap_or_else(rocksdb_options);
         if self.enable_statistics {
             options = enable_statistics(options);
@@ -560,7 +663,12 @@ impl RocksDBOptions {
             self.cf_names.unwrap_or_else(|| DBCol::iter().map(|col| col_name(col)).collect());
         let cf_descriptors = self.cf_descriptors.unwrap_or_else(|| {
             DBCol::iter()
-                .map(|col| ColumnFamilyDescriptor::new(col_name(col), rocksdb_column_options(col)))
+                .map(|col| {
+                    ColumnFamilyDescriptor::new(
+                        col_name(col),
+                        rocksdb_column_options(col, &column_tuning[col as usize]),
+                    )
+                })
                 .collect()
         });
         let db = DB::open_cf_descriptors(&options, path, cf_descriptors)?;
@@ -582,6 +690,8 @@ impl RocksDBOptions {
             check_free_space_interval: self.check_free_space_interval,
             check_free_space_counter: std::sync::atomic::AtomicU16::new(0),
             free_space_threshold: self.free_space_threshold,
+            read_only: false,
+            column_tuning,
             _instance_counter: InstanceCounter::new(),
         })
     }
@@ -618,6 +728,14 @@ pub trait Database: Sync + Send {
     fn get_store_statistics(&self) -> Option<StoreStatistics> {
         None
     }
+    /// Whether writes to the database are rejected.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+    /// Block cache and write buffer sizes the columns were opened with.
+    fn get_column_tuning(&self) -> Option<Vec<(DBCol, ColumnTuning)>> {
+        None
+    }
 }
 
 impl Database for RocksDB {
@@ -715,6 +833,15 @@ impl Database for RocksDB {
         Some(self)
     }
 
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn get_column_tuning(&self) -> Option<Vec<(DBCol, ColumnTuning)>> {
+        use strum::IntoEnumIterator;
+        Some(DBCol::iter().zip(self.column_tuning.iter().copied()).collect())
+    }
+
     fn get_store_statistics(&self) -> Option<StoreStatistics> {
         if let Some(stats_str) = self.db_opt.get_statistics() {
             match parse_statistics(&stats_str) {
@@ -868,20 +995,37 @@ fn rocksdb_block_based_options(cache_size: usize) -> BlockBasedOptions {
     block_opts
 }
 
-// TODO(#5213) Use ByteSize package to represent sizes.
-fn choose_cache_size(col: DBCol) -> usize {
-    match col {
-        DBCol::ColState => 512 * 1024 * 1024,
-        _ => 32 * 1024 * 1024,
+/// Sizes of the RocksDB block cache and write buffer of a column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColumnTuning {
+    pub block_cache_size: bytesize::ByteSize,
+    /// Size of a single memtable, a column keeps up to 6 of them in memory.
+    pub write_buffer_size: bytesize::ByteSize,
+}
+
+impl ColumnTuning {
+    /// Settings of the column when they are not overridden.
+    pub fn default_for(col: DBCol) -> Self {
+        let block_cache_size = match col {
+            DBCol::ColState => bytesize::ByteSize::mib(512),
+            _ => bytesize::ByteSize::mib(32),
+        };
+        // The write buffer size `optimize_level_style_compaction` picks for
+        // `MEMTABLE_MEMORY_BUDGET`.
+        let write_buffer_size = bytesize::ByteSize::b(MEMTABLE_MEMORY_BUDGET as u64 / 4);
+        ColumnTuning { block_cache_size, write_buffer_size }
     }
 }
 
-fn rocksdb_column_options(col: DBCol) -> Options {
+const MEMTABLE_MEMORY_BUDGET: usize = 128 * bytesize::MIB as usize;
+
+fn rocksdb_column_options(col: DBCol, tuning: &ColumnTuning) -> Options {
     let mut opts = Options::default();
     set_compression_options(&mut opts);
     opts.set_level_compaction_dynamic_level_bytes(true);
-    let cache_size = choose_cache_size(col);
-    opts.set_block_based_table_factory(&rocksdb_block_based_options(cache_size));
+    opts.set_block_based_table_factory(&rocksdb_block_based_options(
+        tuning.block_cache_size.as_u64() as usize,
+    ));
 
     // Note that this function changes a lot of rustdb parameters including:
     //      write_buffer_size = memtable_memory_budget / 4
@@ -895,8 +1039,8 @@ fn rocksdb_column_options(col: DBCol) -> Options {
     // the rest use LZ4 compression.
     // See the implementation here:
     //      https://github.com/facebook/rocksdb/blob/c18c4a081c74251798ad2a1abf83bad417518481/options/options.cc#L588.
-    let memtable_memory_budget = 128 * bytesize::MIB as usize;
-    opts.optimize_level_style_compaction(memtable_memory_budget);
+    opts.optimize_level_style_compaction(MEMTABLE_MEMORY_BUDGET);
+    opts.set_write_buffer_size(tuning.write_buffer_size.as_u64() as usize);
 
     opts.set_target_file_size_base(64 * bytesize::MIB);
     if col.is_rc() {
@@ -4,7 +4,7 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use rocksdb::{ColumnFamilyDescriptor, MergeOperands, Options};
 use strum::IntoEnumIterator;
 
-use crate::db::{col_name, rocksdb_column_options, DBError, RocksDB, RocksDBOptions};
+use crate::db::{col_name, rocksdb_column_options, ColumnTuning, DBError, RocksDB, RocksDBOptions};
 use crate::DBCol;
 
 fn refcount_merge_v6(
@@ -47,7 +47,10 @@ fn merge_refcounted_records_v6(result: &mut Vec<u8>, val: &[u8]) {
 }
 
 fn rocksdb_column_options_v6(col: DBCol) -> Options {
-    let mut opts = rocksdb_column_options(DBCol::ColDbVersion);
+    let mut opts = rocksdb_column_options(
+        DBCol::ColDbVersion,
+        &ColumnTuning::default_for(DBCol::ColDbVersion),
+    );
 
     if col == DBCol::ColState {
         opts.set_merge_operator("refcount merge", refcount_merge_v6, refcount_merge_v6);
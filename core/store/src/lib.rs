@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufReader, Read, Write};
 use std::ops::Deref;
@@ -11,8 +12,10 @@ use lru::LruCache;
 
 pub use db::DBCol::{self, *};
 pub use db::{
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
-    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC, SKIP_COL_GC, TAIL_KEY,
+    ColumnTuning, CHAIN_SCHEMA_VERSION_KEY, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
+    HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY,
+    PARTIALLY_APPLIED_BLOCK_KEY, QUARANTINED_SHARD_KEY, REJECTED_BLOCKS_NEXT_INDEX_KEY,
+    SHOULD_COL_GC, SKIP_COL_GC, SPILLED_STORE_UPDATE_KEY, TAIL_KEY,
 };
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, Account};
@@ -35,7 +38,7 @@ pub use crate::trie::iterator::TrieIterator;
 pub use crate::trie::update::{TrieUpdate, TrieUpdateIterator, TrieUpdateValuePtr};
 pub use crate::trie::{
     split_state, ApplyStatePartResult, KeyForStateChanges, PartialStorage, ShardTries, Trie,
-    TrieChanges, WrappedTrieChanges,
+    TrieChanges, TrieOverlay, WrappedTrieChanges,
 };
 
 pub mod db;
@@ -156,6 +159,18 @@ impl Store {
     pub fn get_store_statistics(&self) -> Option<StoreStatistics> {
         self.storage.get_store_statistics()
     }
+
+    /// Block cache and write buffer sizes the columns were opened with, if the store is backed
+    /// by RocksDB.
+    pub fn get_column_tuning(&self) -> Option<Vec<(DBCol, ColumnTuning)>> {
+        self.storage.get_column_tuning()
+    }
+
+    /// Whether the store was opened read-only, e.g. by a secondary process serving requests from
+    /// the database of a running node. Writes to such a store fail.
+    pub fn is_read_only(&self) -> bool {
+        self.storage.is_read_only()
+    }
 }
 
 /// Keeps track of current changes to the database and can commit all of them to the database.
@@ -208,6 +223,32 @@ impl StoreUpdate {
         self.transaction.delete_all(column);
     }
 
+    /// Total size in bytes of the keys and values of the operations in this update.
+    pub fn size_bytes(&self) -> u64 {
+        self.transaction
+            .ops
+            .iter()
+            .map(|op| match op {
+                DBOp::Insert { key, value, .. } | DBOp::UpdateRefcount { key, value, .. } => {
+                    key.len() + value.len()
+                }
+                DBOp::Delete { key, .. } => key.len(),
+                DBOp::DeleteAll { .. } => 0,
+            } as u64)
+            .sum()
+    }
+
+    /// Whether committing the update also updates the trie caches.
+    pub fn updates_tries(&self) -> bool {
+        self.tries.is_some()
+    }
+
+    /// Consumes the update, returning its operations. They can be merged into another update
+    /// with `merge_transaction`.
+    pub fn into_transaction(self) -> DBTransaction {
+        self.transaction
+    }
+
     /// Merge another store update into this one.
     pub fn merge(&mut self, other: StoreUpdate) {
         if let Some(tries) = other.tries {
@@ -235,20 +276,23 @@ impl StoreUpdate {
         }
     }
 
+    /// Whether this update and `other` write or delete the same key of a column without
+    /// reference counting, so that the update merging both would overwrite itself.
+    pub fn overlaps(&self, other: &StoreUpdate) -> bool {
+        let other_keys =
+            other.transaction.ops.iter().filter_map(non_refcount_key).collect::<HashSet<_>>();
+        self.transaction
+            .ops
+            .iter()
+            .filter_map(non_refcount_key)
+            .any(|key| other_keys.contains(&key))
+    }
+
     pub fn commit(self) -> Result<(), io::Error> {
         debug_assert!(
             {
-                let non_refcount_keys = self
-                    .transaction
-                    .ops
-                    .iter()
-                    .filter_map(|op| match op {
-                        DBOp::Insert { col, key, .. } => Some((*col as u8, key)),
-                        DBOp::Delete { col, key } => Some((*col as u8, key)),
-                        DBOp::UpdateRefcount { .. } => None,
-                        DBOp::DeleteAll { .. } => None,
-                    })
-                    .collect::<Vec<_>>();
+                let non_refcount_keys =
+                    self.transaction.ops.iter().filter_map(non_refcount_key).collect::<Vec<_>>();
                 non_refcount_keys.len()
                     == non_refcount_keys.iter().collect::<std::collections::HashSet<_>>().len()
             },
@@ -266,6 +310,17 @@ impl StoreUpdate {
     }
 }
 
+/// Column and key of an operation replacing the value of the key, `None` for the operations on
+/// reference counted values and on whole columns.
+fn non_refcount_key(op: &DBOp) -> Option<(u8, &[u8])> {
+    match op {
+        DBOp::Insert { col, key, .. } | DBOp::Delete { col, key } => {
+            Some((*col as u8, key.as_slice()))
+        }
+        DBOp::UpdateRefcount { .. } | DBOp::DeleteAll { .. } => None,
+    }
+}
+
 impl fmt::Debug for StoreUpdate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Store Update {{")?;
@@ -312,10 +367,12 @@ pub struct StoreConfig {
     /// Re-export storage layer statistics as prometheus metrics.
     /// Minor performance impact is expected.
     pub enable_statistics: bool,
+    /// Settings of the columns which differ from `ColumnTuning::default_for`.
+    pub column_tuning: HashMap<DBCol, ColumnTuning>,
 }
 
 pub fn create_store_with_config(path: &Path, store_config: StoreConfig) -> Store {
-    let mut opts = RocksDBOptions::default();
+    let mut opts = RocksDBOptions::default().column_tuning(store_config.column_tuning);
     if store_config.enable_statistics {
         opts = opts.enable_statistics();
     }
@@ -12,9 +12,9 @@ use near_primitives::types::{
 };
 
 use crate::db::{DBCol, DBOp, DBTransaction};
-use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
+use crate::trie::trie_storage::{TrieCache, TrieCachingStorage, TrieOverlay};
 use crate::trie::{TrieRefcountChange, POISONED_LOCK_ERR};
-use crate::{StorageError, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
+use crate::{ColState, StorageError, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
 
 struct ShardTriesInner {
     store: Store,
@@ -22,6 +22,8 @@ struct ShardTriesInner {
     caches: RwLock<HashMap<ShardUId, TrieCache>>,
     /// Cache for readers.
     view_caches: RwLock<HashMap<ShardUId, TrieCache>>,
+    /// Given to the tries created while it's set, see `ShardTries::set_overlay`.
+    overlay: RwLock<Option<TrieOverlay>>,
 }
 
 #[derive(Clone)]
@@ -41,6 +43,7 @@ impl ShardTries {
             store,
             caches: RwLock::new(Self::get_new_cache(&shards)),
             view_caches: RwLock::new(Self::get_new_cache(&shards)),
+            overlay: RwLock::new(None),
         }))
     }
 
@@ -62,8 +65,9 @@ impl ShardTries {
             let mut caches = caches_to_use.write().expect(POISONED_LOCK_ERR);
             caches.entry(shard_uid).or_insert_with(TrieCache::new).clone()
         };
-        let store = Box::new(TrieCachingStorage::new(self.0.store.clone(), cache, shard_uid));
-        Trie::new(store, shard_uid)
+        let mut store = TrieCachingStorage::new(self.0.store.clone(), cache, shard_uid);
+        store.overlay = self.0.overlay.read().expect(POISONED_LOCK_ERR).clone();
+        Trie::new(Box::new(store), shard_uid)
     }
 
     pub fn get_trie_for_shard(&self, shard_uid: ShardUId) -> Trie {
@@ -74,10 +78,31 @@ impl ShardTries {
         self.get_trie_for_shard_internal(shard_uid, true)
     }
 
+    /// Makes the tries created from now on read the nodes and values of `overlay` before the
+    /// store, until the overlay is unset. A value is the same wherever it's read from, as it's
+    /// looked up by its hash, so other readers are unaffected.
+    pub fn set_overlay(&self, overlay: Option<TrieOverlay>) {
+        *self.0.overlay.write().expect(POISONED_LOCK_ERR) = overlay;
+    }
+
     pub fn get_store(&self) -> Store {
         self.0.store.clone()
     }
 
+    /// Reads the serialized trie node with the given hash from the store, bypassing the caches.
+    pub fn retrieve_raw_bytes(
+        &self,
+        shard_uid: ShardUId,
+        hash: &CryptoHash,
+    ) -> Result<Vec<u8>, StorageError> {
+        let key = TrieCachingStorage::get_key_from_shard_uid_and_hash(shard_uid, hash);
+        self.0
+            .store
+            .get(ColState, key.as_ref())
+            .map_err(|_| StorageError::StorageInternalError)?
+            .ok_or_else(|| StorageError::StorageInconsistentState("Trie node missing".to_string()))
+    }
+
     pub fn update_cache(&self, transaction: &DBTransaction) -> std::io::Result<()> {
         let mut caches = self.0.caches.write().expect(POISONED_LOCK_ERR);
         let mut shards = HashMap::new();
@@ -279,6 +304,10 @@ impl WrappedTrieChanges {
         &self.state_changes
     }
 
+    pub fn trie_changes(&self) -> &TrieChanges {
+        &self.trie_changes
+    }
+
     pub fn insertions_into(&self, store_update: &mut StoreUpdate) -> Result<(), StorageError> {
         self.tries.apply_insertions(&self.trie_changes, self.shard_uid, store_update)
     }
@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use near_primitives::challenge::PartialState;
 use near_primitives::hash::CryptoHash;
@@ -8,6 +10,7 @@ use tracing::error;
 
 use crate::trie::iterator::TrieTraversalItem;
 use crate::trie::nibble_slice::NibbleSlice;
+use crate::trie::trie_storage::TrieNodeHashRecordingStorage;
 use crate::trie::{
     ApplyStatePartResult, NodeHandle, RawTrieNodeWithSize, TrieNode, TrieNodeWithSize,
 };
@@ -15,6 +18,10 @@ use crate::{PartialStorage, StorageError, Trie, TrieChanges, TrieIterator};
 use near_primitives::contract::ContractCode;
 use near_primitives::state_record::is_contract_code_key;
 
+/// Number of leading bytes of the trie nodes kept to sort the nodes of a state part, see
+/// `Trie::get_trie_node_hashes_for_part`.
+const STATE_PART_NODE_SORT_PREFIX_LEN: usize = 64;
+
 impl Trie {
     /// Computes the set of trie nodes for a state part.
     ///
@@ -39,6 +46,63 @@ impl Trie {
         Ok(trie_nodes)
     }
 
+    /// Same as `get_trie_nodes_for_part`, but returns the hashes and sizes of the trie nodes, in
+    /// the order of the nodes in the part, instead of the nodes. The nodes aren't held in memory,
+    /// so that a large part can be serialized one node at a time, see
+    /// `ShardTries::retrieve_raw_bytes`.
+    pub fn get_trie_node_hashes_for_part(
+        &self,
+        part_id: PartId,
+        state_root: &StateRoot,
+    ) -> Result<Vec<(CryptoHash, usize)>, StorageError> {
+        let storage =
+            self.storage.as_caching_storage().expect("Storage should be TrieCachingStorage");
+        let recorded = Rc::new(RefCell::new(HashSet::new()));
+        let with_recording = Trie {
+            storage: Box::new(TrieNodeHashRecordingStorage {
+                store: storage.store.clone(),
+                shard_uid: storage.shard_uid,
+                recorded: recorded.clone(),
+            }),
+        };
+        with_recording.visit_nodes_for_state_part(state_root, part_id)?;
+
+        // The nodes of a part are sorted by value, see `recorded_storage`. Only a prefix of each
+        // node is kept for sorting, and the nodes with equal prefixes are read again to be
+        // compared as a whole.
+        let mut nodes = vec![];
+        for node_hash in recorded.borrow().iter() {
+            let node = self.storage.retrieve_raw_bytes(node_hash)?;
+            let prefix_len = std::cmp::min(node.len(), STATE_PART_NODE_SORT_PREFIX_LEN);
+            nodes.push((node[..prefix_len].to_vec(), node.len(), *node_hash));
+        }
+        nodes.sort();
+        let mut result = Vec::with_capacity(nodes.len());
+        let mut start = 0;
+        while start < nodes.len() {
+            let end = start
+                + nodes[start..]
+                    .iter()
+                    .take_while(|(prefix, ..)| prefix == &nodes[start].0)
+                    .count();
+            if end - start == 1 {
+                let (_, size, node_hash) = &nodes[start];
+                result.push((*node_hash, *size));
+            } else {
+                let mut group = nodes[start..end]
+                    .iter()
+                    .map(|(_, size, node_hash)| {
+                        Ok((self.storage.retrieve_raw_bytes(node_hash)?, *size, *node_hash))
+                    })
+                    .collect::<Result<Vec<_>, StorageError>>()?;
+                group.sort();
+                result.extend(group.into_iter().map(|(_, size, node_hash)| (node_hash, size)));
+            }
+            start = end;
+        }
+        Ok(result)
+    }
+
     /// Assume we lay out all trie nodes in dfs order visiting children after the parent.
     /// We take all node sizes (memory_usage_direct()) and take all nodes intersecting with
     /// [size_start, size_end) interval, also all nodes necessary to prove it and some
@@ -737,4 +801,33 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_get_trie_node_hashes_for_part() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let tries = create_tries();
+            let shard_uid = ShardUId::single_shard();
+            let trie = tries.get_view_trie_for_shard(shard_uid);
+            let trie_changes = gen_changes(&mut rng, 10);
+            let state_root =
+                test_populate_trie(&tries, &Trie::empty_root(), shard_uid, trie_changes);
+            for _ in 0..10 {
+                let num_parts: u64 = rng.gen_range(1, 10);
+                let part_id = PartId::new(rng.gen_range(0, num_parts), num_parts);
+                let PartialState(trie_nodes) =
+                    trie.get_trie_nodes_for_part(part_id, &state_root).unwrap();
+                let node_hashes = trie.get_trie_node_hashes_for_part(part_id, &state_root).unwrap();
+                let streamed_nodes = node_hashes
+                    .into_iter()
+                    .map(|(node_hash, size)| {
+                        let node = tries.retrieve_raw_bytes(shard_uid, &node_hash).unwrap();
+                        assert_eq!(node.len(), size);
+                        node
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(streamed_nodes, trie_nodes);
+            }
+        }
+    }
 }
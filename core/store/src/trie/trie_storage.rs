@@ -1,12 +1,13 @@
 use std::borrow::Borrow;
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
 use near_primitives::hash::CryptoHash;
 
 use crate::db::refcount::decode_value_with_rc;
-use crate::trie::POISONED_LOCK_ERR;
-use crate::{ColState, StorageError, Store};
+use crate::trie::{TrieRefcountChange, POISONED_LOCK_ERR};
+use crate::{ColState, StorageError, Store, TrieChanges};
 use lru::LruCache;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::types::TrieCacheMode;
@@ -58,6 +59,38 @@ impl TrieCache {
     }
 }
 
+/// Trie nodes and values held in memory on top of the store, so that a state can be computed and
+/// read again without being written, see `ShardTries::set_overlay`.
+#[derive(Clone, Default)]
+pub struct TrieOverlay(Arc<Mutex<HashMap<(ShardUId, CryptoHash), Arc<[u8]>>>>);
+
+impl TrieOverlay {
+    /// Adds the inserted nodes and values of `trie_changes`. Deletions are ignored, the overlay
+    /// only grows.
+    pub fn add_insertions(&self, trie_changes: &TrieChanges, shard_uid: ShardUId) {
+        let mut guard = self.0.lock().expect(POISONED_LOCK_ERR);
+        for TrieRefcountChange { trie_node_or_value_hash, trie_node_or_value, .. } in
+            &trie_changes.insertions
+        {
+            guard
+                .entry((shard_uid, *trie_node_or_value_hash))
+                .or_insert_with(|| trie_node_or_value.as_slice().into());
+        }
+    }
+
+    pub fn get(&self, shard_uid: ShardUId, hash: &CryptoHash) -> Option<Arc<[u8]>> {
+        self.0.lock().expect(POISONED_LOCK_ERR).get(&(shard_uid, *hash)).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().expect(POISONED_LOCK_ERR).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 pub trait TrieStorage {
     /// Get bytes of a serialized TrieNode.
     /// # Errors
@@ -115,6 +148,34 @@ impl TrieStorage for TrieRecordingStorage {
     }
 }
 
+/// Storage which records the hashes of the nodes read, but not the nodes themselves, which are
+/// read from the store again on every access. See `Trie::get_trie_node_hashes_for_part`.
+pub struct TrieNodeHashRecordingStorage {
+    pub(crate) store: Store,
+    pub(crate) shard_uid: ShardUId,
+    pub(crate) recorded: Rc<RefCell<HashSet<CryptoHash>>>,
+}
+
+impl TrieStorage for TrieNodeHashRecordingStorage {
+    fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        let key = TrieCachingStorage::get_key_from_shard_uid_and_hash(self.shard_uid, hash);
+        let val = self
+            .store
+            .get(ColState, key.as_ref())
+            .map_err(|_| StorageError::StorageInternalError)?;
+        if let Some(val) = val {
+            self.recorded.borrow_mut().insert(*hash);
+            Ok(val.into())
+        } else {
+            Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
+        }
+    }
+
+    fn get_touched_nodes_count(&self) -> u64 {
+        unimplemented!();
+    }
+}
+
 /// Storage for validating recorded partial storage.
 /// visited_nodes are to validate that partial storage doesn't contain unnecessary nodes.
 pub struct TrieMemoryPartialStorage {
@@ -174,6 +235,10 @@ pub struct TrieCachingStorage {
 
     /// Counts retrieved trie nodes. Used to compute gas cost for touching trie nodes.
     pub(crate) counter: Cell<u64>,
+
+    /// Looked up before the store. Its nodes are counted like the ones read from the store, so
+    /// that the state is computed the same way on top of it.
+    pub(crate) overlay: Option<TrieOverlay>,
 }
 
 impl TrieCachingStorage {
@@ -185,6 +250,7 @@ impl TrieCachingStorage {
             cache_mode: Cell::new(TrieCacheMode::CachingShard),
             chunk_cache: RefCell::new(Default::default()),
             counter: Cell::new(0u64),
+            overlay: None,
         }
     }
 
@@ -226,10 +292,14 @@ impl TrieStorage for TrieCachingStorage {
             return Ok(val.clone());
         }
 
-        // Try to get value from shard cache containing most recently touched nodes.
+        let overlay_val =
+            self.overlay.as_ref().and_then(|overlay| overlay.get(self.shard_uid, hash));
+
+        // Try to get value from shard cache containing most recently touched nodes, or from the
+        // overlay. Values of the overlay aren't in the store, so they aren't put into the shard cache.
         let mut guard = self.shard_cache.0.lock().expect(POISONED_LOCK_ERR);
-        let val = match guard.get(hash) {
-            Some(val) => val.clone(),
+        let val = match guard.get(hash).cloned().or(overlay_val) {
+            Some(val) => val,
             None => {
                 // If value is not present in cache, get it from the storage.
                 let key = Self::get_key_from_shard_uid_and_hash(self.shard_uid, hash);
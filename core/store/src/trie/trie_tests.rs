@@ -212,7 +212,7 @@ mod caching_storage_tests {
     use crate::test_utils::{create_test_store, create_tries};
     use crate::trie::trie_storage::TRIE_LIMIT_CACHED_VALUE_SIZE;
     use crate::trie::{TrieCache, TrieCachingStorage, TrieRefcountChange};
-    use crate::{Store, TrieChanges};
+    use crate::{ColState, Store, TrieChanges, TrieOverlay};
     use assert_matches::assert_matches;
     use near_primitives::hash::hash;
     use near_primitives::types::TrieCacheMode;
@@ -365,4 +365,38 @@ mod caching_storage_tests {
         assert_eq!(result.unwrap().as_ref(), value);
         assert_eq!(count_before, count_after);
     }
+
+    /// Check that a state can be read from the overlay without being written, and that its values
+    /// are counted but not put into the shard cache.
+    #[test]
+    fn test_overlay() {
+        let tries = create_tries();
+        let shard_uid = ShardUId::single_shard();
+        let changes =
+            vec![(b"a".to_vec(), Some(b"1".to_vec())), (b"b".to_vec(), Some(b"2".to_vec()))];
+        let trie_changes = tries
+            .get_trie_for_shard(shard_uid)
+            .update(&Trie::empty_root(), changes.into_iter())
+            .unwrap();
+        let state_root = trie_changes.new_root;
+        let overlay = TrieOverlay::default();
+        overlay.add_insertions(&trie_changes, shard_uid);
+        assert!(!overlay.is_empty());
+
+        tries.set_overlay(Some(overlay));
+        let trie = tries.get_trie_for_shard(shard_uid);
+        assert_eq!(trie.get(&state_root, b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(trie.get(&state_root, b"b").unwrap(), Some(b"2".to_vec()));
+        let storage = trie.storage.as_caching_storage().unwrap();
+        assert!(storage.get_touched_nodes_count() > 0);
+        assert_eq!(storage.shard_cache.len(), 0);
+        assert!(tries.get_store().iter(ColState).next().is_none());
+
+        tries.set_overlay(None);
+        let trie = tries.get_trie_for_shard(shard_uid);
+        assert_matches!(
+            trie.get(&state_root, b"a"),
+            Err(StorageError::StorageInconsistentState(_))
+        );
+    }
 }
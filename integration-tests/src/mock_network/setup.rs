@@ -286,6 +286,7 @@ pub fn setup_mock_network(
         config.validator_signer.clone(),
         telemetry,
         None,
+        None,
         #[cfg(feature = "test_features")]
         adv.clone(),
     );
@@ -296,6 +297,7 @@ pub fn setup_mock_network(
         client_runtime.clone(),
         network_adapter.clone(),
         config.client_config.clone(),
+        None,
         #[cfg(feature = "test_features")]
         adv.clone(),
     );
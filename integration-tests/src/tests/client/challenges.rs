@@ -3,12 +3,14 @@ use std::sync::Arc;
 
 use borsh::BorshSerialize;
 
+use near_chain::chain::ValidatedChallenge;
 use near_chain::missing_chunks::MissingChunksPool;
-use near_chain::types::BlockEconomicsConfig;
+use near_chain::test_utils::KeyValueRuntime;
+use near_chain::types::{BlockEconomicsConfig, BlockValidationConfig};
 use near_chain::validate::validate_challenge;
 use near_chain::{
     Block, Chain, ChainGenesis, ChainStoreAccess, DoomslugThresholdMode, Error, ErrorKind,
-    Provenance,
+    Provenance, RuntimeAdapter,
 };
 use near_chain_configs::Genesis;
 use near_chunks::ShardsManager;
@@ -19,13 +21,15 @@ use near_logger_utils::init_test_logger;
 use near_network::test_utils::MockPeerManagerAdapter;
 use near_network::types::NetworkRequests;
 use near_primitives::challenge::{
-    BlockDoubleSign, Challenge, ChallengeBody, ChunkProofs, MaybeEncodedShardChunk,
+    BlockDoubleSign, Challenge, ChallengeBody, ChunkProofs, ChunkState, MaybeEncodedShardChunk,
+    SlashedValidator,
 };
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, MerklePath, PartialMerkleTree};
 use near_primitives::num_rational::Rational;
 use near_primitives::receipt::Receipt;
 use near_primitives::serialize::BaseDecode;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::{EncodedShardChunk, ReedSolomonWrapper};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, EpochId, StateRoot};
@@ -243,6 +247,56 @@ fn test_verify_chunk_proofs_challenge_transaction_order() {
     assert_eq!(challenge_result.unwrap(), (*block.hash(), vec!["test0".parse().unwrap()]));
 }
 
+#[test]
+fn test_process_challenges_batch() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.produce_block(0, 1);
+    let (chunk, _merkle_paths, _receipts, block) = create_invalid_proofs_chunk(&mut env.clients[0]);
+    let head_header = env.clients[0].chain.head_header().unwrap().clone();
+    let signer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let non_validator =
+        InMemoryValidatorSigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+
+    // Invalidates `block`.
+    let chunk_proofs = ChallengeBody::ChunkProofs(ChunkProofs {
+        block_header: block.header().try_to_vec().unwrap(),
+        chunk: MaybeEncodedShardChunk::Encoded(chunk),
+        merkle_proof: Block::compute_chunk_headers_root(block.chunks().iter()).1[0].clone(),
+    });
+    // Malicious, slashes the challenger.
+    let double_sign = ChallengeBody::BlockDoubleSign(BlockDoubleSign {
+        left_block_header: head_header.try_to_vec().unwrap(),
+        right_block_header: head_header.try_to_vec().unwrap(),
+    });
+    let challenges = (0..100)
+        .map(|i| match i % 3 {
+            0 => Challenge::produce(chunk_proofs.clone(), &signer),
+            1 => Challenge::produce(double_sign.clone(), &signer),
+            // Invalid, not signed by a validator.
+            _ => Challenge::produce(double_sign.clone(), &non_validator),
+        })
+        .collect();
+
+    let validated_challenges = env.clients[0].chain.process_challenges(challenges).unwrap();
+    assert_eq!(
+        validated_challenges,
+        vec![
+            ValidatedChallenge {
+                challenged_block: Some(*block.hash()),
+                slashed_validators: vec![SlashedValidator::new("test0".parse().unwrap(), false)],
+            },
+            ValidatedChallenge {
+                challenged_block: None,
+                slashed_validators: vec![SlashedValidator::new("test0".parse().unwrap(), false)],
+            },
+        ]
+    );
+    assert!(env.clients[0].chain.mut_store().is_block_challenged(block.hash()).unwrap());
+    assert!(!env.clients[0].chain.mut_store().is_block_challenged(head_header.hash()).unwrap());
+}
+
 fn challenge(
     env: TestEnv,
     shard_id: usize,
@@ -390,6 +444,7 @@ fn test_verify_chunk_invalid_state_challenge() {
             &empty_chunks_pool,
             epoch_length,
             &economics_config,
+            BlockValidationConfig::default(),
             DoomslugThresholdMode::NoApprovals,
             &genesis_block,
             transaction_validity_period,
@@ -449,6 +504,162 @@ fn test_verify_chunk_invalid_state_challenge() {
     }
 }
 
+/// The chunk apply inputs of a block are the ones used by the chunk state challenge against the
+/// chunk of its child.
+#[test]
+fn test_chunk_apply_inputs_match_chunk_state_challenge() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let transaction_validity_period = genesis.config.transaction_validity_period;
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(vec![Arc::new(nearcore::NightshadeRuntime::test(
+            Path::new("../../../.."),
+            create_test_store(),
+            &genesis,
+        ))])
+        .build();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let genesis_block = env.clients[0].chain.genesis_block().clone();
+    env.produce_block(0, 1);
+    env.clients[0].process_tx(
+        SignedTransaction::send_money(
+            1,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            1000,
+            genesis_hash,
+        ),
+        false,
+        false,
+    );
+    for height in 2..7 {
+        env.produce_block(0, height);
+    }
+
+    let client = &mut env.clients[0];
+    let mut has_receipts = false;
+    for height in 2..6 {
+        let prev_block = client.chain.get_block_by_height(height - 1).unwrap().clone();
+        let block = client.chain.get_block_by_height(height).unwrap().clone();
+        let next_block = client.chain.get_block_by_height(height + 1).unwrap().clone();
+        let inputs = client.chain.get_chunk_apply_inputs(block.hash(), 0).unwrap();
+
+        let chunk = client.chain.get_chunk(&block.chunks()[0].chunk_hash()).unwrap().clone();
+        assert_eq!(inputs.chunk, chunk);
+        assert_eq!(inputs.prev_chunk_height_included, prev_block.chunks()[0].height_included());
+        assert_eq!(inputs.gas_price, prev_block.header().gas_price());
+        assert_eq!(
+            &inputs.prev_chunk_extra,
+            client.chain.get_chunk_extra(prev_block.hash(), &ShardUId::single_shard()).unwrap()
+        );
+        assert!(!inputs.is_first_block_with_chunk_of_version);
+        has_receipts |= !inputs.receipts.is_empty();
+
+        let chunk_state = {
+            use near_chain::chain::{ChainUpdate, OrphanBlockPool};
+            let chain = &mut client.chain;
+            let adapter = chain.runtime_adapter.clone();
+            let epoch_length = chain.epoch_length;
+            let empty_block_pool = OrphanBlockPool::new();
+            let empty_chunks_pool = MissingChunksPool::new();
+            let chain_genesis = ChainGenesis::from(&genesis);
+            let economics_config = BlockEconomicsConfig::from(&chain_genesis);
+
+            let mut chain_update = ChainUpdate::new(
+                chain.mut_store(),
+                adapter,
+                &empty_block_pool,
+                &empty_chunks_pool,
+                epoch_length,
+                &economics_config,
+                BlockValidationConfig::default(),
+                DoomslugThresholdMode::NoApprovals,
+                &genesis_block,
+                transaction_validity_period,
+                None,
+            );
+            chain_update
+                .create_chunk_state_challenge(&block, &next_block, &next_block.chunks()[0])
+                .unwrap()
+        };
+        assert_eq!(chunk_state.prev_chunk, inputs.chunk);
+    }
+    // The transfer receipt is delivered to one of the inspected blocks.
+    assert!(has_receipts);
+}
+
+fn create_chunk_state_challenge(
+    chain: &mut Chain,
+    chain_genesis: &ChainGenesis,
+    prev_block: &Block,
+    block: &Block,
+) -> Result<ChunkState, Error> {
+    use near_chain::chain::{ChainUpdate, OrphanBlockPool};
+    let adapter = chain.runtime_adapter.clone();
+    let epoch_length = chain.epoch_length;
+    let genesis_block = chain.genesis_block().clone();
+    let empty_block_pool = OrphanBlockPool::new();
+    let empty_chunks_pool = MissingChunksPool::new();
+    let economics_config = BlockEconomicsConfig::from(chain_genesis);
+
+    let mut chain_update = ChainUpdate::new(
+        chain.mut_store(),
+        adapter,
+        &empty_block_pool,
+        &empty_chunks_pool,
+        epoch_length,
+        &economics_config,
+        BlockValidationConfig::default(),
+        DoomslugThresholdMode::NoApprovals,
+        &genesis_block,
+        chain_genesis.transaction_validity_period,
+        None,
+    );
+    chain_update.create_chunk_state_challenge(prev_block, block, &block.chunks()[0])
+}
+
+#[test]
+fn test_chunk_state_challenge_non_deterministic_apply() {
+    init_test_logger();
+    let chain_genesis = ChainGenesis::test();
+    let runtime = Arc::new(KeyValueRuntime::new_with_validators(
+        create_test_store(),
+        vec![vec!["test0".parse().unwrap()]],
+        1,
+        1,
+        chain_genesis.epoch_length,
+    ));
+    let mut env = TestEnv::builder(chain_genesis.clone())
+        .runtime_adapters(vec![runtime.clone() as Arc<dyn RuntimeAdapter>])
+        .build();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    let chain = &mut env.clients[0].chain;
+    let prev_block = chain.get_block_by_height(2).unwrap().clone();
+    let block = chain.get_block_by_height(3).unwrap().clone();
+    assert_eq!(prev_block.chunks()[0].height_included(), 2);
+    let expected_root =
+        *chain.get_chunk_extra(prev_block.hash(), &ShardUId::single_shard()).unwrap().state_root();
+
+    create_chunk_state_challenge(chain, &chain_genesis, &prev_block, &block).unwrap();
+
+    runtime.set_nondeterministic_apply(true);
+    match create_chunk_state_challenge(chain, &chain_genesis, &prev_block, &block)
+        .unwrap_err()
+        .kind()
+    {
+        ErrorKind::NonDeterministicApply { shard_id, expected_root: root, got_root } => {
+            assert_eq!(shard_id, 0);
+            assert_eq!(root, expected_root);
+            assert_ne!(got_root, expected_root);
+        }
+        kind => panic!("unexpected error {:?}", kind),
+    }
+}
+
 /// Receive invalid state transition in chunk as next chunk producer.
 /// TODO(2445): Enable challenges when they are working correctly.
 #[test]
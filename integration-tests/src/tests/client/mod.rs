@@ -7,3 +7,5 @@ mod sandbox;
 mod sharding_upgrade;
 #[cfg(feature = "test_features")]
 mod shards_manager;
+#[cfg(feature = "test_features")]
+mod state_sync_corruption;
@@ -1,8 +1,10 @@
-use std::collections::{HashSet, VecDeque};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Barrier, RwLock};
+use std::time::Duration;
 
 use actix::System;
 use assert_matches::assert_matches;
@@ -10,17 +12,26 @@ use futures::{future, FutureExt};
 use near_primitives::num_rational::Rational;
 
 use near_actix_test_utils::run_actix;
-use near_chain::chain::{ApplyStatePartsRequest, NUM_EPOCHS_TO_KEEP_STORE_DATA};
-use near_chain::types::LatestKnown;
+use near_chain::chain::{
+    do_apply_chunks, ApplyStatePartsRequest, BlockCatchUpRequest, BlocksCatchUpState,
+    EpochStateDownloadStatus, ReceiptBacklog, ShardStateDownloadStatus, SyncStallCause,
+    NUM_EPOCHS_TO_KEEP_STORE_DATA,
+};
+use near_chain::state_header_cache::StateHeaderCache;
+use near_chain::sync_hash_registry::SyncHashRegistry;
+use near_chain::test_utils::KeyValueRuntime;
+use near_chain::types::{LatestKnown, ReceiptsRootCheckMode};
 use near_chain::validate::validate_chunk_with_chunk_extra;
 use near_chain::{
-    Block, ChainGenesis, ChainStore, ChainStoreAccess, ErrorKind, Provenance, RuntimeAdapter,
+    verify_chain_segment_proof, Block, BlockSaveMode, Chain, ChainGenesis, ChainMisbehavior,
+    ChainStore, ChainStoreAccess, DoomslugThresholdMode, ErrorKind, Provenance, RuntimeAdapter,
+    StateSyncServingStats,
 };
-use near_chain_configs::{ClientConfig, Genesis};
+use near_chain_configs::{ClientConfig, EconomicsOverrides, GcSchedule, Genesis, TxRoutingPolicy};
 use near_chunks::{ChunkStatus, ShardsManager};
 use near_client::test_utils::{
-    create_chunk_on_height, run_catchup, setup_client, setup_mock, setup_mock_all_validators,
-    TestEnv,
+    create_chunk_on_height, create_chunk_on_height_for_shard, run_catchup, setup_client,
+    setup_mock, setup_mock_all_validators, TestEnv,
 };
 use near_client::{Client, GetBlock, GetBlockWithMerkleTree};
 use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
@@ -37,14 +48,14 @@ use near_primitives::epoch_manager::RngSeed;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::errors::TxExecutionError;
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::merkle::verify_hash;
-use near_primitives::receipt::DelayedReceiptIndices;
+use near_primitives::merkle::{verify_hash, verify_path, Direction, MerklePathItem};
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt};
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::{
-    EncodedShardChunk, ReedSolomonWrapper, ShardChunkHeader, ShardChunkHeaderInner,
-    ShardChunkHeaderV3,
+    EncodedShardChunk, PartialEncodedChunk, ReceiptProof, ReedSolomonWrapper, ShardChunkHeader,
+    ShardChunkHeaderInner, ShardChunkHeaderV3, ShardInfo, ShardProof, StateSyncInfo,
 };
 use near_primitives::state_part::PartId;
 use near_primitives::syncing::{get_num_state_parts, ShardStateSyncResponseHeader, StatePartKey};
@@ -53,8 +64,11 @@ use near_primitives::transaction::{
     Transaction,
 };
 use near_primitives::trie_key::TrieKey;
+use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::types::{AccountId, BlockHeight, EpochId, NumBlocks, ProtocolVersion};
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockHeightDelta, EpochId, NumBlocks, ProtocolVersion, ShardId,
+};
 use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::ProtocolFeature;
@@ -62,9 +76,11 @@ use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
     BlockHeaderView, FinalExecutionStatus, QueryRequest, QueryResponseKind,
 };
-use near_store::db::DBCol::ColStateParts;
-use near_store::get;
+use near_store::db::DBCol::{
+    ColBlockMisc, ColEpochLightClientBlocks, ColStateParts, ColTransactionResult, ColTransactions,
+};
 use near_store::test_utils::create_test_store;
+use near_store::{get, SPILLED_STORE_UPDATE_KEY};
 use nearcore::config::{GenesisExt, TESTING_INIT_BALANCE, TESTING_INIT_STAKE};
 use nearcore::{TrackedConfig, NEAR_BASE};
 use rand::prelude::StdRng;
@@ -1052,6 +1068,66 @@ fn test_process_invalid_tx() {
     );
 }
 
+#[test]
+fn test_tx_routing_policy() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.produce_block(0, 1);
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let mut nonce = 0;
+    let mut send_money = |signer_id: &str| {
+        nonce += 1;
+        let signer =
+            InMemorySigner::from_seed(signer_id.parse().unwrap(), KeyType::ED25519, signer_id);
+        SignedTransaction::send_money(
+            nonce,
+            signer_id.parse().unwrap(),
+            "test0".parse().unwrap(),
+            &signer,
+            1,
+            genesis_hash,
+        )
+    };
+    let mut process_tx = |env: &mut TestEnv, signer_id: &str| {
+        env.clients[0].process_tx(send_money(signer_id), false, false)
+    };
+    let rejected = |signer_id: &str| NetworkClientResponses::TxRejectedByRoutingPolicy {
+        signer_id: signer_id.parse().unwrap(),
+    };
+
+    assert_eq!(process_tx(&mut env, "test0"), NetworkClientResponses::ValidTx);
+    assert_eq!(process_tx(&mut env, "test1"), NetworkClientResponses::ValidTx);
+
+    env.clients[0].config.tx_routing_policy =
+        Some(TxRoutingPolicy::DenyList(vec!["test1".parse().unwrap()].into_iter().collect()));
+    assert_eq!(process_tx(&mut env, "test0"), NetworkClientResponses::ValidTx);
+    assert_eq!(process_tx(&mut env, "test1"), rejected("test1"));
+
+    env.clients[0].config.tx_routing_policy =
+        Some(TxRoutingPolicy::AllowList(vec!["test1".parse().unwrap()].into_iter().collect()));
+    assert_eq!(process_tx(&mut env, "test0"), rejected("test0"));
+    assert_eq!(process_tx(&mut env, "test1"), NetworkClientResponses::ValidTx);
+    // Rejected transactions are not forwarded anywhere.
+    while let Some(request) = env.network_adapters[0].pop() {
+        assert!(!matches!(
+            request.as_network_requests(),
+            NetworkRequests::ForwardTx(_, tx) if tx.transaction.signer_id.as_ref() == "test0"
+        ));
+    }
+    // The policy only restricts transactions submitted to this node directly.
+    assert_eq!(
+        env.clients[0].process_tx(send_money("test0"), false, true),
+        NetworkClientResponses::ValidTx
+    );
+    assert_eq!(
+        env.clients[0].process_tx(send_money("test0"), true, false),
+        NetworkClientResponses::ValidTx
+    );
+}
+
 /// If someone produce a block with Utc::now() + 1 min, we should produce a block with valid timestamp
 #[test]
 fn test_time_attack() {
@@ -1201,6 +1277,28 @@ fn test_invalid_height_too_old() {
     assert!(matches!(res.unwrap_err().kind(), ErrorKind::InvalidBlockHeight(_)));
 }
 
+/// A chunk included two heights after the height it was produced for is recorded with its delay
+/// and counted for its chunk producer.
+#[test]
+fn test_chunk_inclusion_delay() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.produce_block(0, 1);
+    // The blocks at heights 2 and 3 are skipped, so the chunk produced for height 2 is included
+    // at height 4.
+    let block = env.clients[0].produce_block(4).unwrap().unwrap();
+    assert_eq!(block.chunks()[0].height_created(), 2);
+    assert_eq!(block.chunks()[0].height_included(), 4);
+    env.process_block(0, block, Provenance::PRODUCED);
+    env.produce_block(0, 5);
+
+    let stats = env.clients[0].chain.chunk_inclusion_delay_stats();
+    let delays = &stats.delays_per_shard[&0];
+    assert_eq!(delays.range(1..).collect::<Vec<_>>(), vec![(&2, &1)]);
+    assert!(delays[&0] >= 1);
+    assert_eq!(stats.delayed_chunks_per_producer, HashMap::from([("test0".parse().unwrap(), 1)]));
+}
+
 #[test]
 fn test_bad_orphan() {
     let mut genesis = ChainGenesis::test();
@@ -1376,6 +1474,97 @@ fn test_bad_chunk_mask() {
     }
 }
 
+/// Incoming receipts of a shard pile up while it misses chunks, and are consumed by its next chunk.
+#[test]
+fn test_receipt_backlog() {
+    init_test_logger();
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = 100;
+    let validators = vec!["test0".parse().unwrap(), "test1".parse().unwrap()];
+    let mut clients: Vec<Client> = validators
+        .iter()
+        .map(|account_id| {
+            setup_client(
+                create_test_store(),
+                vec![validators.clone()],
+                1,
+                2,
+                Some(account_id.clone()),
+                false,
+                Arc::new(MockPeerManagerAdapter::default()),
+                chain_genesis.clone(),
+                TEST_SEED,
+            )
+        })
+        .collect();
+    // Shard 1 misses the chunks at heights 2, 3 and 4.
+    let mut backlogs = vec![];
+    for height in 1..=5 {
+        let shard_ids: &[ShardId] = if height == 1 || height == 5 { &[0, 1] } else { &[0] };
+        let mut chunk_headers = vec![];
+        for &shard_id in shard_ids {
+            let chunk_producer = ((shard_id + height + 1) % 2) as usize;
+            let (encoded_chunk, merkle_paths, receipts) =
+                create_chunk_on_height_for_shard(&mut clients[chunk_producer], height, shard_id);
+            for client in clients.iter_mut() {
+                let mut chain_store =
+                    ChainStore::new(client.chain.store().store().clone(), chain_genesis.height);
+                client
+                    .shards_mgr
+                    .distribute_encoded_chunk(
+                        encoded_chunk.clone(),
+                        merkle_paths.clone(),
+                        receipts.clone(),
+                        &mut chain_store,
+                    )
+                    .unwrap();
+            }
+            let mut chunk_header = encoded_chunk.cloned_header();
+            *chunk_header.height_included_mut() = height;
+            chunk_headers.push(chunk_header);
+        }
+
+        let block_producer = (height % 2) as usize;
+        let mut block = clients[block_producer].produce_block(height).unwrap().unwrap();
+        let mut block_chunks: Vec<_> = block.chunks().iter().cloned().collect();
+        for chunk_header in chunk_headers {
+            let shard_id = chunk_header.shard_id() as usize;
+            block_chunks[shard_id] = chunk_header;
+        }
+        block.set_chunks(block_chunks.clone());
+        block.mut_header().get_mut().inner_rest.chunk_headers_root =
+            Block::compute_chunk_headers_root(&block_chunks).0;
+        block.mut_header().get_mut().inner_rest.chunk_tx_root =
+            Block::compute_chunk_tx_root(&block_chunks);
+        block.mut_header().get_mut().inner_rest.chunk_receipts_root =
+            Block::compute_chunk_receipts_root(&block_chunks);
+        block.mut_header().get_mut().inner_lite.prev_state_root =
+            Block::compute_state_root(&block_chunks);
+        block.mut_header().get_mut().inner_rest.chunk_mask =
+            block_chunks.iter().map(|chunk| chunk.height_included() == height).collect();
+        block
+            .mut_header()
+            .resign(&*clients[block_producer].validator_signer.as_ref().unwrap().clone());
+        for client in clients.iter_mut() {
+            let (_, res) = client.process_block(block.clone().into(), Provenance::NONE);
+            res.unwrap();
+        }
+        let backlog = clients[0].chain.receipt_backlog_per_shard();
+        assert_eq!(backlog[&0], ReceiptBacklog::default());
+        backlogs.push(backlog[&1]);
+    }
+
+    // Every chunk of shard 0 carries a receipt proof for shard 1.
+    assert_eq!(
+        backlogs.iter().map(|backlog| backlog.num_receipt_proofs).collect::<Vec<_>>(),
+        vec![0, 1, 2, 3, 0]
+    );
+    assert!(backlogs[1].size_bytes > 0);
+    assert!(backlogs[2].size_bytes > backlogs[1].size_bytes);
+    assert!(backlogs[3].size_bytes > backlogs[2].size_bytes);
+    assert_eq!(backlogs[4], ReceiptBacklog::default());
+}
+
 #[test]
 fn test_minimum_gas_price() {
     let min_gas_price = 100;
@@ -1390,6 +1579,146 @@ fn test_minimum_gas_price() {
     assert!(block.header().gas_price() >= min_gas_price);
 }
 
+/// Blocks from the activation height on are produced and validated with the overridden gas price
+/// bounds, and nodes without the overrides reject them.
+#[test]
+fn test_economics_overrides() {
+    init_test_logger();
+    let activation_height = 6;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.min_gas_price = 100;
+    chain_genesis.gas_price_adjustment_rate = Rational::new(1, 10);
+    let overrides = EconomicsOverrides {
+        activation_height,
+        min_gas_price: 1_000,
+        max_gas_price: 10_000,
+        gas_price_adjustment_rate: Rational::new(1, 10),
+    };
+    let mut env = TestEnv::builder(chain_genesis).clients_count(2).build();
+    assert_eq!(
+        env.clients[1]
+            .chain
+            .set_economics_overrides("mainnet", overrides.clone())
+            .unwrap_err()
+            .kind(),
+        ErrorKind::EconomicsOverridesNotAllowed("mainnet".to_string())
+    );
+    env.clients[0].chain.set_economics_overrides("unittest", overrides.clone()).unwrap();
+
+    for height in 1..activation_height {
+        let block = produce_block_for_two_clients(&mut env, height);
+        assert!(block.header().gas_price() < overrides.min_gas_price);
+    }
+
+    let block = env.clients[0].produce_block(activation_height).unwrap().unwrap();
+    assert_eq!(block.header().gas_price(), overrides.min_gas_price);
+    env.process_block(0, block.clone(), Provenance::PRODUCED);
+    let (_, res) = env.clients[1].process_block(block.clone().into(), Provenance::NONE);
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidGasPrice);
+
+    env.clients[1].chain.set_economics_overrides("unittest", overrides.clone()).unwrap();
+    let (_, res) = env.clients[1].process_block(block.clone().into(), Provenance::NONE);
+    if let Err(e) = res {
+        assert_matches!(e.kind(), ErrorKind::ChunksMissing(_));
+        env.process_partial_encoded_chunks_requests(1);
+    }
+    assert_eq!(&env.clients[1].chain.head().unwrap().last_block_hash, block.hash());
+
+    for height in activation_height + 1..=2 * activation_height {
+        let block = produce_block_for_two_clients(&mut env, height);
+        assert!(block.header().gas_price() >= overrides.min_gas_price);
+        assert!(block.header().gas_price() <= overrides.max_gas_price);
+    }
+    let parameters = env.clients[1].chain.chain_parameters().unwrap();
+    assert_eq!(parameters.min_gas_price, overrides.min_gas_price);
+}
+
+/// A block whose chunk carries receipts not covered by the chunk's outgoing receipts root is
+/// rejected when incoming receipts are verified, and accepted otherwise.
+#[test]
+fn test_verify_incoming_receipts() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    assert!(env.clients[0].chain.verify_incoming_receipts);
+    env.produce_block(0, 1);
+    let block = env.clients[0].produce_block(2).unwrap().unwrap();
+
+    let chunk_hash = block.chunks()[0].chunk_hash();
+    let partial_chunk =
+        env.clients[0].chain.mut_store().get_partial_chunk(&chunk_hash).unwrap().clone();
+    let receipt = Receipt::new_balance_refund(&"test0".parse().unwrap(), 1);
+    let receipt_proof =
+        ReceiptProof(vec![receipt], ShardProof { from_shard_id: 0, to_shard_id: 0, proof: vec![] });
+    let corrupted_chunk = PartialEncodedChunk::new(
+        partial_chunk.cloned_header(),
+        partial_chunk.parts().clone(),
+        vec![receipt_proof],
+    );
+    let mut store_update = env.clients[0].chain.mut_store().store_update();
+    store_update.save_partial_chunk(corrupted_chunk);
+    store_update.commit().unwrap();
+
+    let (_, res) = env.clients[0].process_block(block.clone().into(), Provenance::PRODUCED);
+    assert_eq!(
+        res.unwrap_err().kind(),
+        ErrorKind::InvalidIncomingReceiptProof { from_shard_id: 0, to_shard_id: 0 }
+    );
+    assert_eq!(env.clients[0].chain.head().unwrap().height, 1);
+
+    env.clients[0].chain.verify_incoming_receipts = false;
+    let (_, res) = env.clients[0].process_block(block.clone().into(), Provenance::PRODUCED);
+    res.unwrap();
+    assert_eq!(&env.clients[0].chain.head().unwrap().last_block_hash, block.hash());
+}
+
+/// Parts of stored partial chunks are served with proofs against the encoded merkle root of their
+/// chunk, and the garbage collection of archival nodes keeps them for the configured number of
+/// latest epochs.
+#[test]
+fn test_retain_partial_chunk_parts() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let num_blocks = epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 3);
+    // Returns the lowest height whose chunk part is still available after producing the blocks.
+    let oldest_available_part = |retain_partial_chunk_parts_epochs: u64| {
+        let mut env = TestEnv::builder(chain_genesis.clone()).build();
+        env.clients[0].config.archive = true;
+        env.clients[0].chain.retain_partial_chunk_parts_epochs = retain_partial_chunk_parts_epochs;
+        for height in 1..=num_blocks {
+            env.produce_block(0, height);
+        }
+        let mut oldest = None;
+        for height in (1..=num_blocks).rev() {
+            let chain = &mut env.clients[0].chain;
+            let chunk_hash = chain.get_block_by_height(height).unwrap().chunks()[0].chunk_hash();
+            let part = match chain.get_partial_chunk_part(&chunk_hash, 0) {
+                Ok(part) => part,
+                Err(err) => {
+                    assert_eq!(err.kind(), ErrorKind::ChunkMissing(chunk_hash));
+                    break;
+                }
+            };
+            assert_eq!(part.header.chunk_hash, chunk_hash.0);
+            assert_eq!(part.header.height_created, height);
+            assert!(verify_path(part.header.encoded_merkle_root, &part.merkle_proof, &part.part));
+            let num_total_parts = chain.runtime_adapter.num_total_parts() as u64;
+            assert_matches!(
+                chain.get_partial_chunk_part(&chunk_hash, num_total_parts).unwrap_err().kind(),
+                ErrorKind::DBNotFoundErr(_)
+            );
+            oldest = Some(height);
+        }
+        oldest.unwrap()
+    };
+
+    // By default the partial chunks are collected like the rest of the redundant chunk data.
+    assert_eq!(oldest_available_part(0), num_blocks - NUM_EPOCHS_TO_KEEP_STORE_DATA * epoch_length);
+    let retain_epochs = NUM_EPOCHS_TO_KEEP_STORE_DATA + 2;
+    assert_eq!(oldest_available_part(retain_epochs), num_blocks - retain_epochs * epoch_length);
+}
+
 fn test_gc_with_epoch_length_common(epoch_length: NumBlocks) {
     let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
     genesis.config.epoch_length = epoch_length;
@@ -1448,6 +1777,78 @@ fn test_gc_with_epoch_length() {
     }
 }
 
+#[test]
+fn test_gc_every_n_blocks() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.clients[0].config.gc_schedule = GcSchedule::EveryNBlocks(10);
+    let num_blocks = 60;
+    let mut prev_tail = env.clients[0].chain.store().tail().unwrap();
+    for i in 1..=num_blocks {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block, Provenance::PRODUCED);
+        let tail = env.clients[0].chain.store().tail().unwrap();
+        let fork_tail = env.clients[0].chain.store().fork_tail().unwrap();
+        assert!(tail >= prev_tail);
+        if i % 10 != 0 {
+            // GC only runs on every tenth head update.
+            assert_eq!(tail, prev_tail);
+        }
+        assert!(fork_tail <= tail);
+        prev_tail = tail;
+    }
+
+    // GC ran on the last head update and caught up to the GC stop height.
+    let head = env.clients[0].chain.head().unwrap();
+    let gc_stop_height = env.clients[0].runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+    assert!(gc_stop_height > epoch_length);
+    assert_eq!(env.clients[0].chain.store().tail().unwrap(), gc_stop_height - 1);
+    for i in 1..gc_stop_height {
+        assert!(env.clients[0].chain.get_block_by_height(i).is_err());
+    }
+    for i in gc_stop_height..=num_blocks {
+        assert!(env.clients[0].chain.get_block_by_height(i).is_ok());
+    }
+}
+
+/// A node which is never idle still collects garbage once it was deferred for long enough.
+#[test]
+fn test_gc_when_idle_under_load() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.clients[0].config.gc_schedule =
+        GcSchedule::WhenIdle { min_idle_ms: 3_600_000, max_deferred_blocks: 9 };
+    let num_blocks = 60;
+    let mut prev_tail = env.clients[0].chain.store().tail().unwrap();
+    for i in 1..=num_blocks {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block, Provenance::PRODUCED);
+        let tail = env.clients[0].chain.store().tail().unwrap();
+        if i % 10 != 0 {
+            // GC is forced on every tenth head update.
+            assert_eq!(tail, prev_tail);
+        }
+        prev_tail = tail;
+    }
+
+    let head = env.clients[0].chain.head().unwrap();
+    let gc_stop_height = env.clients[0].runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+    assert!(gc_stop_height > epoch_length);
+    assert_eq!(env.clients[0].chain.store().tail().unwrap(), gc_stop_height - 1);
+}
+
 /// When an epoch is very long there should not be anything garbage collected unexpectedly
 #[test]
 fn test_gc_long_epoch() {
@@ -1479,6 +1880,73 @@ fn test_gc_block_skips() {
     }
 }
 
+#[test]
+fn test_garbage_collect_to_height() {
+    let epoch_length = 50;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..=300 {
+        env.produce_block(0, i);
+    }
+    let regular_tail = env.clients[0].chain.tail().unwrap();
+    let target_tail = 150;
+    assert!(regular_tail < target_tail);
+    let tries = env.clients[0].runtime_adapter.get_tries();
+
+    // Pruning has to be acknowledged in the config.
+    let err = env.clients[0]
+        .chain
+        .garbage_collect_to_height(target_tail, tries.clone(), 100)
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::GCError(_)));
+    env.clients[0].chain.allow_aggressive_gc = true;
+
+    // The data needed to process new blocks is kept.
+    let max_tail = env.clients[0].chain.max_aggressive_gc_tail().unwrap();
+    assert!(max_tail >= target_tail);
+    assert!(max_tail < 300 - epoch_length);
+    let err = env.clients[0]
+        .chain
+        .garbage_collect_to_height(max_tail + 1, tries.clone(), 100)
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::GCError(_)));
+    assert_eq!(env.clients[0].chain.tail().unwrap(), regular_tail);
+
+    // The block budget bounds each call.
+    let report =
+        env.clients[0].chain.garbage_collect_to_height(target_tail, tries.clone(), 10).unwrap();
+    assert_eq!(report.blocks_removed, 10);
+    assert!(!report.reached_target);
+    assert_eq!(report.tail, regular_tail + 10);
+    let report =
+        env.clients[0].chain.garbage_collect_to_height(target_tail, tries.clone(), 1000).unwrap();
+    assert_eq!(report.blocks_removed, target_tail - regular_tail - 10);
+    assert!(report.reached_target);
+    assert_eq!(report.tail, target_tail);
+    for i in 1..=target_tail {
+        assert!(env.clients[0].chain.get_block_by_height(i).is_err());
+    }
+    for i in target_tail + 1..=300 {
+        assert!(env.clients[0].chain.get_block_by_height(i).is_ok());
+    }
+
+    // Blocks keep being processed, and the regular garbage collection resumes once its stop
+    // height passes the new tail.
+    for i in 301..=500 {
+        env.produce_block(0, i);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(head.height, 500);
+    let gc_stop_height = env.clients[0].runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+    assert!(gc_stop_height > target_tail + 1);
+    assert_eq!(env.clients[0].chain.tail().unwrap(), gc_stop_height - 1);
+}
+
 #[test]
 fn test_gc_chunk_tail() {
     let mut chain_genesis = ChainGenesis::test();
@@ -1528,62 +1996,230 @@ fn test_gc_execution_outcome() {
     assert!(env.clients[0].chain.get_final_transaction_result(&tx_hash).is_err());
 }
 
+/// The outcomes of a transaction are still returned without the transaction once it is gone.
 #[test]
-#[cfg_attr(not(feature = "expensive_tests"), ignore)]
-fn test_gc_after_state_sync() {
-    let epoch_length = 1024;
+fn test_final_transaction_result_without_transaction() {
+    let epoch_length = 5;
     let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
     genesis.config.epoch_length = epoch_length;
     let mut chain_genesis = ChainGenesis::test();
     chain_genesis.epoch_length = epoch_length;
     let mut env = TestEnv::builder(chain_genesis)
-        .clients_count(2)
-        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
         .build();
-    for i in 1..epoch_length * 4 + 2 {
-        let block = env.clients[0].produce_block(i).unwrap().unwrap();
-        env.process_block(0, block.clone(), Provenance::PRODUCED);
-        env.process_block(1, block, Provenance::NONE);
-    }
-    let sync_height = epoch_length * 4 + 1;
-    let sync_block = env.clients[0].chain.get_block_by_height(sync_height).unwrap().clone();
-    let sync_hash = *sync_block.hash();
-    let prev_block_hash = *sync_block.header().prev_hash();
-    // reset cache
-    for i in epoch_length * 3 - 1..sync_height - 1 {
-        let block_hash = *env.clients[0].chain.get_block_by_height(i).unwrap().hash();
-        assert!(env.clients[1].chain.runtime_adapter.get_epoch_start_height(&block_hash).is_ok());
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    let tx_hash = tx.get_hash();
+
+    env.clients[0].process_tx(tx, false, false);
+    for i in 1..epoch_length {
+        env.produce_block(0, i);
     }
-    env.clients[1].chain.reset_data_pre_state_sync(sync_hash).unwrap();
-    assert_eq!(env.clients[1].runtime_adapter.get_gc_stop_height(&sync_hash), 0);
-    // mimic what we do in possible_targets
-    assert!(env.clients[1].runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash).is_ok());
-    let tries = env.clients[1].runtime_adapter.get_tries();
-    assert!(env.clients[1].chain.clear_data(tries, 2).is_ok());
+    // Drop the transaction before it is read, so that it isn't cached by the chain store.
+    let mut store_update = env.clients[0].chain.store().store().store_update();
+    store_update.update_refcount(ColTransactions, tx_hash.as_ref(), &[], -1);
+    store_update.commit().unwrap();
+
+    let chain = &mut env.clients[0].chain;
+    assert_matches!(
+        chain.get_final_transaction_result(&tx_hash).unwrap_err().kind(),
+        ErrorKind::DBNotFoundErr(_)
+    );
+    let lite_outcome = chain.get_final_transaction_result_lite(&tx_hash).unwrap();
+    assert_eq!(lite_outcome.transaction_hash, tx_hash);
+    assert_matches!(lite_outcome.status, FinalExecutionStatus::SuccessValue(_));
+    assert_eq!(lite_outcome.transaction_outcome.id, tx_hash);
+    assert!(!lite_outcome.receipts_outcome.is_empty());
 }
 
+/// Once the outcome of a receipt of a transaction is garbage collected, the remaining outcomes
+/// are returned if partial results are allowed.
 #[test]
-#[cfg_attr(not(feature = "expensive_tests"), ignore)]
-fn test_process_block_after_state_sync() {
-    let epoch_length = 1024;
-    // test with shard_version > 0
-    let mut genesis = Genesis::test_sharded_new_version(
-        vec!["test0".parse().unwrap(), "test1".parse().unwrap()],
-        1,
-        vec![1],
-    );
+fn test_final_transaction_result_with_missing_receipt_outcome() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
     genesis.config.epoch_length = epoch_length;
     let mut chain_genesis = ChainGenesis::test();
     chain_genesis.epoch_length = epoch_length;
     let mut env = TestEnv::builder(chain_genesis)
         .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
         .build();
-    for i in 1..epoch_length * 4 + 2 {
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    let tx_hash = tx.get_hash();
+
+    env.clients[0].process_tx(tx, false, false);
+    for i in 1..epoch_length {
         env.produce_block(0, i);
     }
-    let sync_height = epoch_length * 4 + 1;
-    let sync_block = env.clients[0].chain.get_block_by_height(sync_height).unwrap().clone();
-    let sync_hash = *sync_block.hash();
+    let chain = &mut env.clients[0].chain;
+    let final_outcome = chain.get_final_transaction_result(&tx_hash).unwrap();
+    assert_matches!(final_outcome.status, FinalExecutionStatus::SuccessValue(_));
+    let full_outcome = chain.get_final_transaction_result_opts(&tx_hash, true).unwrap();
+    assert_eq!(full_outcome.final_outcome, final_outcome);
+    assert!(full_outcome.missing_receipt_ids.is_empty());
+
+    // Delete the outcome of the receipt the transaction was converted to, like garbage
+    // collection does.
+    let receipt_id = final_outcome.transaction_outcome.outcome.receipt_ids[0];
+    let mut store_update = chain.store().store().store_update();
+    store_update.delete(ColTransactionResult, receipt_id.as_ref());
+    store_update.commit().unwrap();
+
+    assert_matches!(
+        chain.get_final_transaction_result(&tx_hash).unwrap_err().kind(),
+        ErrorKind::DBNotFoundErr(_)
+    );
+    let partial_outcome = chain.get_final_transaction_result_opts(&tx_hash, true).unwrap();
+    assert_eq!(partial_outcome.final_outcome.status, FinalExecutionStatus::Started);
+    assert_eq!(partial_outcome.final_outcome.transaction, final_outcome.transaction);
+    assert_eq!(
+        partial_outcome.final_outcome.transaction_outcome,
+        final_outcome.transaction_outcome
+    );
+    // The receipts produced by the receipt can't be found without its outcome.
+    assert!(partial_outcome.final_outcome.receipts_outcome.is_empty());
+    assert_eq!(partial_outcome.missing_receipt_ids, vec![receipt_id]);
+}
+
+/// Garbage collecting the outcomes of the block which executed a receipt produced by another
+/// receipt of the transaction keeps the outcomes of the earlier receipts in the partial result.
+#[test]
+fn test_final_transaction_result_with_garbage_collected_second_level_receipt() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let height = deploy_test_contract(
+        &mut env,
+        "test0".parse().unwrap(),
+        near_test_contracts::rs_contract(),
+        epoch_length,
+        1,
+    );
+    let last_block_hash = *env.clients[0].chain.get_block_by_height(height - 1).unwrap().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::from_actions(
+        height,
+        "test0".parse().unwrap(),
+        "test0".parse().unwrap(),
+        &signer,
+        vec![Action::FunctionCall(FunctionCallAction {
+            method_name: "log_something".to_string(),
+            args: vec![],
+            gas: 100_000_000_000_000,
+            deposit: 0,
+        })],
+        last_block_hash,
+    );
+    let tx_hash = tx.get_hash();
+    env.clients[0].process_tx(tx, false, false);
+    produce_blocks_from_height(&mut env, epoch_length, height);
+
+    let chain = &mut env.clients[0].chain;
+    let final_outcome = chain.get_final_transaction_result(&tx_hash).unwrap();
+    assert_matches!(final_outcome.status, FinalExecutionStatus::SuccessValue(_));
+    // The function call is executed with the transaction and refunds the unused gas in a later
+    // block.
+    let function_call_outcome = final_outcome.receipts_outcome[0].clone();
+    let refund_id = function_call_outcome.outcome.receipt_ids[0];
+    let refund_outcome =
+        final_outcome.receipts_outcome.iter().find(|outcome| outcome.id == refund_id).unwrap();
+    assert_ne!(refund_outcome.block_hash, function_call_outcome.block_hash);
+
+    let refund_block = chain.get_block(&refund_outcome.block_hash).unwrap().clone();
+    let mut chain_store_update = chain.mut_store().store_update();
+    chain_store_update.gc_outcomes(&refund_block).unwrap();
+    chain_store_update.commit().unwrap();
+
+    assert_matches!(
+        chain.get_final_transaction_result(&tx_hash).unwrap_err().kind(),
+        ErrorKind::DBNotFoundErr(_)
+    );
+    let partial_outcome = chain.get_final_transaction_result_opts(&tx_hash, true).unwrap();
+    assert_eq!(partial_outcome.final_outcome.status, FinalExecutionStatus::Started);
+    assert_eq!(
+        partial_outcome.final_outcome.transaction_outcome,
+        final_outcome.transaction_outcome
+    );
+    assert_eq!(partial_outcome.final_outcome.receipts_outcome, vec![function_call_outcome]);
+    assert_eq!(partial_outcome.missing_receipt_ids, vec![refund_id]);
+}
+
+#[test]
+#[cfg_attr(not(feature = "expensive_tests"), ignore)]
+fn test_gc_after_state_sync() {
+    let epoch_length = 1024;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    for i in 1..epoch_length * 4 + 2 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let sync_height = epoch_length * 4 + 1;
+    let sync_block = env.clients[0].chain.get_block_by_height(sync_height).unwrap().clone();
+    let sync_hash = *sync_block.hash();
+    let prev_block_hash = *sync_block.header().prev_hash();
+    // reset cache
+    for i in epoch_length * 3 - 1..sync_height - 1 {
+        let block_hash = *env.clients[0].chain.get_block_by_height(i).unwrap().hash();
+        assert!(env.clients[1].chain.runtime_adapter.get_epoch_start_height(&block_hash).is_ok());
+    }
+    env.clients[1].chain.reset_data_pre_state_sync(sync_hash).unwrap();
+    assert_eq!(env.clients[1].runtime_adapter.get_gc_stop_height(&sync_hash), 0);
+    // mimic what we do in possible_targets
+    assert!(env.clients[1].runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash).is_ok());
+    let tries = env.clients[1].runtime_adapter.get_tries();
+    assert!(env.clients[1].chain.clear_data(tries, 2).is_ok());
+}
+
+#[test]
+#[cfg_attr(not(feature = "expensive_tests"), ignore)]
+fn test_process_block_after_state_sync() {
+    let epoch_length = 1024;
+    // test with shard_version > 0
+    let mut genesis = Genesis::test_sharded_new_version(
+        vec!["test0".parse().unwrap(), "test1".parse().unwrap()],
+        1,
+        vec![1],
+    );
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..epoch_length * 4 + 2 {
+        env.produce_block(0, i);
+    }
+    let sync_height = epoch_length * 4 + 1;
+    let sync_block = env.clients[0].chain.get_block_by_height(sync_height).unwrap().clone();
+    let sync_hash = *sync_block.hash();
     let chunk_extra = env.clients[0]
         .chain
         .get_chunk_extra(&sync_hash, &ShardUId { version: 1, shard_id: 0 })
@@ -1752,10 +2388,13 @@ fn test_not_resync_old_blocks() {
     }
 }
 
-#[test]
-fn test_gc_tail_update() {
+/// Sets up two clients with the given epoch length, produces `num_blocks` blocks on the first
+/// one and syncs their headers to the second one, which is then ready for state sync.
+fn prepare_env_with_synced_headers(
+    epoch_length: BlockHeightDelta,
+    num_blocks: NumBlocks,
+) -> (TestEnv, Vec<Block>) {
     let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
-    let epoch_length = 2;
     genesis.config.epoch_length = epoch_length;
     let mut chain_genesis = ChainGenesis::test();
     chain_genesis.epoch_length = epoch_length;
@@ -1764,22 +2403,27 @@ fn test_gc_tail_update() {
         .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
         .build();
     let mut blocks = vec![];
-    for i in 1..=epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 1) {
+    for i in 1..=num_blocks {
         let block = env.clients[0].produce_block(i).unwrap().unwrap();
         env.process_block(0, block.clone(), Provenance::PRODUCED);
         blocks.push(block);
     }
     let headers = blocks.iter().map(|b| b.header().clone()).collect::<Vec<_>>();
     env.clients[1].sync_block_headers(headers).unwrap();
-    // simulate save sync hash block
-    let prev_sync_block = blocks[blocks.len() - 3].clone();
-    let prev_sync_hash = *prev_sync_block.hash();
-    let prev_sync_height = prev_sync_block.header().height();
-    let sync_block = blocks[blocks.len() - 2].clone();
+    (env, blocks)
+}
+
+/// Simulates the second client finishing state sync at `sync_block` the way the client actor
+/// does: the chain data is reset, the sync block and the block before it are saved and the heads
+/// are moved to them.
+fn simulate_state_sync_blocks(env: &mut TestEnv, prev_sync_block: &Block, sync_block: &Block) {
     env.clients[1].chain.reset_data_pre_state_sync(*sync_block.hash()).unwrap();
-    env.clients[1].chain.save_block(prev_sync_block.into()).unwrap();
+    env.clients[1]
+        .chain
+        .save_block(prev_sync_block.clone().into(), BlockSaveMode::StateSyncBoundary)
+        .unwrap();
     let mut store_update = env.clients[1].chain.mut_store().store_update();
-    store_update.inc_block_refcount(&prev_sync_hash).unwrap();
+    store_update.inc_block_refcount(prev_sync_block.hash()).unwrap();
     store_update.save_block(sync_block.clone());
     store_update.commit().unwrap();
     env.clients[1]
@@ -1793,10 +2437,74 @@ fn test_gc_tail_update() {
             &mut |_| {},
         )
         .unwrap();
+}
+
+#[test]
+fn test_gc_tail_update() {
+    let epoch_length = 2;
+    let (mut env, mut blocks) = prepare_env_with_synced_headers(
+        epoch_length,
+        epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 1),
+    );
+    // simulate save sync hash block
+    let prev_sync_block = blocks[blocks.len() - 3].clone();
+    let prev_sync_height = prev_sync_block.header().height();
+    let sync_block = blocks[blocks.len() - 2].clone();
+    simulate_state_sync_blocks(&mut env, &prev_sync_block, &sync_block);
     env.process_block(1, blocks.pop().unwrap(), Provenance::NONE);
     assert_eq!(env.clients[1].chain.store().tail().unwrap(), prev_sync_height);
 }
 
+/// The block saved before the sync block isn't referenced by a refcount of its own previous
+/// block, so garbage collection removes it together with the sync block once the tail gets there.
+#[test]
+fn test_gc_state_sync_boundary_block() {
+    let epoch_length = 2;
+    let (mut env, blocks) = prepare_env_with_synced_headers(
+        epoch_length,
+        epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 1),
+    );
+    let prev_sync_block = blocks[blocks.len() - 2].clone();
+    let prev_sync_hash = *prev_sync_block.hash();
+    let prev_sync_height = prev_sync_block.header().height();
+    let sync_block = blocks[blocks.len() - 1].clone();
+    simulate_state_sync_blocks(&mut env, &prev_sync_block, &sync_block);
+    assert!(env.clients[1].chain.store().is_state_sync_boundary_block(&prev_sync_hash).unwrap());
+    assert_eq!(env.clients[1].chain.store().tail().unwrap(), prev_sync_height);
+
+    let first_height = sync_block.header().height() + 1;
+    for i in first_height..first_height + epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 3) {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let tries = env.clients[1].runtime_adapter.get_tries();
+    env.clients[1].chain.clear_data(tries, 100).unwrap();
+
+    assert!(env.clients[1].chain.store().tail().unwrap() > sync_block.header().height());
+    assert!(env.clients[1].chain.get_block(&prev_sync_hash).is_err());
+    assert!(env.clients[1].chain.get_block(sync_block.hash()).is_err());
+    assert!(!env.clients[1].chain.store().is_state_sync_boundary_block(&prev_sync_hash).unwrap());
+}
+
+/// After state sync the final head should follow the synced head instead of staying at genesis.
+#[test]
+fn test_final_head_after_state_sync() {
+    let epoch_length = 5;
+    let (mut env, blocks) = prepare_env_with_synced_headers(epoch_length, epoch_length * 3);
+    let prev_sync_block = blocks[blocks.len() - 2].clone();
+    let prev_sync_hash = *prev_sync_block.hash();
+    let sync_block = blocks[blocks.len() - 1].clone();
+    simulate_state_sync_blocks(&mut env, &prev_sync_block, &sync_block);
+
+    let head = env.clients[1].chain.head().unwrap();
+    let final_head = env.clients[1].chain.final_head().unwrap();
+    assert_eq!(head.last_block_hash, prev_sync_hash);
+    assert_eq!(&final_head.last_block_hash, prev_sync_block.header().last_final_block());
+    assert!(final_head.height > 0);
+    assert!(head.height - final_head.height <= epoch_length);
+}
+
 /// Test that transaction does not become invalid when there is some gas price change.
 #[test]
 fn test_gas_price_change() {
@@ -2098,6 +2806,186 @@ fn test_sync_hash_validity() {
     }
 }
 
+/// Requesting state with the hash of a block next to the first block of an epoch suggests the
+/// hash of that first block.
+#[test]
+fn test_sync_hash_suggestion() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..19 {
+        env.produce_block(0, i);
+    }
+    let chain = &mut env.clients[0].chain;
+    for boundary_height in [11, 16] {
+        let boundary_hash = *chain.get_header_by_height(boundary_height).unwrap().hash();
+        assert!(chain.check_sync_hash_validity(&boundary_hash).unwrap());
+        for height in [boundary_height - 1, boundary_height + 1] {
+            let hash = *chain.get_header_by_height(height).unwrap().hash();
+            assert!(!chain.check_sync_hash_validity(&hash).unwrap());
+            assert_eq!(chain.suggest_sync_hash(&hash).unwrap(), Some(boundary_hash));
+        }
+    }
+    let hash = *chain.get_header_by_height(3).unwrap().hash();
+    assert_eq!(chain.suggest_sync_hash(&hash).unwrap(), None);
+}
+
+/// Corrupted local data makes a state request fail with an error instead of crashing the node,
+/// and the header is served again once the data is fixed.
+#[test]
+fn test_state_response_header_local_data_corruption() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..13 {
+        env.produce_block(0, i);
+    }
+    let chain = &mut env.clients[0].chain;
+    let sync_hash = *chain.get_header_by_height(11).unwrap().hash();
+    assert!(chain.check_sync_hash_validity(&sync_hash).unwrap());
+
+    let receipt_proofs = chain.mut_store().get_incoming_receipts(&sync_hash, 0).unwrap().clone();
+    let mut corrupted_receipt_proofs = receipt_proofs.clone();
+    corrupted_receipt_proofs[0].0.push(Receipt::new_balance_refund(&"test0".parse().unwrap(), 1));
+    let mut store_update = chain.mut_store().store_update();
+    store_update.save_incoming_receipt(&sync_hash, 0, corrupted_receipt_proofs);
+    store_update.commit().unwrap();
+    assert_matches!(
+        chain.get_state_response_header(0, sync_hash).unwrap_err().kind(),
+        ErrorKind::StateSyncLocalDataCorrupted { shard_id: 0, .. }
+    );
+
+    let mut store_update = chain.mut_store().store_update();
+    store_update.save_incoming_receipt(&sync_hash, 0, receipt_proofs);
+    store_update.commit().unwrap();
+    assert!(chain.get_state_response_header(0, sync_hash).is_ok());
+}
+
+/// Only valid sync hashes count towards the limit of served sync hashes, and requests for more of
+/// them than allowed are rejected as busy.
+#[test]
+fn test_state_request_sync_hash_limit() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..13 {
+        env.produce_block(0, i);
+    }
+    let chain = &mut env.clients[0].chain;
+    chain.set_sync_hash_registry(Arc::new(SyncHashRegistry::new(1, Duration::from_secs(600))));
+    let sync_hash = *chain.get_header_by_height(11).unwrap().hash();
+    let prev_sync_hash = *chain.get_header_by_height(6).unwrap().hash();
+    assert!(chain.check_sync_hash_validity(&prev_sync_hash).unwrap());
+
+    // Unknown and invalid sync hashes don't take the place of valid ones.
+    for i in 0..100u8 {
+        let bad_hash = hash(&[i]);
+        assert_matches!(
+            chain.check_state_request_sync_hash(&bad_hash).unwrap_err().kind(),
+            ErrorKind::DBNotFoundErr(_)
+        );
+    }
+    let not_boundary_hash = *chain.get_header_by_height(8).unwrap().hash();
+    assert!(!chain.check_state_request_sync_hash(&not_boundary_hash).unwrap());
+
+    assert!(chain.check_state_request_sync_hash(&sync_hash).unwrap());
+    assert!(chain.get_state_response_header(0, sync_hash).is_ok());
+    // Valid sync hashes count towards the limit too.
+    assert_matches!(
+        chain.check_state_request_sync_hash(&prev_sync_hash).unwrap_err().kind(),
+        ErrorKind::StateSyncBusy(rejected) if *rejected == prev_sync_hash
+    );
+    assert_eq!(chain.suggest_served_sync_hash(&prev_sync_hash), Some(sync_hash));
+    assert_eq!(chain.suggest_served_sync_hash(&sync_hash), None);
+    // Sync hashes already served are not rejected.
+    assert!(chain.check_state_request_sync_hash(&sync_hash).unwrap());
+}
+
+/// The summary of an epoch is saved by epoch height when the next one starts and counts its
+/// blocks, chunks, transactions and the gas used to apply the chunks.
+#[test]
+fn test_epoch_summary() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    for i in 1..=16 {
+        env.produce_block(0, i);
+        if i == 12 {
+            for nonce in 1..=3 {
+                let tx = SignedTransaction::send_money(
+                    nonce,
+                    "test0".parse().unwrap(),
+                    "test1".parse().unwrap(),
+                    &signer,
+                    100,
+                    genesis_hash,
+                );
+                env.clients[0].process_tx(tx, false, false);
+            }
+        }
+    }
+
+    let client = &mut env.clients[0];
+    let chain = &mut client.chain;
+    let runtime_adapter = &client.runtime_adapter;
+    // The first epoch shares its id with the genesis block, but not its height.
+    let mut epoch_heights = vec![];
+    for (first_height, expected_txs) in [(1, 0), (6, 0), (11, 3)] {
+        let first_header = chain.get_header_by_height(first_height).unwrap().clone();
+        let last_hash = *chain.get_header_by_height(first_height + 4).unwrap().hash();
+        let epoch_height =
+            runtime_adapter.get_epoch_height_from_prev_block(first_header.prev_hash()).unwrap();
+        epoch_heights.push(epoch_height);
+        let summary = chain.get_epoch_summary(epoch_height).unwrap();
+        assert_eq!(summary.epoch_height, epoch_height);
+        assert_eq!(summary.blocks_produced, 5);
+        assert_eq!(summary.chunks_produced_per_shard, vec![5]);
+        assert_eq!(summary.total_txs, expected_txs);
+        assert_eq!(summary.first_block_hash, *first_header.hash());
+        assert_eq!(summary.last_block_hash, last_hash);
+        let gas_used = (first_height..first_height + 5)
+            .map(|height| {
+                let hash = *chain.get_header_by_height(height).unwrap().hash();
+                chain.get_chunk_extra(&hash, &ShardUId::single_shard()).unwrap().gas_used()
+            })
+            .sum::<u64>();
+        assert_eq!(summary.total_gas_used, gas_used);
+        assert_eq!(summary.total_gas_used > 0, expected_txs > 0);
+    }
+    epoch_heights.dedup();
+    assert_eq!(epoch_heights.len(), 3);
+
+    let head = chain.head().unwrap();
+    let epoch_height =
+        runtime_adapter.get_epoch_height_from_prev_block(&head.prev_block_hash).unwrap();
+    assert_eq!(
+        chain.get_epoch_summary(epoch_height).unwrap_err().kind(),
+        ErrorKind::EpochSummaryNotComputed(epoch_height)
+    );
+}
+
 /// Only process one block per height
 #[test]
 fn test_not_process_height_twice() {
@@ -2141,33 +3029,242 @@ fn test_block_height_processed_orphan() {
 }
 
 #[test]
-fn test_validate_chunk_extra() {
+fn test_regenerate_epoch_light_client_block() {
     let epoch_length = 5;
     let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
     genesis.config.epoch_length = epoch_length;
-    let mut env = TestEnv::builder(ChainGenesis::test())
-        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
-        .build();
-    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
-    let genesis_height = genesis_block.header().height();
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let runtimes = create_nightshade_runtimes(&genesis, 1);
+    let mut env =
+        TestEnv::builder(chain_genesis.clone()).runtime_adapters(runtimes.clone()).build();
+    for i in 1..=3 * epoch_length {
+        env.produce_block(0, i);
+    }
+    let epoch_id = env.clients[0]
+        .chain
+        .get_block_by_height(epoch_length + 2)
+        .unwrap()
+        .header()
+        .epoch_id()
+        .clone();
+    let original = env.clients[0].chain.get_light_client_block_at_epoch(&epoch_id, false).unwrap();
 
-    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
-    let tx = SignedTransaction::from_actions(
-        1,
-        "test0".parse().unwrap(),
-        "test0".parse().unwrap(),
-        &signer,
-        vec![Action::DeployContract(DeployContractAction {
-            code: near_test_contracts::rs_contract().to_vec(),
-        })],
-        *genesis_block.hash(),
+    let mut store_update = runtimes[0].get_store().store_update();
+    store_update.delete(ColEpochLightClientBlocks, epoch_id.0.as_ref());
+    store_update.commit().unwrap();
+
+    // Chains created after the deletion don't have the record cached.
+    let new_chain = || {
+        Chain::new_for_view_client(
+            runtimes[0].clone(),
+            &chain_genesis,
+            DoomslugThresholdMode::NoApprovals,
+        )
+        .unwrap()
+    };
+    let mut chain = new_chain();
+    assert_matches!(
+        chain.get_light_client_block_at_epoch(&epoch_id, false).unwrap_err().kind(),
+        ErrorKind::DBNotFoundErr(_)
     );
-    env.clients[0].process_tx(tx, false, false);
-    let mut last_block = genesis_block;
-    for i in 1..3 {
-        last_block = env.clients[0].produce_block(i).unwrap().unwrap();
-        env.process_block(0, last_block.clone(), Provenance::PRODUCED);
-    }
+    assert_eq!(chain.get_light_client_block_at_epoch(&epoch_id, true).unwrap(), original);
+    // The regenerated record is persisted.
+    assert_eq!(new_chain().get_light_client_block_at_epoch(&epoch_id, false).unwrap(), original);
+
+    // The current epoch hasn't finished yet, so there is nothing to regenerate.
+    let head_epoch_id = chain.head().unwrap().epoch_id;
+    assert_matches!(
+        chain.get_light_client_block_at_epoch(&head_epoch_id, true).unwrap_err().kind(),
+        ErrorKind::DBNotFoundErr(_)
+    );
+}
+
+#[test]
+fn test_chain_segment_proof() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..=6 * epoch_length {
+        env.produce_block(0, i);
+    }
+    let chain = &mut env.clients[0].chain;
+    // The block producers never change, so the genesis commits to the ones of every epoch.
+    let trusted_bp_hash = *chain.get_header_by_height(0).unwrap().next_bp_hash();
+    let from_hash = *chain.get_header_by_height(2 * epoch_length + 1).unwrap().hash();
+    let to_hash = *chain.get_header_by_height(4 * epoch_length + 3).unwrap().hash();
+
+    let proof = chain.export_chain_segment_proof(&from_hash, &to_hash).unwrap();
+    assert_eq!(proof.headers.len(), 2 * epoch_length as usize + 3);
+    assert_eq!(proof.headers.first().unwrap().hash(), &from_hash);
+    assert_eq!(proof.headers.last().unwrap().hash(), &to_hash);
+    assert!(!proof.epoch_light_client_blocks.is_empty());
+    verify_chain_segment_proof(&trusted_bp_hash, &proof).unwrap();
+
+    assert_matches!(
+        verify_chain_segment_proof(&hash(b"other"), &proof).unwrap_err().kind(),
+        ErrorKind::InvalidChainSegmentProof(_)
+    );
+    let mut tampered = proof.clone();
+    tampered.headers.remove(epoch_length as usize);
+    assert_matches!(
+        verify_chain_segment_proof(&trusted_bp_hash, &tampered).unwrap_err().kind(),
+        ErrorKind::InvalidChainSegmentProof(_)
+    );
+    let mut tampered = proof;
+    tampered.head.inner_lite.height += 1;
+    assert_matches!(
+        verify_chain_segment_proof(&trusted_bp_hash, &tampered).unwrap_err().kind(),
+        ErrorKind::InvalidChainSegmentProof(_)
+    );
+
+    // The head block is not final yet.
+    let head_hash = chain.head().unwrap().last_block_hash;
+    assert_matches!(
+        chain.export_chain_segment_proof(&from_hash, &head_hash).unwrap_err().kind(),
+        ErrorKind::NotFinalYet { .. }
+    );
+}
+
+#[test]
+fn test_sync_diagnostics_missing_chunks() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    for i in 1..3 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        // The second block is not processed as produced, so that no chunk is stored for height 3.
+        let provenance = if i == 1 { Provenance::PRODUCED } else { Provenance::NONE };
+        env.process_block(0, block, provenance);
+    }
+    assert!(env.clients[0].chain.sync_diagnostics().unwrap().likely_causes().is_empty());
+
+    // Include a chunk which the client never received.
+    let validator_signer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let (encoded_chunk, _, _) = create_chunk_on_height(&mut env.clients[0], 3);
+    let mut block = env.clients[0].produce_block(3).unwrap().unwrap();
+    let mut chunk_header = encoded_chunk.cloned_header();
+    *chunk_header.height_included_mut() = 3;
+    let chunk_headers = vec![chunk_header];
+    block.set_chunks(chunk_headers.clone());
+    block.mut_header().get_mut().inner_rest.chunk_headers_root =
+        Block::compute_chunk_headers_root(&chunk_headers).0;
+    block.mut_header().get_mut().inner_rest.chunk_tx_root =
+        Block::compute_chunk_tx_root(&chunk_headers);
+    block.mut_header().get_mut().inner_rest.chunk_receipts_root =
+        Block::compute_chunk_receipts_root(&chunk_headers);
+    block.mut_header().get_mut().inner_lite.prev_state_root =
+        Block::compute_state_root(&chunk_headers);
+    block.mut_header().get_mut().inner_rest.chunk_mask = vec![true];
+    block.mut_header().resign(&validator_signer);
+    let (_, res) = env.clients[0].process_block(block.into(), Provenance::NONE);
+    assert_matches!(res.unwrap_err().kind(), ErrorKind::ChunksMissing(_));
+
+    let diagnostics = env.clients[0].chain.sync_diagnostics().unwrap();
+    assert_eq!(diagnostics.head_height, 2);
+    assert_eq!(diagnostics.num_blocks_with_missing_chunks, 1);
+    assert_eq!(diagnostics.likely_causes(), vec![SyncStallCause::MissingChunks]);
+}
+
+/// Produces blocks until some chunk generates outgoing receipts, tampers the receipts stored for
+/// it and returns the result of processing the block with the next chunk, which claims the root of
+/// the original receipts.
+fn process_block_after_tampered_receipts(
+    mode: ReceiptsRootCheckMode,
+) -> Result<(), near_chain::Error> {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.clients[0].chain.receipts_root_check_mode = mode;
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    env.clients[0].process_tx(tx, false, false);
+
+    let mut height = 1;
+    let mut receipts = loop {
+        assert!(height < 10, "send money transaction didn't produce any receipts");
+        env.produce_block(0, height);
+        let block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+        let receipts =
+            env.clients[0].chain.mut_store().get_outgoing_receipts(&block_hash, 0).unwrap().clone();
+        if !receipts.is_empty() {
+            break receipts;
+        }
+        height += 1;
+    };
+    // The next chunk is already produced, so the change is only visible to block processing.
+    receipts.push(receipts[0].clone());
+    let block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+    let mut store_update = env.clients[0].chain.mut_store().store_update();
+    store_update.save_outgoing_receipt(&block_hash, 0, receipts);
+    store_update.commit().unwrap();
+
+    let block = env.clients[0].produce_block(height + 1).unwrap().unwrap();
+    assert_eq!(block.chunks()[0].height_included(), height + 1);
+    let (_, result) = env.clients[0].process_block(block.into(), Provenance::PRODUCED);
+    result.map(|_| ())
+}
+
+#[test]
+fn test_strict_receipt_root_checks() {
+    let err =
+        process_block_after_tampered_receipts(ReceiptsRootCheckMode::FailOnMismatch).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::OutgoingReceiptsRootMismatch(0));
+
+    // Only logged, the block is then rejected by the regular chunk validation.
+    let err =
+        process_block_after_tampered_receipts(ReceiptsRootCheckMode::LogMismatch).unwrap_err();
+    assert_ne!(err.kind(), ErrorKind::OutgoingReceiptsRootMismatch(0));
+    let err = process_block_after_tampered_receipts(ReceiptsRootCheckMode::Disabled).unwrap_err();
+    assert_ne!(err.kind(), ErrorKind::OutgoingReceiptsRootMismatch(0));
+}
+
+#[test]
+fn test_validate_chunk_extra() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
+    let genesis_height = genesis_block.header().height();
+
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::from_actions(
+        1,
+        "test0".parse().unwrap(),
+        "test0".parse().unwrap(),
+        &signer,
+        vec![Action::DeployContract(DeployContractAction {
+            code: near_test_contracts::rs_contract().to_vec(),
+        })],
+        *genesis_block.hash(),
+    );
+    env.clients[0].process_tx(tx, false, false);
+    let mut last_block = genesis_block;
+    for i in 1..3 {
+        last_block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, last_block.clone(), Provenance::PRODUCED);
+    }
 
     // Construct a chunk that such when the receipts generated by this chunk are included
     // in blocks of different heights, the state transitions are different.
@@ -2345,40 +3442,520 @@ fn test_catchup_gas_price_change() {
         .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
         .is_err());
 
-    // Simulate state sync
-    let sync_hash = *blocks[5].hash();
-    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    // Simulate state sync
+    let sync_hash = *blocks[5].hash();
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    sync_state_parts_from_client_0(&mut env, sync_hash);
+    env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap();
+    let chunk_extra_after_sync = env.clients[1]
+        .chain
+        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
+        .unwrap()
+        .clone();
+    let expected_chunk_extra = env.clients[0]
+        .chain
+        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
+        .unwrap()
+        .clone();
+    // The chunk extra of the prev block of sync block should be the same as the node that it is syncing from
+    assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
+}
+
+/// Downloads the state of shard 0 at `sync_hash` from client 0 and applies it on client 1,
+/// leaving the finalization of state sync to the caller.
+fn sync_state_parts_from_client_0(env: &mut TestEnv, sync_hash: CryptoHash) {
+    let state_sync_header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
+    let state_root = match &state_sync_header {
+        ShardStateSyncResponseHeader::V1(header) => header.chunk.header.inner.prev_state_root,
+        ShardStateSyncResponseHeader::V2(header) => {
+            *header.chunk.cloned_header().take_inner().prev_state_root()
+        }
+    };
+    //let state_root = state_sync_header.chunk.header.inner.prev_state_root;
+    let state_root_node =
+        env.clients[0].runtime_adapter.get_state_root_node(0, &sync_hash, &state_root).unwrap();
+    let num_parts = get_num_state_parts(state_root_node.memory_usage);
+    let state_sync_parts = (0..num_parts)
+        .map(|i| {
+            env.clients[0]
+                .chain
+                .get_state_response_part(0, i, sync_hash)
+                .unwrap()
+                .into_vec()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    env.clients[1].chain.set_state_header(0, sync_hash, state_sync_header).unwrap();
+    for i in 0..num_parts {
+        env.clients[1]
+            .chain
+            .set_state_part(
+                0,
+                sync_hash,
+                PartId::new(i, num_parts),
+                &state_sync_parts[i as usize],
+                None,
+            )
+            .unwrap();
+    }
+    let rt = Arc::clone(&env.clients[1].runtime_adapter);
+    let f = move |msg: ApplyStatePartsRequest| {
+        use borsh::BorshSerialize;
+        let store = rt.get_store();
+
+        for part_id in 0..msg.num_parts {
+            let key = StatePartKey(msg.sync_hash, msg.shard_id, part_id).try_to_vec().unwrap();
+            let part = store.get(ColStateParts, &key).unwrap().unwrap();
+
+            rt.apply_state_part(
+                msg.shard_id,
+                &msg.state_root,
+                PartId::new(part_id, msg.num_parts),
+                &part,
+                &msg.epoch_id,
+            )
+            .unwrap();
+        }
+    };
+    env.clients[1].chain.schedule_apply_state_parts(0, sync_hash, num_parts, &f).unwrap();
+}
+
+/// State sync finalization interrupted after a few heights resumes where it stopped, ends up with
+/// the same chunk extras as the node it synced from, and doesn't save anything twice.
+#[test]
+fn test_set_state_finalize_resume() {
+    init_test_logger();
+    let epoch_length = 10;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    // The last new chunk before the sync block is included at `last_chunk_height`, so
+    // finalization has to build the chain through the three heights after it.
+    let last_chunk_height = epoch_length - 3;
+    let mut blocks = vec![];
+    for height in 1..=epoch_length + 1 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        blocks.push(block.clone());
+        let produce_chunk = height < last_chunk_height;
+        env.process_block_with_options(0, block.clone(), Provenance::PRODUCED, true, produce_chunk);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let sync_hash = *blocks[epoch_length as usize].hash();
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    sync_state_parts_from_client_0(&mut env, sync_hash);
+
+    // Apply the chunk and build the first two heights after it, then stop as if the node was
+    // restarted.
+    for _ in 0..3 {
+        assert!(env.clients[1].chain.set_state_finalize_step(0, sync_hash).unwrap());
+    }
+    assert_eq!(
+        env.clients[1].chain.store().get_state_sync_finalize_progress(0, &sync_hash).unwrap(),
+        Some(last_chunk_height + 2)
+    );
+    env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap();
+    assert_eq!(
+        env.clients[1].chain.store().get_state_sync_finalize_progress(0, &sync_hash).unwrap(),
+        None
+    );
+    let chunk_extras = |env: &mut TestEnv, client_id: usize| {
+        (last_chunk_height..=epoch_length)
+            .map(|height| {
+                env.clients[client_id]
+                    .chain
+                    .get_chunk_extra(blocks[height as usize - 1].hash(), &ShardUId::single_shard())
+                    .unwrap()
+                    .clone()
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected_chunk_extras = chunk_extras(&mut env, 0);
+    assert_eq!(chunk_extras(&mut env, 1), expected_chunk_extras);
+
+    // Finalizing again from scratch finds everything already saved.
+    env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap();
+    assert_eq!(chunk_extras(&mut env, 1), expected_chunk_extras);
+
+    // A saved chunk extra which doesn't match the recomputed one is reported.
+    let last_chunk_hash = *blocks[last_chunk_height as usize - 1].hash();
+    let mut corrupted_chunk_extra = expected_chunk_extras[0].clone();
+    *corrupted_chunk_extra.state_root_mut() = CryptoHash::default();
+    let mut store_update = env.clients[1].chain.mut_store().store_update();
+    store_update.save_chunk_extra(
+        &last_chunk_hash,
+        &ShardUId::single_shard(),
+        corrupted_chunk_extra,
+    );
+    store_update.commit().unwrap();
+    let err = env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap_err();
+    assert_matches!(
+        err.kind(),
+        ErrorKind::StateSyncChunkExtraMismatch { block_hash, shard_id: 0 }
+            if block_hash == last_chunk_hash
+    );
+}
+
+/// The state download of a shard recorded for catchup is reported through each of its stages,
+/// until its state is finalized.
+#[test]
+fn test_state_download_status() {
+    init_test_logger();
+    let epoch_length = 10;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    let mut blocks = vec![];
+    for height in 1..=epoch_length + 1 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        blocks.push(block.clone());
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let sync_block = blocks[epoch_length as usize].clone();
+    let sync_hash = *sync_block.hash();
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    assert_eq!(env.clients[1].chain.state_download_status().unwrap(), vec![]);
+
+    let mut store_update = env.clients[1].chain.mut_store().store_update();
+    store_update.add_state_dl_info(StateSyncInfo {
+        epoch_tail_hash: sync_hash,
+        shards: vec![ShardInfo(0, sync_block.chunks()[0].chunk_hash())],
+    });
+    store_update.commit().unwrap();
+    let status = |env: &mut TestEnv| {
+        let mut statuses = env.clients[1].chain.state_download_status().unwrap();
+        assert_eq!(statuses.len(), 1);
+        let EpochStateDownloadStatus { epoch_id, sync_hash: status_sync_hash, mut shards } =
+            statuses.pop().unwrap();
+        assert_eq!(&epoch_id, sync_block.header().epoch_id());
+        assert_eq!(status_sync_hash, sync_hash);
+        assert_eq!(shards.len(), 1);
+        shards.pop().unwrap()
+    };
+    assert_eq!(
+        status(&mut env),
+        ShardStateDownloadStatus {
+            shard_id: 0,
+            header_stored: false,
+            parts_downloaded: 0,
+            parts_total: 0,
+            finalize_height: None,
+            finalized: false,
+        }
+    );
+
+    // All the parts are downloaded and applied, but the state isn't finalized yet.
+    sync_state_parts_from_client_0(&mut env, sync_hash);
+    let shard_status = status(&mut env);
+    assert!(shard_status.header_stored);
+    assert!(shard_status.parts_total > 0);
+    assert_eq!(shard_status.parts_downloaded, shard_status.parts_total);
+    assert_eq!(shard_status.finalize_height, None);
+    assert!(!shard_status.finalized);
+
+    assert!(env.clients[1].chain.set_state_finalize_step(0, sync_hash).unwrap());
+    let finalize_height =
+        env.clients[1].chain.store().get_state_sync_finalize_progress(0, &sync_hash).unwrap();
+    assert!(finalize_height.is_some());
+    let shard_status = status(&mut env);
+    assert_eq!(shard_status.finalize_height, finalize_height);
+    assert!(!shard_status.finalized);
+
+    env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap();
+    let shard_status = status(&mut env);
+    assert_eq!(shard_status.finalize_height, None);
+    assert!(shard_status.finalized);
+}
+
+/// A secondary process with the database opened read-only serves the same state sync header and
+/// parts as the node owning the database, without trying to persist them.
+#[test]
+fn test_state_response_from_read_only_store() {
+    use borsh::BorshSerialize;
+    init_test_logger();
+    let epoch_length = 5;
+    let genesis = {
+        let mut genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+        genesis.config.epoch_length = epoch_length;
+        genesis
+    };
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let dir = tempfile::Builder::new().prefix("read_only_state_response").tempdir().unwrap();
+    let sync_hash = {
+        let runtime = nearcore::NightshadeRuntime::test(
+            Path::new("../../../.."),
+            near_store::create_store(dir.path()),
+            &genesis,
+        );
+        let mut env = TestEnv::builder(chain_genesis.clone())
+            .runtime_adapters(vec![Arc::new(runtime) as Arc<dyn RuntimeAdapter>])
+            .build();
+        let mut blocks = vec![];
+        for height in 1..=epoch_length + 1 {
+            let block = env.clients[0].produce_block(height).unwrap().unwrap();
+            blocks.push(block.clone());
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+        let sync_hash = *blocks[epoch_length as usize].hash();
+        assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+        sync_hash
+    };
+
+    let store = near_store::create_store_with_config(
+        dir.path(),
+        near_store::StoreConfig { read_only: true, ..Default::default() },
+    );
+    assert!(store.is_read_only());
+    let runtime: Arc<dyn RuntimeAdapter> = Arc::new(nearcore::NightshadeRuntime::test(
+        Path::new("../../../.."),
+        store.clone(),
+        &genesis,
+    ));
+    let mut chain = Chain::new_for_view_client(
+        runtime.clone(),
+        &chain_genesis,
+        DoomslugThresholdMode::NoApprovals,
+    )
+    .unwrap();
+    let header = chain.get_state_response_header(0, sync_hash).unwrap();
+    let state_root = match &header {
+        ShardStateSyncResponseHeader::V1(header) => header.chunk.header.inner.prev_state_root,
+        ShardStateSyncResponseHeader::V2(header) => {
+            *header.chunk.cloned_header().take_inner().prev_state_root()
+        }
+    };
+    let state_root_node = runtime.get_state_root_node(0, &sync_hash, &state_root).unwrap();
+    let num_parts = get_num_state_parts(state_root_node.memory_usage);
+    for part_id in 0..num_parts {
+        let part =
+            chain.get_state_response_part(0, part_id, sync_hash).unwrap().into_vec().unwrap();
+        assert_eq!(
+            chain.get_state_response_part(0, part_id, sync_hash).unwrap().into_vec().unwrap(),
+            part
+        );
+        let key = StatePartKey(sync_hash, 0, part_id).try_to_vec().unwrap();
+        assert!(store.get(ColStateParts, &key).unwrap().is_none());
+    }
+    assert_eq!(chain.get_state_response_header(0, sync_hash).unwrap(), header);
+}
+
+/// The served state sync headers and parts are tallied by sync hash and counted by the metrics of
+/// their shard.
+#[test]
+fn test_state_sync_serving_stats() {
+    use borsh::BorshSerialize;
+    init_test_logger();
+    let served_before = state_sync_served_counters();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::from(&genesis))
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let mut blocks = vec![];
+    for height in 1..=epoch_length + 1 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        blocks.push(block.clone());
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+    let sync_hash = *blocks[epoch_length as usize].hash();
+    let chain = &mut env.clients[0].chain;
+    assert!(chain.state_sync_serving_stats().is_empty());
+
+    let header = chain.get_state_response_header(0, sync_hash).unwrap();
+    let header_size = header.try_to_vec().unwrap().len() as u64;
+    let state_root = match &header {
+        ShardStateSyncResponseHeader::V1(header) => header.chunk.header.inner.prev_state_root,
+        ShardStateSyncResponseHeader::V2(header) => {
+            *header.chunk.cloned_header().take_inner().prev_state_root()
+        }
+    };
+    let state_root_node =
+        env.clients[0].runtime_adapter.get_state_root_node(0, &sync_hash, &state_root).unwrap();
+    let num_parts = get_num_state_parts(state_root_node.memory_usage);
+    let chain = &mut env.clients[0].chain;
+    let mut parts_size = 0;
+    for part_id in 0..num_parts {
+        // The second request is served from storage.
+        let part =
+            chain.get_state_response_part(0, part_id, sync_hash).unwrap().into_vec().unwrap();
+        assert_eq!(
+            chain.get_state_response_part(0, part_id, sync_hash).unwrap().into_vec().unwrap(),
+            part
+        );
+        parts_size += 2 * part.len() as u64;
+    }
+    // Failed requests aren't tallied.
+    assert!(chain.get_state_response_part(0, num_parts, sync_hash).is_err());
+    assert!(chain.get_state_response_header(0, *blocks[1].hash()).is_err());
+
+    let stats = chain.state_sync_serving_stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(
+        stats[&sync_hash],
+        StateSyncServingStats {
+            headers_served: 1,
+            parts_served: 2 * num_parts,
+            cache_hits: num_parts,
+            bytes_served: header_size + parts_size,
+        }
+    );
+
+    // Other tests may serve shard 0 at the same time, so the counters grow by at least as much.
+    let served_after = state_sync_served_counters();
+    let served = |counter: &str, item: &str| {
+        let key = (counter.to_string(), item.to_string());
+        served_after.get(&key).copied().unwrap_or(0) - served_before.get(&key).copied().unwrap_or(0)
+    };
+    assert!(served("near_state_sync_served_requests_total", "header") >= 1);
+    assert!(served("near_state_sync_served_requests_total", "part") >= 2 * num_parts);
+    assert!(served("near_state_sync_served_cache_hits_total", "part") >= num_parts);
+    assert!(served("near_state_sync_served_bytes_total", "header") >= header_size);
+    assert!(served("near_state_sync_served_bytes_total", "part") >= parts_size);
+}
+
+/// Values of the state sync serving counters of shard 0 by counter name and served item.
+fn state_sync_served_counters() -> HashMap<(String, String), u64> {
+    let mut counters = HashMap::new();
+    for family in near_metrics::gather() {
+        if !family.get_name().starts_with("near_state_sync_served_") {
+            continue;
+        }
+        for metric in family.get_metric() {
+            let label = |name: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.get_name() == name)
+                    .map(|label| label.get_value())
+            };
+            if let (Some("0"), Some(item)) = (label("shard_id"), label("item")) {
+                counters.insert(
+                    (family.get_name().to_string(), item.to_string()),
+                    metric.get_counter().get_value() as u64,
+                );
+            }
+        }
+    }
+    counters
+}
+
+/// Replaying the chain with trusted chunks reaches the same state as the regular processing.
+#[test]
+fn test_trusted_chain_replay() {
+    init_test_logger();
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = 5;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    // client 1 is not a validator, but tracks the shard
+    let runtimes: Vec<Arc<dyn RuntimeAdapter>> = vec![
+        create_nightshade_runtimes(&genesis, 1).pop().unwrap(),
+        Arc::new(nearcore::NightshadeRuntime::test_with_runtime_config_store(
+            Path::new("../../../.."),
+            create_test_store(),
+            &genesis,
+            TrackedConfig::AllShards,
+            RuntimeConfigStore::test(),
+        )),
+    ];
+    let mut env =
+        TestEnv::builder(chain_genesis).clients_count(2).runtime_adapters(runtimes).build();
+    assert_eq!(
+        env.clients[1].chain.enable_trusted_chain_replay("mainnet").unwrap_err().kind(),
+        ErrorKind::TrustedChainReplayNotAllowed("mainnet".to_string())
+    );
+    env.clients[1].chain.enable_trusted_chain_replay(&genesis.config.chain_id).unwrap();
+
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let mut blocks = vec![];
+    for height in 1..=12 {
+        let tx = SignedTransaction::send_money(
+            height,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        env.clients[0].process_tx(tx, false, false);
+        blocks.push(produce_block_for_two_clients(&mut env, height));
+    }
+
+    let shard_uid = ShardUId::single_shard();
+    let last_hash = *blocks.last().unwrap().hash();
+    let state_root = |env: &mut TestEnv, client: usize| {
+        *env.clients[client].chain.get_chunk_extra(&last_hash, &shard_uid).unwrap().state_root()
+    };
+    let expected_state_root = state_root(&mut env, 0);
+    let genesis_state_root = env.clients[0].chain.genesis_block().chunks()[0].prev_state_root();
+    assert_ne!(expected_state_root, genesis_state_root);
+    assert_eq!(state_root(&mut env, 1), expected_state_root);
+}
+
+/// Produces a block on client 0 and processes it on client 1 as well, fetching its chunks from
+/// client 0. Client 1 doesn't run catchup.
+fn produce_block_for_two_clients(env: &mut TestEnv, height: BlockHeight) -> Block {
+    let block = env.clients[0].produce_block(height).unwrap().unwrap();
+    env.process_block(0, block.clone(), Provenance::PRODUCED);
+    let (_, res) = env.clients[1].process_block(block.clone().into(), Provenance::NONE);
+    if let Err(e) = res {
+        assert_matches!(e.kind(), ErrorKind::ChunksMissing(_));
+        env.process_partial_encoded_chunks_requests(1);
+    }
+    assert_eq!(&env.clients[1].chain.head().unwrap().last_block_hash, block.hash());
+    block
+}
+
+/// Downloads the state of shard 0 at `sync_hash` from client 0 on client 1 and catches up the
+/// blocks client 1 processed since. Returns the number of blocks whose store updates were spilled
+/// to the store during catchup.
+fn sync_state_and_catch_up_client_1(env: &mut TestEnv, sync_hash: CryptoHash) -> u64 {
+    let me = Some(env.get_client_id(1).clone());
     let state_sync_header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
-    let state_root = match &state_sync_header {
-        ShardStateSyncResponseHeader::V1(header) => header.chunk.header.inner.prev_state_root,
-        ShardStateSyncResponseHeader::V2(header) => {
-            *header.chunk.cloned_header().take_inner().prev_state_root()
-        }
-    };
-    //let state_root = state_sync_header.chunk.header.inner.prev_state_root;
+    let state_root = state_sync_header.chunk_prev_state_root();
     let state_root_node =
         env.clients[0].runtime_adapter.get_state_root_node(0, &sync_hash, &state_root).unwrap();
     let num_parts = get_num_state_parts(state_root_node.memory_usage);
     let state_sync_parts = (0..num_parts)
-        .map(|i| env.clients[0].chain.get_state_response_part(0, i, sync_hash).unwrap())
+        .map(|i| {
+            env.clients[0]
+                .chain
+                .get_state_response_part(0, i, sync_hash)
+                .unwrap()
+                .into_vec()
+                .unwrap()
+        })
         .collect::<Vec<_>>();
-
     env.clients[1].chain.set_state_header(0, sync_hash, state_sync_header).unwrap();
     for i in 0..num_parts {
         env.clients[1]
             .chain
-            .set_state_part(0, sync_hash, PartId::new(i, num_parts), &state_sync_parts[i as usize])
+            .set_state_part(
+                0,
+                sync_hash,
+                PartId::new(i, num_parts),
+                &state_sync_parts[i as usize],
+                None,
+            )
             .unwrap();
     }
     let rt = Arc::clone(&env.clients[1].runtime_adapter);
     let f = move |msg: ApplyStatePartsRequest| {
         use borsh::BorshSerialize;
         let store = rt.get_store();
-
         for part_id in 0..msg.num_parts {
             let key = StatePartKey(msg.sync_hash, msg.shard_id, part_id).try_to_vec().unwrap();
             let part = store.get(ColStateParts, &key).unwrap().unwrap();
-
             rt.apply_state_part(
                 msg.shard_id,
                 &msg.state_root,
@@ -2391,18 +3968,430 @@ fn test_catchup_gas_price_change() {
     };
     env.clients[1].chain.schedule_apply_state_parts(0, sync_hash, num_parts, &f).unwrap();
     env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap();
-    let chunk_extra_after_sync = env.clients[1]
-        .chain
-        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
-        .unwrap()
-        .clone();
-    let expected_chunk_extra = env.clients[0]
+
+    // Catch up the blocks of the epoch
+    let epoch_id = env.clients[1].chain.get_block_header(&sync_hash).unwrap().epoch_id().clone();
+    let memory_limit_bytes = env.clients[1].config.catchup_saved_updates_memory_limit_bytes;
+    let mut catchup_state = BlocksCatchUpState::new(sync_hash, epoch_id, memory_limit_bytes);
+    let catchup_requests = RefCell::new(vec![]);
+    while !catchup_state.is_finished() {
+        env.clients[1]
+            .chain
+            .catchup_blocks_step(
+                &me,
+                &sync_hash,
+                &mut catchup_state,
+                &|request: BlockCatchUpRequest| catchup_requests.borrow_mut().push(request),
+            )
+            .unwrap();
+        for request in catchup_requests.borrow_mut().drain(..) {
+            let results = do_apply_chunks(request.work);
+            let saved_store_update =
+                catchup_state.scheduled_blocks.remove(&request.block_hash).unwrap();
+            catchup_state
+                .processed_blocks
+                .insert(request.block_hash, (saved_store_update, results));
+        }
+    }
+    env.clients[1]
         .chain
-        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
-        .unwrap()
-        .clone();
-    // The chunk extra of the prev block of sync block should be the same as the node that it is syncing from
-    assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
+        .finish_catchup_blocks(
+            &me,
+            &sync_hash,
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+            &mut |_| {},
+            &catchup_state.done_blocks,
+        )
+        .unwrap();
+    catchup_state.num_spilled_blocks
+}
+
+/// Sets up a validator and a second client which is not a validator, but tracks the shard.
+fn setup_env_with_tracking_client() -> TestEnv {
+    init_test_logger();
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = 5;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let runtimes: Vec<Arc<dyn RuntimeAdapter>> = vec![
+        create_nightshade_runtimes(&genesis, 1).pop().unwrap(),
+        Arc::new(nearcore::NightshadeRuntime::test_with_runtime_config_store(
+            Path::new("../../../.."),
+            create_test_store(),
+            &genesis,
+            TrackedConfig::AllShards,
+            RuntimeConfigStore::test(),
+        )),
+    ];
+    TestEnv::builder(chain_genesis).clients_count(2).runtime_adapters(runtimes).build()
+}
+
+/// A quarantined shard is no longer applied, and its state is downloaded again and caught up at
+/// the start of the next epoch, which lifts the quarantine.
+#[test]
+fn test_quarantined_shard_state_resync() {
+    let mut env = setup_env_with_tracking_client();
+    let shard_uid = ShardUId::single_shard();
+
+    let mut blocks = vec![];
+    for height in 1..=3 {
+        blocks.push(produce_block_for_two_clients(&mut env, height));
+    }
+    env.clients[1].chain.quarantine_shard_state(shard_uid, "test".to_string()).unwrap();
+    let quarantined_shards = env.clients[1].chain.shard_quarantine_status().unwrap();
+    assert_eq!(quarantined_shards[&shard_uid].head_hash, *blocks[2].hash());
+    for height in 4..=8 {
+        blocks.push(produce_block_for_two_clients(&mut env, height));
+    }
+
+    // The shard is not applied after the quarantine, and is downloaded again at the epoch boundary
+    assert!(env.clients[1].chain.get_chunk_extra(blocks[3].hash(), &shard_uid).is_err());
+    let sync_hash = *blocks[5].hash();
+    let state_sync_infos = env.clients[1].chain.store().iterate_state_sync_infos();
+    assert_eq!(state_sync_infos.len(), 1);
+    assert_eq!(state_sync_infos[0].0, sync_hash);
+    assert_eq!(state_sync_infos[0].1.shards.iter().map(|info| info.0).collect::<Vec<_>>(), vec![0]);
+
+    sync_state_and_catch_up_client_1(&mut env, sync_hash);
+    assert!(env.clients[1].chain.shard_quarantine_status().unwrap().is_empty());
+    assert!(env.clients[1].chain.store().iterate_state_sync_infos().is_empty());
+
+    // The shard is applied normally again
+    for height in 9..=11 {
+        blocks.push(produce_block_for_two_clients(&mut env, height));
+    }
+    for block in &blocks[4..] {
+        let chunk_extra =
+            env.clients[1].chain.get_chunk_extra(block.hash(), &shard_uid).unwrap().clone();
+        let expected_chunk_extra =
+            env.clients[0].chain.get_chunk_extra(block.hash(), &shard_uid).unwrap().clone();
+        assert_eq!(chunk_extra, expected_chunk_extra);
+    }
+}
+
+/// Blocks processed while the state for their epoch is being downloaded are reported as partially
+/// applied with the shards deferred to catchup, until they are caught up.
+#[test]
+fn test_partially_applied_blocks() {
+    let mut env = setup_env_with_tracking_client();
+    let mut blocks = vec![];
+    for height in 1..=3 {
+        blocks.push(produce_block_for_two_clients(&mut env, height));
+    }
+    // The quarantine makes client 1 download the state of the shard again in the next epoch.
+    let shard_uid = ShardUId::single_shard();
+    env.clients[1].chain.quarantine_shard_state(shard_uid, "test".to_string()).unwrap();
+    for height in 4..=8 {
+        blocks.push(produce_block_for_two_clients(&mut env, height));
+    }
+    assert!(env.clients[0].chain.partially_applied_blocks().unwrap().is_empty());
+
+    // Blocks from the first block of the epoch on wait for the state download.
+    let sync_hash = *blocks[5].hash();
+    let mut expected = blocks[5..].iter().map(|block| (*block.hash(), vec![0])).collect::<Vec<_>>();
+    expected.sort();
+    let mut partially_applied_blocks = env.clients[1].chain.partially_applied_blocks().unwrap();
+    partially_applied_blocks.sort();
+    assert_eq!(partially_applied_blocks, expected);
+    assert_eq!(env.clients[1].chain.sync_diagnostics().unwrap().num_partially_applied_blocks, 3);
+
+    sync_state_and_catch_up_client_1(&mut env, sync_hash);
+    assert!(env.clients[1].chain.partially_applied_blocks().unwrap().is_empty());
+    assert_eq!(env.clients[1].chain.sync_diagnostics().unwrap().num_partially_applied_blocks, 0);
+}
+
+/// Store updates of blocks being caught up are spilled to the store once the ones held in memory
+/// exceed the limit, and the blocks are caught up the same way as without spilling.
+#[test]
+fn test_catchup_spills_saved_store_updates() {
+    let shard_uid = ShardUId::single_shard();
+    let mut caught_up_chunk_extras = vec![];
+    for memory_limit_bytes in [u64::MAX, 0] {
+        let mut env = setup_env_with_tracking_client();
+        env.clients[1].config.catchup_saved_updates_memory_limit_bytes = memory_limit_bytes;
+        let mut blocks = vec![];
+        for height in 1..=3 {
+            blocks.push(produce_block_for_two_clients(&mut env, height));
+        }
+        // The quarantine makes client 1 catch up the blocks of the next epoch.
+        env.clients[1].chain.quarantine_shard_state(shard_uid, "test".to_string()).unwrap();
+        for height in 4..=8 {
+            blocks.push(produce_block_for_two_clients(&mut env, height));
+        }
+
+        let num_spilled_blocks = sync_state_and_catch_up_client_1(&mut env, *blocks[5].hash());
+        assert_eq!(num_spilled_blocks, if memory_limit_bytes == 0 { 3 } else { 0 });
+        let store = env.clients[1].runtime_adapter.get_store();
+        assert_eq!(store.iter_prefix(ColBlockMisc, SPILLED_STORE_UPDATE_KEY).count(), 0);
+
+        let chunk_extras = |env: &mut TestEnv, client: usize| {
+            blocks[5..]
+                .iter()
+                .map(|block| {
+                    env.clients[client]
+                        .chain
+                        .get_chunk_extra(block.hash(), &shard_uid)
+                        .unwrap()
+                        .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected_chunk_extras = chunk_extras(&mut env, 0);
+        let caught_up = chunk_extras(&mut env, 1);
+        assert_eq!(caught_up, expected_chunk_extras);
+        caught_up_chunk_extras.push(caught_up);
+    }
+    assert_eq!(caught_up_chunk_extras[0], caught_up_chunk_extras[1]);
+}
+
+/// A chunk produced by the node itself which doesn't match its own chunk extra quarantines the
+/// shard when `quarantine_shard_on_state_mismatch` is set, and the block is accepted without
+/// applying the chunk.
+#[test]
+fn test_quarantine_shard_on_own_chunk_state_mismatch() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::from(&genesis))
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    env.clients[0].config.quarantine_shard_on_state_mismatch = true;
+    let shard_uid = ShardUId::single_shard();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+
+    // Corrupt the chunk extra the next chunk was produced from
+    let head_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+    let mut chain_store_update = env.clients[0].chain.mut_store().store_update();
+    chain_store_update.save_chunk_extra(
+        &head_hash,
+        &shard_uid,
+        ChunkExtra::new_with_only_state_root(&CryptoHash::default()),
+    );
+    chain_store_update.commit().unwrap();
+
+    let block = env.clients[0].produce_block(4).unwrap().unwrap();
+    let (accepted_blocks, res) =
+        env.clients[0].process_block(block.clone().into(), Provenance::PRODUCED);
+    assert_eq!(res.unwrap().unwrap().last_block_hash, *block.hash());
+    assert_eq!(
+        accepted_blocks.iter().map(|accepted| accepted.hash).collect::<Vec<_>>(),
+        vec![*block.hash()]
+    );
+    assert!(env.clients[0].chain.is_block_invalid(block.hash()).is_none());
+    let quarantined_shards = env.clients[0].chain.shard_quarantine_status().unwrap();
+    assert_eq!(quarantined_shards.keys().collect::<Vec<_>>(), vec![&shard_uid]);
+}
+
+/// Concurrent requests for the same state sync header share a single generation, the
+/// requests arriving while it runs are turned away instead of waiting for it.
+#[test]
+fn test_state_response_header_single_generation() {
+    init_test_logger();
+    let chain_genesis = ChainGenesis::test();
+    let epoch_length = chain_genesis.epoch_length;
+    let runtime = Arc::new(KeyValueRuntime::new_with_validators(
+        create_test_store(),
+        vec![vec!["test0".parse().unwrap()]],
+        1,
+        1,
+        epoch_length,
+    ));
+    let mut env = TestEnv::builder(chain_genesis.clone())
+        .runtime_adapters(vec![runtime.clone() as Arc<dyn RuntimeAdapter>])
+        .build();
+    for i in 1..=2 * epoch_length + 1 {
+        env.produce_block(0, i);
+    }
+    let sync_hash = (1..=2 * epoch_length + 1)
+        .rev()
+        .find_map(|height| {
+            let chain = &mut env.clients[0].chain;
+            let hash = *chain.get_block_by_height(height).unwrap().hash();
+            chain.check_sync_hash_validity(&hash).unwrap().then(|| hash)
+        })
+        .unwrap();
+
+    runtime.set_state_root_node_delay(Duration::from_millis(200));
+    let calls_before = runtime.num_state_root_node_calls();
+    let state_header_cache = Arc::new(StateHeaderCache::default());
+    let barrier = Arc::new(Barrier::new(10));
+    let handles = (0..10)
+        .map(|_| {
+            let runtime = runtime.clone();
+            let chain_genesis = chain_genesis.clone();
+            let state_header_cache = state_header_cache.clone();
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                let mut chain = Chain::new_for_view_client(
+                    runtime,
+                    &chain_genesis,
+                    DoomslugThresholdMode::NoApprovals,
+                )
+                .unwrap();
+                chain.set_state_header_cache(state_header_cache);
+                barrier.wait();
+                match chain.get_state_response_header(0, sync_hash) {
+                    Ok(header) => Some(header),
+                    Err(e) => {
+                        assert!(
+                            matches!(e.kind(), ErrorKind::StateSyncHeaderInFlight { .. }),
+                            "{}",
+                            e
+                        );
+                        None
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let headers =
+        handles.into_iter().filter_map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(runtime.num_state_root_node_calls() - calls_before, 1);
+    assert!(!headers.is_empty());
+    assert!(headers.iter().all(|header| header == &headers[0]));
+
+    let mut chain = Chain::new_for_view_client(
+        runtime.clone(),
+        &chain_genesis,
+        DoomslugThresholdMode::NoApprovals,
+    )
+    .unwrap();
+    chain.set_state_header_cache(state_header_cache);
+    assert_eq!(chain.get_state_response_header(0, sync_hash).unwrap(), headers[0]);
+    assert_eq!(runtime.num_state_root_node_calls() - calls_before, 1);
+}
+
+/// The state of the accounts touched by a new chunk is prefetched before its block is processed.
+#[test]
+fn test_prefetch_state_keys_for_block() {
+    init_test_logger();
+    let chain_genesis = ChainGenesis::test();
+    let runtime = Arc::new(KeyValueRuntime::new_with_validators(
+        create_test_store(),
+        vec![vec!["test0".parse().unwrap()]],
+        1,
+        1,
+        chain_genesis.epoch_length,
+    ));
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(vec![runtime.clone() as Arc<dyn RuntimeAdapter>])
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    for (nonce, signer_id) in ["test1", "test0"].iter().enumerate() {
+        let signer =
+            InMemorySigner::from_seed(signer_id.parse().unwrap(), KeyType::ED25519, signer_id);
+        let tx = SignedTransaction::send_money(
+            nonce as u64 + 1,
+            signer_id.parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        env.clients[0].process_tx(tx, false, false);
+    }
+    for i in 1..=4 {
+        env.produce_block(0, i);
+    }
+
+    let calls = runtime.prefetch_state_keys_calls();
+    assert_eq!(calls.len(), 1, "{:?}", calls);
+    let (shard_uid, _, accounts) = &calls[0];
+    assert_eq!(shard_uid.shard_id, 0);
+    assert_eq!(accounts, &vec!["test0".parse().unwrap(), "test1".parse().unwrap()]);
+}
+
+/// Headers with invalid signatures received during header sync and invalid state sync headers
+/// are reported to the misbehavior sink of the chain.
+#[test]
+fn test_chain_misbehavior_reports() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis).clients_count(2).build();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    env.clients[0].chain.malicious_behavior_sink = Some(sender.clone());
+    env.clients[1].chain.malicious_behavior_sink = Some(sender);
+    for i in 1..=2 * epoch_length + 1 {
+        env.produce_block(0, i);
+    }
+
+    let mut headers = (1..=3)
+        .map(|height| env.clients[0].chain.get_header_by_height(height).unwrap().clone())
+        .collect::<Vec<_>>();
+    headers[2].get_mut().inner_lite.timestamp += 1;
+    headers[2].get_mut().init();
+    let res = env.clients[1].chain.sync_block_headers(headers.clone(), &mut |_| {});
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidSignature);
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        ChainMisbehavior::InvalidHeader {
+            header_hash: *headers[2].hash(),
+            error: ErrorKind::InvalidSignature
+        }
+    );
+    assert!(receiver.try_recv().is_err());
+
+    let sync_hash = (1..=2 * epoch_length + 1)
+        .rev()
+        .find_map(|height| {
+            let chain = &mut env.clients[0].chain;
+            let hash = *chain.get_block_by_height(height).unwrap().hash();
+            chain.check_sync_hash_validity(&hash).unwrap().then(|| hash)
+        })
+        .unwrap();
+    let mut state_header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
+    let extra_proof = MerklePathItem { hash: CryptoHash::default(), direction: Direction::Left };
+    match &mut state_header {
+        ShardStateSyncResponseHeader::V1(header) => header.chunk_proof.push(extra_proof),
+        ShardStateSyncResponseHeader::V2(header) => header.chunk_proof.push(extra_proof),
+    }
+    assert!(env.clients[0].chain.set_state_header(0, sync_hash, state_header).is_err());
+    let misbehavior = receiver.try_recv().unwrap();
+    assert_matches!(misbehavior, ChainMisbehavior::InvalidStateHeader { shard_id: 0, .. });
+    assert_eq!(misbehavior.hash(), &sync_hash);
+    assert!(receiver.try_recv().is_err());
+}
+
+/// Headers stored without their epoch manager data break the sync of the following headers
+/// until the data is added back by `Chain::repair_epoch_data`.
+#[test]
+fn test_repair_epoch_data() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    for i in 1..=5 {
+        env.produce_block(0, i);
+    }
+    let headers = (1..=5)
+        .map(|height| env.clients[0].chain.get_header_by_height(height).unwrap().clone())
+        .collect::<Vec<_>>();
+
+    // Simulate a partial write: the headers are stored, their epoch manager data is not.
+    let mut chain_store_update = env.clients[1].chain.mut_store().store_update();
+    for header in &headers[..3] {
+        chain_store_update.save_block_header(header.clone()).unwrap();
+    }
+    chain_store_update.commit().unwrap();
+    assert!(env.clients[1].chain.sync_block_headers(headers[3..].to_vec(), &mut |_| {}).is_err());
+
+    let report = env.clients[1].chain.repair_epoch_data(0, 5).unwrap();
+    assert_eq!(report.headers_checked, 3);
+    assert_eq!(report.repaired, headers[..3].iter().map(|h| *h.hash()).collect::<Vec<_>>());
+    assert!(env.clients[1].chain.repair_epoch_data(0, 5).unwrap().repaired.is_empty());
+
+    env.clients[1].chain.sync_block_headers(headers[3..].to_vec(), &mut |_| {}).unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().height, 5);
 }
 
 #[test]
@@ -2456,7 +4445,7 @@ fn test_block_execution_outcomes() {
     assert_eq!(chunk.transactions().len(), 3);
     let execution_outcomes_from_block = env.clients[0]
         .chain
-        .get_block_execution_outcomes(block.hash())
+        .get_block_execution_outcomes(block.hash(), true)
         .unwrap()
         .remove(&0)
         .unwrap();
@@ -2477,7 +4466,7 @@ fn test_block_execution_outcomes() {
     assert!(next_chunk.receipts().is_empty());
     let execution_outcomes_from_block = env.clients[0]
         .chain
-        .get_block_execution_outcomes(next_block.hash())
+        .get_block_execution_outcomes(next_block.hash(), true)
         .unwrap()
         .remove(&0)
         .unwrap();
@@ -2485,6 +4474,49 @@ fn test_block_execution_outcomes() {
     assert!(execution_outcomes_from_block[0].outcome_with_id.id == delayed_receipt_id[0]);
 }
 
+#[test]
+fn test_verified_block_execution_outcomes() {
+    init_test_logger();
+
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = 5;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        1,
+        *genesis_block.hash(),
+    );
+    let tx_hash = tx.get_hash();
+    env.clients[0].process_tx(tx, false, false);
+    for i in 1..4 {
+        env.produce_block(0, i);
+    }
+
+    let block_hash = env.clients[0].chain.get_execution_outcome(&tx_hash).unwrap().block_hash;
+    assert!(env.clients[0].chain.get_block_execution_outcomes(&block_hash, true).is_ok());
+
+    // Tamper with the stored outcome of the transaction, leaving its proof as is.
+    let mut outcomes = env.clients[0].chain.store().get_outcomes_by_id(&tx_hash).unwrap();
+    for outcome in outcomes.iter_mut() {
+        outcome.outcome_with_id.outcome.gas_burnt += 1;
+    }
+    let mut store_update = env.clients[0].chain.store().store().store_update();
+    store_update.set_ser(ColTransactionResult, tx_hash.as_ref(), &outcomes).unwrap();
+    store_update.commit().unwrap();
+
+    assert!(env.clients[0].chain.get_block_execution_outcomes(&block_hash, false).is_ok());
+    let res = env.clients[0].chain.get_block_execution_outcomes(&block_hash, true);
+    assert!(matches!(res.unwrap_err().kind(), ErrorKind::OutcomesRootMismatch(0)));
+}
+
 #[test]
 fn test_refund_receipts_processing() {
     init_test_logger();
@@ -2586,7 +4618,7 @@ fn test_refund_receipts_processing() {
         let block = env.clients[0].chain.get_block_by_height(i).unwrap().clone();
         let execution_outcomes_from_block = env.clients[0]
             .chain
-            .get_block_execution_outcomes(block.hash())
+            .get_block_execution_outcomes(block.hash(), false)
             .unwrap()
             .remove(&0)
             .unwrap();
@@ -2601,6 +4633,69 @@ fn test_refund_receipts_processing() {
     assert_eq!(processed_refund_receipt_ids, refund_receipt_ids);
 }
 
+/// A transfer to a non-existing account on another shard fails there and sends a refund back.
+/// The outcome of the refund leads back through the failed receipt to the transaction.
+#[test]
+fn test_outcome_ancestry_cross_shard() {
+    init_test_logger();
+
+    let mut genesis = Genesis::test_sharded_new_version(
+        vec!["test0".parse().unwrap(), "test1".parse().unwrap()],
+        1,
+        vec![1, 1],
+    );
+    genesis.config.epoch_length = 100;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
+    let runtime_adapter = env.clients[0].runtime_adapter.clone();
+    let epoch_id = genesis_block.header().epoch_id().clone();
+    let sender_shard_id =
+        runtime_adapter.account_id_to_shard_id(&"test0".parse().unwrap(), &epoch_id).unwrap();
+    let receiver_id: AccountId = (0..)
+        .map(|i| format!("random_account{}", i).parse().unwrap())
+        .find(|account_id| {
+            runtime_adapter.account_id_to_shard_id(account_id, &epoch_id).unwrap()
+                != sender_shard_id
+        })
+        .unwrap();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        receiver_id,
+        &signer,
+        1,
+        *genesis_block.hash(),
+    );
+    let tx_hash = tx.get_hash();
+    env.clients[0].process_tx(tx, false, false);
+    for height in 1..10 {
+        env.produce_block(0, height);
+    }
+
+    let chain = &mut env.clients[0].chain;
+    assert_eq!(chain.get_outcome_ancestry(&tx_hash).unwrap(), vec![tx_hash]);
+    let tx_outcome = chain.get_execution_outcome(&tx_hash).unwrap();
+    let receipt_id = tx_outcome.outcome_with_id.outcome.receipt_ids[0];
+    let receipt_outcome = chain.get_execution_outcome(&receipt_id).unwrap();
+    assert!(matches!(
+        receipt_outcome.outcome_with_id.outcome.status,
+        ExecutionStatus::Failure(TxExecutionError::ActionError(_))
+    ));
+    let refund_receipt_ids = receipt_outcome.outcome_with_id.outcome.receipt_ids;
+    assert!(!refund_receipt_ids.is_empty());
+    for refund_receipt_id in refund_receipt_ids {
+        assert_eq!(
+            chain.get_outcome_ancestry(&refund_receipt_id).unwrap(),
+            vec![refund_receipt_id, receipt_id, tx_hash]
+        );
+    }
+    assert!(chain.get_outcome_ancestry(&hash(&[1])).is_err());
+}
+
 #[test]
 fn test_wasmer2_upgrade() {
     let mut capture = near_logger_utils::TracingCapture::enable();
@@ -3540,7 +5635,9 @@ mod access_key_nonce_range_tests {
             Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
         genesis.config.epoch_length = epoch_length;
         genesis.config.protocol_version = protocol_version;
-        let mut env = TestEnv::builder(ChainGenesis::test())
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.protocol_version = protocol_version;
+        let mut env = TestEnv::builder(chain_genesis)
             .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
             .build();
         let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
@@ -4219,7 +6316,7 @@ mod cap_max_gas_price_tests {
                 .get_epoch_protocol_version(block.header().epoch_id())
                 .unwrap();
             let min_gas_price =
-                env.clients[0].chain.block_economics_config.min_gas_price(protocol_version);
+                env.clients[0].chain.block_economics_config.min_gas_price(protocol_version, i);
             was_congested |= block.chunks()[0].gas_used() >= block.chunks()[0].gas_limit();
             price_exceeded_limit |= block.header().gas_price() > 20 * min_gas_price;
         }
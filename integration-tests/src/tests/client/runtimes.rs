@@ -108,8 +108,9 @@ fn test_cap_max_gas_price() {
         .runtime_adapter
         .get_epoch_protocol_version(last_block.header().epoch_id())
         .unwrap();
-    let min_gas_price = env.clients[0].chain.block_economics_config.min_gas_price(protocol_version);
-    let max_gas_price = env.clients[0].chain.block_economics_config.max_gas_price(protocol_version);
+    let economics = &env.clients[0].chain.block_economics_config;
+    let min_gas_price = economics.min_gas_price(protocol_version, epoch_length - 1);
+    let max_gas_price = economics.max_gas_price(protocol_version, epoch_length - 1);
     assert!(max_gas_price <= 20 * min_gas_price);
 }
 
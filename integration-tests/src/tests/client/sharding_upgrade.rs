@@ -3,19 +3,19 @@ use borsh::BorshSerialize;
 use crate::tests::client::process_blocks::{
     create_nightshade_runtimes, set_block_protocol_version,
 };
-use near_chain::{ChainGenesis, Provenance};
+use near_chain::{ChainGenesis, ErrorKind, Provenance};
 use near_chain_configs::Genesis;
 use near_client::test_utils::TestEnv;
 use near_crypto::{InMemorySigner, KeyType, Signer};
 use near_logger_utils::init_test_logger;
 use near_primitives::account::id::AccountId;
 use near_primitives::block::Block;
-use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout};
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout, ShardUId};
 use near_primitives::transaction::{
     Action, DeployContractAction, FunctionCallAction, SignedTransaction,
 };
-use near_primitives::types::{BlockHeight, ProtocolVersion, ShardId};
+use near_primitives::types::{BlockHeight, EpochId, ProtocolVersion, ShardId};
 use near_primitives::version::ProtocolFeature;
 use near_primitives::views::QueryRequest;
 use near_primitives::views::{ExecutionStatusView, FinalExecutionStatus};
@@ -244,6 +244,42 @@ impl TestShardUpgradeEnv {
         }
     }
 
+    /// Check that chain.get_prev_block_hash_with_new_chunk function returns the same result as
+    /// walking back the chain block by block, for the block at `height` and all shards in it.
+    /// When the shard layout changes on the way back, the walk follows the parent shard.
+    fn check_prev_block_with_new_chunk(&mut self, height: BlockHeight) {
+        let runtime_adapter = self.env.clients[0].runtime_adapter.clone();
+        let chain = &mut self.env.clients[0].chain;
+        let block = chain.get_block_by_height(height).unwrap().clone();
+        for shard_id in 0..block.chunks().len() as ShardId {
+            let mut shard_layout =
+                runtime_adapter.get_shard_layout(block.header().epoch_id()).unwrap();
+            let mut target_shard_id = shard_id;
+            let mut prev_hash = *block.header().prev_hash();
+            let mut expected = None;
+            while prev_hash != CryptoHash::default() {
+                let prev_block = chain.get_block(&prev_hash).unwrap().clone();
+                let prev_shard_layout =
+                    runtime_adapter.get_shard_layout(prev_block.header().epoch_id()).unwrap();
+                if prev_shard_layout != shard_layout {
+                    target_shard_id = shard_layout.get_parent_shard_id(target_shard_id).unwrap();
+                    shard_layout = prev_shard_layout;
+                }
+                if prev_block.chunks()[target_shard_id as usize].height_included()
+                    == prev_block.header().height()
+                {
+                    expected = Some((prev_hash, target_shard_id));
+                    break;
+                }
+                prev_hash = *prev_block.header().prev_hash();
+            }
+            assert_eq!(
+                chain.get_prev_block_hash_with_new_chunk(block.hash(), shard_id).unwrap(),
+                expected
+            );
+        }
+    }
+
     /// This functions checks that the outcomes of all transactions and associated receipts
     /// have successful status
     /// If `allow_not_started` is true, allow transactions status to be NotStarted
@@ -657,6 +693,7 @@ fn test_shard_layout_upgrade_missing_chunks(p_missing: f64) {
         let last_height = test_env.env.clients[0].chain.head().unwrap().height;
         for height in last_height - 3..=last_height {
             test_env.check_next_block_with_new_chunk(height);
+            test_env.check_prev_block_with_new_chunk(height);
         }
     }
 
@@ -666,6 +703,7 @@ fn test_shard_layout_upgrade_missing_chunks(p_missing: f64) {
         let last_height = test_env.env.clients[0].chain.head().unwrap().height;
         for height in last_height - 3..=last_height {
             test_env.check_next_block_with_new_chunk(height);
+            test_env.check_prev_block_with_new_chunk(height);
         }
     }
 
@@ -689,3 +727,47 @@ fn test_shard_layout_upgrade_missing_chunks_mid_missing_prob() {
 fn test_shard_layout_upgrade_missing_chunks_high_missing_prob() {
     test_shard_layout_upgrade_missing_chunks(0.9);
 }
+
+/// The shard of an account and the shard layout are resolved with the layout of the requested
+/// epoch, on both sides of the shard layout upgrade.
+#[test]
+fn test_resolve_account_shard_across_shard_layout_upgrade() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut test_env = TestShardUpgradeEnv::new(epoch_length, 2, 2, 0, None);
+    for _ in 1..=3 * epoch_length + 1 {
+        test_env.step(0.);
+    }
+    let chain = &mut test_env.env.clients[0].chain;
+    let old_epoch_id = chain.genesis_block().header().epoch_id().clone();
+    let new_epoch_id = chain.head().unwrap().epoch_id;
+    let account_id: AccountId = "test1".parse().unwrap();
+
+    assert_eq!(
+        chain.resolve_account_shard(&account_id, Some(old_epoch_id.clone())).unwrap(),
+        (0, ShardUId { version: 0, shard_id: 0 })
+    );
+    let new_shard = (3, ShardUId { version: 1, shard_id: 3 });
+    assert_eq!(chain.resolve_account_shard(&account_id, None).unwrap(), new_shard);
+    assert_eq!(
+        chain.resolve_account_shard(&account_id, Some(new_epoch_id.clone())).unwrap(),
+        new_shard
+    );
+
+    let old_layout = chain.get_shard_layout_view(Some(old_epoch_id.clone())).unwrap();
+    assert_eq!(old_layout.epoch_id, old_epoch_id);
+    assert_eq!((old_layout.version, old_layout.num_shards), (0, 1));
+    assert!(old_layout.fixed_shards.is_empty());
+    assert!(old_layout.boundary_accounts.is_empty());
+    let new_layout = chain.get_shard_layout_view(None).unwrap();
+    assert_eq!(new_layout.epoch_id, new_epoch_id);
+    assert_eq!((new_layout.version, new_layout.num_shards), (1, 4));
+    assert_eq!(new_layout.fixed_shards, vec!["test0".parse().unwrap()]);
+    assert_eq!(new_layout.boundary_accounts, vec!["abc".parse().unwrap(), "foo".parse().unwrap()]);
+
+    let unknown_epoch_id = EpochId(hash(b"unknown"));
+    assert_matches!(
+        chain.get_shard_layout_view(Some(unknown_epoch_id)).unwrap_err().kind(),
+        ErrorKind::EpochOutOfBounds(_)
+    );
+}
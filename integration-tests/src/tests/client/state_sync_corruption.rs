@@ -0,0 +1,191 @@
+use crate::tests::client::process_blocks::create_nightshade_runtimes;
+use near_chain::state_sync_corruptor::StateSyncCorruptor;
+use near_chain::{ChainGenesis, ErrorKind, PartProvenance, PeerStatePartStats};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_logger_utils::init_test_logger;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::state_part::PartId;
+use near_primitives::syncing::get_num_state_parts;
+use near_primitives::types::{BlockHeight, ShardId};
+use nearcore::config::GenesisExt;
+use std::time::Duration;
+
+const EPOCH_LENGTH: BlockHeight = 5;
+const NUM_SHARDS: ShardId = 2;
+
+/// Sets up client 0 with a two shard chain spanning two epochs and client 1 with the headers of
+/// that chain, as if it had done header sync. Returns the hash of the first block of the second
+/// epoch, from which client 1 can state sync.
+fn setup_env() -> (TestEnv, CryptoHash) {
+    init_test_logger();
+    let mut genesis = Genesis::test_sharded(
+        vec!["test0".parse().unwrap(), "test1".parse().unwrap()],
+        1,
+        vec![1; NUM_SHARDS as usize],
+    );
+    genesis.config.epoch_length = EPOCH_LENGTH;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    for height in 1..=EPOCH_LENGTH + 2 {
+        env.produce_block(0, height);
+    }
+    let headers = (1..=EPOCH_LENGTH + 2)
+        .map(|height| env.clients[0].chain.get_header_by_height(height).unwrap().clone())
+        .collect();
+    env.clients[1]
+        .chain
+        .sync_block_headers(headers, &mut |_| panic!("unexpected challenge"))
+        .unwrap();
+
+    let sync_hash = *env.clients[0].chain.get_header_by_height(EPOCH_LENGTH + 1).unwrap().hash();
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    (env, sync_hash)
+}
+
+/// Serves the state of every shard at `sync_hash` from client 0 to client 1, stopping at the
+/// first header or part client 1 rejects.
+fn sync_state(
+    env: &mut TestEnv,
+    sync_hash: CryptoHash,
+    corruptor: Option<StateSyncCorruptor>,
+) -> Result<(), near_chain::Error> {
+    env.clients[0].chain.adv_set_state_sync_corruptor(corruptor);
+    for shard_id in 0..NUM_SHARDS {
+        let header = env.clients[0].chain.get_state_response_header(shard_id, sync_hash).unwrap();
+        let num_parts = get_num_state_parts(header.state_root_node().memory_usage);
+        env.clients[1].chain.set_state_header(shard_id, sync_hash, header)?;
+        for part_id in 0..num_parts {
+            let part = env.clients[0]
+                .chain
+                .get_state_response_part(shard_id, part_id, sync_hash)
+                .unwrap()
+                .into_vec()
+                .unwrap();
+            env.clients[1].chain.set_state_part(
+                shard_id,
+                sync_hash,
+                PartId::new(part_id, num_parts),
+                &part,
+                None,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that state sync with the given corruption is rejected with `expected_error`.
+fn check_rejected(corruptor: StateSyncCorruptor, expected_error: ErrorKind) {
+    let (mut env, sync_hash) = setup_env();
+    let err = sync_state(&mut env, sync_hash, Some(corruptor)).unwrap_err();
+    assert_eq!(err.kind(), expected_error, "unexpected error for {:?}", corruptor);
+}
+
+#[test]
+fn test_state_sync_without_corruption() {
+    let (mut env, sync_hash) = setup_env();
+    sync_state(&mut env, sync_hash, None).unwrap();
+}
+
+#[test]
+fn test_state_sync_flip_root_proof() {
+    check_rejected(
+        StateSyncCorruptor::FlipRootProof,
+        ErrorKind::InvalidStateHeaderReceiptProofs(0),
+    );
+}
+
+#[test]
+fn test_state_sync_reorder_incoming_receipts() {
+    check_rejected(
+        StateSyncCorruptor::ReorderIncomingReceipts,
+        ErrorKind::InvalidStateHeaderIncomingReceipts(0),
+    );
+}
+
+#[test]
+fn test_state_sync_truncate_part() {
+    check_rejected(
+        StateSyncCorruptor::TruncatePart,
+        ErrorKind::InvalidStatePart { shard_id: 0, part_id: 0 },
+    );
+}
+
+#[test]
+fn test_state_sync_duplicate_from_shard_id() {
+    check_rejected(
+        StateSyncCorruptor::DuplicateFromShardId,
+        ErrorKind::InvalidStateHeaderReceiptProofs(0),
+    );
+}
+
+/// Parts downloaded for the state sync are summarized per peer they were requested from, until
+/// the parts of the shard are cleared.
+#[test]
+fn test_state_part_provenance_stats() {
+    let (mut env, sync_hash) = setup_env();
+    let peers = [PeerId::random(), PeerId::random()];
+    let request_sent = chrono::Utc::now();
+    let mut num_parts_by_shard = vec![];
+    for shard_id in 0..NUM_SHARDS {
+        let header = env.clients[0].chain.get_state_response_header(shard_id, sync_hash).unwrap();
+        let num_parts = get_num_state_parts(header.state_root_node().memory_usage);
+        env.clients[1].chain.set_state_header(shard_id, sync_hash, header).unwrap();
+        for part_id in 0..num_parts {
+            let part = env.clients[0]
+                .chain
+                .get_state_response_part(shard_id, part_id, sync_hash)
+                .unwrap()
+                .into_vec()
+                .unwrap();
+            // The parts of shard 0 take 100ms, the parts of shard 1 take 200ms and 100ms longer
+            // for each next part.
+            let latency_ms = 100 * (1 + shard_id * (1 + part_id)) as i64;
+            let provenance = PartProvenance {
+                peer_id: peers[shard_id as usize].clone(),
+                request_sent,
+                received: request_sent + chrono::Duration::milliseconds(latency_ms),
+            };
+            env.clients[1]
+                .chain
+                .set_state_part(
+                    shard_id,
+                    sync_hash,
+                    PartId::new(part_id, num_parts),
+                    &part,
+                    Some(provenance),
+                )
+                .unwrap();
+        }
+        num_parts_by_shard.push(num_parts);
+    }
+
+    let (num_parts_0, num_parts_1) = (num_parts_by_shard[0], num_parts_by_shard[1]);
+    let expected = vec![
+        PeerStatePartStats {
+            peer_id: peers[1].clone(),
+            num_parts: num_parts_1 as usize,
+            average_latency: Duration::from_millis(150 + 50 * num_parts_1),
+            max_latency: Duration::from_millis(100 + 100 * num_parts_1),
+        },
+        PeerStatePartStats {
+            peer_id: peers[0].clone(),
+            num_parts: num_parts_0 as usize,
+            average_latency: Duration::from_millis(100),
+            max_latency: Duration::from_millis(100),
+        },
+    ];
+    assert_eq!(env.clients[1].chain.state_part_provenance_stats(), expected);
+    assert_eq!(env.clients[1].chain.sync_diagnostics().unwrap().state_part_providers, expected);
+
+    env.clients[1].chain.clear_downloaded_parts(1, sync_hash, num_parts_1).unwrap();
+    assert_eq!(env.clients[1].chain.state_part_provenance_stats(), expected[1..].to_vec());
+
+    // Abandoned state syncs don't clear their parts.
+    env.clients[1].chain.retain_state_part_provenance(|hash| hash != &sync_hash);
+    assert_eq!(env.clients[1].chain.state_part_provenance_stats(), vec![]);
+}
@@ -55,7 +55,7 @@ fn test_block_unknown_block_error() {
                                         );
                                         assert_eq!(
                                             error_json["cause"]["name"],
-                                            serde_json::json!("UNKNOWN_BLOCK")
+                                            serde_json::json!("NOT_YET_PRODUCED")
                                         );
                                         System::current().stop();
                                     })
@@ -84,6 +84,7 @@ pub fn setup_network_node(
             Some(signer),
             telemetry_actor,
             None,
+            None,
             #[cfg(feature = "test_features")]
             adv.clone(),
         )
@@ -94,6 +95,7 @@ pub fn setup_network_node(
             runtime.clone(),
             network_adapter,
             client_config,
+            None,
             #[cfg(feature = "test_features")]
             adv.clone(),
         );
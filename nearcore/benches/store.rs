@@ -30,7 +30,7 @@ fn read_trie_items(bench: &mut Bencher, shard_id: usize, read_only: bool) {
         tracing::info!(target: "neard", "{:?}", home_dir);
         let store = create_store_with_config(
             &get_store_path(&home_dir),
-            StoreConfig { read_only, enable_statistics: false },
+            StoreConfig { read_only, ..Default::default() },
         );
 
         let mut chain_store =
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
@@ -7,19 +8,28 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context};
+use bytesize::ByteSize;
 use hyper::body::HttpBody;
 use indicatif::{ProgressBar, ProgressStyle};
 use near_primitives::time::Clock;
 use num_rational::Rational;
 use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use sysinfo::{System, SystemExt};
 #[cfg(test)]
 use tempfile::tempdir;
 use tokio::io::AsyncWriteExt;
 use tracing::{error, info, warn};
 
 use near_chain_configs::{
-    get_initial_supply, ClientConfig, Genesis, GenesisConfig, GenesisValidationMode,
-    LogSummaryStyle,
+    get_initial_supply, is_public_chain, ClientConfig, EconomicsOverrides, GcSchedule, Genesis,
+    GenesisConfig, GenesisValidationMode, LogSummaryStyle, TxRoutingPolicy,
+    UnsupportedProtocolVersionPolicy, DEFAULT_CATCHUP_SAVED_UPDATES_MEMORY_LIMIT_BYTES,
+    DEFAULT_FUTURE_BLOCK_GRACE_PERIOD, DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE,
+    DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND, DEFAULT_MAX_BLOCK_SERIALIZED_BYTES,
+    DEFAULT_MAX_CHUNK_TRANSACTIONS, DEFAULT_MAX_ORPHANS_PER_PREV_HASH,
+    DEFAULT_RECEIPT_BACKLOG_WARN_BYTES, DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES,
+    DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW,
 };
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 #[cfg(feature = "json_rpc")]
@@ -27,10 +37,9 @@ use near_jsonrpc::RpcConfig;
 use near_network::test_utils::open_port;
 use near_network_primitives::types::{NetworkConfig, ROUTED_MESSAGE_TTL};
 use near_primitives::account::{AccessKey, Account};
+use near_primitives::epoch_manager::AllEpochConfig;
 use near_primitives::hash::CryptoHash;
-#[cfg(test)]
-use near_primitives::shard_layout::account_id_to_shard_id;
-use near_primitives::shard_layout::ShardLayout;
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{
     AccountId, AccountInfo, Balance, BlockHeightDelta, EpochHeight, Gas, NumBlocks, NumSeats,
@@ -41,6 +50,7 @@ use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner
 use near_primitives::version::PROTOCOL_VERSION;
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::RosettaRpcConfig;
+use near_store::{ColumnTuning, DBCol};
 use near_telemetry::TelemetryConfig;
 
 /// Initial balance used in tests.
@@ -136,6 +146,9 @@ pub const MINIMUM_STAKE_DIVISOR: u64 = 10;
 /// Number of epochs before protocol upgrade.
 pub const PROTOCOL_UPGRADE_NUM_EPOCHS: EpochHeight = 2;
 
+/// Default estimate of the memory needed to keep up with one tracked shard.
+const DEFAULT_MEMORY_PER_TRACKED_SHARD_MB: u64 = 2048;
+
 pub const CONFIG_FILENAME: &str = "config.json";
 pub const GENESIS_CONFIG_FILENAME: &str = "genesis.json";
 pub const NODE_KEY_FILE: &str = "node_key.json";
@@ -295,7 +308,11 @@ fn default_state_sync_timeout() -> Duration {
 }
 
 fn default_header_sync_expected_height_per_second() -> u64 {
-    10
+    DEFAULT_HEADER_SYNC_EXPECTED_HEIGHT_PER_SECOND
+}
+
+fn default_header_sync_commit_batch_size() -> usize {
+    DEFAULT_HEADER_SYNC_COMMIT_BATCH_SIZE
 }
 
 fn default_sync_check_period() -> Duration {
@@ -322,10 +339,50 @@ fn default_view_client_throttle_period() -> Duration {
     Duration::from_secs(30)
 }
 
+fn default_state_sync_max_served_sync_hashes() -> usize {
+    DEFAULT_STATE_SYNC_MAX_SERVED_SYNC_HASHES
+}
+
+fn default_state_sync_served_sync_hashes_window() -> Duration {
+    DEFAULT_STATE_SYNC_SERVED_SYNC_HASHES_WINDOW
+}
+
 fn default_trie_viewer_state_size_limit() -> Option<u64> {
     Some(50_000)
 }
 
+fn default_max_block_serialized_bytes() -> usize {
+    DEFAULT_MAX_BLOCK_SERIALIZED_BYTES
+}
+
+fn default_max_chunk_transactions() -> usize {
+    DEFAULT_MAX_CHUNK_TRANSACTIONS
+}
+
+fn default_sync_stall_log_threshold() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_max_orphans_per_prev_hash() -> usize {
+    DEFAULT_MAX_ORPHANS_PER_PREV_HASH
+}
+
+fn default_receipt_backlog_warn_bytes() -> u64 {
+    DEFAULT_RECEIPT_BACKLOG_WARN_BYTES
+}
+
+fn default_catchup_saved_updates_memory_limit_bytes() -> u64 {
+    DEFAULT_CATCHUP_SAVED_UPDATES_MEMORY_LIMIT_BYTES
+}
+
+fn default_missing_chunks_processing_budget() -> Duration {
+    Duration::from_millis(200)
+}
+
+fn default_future_block_grace_period() -> Duration {
+    DEFAULT_FUTURE_BLOCK_GRACE_PERIOD
+}
+
 fn default_use_checkpoints_for_db_migration() -> bool {
     true
 }
@@ -334,6 +391,10 @@ fn default_enable_rocksdb_statistics() -> bool {
     false
 }
 
+fn default_memory_per_tracked_shard_mb() -> u64 {
+    DEFAULT_MEMORY_PER_TRACKED_SHARD_MB
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -376,6 +437,9 @@ pub struct Consensus {
     /// Expected increase of header head weight per second during header sync
     #[serde(default = "default_header_sync_expected_height_per_second")]
     pub header_sync_expected_height_per_second: u64,
+    /// Number of headers validated during header sync before they are committed to the store
+    #[serde(default = "default_header_sync_commit_batch_size")]
+    pub header_sync_commit_batch_size: usize,
     /// How frequently we check whether we need to sync
     #[serde(default = "default_sync_check_period")]
     pub sync_check_period: Duration,
@@ -385,6 +449,51 @@ pub struct Consensus {
     /// Time between running doomslug timer.
     #[serde(default = "default_doomslug_step_period")]
     pub doomslug_step_period: Duration,
+    /// Blocks with larger serialized size are rejected.
+    #[serde(default = "default_max_block_serialized_bytes")]
+    pub max_block_serialized_bytes: usize,
+    /// Chunks with more transactions are rejected.
+    #[serde(default = "default_max_chunk_transactions")]
+    pub max_chunk_transactions: usize,
+    /// Run catchup before processing new blocks if split states fall behind.
+    #[serde(default)]
+    pub prioritize_split_state_catchup: bool,
+    /// Spill the store updates of blocks being caught up to the store once the ones held in
+    /// memory exceed this size in bytes.
+    #[serde(default = "default_catchup_saved_updates_memory_limit_bytes")]
+    pub catchup_saved_updates_memory_limit_bytes: u64,
+    /// Log sync diagnostics if the head doesn't advance for this long.
+    #[serde(default = "default_sync_stall_log_threshold")]
+    pub sync_stall_log_threshold: Duration,
+    /// Orphans are dropped if this many orphans with the same prev block are already stored.
+    #[serde(default = "default_max_orphans_per_prev_hash")]
+    pub max_orphans_per_prev_hash: usize,
+    /// Log a warning once the incoming receipts waiting for a new chunk of a shard exceed this
+    /// size in bytes.
+    #[serde(default = "default_receipt_backlog_warn_bytes")]
+    pub receipt_backlog_warn_bytes: u64,
+    /// Time budget for processing blocks whose missing chunks arrived in one go.
+    #[serde(default = "default_missing_chunks_processing_budget")]
+    pub missing_chunks_processing_budget: Duration,
+    /// How far beyond the acceptable time difference a block may be ahead of the local clock to
+    /// be delayed instead of rejected.
+    #[serde(default = "default_future_block_grace_period")]
+    pub future_block_grace_period: Duration,
+    /// Recompute outgoing receipts roots after applying chunks and compare them with the next
+    /// chunk headers.
+    #[serde(default)]
+    pub strict_receipt_root_checks: bool,
+    /// Reject blocks on a mismatch found by the strict receipts root checks.
+    #[serde(default)]
+    pub strict_receipt_root_checks_fail_on_mismatch: bool,
+    /// Quarantine and re-download the state of a shard when a chunk produced by this node doesn't
+    /// match its own state.
+    #[serde(default)]
+    pub quarantine_shard_on_state_mismatch: bool,
+    /// Verify the merkle proofs of the incoming receipts of every block. Enabled by default for
+    /// validators.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_incoming_receipts: Option<bool>,
 }
 
 impl Default for Consensus {
@@ -408,13 +517,177 @@ impl Default for Consensus {
             state_sync_timeout: default_state_sync_timeout(),
             header_sync_expected_height_per_second: default_header_sync_expected_height_per_second(
             ),
+            header_sync_commit_batch_size: default_header_sync_commit_batch_size(),
             sync_check_period: default_sync_check_period(),
             sync_step_period: default_sync_step_period(),
             doomslug_step_period: default_doomslug_step_period(),
+            max_block_serialized_bytes: default_max_block_serialized_bytes(),
+            max_chunk_transactions: default_max_chunk_transactions(),
+            prioritize_split_state_catchup: false,
+            catchup_saved_updates_memory_limit_bytes:
+                default_catchup_saved_updates_memory_limit_bytes(),
+            sync_stall_log_threshold: default_sync_stall_log_threshold(),
+            max_orphans_per_prev_hash: default_max_orphans_per_prev_hash(),
+            receipt_backlog_warn_bytes: default_receipt_backlog_warn_bytes(),
+            missing_chunks_processing_budget: default_missing_chunks_processing_budget(),
+            future_block_grace_period: default_future_block_grace_period(),
+            strict_receipt_root_checks: false,
+            strict_receipt_root_checks_fail_on_mismatch: false,
+            quarantine_shard_on_state_mismatch: false,
+            verify_incoming_receipts: None,
         }
     }
 }
 
+/// Preset of the RocksDB settings of the columns for the role of the node.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreProfile {
+    /// Applies chunks, so it reads and writes the state and the trie changes of every block,
+    /// while transactions and outcomes are only written.
+    Validator,
+    /// Serves queries of the state and of transactions, receipts and their outcomes.
+    Rpc,
+    /// Keeps the whole history, which makes caching the state less useful while blocks, chunks
+    /// and outcomes of any height are read.
+    Archival,
+}
+
+impl StoreProfile {
+    fn column_tuning(self, col: DBCol) -> ColumnTuning {
+        let mut tuning = ColumnTuning::default_for(col);
+        match (self, col) {
+            (StoreProfile::Validator, DBCol::ColState) => {
+                tuning.block_cache_size = ByteSize::gib(1);
+                tuning.write_buffer_size = ByteSize::mib(128);
+            }
+            (StoreProfile::Validator, DBCol::ColTrieChanges | DBCol::ColStateChanges) => {
+                tuning.write_buffer_size = ByteSize::mib(64);
+            }
+            (
+                StoreProfile::Validator,
+                DBCol::ColBlockHeader | DBCol::ColBlockInfo | DBCol::ColChunkExtra,
+            ) => {
+                tuning.block_cache_size = ByteSize::mib(64);
+            }
+            (
+                StoreProfile::Validator,
+                DBCol::ColTransactions
+                | DBCol::ColReceipts
+                | DBCol::ColTransactionResult
+                | DBCol::ColOutcomeIds
+                | DBCol::ColOutcomesByBlockShard
+                | DBCol::ColOutcomeParent,
+            ) => {
+                tuning.block_cache_size = ByteSize::mib(8);
+            }
+            (StoreProfile::Rpc, DBCol::ColState) => {
+                tuning.block_cache_size = ByteSize::gib(1);
+            }
+            (
+                StoreProfile::Rpc,
+                DBCol::ColBlock
+                | DBCol::ColChunks
+                | DBCol::ColTransactions
+                | DBCol::ColReceipts
+                | DBCol::ColTransactionResult
+                | DBCol::ColOutcomesByBlockShard
+                | DBCol::ColStateChanges,
+            ) => {
+                tuning.block_cache_size = ByteSize::mib(128);
+            }
+            (StoreProfile::Archival, DBCol::ColState) => {
+                tuning.block_cache_size = ByteSize::mib(256);
+            }
+            (
+                StoreProfile::Archival,
+                DBCol::ColBlock
+                | DBCol::ColChunks
+                | DBCol::ColTransactions
+                | DBCol::ColReceipts
+                | DBCol::ColTransactionResult
+                | DBCol::ColOutcomesByBlockShard,
+            ) => {
+                tuning.block_cache_size = ByteSize::mib(128);
+            }
+            _ => {}
+        }
+        tuning
+    }
+}
+
+/// Overrides of the RocksDB settings of a column, in bytes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnTuningConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_cache_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_buffer_size: Option<u64>,
+}
+
+/// RocksDB block cache and write buffer sizes of the columns of the store.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoreTuningConfig {
+    /// Preset for the role of the node. Without it the columns keep their default settings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<StoreProfile>,
+    /// Settings of single columns by column name, e.g. `ColState`, applied over the profile.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub columns: BTreeMap<String, ColumnTuningConfig>,
+}
+
+impl StoreTuningConfig {
+    /// Settings of all columns the store is opened with.
+    pub fn column_tuning(&self) -> anyhow::Result<HashMap<DBCol, ColumnTuning>> {
+        let mut column_tuning: HashMap<DBCol, ColumnTuning> = DBCol::iter()
+            .map(|col| {
+                let tuning = match self.profile {
+                    Some(profile) => profile.column_tuning(col),
+                    None => ColumnTuning::default_for(col),
+                };
+                (col, tuning)
+            })
+            .collect();
+        for (name, overrides) in self.columns.iter() {
+            let col = DBCol::iter()
+                .find(|col| format!("{:?}", col) == *name)
+                .ok_or_else(|| anyhow!("store_tuning has settings of unknown column {}", name))?;
+            let tuning = column_tuning.get_mut(&col).unwrap();
+            if let Some(block_cache_size) = overrides.block_cache_size {
+                tuning.block_cache_size = ByteSize::b(block_cache_size);
+            }
+            if let Some(write_buffer_size) = overrides.write_buffer_size {
+                tuning.write_buffer_size = ByteSize::b(write_buffer_size);
+            }
+        }
+        Ok(column_tuning)
+    }
+
+    /// Checks that the columns are known and that their block caches and write buffers fit in
+    /// `total_memory`.
+    pub fn validate(&self, total_memory: ByteSize) -> anyhow::Result<()> {
+        let column_tuning = self.column_tuning()?;
+        if let Some(col) =
+            DBCol::iter().find(|col| column_tuning[col].write_buffer_size.as_u64() == 0)
+        {
+            bail!("store_tuning write_buffer_size of column {:?} is zero", col);
+        }
+        let total = ByteSize::b(
+            column_tuning
+                .values()
+                .map(|tuning| tuning.block_cache_size.as_u64() + tuning.write_buffer_size.as_u64())
+                .sum(),
+        );
+        if total > total_memory {
+            bail!(
+                "store_tuning block caches and write buffers take {}, more than the {} of memory",
+                total,
+                total_memory
+            );
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
 pub struct Config {
@@ -437,11 +710,38 @@ pub struct Config {
     pub log_summary_style: LogSummaryStyle,
     #[serde(default = "default_gc_blocks_limit")]
     pub gc_blocks_limit: NumBlocks,
+    #[serde(default)]
+    pub gc_schedule: GcSchedule,
+    #[serde(default)]
+    pub record_discarded_forks: bool,
+    /// Validators keep a record of the approvals included in the blocks they produce.
+    #[serde(default)]
+    pub record_approval_audit: bool,
+    /// Archival nodes keep the partial encoded chunks of the blocks of this many latest epochs.
+    #[serde(default)]
+    pub retain_partial_chunk_parts_epochs: u64,
+    /// Allows `neard gc_to_height` to prune data beyond the regular garbage collection.
+    #[serde(default)]
+    pub allow_aggressive_gc: bool,
+    /// Skip the validation of chunk header signatures, chunk extras and transactions order,
+    /// except for state roots, to speed up replaying a trusted chain. Not allowed on mainnet and
+    /// testnet.
+    #[serde(default)]
+    pub trusted_chain_replay: bool,
     #[serde(default = "default_view_client_threads")]
     pub view_client_threads: usize,
     pub epoch_sync_enabled: bool,
     #[serde(default = "default_view_client_throttle_period")]
     pub view_client_throttle_period: Duration,
+    /// Size of the cache of headers, blocks and chunk extras shared by the view client threads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub view_client_shared_cache_mb: Option<u64>,
+    /// Maximum number of distinct sync hashes state is served for at once.
+    #[serde(default = "default_state_sync_max_served_sync_hashes")]
+    pub state_sync_max_served_sync_hashes: usize,
+    /// Time after which a sync hash state isn't requested for stops counting towards the limit.
+    #[serde(default = "default_state_sync_served_sync_hashes_window")]
+    pub state_sync_served_sync_hashes_window: Duration,
     #[serde(default = "default_trie_viewer_state_size_limit")]
     pub trie_viewer_state_size_limit: Option<u64>,
     /// If set, overrides value in genesis configuration.
@@ -458,6 +758,28 @@ pub struct Config {
     pub db_migration_snapshot_path: Option<PathBuf>,
     #[serde(default = "default_enable_rocksdb_statistics")]
     pub enable_rocksdb_statistics: bool,
+    /// RocksDB block cache and write buffer sizes of the columns, see `StoreTuningConfig`.
+    #[serde(default)]
+    pub store_tuning: StoreTuningConfig,
+    /// Restricts the signers whose transactions are accepted, see `TxRoutingPolicy`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_routing_policy: Option<TxRoutingPolicy>,
+    /// What to do with blocks from an epoch with a protocol version newer than the binary
+    /// supports, see `UnsupportedProtocolVersionPolicy`.
+    #[serde(default)]
+    pub on_unsupported_protocol_version: UnsupportedProtocolVersionPolicy,
+    /// Gas price parameters replacing the genesis ones from a given height on, see
+    /// `EconomicsOverrides`. Not allowed on mainnet and testnet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub economics_overrides: Option<EconomicsOverrides>,
+    /// Estimate of the memory needed to keep up with one tracked shard, checked against the
+    /// memory of the machine at startup.
+    #[serde(default = "default_memory_per_tracked_shard_mb")]
+    pub memory_per_tracked_shard_mb: u64,
+    /// Refuse to start if the machine doesn't have the resources to keep up with the tracked
+    /// shards instead of only warning about it.
+    #[serde(default)]
+    pub strict_resource_checks: bool,
 }
 
 impl Default for Config {
@@ -479,14 +801,29 @@ impl Default for Config {
             archive: false,
             log_summary_style: LogSummaryStyle::Colored,
             gc_blocks_limit: default_gc_blocks_limit(),
+            gc_schedule: GcSchedule::default(),
+            record_discarded_forks: false,
+            record_approval_audit: false,
+            retain_partial_chunk_parts_epochs: 0,
+            allow_aggressive_gc: false,
+            trusted_chain_replay: false,
             epoch_sync_enabled: true,
             view_client_threads: default_view_client_threads(),
             view_client_throttle_period: default_view_client_throttle_period(),
+            view_client_shared_cache_mb: None,
+            state_sync_max_served_sync_hashes: default_state_sync_max_served_sync_hashes(),
+            state_sync_served_sync_hashes_window: default_state_sync_served_sync_hashes_window(),
             trie_viewer_state_size_limit: default_trie_viewer_state_size_limit(),
             max_gas_burnt_view: None,
             db_migration_snapshot_path: None,
             use_db_migration_snapshot: true,
             enable_rocksdb_statistics: false,
+            store_tuning: StoreTuningConfig::default(),
+            tx_routing_policy: None,
+            on_unsupported_protocol_version: UnsupportedProtocolVersionPolicy::default(),
+            economics_overrides: None,
+            memory_per_tracked_shard_mb: default_memory_per_tracked_shard_mb(),
+            strict_resource_checks: false,
         }
     }
 }
@@ -523,6 +860,19 @@ impl Config {
         None
     }
 
+    /// Number of shards of the layout the node keeps up with: all of them if any shard is tracked,
+    /// otherwise the shards of the tracked accounts, see `TrackedConfig::from_config`.
+    pub fn num_tracked_shards(&self, shard_layout: &ShardLayout) -> usize {
+        if !self.tracked_shards.is_empty() {
+            return shard_layout.num_shards() as usize;
+        }
+        self.tracked_accounts
+            .iter()
+            .map(|account_id| account_id_to_shard_id(account_id, shard_layout))
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
     #[allow(unused_variables)]
     pub fn set_rpc_addr(&mut self, addr: String) {
         #[cfg(feature = "json_rpc")]
@@ -673,6 +1023,7 @@ impl NearConfig {
                 header_sync_expected_height_per_second: config
                     .consensus
                     .header_sync_expected_height_per_second,
+                header_sync_commit_batch_size: config.consensus.header_sync_commit_batch_size,
                 state_sync_timeout: config.consensus.state_sync_timeout,
                 min_num_peers: config.consensus.min_num_peers,
                 log_summary_period: Duration::from_secs(10),
@@ -693,11 +1044,45 @@ impl NearConfig {
                 archive: config.archive,
                 log_summary_style: config.log_summary_style,
                 gc_blocks_limit: config.gc_blocks_limit,
+                gc_schedule: config.gc_schedule,
+                record_discarded_forks: config.record_discarded_forks,
+                record_approval_audit: config.record_approval_audit,
+                retain_partial_chunk_parts_epochs: config.retain_partial_chunk_parts_epochs,
+                allow_aggressive_gc: config.allow_aggressive_gc,
+                trusted_chain_replay: config.trusted_chain_replay,
                 view_client_threads: config.view_client_threads,
                 epoch_sync_enabled: config.epoch_sync_enabled,
                 view_client_throttle_period: config.view_client_throttle_period,
+                view_client_shared_cache_mb: config.view_client_shared_cache_mb,
+                state_sync_max_served_sync_hashes: config.state_sync_max_served_sync_hashes,
+                state_sync_served_sync_hashes_window: config.state_sync_served_sync_hashes_window,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
+                max_block_serialized_bytes: config.consensus.max_block_serialized_bytes,
+                max_chunk_transactions: config.consensus.max_chunk_transactions,
+                prioritize_split_state_catchup: config.consensus.prioritize_split_state_catchup,
+                catchup_saved_updates_memory_limit_bytes: config
+                    .consensus
+                    .catchup_saved_updates_memory_limit_bytes,
+                sync_stall_log_threshold: config.consensus.sync_stall_log_threshold,
+                max_orphans_per_prev_hash: config.consensus.max_orphans_per_prev_hash,
+                receipt_backlog_warn_bytes: config.consensus.receipt_backlog_warn_bytes,
+                missing_chunks_processing_budget: config.consensus.missing_chunks_processing_budget,
+                future_block_grace_period: config.consensus.future_block_grace_period,
+                strict_receipt_root_checks: config.consensus.strict_receipt_root_checks,
+                strict_receipt_root_checks_fail_on_mismatch: config
+                    .consensus
+                    .strict_receipt_root_checks_fail_on_mismatch,
+                quarantine_shard_on_state_mismatch: config
+                    .consensus
+                    .quarantine_shard_on_state_mismatch,
+                verify_incoming_receipts: config
+                    .consensus
+                    .verify_incoming_receipts
+                    .unwrap_or(validator_signer.is_some()),
+                tx_routing_policy: config.tx_routing_policy,
+                on_unsupported_protocol_version: config.on_unsupported_protocol_version,
+                economics_overrides: config.economics_overrides,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
@@ -1650,20 +2035,125 @@ pub fn load_config(
         format!("Failed reading node key file from {}", node_key_path.display())
     })?;
 
-    let genesis_records_file = config.genesis_records_file.clone();
-    Ok(NearConfig::new(
-        config,
-        match genesis_records_file {
-            Some(genesis_records_file) => Genesis::from_files(
-                &genesis_file,
-                &dir.join(genesis_records_file),
-                genesis_validation,
-            ),
-            None => Genesis::from_file(&genesis_file, genesis_validation),
-        },
-        network_signer.into(),
-        validator_signer,
-    ))
+    let genesis = match &config.genesis_records_file {
+        Some(genesis_records_file) => {
+            Genesis::from_files(&genesis_file, &dir.join(genesis_records_file), genesis_validation)
+        }
+        None => Genesis::from_file(&genesis_file, genesis_validation),
+    };
+    validate_economics_overrides(&config, &genesis.config.chain_id)?;
+    validate_store_tuning(&config)?;
+    validate_tracked_shards_resources(&config, &genesis)?;
+    Ok(NearConfig::new(config, genesis, network_signer.into(), validator_signer))
+}
+
+/// Economics overrides change how blocks are validated, so they're refused on public networks.
+fn validate_economics_overrides(config: &Config, chain_id: &str) -> anyhow::Result<()> {
+    if let Some(overrides) = &config.economics_overrides {
+        if is_public_chain(chain_id) {
+            bail!("economics_overrides are not allowed on chain {}", chain_id);
+        }
+        if overrides.min_gas_price > overrides.max_gas_price {
+            bail!(
+                "economics_overrides min_gas_price {} is above max_gas_price {}",
+                overrides.min_gas_price,
+                overrides.max_gas_price
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Custom column settings are checked against the memory of the machine, the defaults are kept
+/// as they are.
+fn validate_store_tuning(config: &Config) -> anyhow::Result<()> {
+    if config.store_tuning == StoreTuningConfig::default() {
+        return Ok(());
+    }
+    let mut system = System::new();
+    system.refresh_memory();
+    config.store_tuning.validate(ByteSize::kib(system.get_total_memory()))
+}
+
+/// Resources of the machine the tracked shards are checked against.
+#[derive(Clone, Debug)]
+pub struct MachineResources {
+    /// Number of threads chunks are applied on.
+    pub apply_chunks_threads: usize,
+    /// Number of cores available to the node.
+    pub available_parallelism: usize,
+    pub total_memory: ByteSize,
+}
+
+impl MachineResources {
+    pub fn detect() -> Self {
+        let mut system = System::new();
+        system.refresh_memory();
+        MachineResources {
+            // Chunks of a block are applied in parallel on the global rayon pool.
+            apply_chunks_threads: rayon::current_num_threads(),
+            available_parallelism: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            total_memory: ByteSize::kib(system.get_total_memory()),
+        }
+    }
+}
+
+/// Describes why the machine can't keep up with `num_tracked_shards` shards, `None` if it can.
+/// Every tracked shard needs a core to apply its chunks on in parallel with the other shards and
+/// `memory_per_shard` of memory.
+fn tracked_shards_over_provisioning(
+    num_tracked_shards: usize,
+    memory_per_shard: ByteSize,
+    resources: &MachineResources,
+) -> Option<String> {
+    let parallelism = resources.apply_chunks_threads.min(resources.available_parallelism);
+    if num_tracked_shards > parallelism {
+        return Some(format!(
+            "{} tracked shards can't be applied in parallel on {} apply threads and {} cores",
+            num_tracked_shards, resources.apply_chunks_threads, resources.available_parallelism
+        ));
+    }
+    let needed_memory = ByteSize::b(memory_per_shard.as_u64() * num_tracked_shards as u64);
+    if needed_memory > resources.total_memory {
+        return Some(format!(
+            "{} tracked shards need an estimated {} of memory but the machine has {}",
+            num_tracked_shards, needed_memory, resources.total_memory
+        ));
+    }
+    None
+}
+
+/// The shard layout of the current protocol version. It differs from the genesis layout on
+/// mainnet and testnet, which started with a single shard.
+fn current_shard_layout(genesis_config: &GenesisConfig) -> ShardLayout {
+    AllEpochConfig::from(genesis_config).for_protocol_version(PROTOCOL_VERSION).shard_layout.clone()
+}
+
+/// Nodes tracking more shards than the machine can handle fall behind the chain, so it's pointed
+/// out at startup, or refused with `strict_resource_checks`.
+fn validate_tracked_shards_resources(config: &Config, genesis: &Genesis) -> anyhow::Result<()> {
+    let num_tracked_shards = config.num_tracked_shards(&current_shard_layout(&genesis.config));
+    let resources = MachineResources::detect();
+    let memory_per_shard = ByteSize::mib(config.memory_per_tracked_shard_mb);
+    let problem =
+        match tracked_shards_over_provisioning(num_tracked_shards, memory_per_shard, &resources) {
+            Some(problem) => problem,
+            None => return Ok(()),
+        };
+    if config.strict_resource_checks {
+        bail!("Tracked shards are over-provisioned: {}", problem);
+    }
+    warn!(
+        target: "config",
+        num_tracked_shards,
+        apply_chunks_threads = resources.apply_chunks_threads,
+        available_parallelism = resources.available_parallelism,
+        total_memory = %resources.total_memory,
+        memory_per_shard = %memory_per_shard,
+        "Tracked shards are over-provisioned, the node may fall behind: {}",
+        problem
+    );
+    Ok(())
 }
 
 pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
@@ -1737,3 +2227,131 @@ fn test_init_config_localnet() {
         2
     );
 }
+
+#[test]
+fn test_economics_overrides_validation() {
+    let mut config = Config::default();
+    assert!(validate_economics_overrides(&config, "mainnet").is_ok());
+    config.economics_overrides = Some(EconomicsOverrides {
+        activation_height: 100,
+        min_gas_price: 100,
+        max_gas_price: 1000,
+        gas_price_adjustment_rate: Rational::new(1, 100),
+    });
+    assert!(validate_economics_overrides(&config, "localnet").is_ok());
+    assert!(validate_economics_overrides(&config, "mainnet").is_err());
+    assert!(validate_economics_overrides(&config, "testnet").is_err());
+    config.economics_overrides.as_mut().unwrap().min_gas_price = 10_000;
+    assert!(validate_economics_overrides(&config, "localnet").is_err());
+}
+
+#[test]
+fn test_store_tuning_profiles() {
+    let default_tuning = StoreTuningConfig::default().column_tuning().unwrap();
+    assert_eq!(default_tuning.len(), DBCol::iter().count());
+    for col in DBCol::iter() {
+        assert_eq!(default_tuning[&col], ColumnTuning::default_for(col));
+    }
+
+    let profile_tuning = |profile| {
+        StoreTuningConfig { profile: Some(profile), ..Default::default() }.column_tuning().unwrap()
+    };
+    let validator = profile_tuning(StoreProfile::Validator);
+    assert_eq!(
+        validator[&DBCol::ColState],
+        ColumnTuning { block_cache_size: ByteSize::gib(1), write_buffer_size: ByteSize::mib(128) }
+    );
+    assert_eq!(validator[&DBCol::ColTransactionResult].block_cache_size, ByteSize::mib(8));
+    assert_eq!(validator[&DBCol::ColPeers], ColumnTuning::default_for(DBCol::ColPeers));
+
+    let rpc = profile_tuning(StoreProfile::Rpc);
+    assert_eq!(rpc[&DBCol::ColState].block_cache_size, ByteSize::gib(1));
+    assert_eq!(
+        rpc[&DBCol::ColState].write_buffer_size,
+        ColumnTuning::default_for(DBCol::ColState).write_buffer_size
+    );
+    assert_eq!(rpc[&DBCol::ColTransactionResult].block_cache_size, ByteSize::mib(128));
+
+    let archival = profile_tuning(StoreProfile::Archival);
+    assert_eq!(archival[&DBCol::ColState].block_cache_size, ByteSize::mib(256));
+    assert_eq!(archival[&DBCol::ColChunks].block_cache_size, ByteSize::mib(128));
+
+    // Column settings are applied over the profile.
+    let config: StoreTuningConfig = serde_json::from_str(
+        r#"{"profile": "Rpc", "columns": {"ColState": {"write_buffer_size": 1048576}}}"#,
+    )
+    .unwrap();
+    let tuning = config.column_tuning().unwrap();
+    assert_eq!(
+        tuning[&DBCol::ColState],
+        ColumnTuning { block_cache_size: ByteSize::gib(1), write_buffer_size: ByteSize::mib(1) }
+    );
+}
+
+#[test]
+fn test_tracked_shards_resources() {
+    let resources = MachineResources {
+        apply_chunks_threads: 8,
+        available_parallelism: 4,
+        total_memory: ByteSize::gib(16),
+    };
+    let memory_per_shard = ByteSize::gib(2);
+    assert_eq!(tracked_shards_over_provisioning(4, memory_per_shard, &resources), None);
+    // Limited by the number of cores rather than apply threads.
+    assert!(tracked_shards_over_provisioning(5, memory_per_shard, &resources).is_some());
+    let resources = MachineResources { apply_chunks_threads: 2, ..resources };
+    assert!(tracked_shards_over_provisioning(3, memory_per_shard, &resources).is_some());
+    let resources = MachineResources { apply_chunks_threads: 8, ..resources };
+    assert!(tracked_shards_over_provisioning(4, ByteSize::gib(5), &resources).is_some());
+
+    let shard_layout = ShardLayout::v1(
+        vec!["test0".parse().unwrap()],
+        vec!["abc".parse().unwrap(), "foo".parse().unwrap()],
+        None,
+        0,
+    );
+    let mut config = Config::default();
+    assert_eq!(config.num_tracked_shards(&shard_layout), 0);
+    config.tracked_accounts =
+        vec!["aaa".parse().unwrap(), "aab".parse().unwrap(), "zzz".parse().unwrap()];
+    assert_eq!(config.num_tracked_shards(&shard_layout), 2);
+    config.tracked_shards = vec![0];
+    assert_eq!(config.num_tracked_shards(&shard_layout), shard_layout.num_shards() as usize);
+
+    // A chain started with a single shard before simple nightshade runs with more shards now.
+    let genesis_config = GenesisConfig {
+        protocol_version: near_primitives::version::ProtocolFeature::SimpleNightshade
+            .protocol_version()
+            - 1,
+        avg_hidden_validator_seats_per_shard: vec![0],
+        shard_layout: ShardLayout::v0_single_shard(),
+        simple_nightshade_shard_layout: Some(ShardLayout::v1_test()),
+        ..Default::default()
+    };
+    assert_eq!(current_shard_layout(&genesis_config), ShardLayout::v1_test());
+}
+
+#[test]
+fn test_store_tuning_validation() {
+    let config = StoreTuningConfig { profile: Some(StoreProfile::Validator), ..Default::default() };
+    assert!(config.validate(ByteSize::gib(64)).is_ok());
+    assert!(config.validate(ByteSize::gib(1)).is_err());
+
+    let mut config = StoreTuningConfig::default();
+    config.columns.insert(
+        "ColBlock".to_string(),
+        ColumnTuningConfig {
+            block_cache_size: Some(ByteSize::gib(100).as_u64()),
+            ..Default::default()
+        },
+    );
+    assert!(config.validate(ByteSize::gib(64)).is_err());
+    config.columns.get_mut("ColBlock").unwrap().block_cache_size = None;
+    assert!(config.validate(ByteSize::gib(64)).is_ok());
+    config.columns.get_mut("ColBlock").unwrap().write_buffer_size = Some(0);
+    assert!(config.validate(ByteSize::gib(64)).is_err());
+
+    let mut config = StoreTuningConfig::default();
+    config.columns.insert("ColUnknown".to_string(), ColumnTuningConfig::default());
+    assert!(config.column_tuning().is_err());
+}
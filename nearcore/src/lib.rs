@@ -5,11 +5,16 @@ use crate::migrations::{
 };
 pub use crate::runtime::NightshadeRuntime;
 pub use crate::shard_tracker::TrackedConfig;
-use actix::{Actor, Addr, Arbiter};
+use actix::{Actor, Addr, Arbiter, MailboxError};
 use actix_rt::ArbiterHandle;
 use actix_web;
 use anyhow::Context;
-use near_chain::ChainGenesis;
+use near_chain::chain::{GcPlan, GcReport, PrepareShutdown, RepairReport, ShutdownReport};
+use near_chain::migrations::{
+    get_chain_schema_version, migrate_chain_schema, CHAIN_SCHEMA_VERSION,
+};
+use near_chain::shared_chain_cache::SharedChainCache;
+use near_chain::{Chain, ChainGenesis, ChainStore, DoomslugThresholdMode};
 #[cfg(feature = "test_features")]
 use near_client::AdversarialControls;
 use near_client::{start_client, start_view_client, ClientActor, ViewClientActor};
@@ -17,6 +22,7 @@ use near_network::routing::start_routing_table_actor;
 use near_network::test_utils::NetworkRecipient;
 use near_network::PeerManagerActor;
 use near_primitives::network::PeerId;
+use near_primitives::types::{BlockHeight, NumBlocks};
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::start_rosetta_rpc;
 #[cfg(feature = "performance_stats")]
@@ -34,6 +40,7 @@ use near_telemetry::TelemetryActor;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::oneshot;
 use tracing::{error, info, trace};
 
@@ -114,21 +121,11 @@ fn create_db_checkpoint(path: &Path, near_config: &NearConfig) -> Result<PathBuf
     Ok(checkpoint_path)
 }
 
-/// Function checks current version of the database and applies migrations to the database.
-pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
-    let db_version = get_store_version(path);
-    if db_version > near_primitives::version::DB_VERSION {
-        error!(target: "near", "DB version {} is created by a newer version of neard, please update neard or delete data", db_version);
-        std::process::exit(1);
-    }
-
-    if db_version == near_primitives::version::DB_VERSION {
-        return;
-    }
-
-    // Before starting a DB migration, create a consistent snapshot of the database. If a migration
-    // fails, it can be used to quickly restore the database to its original state.
-    let checkpoint_path = if near_config.config.use_db_migration_snapshot {
+/// Before starting a DB migration, creates a consistent snapshot of the database if it is enabled
+/// in the config. If a migration fails, the snapshot can be used to quickly restore the database
+/// to its original state.
+fn create_migration_snapshot(path: &Path, near_config: &NearConfig) -> Option<PathBuf> {
+    if near_config.config.use_db_migration_snapshot {
         match create_db_checkpoint(path, near_config) {
             Ok(checkpoint_path) => {
                 info!(target: "near", "Created a DB checkpoint before a DB migration: '{}'. Please recover from this checkpoint if the migration gets interrupted.", checkpoint_path.display());
@@ -150,7 +147,43 @@ pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
         }
     } else {
         None
-    };
+    }
+}
+
+/// Removes the snapshot after a successful DB migration to avoid it taking up precious disk space.
+fn remove_migration_snapshot(checkpoint_path: Option<PathBuf>) {
+    if let Some(checkpoint_path) = checkpoint_path {
+        info!(target: "near", "Deleting the database migration snapshot at '{}'", checkpoint_path.display());
+        match std::fs::remove_dir_all(&checkpoint_path) {
+            Ok(_) => {
+                info!(target: "near", "Deleted the database migration snapshot at '{}'", checkpoint_path.display());
+            }
+            Err(err) => {
+                error!(
+                    "Failed to delete the database migration snapshot at '{}'.\n\
+                    \tError: {:#?}.\n\
+                    \n\
+                    Please delete the database migration snapshot manually before the next start of the node.",
+                    checkpoint_path.display(),
+                    err);
+            }
+        }
+    }
+}
+
+/// Function checks current version of the database and applies migrations to the database.
+pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
+    let db_version = get_store_version(path);
+    if db_version > near_primitives::version::DB_VERSION {
+        error!(target: "near", "DB version {} is created by a newer version of neard, please update neard or delete data", db_version);
+        std::process::exit(1);
+    }
+
+    if db_version == near_primitives::version::DB_VERSION {
+        return;
+    }
+
+    let checkpoint_path = create_migration_snapshot(path, near_config);
 
     // Add migrations here based on `db_version`.
     if db_version <= 1 {
@@ -327,6 +360,72 @@ pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
         info!(target: "near", "Migrate DB from version 30 to 31");
         migrate_30_to_31(path, &near_config);
     }
+    if db_version <= 31 {
+        // version 31 => 32: add ColPrevBlockWithNewChunk
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        // Links are only saved for blocks processed after the upgrade, lookups fall back to
+        // walking the chain for older blocks.
+        info!(target: "near", "Migrate DB from version 31 to 32");
+        let store = create_store(path);
+        set_store_version(&store, 32);
+    }
+    if db_version <= 32 {
+        // version 32 => 33: add ColDiscardedForks
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 32 to 33");
+        let store = create_store(path);
+        set_store_version(&store, 33);
+    }
+    if db_version <= 33 {
+        // version 33 => 34: add ColOutcomeParent
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 33 to 34");
+        let store = create_store(path);
+        set_store_version(&store, 34);
+    }
+    if db_version <= 34 {
+        // version 34 => 35: add ColStateSyncFinalizeProgress
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 34 to 35");
+        let store = create_store(path);
+        set_store_version(&store, 35);
+    }
+    if db_version <= 35 {
+        // version 35 => 36: add ColStateSyncBoundaryBlocks
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 35 to 36");
+        let store = create_store(path);
+        set_store_version(&store, 36);
+    }
+    if db_version <= 36 {
+        // version 36 => 37: add ColEpochSummaryCounters and ColEpochSummaries
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 36 to 37");
+        let store = create_store(path);
+        set_store_version(&store, 37);
+    }
+    if db_version <= 37 {
+        // version 37 => 38: add ColOutcomesByBlockShard
+        // Does not need to do anything since open db with option `create_missing_column_families`.
+        // Outcomes of the blocks saved before are read through ColOutcomeIds.
+        info!(target: "near", "Migrate DB from version 37 to 38");
+        let store = create_store(path);
+        set_store_version(&store, 38);
+    }
+    if db_version <= 38 {
+        // version 38 => 39: add ColRejectedBlocks
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 38 to 39");
+        let store = create_store(path);
+        set_store_version(&store, 39);
+    }
+    if db_version <= 39 {
+        // version 39 => 40: add ColApprovalAudit
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        info!(target: "near", "Migrate DB from version 39 to 40");
+        let store = create_store(path);
+        set_store_version(&store, 40);
+    }
 
     #[cfg(feature = "nightly_protocol")]
     {
@@ -342,24 +441,33 @@ pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
         debug_assert_eq!(db_version, near_primitives::version::DB_VERSION);
     }
 
-    // DB migration was successful, remove the checkpoint to avoid it taking up precious disk space.
-    if let Some(checkpoint_path) = checkpoint_path {
-        info!(target: "near", "Deleting the database migration snapshot at '{}'", checkpoint_path.display());
-        match std::fs::remove_dir_all(&checkpoint_path) {
-            Ok(_) => {
-                info!(target: "near", "Deleted the database migration snapshot at '{}'", checkpoint_path.display());
-            }
-            Err(err) => {
-                error!(
-                    "Failed to delete the database migration snapshot at '{}'.\n\
-                    \tError: {:#?}.\n\
-                    \n\
-                    Please delete the database migration snapshot manually before the next start of the node.",
-                    checkpoint_path.display(),
-                    err);
-            }
-        }
+    // DB migration was successful, remove the checkpoint.
+    remove_migration_snapshot(checkpoint_path);
+}
+
+/// Applies the pending migrations of the chain data, see `near_chain::migrations`.
+pub fn apply_chain_migrations(path: &Path, near_config: &NearConfig) {
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let chain_store = ChainStore::new(create_store(path), genesis_height);
+    let chain_version =
+        get_chain_schema_version(&chain_store).expect("Failed to read the chain schema version");
+    // The snapshot can only be created once the database is closed.
+    drop(chain_store);
+    if chain_version == CHAIN_SCHEMA_VERSION {
+        return;
+    }
+    if chain_version > CHAIN_SCHEMA_VERSION {
+        error!(target: "near", "Chain schema version {} is created by a newer version of neard, please update neard or delete data", chain_version);
+        std::process::exit(1);
     }
+
+    let checkpoint_path = create_migration_snapshot(path, near_config);
+    let mut chain_store = ChainStore::new(create_store(path), genesis_height);
+    if let Err(err) = migrate_chain_schema(&mut chain_store) {
+        panic!("Failed to migrate chain data: {}", err);
+    }
+    drop(chain_store);
+    remove_migration_snapshot(checkpoint_path);
 }
 
 pub fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> Store {
@@ -367,12 +475,18 @@ pub fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> Stor
     let store_exists = store_path_exists(&path);
     if store_exists {
         apply_store_migrations(&path, near_config);
+        apply_chain_migrations(&path, near_config);
     }
     let store = create_store_with_config(
         &path,
         StoreConfig {
             read_only: false,
             enable_statistics: near_config.config.enable_rocksdb_statistics,
+            column_tuning: near_config
+                .config
+                .store_tuning
+                .column_tuning()
+                .expect("Invalid store_tuning config"),
         },
     );
     if !store_exists {
@@ -388,6 +502,17 @@ pub struct NearNode {
     pub rpc_servers: Vec<(&'static str, actix_web::dev::Server)>,
 }
 
+impl NearNode {
+    /// Stops block processing and waits up to `timeout` for the blocks in flight, so that the
+    /// node can be stopped without leaving the chain in the middle of an update.
+    pub async fn prepare_shutdown(
+        &self,
+        timeout: Duration,
+    ) -> Result<ShutdownReport, MailboxError> {
+        self.client.send(PrepareShutdown { timeout }).await
+    }
+}
+
 pub fn start_with_config(home_dir: &Path, config: NearConfig) -> Result<NearNode, anyhow::Error> {
     start_with_config_and_synchronization(home_dir, config, None)
 }
@@ -417,12 +542,18 @@ pub fn start_with_config_and_synchronization(
     #[cfg(feature = "test_features")]
     let adv = Arc::new(std::sync::RwLock::new(AdversarialControls::default()));
 
+    // Shared with the client, so that it invalidates the blocks it garbage collects.
+    let shared_chain_cache = config
+        .client_config
+        .view_client_shared_cache_mb
+        .map(|capacity_mb| Arc::new(SharedChainCache::with_capacity_mb(capacity_mb)));
     let view_client = start_view_client(
         config.validator_signer.as_ref().map(|signer| signer.validator_id().clone()),
         chain_genesis.clone(),
         runtime.clone(),
         network_adapter.clone(),
         config.client_config.clone(),
+        shared_chain_cache.clone(),
         #[cfg(feature = "test_features")]
         adv.clone(),
     );
@@ -435,6 +566,7 @@ pub fn start_with_config_and_synchronization(
         config.validator_signer,
         telemetry,
         shutdown_signal,
+        shared_chain_cache,
         #[cfg(feature = "test_features")]
         adv.clone(),
     );
@@ -567,10 +699,8 @@ pub fn recompress_storage(home_dir: &Path, opts: RecompressOpts) -> anyhow::Resu
     );
 
     info!(target: "recompress", src = %src_dir.display(), dest = %opts.dest_dir.display(), "Recompressing database");
-    let src_store = create_store_with_config(
-        &src_dir,
-        StoreConfig { read_only: true, enable_statistics: false },
-    );
+    let src_store =
+        create_store_with_config(&src_dir, StoreConfig { read_only: true, ..Default::default() });
 
     let final_head_height = if skip_columns.contains(&DBCol::ColPartialChunks) {
         let tip: Option<near_primitives::block::Tip> =
@@ -653,3 +783,109 @@ pub fn recompress_storage(home_dir: &Path, opts: RecompressOpts) -> anyhow::Resu
     info!(target: "recompress", dest_dir = ?opts.dest_dir, "Database recompressed");
     Ok(())
 }
+
+/// Adds the epoch manager data missing for the stored block headers at heights from
+/// `from_height` to `to_height`, or up to the header head if `to_height` isn't given. See
+/// `Chain::repair_epoch_data`.
+pub fn repair_epoch_data(
+    home_dir: &Path,
+    near_config: &NearConfig,
+    from_height: BlockHeight,
+    to_height: Option<BlockHeight>,
+) -> anyhow::Result<RepairReport> {
+    let store = init_and_migrate_store(home_dir, near_config);
+    let runtime = Arc::new(NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        near_config,
+        near_config.client_config.trie_viewer_state_size_limit,
+        near_config.client_config.max_gas_burnt_view,
+    ));
+    let mut chain = Chain::new(
+        runtime,
+        &ChainGenesis::from(&near_config.genesis),
+        DoomslugThresholdMode::TwoThirds,
+    )?;
+    let to_height = match to_height {
+        Some(to_height) => to_height,
+        None => chain.header_head()?.height,
+    };
+    info!(target: "near", "Repairing epoch data of headers at heights {}..={}", from_height, to_height);
+    Ok(chain.repair_epoch_data(from_height, to_height)?)
+}
+
+/// Reports what the next garbage collection run removing at most `gc_blocks_limit` blocks would
+/// delete, without modifying the database. The database is opened read-only and has to be
+/// migrated already. See `near_chain::chain::simulate_gc`.
+pub fn simulate_gc(
+    home_dir: &Path,
+    near_config: &NearConfig,
+    gc_blocks_limit: NumBlocks,
+) -> anyhow::Result<GcPlan> {
+    let path = get_store_path(home_dir);
+    anyhow::ensure!(store_path_exists(&path), "{}: storage doesn’t exist", path.display());
+    let db_version = get_store_version(&path);
+    anyhow::ensure!(
+        db_version == near_primitives::version::DB_VERSION,
+        "{}: expected DB version {} but got {}, run the node to migrate it first",
+        path.display(),
+        near_primitives::version::DB_VERSION,
+        db_version
+    );
+    let store =
+        create_store_with_config(&path, StoreConfig { read_only: true, ..Default::default() });
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let chain_version = get_chain_schema_version(&chain_store)?;
+    anyhow::ensure!(
+        chain_version == CHAIN_SCHEMA_VERSION,
+        "{}: expected chain schema version {} but got {}, run the node to migrate it first",
+        path.display(),
+        CHAIN_SCHEMA_VERSION,
+        chain_version
+    );
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        near_config,
+        near_config.client_config.trie_viewer_state_size_limit,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    Ok(near_chain::chain::simulate_gc(&mut chain_store, &runtime, gc_blocks_limit)?)
+}
+
+/// Prunes the data of the blocks up to `target_tail`, removing at most `gc_blocks_limit` blocks
+/// per database commit batch until the target is reached or nothing more can be removed.
+/// Requires `allow_aggressive_gc` in the config. See `Chain::garbage_collect_to_height`.
+pub fn gc_to_height(
+    home_dir: &Path,
+    near_config: &NearConfig,
+    target_tail: BlockHeight,
+    gc_blocks_limit: NumBlocks,
+) -> anyhow::Result<GcReport> {
+    let store = init_and_migrate_store(home_dir, near_config);
+    let runtime = Arc::new(NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        near_config,
+        near_config.client_config.trie_viewer_state_size_limit,
+        near_config.client_config.max_gas_burnt_view,
+    ));
+    let mut chain = Chain::new(
+        runtime,
+        &ChainGenesis::from(&near_config.genesis),
+        DoomslugThresholdMode::TwoThirds,
+    )?;
+    chain.allow_aggressive_gc = near_config.client_config.allow_aggressive_gc;
+    let tries = chain.runtime_adapter.get_tries();
+    info!(target: "near", "Pruning blocks from tail {} to {}", chain.tail()?, target_tail);
+    let mut blocks_removed = 0;
+    loop {
+        let report =
+            chain.garbage_collect_to_height(target_tail, tries.clone(), gc_blocks_limit)?;
+        blocks_removed += report.blocks_removed;
+        if report.reached_target || report.blocks_removed == 0 {
+            return Ok(GcReport { blocks_removed, ..report });
+        }
+        info!(target: "near", "Pruned {} blocks, tail is at {}", blocks_removed, report.tail);
+    }
+}
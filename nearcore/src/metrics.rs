@@ -1,4 +1,4 @@
-use near_metrics::{try_create_histogram_vec, HistogramVec};
+use near_metrics::{try_create_histogram_vec, try_create_int_counter, HistogramVec, IntCounter};
 use once_cell::sync::Lazy;
 
 pub static APPLY_CHUNK_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
@@ -24,3 +24,19 @@ pub static SECONDS_PER_PETAGAS: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+pub static PREFETCHED_STATE_KEYS: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_prefetched_state_keys_total",
+        "Number of accounts prefetched before applying a chunk",
+    )
+    .unwrap()
+});
+
+pub static PREFETCHED_STATE_KEYS_READ: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_prefetched_state_keys_read_total",
+        "Number of prefetched accounts later read by the application of the chunk",
+    )
+    .unwrap()
+});
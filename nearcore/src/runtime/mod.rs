@@ -1,8 +1,9 @@
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 
 use borsh::ser::BorshSerialize;
@@ -11,7 +12,8 @@ use tracing::{debug, error, info, warn};
 
 use near_chain::chain::NUM_EPOCHS_TO_KEEP_STORE_DATA;
 use near_chain::types::{
-    ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, ValidatorInfoIdentifier,
+    ApplySplitStateResult, ApplyTransactionResult, BlockApprovals, BlockHeaderInfo,
+    ShardCareMatrix, StatePartStream, ValidatorInfoIdentifier,
 };
 use near_chain::{BlockHeader, Doomslug, DoomslugThresholdMode, Error, ErrorKind, RuntimeAdapter};
 use near_chain_configs::{Genesis, GenesisConfig, ProtocolConfig};
@@ -20,7 +22,7 @@ use near_epoch_manager::EpochManager;
 use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::block::{Approval, ApprovalInner};
-use near_primitives::challenge::ChallengesResult;
+use near_primitives::challenge::{ChallengesResult, PartialState};
 use near_primitives::contract::ContractCode;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
@@ -44,9 +46,9 @@ use near_primitives::views::{
     QueryResponseKind, ViewApplyState, ViewStateResult,
 };
 use near_store::{
-    get_genesis_hash, get_genesis_state_roots, set_genesis_hash, set_genesis_state_roots,
-    ApplyStatePartResult, ColState, PartialStorage, ShardTries, Store, StoreCompiledContractCache,
-    StoreUpdate, Trie, WrappedTrieChanges,
+    get_account, get_genesis_hash, get_genesis_state_roots, set_genesis_hash,
+    set_genesis_state_roots, ApplyStatePartResult, ColState, PartialStorage, ShardTries, Store,
+    StoreCompiledContractCache, StoreUpdate, Trie, WrappedTrieChanges,
 };
 use near_vm_runner::precompile_contract;
 use node_runtime::adapter::ViewRuntimeAdapter;
@@ -76,6 +78,8 @@ pub mod errors;
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 const STATE_DUMP_FILE: &str = "state_dump";
 const GENESIS_ROOTS_FILE: &str = "genesis_roots";
+/// Number of threads reading prefetched accounts.
+const NUM_PREFETCH_THREADS: usize = 2;
 
 /// Wrapper type for epoch manager to get avoid implementing trait for foreign types.
 pub struct SafeEpochManager(pub Arc<RwLock<EpochManager>>);
@@ -142,6 +146,12 @@ pub struct NightshadeRuntime {
     shard_tracker: ShardTracker,
     genesis_state_roots: Vec<StateRoot>,
     migration_data: Arc<MigrationData>,
+    /// Accounts prefetched for the next application of each shard, with the state root they were
+    /// prefetched at.
+    prefetched_accounts: Arc<Mutex<HashMap<ShardUId, (StateRoot, HashSet<AccountId>)>>>,
+    /// Pool reading the prefetched accounts, kept small so that it doesn't compete with the
+    /// application of the chunks.
+    prefetch_pool: rayon::ThreadPool,
 }
 
 impl NightshadeRuntime {
@@ -210,6 +220,12 @@ impl NightshadeRuntime {
             shard_tracker,
             genesis_state_roots: state_roots,
             migration_data: Arc::new(load_migration_data(&genesis.config.chain_id)),
+            prefetched_accounts: Arc::new(Mutex::new(HashMap::new())),
+            prefetch_pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(NUM_PREFETCH_THREADS)
+                .thread_name(|i| format!("state_prefetch_{}", i))
+                .build()
+                .unwrap(),
         }
     }
 
@@ -586,6 +602,19 @@ impl NightshadeRuntime {
             })?;
 
         let shard_uid = self.get_shard_uid_from_prev_hash(shard_id, prev_block_hash)?;
+        let prefetched =
+            self.prefetched_accounts.lock().expect(POISONED_LOCK_ERR).remove(&shard_uid);
+        if let Some((prefetched_state_root, prefetched_accounts)) = prefetched {
+            if prefetched_state_root == state_root {
+                let read_accounts = receipts
+                    .iter()
+                    .map(|receipt| &receipt.receiver_id)
+                    .chain(transactions.iter().map(|tx| &tx.transaction.signer_id))
+                    .filter(|account_id| prefetched_accounts.contains(*account_id))
+                    .collect::<HashSet<_>>();
+                metrics::PREFETCHED_STATE_KEYS_READ.inc_by(read_accounts.len() as u64);
+            }
+        }
 
         let result = ApplyTransactionResult {
             trie_changes: WrappedTrieChanges::new(
@@ -636,6 +665,115 @@ impl NightshadeRuntime {
             });
         Ok(())
     }
+
+    /// Returns StorageError when storage is inconsistent.
+    /// `block_hash` is a block whose `prev_state_root` is `state_root`
+    fn get_trie_nodes_for_part(
+        &self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        state_root: &StateRoot,
+        part_id: PartId,
+    ) -> Result<PartialState, Error> {
+        let epoch_id = self.get_epoch_id(block_hash)?;
+        let shard_uid = self.get_shard_uid_from_epoch_id(shard_id, &epoch_id)?;
+        let trie = self.tries.get_view_trie_for_shard(shard_uid);
+        match trie.get_trie_nodes_for_part(part_id, state_root) {
+            Ok(partial_state) => Ok(partial_state),
+            Err(e) => {
+                error!(target: "runtime",
+                       "Can't get_trie_nodes_for_part for block {:?} state root {:?}, part_id {:?}, num_parts {:?}, {:?}",
+                       block_hash, state_root, part_id.idx, part_id.total, e
+                );
+                Err(e.to_string().into())
+            }
+        }
+    }
+
+    /// Checks the approvals of a block against its approvers, see
+    /// `EpochManager::get_all_block_approvers_ordered`.
+    fn verify_approval_signatures(
+        approvers: &[(ApprovalStake, bool)],
+        prev_block_hash: &CryptoHash,
+        prev_block_height: BlockHeight,
+        block_height: BlockHeight,
+        approvals: &[Option<Signature>],
+    ) -> bool {
+        if approvals.len() > approvers.len() {
+            return false;
+        }
+
+        let message_to_sign = Approval::get_data_for_sig(
+            &if prev_block_height + 1 == block_height {
+                ApprovalInner::Endorsement(*prev_block_hash)
+            } else {
+                ApprovalInner::Skip(prev_block_height)
+            },
+            block_height,
+        );
+
+        for ((validator, is_slashed), may_be_signature) in approvers.iter().zip(approvals.iter()) {
+            if let Some(signature) = may_be_signature {
+                if *is_slashed || !signature.verify(message_to_sign.as_ref(), &validator.public_key)
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Reader of the borsh serialization of a `PartialState`, which reads the trie nodes from the
+/// store one at a time and drops them once they are read.
+struct PartialStateReader {
+    tries: ShardTries,
+    shard_uid: ShardUId,
+    /// Hashes of the nodes which are not read yet.
+    nodes: std::vec::IntoIter<(CryptoHash, usize)>,
+    /// Serialized bytes which are not read yet.
+    pending: io::Cursor<Vec<u8>>,
+}
+
+impl PartialStateReader {
+    /// `nodes` are the hashes and sizes of the trie nodes of the part, see
+    /// `Trie::get_trie_node_hashes_for_part`.
+    fn stream(
+        tries: ShardTries,
+        shard_uid: ShardUId,
+        nodes: Vec<(CryptoHash, usize)>,
+    ) -> StatePartStream {
+        let len = 4 + nodes.iter().map(|(_, size)| 4 + size).sum::<usize>();
+        let pending = io::Cursor::new((nodes.len() as u32).to_le_bytes().to_vec());
+        StatePartStream::new(
+            len,
+            Box::new(PartialStateReader { tries, shard_uid, nodes: nodes.into_iter(), pending }),
+        )
+    }
+}
+
+impl Read for PartialStateReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.pending.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.nodes.next() {
+                Some((node_hash, _)) => {
+                    let node = self
+                        .tries
+                        .retrieve_raw_bytes(self.shard_uid, &node_hash)
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+                    let mut pending = Vec::with_capacity(4 + node.len());
+                    pending.extend_from_slice(&(node.len() as u32).to_le_bytes());
+                    pending.extend_from_slice(&node);
+                    self.pending = io::Cursor::new(pending);
+                }
+                None => return Ok(0),
+            }
+        }
+    }
 }
 
 fn format_total_gas_burnt(gas: Gas) -> String {
@@ -693,6 +831,40 @@ impl RuntimeAdapter for NightshadeRuntime {
         Ok(self.tries.get_trie_for_shard(shard_uid))
     }
 
+    fn prefetch_state_keys(
+        &self,
+        shard_uid: ShardUId,
+        state_root: StateRoot,
+        accounts: Vec<AccountId>,
+    ) -> Result<(), Error> {
+        metrics::PREFETCHED_STATE_KEYS.inc_by(accounts.len() as u64);
+        self.prefetched_accounts
+            .lock()
+            .expect(POISONED_LOCK_ERR)
+            .insert(shard_uid, (state_root, accounts.iter().cloned().collect()));
+        let tries = self.tries.clone();
+        let prefetched_accounts = self.prefetched_accounts.clone();
+        self.prefetch_pool.spawn(move || {
+            // The chunk was applied or a newer prefetch was requested while this one was queued.
+            let is_current = prefetched_accounts
+                .lock()
+                .expect(POISONED_LOCK_ERR)
+                .get(&shard_uid)
+                .map_or(false, |(prefetched_state_root, _)| *prefetched_state_root == state_root);
+            if !is_current {
+                return;
+            }
+            // Reading the accounts is enough to put their trie nodes into the shard cache.
+            let state_update = tries.new_trie_update(shard_uid, state_root);
+            for account_id in accounts {
+                if let Err(err) = get_account(&state_update, &account_id) {
+                    debug!(target: "runtime", "Failed to prefetch {}: {:?}", account_id, err);
+                }
+            }
+        });
+        Ok(())
+    }
+
     fn get_view_trie_for_shard(
         &self,
         shard_id: ShardId,
@@ -978,28 +1150,67 @@ impl RuntimeAdapter for NightshadeRuntime {
             let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
             epoch_manager.get_all_block_approvers_ordered(prev_block_hash).map_err(Error::from)?
         };
-        if approvals.len() > info.len() {
-            return Ok(false);
-        }
-
-        let message_to_sign = Approval::get_data_for_sig(
-            &if prev_block_height + 1 == block_height {
-                ApprovalInner::Endorsement(*prev_block_hash)
-            } else {
-                ApprovalInner::Skip(prev_block_height)
-            },
+        Ok(Self::verify_approval_signatures(
+            &info,
+            prev_block_hash,
+            prev_block_height,
             block_height,
-        );
+            approvals,
+        ))
+    }
 
-        for ((validator, is_slashed), may_be_signature) in info.into_iter().zip(approvals.iter()) {
-            if let Some(signature) = may_be_signature {
-                if is_slashed || !signature.verify(message_to_sign.as_ref(), &validator.public_key)
-                {
-                    return Ok(false);
-                }
+    fn verify_approvals_and_threshold_batch(
+        &self,
+        doomslug_threshold_mode: DoomslugThresholdMode,
+        blocks: &[BlockApprovals],
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        // Blocks with the same epochs have the same approvers, so they are fetched only once.
+        let mut approvers_by_epochs = HashMap::new();
+        let mut checks = Vec::with_capacity(blocks.len());
+        {
+            let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+            for block in blocks {
+                let epochs = (
+                    &block.epoch_id,
+                    &block.next_epoch_id,
+                    epoch_manager.next_block_needs_next_epoch_approvals(&block.prev_block_hash)?,
+                );
+                let approvers = match approvers_by_epochs.get(&epochs) {
+                    Some(approvers) => Arc::clone(approvers),
+                    None => {
+                        let approvers = Arc::new(
+                            epoch_manager
+                                .get_all_block_approvers_ordered(&block.prev_block_hash)?,
+                        );
+                        approvers_by_epochs.insert(epochs, Arc::clone(&approvers));
+                        approvers
+                    }
+                };
+                checks.push((block, approvers));
             }
         }
-        Ok(true)
+        Ok(checks
+            .par_iter()
+            .map(|(block, approvers)| {
+                if !Self::verify_approval_signatures(
+                    approvers,
+                    &block.prev_block_hash,
+                    block.prev_block_height,
+                    block.block_height,
+                    &block.approvals,
+                ) {
+                    return Err(ErrorKind::InvalidApprovals.into());
+                }
+                if !Doomslug::can_block_be_produced_with_approvers(
+                    doomslug_threshold_mode,
+                    &block.approvals,
+                    approvers,
+                ) {
+                    return Err(ErrorKind::NotEnoughApprovals.into());
+                }
+                Ok(())
+            })
+            .collect())
     }
 
     fn get_epoch_block_producers_ordered(
@@ -1184,6 +1395,15 @@ impl RuntimeAdapter for NightshadeRuntime {
         self.shard_tracker.will_care_about_shard(account_id, parent_hash, shard_id, is_me)
     }
 
+    fn cares_about_shards(
+        &self,
+        me: Option<&AccountId>,
+        parent_hash: &CryptoHash,
+    ) -> Result<ShardCareMatrix, Error> {
+        let (this_epoch, next_epoch) = self.shard_tracker.cares_about_shards(me, parent_hash)?;
+        Ok(ShardCareMatrix { this_epoch, next_epoch })
+    }
+
     fn is_next_block_epoch_start(&self, parent_hash: &CryptoHash) -> Result<bool, Error> {
         let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
         epoch_manager.is_next_block_epoch_start(parent_hash).map_err(Error::from)
@@ -1594,22 +1814,33 @@ impl RuntimeAdapter for NightshadeRuntime {
         state_root: &StateRoot,
         part_id: PartId,
     ) -> Result<Vec<u8>, Error> {
+        let result = self
+            .get_trie_nodes_for_part(shard_id, block_hash, state_root, part_id)?
+            .try_to_vec()
+            .expect("serializer should not fail");
+        Ok(result)
+    }
+
+    fn obtain_state_part_stream(
+        &self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        state_root: &StateRoot,
+        part_id: PartId,
+    ) -> Result<StatePartStream, Error> {
         let epoch_id = self.get_epoch_id(block_hash)?;
         let shard_uid = self.get_shard_uid_from_epoch_id(shard_id, &epoch_id)?;
         let trie = self.tries.get_view_trie_for_shard(shard_uid);
-        let result = match trie.get_trie_nodes_for_part(part_id, state_root) {
-            Ok(partial_state) => partial_state,
+        match trie.get_trie_node_hashes_for_part(part_id, state_root) {
+            Ok(nodes) => Ok(PartialStateReader::stream(self.tries.clone(), shard_uid, nodes)),
             Err(e) => {
                 error!(target: "runtime",
-                       "Can't get_trie_nodes_for_part for block {:?} state root {:?}, part_id {:?}, num_parts {:?}, {:?}",
+                       "Can't get_trie_node_hashes_for_part for block {:?} state root {:?}, part_id {:?}, num_parts {:?}, {:?}",
                        block_hash, state_root, part_id.idx, part_id.total, e
                 );
-                return Err(e.to_string().into());
+                Err(e.to_string().into())
             }
         }
-        .try_to_vec()
-        .expect("serializer should not fail");
-        Ok(result)
     }
 
     fn validate_state_part(&self, state_root: &StateRoot, part_id: PartId, data: &Vec<u8>) -> bool {
@@ -3458,4 +3689,36 @@ mod test {
         assert_eq!(env.last_proposals.len(), 1);
         assert_eq!(env.last_proposals[0].stake(), 0);
     }
+
+    #[test]
+    fn test_state_part_streaming() {
+        init_test_logger();
+        let validators = vec!["test1".parse().unwrap()];
+        let env = TestEnv::new("test_state_part_streaming", vec![validators], 2, false);
+        let block_hash = hash(&vec![env.head.height as u8]);
+        let epoch_id = env.runtime.get_epoch_id(&block_hash).unwrap();
+        let shard_uid = env.runtime.get_shard_uid_from_epoch_id(0, &epoch_id).unwrap();
+
+        // 64 MB of values of various sizes, many of them starting with the same bytes.
+        let tries = env.runtime.get_tries();
+        let changes = (0..4096u32).map(|i| {
+            let value = vec![(i % 256) as u8; 16 * 1024 - 17 + (i as usize % 35)];
+            (format!("key{}", i).into_bytes(), Some(value))
+        });
+        let trie_changes =
+            tries.get_trie_for_shard(shard_uid).update(&env.state_roots[0], changes).unwrap();
+        let (store_update, state_root) = tries.apply_all(&trie_changes, shard_uid).unwrap();
+        store_update.commit().unwrap();
+
+        let part_id = PartId::new(0, 1);
+        let owned = env.runtime.obtain_state_part(0, &block_hash, &state_root, part_id).unwrap();
+        assert!(owned.len() > 64 * 1024 * 1024);
+        let stream =
+            env.runtime.obtain_state_part_stream(0, &block_hash, &state_root, part_id).unwrap();
+        assert_eq!(stream.len(), owned.len());
+        let mut streamed = vec![];
+        let streamed_hash = stream.write_to(&mut streamed).unwrap();
+        assert!(streamed == owned);
+        assert_eq!(streamed_hash, hash(&owned));
+    }
 }
@@ -6,7 +6,7 @@ use near_epoch_manager::EpochManager;
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::account_id_to_shard_id;
-use near_primitives::types::{AccountId, EpochId, ShardId};
+use near_primitives::types::{AccountId, EpochId, NumShards, ShardId};
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
@@ -131,6 +131,72 @@ impl ShardTracker {
         matches!(self.tracked_config, TrackedConfig::AllShards)
             || self.tracks_shard(shard_id, parent_hash).unwrap_or(false)
     }
+
+    /// For every shard of the epoch after `parent_hash`, whether `account_id` produces chunks for
+    /// it or for any shard it splits into in the next epoch.
+    fn shards_produced_next_epoch(
+        epoch_manager: &mut EpochManager,
+        parent_hash: &CryptoHash,
+        account_id: &AccountId,
+        num_shards: NumShards,
+    ) -> Result<BitMask, EpochError> {
+        let next_epoch_id = epoch_manager.get_next_epoch_id_from_prev_block(parent_hash)?;
+        let next_epoch_shards = epoch_manager.shards_produced_by(&next_epoch_id, account_id)?;
+        if !epoch_manager.will_shard_layout_change(parent_hash)? {
+            return Ok(next_epoch_shards);
+        }
+        let shard_layout = epoch_manager.get_shard_layout(&next_epoch_id)?;
+        Ok((0..num_shards)
+            .map(|shard_id| {
+                shard_layout
+                    .get_split_shard_ids(shard_id)
+                    .expect("all shard layouts expect the first one must have a split map")
+                    .into_iter()
+                    .any(|split_shard_id| next_epoch_shards[split_shard_id as usize])
+            })
+            .collect())
+    }
+
+    /// Same as `care_about_shard` and `will_care_about_shard` with `is_me` for all shards of the
+    /// epoch after `parent_hash`, but looks up the epochs and chunk producers only once.
+    pub fn cares_about_shards(
+        &self,
+        account_id: Option<&AccountId>,
+        parent_hash: &CryptoHash,
+    ) -> Result<(BitMask, BitMask), EpochError> {
+        let (epoch_id, mut this_epoch, mut next_epoch) = {
+            let mut epoch_manager = self.epoch_manager.write().expect(POISONED_LOCK_ERR);
+            let epoch_id = epoch_manager.get_epoch_id_from_prev_block(parent_hash)?;
+            let num_shards = epoch_manager.get_shard_layout(&epoch_id)?.num_shards();
+            let mut this_epoch = vec![false; num_shards as usize];
+            let mut next_epoch = vec![false; num_shards as usize];
+            if let Some(account_id) = account_id {
+                // Errors are treated as not caring about the shards, same as in
+                // `care_about_shard` and `will_care_about_shard`.
+                if let Ok(shards) = epoch_manager.shards_produced_by(&epoch_id, account_id) {
+                    this_epoch = shards;
+                }
+                if let Ok(shards) = Self::shards_produced_next_epoch(
+                    &mut epoch_manager,
+                    parent_hash,
+                    account_id,
+                    num_shards,
+                ) {
+                    next_epoch = shards;
+                }
+            }
+            (epoch_id, this_epoch, next_epoch)
+        };
+        for (shard_id, (cares, will_care)) in
+            this_epoch.iter_mut().zip(next_epoch.iter_mut()).enumerate()
+        {
+            let tracks_shard = matches!(self.tracked_config, TrackedConfig::AllShards)
+                || self.tracks_shard_at_epoch(shard_id as ShardId, &epoch_id).unwrap_or(false);
+            *cares |= tracks_shard;
+            *will_care |= tracks_shard;
+        }
+        Ok((this_epoch, next_epoch))
+    }
 }
 
 #[cfg(test)]
@@ -144,7 +210,9 @@ mod tests {
     use near_primitives::epoch_manager::{AllEpochConfig, EpochConfig, ShardConfig};
     use near_primitives::hash::CryptoHash;
     use near_primitives::types::validator_stake::ValidatorStake;
-    use near_primitives::types::{BlockHeight, EpochId, NumShards, ProtocolVersion, ShardId};
+    use near_primitives::types::{
+        AccountId, BlockHeight, EpochId, NumShards, ProtocolVersion, ShardId,
+    };
     use near_store::test_utils::create_test_store;
 
     use super::{account_id_to_shard_id, ShardTracker};
@@ -259,6 +327,29 @@ mod tests {
             .collect()
     }
 
+    /// Checks that the batch lookup makes the same decisions as the per shard one.
+    fn check_cares_about_shards(
+        tracker: &ShardTracker,
+        num_shards: NumShards,
+        parent_hash: &CryptoHash,
+    ) {
+        let validator: AccountId = "test".parse().unwrap();
+        for account_id in vec![None, Some(&validator)] {
+            let (this_epoch, next_epoch) =
+                tracker.cares_about_shards(account_id, parent_hash).unwrap();
+            let expected_this_epoch: Vec<_> = (0..num_shards)
+                .map(|shard_id| tracker.care_about_shard(account_id, parent_hash, shard_id, true))
+                .collect();
+            let expected_next_epoch: Vec<_> = (0..num_shards)
+                .map(|shard_id| {
+                    tracker.will_care_about_shard(account_id, parent_hash, shard_id, true)
+                })
+                .collect();
+            assert_eq!(this_epoch, expected_this_epoch);
+            assert_eq!(next_epoch, expected_next_epoch);
+        }
+    }
+
     #[test]
     fn test_track_accounts() {
         let num_shards = 4;
@@ -283,6 +374,7 @@ mod tests {
             get_all_shards_will_care_about(&tracker, num_shards, &CryptoHash::default()),
             total_tracked_shards
         );
+        check_cares_about_shards(&tracker, num_shards, &CryptoHash::default());
     }
 
     #[test]
@@ -301,6 +393,7 @@ mod tests {
             get_all_shards_will_care_about(&tracker, num_shards, &CryptoHash::default()),
             total_tracked_shards
         );
+        check_cares_about_shards(&tracker, num_shards, &CryptoHash::default());
     }
 
     #[test]
@@ -380,6 +473,7 @@ mod tests {
                 get_all_shards_will_care_about(&tracker, num_shards, &h[i - 1]),
                 total_tracked_shards
             );
+            check_cares_about_shards(&tracker, num_shards, &h[i - 1]);
         }
     }
 }
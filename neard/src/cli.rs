@@ -2,16 +2,20 @@ use clap::{Args, Parser};
 use futures::future::FutureExt;
 use near_chain_configs::GenesisValidationMode;
 use near_o11y::{default_subscriber, EnvFilterBuilder};
-use near_primitives::types::{Gas, NumSeats, NumShards};
+use near_primitives::types::{BlockHeight, Gas, NumBlocks, NumSeats, NumShards};
 use near_state_viewer::StateViewerSubCommand;
 use near_store::db::RocksDB;
 use nearcore::get_store_path;
 use std::fs;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::sync::oneshot;
 use tracing::{debug, error, info, warn};
 
+/// How long to wait for the blocks in flight to be processed once the node is asked to stop.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// NEAR Protocol Node
 #[derive(Parser)]
 #[clap(version = crate::NEARD_VERSION_STRING.as_str())]
@@ -93,6 +97,15 @@ impl NeardCmd {
             NeardSubCommand::RecompressStorage(cmd) => {
                 cmd.run(&home_dir);
             }
+            NeardSubCommand::RepairEpochData(cmd) => {
+                cmd.run(&home_dir, genesis_validation);
+            }
+            NeardSubCommand::GcToHeight(cmd) => {
+                cmd.run(&home_dir, genesis_validation);
+            }
+            NeardSubCommand::SimulateGc(cmd) => {
+                cmd.run(&home_dir, genesis_validation);
+            }
         }
     }
 }
@@ -178,6 +191,34 @@ pub(super) enum NeardSubCommand {
     /// tool, it is planned to be removed by the end of 2022.
     #[clap(name = "recompress_storage")]
     RecompressStorage(RecompressStorageSubCommand),
+    /// Adds the epoch manager data missing for stored block headers.
+    ///
+    /// A node stopped between saving a block header and recording it in the
+    /// epoch manager fails to sync the following headers with
+    /// `EpochOutOfBounds` errors.  This command records the stored headers the
+    /// epoch manager doesn’t know about, in the order of their heights.  The
+    /// node must not be running.
+    #[clap(name = "repair_epoch_data")]
+    RepairEpochData(RepairEpochDataSubCommand),
+    /// (unsafe) Prunes the blocks up to the given height, beyond what the
+    /// regular garbage collection removes.
+    ///
+    /// Frees disk space at the cost of no longer being able to serve the
+    /// removed history to peers and RPC users.  Requires `allow_aggressive_gc`
+    /// to be set in the config.  The blocks of the current and the previous
+    /// epoch are always kept, as is a margin behind the final block.  The node
+    /// must not be running.
+    #[clap(name = "gc_to_height", hide = true)]
+    GcToHeight(GcToHeightSubCommand),
+    /// Reports what garbage collection would delete, without deleting
+    /// anything.
+    ///
+    /// Lists the fork blocks and the height range of the canonical blocks the
+    /// next garbage collection run would remove, and estimates the number of
+    /// deleted database keys.  Useful before enabling garbage collection on a
+    /// node which kept its history so far.  The node must not be running.
+    #[clap(name = "simulate_gc")]
+    SimulateGc(SimulateGcSubCommand),
 }
 
 #[derive(Parser)]
@@ -399,7 +440,7 @@ impl RunCmd {
         let (tx, rx) = oneshot::channel::<()>();
         let sys = actix::System::new();
         sys.block_on(async move {
-            let nearcore::NearNode { rpc_servers, .. } =
+            let node =
                 nearcore::start_with_config_and_synchronization(home_dir, near_config, Some(tx))
                     .expect("start_with_config");
 
@@ -418,11 +459,15 @@ impl RunCmd {
                 "Ctrl+C"
             };
             info!(target: "neard", "Got '{}', stopping...", sig);
-            futures::future::join_all(rpc_servers.iter().map(|(name, server)| async move {
+            futures::future::join_all(node.rpc_servers.iter().map(|(name, server)| async move {
                 server.stop(true).await;
                 debug!(target: "neard", "{} server stopped", name);
             }))
             .await;
+            match node.prepare_shutdown(SHUTDOWN_TIMEOUT).await {
+                Ok(report) => debug!(target: "neard", "Chain prepared for shutdown: {:?}", report),
+                Err(err) => warn!(target: "neard", "Failed to prepare chain for shutdown: {}", err),
+            }
             actix::System::current().stop();
         });
         sys.run().unwrap();
@@ -501,6 +546,121 @@ impl RecompressStorageSubCommand {
     }
 }
 
+#[derive(Parser)]
+pub(super) struct RepairEpochDataSubCommand {
+    /// Height of the first headers to check.
+    #[clap(long, default_value = "0")]
+    from_height: BlockHeight,
+    /// Height of the last headers to check, the height of the header head by
+    /// default.
+    #[clap(long)]
+    to_height: Option<BlockHeight>,
+}
+
+impl RepairEpochDataSubCommand {
+    pub(super) fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        let near_config = nearcore::config::load_config(&home_dir, genesis_validation)
+            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
+        match nearcore::repair_epoch_data(&home_dir, &near_config, self.from_height, self.to_height)
+        {
+            Ok(report) => {
+                info!(
+                    target: "neard",
+                    "Checked {} headers, repaired {}",
+                    report.headers_checked,
+                    report.repaired.len()
+                );
+                for hash in report.repaired {
+                    info!(target: "neard", "Repaired header {}", hash);
+                }
+            }
+            Err(err) => {
+                error!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct GcToHeightSubCommand {
+    /// Height of the new tail, the highest height whose blocks are removed.
+    #[clap(long)]
+    target_tail: BlockHeight,
+    /// Maximum number of blocks removed in one batch.
+    #[clap(long, default_value = "100")]
+    gc_blocks_limit: NumBlocks,
+}
+
+impl GcToHeightSubCommand {
+    pub(super) fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        let near_config = nearcore::config::load_config(&home_dir, genesis_validation)
+            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
+        match nearcore::gc_to_height(
+            &home_dir,
+            &near_config,
+            self.target_tail,
+            self.gc_blocks_limit,
+        ) {
+            Ok(report) => {
+                info!(
+                    target: "neard",
+                    "Removed {} blocks, tail is at {}",
+                    report.blocks_removed,
+                    report.tail
+                );
+                if !report.reached_target {
+                    warn!(
+                        target: "neard",
+                        "Tail didn't reach {}, run the command again after the node made progress",
+                        self.target_tail
+                    );
+                }
+            }
+            Err(err) => {
+                error!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct SimulateGcSubCommand {
+    /// Maximum number of blocks removed by the run, `gc_blocks_limit` of the
+    /// config by default.
+    #[clap(long)]
+    gc_blocks_limit: Option<NumBlocks>,
+}
+
+impl SimulateGcSubCommand {
+    pub(super) fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        let near_config = nearcore::config::load_config(&home_dir, genesis_validation)
+            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
+        let gc_blocks_limit =
+            self.gc_blocks_limit.unwrap_or(near_config.client_config.gc_blocks_limit);
+        match nearcore::simulate_gc(&home_dir, &near_config, gc_blocks_limit) {
+            Ok(plan) => {
+                info!(
+                    target: "neard",
+                    "Would remove {} fork blocks and the canonical blocks at heights {}..{}, about {} keys",
+                    plan.fork_blocks.len(),
+                    plan.canonical_range.start,
+                    plan.canonical_range.end,
+                    plan.estimated_keys
+                );
+                for hash in plan.fork_blocks {
+                    info!(target: "neard", "Would remove fork block {}", hash);
+                }
+            }
+            Err(err) => {
+                error!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
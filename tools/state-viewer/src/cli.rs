@@ -76,6 +76,9 @@ pub enum StateViewerSubCommand {
     /// Replay headers from chain.
     #[clap(name = "replay")]
     Replay(ReplayCmd),
+    /// Re-apply blocks at a range of heights and compare resulting chunk extras with stored ones.
+    #[clap(name = "replay_blocks")]
+    ReplayBlocks(ReplayBlocksCmd),
     /// Apply blocks at a range of heights for a single shard.
     #[clap(name = "apply_range")]
     ApplyRange(ApplyRangeCmd),
@@ -125,7 +128,7 @@ impl StateViewerSubCommand {
             .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
         let store = create_store_with_config(
             &get_store_path(home_dir),
-            StoreConfig { read_only: true, enable_statistics: false },
+            StoreConfig { read_only: true, ..Default::default() },
         );
         match self {
             StateViewerSubCommand::Peers => peers(store),
@@ -134,6 +137,7 @@ impl StateViewerSubCommand {
             StateViewerSubCommand::DumpStateRedis(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::Chain(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::Replay(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::ReplayBlocks(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::ApplyRange(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::Apply(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::ViewChain(cmd) => cmd.run(near_config, store),
@@ -217,6 +221,24 @@ impl ReplayCmd {
     }
 }
 
+#[derive(Parser)]
+pub struct ReplayBlocksCmd {
+    #[clap(long)]
+    start_index: BlockHeight,
+    #[clap(long)]
+    end_index: BlockHeight,
+    /// Shards to compare, can be repeated. All shards are compared if not specified.
+    #[clap(long)]
+    shard_id: Vec<ShardId>,
+}
+
+impl ReplayBlocksCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+        let shard_ids = if self.shard_id.is_empty() { None } else { Some(self.shard_id) };
+        replay_blocks(self.start_index, self.end_index, shard_ids, home_dir, near_config, store);
+    }
+}
+
 #[derive(Parser)]
 pub struct ApplyRangeCmd {
     #[clap(long)]
@@ -6,7 +6,10 @@ use ansi_term::Color::Red;
 use near_chain::chain::collect_receipts_from_response;
 use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
 use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo};
-use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
+use near_chain::{
+    Chain, ChainGenesis, ChainStore, ChainStoreAccess, ChainStoreUpdate, DoomslugThresholdMode,
+    RuntimeAdapter,
+};
 use near_epoch_manager::EpochManager;
 use near_network::iter_peers_from_store;
 use near_primitives::account::id::AccountId;
@@ -333,6 +336,50 @@ pub(crate) fn replay_chain(
     }
 }
 
+pub(crate) fn replay_blocks(
+    start_height: BlockHeight,
+    end_height: BlockHeight,
+    shard_ids: Option<Vec<ShardId>>,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) {
+    let runtime_adapter: Arc<dyn RuntimeAdapter> = Arc::new(NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    ));
+    let chain_genesis = ChainGenesis::from(&near_config.genesis);
+    let mut chain = Chain::new_for_view_client(
+        runtime_adapter,
+        &chain_genesis,
+        DoomslugThresholdMode::TwoThirds,
+    )
+    .unwrap();
+    let me = near_config.validator_signer.as_ref().map(|signer| signer.validator_id().clone());
+    let report = chain.replay_blocks(&me, start_height, end_height, shard_ids).unwrap();
+    println!(
+        "Replayed {} blocks, compared {} chunk extras",
+        report.blocks_replayed, report.chunks_compared
+    );
+    if report.divergences.is_empty() {
+        println!("No divergences found");
+    }
+    for divergence in report.divergences.values() {
+        println!(
+            "{} shard {} at height {}, block {}",
+            Red.bold().paint("DIVERGENCE"),
+            divergence.shard_id,
+            divergence.height,
+            divergence.block_hash
+        );
+        println!("Stored chunk extra: {:?}", divergence.stored);
+        println!("Replayed chunk extra: {:?}", divergence.replayed);
+    }
+}
+
 pub(crate) fn resulting_chunk_extra(result: &ApplyTransactionResult, gas_limit: Gas) -> ChunkExtra {
     let (outcome_root, _) = ApplyTransactionResult::compute_outcomes_proof(&result.outcomes);
     ChunkExtra::new(
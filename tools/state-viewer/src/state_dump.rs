@@ -264,6 +264,7 @@ mod test {
         let mut chain_genesis = ChainGenesis::test();
         chain_genesis.epoch_length = epoch_length;
         chain_genesis.gas_limit = genesis.config.gas_limit;
+        chain_genesis.protocol_version = protocol_version;
         let env = TestEnv::builder(chain_genesis)
             .validator_seats(2)
             .runtime_adapters(vec![Arc::new(nightshade_runtime)])